@@ -0,0 +1,103 @@
+#ifndef CRYPTO_FFI_H
+#define CRYPTO_FFI_H
+
+/* Generated by cbindgen from crypto-ffi/src/lib.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define CRYPTO_OK 0
+
+#define CRYPTO_ERR_NULL_POINTER -1
+
+#define CRYPTO_ERR_INVALID_KEY -2
+
+#define CRYPTO_ERR_BUFFER_TOO_SMALL -3
+
+#define CRYPTO_ERR_ENCRYPTION_FAILED -4
+
+#define CRYPTO_ERR_DECRYPTION_FAILED -5
+
+#define CRYPTO_ERR_INTERNAL -6
+
+/**
+ * ChaCha20-Poly1305 key, nonce and tag sizes in bytes.
+ */
+#define CRYPTO_CHACHA_KEY_LEN 32
+
+#define CRYPTO_CHACHA_NONCE_LEN 12
+
+#define CRYPTO_CHACHA_TAG_LEN 16
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Encrypt `input` with ChaCha20-Poly1305, writing ciphertext || tag
+ * (`in_len + 16` bytes) to `out`.
+ *
+ * # Safety
+ * `key` must point to `key_len` readable bytes, `nonce` to 12, `aad` to
+ * `aad_len` and `input` to `in_len`. `out` must be writable for `out_cap`
+ * bytes and `out_len` must be a writable `size_t`.
+ */
+int32_t crypto_chacha_encrypt(const uint8_t *key,
+                              size_t key_len,
+                              const uint8_t *nonce,
+                              const uint8_t *aad,
+                              size_t aad_len,
+                              const uint8_t *input,
+                              size_t in_len,
+                              uint8_t *out,
+                              size_t out_cap,
+                              size_t *out_len);
+
+/**
+ * Verify and decrypt ciphertext || tag from `input`, writing `in_len - 16`
+ * bytes of plaintext to `out`. Nothing is written if authentication fails.
+ *
+ * # Safety
+ * As for [`crypto_chacha_encrypt`].
+ */
+int32_t crypto_chacha_decrypt(const uint8_t *key,
+                              size_t key_len,
+                              const uint8_t *nonce,
+                              const uint8_t *aad,
+                              size_t aad_len,
+                              const uint8_t *input,
+                              size_t in_len,
+                              uint8_t *out,
+                              size_t out_cap,
+                              size_t *out_len);
+
+/**
+ * XOR `data` in place with the RC4 keystream for `key` (5 to 256 bytes).
+ *
+ * # Safety
+ * `key` must point to `key_len` readable bytes and `data` to `data_len`
+ * readable and writable bytes.
+ */
+int32_t crypto_rc4_apply(const uint8_t *key, size_t key_len, uint8_t *data, size_t data_len);
+
+/**
+ * Fill `out` with `out_len` bytes from the system's secure RNG.
+ *
+ * # Safety
+ * `out` must be writable for `out_len` bytes.
+ */
+int32_t crypto_generate_key(uint8_t *out, size_t out_len);
+
+/**
+ * A static, NUL-terminated description of `code`. Never NULL.
+ */
+const char *crypto_error_message(int32_t code);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CRYPTO_FFI_H */
@@ -0,0 +1,558 @@
+/* C ABI for the core crypto functions, built as a cdylib.
+ *
+ * This is the only crate in the workspace that allows unsafe code, and only
+ * to turn C pointers into slices. The header in include/crypto_ffi.h is
+ * regenerated by build.rs (cbindgen) on every build.
+ *
+ * Conventions for every function:
+ *   - the return value is CRYPTO_OK or a negative CRYPTO_ERR_* code, which
+ *     crypto_error_message() turns into a static string
+ *   - a pointer may be NULL only when its length is 0
+ *   - output is written only on success; when out_cap is too small the
+ *     required size is stored in *out_len and nothing else is written
+ *   - out may equal input (in-place operation)
+ *   - panics never cross the boundary; they surface as CRYPTO_ERR_INTERNAL */
+#![deny(unsafe_op_in_unsafe_fn)]
+
+use std::ffi::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crypto::{chacha20_poly1305_cipher_with_aad, generate_key, ErrorStates, Rc4};
+
+pub const CRYPTO_OK: i32 = 0;
+pub const CRYPTO_ERR_NULL_POINTER: i32 = -1;
+pub const CRYPTO_ERR_INVALID_KEY: i32 = -2;
+pub const CRYPTO_ERR_BUFFER_TOO_SMALL: i32 = -3;
+pub const CRYPTO_ERR_ENCRYPTION_FAILED: i32 = -4;
+pub const CRYPTO_ERR_DECRYPTION_FAILED: i32 = -5;
+pub const CRYPTO_ERR_INTERNAL: i32 = -6;
+
+/// ChaCha20-Poly1305 key, nonce and tag sizes in bytes.
+pub const CRYPTO_CHACHA_KEY_LEN: usize = 32;
+pub const CRYPTO_CHACHA_NONCE_LEN: usize = 12;
+pub const CRYPTO_CHACHA_TAG_LEN: usize = 16;
+
+/* Run `f`, mapping a panic to CRYPTO_ERR_INTERNAL */
+fn boundary<F: FnOnce() -> Result<(), i32>>(f: F) -> i32 {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => CRYPTO_OK,
+        Ok(Err(code)) => code,
+        Err(_) => CRYPTO_ERR_INTERNAL,
+    }
+}
+
+/* Borrow `len` bytes at `ptr`, allowing NULL only for an empty buffer */
+unsafe fn input<'a>(ptr: *const u8, len: usize) -> Result<&'a [u8], i32> {
+    match (ptr.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(CRYPTO_ERR_NULL_POINTER),
+        /* SAFETY: the caller guarantees `ptr` is valid for `len` bytes */
+        (false, _) => Ok(unsafe { slice::from_raw_parts(ptr, len) }),
+    }
+}
+
+/* Copy `result` to `out`, or report the size needed */
+unsafe fn output(
+    result: &[u8],
+    out: *mut u8,
+    out_cap: usize,
+    out_len: *mut usize,
+) -> Result<(), i32> {
+    if out_len.is_null() {
+        return Err(CRYPTO_ERR_NULL_POINTER);
+    }
+    /* SAFETY: checked non-null; the caller guarantees it is writable */
+    unsafe { *out_len = result.len() };
+    if out_cap < result.len() {
+        return Err(CRYPTO_ERR_BUFFER_TOO_SMALL);
+    }
+    if result.is_empty() {
+        return Ok(());
+    }
+    if out.is_null() {
+        return Err(CRYPTO_ERR_NULL_POINTER);
+    }
+    /* SAFETY: `out` holds at least out_cap >= result.len() bytes, and
+     * `result` is our own allocation, so the ranges cannot overlap */
+    unsafe { ptr::copy_nonoverlapping(result.as_ptr(), out, result.len()) };
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn chacha(
+    key: *const u8,
+    key_len: usize,
+    nonce: *const u8,
+    aad: *const u8,
+    aad_len: usize,
+    input_ptr: *const u8,
+    in_len: usize,
+    out: *mut u8,
+    out_cap: usize,
+    out_len: *mut usize,
+    encrypt: bool,
+) -> i32 {
+    boundary(|| {
+        /* SAFETY: forwarded from the caller's contract */
+        let key = unsafe { input(key, key_len) }?;
+        if key.len() != CRYPTO_CHACHA_KEY_LEN {
+            return Err(CRYPTO_ERR_INVALID_KEY);
+        }
+        if nonce.is_null() {
+            return Err(CRYPTO_ERR_NULL_POINTER);
+        }
+        /* SAFETY: non-null, and the caller guarantees 12 readable bytes */
+        let nonce: [u8; CRYPTO_CHACHA_NONCE_LEN] =
+            unsafe { slice::from_raw_parts(nonce, CRYPTO_CHACHA_NONCE_LEN) }
+                .try_into()
+                .unwrap();
+        let aad = unsafe { input(aad, aad_len) }?;
+        /* Copied before `out` is touched, so in-place calls are sound */
+        let data = unsafe { input(input_ptr, in_len) }?.to_vec();
+
+        if !encrypt && data.len() < CRYPTO_CHACHA_TAG_LEN {
+            return Err(CRYPTO_ERR_DECRYPTION_FAILED);
+        }
+        /* Check capacity before doing the work */
+        let needed = if encrypt {
+            data.len() + CRYPTO_CHACHA_TAG_LEN
+        } else {
+            data.len() - CRYPTO_CHACHA_TAG_LEN
+        };
+        if out_len.is_null() {
+            return Err(CRYPTO_ERR_NULL_POINTER);
+        }
+        if out_cap < needed {
+            /* SAFETY: checked non-null; the caller guarantees it is writable */
+            unsafe { *out_len = needed };
+            return Err(CRYPTO_ERR_BUFFER_TOO_SMALL);
+        }
+
+        let result = chacha20_poly1305_cipher_with_aad(key, &nonce, aad, data, encrypt).map_err(
+            |e| match e {
+                ErrorStates::KeyInitializationFailed => CRYPTO_ERR_INVALID_KEY,
+                ErrorStates::EncryptionFailed => CRYPTO_ERR_ENCRYPTION_FAILED,
+                _ => CRYPTO_ERR_DECRYPTION_FAILED,
+            },
+        )?;
+        unsafe { output(&result, out, out_cap, out_len) }
+    })
+}
+
+/// Encrypt `input` with ChaCha20-Poly1305, writing ciphertext || tag
+/// (`in_len + 16` bytes) to `out`.
+///
+/// # Safety
+/// `key` must point to `key_len` readable bytes, `nonce` to 12, `aad` to
+/// `aad_len` and `input` to `in_len`. `out` must be writable for `out_cap`
+/// bytes and `out_len` must be a writable `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_chacha_encrypt(
+    key: *const u8,
+    key_len: usize,
+    nonce: *const u8,
+    aad: *const u8,
+    aad_len: usize,
+    input: *const u8,
+    in_len: usize,
+    out: *mut u8,
+    out_cap: usize,
+    out_len: *mut usize,
+) -> i32 {
+    /* SAFETY: forwarded from the caller's contract */
+    unsafe {
+        chacha(
+            key, key_len, nonce, aad, aad_len, input, in_len, out, out_cap, out_len, true,
+        )
+    }
+}
+
+/// Verify and decrypt ciphertext || tag from `input`, writing `in_len - 16`
+/// bytes of plaintext to `out`. Nothing is written if authentication fails.
+///
+/// # Safety
+/// As for [`crypto_chacha_encrypt`].
+#[no_mangle]
+pub unsafe extern "C" fn crypto_chacha_decrypt(
+    key: *const u8,
+    key_len: usize,
+    nonce: *const u8,
+    aad: *const u8,
+    aad_len: usize,
+    input: *const u8,
+    in_len: usize,
+    out: *mut u8,
+    out_cap: usize,
+    out_len: *mut usize,
+) -> i32 {
+    /* SAFETY: forwarded from the caller's contract */
+    unsafe {
+        chacha(
+            key, key_len, nonce, aad, aad_len, input, in_len, out, out_cap, out_len, false,
+        )
+    }
+}
+
+/// XOR `data` in place with the RC4 keystream for `key` (5 to 256 bytes).
+///
+/// # Safety
+/// `key` must point to `key_len` readable bytes and `data` to `data_len`
+/// readable and writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_rc4_apply(
+    key: *const u8,
+    key_len: usize,
+    data: *mut u8,
+    data_len: usize,
+) -> i32 {
+    boundary(|| {
+        /* SAFETY: forwarded from the caller's contract */
+        let key = unsafe { input(key, key_len) }?;
+        if !(5..=256).contains(&key.len()) {
+            return Err(CRYPTO_ERR_INVALID_KEY);
+        }
+        if data_len == 0 {
+            return Ok(());
+        }
+        if data.is_null() {
+            return Err(CRYPTO_ERR_NULL_POINTER);
+        }
+        /* SAFETY: non-null, and the caller guarantees data_len bytes */
+        let data = unsafe { slice::from_raw_parts_mut(data, data_len) };
+        Rc4::new(key).apply_keystream(data);
+        Ok(())
+    })
+}
+
+/// Fill `out` with `out_len` bytes from the system's secure RNG.
+///
+/// # Safety
+/// `out` must be writable for `out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_generate_key(out: *mut u8, out_len: usize) -> i32 {
+    boundary(|| {
+        if out_len == 0 {
+            return Ok(());
+        }
+        if out.is_null() {
+            return Err(CRYPTO_ERR_NULL_POINTER);
+        }
+        /* SAFETY: non-null, and the caller guarantees out_len bytes */
+        generate_key(unsafe { slice::from_raw_parts_mut(out, out_len) });
+        Ok(())
+    })
+}
+
+/// A static, NUL-terminated description of `code`. Never NULL.
+#[no_mangle]
+pub extern "C" fn crypto_error_message(code: i32) -> *const c_char {
+    let message: &'static [u8] = match code {
+        CRYPTO_OK => b"success\0",
+        CRYPTO_ERR_NULL_POINTER => b"required pointer was NULL\0",
+        CRYPTO_ERR_INVALID_KEY => b"invalid key length\0",
+        CRYPTO_ERR_BUFFER_TOO_SMALL => b"output buffer too small; see out_len\0",
+        CRYPTO_ERR_ENCRYPTION_FAILED => b"encryption failed\0",
+        CRYPTO_ERR_DECRYPTION_FAILED => b"decryption failed (wrong key or corrupted data)\0",
+        CRYPTO_ERR_INTERNAL => b"internal error\0",
+        _ => b"unknown error code\0",
+    };
+    message.as_ptr().cast()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use std::path::Path;
+    use std::process::Command;
+
+    const KEY: [u8; 32] = [0x42; 32];
+    const NONCE: [u8; 12] = [0x24; 12];
+
+    fn encrypt(aad: &[u8], input: &[u8], out: &mut [u8], out_len: &mut usize) -> i32 {
+        unsafe {
+            crypto_chacha_encrypt(
+                KEY.as_ptr(),
+                KEY.len(),
+                NONCE.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                input.as_ptr(),
+                input.len(),
+                out.as_mut_ptr(),
+                out.len(),
+                out_len,
+            )
+        }
+    }
+
+    fn decrypt(aad: &[u8], input: &[u8], out: &mut [u8], out_len: &mut usize) -> i32 {
+        unsafe {
+            crypto_chacha_decrypt(
+                KEY.as_ptr(),
+                KEY.len(),
+                NONCE.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                input.as_ptr(),
+                input.len(),
+                out.as_mut_ptr(),
+                out.len(),
+                out_len,
+            )
+        }
+    }
+
+    #[test]
+    fn chacha_round_trip_matches_core() {
+        let mut sealed = [0u8; 64];
+        let mut len = 0;
+        assert_eq!(
+            encrypt(b"hdr", b"Hello World!", &mut sealed, &mut len),
+            CRYPTO_OK
+        );
+        assert_eq!(len, 12 + 16);
+        assert_eq!(
+            sealed[..len],
+            chacha20_poly1305_cipher_with_aad(&KEY, &NONCE, b"hdr", b"Hello World!".to_vec(), true)
+                .unwrap()
+        );
+
+        let mut opened = [0u8; 64];
+        let mut opened_len = 0;
+        assert_eq!(
+            decrypt(b"hdr", &sealed[..len], &mut opened, &mut opened_len),
+            CRYPTO_OK
+        );
+        assert_eq!(&opened[..opened_len], b"Hello World!");
+    }
+
+    #[test]
+    fn buffer_size_contract() {
+        /* Too small: the required size is reported and nothing is written */
+        let mut small = [0xee; 27];
+        let mut len = 0;
+        assert_eq!(
+            encrypt(&[], b"Hello World!", &mut small, &mut len),
+            CRYPTO_ERR_BUFFER_TOO_SMALL
+        );
+        assert_eq!(len, 28);
+        assert_eq!(small, [0xee; 27]);
+
+        /* A NULL, zero-capacity output is a size query */
+        let mut len = 0;
+        let code = unsafe {
+            crypto_chacha_encrypt(
+                KEY.as_ptr(),
+                32,
+                NONCE.as_ptr(),
+                ptr::null(),
+                0,
+                b"abc".as_ptr(),
+                3,
+                ptr::null_mut(),
+                0,
+                &mut len,
+            )
+        };
+        assert_eq!((code, len), (CRYPTO_ERR_BUFFER_TOO_SMALL, 19));
+
+        /* Empty input with NULL pointers everywhere optional */
+        let mut out = [0u8; 16];
+        let code = unsafe {
+            crypto_chacha_encrypt(
+                KEY.as_ptr(),
+                32,
+                NONCE.as_ptr(),
+                ptr::null(),
+                0,
+                ptr::null(),
+                0,
+                out.as_mut_ptr(),
+                out.len(),
+                &mut len,
+            )
+        };
+        assert_eq!((code, len), (CRYPTO_OK, 16));
+    }
+
+    #[test]
+    fn in_place_operation() {
+        let mut buf = [0u8; 28];
+        buf[..12].copy_from_slice(b"Hello World!");
+        let mut len = 0;
+        let ptr = buf.as_mut_ptr();
+        let code = unsafe {
+            crypto_chacha_encrypt(
+                KEY.as_ptr(),
+                32,
+                NONCE.as_ptr(),
+                ptr::null(),
+                0,
+                ptr,
+                12,
+                ptr,
+                28,
+                &mut len,
+            )
+        };
+        assert_eq!((code, len), (CRYPTO_OK, 28));
+
+        let code = unsafe {
+            crypto_chacha_decrypt(
+                KEY.as_ptr(),
+                32,
+                NONCE.as_ptr(),
+                ptr::null(),
+                0,
+                ptr,
+                28,
+                ptr,
+                28,
+                &mut len,
+            )
+        };
+        assert_eq!((code, len), (CRYPTO_OK, 12));
+        assert_eq!(&buf[..12], b"Hello World!");
+    }
+
+    #[test]
+    fn error_codes() {
+        let mut sealed = [0u8; 28];
+        let mut len = 0;
+        encrypt(&[], b"Hello World!", &mut sealed, &mut len);
+
+        /* Wrong AAD, tampered tag and short input all fail without output */
+        let mut out = [0xee; 28];
+        assert_eq!(
+            decrypt(b"other", &sealed, &mut out, &mut len),
+            CRYPTO_ERR_DECRYPTION_FAILED
+        );
+        sealed[27] ^= 1;
+        assert_eq!(
+            decrypt(&[], &sealed, &mut out, &mut len),
+            CRYPTO_ERR_DECRYPTION_FAILED
+        );
+        assert_eq!(
+            decrypt(&[], &sealed[..15], &mut out, &mut len),
+            CRYPTO_ERR_DECRYPTION_FAILED
+        );
+        assert_eq!(out, [0xee; 28]);
+
+        let code = unsafe {
+            crypto_chacha_encrypt(
+                KEY.as_ptr(),
+                16,
+                NONCE.as_ptr(),
+                ptr::null(),
+                0,
+                ptr::null(),
+                0,
+                out.as_mut_ptr(),
+                28,
+                &mut len,
+            )
+        };
+        assert_eq!(code, CRYPTO_ERR_INVALID_KEY);
+
+        let code = unsafe {
+            crypto_chacha_encrypt(
+                KEY.as_ptr(),
+                32,
+                ptr::null(),
+                ptr::null(),
+                0,
+                ptr::null(),
+                0,
+                out.as_mut_ptr(),
+                28,
+                &mut len,
+            )
+        };
+        assert_eq!(code, CRYPTO_ERR_NULL_POINTER);
+
+        let code = unsafe {
+            crypto_chacha_encrypt(
+                KEY.as_ptr(),
+                32,
+                NONCE.as_ptr(),
+                ptr::null(),
+                4,
+                ptr::null(),
+                0,
+                out.as_mut_ptr(),
+                28,
+                &mut len,
+            )
+        };
+        assert_eq!(code, CRYPTO_ERR_NULL_POINTER);
+
+        for code in [0, -1, -2, -3, -4, -5, -6, 7] {
+            let message = unsafe { CStr::from_ptr(crypto_error_message(code)) };
+            assert!(!message.to_bytes().is_empty());
+        }
+        let unknown = unsafe { CStr::from_ptr(crypto_error_message(7)) };
+        assert_eq!(unknown.to_str(), Ok("unknown error code"));
+    }
+
+    // Wikipedia RC4 test vectors, key "Secret" ("Key" is below the minimum)
+    #[test]
+    fn rc4_apply() {
+        let mut data = *b"Attack at dawn";
+        let code =
+            unsafe { crypto_rc4_apply(b"Secret".as_ptr(), 6, data.as_mut_ptr(), data.len()) };
+        assert_eq!(code, CRYPTO_OK);
+        assert_eq!(
+            data,
+            [0x45, 0xa0, 0x1f, 0x64, 0x5f, 0xc3, 0x5b, 0x38, 0x35, 0x52, 0x54, 0x4b, 0x9b, 0xf5]
+        );
+
+        let code = unsafe { crypto_rc4_apply(b"Key".as_ptr(), 3, data.as_mut_ptr(), data.len()) };
+        assert_eq!(code, CRYPTO_ERR_INVALID_KEY);
+        let code = unsafe { crypto_rc4_apply(b"Secret".as_ptr(), 6, ptr::null_mut(), 4) };
+        assert_eq!(code, CRYPTO_ERR_NULL_POINTER);
+    }
+
+    #[test]
+    fn generate_key_fills_buffer() {
+        let (mut a, mut b) = ([0u8; 32], [0u8; 32]);
+        assert_eq!(
+            unsafe { crypto_generate_key(a.as_mut_ptr(), 32) },
+            CRYPTO_OK
+        );
+        assert_eq!(
+            unsafe { crypto_generate_key(b.as_mut_ptr(), 32) },
+            CRYPTO_OK
+        );
+        assert_ne!(a, b);
+        assert_eq!(
+            unsafe { crypto_generate_key(ptr::null_mut(), 32) },
+            CRYPTO_ERR_NULL_POINTER
+        );
+    }
+
+    #[test]
+    fn header_compiles_as_c() {
+        let header = Path::new(env!("CARGO_MANIFEST_DIR")).join("include/crypto_ffi.h");
+        let source = format!(
+            "#include \"{}\"\nint main(void) {{ return CRYPTO_OK; }}\n",
+            header.display()
+        );
+        let dir = std::env::temp_dir().join(format!("crypto-ffi-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("check.c"), source).unwrap();
+
+        match Command::new("cc")
+            .args(["-std=c99", "-Wall", "-Werror", "-fsyntax-only", "check.c"])
+            .current_dir(&dir)
+            .status()
+        {
+            Ok(status) => assert!(status.success()),
+            /* No C compiler on this machine; nothing to check against */
+            Err(_) => eprintln!("cc not found, skipping"),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,309 @@
+/* Python bindings (PyO3), built with maturin as the `crypto_py` module.
+ *
+ * Blobs from `encrypt` are nonce (12 bytes) || ciphertext || tag, the same
+ * single-shot layout `rcli chacha --decrypt` still accepts. `StreamEncryptor`
+ * and `decrypt_stream` use the chunked rcli file format, so files written
+ * from Python decrypt with the CLI and vice versa.
+ *
+ * Every crypto::ErrorStates variant maps to its own exception class, all
+ * deriving from CryptoError. */
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crypto::fs::{decrypt_stream as decrypt_stream_into, FsCryptoError};
+use crypto::header::{Algorithm, FileHeader, DEFAULT_CHUNK_SIZE, NONCE_PREFIX_LEN};
+use crypto::stream::StreamEncryptor as ChunkSealer;
+use crypto::{
+    chacha20_poly1305_cipher_with_aad, generate_key as fill_key, generate_nonce, ErrorStates, Rc4,
+};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+create_exception!(crypto_py, CryptoError, PyException);
+create_exception!(crypto_py, InvalidKeyError, CryptoError);
+create_exception!(crypto_py, EncryptionError, CryptoError);
+create_exception!(crypto_py, DecryptionError, CryptoError);
+create_exception!(crypto_py, CommitmentMismatchError, CryptoError);
+create_exception!(crypto_py, KeyReuseError, CryptoError);
+create_exception!(crypto_py, StreamTruncatedError, CryptoError);
+create_exception!(crypto_py, KeyExhaustedError, CryptoError);
+create_exception!(crypto_py, WrongKeyError, DecryptionError);
+create_exception!(crypto_py, HeaderError, CryptoError);
+
+fn to_py_err(e: ErrorStates) -> PyErr {
+    match e {
+        ErrorStates::KeyInitializationFailed => InvalidKeyError::new_err("invalid key"),
+        ErrorStates::EncryptionFailed => EncryptionError::new_err("encryption failed"),
+        ErrorStates::DecryptionFailed => {
+            DecryptionError::new_err("decryption failed (wrong key or corrupted data)")
+        }
+        ErrorStates::CommitmentMismatch => {
+            CommitmentMismatchError::new_err("blob was not sealed under this key")
+        }
+        ErrorStates::KeyReuse => KeyReuseError::new_err("the same key was used for two roles"),
+        ErrorStates::StreamTruncated => {
+            StreamTruncatedError::new_err("encrypted stream is truncated")
+        }
+        ErrorStates::KeyExhausted => {
+            KeyExhaustedError::new_err("key has reached its operation limit")
+        }
+        ErrorStates::WrongKey => {
+            WrongKeyError::new_err("wrong key (does not match the key check value)")
+        }
+        ErrorStates::AadMismatch => {
+            DecryptionError::new_err("associated data does not match what the data was bound to")
+        }
+    }
+}
+
+fn fs_to_py_err(e: FsCryptoError) -> PyErr {
+    match e {
+        FsCryptoError::Crypto(e) => to_py_err(e),
+        FsCryptoError::Header(_) => HeaderError::new_err(e.to_string()),
+        FsCryptoError::Io(e) => PyOSError::new_err(e.to_string()),
+    }
+}
+
+fn check_key(key: &[u8]) -> PyResult<()> {
+    if key.len() != KEY_LEN {
+        return Err(InvalidKeyError::new_err(format!(
+            "key must be {} bytes, got {}",
+            KEY_LEN,
+            key.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Encrypt with ChaCha20-Poly1305 under a fresh random nonce, returning
+/// nonce || ciphertext || tag.
+#[pyfunction]
+#[pyo3(signature = (key, plaintext, aad = &[] as &[u8]), text_signature = "(key, plaintext, aad=b\"\")")]
+fn encrypt<'py>(
+    py: Python<'py>,
+    key: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> PyResult<Bound<'py, PyBytes>> {
+    check_key(key)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    generate_nonce(&mut nonce);
+
+    let sealed = chacha20_poly1305_cipher_with_aad(key, &nonce, aad, plaintext.to_vec(), true)
+        .map_err(to_py_err)?;
+    let mut blob = nonce.to_vec();
+    blob.extend_from_slice(&sealed);
+    Ok(PyBytes::new(py, &blob))
+}
+
+/// Decrypt a blob produced by `encrypt`.
+#[pyfunction]
+#[pyo3(signature = (key, blob, aad = &[] as &[u8]), text_signature = "(key, blob, aad=b\"\")")]
+fn decrypt<'py>(
+    py: Python<'py>,
+    key: &[u8],
+    blob: &[u8],
+    aad: &[u8],
+) -> PyResult<Bound<'py, PyBytes>> {
+    check_key(key)?;
+    if blob.len() < NONCE_LEN {
+        return Err(to_py_err(ErrorStates::DecryptionFailed));
+    }
+    let (nonce, sealed) = blob.split_at(NONCE_LEN);
+    let plaintext = chacha20_poly1305_cipher_with_aad(
+        key,
+        nonce.try_into().unwrap(),
+        aad,
+        sealed.to_vec(),
+        false,
+    )
+    .map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &plaintext))
+}
+
+/// 32 random bytes from the system's secure RNG.
+#[pyfunction]
+fn generate_key(py: Python<'_>) -> Bound<'_, PyBytes> {
+    let mut key = [0u8; KEY_LEN];
+    fill_key(&mut key);
+    PyBytes::new(py, &key)
+}
+
+/// XOR `data` with the RC4 keystream for `key` (5 to 256 bytes).
+#[pyfunction]
+fn rc4_apply<'py>(py: Python<'py>, key: &[u8], data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    if !(5..=256).contains(&key.len()) {
+        return Err(InvalidKeyError::new_err("RC4 keys are 5 to 256 bytes"));
+    }
+    let mut data = data.to_vec();
+    Rc4::new(key).apply_keystream(&mut data);
+    Ok(PyBytes::new(py, &data))
+}
+
+/// Decrypt a complete rcli file (as written by `rcli chacha --encrypt` or
+/// `StreamEncryptor`).
+#[pyfunction]
+fn decrypt_stream<'py>(py: Python<'py>, key: &[u8], data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    check_key(key)?;
+    let mut plaintext = Vec::new();
+    decrypt_stream_into(&mut &data[..], &mut plaintext, key).map_err(fs_to_py_err)?;
+    Ok(PyBytes::new(py, &plaintext))
+}
+
+/* Splits pushed data into sealed chunks. A full chunk is held back until
+ * more data arrives, since the last chunk must be sealed as final even when
+ * it is full (matching crypto::fs::encrypt_stream). */
+struct Chunker {
+    sealer: Option<ChunkSealer>,
+    header: Option<Vec<u8>>,
+    chunk_size: usize,
+    pending: Vec<u8>,
+}
+
+impl Chunker {
+    fn new(key: &[u8], chunk_size: u32) -> Result<Self, String> {
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        generate_nonce(&mut nonce_prefix);
+
+        let header =
+            FileHeader::new(Algorithm::ChaCha20Poly1305, chunk_size, nonce_prefix).to_bytes();
+        FileHeader::parse(&header).map_err(|_| format!("unsupported chunk size {}", chunk_size))?;
+        let sealer =
+            ChunkSealer::new(key, &nonce_prefix, &header).map_err(|e| format!("{:?}", e))?;
+
+        Ok(Chunker {
+            sealer: Some(sealer),
+            header: Some(header),
+            chunk_size: chunk_size as usize,
+            pending: Vec::new(),
+        })
+    }
+
+    fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, ErrorStates> {
+        let sealer = self.sealer.as_mut().ok_or(ErrorStates::EncryptionFailed)?;
+        let mut out = self.header.take().unwrap_or_default();
+        self.pending.extend_from_slice(data);
+
+        let mut start = 0;
+        while self.pending.len() - start > self.chunk_size {
+            let mut chunk = self.pending[start..start + self.chunk_size].to_vec();
+            sealer.seal_chunk(&mut chunk)?;
+            out.extend_from_slice(&chunk);
+            start += self.chunk_size;
+        }
+        self.pending.drain(..start);
+        Ok(out)
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, ErrorStates> {
+        let sealer = self.sealer.take().ok_or(ErrorStates::EncryptionFailed)?;
+        let mut out = self.header.take().unwrap_or_default();
+        let mut chunk = core::mem::take(&mut self.pending);
+        sealer.seal_last(&mut chunk)?;
+        out.extend_from_slice(&chunk);
+        Ok(out)
+    }
+}
+
+/// Incremental encryption to the rcli file format. Concatenating every
+/// `push_chunk` result and the `finish` result gives the complete file.
+#[pyclass]
+struct StreamEncryptor(Chunker);
+
+#[pymethods]
+impl StreamEncryptor {
+    #[new]
+    #[pyo3(signature = (key, chunk_size = DEFAULT_CHUNK_SIZE))]
+    fn new(key: &[u8], chunk_size: u32) -> PyResult<Self> {
+        check_key(key)?;
+        Chunker::new(key, chunk_size)
+            .map(StreamEncryptor)
+            .map_err(PyValueError::new_err)
+    }
+
+    /// Add plaintext, returning whatever output is ready (possibly empty).
+    fn push_chunk<'py>(&mut self, py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+        let out = self
+            .0
+            .push(data)
+            .map_err(|_| PyValueError::new_err("stream already finished"))?;
+        Ok(PyBytes::new(py, &out))
+    }
+
+    /// Seal the final chunk. The encryptor cannot be used afterwards.
+    fn finish<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let out = self
+            .0
+            .finish()
+            .map_err(|_| PyValueError::new_err("stream already finished"))?;
+        Ok(PyBytes::new(py, &out))
+    }
+}
+
+#[pymodule]
+mod crypto_py {
+    #[pymodule_export]
+    use super::{
+        decrypt, decrypt_stream, encrypt, generate_key, rc4_apply, CommitmentMismatchError,
+        CryptoError, DecryptionError, EncryptionError, HeaderError, InvalidKeyError,
+        KeyExhaustedError, KeyReuseError, StreamEncryptor, StreamTruncatedError, WrongKeyError,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::fs::{encrypt_stream, EncryptOptions};
+    use crypto::header::MIN_CHUNK_SIZE;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    fn push_all(pieces: &[&[u8]]) -> Vec<u8> {
+        let mut chunker = Chunker::new(&KEY, MIN_CHUNK_SIZE).unwrap();
+        let mut out = Vec::new();
+        for piece in pieces {
+            out.extend(chunker.push(piece).unwrap());
+        }
+        out.extend(chunker.finish().unwrap());
+        out
+    }
+
+    fn open(file: &[u8]) -> Vec<u8> {
+        let mut plain = Vec::new();
+        decrypt_stream_into(&mut &file[..], &mut plain, &KEY).unwrap();
+        plain
+    }
+
+    #[test]
+    fn chunker_matches_file_layout() {
+        let size = MIN_CHUNK_SIZE as usize;
+        let data: Vec<u8> = (0..size * 3).map(|i| i as u8).collect();
+
+        for len in [0, 1, size - 1, size, size + 1, 2 * size, 3 * size] {
+            let split = len / 3;
+            let pushed = push_all(&[&data[..split], &[], &data[split..len]]);
+            assert_eq!(open(&pushed), data[..len]);
+
+            /* Same length as encrypt_stream output (only the nonce differs) */
+            let mut expected = Vec::new();
+            let opts = EncryptOptions {
+                chunk_size: MIN_CHUNK_SIZE,
+                ..EncryptOptions::default()
+            };
+            encrypt_stream(&mut &data[..len], &mut expected, &KEY, &opts).unwrap();
+            assert_eq!(pushed.len(), expected.len(), "len {}", len);
+        }
+    }
+
+    #[test]
+    fn chunker_rejects_use_after_finish() {
+        let mut chunker = Chunker::new(&KEY, MIN_CHUNK_SIZE).unwrap();
+        chunker.finish().unwrap();
+        assert!(chunker.push(b"more").is_err());
+        assert!(chunker.finish().is_err());
+        assert!(Chunker::new(&KEY, 16).is_err());
+    }
+}
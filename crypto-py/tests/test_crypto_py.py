@@ -0,0 +1,151 @@
+"""Run with: maturin develop && pytest tests (from crypto-py/).
+
+The CLI test invokes `cargo run -p rcli`, or $RCLI if set to a built binary.
+"""
+import os
+import subprocess
+from pathlib import Path
+
+import pytest
+
+import crypto_py
+
+WORKSPACE = Path(__file__).resolve().parents[2]
+KEY = bytes(range(32))
+
+
+def test_round_trip():
+    key = crypto_py.generate_key()
+    for msg in [b"", b"a", b"Hello World!", os.urandom(100_000)]:
+        blob = crypto_py.encrypt(key, msg)
+        assert len(blob) == 12 + len(msg) + 16
+        assert crypto_py.decrypt(key, blob) == msg
+
+
+def test_round_trip_with_aad():
+    blob = crypto_py.encrypt(KEY, b"payload", aad=b"header")
+    assert crypto_py.decrypt(KEY, blob, b"header") == b"payload"
+    with pytest.raises(crypto_py.DecryptionError):
+        crypto_py.decrypt(KEY, blob)
+    with pytest.raises(crypto_py.DecryptionError):
+        crypto_py.decrypt(KEY, blob, aad=b"other")
+
+
+def test_fresh_nonce_per_call():
+    assert crypto_py.encrypt(KEY, b"same") != crypto_py.encrypt(KEY, b"same")
+
+
+def test_generate_key():
+    a, b = crypto_py.generate_key(), crypto_py.generate_key()
+    assert isinstance(a, bytes) and len(a) == 32
+    assert a != b
+
+
+def test_tampering_and_wrong_key():
+    blob = bytearray(crypto_py.encrypt(KEY, b"secret"))
+    blob[-1] ^= 1
+    with pytest.raises(crypto_py.DecryptionError):
+        crypto_py.decrypt(KEY, bytes(blob))
+    with pytest.raises(crypto_py.DecryptionError):
+        crypto_py.decrypt(bytes(32), crypto_py.encrypt(KEY, b"secret"))
+    with pytest.raises(crypto_py.DecryptionError):
+        crypto_py.decrypt(KEY, b"short")
+
+
+def test_exception_hierarchy():
+    classes = [
+        crypto_py.InvalidKeyError,
+        crypto_py.EncryptionError,
+        crypto_py.DecryptionError,
+        crypto_py.CommitmentMismatchError,
+        crypto_py.KeyReuseError,
+        crypto_py.StreamTruncatedError,
+        crypto_py.KeyExhaustedError,
+        crypto_py.WrongKeyError,
+        crypto_py.HeaderError,
+    ]
+    assert len(set(classes)) == len(classes)
+    for cls in classes:
+        assert issubclass(cls, crypto_py.CryptoError)
+    assert issubclass(crypto_py.WrongKeyError, crypto_py.DecryptionError)
+    assert issubclass(crypto_py.CryptoError, Exception)
+
+
+def test_invalid_keys():
+    with pytest.raises(crypto_py.InvalidKeyError):
+        crypto_py.encrypt(b"too short", b"data")
+    with pytest.raises(crypto_py.InvalidKeyError):
+        crypto_py.decrypt(bytes(33), bytes(40))
+    with pytest.raises(crypto_py.InvalidKeyError):
+        crypto_py.rc4_apply(b"1234", b"data")
+    with pytest.raises(crypto_py.InvalidKeyError):
+        crypto_py.StreamEncryptor(bytes(16))
+
+
+# RFC 6229, 40-bit key 0x0102030405, keystream at offset 0
+def test_rc4_known_answer():
+    keystream = crypto_py.rc4_apply(bytes([1, 2, 3, 4, 5]), bytes(16))
+    assert keystream.hex() == "b2396305f03dc027ccc3524a0a1118a8"
+    data = b"symmetric"
+    assert crypto_py.rc4_apply(b"Key01", crypto_py.rc4_apply(b"Key01", data)) == data
+
+
+@pytest.mark.parametrize("length", [0, 1, 4095, 4096, 4097, 3 * 4096])
+def test_stream_round_trip(length):
+    data = os.urandom(length)
+    enc = crypto_py.StreamEncryptor(KEY, chunk_size=4096)
+    out = b""
+    for i in range(0, len(data), 1000):
+        out += enc.push_chunk(data[i : i + 1000])
+    out += enc.finish()
+    assert out[:4] == b"RCLI"
+    assert crypto_py.decrypt_stream(KEY, out) == data
+
+
+def test_stream_errors():
+    enc = crypto_py.StreamEncryptor(KEY, chunk_size=4096)
+    out = enc.push_chunk(bytes(10_000)) + enc.finish()
+    with pytest.raises(ValueError):
+        enc.push_chunk(b"late")
+    with pytest.raises(ValueError):
+        crypto_py.StreamEncryptor(KEY, chunk_size=1)
+
+    with pytest.raises(crypto_py.StreamTruncatedError):
+        crypto_py.decrypt_stream(KEY, out[: 19 + 4096 + 16])
+    with pytest.raises(crypto_py.DecryptionError):
+        crypto_py.decrypt_stream(bytes(32), out)
+    with pytest.raises(crypto_py.HeaderError):
+        crypto_py.decrypt_stream(KEY, b"NOPE" + out[4:])
+
+
+def run_rcli(*args):
+    rcli = os.environ.get("RCLI")
+    cmd = [rcli] if rcli else ["cargo", "run", "-q", "-p", "rcli", "--"]
+    subprocess.run(cmd + list(args), cwd=WORKSPACE, check=True, capture_output=True)
+
+
+def test_cli_encrypted_file_decrypts_in_python(tmp_path):
+    path = tmp_path / "message.txt"
+    data = b"written by rcli\n" * 1000
+    path.write_bytes(data)
+
+    run_rcli("chacha", "--encrypt", "-f", str(path), "-k", *[f"{b:02x}" for b in KEY])
+    assert crypto_py.decrypt_stream(KEY, path.read_bytes()) == data
+
+
+def test_python_stream_decrypts_with_cli(tmp_path):
+    data = b"written by python\n" * 1000
+    enc = crypto_py.StreamEncryptor(KEY)
+    path = tmp_path / "message.rcli"
+    path.write_bytes(enc.push_chunk(data) + enc.finish())
+
+    run_rcli("chacha", "--decrypt", "-f", str(path), "-k", *[f"{b:02x}" for b in KEY])
+    assert path.read_bytes() == data
+
+
+def test_python_blob_decrypts_with_cli(tmp_path):
+    path = tmp_path / "legacy.bin"
+    path.write_bytes(crypto_py.encrypt(KEY, b"single-shot blob"))
+
+    run_rcli("chacha", "--decrypt", "-f", str(path), "-k", *[f"{b:02x}" for b in KEY])
+    assert path.read_bytes() == b"single-shot blob"
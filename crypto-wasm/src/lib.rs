@@ -0,0 +1,551 @@
+/* JavaScript/WebAssembly bindings (wasm-bindgen).
+ *
+ * `encrypt` returns nonce (12 bytes) || ciphertext || tag, matching
+ * crypto-py and the CLI's single-shot format. `StreamEncryptor` and
+ * `StreamDecryptor` speak the chunked rcli file format and accept input in
+ * arbitrary pieces, so a file can be piped from a ReadableStream without
+ * ever holding all of it in memory.
+ *
+ * Errors are thrown as `Error` objects named "CryptoError" carrying a
+ * stable `.code` string (see `ErrorCode::as_str`); messages may change,
+ * codes may not. */
+use wasm_bindgen::prelude::*;
+
+use crypto::header::{
+    Algorithm, FileHeader, HeaderError, DEFAULT_CHUNK_SIZE, HEADER_FIXED_LEN, NONCE_PREFIX_LEN,
+};
+use crypto::stream::{StreamDecryptor as ChunkOpener, StreamEncryptor as ChunkSealer, TAG_LEN};
+use crypto::{
+    chacha20_poly1305_cipher_with_aad, generate_key as fill_key, generate_nonce, key_check_value,
+    ErrorStates,
+};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorCode {
+    InvalidKey,
+    EncryptionFailed,
+    DecryptionFailed,
+    CommitmentMismatch,
+    KeyReuse,
+    StreamTruncated,
+    KeyExhausted,
+    WrongKey,
+    /* Input is not an rcli file, or its header is unsupported */
+    InvalidHeader,
+    /* push or finish called after finish */
+    StreamFinished,
+    /* Chunk size outside MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE */
+    InvalidChunkSize,
+}
+
+impl ErrorCode {
+    fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidKey => "INVALID_KEY",
+            ErrorCode::EncryptionFailed => "ENCRYPTION_FAILED",
+            ErrorCode::DecryptionFailed => "DECRYPTION_FAILED",
+            ErrorCode::CommitmentMismatch => "COMMITMENT_MISMATCH",
+            ErrorCode::KeyReuse => "KEY_REUSE",
+            ErrorCode::StreamTruncated => "STREAM_TRUNCATED",
+            ErrorCode::KeyExhausted => "KEY_EXHAUSTED",
+            ErrorCode::WrongKey => "WRONG_KEY",
+            ErrorCode::InvalidHeader => "INVALID_HEADER",
+            ErrorCode::StreamFinished => "STREAM_FINISHED",
+            ErrorCode::InvalidChunkSize => "INVALID_CHUNK_SIZE",
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidKey => "key must be 32 bytes",
+            ErrorCode::EncryptionFailed => "encryption failed",
+            ErrorCode::DecryptionFailed => "decryption failed (wrong key or corrupted data)",
+            ErrorCode::CommitmentMismatch => "blob was not sealed under this key",
+            ErrorCode::KeyReuse => "the same key was used for two roles",
+            ErrorCode::StreamTruncated => "encrypted stream is truncated",
+            ErrorCode::KeyExhausted => "key has reached its operation limit",
+            ErrorCode::WrongKey => "wrong key (does not match the key check value)",
+            ErrorCode::InvalidHeader => "not an rcli encrypted file, or unsupported header",
+            ErrorCode::StreamFinished => "stream already finished",
+            ErrorCode::InvalidChunkSize => "unsupported chunk size",
+        }
+    }
+}
+
+impl From<ErrorStates> for ErrorCode {
+    fn from(e: ErrorStates) -> Self {
+        match e {
+            ErrorStates::KeyInitializationFailed => ErrorCode::InvalidKey,
+            ErrorStates::EncryptionFailed => ErrorCode::EncryptionFailed,
+            ErrorStates::DecryptionFailed => ErrorCode::DecryptionFailed,
+            ErrorStates::CommitmentMismatch => ErrorCode::CommitmentMismatch,
+            ErrorStates::KeyReuse => ErrorCode::KeyReuse,
+            ErrorStates::StreamTruncated => ErrorCode::StreamTruncated,
+            ErrorStates::KeyExhausted => ErrorCode::KeyExhausted,
+            ErrorStates::WrongKey => ErrorCode::WrongKey,
+            /* Streams here are never bound to associated data */
+            ErrorStates::AadMismatch => ErrorCode::DecryptionFailed,
+        }
+    }
+}
+
+impl From<HeaderError> for ErrorCode {
+    fn from(_: HeaderError) -> Self {
+        ErrorCode::InvalidHeader
+    }
+}
+
+impl From<ErrorCode> for JsValue {
+    fn from(code: ErrorCode) -> Self {
+        let error = js_sys::Error::new(code.message());
+        error.set_name("CryptoError");
+        /* Setting a property on a fresh Error object cannot fail */
+        let _ = js_sys::Reflect::set(&error, &"code".into(), &code.as_str().into());
+        error.into()
+    }
+}
+
+fn check_key(key: &[u8]) -> Result<[u8; KEY_LEN], ErrorCode> {
+    key.try_into().map_err(|_| ErrorCode::InvalidKey)
+}
+
+fn seal_blob(key: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+    check_key(key)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    generate_nonce(&mut nonce);
+
+    let sealed = chacha20_poly1305_cipher_with_aad(key, &nonce, aad, plaintext.to_vec(), true)?;
+    let mut blob = nonce.to_vec();
+    blob.extend_from_slice(&sealed);
+    Ok(blob)
+}
+
+fn open_blob(key: &[u8], blob: &[u8], aad: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+    check_key(key)?;
+    if blob.len() < NONCE_LEN + TAG_LEN {
+        return Err(ErrorCode::DecryptionFailed);
+    }
+    let (nonce, sealed) = blob.split_at(NONCE_LEN);
+    let nonce: &[u8; NONCE_LEN] = nonce.try_into().unwrap();
+    Ok(chacha20_poly1305_cipher_with_aad(
+        key,
+        nonce,
+        aad,
+        sealed.to_vec(),
+        false,
+    )?)
+}
+
+/// Encrypt with ChaCha20-Poly1305 under a fresh random nonce, returning
+/// nonce || ciphertext || tag.
+#[wasm_bindgen]
+pub fn encrypt(key: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, JsValue> {
+    Ok(seal_blob(key, plaintext, aad)?)
+}
+
+/// Decrypt a blob produced by `encrypt`.
+#[wasm_bindgen]
+pub fn decrypt(key: &[u8], blob: &[u8], aad: &[u8]) -> Result<Vec<u8>, JsValue> {
+    Ok(open_blob(key, blob, aad)?)
+}
+
+/// 32 random bytes from `crypto.getRandomValues`.
+#[wasm_bindgen(js_name = generateKey)]
+pub fn generate_key() -> Vec<u8> {
+    let mut key = vec![0u8; KEY_LEN];
+    fill_key(&mut key);
+    key
+}
+
+/* Both directions hold back one full chunk until more input arrives: the
+ * final chunk is sealed as last even when it is full, so a full chunk can
+ * only be processed as non-final once something follows it. This is the
+ * same framing as crypto::fs::{encrypt_stream, decrypt_stream}. */
+struct Sealer {
+    sealer: Option<ChunkSealer>,
+    header: Option<Vec<u8>>,
+    chunk_size: usize,
+    pending: Vec<u8>,
+}
+
+impl Sealer {
+    fn new(key: &[u8], chunk_size: u32) -> Result<Self, ErrorCode> {
+        check_key(key)?;
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        generate_nonce(&mut nonce_prefix);
+
+        let header =
+            FileHeader::new(Algorithm::ChaCha20Poly1305, chunk_size, nonce_prefix).to_bytes();
+        FileHeader::parse(&header).map_err(|_| ErrorCode::InvalidChunkSize)?;
+
+        Ok(Sealer {
+            sealer: Some(ChunkSealer::new(key, &nonce_prefix, &header)?),
+            header: Some(header),
+            chunk_size: chunk_size as usize,
+            pending: Vec::new(),
+        })
+    }
+
+    fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+        let sealer = self.sealer.as_mut().ok_or(ErrorCode::StreamFinished)?;
+        let mut out = self.header.take().unwrap_or_default();
+        self.pending.extend_from_slice(data);
+
+        let mut start = 0;
+        while self.pending.len() - start > self.chunk_size {
+            let mut chunk = self.pending[start..start + self.chunk_size].to_vec();
+            sealer.seal_chunk(&mut chunk)?;
+            out.extend_from_slice(&chunk);
+            start += self.chunk_size;
+        }
+        self.pending.drain(..start);
+        Ok(out)
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, ErrorCode> {
+        let sealer = self.sealer.take().ok_or(ErrorCode::StreamFinished)?;
+        let mut out = self.header.take().unwrap_or_default();
+        let mut chunk = core::mem::take(&mut self.pending);
+        sealer.seal_last(&mut chunk)?;
+        out.extend_from_slice(&chunk);
+        Ok(out)
+    }
+}
+
+enum OpenerState {
+    /* Still collecting the header; the key is kept until it is complete */
+    Header([u8; KEY_LEN]),
+    Body {
+        opener: Box<ChunkOpener>,
+        sealed_size: usize,
+    },
+    Finished,
+}
+
+struct Opener {
+    state: OpenerState,
+    pending: Vec<u8>,
+}
+
+impl Opener {
+    fn new(key: &[u8]) -> Result<Self, ErrorCode> {
+        Ok(Opener {
+            state: OpenerState::Header(check_key(key)?),
+            pending: Vec::new(),
+        })
+    }
+
+    fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+        if let OpenerState::Finished = self.state {
+            return Err(ErrorCode::StreamFinished);
+        }
+        self.pending.extend_from_slice(data);
+
+        if let OpenerState::Header(key) = &self.state {
+            match FileHeader::parse(&self.pending) {
+                /* Decompression, unpadding and AES-GCM are not built for
+                 * wasm */
+                Ok((header, _))
+                    if header.compression.is_some()
+                        || header.padding.is_some()
+                        || header.algorithm != Algorithm::ChaCha20Poly1305 =>
+                {
+                    return Err(ErrorCode::InvalidHeader)
+                }
+                Ok((header, _))
+                    if header
+                        .key_check
+                        .is_some_and(|kcv| kcv != key_check_value(key)) =>
+                {
+                    return Err(ErrorCode::WrongKey)
+                }
+                Ok((header, len)) => {
+                    let open = if header.ratchet {
+                        ChunkOpener::new_ratcheting
+                    } else {
+                        ChunkOpener::new
+                    };
+                    let opener = Box::new(open(key, &header.nonce_prefix, &self.pending[..len])?);
+                    self.pending.drain(..len);
+                    self.state = OpenerState::Body {
+                        opener,
+                        sealed_size: header.chunk_size as usize + TAG_LEN,
+                    };
+                }
+                Err(HeaderError::Truncated) => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let mut out = Vec::new();
+        if let OpenerState::Body {
+            opener,
+            sealed_size,
+        } = &mut self.state
+        {
+            let mut start = 0;
+            while self.pending.len() - start > *sealed_size {
+                let mut chunk = self.pending[start..start + *sealed_size].to_vec();
+                opener.open_chunk(&mut chunk)?;
+                out.extend_from_slice(&chunk);
+                start += *sealed_size;
+            }
+            self.pending.drain(..start);
+        }
+        Ok(out)
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, ErrorCode> {
+        match core::mem::replace(&mut self.state, OpenerState::Finished) {
+            OpenerState::Finished => Err(ErrorCode::StreamFinished),
+            OpenerState::Header(_) => Err(if self.pending.len() < HEADER_FIXED_LEN {
+                ErrorCode::StreamTruncated
+            } else {
+                ErrorCode::InvalidHeader
+            }),
+            OpenerState::Body { opener, .. } => {
+                let mut chunk = core::mem::take(&mut self.pending);
+                if chunk.len() < TAG_LEN {
+                    return Err(ErrorCode::StreamTruncated);
+                }
+                opener.open_last(&mut chunk)?;
+                Ok(chunk)
+            }
+        }
+    }
+}
+
+/// Incremental encryption to the rcli file format. Concatenating every
+/// `push` result and the `finish` result gives the complete file.
+#[wasm_bindgen]
+pub struct StreamEncryptor(Sealer);
+
+#[wasm_bindgen]
+impl StreamEncryptor {
+    /// `chunkSize` defaults to 64 KiB.
+    #[wasm_bindgen(constructor)]
+    pub fn new(key: &[u8], chunk_size: Option<u32>) -> Result<StreamEncryptor, JsValue> {
+        Ok(StreamEncryptor(Sealer::new(
+            key,
+            chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE),
+        )?))
+    }
+
+    /// Add plaintext, returning whatever ciphertext is ready (possibly empty).
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, JsValue> {
+        Ok(self.0.push(data)?)
+    }
+
+    /// Seal the final chunk. The encryptor cannot be used afterwards.
+    pub fn finish(&mut self) -> Result<Vec<u8>, JsValue> {
+        Ok(self.0.finish()?)
+    }
+}
+
+/// Incremental decryption of an rcli file. Plaintext is only returned once
+/// its chunk authenticates, but the stream as a whole is not known to be
+/// complete until `finish` succeeds.
+#[wasm_bindgen]
+pub struct StreamDecryptor(Opener);
+
+#[wasm_bindgen]
+impl StreamDecryptor {
+    #[wasm_bindgen(constructor)]
+    pub fn new(key: &[u8]) -> Result<StreamDecryptor, JsValue> {
+        Ok(StreamDecryptor(Opener::new(key)?))
+    }
+
+    /// Add ciphertext, returning whatever plaintext is ready (possibly empty).
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, JsValue> {
+        Ok(self.0.push(data)?)
+    }
+
+    /// Open the final chunk, failing if the stream was cut short.
+    pub fn finish(&mut self) -> Result<Vec<u8>, JsValue> {
+        Ok(self.0.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::header::MIN_CHUNK_SIZE;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    /* Key 00 01 .. 1f used to produce the tests/fixture* files */
+    const FIXTURE_KEY: [u8; 32] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30, 31,
+    ];
+
+    fn fixture_plaintext() -> Vec<u8> {
+        (0..2500u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn open_in_pieces(key: &[u8], file: &[u8], piece: usize) -> Result<Vec<u8>, ErrorCode> {
+        let mut opener = Opener::new(key)?;
+        let mut out = Vec::new();
+        for data in file.chunks(piece) {
+            out.extend(opener.push(data)?);
+        }
+        out.extend(opener.finish()?);
+        Ok(out)
+    }
+
+    // crypto-py StreamEncryptor(key, chunk_size=1024) over the fixture
+    // plaintext; also checked with `rcli chacha --decrypt`
+    #[test]
+    fn native_stream_fixture() {
+        let file = include_bytes!("../tests/fixture.rcli");
+        for piece in [1, 7, 19, 20, 1040, 1041, 4096] {
+            assert_eq!(
+                open_in_pieces(&FIXTURE_KEY, file, piece),
+                Ok(fixture_plaintext()),
+                "piece {}",
+                piece
+            );
+        }
+    }
+
+    // crypto-py encrypt(key, b"Hello from the native crate!", b"fixture aad")
+    #[test]
+    fn native_blob_fixture() {
+        let blob = include_bytes!("../tests/fixture_blob.bin");
+        assert_eq!(
+            open_blob(&FIXTURE_KEY, blob, b"fixture aad").as_deref(),
+            Ok(&b"Hello from the native crate!"[..])
+        );
+        assert_eq!(
+            open_blob(&FIXTURE_KEY, blob, b""),
+            Err(ErrorCode::DecryptionFailed)
+        );
+    }
+
+    #[test]
+    fn blob_round_trip_and_errors() {
+        let blob = seal_blob(&KEY, b"payload", b"aad").unwrap();
+        assert_eq!(blob.len(), NONCE_LEN + 7 + TAG_LEN);
+        assert_eq!(open_blob(&KEY, &blob, b"aad").unwrap(), b"payload");
+        assert_eq!(
+            open_blob(&[0; 32], &blob, b"aad"),
+            Err(ErrorCode::DecryptionFailed)
+        );
+        assert_eq!(
+            open_blob(&KEY, &blob[..20], b"aad"),
+            Err(ErrorCode::DecryptionFailed)
+        );
+        assert_eq!(seal_blob(&KEY[..31], b"", b""), Err(ErrorCode::InvalidKey));
+        assert_ne!(
+            seal_blob(&KEY, b"x", b"").unwrap(),
+            seal_blob(&KEY, b"x", b"").unwrap()
+        );
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        let size = MIN_CHUNK_SIZE as usize;
+        let data: Vec<u8> = (0..size * 3).map(|i| i as u8).collect();
+
+        for len in [0, 1, size - 1, size, size + 1, 2 * size, 3 * size] {
+            let mut sealer = Sealer::new(&KEY, MIN_CHUNK_SIZE).unwrap();
+            let mut file = Vec::new();
+            for piece in data[..len].chunks(300) {
+                file.extend(sealer.push(piece).unwrap());
+            }
+            file.extend(sealer.finish().unwrap());
+
+            let mut plain = Vec::new();
+            crypto::fs::decrypt_stream(&mut &file[..], &mut plain, &KEY).unwrap();
+            assert_eq!(plain, data[..len], "len {}", len);
+            assert_eq!(open_in_pieces(&KEY, &file, 333).unwrap(), data[..len]);
+        }
+    }
+
+    #[test]
+    fn stream_errors() {
+        let file = include_bytes!("../tests/fixture.rcli");
+        assert_eq!(
+            open_in_pieces(&KEY, file, 100),
+            Err(ErrorCode::DecryptionFailed)
+        );
+        /* Dropping the last chunk leaves a non-final chunk at the end */
+        assert_eq!(
+            open_in_pieces(&FIXTURE_KEY, &file[..19 + 2 * 1040], 100),
+            Err(ErrorCode::StreamTruncated)
+        );
+        assert_eq!(
+            open_in_pieces(&FIXTURE_KEY, &file[..10], 100),
+            Err(ErrorCode::StreamTruncated)
+        );
+        assert_eq!(
+            open_in_pieces(&FIXTURE_KEY, b"NOPE, not an rcli file", 100),
+            Err(ErrorCode::InvalidHeader)
+        );
+
+        let mut opener = Opener::new(&FIXTURE_KEY).unwrap();
+        opener.push(file).unwrap();
+        opener.finish().unwrap();
+        assert_eq!(opener.push(b"more").err(), Some(ErrorCode::StreamFinished));
+        assert!(Opener::new(&KEY[..16]).is_err());
+
+        let mut sealer = Sealer::new(&KEY, MIN_CHUNK_SIZE).unwrap();
+        sealer.finish().unwrap();
+        assert_eq!(sealer.finish().err(), Some(ErrorCode::StreamFinished));
+        assert!(matches!(
+            Sealer::new(&KEY, 16),
+            Err(ErrorCode::InvalidChunkSize)
+        ));
+    }
+
+    #[test]
+    fn header_extensions() {
+        let seal = |opts: crypto::fs::EncryptOptions| {
+            let mut file = Vec::new();
+            crypto::fs::encrypt_stream(&mut &b"data"[..], &mut file, &KEY, &opts).unwrap();
+            file
+        };
+        let checked = seal(crypto::fs::EncryptOptions {
+            key_check: true,
+            ..Default::default()
+        });
+        assert_eq!(open_in_pieces(&KEY, &checked, 7).unwrap(), b"data");
+        assert_eq!(
+            open_in_pieces(&[0x43; 32], &checked, 7),
+            Err(ErrorCode::WrongKey)
+        );
+
+        let padded = seal(crypto::fs::EncryptOptions {
+            padding: Some(crypto::PaddingScheme::Padme),
+            ..Default::default()
+        });
+        assert_eq!(
+            open_in_pieces(&KEY, &padded, 7),
+            Err(ErrorCode::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn codes_are_distinct() {
+        let codes = [
+            ErrorCode::InvalidKey,
+            ErrorCode::EncryptionFailed,
+            ErrorCode::DecryptionFailed,
+            ErrorCode::CommitmentMismatch,
+            ErrorCode::KeyReuse,
+            ErrorCode::StreamTruncated,
+            ErrorCode::KeyExhausted,
+            ErrorCode::WrongKey,
+            ErrorCode::InvalidHeader,
+            ErrorCode::StreamFinished,
+            ErrorCode::InvalidChunkSize,
+        ];
+        let mut strs: Vec<&str> = codes.iter().map(|c| c.as_str()).collect();
+        strs.sort_unstable();
+        strs.dedup();
+        assert_eq!(strs.len(), codes.len());
+    }
+}
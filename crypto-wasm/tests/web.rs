@@ -0,0 +1,89 @@
+/* Run with: wasm-pack test --node (or --headless --firefox) */
+#![cfg(target_arch = "wasm32")]
+
+use crypto_wasm::{decrypt, encrypt, generate_key, StreamDecryptor, StreamEncryptor};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::*;
+
+const FIXTURE: &[u8] = include_bytes!("fixture.rcli");
+const FIXTURE_BLOB: &[u8] = include_bytes!("fixture_blob.bin");
+
+fn fixture_key() -> Vec<u8> {
+    (0..32).collect()
+}
+
+fn fixture_plaintext() -> Vec<u8> {
+    (0..2500u32).map(|i| (i % 251) as u8).collect()
+}
+
+fn code(err: JsValue) -> String {
+    js_sys::Reflect::get(&err, &"code".into())
+        .unwrap()
+        .as_string()
+        .unwrap()
+}
+
+#[wasm_bindgen_test]
+fn round_trip() {
+    let key = generate_key();
+    assert_eq!(key.len(), 32);
+    assert_ne!(key, generate_key());
+
+    let blob = encrypt(&key, b"Hello World!", b"aad").unwrap();
+    assert_eq!(decrypt(&key, &blob, b"aad").unwrap(), b"Hello World!");
+}
+
+#[wasm_bindgen_test]
+fn typed_errors() {
+    let key = generate_key();
+    let blob = encrypt(&key, b"Hello World!", b"").unwrap();
+
+    let err = decrypt(&key, &blob, b"other").unwrap_err();
+    assert!(err.is_instance_of::<js_sys::Error>());
+    assert_eq!(code(err), "DECRYPTION_FAILED");
+    assert_eq!(
+        code(encrypt(&key[..16], b"", b"").unwrap_err()),
+        "INVALID_KEY"
+    );
+    assert_eq!(
+        code(StreamDecryptor::new(&key).unwrap().finish().unwrap_err()),
+        "STREAM_TRUNCATED"
+    );
+}
+
+// Produced natively; see native_stream_fixture / native_blob_fixture in src/lib.rs
+#[wasm_bindgen_test]
+fn native_fixtures_decrypt() {
+    let key = fixture_key();
+    assert_eq!(
+        decrypt(&key, FIXTURE_BLOB, b"fixture aad").unwrap(),
+        b"Hello from the native crate!"
+    );
+
+    let mut decryptor = StreamDecryptor::new(&key).unwrap();
+    let mut out = Vec::new();
+    for piece in FIXTURE.chunks(500) {
+        out.extend(decryptor.push(piece).unwrap());
+    }
+    out.extend(decryptor.finish().unwrap());
+    assert_eq!(out, fixture_plaintext());
+}
+
+#[wasm_bindgen_test]
+fn stream_round_trip() {
+    let key = generate_key();
+    let data = fixture_plaintext();
+
+    let mut encryptor = StreamEncryptor::new(&key, Some(1024)).unwrap();
+    let mut file = encryptor.push(&data).unwrap();
+    file.extend(encryptor.finish().unwrap());
+    assert_eq!(
+        code(encryptor.push(b"late").unwrap_err()),
+        "STREAM_FINISHED"
+    );
+
+    let mut decryptor = StreamDecryptor::new(&key).unwrap();
+    let mut out = decryptor.push(&file).unwrap();
+    out.extend(decryptor.finish().unwrap());
+    assert_eq!(out, data);
+}
@@ -0,0 +1,120 @@
+/* Throughput of the ChaCha20-Poly1305 paths.
+ *
+ * `chacha20_poly1305_cipher` rebuilds ring's UnboundKey on every call;
+ * `StreamEncryptor` builds it once and reuses it per chunk, so the gap
+ * between the two groups is the cost of key setup. Run with
+ * `cargo bench -p crypto`. */
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use crypto::chacha20_poly1305_cipher;
+use crypto::stream::StreamEncryptor;
+
+const KEY: [u8; 32] = [0x42; 32];
+const NONCE: [u8; 12] = [0x24; 12];
+const NONCE_PREFIX: [u8; 7] = [0x24; 7];
+
+const SIZES: [(usize, &str); 3] = [(1 << 10, "1KiB"), (64 << 10, "64KiB"), (8 << 20, "8MiB")];
+
+const SMALL_COUNT: usize = 4096;
+const SMALL_LEN: usize = 256;
+
+fn one_shot(c: &mut Criterion) {
+    let mut group = c.benchmark_group("one_shot");
+    for (len, name) in SIZES {
+        let data = vec![0x5a; len];
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("encrypt", name), &data, |b, data| {
+            b.iter_batched(
+                || data.clone(),
+                |data| chacha20_poly1305_cipher(&KEY, &NONCE, black_box(data), true).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn reused_context(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reused_context");
+    for (len, name) in SIZES {
+        let data = vec![0x5a; len];
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("seal_chunk", name), &data, |b, data| {
+            /* Key setup happens in the untimed setup closure */
+            b.iter_batched(
+                || {
+                    let encryptor = StreamEncryptor::new(&KEY, &NONCE_PREFIX, &[]).unwrap();
+                    (encryptor, data.clone())
+                },
+                |(mut encryptor, mut chunk)| {
+                    encryptor.seal_chunk(black_box(&mut chunk)).unwrap();
+                    chunk
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn many_small_messages(c: &mut Criterion) {
+    let messages = vec![vec![0x5a; SMALL_LEN]; SMALL_COUNT];
+    let mut group = c.benchmark_group("many_small_messages");
+    group.throughput(Throughput::Bytes((SMALL_COUNT * SMALL_LEN) as u64));
+
+    group.bench_function("one_shot", |b| {
+        b.iter_batched(
+            || messages.clone(),
+            |messages| {
+                for message in messages {
+                    black_box(chacha20_poly1305_cipher(&KEY, &NONCE, message, true).unwrap());
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("reused_context", |b| {
+        b.iter_batched(
+            || messages.clone(),
+            |messages| {
+                let mut encryptor = StreamEncryptor::new(&KEY, &NONCE_PREFIX, &[]).unwrap();
+                for mut message in messages {
+                    encryptor.seal_chunk(&mut message).unwrap();
+                    black_box(message);
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn authentication_failure(c: &mut Criterion) {
+    let mut group = c.benchmark_group("authentication_failure");
+    for (len, name) in SIZES {
+        let mut sealed = chacha20_poly1305_cipher(&KEY, &NONCE, vec![0x5a; len], true).unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("decrypt", name), &sealed, |b, sealed| {
+            b.iter_batched(
+                || sealed.clone(),
+                |sealed| {
+                    chacha20_poly1305_cipher(&KEY, &NONCE, black_box(sealed), false).unwrap_err()
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    one_shot,
+    reused_context,
+    many_small_messages,
+    authentication_failure
+);
+criterion_main!(benches);
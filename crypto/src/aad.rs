@@ -0,0 +1,223 @@
+/* Canonical associated data built from several fields.
+ *
+ * Concatenating fields directly is ambiguous ("ab" + "c" and "a" + "bc"
+ * give the same bytes), so a ciphertext bound to one set of fields would
+ * also open under another. Here every field carries a type tag and a
+ * length, after a domain-separation label:
+ *
+ *   len(label) (u32) || label
+ *   then per field: tag (u8) || len(value) (u32) || value
+ *
+ * Integers are big-endian; tags are 1 = bytes, 2 = UTF-8 string, 3 = u64
+ * (an 8-byte value). The encoding is injective: no two different label
+ * and field lists produce the same bytes.
+ *
+ * The builder only borrows its fields, so `finish_into` encodes without
+ * allocating. */
+use alloc::vec::Vec;
+
+/// Most fields one builder holds.
+pub const MAX_AAD_FIELDS: usize = 16;
+
+const TAG_BYTES: u8 = 1;
+const TAG_STR: u8 = 2;
+const TAG_U64: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AadError {
+    /* The output buffer is shorter than `encoded_len` */
+    BufferTooSmall,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field<'a> {
+    Bytes(&'a [u8]),
+    Str(&'a str),
+    U64([u8; 8]),
+}
+
+impl Field<'_> {
+    fn tag_and_value(&self) -> (u8, &[u8]) {
+        match self {
+            Field::Bytes(b) => (TAG_BYTES, b),
+            Field::Str(s) => (TAG_STR, s.as_bytes()),
+            Field::U64(n) => (TAG_U64, n),
+        }
+    }
+}
+
+/// Builds length-prefixed, domain-separated associated data.
+#[derive(Debug, Clone)]
+pub struct AadBuilder<'a> {
+    label: &'a str,
+    fields: [Option<Field<'a>>; MAX_AAD_FIELDS],
+    count: usize,
+}
+
+impl<'a> AadBuilder<'a> {
+    /// Start a new AAD under `label`, which names what the data is for
+    /// (e.g. "rcli file v1") so AAD built for one purpose never matches
+    /// another's.
+    pub fn new(label: &'a str) -> Self {
+        AadBuilder {
+            label,
+            fields: [None; MAX_AAD_FIELDS],
+            count: 0,
+        }
+    }
+
+    /// Append a byte-string field. Panics past `MAX_AAD_FIELDS` fields.
+    pub fn push_bytes(&mut self, value: &'a [u8]) -> &mut Self {
+        self.push(Field::Bytes(value))
+    }
+
+    /// Append a string field. Panics past `MAX_AAD_FIELDS` fields.
+    pub fn push_str(&mut self, value: &'a str) -> &mut Self {
+        self.push(Field::Str(value))
+    }
+
+    /// Append an integer field. Panics past `MAX_AAD_FIELDS` fields.
+    pub fn push_u64(&mut self, value: u64) -> &mut Self {
+        self.push(Field::U64(value.to_be_bytes()))
+    }
+
+    fn push(&mut self, field: Field<'a>) -> &mut Self {
+        assert!(self.count < MAX_AAD_FIELDS, "too many AAD fields");
+        self.fields[self.count] = Some(field);
+        self.count += 1;
+        self
+    }
+
+    /// Length of the encoded AAD.
+    pub fn encoded_len(&self) -> usize {
+        let fields: usize = self
+            .fields()
+            .map(|field| 1 + 4 + field.tag_and_value().1.len())
+            .sum();
+        4 + self.label.len() + fields
+    }
+
+    /// Encode into a new vector.
+    pub fn finish(&self) -> Vec<u8> {
+        let mut out = alloc::vec![0u8; self.encoded_len()];
+        self.write(&mut out);
+        out
+    }
+
+    /// Encode into the start of `out` without allocating, returning the
+    /// number of bytes written.
+    pub fn finish_into(&self, out: &mut [u8]) -> Result<usize, AadError> {
+        let len = self.encoded_len();
+        let out = out.get_mut(..len).ok_or(AadError::BufferTooSmall)?;
+        self.write(out);
+        Ok(len)
+    }
+
+    fn fields(&self) -> impl Iterator<Item = &Field<'a>> {
+        self.fields[..self.count].iter().flatten()
+    }
+
+    /* `out` is exactly `encoded_len` bytes */
+    fn write(&self, out: &mut [u8]) {
+        let mut at = put_prefixed(out, 0, self.label.as_bytes());
+        for field in self.fields() {
+            let (tag, value) = field.tag_and_value();
+            out[at] = tag;
+            at = put_prefixed(out, at + 1, value);
+        }
+        debug_assert_eq!(at, out.len());
+    }
+}
+
+/* Write len(value) || value at `at`, returning the offset after it */
+fn put_prefixed(out: &mut [u8], at: usize, value: &[u8]) -> usize {
+    out[at..at + 4].copy_from_slice(&(value.len() as u32).to_be_bytes());
+    out[at + 4..at + 4 + value.len()].copy_from_slice(value);
+    at + 4 + value.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_and_types_are_distinct() {
+        let encode = |label: &str, fields: &[&str]| {
+            let mut aad = AadBuilder::new(label);
+            for field in fields {
+                aad.push_str(field);
+            }
+            aad.finish()
+        };
+        let all = [
+            encode("test", &["ab", "c"]),
+            encode("test", &["a", "bc"]),
+            encode("test", &["abc"]),
+            encode("test", &["abc", ""]),
+            encode("test", &["", "abc"]),
+            encode("test", &[]),
+            encode("testa", &["bc"]),
+            encode("", &["test", "abc"]),
+            AadBuilder::new("test").push_bytes(b"abc").finish(),
+            AadBuilder::new("test").push_u64(0x616263).finish(),
+        ];
+        for (i, a) in all.iter().enumerate() {
+            for b in &all[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn finish_into_matches_finish() {
+        let mut aad = AadBuilder::new("rcli chunk");
+        aad.push_str("report.pdf")
+            .push_u64(7)
+            .push_bytes(&[0xff; 3]);
+        let expected = aad.finish();
+        assert_eq!(expected.len(), aad.encoded_len());
+
+        let mut buf = [0xaau8; 64];
+        assert_eq!(aad.finish_into(&mut buf), Ok(expected.len()));
+        assert_eq!(buf[..expected.len()], expected[..]);
+        assert!(buf[expected.len()..].iter().all(|&b| b == 0xaa));
+
+        let mut short = [0u8; 64];
+        assert_eq!(
+            aad.finish_into(&mut short[..expected.len() - 1]),
+            Err(AadError::BufferTooSmall)
+        );
+    }
+
+    // Encoded independently in Python from the layout in the module
+    // comment: struct.pack(">I", len) framing, tags 1/2/3
+    #[test]
+    fn known_answers() {
+        let empty = AadBuilder::new("").finish();
+        assert_eq!(empty, include_bytes!("../tests/aad_empty.bin"));
+
+        let file = AadBuilder::new("rcli file v1")
+            .push_str("notes.txt")
+            .push_u64(1)
+            .push_u64(42)
+            .finish();
+        assert_eq!(file, include_bytes!("../tests/aad_file.bin"));
+
+        let mixed = AadBuilder::new("mixed")
+            .push_bytes(&[])
+            .push_bytes(&[0, 1, 2, 0xff])
+            .push_str("ü")
+            .push_u64(u64::MAX)
+            .finish();
+        assert_eq!(mixed, include_bytes!("../tests/aad_mixed.bin"));
+    }
+
+    #[test]
+    #[should_panic(expected = "too many AAD fields")]
+    fn field_limit() {
+        let mut aad = AadBuilder::new("x");
+        for i in 0..=MAX_AAD_FIELDS as u64 {
+            aad.push_u64(i);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+/* AES-256-CBC with PKCS#7 padding, for exchanging data with systems that
+ * use it (e.g. `openssl enc -aes-256-cbc -K .. -iv ..`). Key derivation
+ * (openssl's EVP_BytesToKey / PBKDF2 "Salted__" header) is left to the
+ * caller.
+ *
+ * NO INTEGRITY: CBC ciphertext can be modified to make predictable
+ * changes to the plaintext, and a service that reveals whether padding
+ * was valid can be used to decrypt everything (a padding oracle). All
+ * decryption failures are reported as the one `DecryptionFailed` to
+ * avoid making that easier, but only a MAC over the ciphertext, checked
+ * before decrypting, actually prevents it. Prefer the AEADs. */
+use alloc::vec::Vec;
+
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockModeDecrypt, BlockModeEncrypt, KeyIvInit};
+use aes::Aes256;
+
+use crate::ErrorStates;
+
+/// AES block (and IV) size in bytes.
+pub const AES_CBC_BLOCK_LEN: usize = 16;
+
+/// Encrypt and pad `plaintext`. The output is 1 to 16 bytes longer than
+/// the input (a whole padding block when it is already block-aligned).
+pub fn aes_256_cbc_encrypt(
+    key: &[u8; 32],
+    iv: &[u8; AES_CBC_BLOCK_LEN],
+    plaintext: &[u8],
+) -> Vec<u8> {
+    cbc::Encryptor::<Aes256>::new(&(*key).into(), &(*iv).into())
+        .encrypt_padded_vec::<Pkcs7>(plaintext)
+}
+
+/// Decrypt and unpad `ciphertext`. A length that is not a positive
+/// multiple of the block size and bad padding are both `DecryptionFailed`.
+pub fn aes_256_cbc_decrypt(
+    key: &[u8; 32],
+    iv: &[u8; AES_CBC_BLOCK_LEN],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, ErrorStates> {
+    if ciphertext.is_empty() || !ciphertext.len().is_multiple_of(AES_CBC_BLOCK_LEN) {
+        return Err(ErrorStates::DecryptionFailed);
+    }
+    cbc::Decryptor::<Aes256>::new(&(*key).into(), &(*iv).into())
+        .decrypt_padded_vec::<Pkcs7>(ciphertext)
+        .map_err(|_| ErrorStates::DecryptionFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    const NIST_KEY: [u8; 32] = [
+        0x60, 0x3d, 0xeb, 0x10, 0x15, 0xca, 0x71, 0xbe, 0x2b, 0x73, 0xae, 0xf0, 0x85, 0x7d, 0x77, 0x81,
+        0x1f, 0x35, 0x2c, 0x07, 0x3b, 0x61, 0x08, 0xd7, 0x2d, 0x98, 0x10, 0xa3, 0x09, 0x14, 0xdf, 0xf4,
+    ];
+    #[rustfmt::skip]
+    const NIST_PLAINTEXT: [u8; 64] = [
+        0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+        0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf, 0x8e, 0x51,
+        0x30, 0xc8, 0x1c, 0x46, 0xa3, 0x5c, 0xe4, 0x11, 0xe5, 0xfb, 0xc1, 0x19, 0x1a, 0x0a, 0x52, 0xef,
+        0xf6, 0x9f, 0x24, 0x45, 0xdf, 0x4f, 0x9b, 0x17, 0xad, 0x2b, 0x41, 0x7b, 0xe6, 0x6c, 0x37, 0x10,
+    ];
+    #[rustfmt::skip]
+    const NIST_CIPHERTEXT: [u8; 64] = [
+        0xf5, 0x8c, 0x4c, 0x04, 0xd6, 0xe5, 0xf1, 0xba, 0x77, 0x9e, 0xab, 0xfb, 0x5f, 0x7b, 0xfb, 0xd6,
+        0x9c, 0xfc, 0x4e, 0x96, 0x7e, 0xdb, 0x80, 0x8d, 0x67, 0x9f, 0x77, 0x7b, 0xc6, 0x70, 0x2c, 0x7d,
+        0x39, 0xf2, 0x33, 0x69, 0xa9, 0xd9, 0xba, 0xcf, 0xa5, 0x30, 0xe2, 0x63, 0x04, 0x23, 0x14, 0x61,
+        0xb2, 0xeb, 0x05, 0xe2, 0xc3, 0x9b, 0xe9, 0xfc, 0xda, 0x6c, 0x19, 0x07, 0x8c, 0x6a, 0x9d, 0x1b,
+    ];
+
+    // NIST SP 800-38A F.2.5 (CBC-AES256.Encrypt); the vector is unpadded,
+    // so it is the prefix before our padding block
+    #[test]
+    fn sp800_38a_f25_vector() {
+        let iv: [u8; 16] = core::array::from_fn(|i| i as u8);
+        let sealed = aes_256_cbc_encrypt(&NIST_KEY, &iv, &NIST_PLAINTEXT);
+        assert_eq!(sealed.len(), 80);
+        assert_eq!(sealed[..64], NIST_CIPHERTEXT);
+        assert_eq!(
+            aes_256_cbc_decrypt(&NIST_KEY, &iv, &sealed).unwrap(),
+            NIST_PLAINTEXT
+        );
+    }
+
+    // Made with `openssl enc -aes-256-cbc -K 00..1f -iv f0e0..00` over
+    // bytes (7i + 3) mod 256
+    #[test]
+    fn openssl_fixtures() {
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let iv: [u8; 16] = core::array::from_fn(|i| 0xf0 - 0x10 * i as u8);
+        let fixtures: [(usize, &[u8]); 6] = [
+            (0, include_bytes!("../tests/aes256cbc_openssl_0.bin")),
+            (1, include_bytes!("../tests/aes256cbc_openssl_1.bin")),
+            (15, include_bytes!("../tests/aes256cbc_openssl_15.bin")),
+            (16, include_bytes!("../tests/aes256cbc_openssl_16.bin")),
+            (17, include_bytes!("../tests/aes256cbc_openssl_17.bin")),
+            (100, include_bytes!("../tests/aes256cbc_openssl_100.bin")),
+        ];
+        for (len, expected) in fixtures {
+            let plaintext: Vec<u8> = (0..len).map(|i| (i * 7 + 3) as u8).collect();
+            assert_eq!(
+                aes_256_cbc_encrypt(&key, &iv, &plaintext),
+                expected,
+                "len {}",
+                len
+            );
+            assert_eq!(
+                aes_256_cbc_decrypt(&key, &iv, expected).unwrap(),
+                plaintext,
+                "len {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn bad_padding_and_lengths_are_indistinct() {
+        let key = [7u8; 32];
+        let iv = [9u8; 16];
+        let sealed = aes_256_cbc_encrypt(&key, &iv, b"attack at dawn!!?");
+
+        /* Flipping the IV-side of the last block alters the padding byte */
+        let mut bad_padding = sealed.clone();
+        let at = bad_padding.len() - 17;
+        bad_padding[at] ^= 0x01;
+        for bad in [&bad_padding[..], &sealed[..0], &sealed[..31], &sealed[..16]] {
+            assert_eq!(
+                aes_256_cbc_decrypt(&key, &iv, bad),
+                Err(ErrorStates::DecryptionFailed)
+            );
+        }
+
+        /* Final blocks whose padding claims more than a block, is zero,
+         * or has a mismatched byte */
+        let mut mismatched = [0x03u8; 16];
+        mismatched[13] = 0x02;
+        for pad in [[0x11u8; 16], [0u8; 16], mismatched] {
+            /* The first block under a zero IV is AES(pad ^ iv), which
+             * decrypts under `iv` to exactly `pad` */
+            let mut block = pad;
+            for (b, v) in block.iter_mut().zip(iv) {
+                *b ^= v;
+            }
+            let raw = aes_256_cbc_encrypt(&key, &[0; 16], &block);
+            assert_eq!(
+                aes_256_cbc_decrypt(&key, &iv, &raw[..16]),
+                Err(ErrorStates::DecryptionFailed)
+            );
+        }
+    }
+}
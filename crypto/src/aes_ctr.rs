@@ -0,0 +1,153 @@
+/* AES-256-CTR, for reading data written by systems that use it bare.
+ *
+ * UNAUTHENTICATED: anyone can flip ciphertext bits and the matching
+ * plaintext bits flip with them, undetected. Use it only to interoperate
+ * with existing data, and authenticate that data some other way (or
+ * better, use the AEADs). Reusing a key/IV pair reveals the XOR of the
+ * two plaintexts.
+ *
+ * The 16-byte IV is the initial counter block; the whole block is
+ * incremented as a 128-bit big-endian integer (NIST SP 800-38A,
+ * "Ctr128BE"), so block n of the keystream is AES(key, IV + n). */
+use aes::cipher::{KeyIvInit, StreamCipher as _, StreamCipherSeek};
+use aes::Aes256;
+
+use crate::StreamCipher;
+
+/// AES block (and IV) size in bytes.
+pub const AES_CTR_BLOCK_LEN: usize = 16;
+
+type Ctr = ctr::Ctr128BE<Aes256>;
+
+/// Stateful AES-256-CTR keystream, for data that arrives in pieces.
+pub struct Aes256Ctr(Ctr);
+
+impl Aes256Ctr {
+    pub fn new(key: &[u8; 32], iv: &[u8; AES_CTR_BLOCK_LEN]) -> Self {
+        Self::new_at_block(key, iv, 0)
+    }
+
+    /// Start the keystream at block `block` (byte offset `16 * block`).
+    pub fn new_at_block(key: &[u8; 32], iv: &[u8; AES_CTR_BLOCK_LEN], block: u64) -> Self {
+        let mut ctr = Ctr::new(&(*key).into(), &(*iv).into());
+        ctr.seek(block as u128 * AES_CTR_BLOCK_LEN as u128);
+        Aes256Ctr(ctr)
+    }
+}
+
+impl StreamCipher for Aes256Ctr {
+    fn apply_keystream(&mut self, data: &mut [u8]) {
+        self.0.apply_keystream(data);
+    }
+}
+
+impl core::fmt::Debug for Aes256Ctr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Aes256Ctr(..)")
+    }
+}
+
+/// En/decrypt `data` in place from the start of the keystream.
+pub fn aes_256_ctr_apply(key: &[u8; 32], iv: &[u8; AES_CTR_BLOCK_LEN], data: &mut [u8]) {
+    aes_256_ctr_apply_at(key, iv, 0, data);
+}
+
+/// En/decrypt `data` in place, starting at keystream block `block`, so a
+/// range beginning at byte `16 * block` can be decrypted on its own.
+pub fn aes_256_ctr_apply_at(
+    key: &[u8; 32],
+    iv: &[u8; AES_CTR_BLOCK_LEN],
+    block: u64,
+    data: &mut [u8],
+) {
+    Aes256Ctr::new_at_block(key, iv, block).apply_keystream(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    const KEY: [u8; 32] = [
+        0x60, 0x3d, 0xeb, 0x10, 0x15, 0xca, 0x71, 0xbe, 0x2b, 0x73, 0xae, 0xf0, 0x85, 0x7d, 0x77, 0x81,
+        0x1f, 0x35, 0x2c, 0x07, 0x3b, 0x61, 0x08, 0xd7, 0x2d, 0x98, 0x10, 0xa3, 0x09, 0x14, 0xdf, 0xf4,
+    ];
+    #[rustfmt::skip]
+    const IV: [u8; 16] = [
+        0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff,
+    ];
+    #[rustfmt::skip]
+    const PLAINTEXT: [u8; 64] = [
+        0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+        0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf, 0x8e, 0x51,
+        0x30, 0xc8, 0x1c, 0x46, 0xa3, 0x5c, 0xe4, 0x11, 0xe5, 0xfb, 0xc1, 0x19, 0x1a, 0x0a, 0x52, 0xef,
+        0xf6, 0x9f, 0x24, 0x45, 0xdf, 0x4f, 0x9b, 0x17, 0xad, 0x2b, 0x41, 0x7b, 0xe6, 0x6c, 0x37, 0x10,
+    ];
+    #[rustfmt::skip]
+    const CIPHERTEXT: [u8; 64] = [
+        0x60, 0x1e, 0xc3, 0x13, 0x77, 0x57, 0x89, 0xa5, 0xb7, 0xa7, 0xf5, 0x04, 0xbb, 0xf3, 0xd2, 0x28,
+        0xf4, 0x43, 0xe3, 0xca, 0x4d, 0x62, 0xb5, 0x9a, 0xca, 0x84, 0xe9, 0x90, 0xca, 0xca, 0xf5, 0xc5,
+        0x2b, 0x09, 0x30, 0xda, 0xa2, 0x3d, 0xe9, 0x4c, 0xe8, 0x70, 0x17, 0xba, 0x2d, 0x84, 0x98, 0x8d,
+        0xdf, 0xc9, 0xc5, 0x8d, 0xb6, 0x7a, 0xad, 0xa6, 0x13, 0xc2, 0xdd, 0x08, 0x45, 0x79, 0x41, 0xa6,
+    ];
+
+    // NIST SP 800-38A F.5.5 (CTR-AES256.Encrypt) and F.5.6 (Decrypt)
+    #[test]
+    fn sp800_38a_f5_vectors() {
+        let mut data = PLAINTEXT;
+        aes_256_ctr_apply(&KEY, &IV, &mut data);
+        assert_eq!(data, CIPHERTEXT);
+        aes_256_ctr_apply(&KEY, &IV, &mut data);
+        assert_eq!(data, PLAINTEXT);
+
+        /* Same through the stateful context, fed unevenly */
+        let mut ctr = Aes256Ctr::new(&KEY, &IV);
+        let mut data = PLAINTEXT;
+        let (head, tail) = data.split_at_mut(7);
+        ctr.apply_keystream(head);
+        ctr.apply_keystream(tail);
+        assert_eq!(data, CIPHERTEXT);
+    }
+
+    #[test]
+    fn seeking_matches_sequential() {
+        /* Each F.5 block on its own, via its block counter */
+        for block in 0..4 {
+            let range = block * 16..block * 16 + 16;
+            let mut data = CIPHERTEXT[range.clone()].to_vec();
+            aes_256_ctr_apply_at(&KEY, &IV, block as u64, &mut data);
+            assert_eq!(data, PLAINTEXT[range]);
+        }
+
+        /* A long buffer, decrypted from several block offsets */
+        let plain: Vec<u8> = (0..1000).map(|i| (i % 253) as u8).collect();
+        let mut sealed = plain.clone();
+        aes_256_ctr_apply(&KEY, &[0; 16], &mut sealed);
+        for block in [0u64, 1, 17, 62] {
+            let start = block as usize * 16;
+            let mut part = sealed[start..].to_vec();
+            aes_256_ctr_apply_at(&KEY, &[0; 16], block, &mut part);
+            assert_eq!(part, plain[start..]);
+        }
+    }
+
+    #[test]
+    fn counter_carries_across_the_whole_iv() {
+        /* The all-ones IV wraps to zero, carrying through every byte */
+        let mut wrapped = [0u8; 16];
+        aes_256_ctr_apply_at(&KEY, &[0xff; 16], 1, &mut wrapped);
+        let mut from_zero = [0u8; 16];
+        aes_256_ctr_apply(&KEY, &[0; 16], &mut from_zero);
+        assert_eq!(wrapped, from_zero);
+    }
+
+    #[test]
+    fn plugs_into_stream_cipher() {
+        fn run(cipher: &mut dyn StreamCipher, data: &mut [u8]) {
+            cipher.apply_keystream(data);
+        }
+        let mut data = PLAINTEXT;
+        run(&mut Aes256Ctr::new(&KEY, &IV), &mut data);
+        assert_eq!(data, CIPHERTEXT);
+    }
+}
@@ -0,0 +1,608 @@
+/* age v1 file format with X25519 recipients, interoperable with age/rage.
+ * [Spec]: https://age-encryption.org/v1
+ *
+ *   age-encryption.org/v1
+ *   -> X25519 <b64(ephemeral share)>
+ *   <b64(ChaCha20-Poly1305(wrap_key, 0, file_key)), wrapped at 64 columns>
+ *   --- <b64(HMAC-SHA256(HKDF(file_key, "", "header"), header up to "---"))>
+ *   nonce (16 bytes) || payload
+ *
+ * wrap_key = HKDF-SHA256(ikm = shared secret, salt = share || recipient,
+ * info = "age-encryption.org/v1/X25519"). The payload is STREAM-chunked
+ * ChaCha20-Poly1305 under HKDF(file_key, nonce, "payload") in 64 KiB
+ * chunks; its 11-byte counter is our `stream` nonce with a zero prefix.
+ * Base64 is standard alphabet, unpadded, and must be canonical. The
+ * ASCII-armored form and scrypt (passphrase) stanzas are not supported. */
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use bech32::primitives::decode::CheckedHrpstring;
+use bech32::{Bech32, Hrp};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::hmac;
+use ring::rand::SystemRandom;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::committing::Len;
+use crate::header::NONCE_PREFIX_LEN;
+use crate::stream::{StreamDecryptor, StreamEncryptor, TAG_LEN};
+use crate::{EntropySource, ErrorStates};
+
+const VERSION_LINE: &str = "age-encryption.org/v1";
+const X25519_TAG: &str = "X25519";
+const X25519_INFO: &[u8] = b"age-encryption.org/v1/X25519";
+
+const PUBLIC_HRP: &str = "age";
+const SECRET_HRP: &str = "age-secret-key-";
+
+const FILE_KEY_LEN: usize = 16;
+const PAYLOAD_NONCE_LEN: usize = 16;
+const CHUNK_SIZE: usize = 64 * 1024;
+/* Header lines and stanza bodies are wrapped at this many columns */
+const COLUMNS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgeError {
+    /* Bad Bech32 string, wrong type of key, or a low-order public key */
+    InvalidKey,
+    /* Header is not valid age v1 syntax */
+    Malformed,
+    /* A newer (or armored) age format */
+    UnsupportedVersion,
+    /* No stanza could be opened with the given identity */
+    NoMatchingIdentity,
+    /* Header was modified after it was written */
+    HeaderMacMismatch,
+    /* Payload chunk failed to authenticate */
+    DecryptionFailed,
+    /* Payload ends before its final chunk */
+    Truncated,
+}
+
+/// An age X25519 recipient (`age1...`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AgePublicKey([u8; 32]);
+
+impl AgePublicKey {
+    /// Rejects low-order points, which would make the shared secret zero.
+    pub fn from_bytes(key: &[u8; 32]) -> Result<Self, AgeError> {
+        let probe = StaticSecret::from([1u8; 32]).diffie_hellman(&PublicKey::from(*key));
+        if !probe.was_contributory() {
+            return Err(AgeError::InvalidKey);
+        }
+        Ok(AgePublicKey(*key))
+    }
+
+    pub fn from_bech32(key: &str) -> Result<Self, AgeError> {
+        Self::from_bytes(&decode_bech32(key, PUBLIC_HRP)?)
+    }
+
+    pub fn to_bech32(&self) -> String {
+        bech32::encode_lower::<Bech32>(Hrp::parse_unchecked(PUBLIC_HRP), &self.0).unwrap()
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+/// An age X25519 identity (`AGE-SECRET-KEY-1...`).
+#[derive(Clone)]
+pub struct AgeIdentity(StaticSecret);
+
+impl AgeIdentity {
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&SystemRandom::new())
+    }
+
+    pub fn generate_with_rng(rng: &dyn EntropySource) -> Self {
+        let mut key = [0u8; 32];
+        rng.fill(&mut key);
+        Self::from_bytes(&key)
+    }
+
+    pub fn from_bytes(key: &[u8; 32]) -> Self {
+        AgeIdentity(StaticSecret::from(*key))
+    }
+
+    pub fn from_bech32(key: &str) -> Result<Self, AgeError> {
+        Ok(Self::from_bytes(&decode_bech32(key, SECRET_HRP)?))
+    }
+
+    /// The `AGE-SECRET-KEY-1...` form, as written by `age-keygen`.
+    pub fn to_bech32(&self) -> String {
+        bech32::encode_upper::<Bech32>(Hrp::parse_unchecked(SECRET_HRP), self.0.as_bytes()).unwrap()
+    }
+
+    pub fn to_public(&self) -> AgePublicKey {
+        AgePublicKey(PublicKey::from(&self.0).to_bytes())
+    }
+}
+
+fn decode_bech32(key: &str, hrp: &str) -> Result<[u8; 32], AgeError> {
+    let parsed = CheckedHrpstring::new::<Bech32>(key.trim()).map_err(|_| AgeError::InvalidKey)?;
+    if parsed.hrp().to_lowercase() != hrp {
+        return Err(AgeError::InvalidKey);
+    }
+    let bytes: Vec<u8> = parsed.byte_iter().collect();
+    bytes.try_into().map_err(|_| AgeError::InvalidKey)
+}
+
+fn hkdf(ikm: &[u8], salt: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    Salt::new(HKDF_SHA256, salt)
+        .extract(ikm)
+        .expand(&[info], Len(out.len()))
+        .and_then(|okm| okm.fill(&mut out))
+        .unwrap();
+    out
+}
+
+/* ChaCha20-Poly1305 under a single-use key, so the nonce is all zeros */
+fn stanza_key(key: &[u8; 32]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).unwrap())
+}
+
+fn x25519_wrap_key(shared: &[u8; 32], share: &[u8; 32], recipient: &[u8; 32]) -> [u8; 32] {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(share);
+    salt[32..].copy_from_slice(recipient);
+    hkdf(shared, &salt, X25519_INFO)
+}
+
+fn header_mac_key(file_key: &[u8; FILE_KEY_LEN]) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, &hkdf(file_key, &[], b"header"))
+}
+
+/* One recipient stanza: "-> tag args...", then the wrapped body */
+struct Stanza<'a> {
+    tag: &'a str,
+    args: Vec<&'a str>,
+    body: Vec<u8>,
+}
+
+struct Header<'a> {
+    stanzas: Vec<Stanza<'a>>,
+    mac: Vec<u8>,
+    /* Bytes covered by the MAC: everything up to and including "---" */
+    mac_input_len: usize,
+    /* Total header length, including the final newline */
+    len: usize,
+}
+
+fn write_stanza(out: &mut String, tag: &str, args: &[&str], body: &[u8]) {
+    out.push_str("->");
+    for arg in core::iter::once(&tag).chain(args) {
+        out.push(' ');
+        out.push_str(arg);
+    }
+    out.push('\n');
+
+    /* The body always ends with a short (possibly empty) line */
+    let body = STANDARD_NO_PAD.encode(body);
+    let mut rest = body.as_str();
+    loop {
+        let (line, tail) = rest.split_at(rest.len().min(COLUMNS));
+        out.push_str(line);
+        out.push('\n');
+        if line.len() < COLUMNS {
+            break;
+        }
+        rest = tail;
+    }
+}
+
+fn is_arg(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| (0x21..=0x7e).contains(&b))
+}
+
+/* Next '\n'-terminated line starting at `pos`, as ASCII text */
+fn next_line<'a>(data: &'a [u8], pos: &mut usize) -> Result<(usize, &'a str), AgeError> {
+    let start = *pos;
+    let len = data[start..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or(AgeError::Malformed)?;
+    *pos = start + len + 1;
+    let line = core::str::from_utf8(&data[start..start + len]).map_err(|_| AgeError::Malformed)?;
+    if !line.is_ascii() {
+        return Err(AgeError::Malformed);
+    }
+    Ok((start, line))
+}
+
+fn parse_header(data: &[u8]) -> Result<Header<'_>, AgeError> {
+    let mut pos = 0;
+    let (_, version) = next_line(data, &mut pos)?;
+    if version != VERSION_LINE {
+        return Err(
+            if version.starts_with("age-encryption.org/") || version.starts_with("-----BEGIN") {
+                AgeError::UnsupportedVersion
+            } else {
+                AgeError::Malformed
+            },
+        );
+    }
+
+    let mut stanzas = Vec::new();
+    loop {
+        let (start, line) = next_line(data, &mut pos)?;
+
+        if let Some(mac) = line.strip_prefix("--- ") {
+            let mac = STANDARD_NO_PAD
+                .decode(mac)
+                .map_err(|_| AgeError::Malformed)?;
+            if stanzas.is_empty() || mac.len() != 32 {
+                return Err(AgeError::Malformed);
+            }
+            return Ok(Header {
+                stanzas,
+                mac,
+                mac_input_len: start + 3,
+                len: pos,
+            });
+        }
+
+        let mut args = line
+            .strip_prefix("-> ")
+            .ok_or(AgeError::Malformed)?
+            .split(' ');
+        let tag = args
+            .next()
+            .filter(|t| is_arg(t))
+            .ok_or(AgeError::Malformed)?;
+        let args: Vec<&str> = args.collect();
+        if !args.iter().all(|a| is_arg(a)) {
+            return Err(AgeError::Malformed);
+        }
+
+        let mut body = String::new();
+        loop {
+            let (_, line) = next_line(data, &mut pos)?;
+            if line.len() > COLUMNS {
+                return Err(AgeError::Malformed);
+            }
+            body.push_str(line);
+            if line.len() < COLUMNS {
+                break;
+            }
+        }
+        let body = STANDARD_NO_PAD
+            .decode(body)
+            .map_err(|_| AgeError::Malformed)?;
+        stanzas.push(Stanza { tag, args, body });
+    }
+}
+
+/* Try to recover the file key from an X25519 stanza addressed to us */
+fn unwrap_x25519(
+    identity: &AgeIdentity,
+    stanza: &Stanza,
+) -> Result<Option<[u8; FILE_KEY_LEN]>, AgeError> {
+    let share: [u8; 32] = match stanza.args[..] {
+        [share] => STANDARD_NO_PAD
+            .decode(share)
+            .map_err(|_| AgeError::Malformed)?
+            .try_into()
+            .map_err(|_| AgeError::Malformed)?,
+        _ => return Err(AgeError::Malformed),
+    };
+    if stanza.body.len() != FILE_KEY_LEN + TAG_LEN {
+        return Err(AgeError::Malformed);
+    }
+
+    let shared = identity.0.diffie_hellman(&PublicKey::from(share));
+    if !shared.was_contributory() {
+        return Err(AgeError::Malformed);
+    }
+    let recipient = identity.to_public();
+    let wrap_key = x25519_wrap_key(shared.as_bytes(), &share, recipient.as_bytes());
+
+    let mut body = stanza.body.clone();
+    let opened = stanza_key(&wrap_key).open_in_place(
+        Nonce::assume_unique_for_key([0; NONCE_LEN]),
+        Aad::empty(),
+        &mut body,
+    );
+    Ok(opened.ok().map(|key| key.try_into().unwrap()))
+}
+
+/// Encrypt `plaintext` to every key in `recipients`, producing a binary
+/// age v1 file. Panics if `recipients` is empty.
+pub fn age_encrypt(recipients: &[AgePublicKey], plaintext: &[u8]) -> Vec<u8> {
+    age_encrypt_with_rng(recipients, plaintext, &SystemRandom::new())
+}
+
+/// As `age_encrypt`, drawing the file key, ephemeral keys and payload
+/// nonce from `rng`.
+pub fn age_encrypt_with_rng(
+    recipients: &[AgePublicKey],
+    plaintext: &[u8],
+    rng: &dyn EntropySource,
+) -> Vec<u8> {
+    assert!(!recipients.is_empty(), "age_encrypt needs a recipient");
+
+    let mut file_key = [0u8; FILE_KEY_LEN];
+    rng.fill(&mut file_key);
+
+    let mut header = String::from(VERSION_LINE);
+    header.push('\n');
+    for recipient in recipients {
+        let mut ephemeral = [0u8; 32];
+        rng.fill(&mut ephemeral);
+        let ephemeral = StaticSecret::from(ephemeral);
+        let share = PublicKey::from(&ephemeral).to_bytes();
+        let shared = ephemeral.diffie_hellman(&PublicKey::from(recipient.0));
+        let wrap_key = x25519_wrap_key(shared.as_bytes(), &share, &recipient.0);
+
+        let mut body = file_key.to_vec();
+        stanza_key(&wrap_key)
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key([0; NONCE_LEN]),
+                Aad::empty(),
+                &mut body,
+            )
+            .unwrap();
+        write_stanza(
+            &mut header,
+            X25519_TAG,
+            &[&STANDARD_NO_PAD.encode(share)],
+            &body,
+        );
+    }
+    header.push_str("---");
+    let mac = hmac::sign(&header_mac_key(&file_key), header.as_bytes());
+    header.push(' ');
+    header.push_str(&STANDARD_NO_PAD.encode(mac.as_ref()));
+    header.push('\n');
+
+    let mut nonce = [0u8; PAYLOAD_NONCE_LEN];
+    rng.fill(&mut nonce);
+    let payload_key = hkdf(&file_key, &nonce, b"payload");
+    let mut stream = StreamEncryptor::new(&payload_key, &[0; NONCE_PREFIX_LEN], &[]).unwrap();
+
+    let chunks = plaintext.len().div_ceil(CHUNK_SIZE).max(1);
+    let mut out = header.into_bytes();
+    out.reserve(PAYLOAD_NONCE_LEN + plaintext.len() + chunks * TAG_LEN);
+    out.extend_from_slice(&nonce);
+
+    /* A full final chunk stays final; only an empty file has an empty chunk */
+    let mut chunks = plaintext.chunks(CHUNK_SIZE).peekable();
+    if chunks.peek().is_none() {
+        let mut chunk = Vec::new();
+        stream.seal_last(&mut chunk).unwrap();
+        out.extend_from_slice(&chunk);
+        return out;
+    }
+    while let Some(chunk) = chunks.next() {
+        let mut chunk = chunk.to_vec();
+        if chunks.peek().is_some() {
+            stream.seal_chunk(&mut chunk).unwrap();
+            out.extend_from_slice(&chunk);
+        } else {
+            stream.seal_last(&mut chunk).unwrap();
+            out.extend_from_slice(&chunk);
+            return out;
+        }
+    }
+    unreachable!()
+}
+
+/// Decrypt a binary age v1 file with an X25519 identity.
+pub fn age_decrypt(identity: &AgeIdentity, data: &[u8]) -> Result<Vec<u8>, AgeError> {
+    let header = parse_header(data)?;
+
+    let mut file_key = None;
+    for stanza in header.stanzas.iter().filter(|s| s.tag == X25519_TAG) {
+        if let Some(key) = unwrap_x25519(identity, stanza)? {
+            file_key = Some(key);
+            break;
+        }
+    }
+    let file_key = file_key.ok_or(AgeError::NoMatchingIdentity)?;
+
+    hmac::verify(
+        &header_mac_key(&file_key),
+        &data[..header.mac_input_len],
+        &header.mac,
+    )
+    .map_err(|_| AgeError::HeaderMacMismatch)?;
+
+    let payload = &data[header.len..];
+    if payload.len() < PAYLOAD_NONCE_LEN + TAG_LEN {
+        return Err(AgeError::Truncated);
+    }
+    let (nonce, payload) = payload.split_at(PAYLOAD_NONCE_LEN);
+    let payload_key = hkdf(&file_key, nonce, b"payload");
+    let mut stream = StreamDecryptor::new(&payload_key, &[0; NONCE_PREFIX_LEN], &[]).unwrap();
+
+    let mut out = Vec::with_capacity(payload.len());
+    let mut chunks = payload.chunks(CHUNK_SIZE + TAG_LEN).peekable();
+    while let Some(chunk) = chunks.next() {
+        let mut chunk = chunk.to_vec();
+        if chunks.peek().is_some() {
+            stream
+                .open_chunk(&mut chunk)
+                .map_err(|_| AgeError::DecryptionFailed)?;
+            out.extend_from_slice(&chunk);
+            continue;
+        }
+
+        let first = stream.chunk_index() == 0;
+        if chunk.len() < TAG_LEN || (chunk.len() == TAG_LEN && !first) {
+            return Err(AgeError::Malformed);
+        }
+        stream.open_last(&mut chunk).map_err(|e| match e {
+            ErrorStates::StreamTruncated => AgeError::Truncated,
+            _ => AgeError::DecryptionFailed,
+        })?;
+        out.extend_from_slice(&chunk);
+        break;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRng;
+
+    /* Identities generated with rage; fixtures were encrypted with it too */
+    const IDENTITY_1: &str =
+        "AGE-SECRET-KEY-18CPH7G8VLZR2MJKCMF3U79WS802WPJTN56TERQYYURHR093Z2QKQT494PZ";
+    const RECIPIENT_1: &str = "age1320uwt87mlx680qyjl9csea3cyphwmm6lj3syawgrje0ezcd89kqjfju6g";
+    const IDENTITY_2: &str =
+        "AGE-SECRET-KEY-1FN3T2YM9XV5PYHUA6XG437XQYSLULFUC06C90FTQ3ZQ3VR93A6PQENWXFR";
+
+    fn identity(key: &str) -> AgeIdentity {
+        AgeIdentity::from_bech32(key).unwrap()
+    }
+
+    /* Plaintext of age_multi_chunk.age */
+    fn multi_chunk_plaintext() -> Vec<u8> {
+        (0..70000u32).map(|i| ((i * 31 + 7) % 251) as u8).collect()
+    }
+
+    #[test]
+    fn bech32_keys() {
+        let id = identity(IDENTITY_1);
+        assert_eq!(id.to_bech32(), IDENTITY_1);
+        assert_eq!(id.to_public().to_bech32(), RECIPIENT_1);
+        assert_eq!(AgePublicKey::from_bech32(RECIPIENT_1), Ok(id.to_public()));
+
+        /* Secret and public keys are not interchangeable */
+        assert!(AgePublicKey::from_bech32(IDENTITY_1).is_err());
+        assert!(AgeIdentity::from_bech32(RECIPIENT_1).is_err());
+
+        let mut typo = String::from(RECIPIENT_1);
+        typo.replace_range(10..11, "q");
+        assert_eq!(AgePublicKey::from_bech32(&typo), Err(AgeError::InvalidKey));
+        assert_eq!(
+            AgePublicKey::from_bytes(&[0; 32]),
+            Err(AgeError::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn decrypts_rage_fixtures() {
+        let hello = include_bytes!("../tests/age_hello.age");
+        assert_eq!(
+            age_decrypt(&identity(IDENTITY_1), hello).unwrap(),
+            b"Hello from age!\n"
+        );
+
+        let empty = include_bytes!("../tests/age_empty.age");
+        assert_eq!(age_decrypt(&identity(IDENTITY_1), empty).unwrap(), b"");
+
+        /* Two recipients plus a grease stanza, spanning two chunks */
+        let multi = include_bytes!("../tests/age_multi_chunk.age");
+        for key in [IDENTITY_1, IDENTITY_2] {
+            assert_eq!(
+                age_decrypt(&identity(key), multi).unwrap(),
+                multi_chunk_plaintext()
+            );
+        }
+    }
+
+    // Produced with TestRng::new(1); rage decrypts it with IDENTITY_1
+    #[test]
+    fn seeded_encryption_matches_vector() {
+        let recipient = AgePublicKey::from_bech32(RECIPIENT_1).unwrap();
+        let file = age_encrypt_with_rng(&[recipient], b"Hello from age!\n", &TestRng::new(1));
+        assert_eq!(file, include_bytes!("../tests/age_seeded.age"));
+
+        let id = AgeIdentity::generate_with_rng(&TestRng::new(2));
+        assert_eq!(
+            id.to_bech32(),
+            "AGE-SECRET-KEY-1DG0NZX8SNE7RJSSEF86EJHCP7KJKE0ZFSQ2GL2W89Y8UZQKTTP0SCHEUPL"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let id = AgeIdentity::generate();
+        let other = identity(IDENTITY_1);
+        let recipients = [id.to_public(), other.to_public()];
+
+        for len in [
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            2 * CHUNK_SIZE,
+        ] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let file = age_encrypt(&recipients, &plaintext);
+            assert!(file.starts_with(b"age-encryption.org/v1\n-> X25519 "));
+
+            assert_eq!(age_decrypt(&id, &file).unwrap(), plaintext);
+            assert_eq!(age_decrypt(&other, &file).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn stanza_wrapping() {
+        /* A body that is an exact multiple of 64 columns gets an empty final line */
+        let mut out = String::from("age-encryption.org/v1\n");
+        write_stanza(&mut out, "grease", &["a", "b"], &[0x55; 48]);
+        assert!(out.ends_with(&alloc::format!("{}\n\n", "V".repeat(64))));
+
+        out.push_str("--- AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\n");
+        let header = parse_header(out.as_bytes()).unwrap();
+        assert_eq!(header.stanzas[0].tag, "grease");
+        assert_eq!(header.stanzas[0].args, ["a", "b"]);
+        assert_eq!(header.stanzas[0].body, [0x55; 48]);
+        assert_eq!(header.len, out.len());
+    }
+
+    #[test]
+    fn tampering_detected() {
+        let id = identity(IDENTITY_1);
+        let file = include_bytes!("../tests/age_hello.age").to_vec();
+
+        assert_eq!(
+            age_decrypt(&AgeIdentity::generate(), &file),
+            Err(AgeError::NoMatchingIdentity)
+        );
+
+        /* Flip one bit of the header MAC */
+        let mac_line = file.windows(4).position(|w| w == b"--- ").unwrap();
+        let mut tampered = file.clone();
+        tampered[mac_line + 4] ^= 0x01;
+        assert_eq!(
+            age_decrypt(&id, &tampered),
+            Err(AgeError::HeaderMacMismatch)
+        );
+
+        let mut tampered = file.clone();
+        *tampered.last_mut().unwrap() ^= 0x01;
+        assert_eq!(age_decrypt(&id, &tampered), Err(AgeError::DecryptionFailed));
+
+        assert_eq!(
+            age_decrypt(&id, b"Hello World!\n"),
+            Err(AgeError::Malformed)
+        );
+        assert_eq!(
+            age_decrypt(&id, b"age-encryption.org/v2\n"),
+            Err(AgeError::UnsupportedVersion)
+        );
+        assert_eq!(
+            age_decrypt(&id, &file[..mac_line]),
+            Err(AgeError::Malformed)
+        );
+    }
+
+    #[test]
+    fn truncation_detected() {
+        let id = identity(IDENTITY_1);
+        let file = age_encrypt(&[id.to_public()], &[0x42; 3 * CHUNK_SIZE]);
+
+        /* Drop the final chunk, ending on a full non-final chunk */
+        let cut = file.len() - (CHUNK_SIZE + TAG_LEN);
+        assert_eq!(age_decrypt(&id, &file[..cut]), Err(AgeError::Truncated));
+    }
+}
@@ -0,0 +1,1257 @@
+/* Archives: a directory tree packed into one rcli stream, which hides
+ * the names, shape and file sizes that encrypting file by file leaves in
+ * the clear.
+ *
+ * The container is written and read strictly in order, so packing and
+ * unpacking hold one chunk and one entry's metadata at a time, plus the
+ * list of paths when packing:
+ *
+ *   0..4    magic "RCLA"
+ *   4       format version (1)
+ *   then    entries, sorted bytewise on their paths:
+ *             kind (1 = file, 2 = directory)
+ *             path length (u16), path
+ *             mode (u32, permission bits)
+ *             mtime (i64 seconds and u32 nanoseconds since the epoch)
+ *             size (u64, 0 for a directory)
+ *             size bytes of data
+ *   then    kind 0, marking the end
+ *   then    with the stream's padding (see `padding`), zeros and the
+ *           container's length (u64), to the bucket's size
+ *
+ * The stream may instead be compressed (see `compress`), though not
+ * both: the padding would follow the compressed data rather than the
+ * container. A compressed archive records the container's length, so
+ * reading one that would unpack to more than the caller allows fails
+ * before any chunk is read.
+ *
+ * All integers are big-endian. Paths are relative, '/'-separated and
+ * UTF-8, as in `manifest`. Only regular files and directories are packed:
+ * symlinks are skipped rather than followed, so nothing outside the root
+ * gets in, and special files have no data to keep.
+ *
+ * Unpacking refuses an entry whose path is absolute, has an empty, "."
+ * or ".." component or a backslash, or does not sort after the one
+ * before it (which also rules out duplicates). Entries are written into a
+ * fresh directory beside the destination that holds nothing but what the
+ * archive made, and no symlinks, so no entry can land outside it. That
+ * directory is renamed into place only after the end marker and the
+ * final chunk have authenticated, so a damaged or truncated archive
+ * leaves nothing behind. Listing, and extracting only some entries,
+ * read the same way but skip file data that is not wanted by its
+ * recorded size. setuid, setgid and sticky bits are neither
+ * stored nor restored. */
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::string::String;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::vec::Vec;
+
+#[cfg(feature = "compress")]
+use crate::compress::{CompressError, DecompressReader};
+use crate::fs::{
+    encrypt_stream, stream_error, DecryptReader, EncryptOptions, FsCryptoError, Summary,
+};
+use crate::generate_nonce;
+use crate::padding::{PaddingScheme, PADDING_TRAILER_LEN};
+
+/// Identifies an archive, once decrypted.
+pub const ARCHIVE_MAGIC: [u8; 4] = *b"RCLA";
+
+/// Current archive format version.
+pub const ARCHIVE_VERSION: u8 = 1;
+
+const KIND_END: u8 = 0;
+const KIND_FILE: u8 = 1;
+const KIND_DIRECTORY: u8 = 2;
+
+/* The permission bits kept: read, write and execute for each class */
+const MODE_MASK: u32 = 0o777;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+}
+
+/// One file or directory in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Relative, '/'-separated path.
+    pub path: String,
+    pub kind: EntryKind,
+    /// Permission bits, 0o777 at most.
+    pub mode: u32,
+    /// Modification time, in seconds since the Unix epoch.
+    pub mtime: i64,
+    pub mtime_nanos: u32,
+    /// Data bytes; 0 for a directory.
+    pub size: u64,
+}
+
+impl Entry {
+    /// The modification time as a `SystemTime`.
+    pub fn modified(&self) -> SystemTime {
+        let nanos = Duration::from_nanos(self.mtime_nanos as u64);
+        match self.mtime {
+            secs @ 0.. => UNIX_EPOCH + Duration::from_secs(secs as u64) + nanos,
+            secs => UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + nanos,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(27 + self.path.len());
+        out.push(match self.kind {
+            EntryKind::File => KIND_FILE,
+            EntryKind::Directory => KIND_DIRECTORY,
+        });
+        out.extend_from_slice(&(self.path.len() as u16).to_be_bytes());
+        out.extend_from_slice(self.path.as_bytes());
+        out.extend_from_slice(&self.mode.to_be_bytes());
+        out.extend_from_slice(&self.mtime.to_be_bytes());
+        out.extend_from_slice(&self.mtime_nanos.to_be_bytes());
+        out.extend_from_slice(&self.size.to_be_bytes());
+        out
+    }
+}
+
+/* (seconds, nanoseconds) since the epoch, seconds negative before it */
+fn unix_time(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+        Err(e) => {
+            let before = e.duration();
+            match before.subsec_nanos() {
+                0 => (-(before.as_secs() as i64), 0),
+                nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    /// The encrypted stream failed: wrong key, damage or truncation.
+    Stream(FsCryptoError),
+    /* Bad magic, an unknown entry kind, a non-UTF-8 path, or bytes after
+     * the end marker */
+    Malformed,
+    UnsupportedVersion(u8),
+    /// An entry path that could land outside the destination, or that
+    /// is out of order.
+    UnsafePath(String),
+    /// The container stops before its end marker.
+    Truncated,
+    /// The destination exists and is not an empty directory.
+    DestinationNotEmpty(PathBuf),
+    /// The compressed stream would unpack to more than allowed, or does
+    /// not decompress.
+    #[cfg(feature = "compress")]
+    Compression(CompressError),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Io(e) => write!(f, "I/O error: {}", e),
+            ArchiveError::Stream(e) => write!(f, "{}", e),
+            ArchiveError::Malformed => write!(f, "malformed archive"),
+            ArchiveError::UnsupportedVersion(v) => {
+                write!(f, "unsupported archive version {}", v)
+            }
+            ArchiveError::UnsafePath(path) => {
+                write!(f, "archive entry {:?} would escape the destination", path)
+            }
+            ArchiveError::Truncated => write!(f, "archive is truncated"),
+            ArchiveError::DestinationNotEmpty(path) => {
+                write!(f, "{} exists and is not an empty directory", path.display())
+            }
+            #[cfg(feature = "compress")]
+            ArchiveError::Compression(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        match stream_error(e) {
+            FsCryptoError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                ArchiveError::Truncated
+            }
+            #[cfg(feature = "compress")]
+            FsCryptoError::Io(e)
+                if e.get_ref().is_some_and(|inner| inner.is::<CompressError>()) =>
+            {
+                let inner = e.into_inner().expect("checked above");
+                ArchiveError::Compression(*inner.downcast().expect("checked above"))
+            }
+            FsCryptoError::Io(e) => ArchiveError::Io(e),
+            e => ArchiveError::Stream(e),
+        }
+    }
+}
+
+impl From<FsCryptoError> for ArchiveError {
+    fn from(e: FsCryptoError) -> Self {
+        match e {
+            FsCryptoError::Io(e) => e.into(),
+            e => ArchiveError::Stream(e),
+        }
+    }
+}
+
+/// Pack the tree under `root` and encrypt it into `writer`. With
+/// `opts.compression`, the container's length is recorded as the
+/// original length; compression and `opts.padding` together are an
+/// `InvalidInput` error.
+pub fn create<W: Write>(
+    root: &Path,
+    writer: &mut W,
+    key: &[u8],
+    opts: &EncryptOptions,
+) -> Result<Summary, FsCryptoError> {
+    let mut packer = pack(root)?;
+    #[cfg(feature = "compress")]
+    if opts.compression.is_some() {
+        if opts.padding.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an archive cannot be both compressed and padded",
+            )
+            .into());
+        }
+        let opts = EncryptOptions {
+            original_len: Some(packer.container_len()),
+            ..opts.clone()
+        };
+        return encrypt_stream(&mut packer, writer, key, &opts);
+    }
+    encrypt_stream(&mut packer, writer, key, opts)
+}
+
+/// What `extract` wrote.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Unpacked {
+    pub files: u64,
+    pub directories: u64,
+    /// Data bytes across all files.
+    pub bytes: u64,
+}
+
+/// Decrypt the archive from `reader` and unpack it as the directory
+/// `into`, which must not exist or be empty. Nothing appears at `into`
+/// unless the whole archive authenticates. A compressed archive may
+/// unpack to at most `limit` bytes of container (see `decrypting`).
+pub fn extract<R: Read>(
+    reader: R,
+    into: &Path,
+    key: &[u8],
+    limit: u64,
+) -> Result<Unpacked, ArchiveError> {
+    unpack(ArchiveReader::decrypting(reader, key, limit)?, into)
+}
+
+/// Decrypt the archive from `reader` and pass each entry to `each`, in
+/// order, writing nothing. Entry metadata and the final chunk are always
+/// authenticated; file data is skipped by its recorded size, so chunks
+/// holding nothing but data are read past without being opened (unless
+/// the archive is compressed). `limit` is as for `extract`.
+pub fn list<R: Read, F: FnMut(&Entry)>(
+    reader: R,
+    key: &[u8],
+    limit: u64,
+    mut each: F,
+) -> Result<(), ArchiveError> {
+    let mut archive = ArchiveReader::decrypting(reader, key, limit)?;
+    while let Some(entry) = archive.next_entry()? {
+        each(&entry);
+    }
+    Ok(())
+}
+
+/// Decrypt the archive from `reader` and copy the data of the first file
+/// entry `wanted` accepts into `writer`. `wanted` sees every entry, in
+/// order; the data of the others is skipped as `list` skips it. The
+/// archive is read to its end, so the last chunk authenticates too.
+/// `None` if no file entry was wanted. `limit` is as for `extract`.
+pub fn extract_file<R: Read, W: Write, F: FnMut(&Entry) -> bool>(
+    reader: R,
+    key: &[u8],
+    limit: u64,
+    mut wanted: F,
+    writer: &mut W,
+) -> Result<Option<Entry>, ArchiveError> {
+    let mut archive = ArchiveReader::decrypting(reader, key, limit)?;
+    let mut found = None;
+    while let Some(entry) = archive.next_entry()? {
+        if wanted(&entry) && found.is_none() && entry.kind == EntryKind::File {
+            archive.copy_data(writer)?;
+            found = Some(entry);
+        }
+    }
+    Ok(found)
+}
+
+/// Decrypt the archive from `reader` and unpack the entries `wanted`
+/// accepts beside `into`, which must not exist or be empty, with the
+/// directories above them. `wanted` sees every entry, in order. Nothing
+/// is at `into` until `Staged::finish`. `limit` is as for `extract`.
+pub fn extract_matching<R: Read, F: FnMut(&Entry) -> bool>(
+    reader: R,
+    into: &Path,
+    key: &[u8],
+    limit: u64,
+    wanted: F,
+) -> Result<Staged, ArchiveError> {
+    stage(ArchiveReader::decrypting(reader, key, limit)?, into, wanted)
+}
+
+/// Yields the container for a tree, reading each file only as its turn
+/// comes.
+pub struct Packer {
+    root: PathBuf,
+    entries: std::vec::IntoIter<Entry>,
+    /* Encoded bytes still to yield, from `pos` */
+    pending: Vec<u8>,
+    pos: usize,
+    /* The file being copied and the bytes it still owes */
+    file: Option<(BufReader<File>, u64)>,
+    path: String,
+    ended: bool,
+    container_len: u64,
+}
+
+/// List the tree under `root` for packing. Files are read later, as the
+/// packer reaches them; one that has changed size by then is an
+/// `InvalidData` error.
+pub fn pack(root: &Path) -> io::Result<Packer> {
+    let mut pending = ARCHIVE_MAGIC.to_vec();
+    pending.push(ARCHIVE_VERSION);
+    let entries = walk(root)?;
+    let container_len = entries
+        .iter()
+        .map(|entry| 27 + entry.path.len() as u64 + entry.size)
+        .sum::<u64>()
+        + pending.len() as u64
+        + 1;
+    Ok(Packer {
+        root: root.to_path_buf(),
+        entries: entries.into_iter(),
+        pending,
+        pos: 0,
+        file: None,
+        path: String::new(),
+        ended: false,
+        container_len,
+    })
+}
+
+impl Packer {
+    /// The length of the container the packer yields, if no file changes
+    /// size first.
+    pub fn container_len(&self) -> u64 {
+        self.container_len
+    }
+
+    fn changed(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            std::format!("{} changed size while being archived", self.path),
+        )
+    }
+}
+
+impl Read for Packer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.pos < self.pending.len() {
+                let n = buf.len().min(self.pending.len() - self.pos);
+                buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            if let Some((file, owed)) = &mut self.file {
+                if *owed > 0 {
+                    let max = buf.len().min(*owed as usize);
+                    let n = file.read(&mut buf[..max])?;
+                    if n == 0 {
+                        return Err(self.changed());
+                    }
+                    *owed -= n as u64;
+                    return Ok(n);
+                }
+                if file.read(&mut [0u8])? != 0 {
+                    return Err(self.changed());
+                }
+                self.file = None;
+            }
+            /* Once ended, stay there: `pending` is spent */
+            if self.ended {
+                return Ok(0);
+            }
+            self.pos = 0;
+            match self.entries.next() {
+                Some(entry) => {
+                    self.pending = entry.encode();
+                    if entry.kind == EntryKind::File {
+                        let file = File::open(self.root.join(&entry.path))?;
+                        self.file = Some((BufReader::new(file), entry.size));
+                    }
+                    self.path = entry.path;
+                }
+                None => {
+                    self.pending = std::vec![KIND_END];
+                    self.ended = true;
+                }
+            }
+        }
+    }
+}
+
+/* Every regular file and directory under `root`, sorted on path */
+fn walk(root: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut pending = std::vec![String::new()];
+    while let Some(dir) = pending.pop() {
+        for item in fs::read_dir(root.join(&dir))? {
+            let item = item?;
+            let name = item.file_name().into_string().map_err(|name| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    std::format!("non-UTF-8 file name {:?}", name),
+                )
+            })?;
+            let path = if dir.is_empty() {
+                name
+            } else {
+                std::format!("{}/{}", dir, name)
+            };
+            /* Neither follows a symlink */
+            let file_type = item.file_type()?;
+            let kind = if file_type.is_dir() {
+                EntryKind::Directory
+            } else if file_type.is_file() {
+                EntryKind::File
+            } else {
+                continue;
+            };
+            if path.len() > u16::MAX as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "path too long for an archive",
+                ));
+            }
+            let metadata = item.metadata()?;
+            let (mtime, mtime_nanos) = unix_time(metadata.modified()?);
+            if kind == EntryKind::Directory {
+                pending.push(path.clone());
+            }
+            entries.push(Entry {
+                path,
+                kind,
+                mode: mode_of(&metadata, kind),
+                mtime,
+                mtime_nanos,
+                size: match kind {
+                    EntryKind::File => metadata.len(),
+                    EntryKind::Directory => 0,
+                },
+            });
+        }
+    }
+    entries.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata, _kind: EntryKind) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & MODE_MASK
+}
+
+/* Elsewhere only read-only is known; the rest is what unix would give */
+#[cfg(not(unix))]
+fn mode_of(metadata: &fs::Metadata, kind: EntryKind) -> u32 {
+    match (kind, metadata.permissions().readonly()) {
+        (EntryKind::Directory, _) => 0o755,
+        (EntryKind::File, true) => 0o444,
+        (EntryKind::File, false) => 0o644,
+    }
+}
+
+/// Reads the entries of a container in order.
+pub struct ArchiveReader<R: Read> {
+    reader: R,
+    /* Data bytes of the current entry not yet read */
+    unread: u64,
+    last_path: Option<String>,
+    ended: bool,
+    /* How data that is not copied out gets passed over */
+    pass: fn(&mut R, u64) -> Result<(), ArchiveError>,
+    /* Bytes of the container up to the end of the current entry */
+    length: u64,
+    /* The padding expected after the end marker */
+    padding: Option<PaddingScheme>,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    /// Read and check the magic and version.
+    pub fn new(mut reader: R) -> Result<Self, ArchiveError> {
+        let mut start = [0u8; 5];
+        reader.read_exact(&mut start)?;
+        if start[..4] != ARCHIVE_MAGIC {
+            return Err(ArchiveError::Malformed);
+        }
+        if start[4] != ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(start[4]));
+        }
+        Ok(ArchiveReader {
+            reader,
+            unread: 0,
+            last_path: None,
+            ended: false,
+            pass: pass_by_reading,
+            length: 5,
+            padding: None,
+        })
+    }
+
+    /// The next entry, skipping whatever is left of the current one's
+    /// data. `None` only once the end marker has been read and nothing
+    /// follows it.
+    pub fn next_entry(&mut self) -> Result<Option<Entry>, ArchiveError> {
+        if self.ended {
+            return Ok(None);
+        }
+        self.skip_data()?;
+        let kind = match self.read_array::<1>()?[0] {
+            KIND_END => {
+                /* Reading on to the end also authenticates the last chunk */
+                self.length += 1;
+                match self.padding {
+                    Some(scheme) => check_padding(&mut self.reader, self.length, scheme)?,
+                    None if self.reader.read(&mut [0u8])? != 0 => {
+                        return Err(ArchiveError::Malformed)
+                    }
+                    None => {}
+                }
+                self.ended = true;
+                return Ok(None);
+            }
+            KIND_FILE => EntryKind::File,
+            KIND_DIRECTORY => EntryKind::Directory,
+            _ => return Err(ArchiveError::Malformed),
+        };
+        let path_len = u16::from_be_bytes(self.read_array()?) as usize;
+        let mut path = std::vec![0u8; path_len];
+        self.reader.read_exact(&mut path)?;
+        let path = String::from_utf8(path).map_err(|_| ArchiveError::Malformed)?;
+        check_path(&path, self.last_path.as_deref())?;
+        let mode = u32::from_be_bytes(self.read_array()?);
+        let mtime = i64::from_be_bytes(self.read_array()?);
+        let mtime_nanos = u32::from_be_bytes(self.read_array()?);
+        let size = u64::from_be_bytes(self.read_array()?);
+        if mode & !MODE_MASK != 0
+            || mtime_nanos >= 1_000_000_000
+            || (kind == EntryKind::Directory && size != 0)
+        {
+            return Err(ArchiveError::Malformed);
+        }
+        self.unread = size;
+        self.length = (self.length + 27 + path_len as u64)
+            .checked_add(size)
+            .ok_or(ArchiveError::Malformed)?;
+        self.last_path = Some(path.clone());
+        Ok(Some(Entry {
+            path,
+            kind,
+            mode,
+            mtime,
+            mtime_nanos,
+            size,
+        }))
+    }
+
+    /// Copy the rest of the current entry's data into `writer`.
+    pub fn copy_data<W: Write>(&mut self, writer: &mut W) -> Result<u64, ArchiveError> {
+        let copied = io::copy(&mut (&mut self.reader).take(self.unread), writer)?;
+        self.unread -= copied;
+        if self.unread != 0 {
+            return Err(ArchiveError::Truncated);
+        }
+        Ok(copied)
+    }
+
+    fn skip_data(&mut self) -> Result<(), ArchiveError> {
+        let unread = core::mem::take(&mut self.unread);
+        (self.pass)(&mut self.reader, unread)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], ArchiveError> {
+        let mut bytes = [0u8; N];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl<R: Read> ArchiveReader<Plaintext<R>> {
+    /// Start reading the archive encrypted in `reader` under `key`. Data
+    /// that is skipped rather than copied out is read past without being
+    /// opened, as `DecryptReader::skip` does: only the chunks holding
+    /// entry metadata or data that is read, and the last, authenticate.
+    /// A compressed archive is decompressed as it is read instead, and
+    /// fails with `Compression` if its container is more than `limit`
+    /// bytes (before any chunk is read, as its length is recorded).
+    /// Without the `compress` feature it fails with
+    /// `HeaderError::Compressed`.
+    pub fn decrypting(reader: R, key: &[u8], limit: u64) -> Result<Self, ArchiveError> {
+        #[cfg(feature = "compress")]
+        {
+            let reader = DecryptReader::open(reader, key, true)?;
+            let header = reader.header();
+            if let Some(codec) = header.compression {
+                let expected = header.original_len;
+                let reader = DecompressReader::new(reader, codec, limit, expected)
+                    .map_err(ArchiveError::Compression)?;
+                return ArchiveReader::new(Plaintext::Compressed(reader));
+            }
+            Self::stored(reader)
+        }
+        #[cfg(not(feature = "compress"))]
+        {
+            let _ = limit;
+            Self::stored(DecryptReader::new(reader, key)?)
+        }
+    }
+
+    fn stored(reader: DecryptReader<R>) -> Result<Self, ArchiveError> {
+        let padding = reader.header().padding;
+        let mut archive = ArchiveReader::new(Plaintext::Stored(reader))?;
+        archive.pass = |reader, n| match reader {
+            Plaintext::Stored(reader) => Ok(reader.skip(n)?),
+            #[cfg(feature = "compress")]
+            reader => pass_by_reading(reader, n),
+        };
+        archive.padding = padding;
+        Ok(archive)
+    }
+}
+
+/// An archive's stream as its container is read: decrypted, and
+/// decompressed if it was compressed.
+pub enum Plaintext<R: Read> {
+    Stored(DecryptReader<R>),
+    #[cfg(feature = "compress")]
+    Compressed(DecompressReader<DecryptReader<R>>),
+}
+
+impl<R: Read> Read for Plaintext<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Plaintext::Stored(reader) => reader.read(buf),
+            #[cfg(feature = "compress")]
+            Plaintext::Compressed(reader) => reader.read(buf),
+        }
+    }
+}
+
+/* What follows a padded container: zeros, then its `length`, exactly
+ * filling the bucket `scheme` gives it */
+fn check_padding<R: Read>(
+    reader: &mut R,
+    length: u64,
+    scheme: PaddingScheme,
+) -> Result<(), ArchiveError> {
+    let padded = scheme.padded_len(length);
+    let zeros = padded - length - PADDING_TRAILER_LEN as u64;
+    let mut buf = [0u8; 8192];
+    let mut left = zeros;
+    while left > 0 {
+        let n = left.min(buf.len() as u64) as usize;
+        reader.read_exact(&mut buf[..n])?;
+        if buf[..n].iter().any(|&b| b != 0) {
+            return Err(ArchiveError::Malformed);
+        }
+        left -= n as u64;
+    }
+    let mut trailer = [0u8; PADDING_TRAILER_LEN];
+    reader.read_exact(&mut trailer)?;
+    if u64::from_be_bytes(trailer) != length || reader.read(&mut [0u8])? != 0 {
+        return Err(ArchiveError::Malformed);
+    }
+    Ok(())
+}
+
+fn pass_by_reading<R: Read>(reader: &mut R, n: u64) -> Result<(), ArchiveError> {
+    if io::copy(&mut reader.take(n), &mut io::sink())? != n {
+        return Err(ArchiveError::Truncated);
+    }
+    Ok(())
+}
+
+/* Relative, plain components only, and after `previous` */
+fn check_path(path: &str, previous: Option<&str>) -> Result<(), ArchiveError> {
+    let plain = !path.is_empty()
+        && !path.contains('\\')
+        && !path.contains('\0')
+        && path
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..");
+    if !plain || previous.is_some_and(|previous| previous >= path) {
+        return Err(ArchiveError::UnsafePath(path.into()));
+    }
+    Ok(())
+}
+
+/// Unpack every entry from `archive` as the directory `into`, which
+/// must not exist or be empty. Entries go into a fresh directory beside
+/// `into` that is renamed into place once the end marker has been read,
+/// and removed on any error.
+pub fn unpack<R: Read>(archive: ArchiveReader<R>, into: &Path) -> Result<Unpacked, ArchiveError> {
+    stage(archive, into, |_| true)?.finish()
+}
+
+/// Unpack the entries from `archive` that `wanted` accepts, with the
+/// directories above them, into a fresh directory beside `into`, which
+/// must not exist or be empty. The data of other entries is skipped.
+pub fn stage<R: Read, F: FnMut(&Entry) -> bool>(
+    mut archive: ArchiveReader<R>,
+    into: &Path,
+    mut wanted: F,
+) -> Result<Staged, ArchiveError> {
+    let existing = match fs::read_dir(into) {
+        Ok(mut items) => match items.next() {
+            None => true,
+            Some(_) => return Err(ArchiveError::DestinationNotEmpty(into.to_path_buf())),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+        Err(e) if e.kind() == io::ErrorKind::NotADirectory => {
+            return Err(ArchiveError::DestinationNotEmpty(into.to_path_buf()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let staging = Staging::beside(into)?;
+    let mut unpacked = Unpacked::default();
+    /* Directory times and modes wait until their contents are written */
+    let mut directories = Vec::new();
+    while let Some(entry) = archive.next_entry()? {
+        if !wanted(&entry) {
+            continue;
+        }
+        let target = staging.path.join(&entry.path);
+        match entry.kind {
+            EntryKind::Directory => {
+                fs::create_dir_all(&target)?;
+                unpacked.directories += 1;
+                directories.push((target, entry));
+            }
+            EntryKind::File => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let file = File::options().write(true).create_new(true).open(&target)?;
+                let mut writer = BufWriter::new(file);
+                unpacked.bytes += archive.copy_data(&mut writer)?;
+                let file = writer.into_inner().map_err(|e| e.into_error())?;
+                restore(&file, &entry)?;
+                unpacked.files += 1;
+            }
+        }
+    }
+    for (path, entry) in directories.iter().rev() {
+        restore(&File::open(path)?, entry)?;
+    }
+    Ok(Staged {
+        staging,
+        into: into.to_path_buf(),
+        existing,
+        unpacked,
+    })
+}
+
+/// Entries unpacked beside their destination, with every chunk they
+/// were read from and the archive's last chunk authenticated, but not
+/// yet in place. Dropping it removes it.
+pub struct Staged {
+    staging: Staging,
+    into: PathBuf,
+    /* `into` was there already, empty */
+    existing: bool,
+    unpacked: Unpacked,
+}
+
+impl Staged {
+    /// What was unpacked.
+    pub fn unpacked(&self) -> Unpacked {
+        self.unpacked
+    }
+
+    /// Move the unpacked tree into place.
+    pub fn finish(mut self) -> Result<Unpacked, ArchiveError> {
+        if self.existing {
+            fs::remove_dir(&self.into)?;
+        }
+        fs::rename(&self.staging.path, &self.into)?;
+        self.staging.kept = true;
+        Ok(self.unpacked)
+    }
+}
+
+/// Give `file` the mode and modification time recorded for `entry`.
+pub fn restore(file: &File, entry: &Entry) -> io::Result<()> {
+    file.set_modified(entry.modified())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(entry.mode))?;
+    }
+    #[cfg(not(unix))]
+    if entry.kind == EntryKind::File {
+        let mut permissions = file.metadata()?.permissions();
+        permissions.set_readonly(entry.mode & 0o222 == 0);
+        file.set_permissions(permissions)?;
+    }
+    Ok(())
+}
+
+/* A new, empty directory beside the destination, removed with all it
+ * holds on drop unless kept */
+struct Staging {
+    path: PathBuf,
+    kept: bool,
+}
+
+impl Staging {
+    fn beside(target: &Path) -> io::Result<Staging> {
+        let dir = match target.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        loop {
+            let mut suffix = [0u8; 6];
+            generate_nonce(&mut suffix);
+            let suffix: String = suffix.iter().map(|b| std::format!("{:02x}", b)).collect();
+            let path = dir.join(std::format!(".{}.rcli-extract-{}", name, suffix));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(Staging { path, kept: false }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+        /* Restored modes may have made directories read-only */
+        #[cfg(unix)]
+        make_writable(&self.path);
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn make_writable(dir: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    let _ = fs::set_permissions(dir, fs::Permissions::from_mode(0o700));
+    if let Ok(items) = fs::read_dir(dir) {
+        for item in items.flatten() {
+            if item.file_type().is_ok_and(|t| t.is_dir()) {
+                make_writable(&item.path());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::MIN_CHUNK_SIZE;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/deep/er")).unwrap();
+        fs::create_dir(root.join("empty")).unwrap();
+        fs::write(root.join("a.txt"), b"top level").unwrap();
+        fs::write(root.join("a/one"), b"one").unwrap();
+        fs::write(root.join("a/deep/er/two"), std::vec![7u8; 100_000]).unwrap();
+        fs::write(root.join("a/nothing"), b"").unwrap();
+        dir
+    }
+
+    fn sealed(root: &Path) -> Vec<u8> {
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            ..EncryptOptions::default()
+        };
+        let mut out = Vec::new();
+        create(root, &mut out, &KEY, &opts).unwrap();
+        out
+    }
+
+    /* The container bytes for a list of (path, kind, data) */
+    fn container(entries: &[(&str, u8, &[u8])]) -> Vec<u8> {
+        let mut out = ARCHIVE_MAGIC.to_vec();
+        out.push(ARCHIVE_VERSION);
+        for (path, kind, data) in entries {
+            out.push(*kind);
+            out.extend_from_slice(&(path.len() as u16).to_be_bytes());
+            out.extend_from_slice(path.as_bytes());
+            out.extend_from_slice(&0o644u32.to_be_bytes());
+            out.extend_from_slice(&0i64.to_be_bytes());
+            out.extend_from_slice(&0u32.to_be_bytes());
+            out.extend_from_slice(&(data.len() as u64).to_be_bytes());
+            out.extend_from_slice(data);
+        }
+        out.push(KIND_END);
+        out
+    }
+
+    fn entries(data: &[u8]) -> Result<Vec<Entry>, ArchiveError> {
+        let mut archive = ArchiveReader::new(data)?;
+        let mut entries = Vec::new();
+        while let Some(entry) = archive.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn packing_is_sorted_and_deterministic() {
+        let dir = tree();
+        let mut packed = Vec::new();
+        let mut packer = pack(dir.path()).unwrap();
+        packer.read_to_end(&mut packed).unwrap();
+        assert_eq!(packer.read(&mut [0u8; 8]).unwrap(), 0);
+        let listed = entries(&packed).unwrap();
+        let paths: Vec<&str> = listed.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "a",
+                "a.txt",
+                "a/deep",
+                "a/deep/er",
+                "a/deep/er/two",
+                "a/nothing",
+                "a/one",
+                "empty"
+            ]
+        );
+        assert_eq!(listed[4].size, 100_000);
+        assert_eq!(listed[0].kind, EntryKind::Directory);
+
+        let mut again = Vec::new();
+        pack(dir.path()).unwrap().read_to_end(&mut again).unwrap();
+        assert_eq!(packed, again);
+    }
+
+    #[test]
+    fn round_trip_keeps_contents_modes_and_times() {
+        let dir = tree();
+        let root = dir.path();
+        let stamp = UNIX_EPOCH + Duration::new(1_600_000_000, 123_456_789);
+        File::options()
+            .write(true)
+            .open(root.join("a/one"))
+            .unwrap()
+            .set_modified(stamp)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(root.join("a.txt"), fs::Permissions::from_mode(0o600)).unwrap();
+            fs::set_permissions(root.join("a/deep"), fs::Permissions::from_mode(0o750)).unwrap();
+        }
+        let archive = sealed(root);
+
+        let out = tempfile::tempdir().unwrap();
+        let into = out.path().join("restore");
+        let unpacked = extract(&archive[..], &into, &KEY, u64::MAX).unwrap();
+        assert_eq!(
+            unpacked,
+            Unpacked {
+                files: 4,
+                directories: 4,
+                bytes: 100_012
+            }
+        );
+        assert_eq!(
+            fs::read(into.join("a/deep/er/two")).unwrap(),
+            std::vec![7u8; 100_000]
+        );
+        assert_eq!(fs::read(into.join("a/nothing")).unwrap(), b"");
+        assert!(into.join("empty").is_dir());
+        let modified = fs::metadata(into.join("a/one"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(modified, stamp);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &str| fs::metadata(into.join(p)).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode("a.txt"), 0o600);
+            assert_eq!(mode("a/deep"), 0o750);
+        }
+        /* the staging directory is gone */
+        assert_eq!(fs::read_dir(out.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn destination_must_be_new_or_empty() {
+        let dir = tree();
+        let archive = sealed(dir.path());
+        let out = tempfile::tempdir().unwrap();
+        /* an empty directory is replaced */
+        extract(&archive[..], out.path(), &KEY, u64::MAX).unwrap();
+        assert!(out.path().join("a.txt").exists());
+        assert!(matches!(
+            extract(&archive[..], out.path(), &KEY, u64::MAX),
+            Err(ArchiveError::DestinationNotEmpty(_))
+        ));
+    }
+
+    #[test]
+    fn escaping_paths_are_refused() {
+        for path in [
+            "../evil",
+            "/etc/passwd",
+            "a/../../evil",
+            "a//b",
+            "./a",
+            "a\\..\\b",
+            "",
+        ] {
+            let data = container(&[(path, KIND_FILE, b"evil")]);
+            assert!(
+                matches!(entries(&data), Err(ArchiveError::UnsafePath(_))),
+                "{:?}",
+                path
+            );
+        }
+        /* out of order, or twice */
+        let data = container(&[("b", KIND_FILE, b""), ("a", KIND_FILE, b"")]);
+        assert!(matches!(entries(&data), Err(ArchiveError::UnsafePath(_))));
+        let data = container(&[("a", KIND_FILE, b""), ("a", KIND_FILE, b"")]);
+        assert!(matches!(entries(&data), Err(ArchiveError::UnsafePath(_))));
+    }
+
+    #[test]
+    fn listing_reads_metadata_and_skips_data() {
+        let dir = tree();
+        let archive = sealed(dir.path());
+        let mut packed = Vec::new();
+        pack(dir.path()).unwrap().read_to_end(&mut packed).unwrap();
+        let mut listed = Vec::new();
+        list(&archive[..], &KEY, u64::MAX, |entry| {
+            listed.push(entry.clone())
+        })
+        .unwrap();
+        assert_eq!(listed, entries(&packed).unwrap());
+
+        /* the first chunk holds the first entries, and the last the end */
+        let header_len = crate::fs::read_header(&mut &archive[..]).unwrap().1.len();
+        for at in [header_len + 5, archive.len() - 5] {
+            let mut corrupted = archive.clone();
+            corrupted[at] ^= 1;
+            assert!(matches!(
+                list(&corrupted[..], &KEY, u64::MAX, |_| {}),
+                Err(ArchiveError::Stream(FsCryptoError::Crypto(
+                    crate::ErrorStates::DecryptionFailed
+                )))
+            ));
+        }
+        let sealed_size = MIN_CHUNK_SIZE as usize + crate::stream::TAG_LEN;
+        let cut = &archive[..header_len + 3 * sealed_size];
+        assert!(list(cut, &KEY, u64::MAX, |_| {}).is_err());
+    }
+
+    #[test]
+    fn some_entries_alone() {
+        let dir = tree();
+        let archive = sealed(dir.path());
+        let out = tempfile::tempdir().unwrap();
+        let into = out.path().join("restore");
+        let staged =
+            extract_matching(&archive[..], &into, &KEY, u64::MAX, |e| e.path == "a/one").unwrap();
+        assert!(!into.exists());
+        assert_eq!(staged.unpacked().files, 1);
+        staged.finish().unwrap();
+        assert_eq!(fs::read(into.join("a/one")).unwrap(), b"one");
+        assert_eq!(fs::read_dir(&into).unwrap().count(), 1);
+        assert_eq!(fs::read_dir(into.join("a")).unwrap().count(), 1);
+
+        /* a staged tree that is dropped leaves nothing behind */
+        let other = out.path().join("other");
+        drop(extract_matching(&archive[..], &other, &KEY, u64::MAX, |_| true).unwrap());
+        assert_eq!(fs::read_dir(out.path()).unwrap().count(), 1);
+
+        let mut data = Vec::new();
+        let mut seen = 0;
+        let found = extract_file(
+            &archive[..],
+            &KEY,
+            u64::MAX,
+            |e| {
+                seen += 1;
+                e.path.ends_with("two")
+            },
+            &mut data,
+        )
+        .unwrap();
+        assert_eq!(found.unwrap().path, "a/deep/er/two");
+        assert_eq!(data, std::vec![7u8; 100_000]);
+        assert_eq!(seen, 8);
+
+        /* damage to the data read out is found, and to data skipped over
+         * is not */
+        let header_len = crate::fs::read_header(&mut &archive[..]).unwrap().1.len();
+        let sealed_size = MIN_CHUNK_SIZE as usize + crate::stream::TAG_LEN;
+        let mut corrupted = archive.clone();
+        corrupted[header_len + 5 * sealed_size + 5] ^= 1;
+        let want_two = |e: &Entry| e.path.ends_with("two");
+        let result = extract_file(&corrupted[..], &KEY, u64::MAX, want_two, &mut io::sink());
+        assert!(matches!(result, Err(ArchiveError::Stream(_))));
+        let want_one = |e: &Entry| e.path == "a/one";
+        let result = extract_file(&corrupted[..], &KEY, u64::MAX, want_one, &mut io::sink());
+        assert!(result.unwrap().is_some());
+    }
+
+    #[test]
+    fn padded_archives_hide_their_size() {
+        let dir = tree();
+        let mut packed = Vec::new();
+        pack(dir.path()).unwrap().read_to_end(&mut packed).unwrap();
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            padding: Some(PaddingScheme::Block(1 << 20)),
+            ..EncryptOptions::default()
+        };
+        let mut archive = Vec::new();
+        create(dir.path(), &mut archive, &KEY, &opts).unwrap();
+        let header_len = crate::fs::read_header(&mut &archive[..]).unwrap().1.len();
+        let chunks = (1usize << 20).div_ceil(MIN_CHUNK_SIZE as usize);
+        assert_eq!(
+            archive.len(),
+            header_len + (1 << 20) + chunks * crate::stream::TAG_LEN
+        );
+
+        let mut listed = Vec::new();
+        list(&archive[..], &KEY, u64::MAX, |entry| {
+            listed.push(entry.clone())
+        })
+        .unwrap();
+        assert_eq!(listed, entries(&packed).unwrap());
+        let out = tempfile::tempdir().unwrap();
+        let into = out.path().join("restore");
+        extract(&archive[..], &into, &KEY, u64::MAX).unwrap();
+        assert_eq!(fs::read(into.join("a/one")).unwrap(), b"one");
+
+        /* the container's length in the trailer is checked */
+        let reader = DecryptReader::new(&archive[..], &KEY).unwrap();
+        let mut archive = ArchiveReader::new(reader).unwrap();
+        archive.padding = Some(PaddingScheme::Block(1 << 20));
+        archive.length += 1;
+        while let Ok(Some(_)) = archive.next_entry() {}
+        assert!(!archive.ended);
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn compressed_archive_unpacks_under_limit() {
+        let dir = tree();
+        let mut packed = Vec::new();
+        let packer = pack(dir.path()).unwrap();
+        let container_len = packer.container_len();
+        { packer }.read_to_end(&mut packed).unwrap();
+        assert_eq!(container_len, packed.len() as u64);
+
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            compression: Some(crate::compress::Codec::Zstd(3)),
+            ..EncryptOptions::default()
+        };
+        let mut archive = Vec::new();
+        create(dir.path(), &mut archive, &KEY, &opts).unwrap();
+        assert!(archive.len() < sealed(dir.path()).len() / 10);
+        let header = crate::fs::read_header(&mut &archive[..]).unwrap().0;
+        assert_eq!(header.original_len, Some(container_len));
+
+        let mut listed = Vec::new();
+        list(&archive[..], &KEY, container_len, |entry| {
+            listed.push(entry.clone())
+        })
+        .unwrap();
+        assert_eq!(listed, entries(&packed).unwrap());
+        let out = tempfile::tempdir().unwrap();
+        let into = out.path().join("restore");
+        extract(&archive[..], &into, &KEY, container_len).unwrap();
+        assert_eq!(
+            fs::read(into.join("a/deep/er/two")).unwrap(),
+            [7u8; 100_000]
+        );
+
+        let elsewhere = out.path().join("elsewhere");
+        assert!(matches!(
+            extract(&archive[..], &elsewhere, &KEY, container_len - 1),
+            Err(ArchiveError::Compression(CompressError::TooLarge { .. }))
+        ));
+        assert!(!elsewhere.exists());
+
+        let padded = EncryptOptions {
+            padding: Some(PaddingScheme::Padme),
+            ..opts
+        };
+        assert!(create(dir.path(), &mut Vec::new(), &KEY, &padded).is_err());
+    }
+
+    #[test]
+    fn damage_leaves_nothing_behind() {
+        let dir = tree();
+        let archive = sealed(dir.path());
+        let out = tempfile::tempdir().unwrap();
+        let into = out.path().join("restore");
+
+        let mut corrupted = archive.clone();
+        let last = corrupted.len() - 5;
+        corrupted[last] ^= 1;
+        assert!(matches!(
+            extract(&corrupted[..], &into, &KEY, u64::MAX),
+            Err(ArchiveError::Stream(FsCryptoError::Crypto(
+                crate::ErrorStates::DecryptionFailed
+            )))
+        ));
+        let sealed_size = MIN_CHUNK_SIZE as usize + crate::stream::TAG_LEN;
+        let header_len = crate::fs::read_header(&mut &archive[..]).unwrap().1.len();
+        let cut = &archive[..header_len + 3 * sealed_size];
+        assert!(matches!(
+            extract(cut, &into, &KEY, u64::MAX),
+            Err(ArchiveError::Stream(FsCryptoError::Crypto(
+                crate::ErrorStates::StreamTruncated
+            )))
+        ));
+        assert_eq!(fs::read_dir(out.path()).unwrap().count(), 0);
+
+        /* a container cut short inside an authentic stream */
+        let mut packed = Vec::new();
+        pack(dir.path()).unwrap().read_to_end(&mut packed).unwrap();
+        packed.truncate(packed.len() - 1);
+        assert!(matches!(entries(&packed), Err(ArchiveError::Truncated)));
+    }
+}
@@ -0,0 +1,364 @@
+/* ASCII armor for RCLI files, for pasting into mail or tickets.
+ *
+ *   -----BEGIN RCLI ENCRYPTED FILE-----
+ *   <standard base64, 64 columns per line>
+ *   -----END RCLI ENCRYPTED FILE-----
+ *
+ * The armor only changes the transport encoding: the bytes between the
+ * markers are exactly what a non-armored run writes, header and all.
+ * Both directions stream, so armoring adds no buffering beyond one line.
+ *
+ * The reader accepts CRLF line endings, blank lines and any wrap width,
+ * since mail clients and editors rewrap freely. Anything after the END
+ * line is ignored. */
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::string::String;
+use std::vec::Vec;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+/// First line of an armored file.
+pub const ARMOR_BEGIN: &str = "-----BEGIN RCLI ENCRYPTED FILE-----";
+/// Last line of an armored file.
+pub const ARMOR_END: &str = "-----END RCLI ENCRYPTED FILE-----";
+/// Base64 characters per armored line.
+pub const ARMOR_LINE_LEN: usize = 64;
+
+/* Input bytes per full line: 64 base64 characters encode 48 bytes */
+const LINE_BYTES: usize = ARMOR_LINE_LEN / 4 * 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArmorError {
+    /* The first line is not ARMOR_BEGIN */
+    MissingBegin,
+    /* Input ends before ARMOR_END */
+    MissingEnd,
+    /* The (1-based) line is not valid base64, or the body stops
+     * mid-group at the END line */
+    BadBase64 { line: usize },
+}
+
+impl fmt::Display for ArmorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArmorError::MissingBegin => write!(f, "armor does not start with {}", ARMOR_BEGIN),
+            ArmorError::MissingEnd => write!(f, "armor is truncated: missing {}", ARMOR_END),
+            ArmorError::BadBase64 { line } => {
+                write!(f, "armor has invalid base64 on line {}", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArmorError {}
+
+impl From<ArmorError> for io::Error {
+    fn from(e: ArmorError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Whether `prefix` (the first bytes of some input) starts an armored
+/// file. Needs `ARMOR_BEGIN.len()` bytes to answer `true`.
+pub fn is_armored(prefix: &[u8]) -> bool {
+    prefix.starts_with(ARMOR_BEGIN.as_bytes())
+}
+
+/// Armor `data` in one go.
+pub fn armor(data: &[u8]) -> String {
+    let mut writer = ArmorWriter::new(Vec::new()).expect("writing to a Vec cannot fail");
+    writer
+        .write_all(data)
+        .expect("writing to a Vec cannot fail");
+    let out = writer.finish().expect("writing to a Vec cannot fail");
+    String::from_utf8(out).expect("armor is ASCII")
+}
+
+/// Remove the armor from `text` in one go.
+pub fn dearmor(text: &[u8]) -> Result<Vec<u8>, ArmorError> {
+    let mut reader = ArmorReader::new(text).map_err(armor_error)?;
+    let mut out = Vec::new();
+    reader.read_to_end(&mut out).map_err(armor_error)?;
+    Ok(out)
+}
+
+/* Reading from a slice only fails with the ArmorError put there */
+fn armor_error(e: io::Error) -> ArmorError {
+    e.into_inner()
+        .and_then(|inner| inner.downcast::<ArmorError>().ok())
+        .map(|e| *e)
+        .unwrap_or(ArmorError::MissingEnd)
+}
+
+/// Streams armored output to `inner`. Call `finish` to write the final
+/// line and the END marker; dropping the writer without it leaves a
+/// truncated block.
+pub struct ArmorWriter<W: Write> {
+    inner: W,
+    /* Bytes not yet making up a full line */
+    pending: Vec<u8>,
+}
+
+impl<W: Write> ArmorWriter<W> {
+    /// Writes the BEGIN line straight away.
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(ARMOR_BEGIN.as_bytes())?;
+        inner.write_all(b"\n")?;
+        Ok(ArmorWriter {
+            inner,
+            pending: Vec::with_capacity(LINE_BYTES),
+        })
+    }
+
+    /// Write the last (possibly short, padded) line and the END marker.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            let line = STANDARD.encode(&self.pending);
+            self.write_line(&line)?;
+        }
+        self.inner.write_all(ARMOR_END.as_bytes())?;
+        self.inner.write_all(b"\n")?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.inner.write_all(line.as_bytes())?;
+        self.inner.write_all(b"\n")
+    }
+}
+
+impl<W: Write> Write for ArmorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let take = buf.len().min(LINE_BYTES - self.pending.len());
+        self.pending.extend_from_slice(&buf[..take]);
+        if self.pending.len() == LINE_BYTES {
+            let line = STANDARD.encode(&self.pending);
+            self.pending.clear();
+            self.write_line(&line)?;
+        }
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Streams the bytes inside an armored block read from `inner`. Errors
+/// are `io::ErrorKind::InvalidData` wrapping an `ArmorError`.
+pub struct ArmorReader<R: BufRead> {
+    inner: R,
+    /* 1-based number of the last line read */
+    line: usize,
+    /* Base64 characters carried over to the next line (fewer than 4),
+     * and the line they came from */
+    carry: Vec<u8>,
+    carry_line: usize,
+    /* The last group decoded ended in '=' */
+    padded: bool,
+    decoded: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: BufRead> ArmorReader<R> {
+    /// Reads and checks the BEGIN line.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut first = Vec::new();
+        inner.read_until(b'\n', &mut first)?;
+        if trim_eol(&first) != ARMOR_BEGIN.as_bytes() {
+            return Err(ArmorError::MissingBegin.into());
+        }
+        Ok(ArmorReader {
+            inner,
+            line: 1,
+            carry: Vec::new(),
+            carry_line: 0,
+            padded: false,
+            decoded: Vec::new(),
+            pos: 0,
+            done: false,
+        })
+    }
+
+    /* Decode lines until some bytes come out or the END line is seen */
+    fn fill(&mut self) -> io::Result<()> {
+        let mut text = Vec::new();
+        while self.pos == self.decoded.len() && !self.done {
+            text.clear();
+            if self.inner.read_until(b'\n', &mut text)? == 0 {
+                return Err(ArmorError::MissingEnd.into());
+            }
+            self.line += 1;
+            let body = text.trim_ascii();
+            if body == ARMOR_END.as_bytes() {
+                if !self.carry.is_empty() {
+                    return Err(ArmorError::BadBase64 {
+                        line: self.carry_line,
+                    }
+                    .into());
+                }
+                self.done = true;
+                break;
+            }
+            if body.is_empty() {
+                continue;
+            }
+            /* Padding only ends the body; more base64 after it would
+             * otherwise decode as a separate message */
+            if self.padded {
+                return Err(ArmorError::BadBase64 { line: self.line }.into());
+            }
+            self.carry.extend_from_slice(body);
+            let whole = self.carry.len() / 4 * 4;
+            self.decoded.clear();
+            self.pos = 0;
+            STANDARD
+                .decode_vec(&self.carry[..whole], &mut self.decoded)
+                .map_err(|_| ArmorError::BadBase64 { line: self.line })?;
+            self.padded = self.carry[..whole].ends_with(b"=");
+            self.carry.drain(..whole);
+            if !self.carry.is_empty() {
+                self.carry_line = self.line;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for ArmorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill()?;
+        let n = buf.len().min(self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn trim_eol(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip_sizes() {
+        for len in [0, 1, 47, 48, 49, 96, 1000] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+            let text = armor(&data);
+            assert!(text.starts_with(ARMOR_BEGIN));
+            assert!(text.ends_with(&format!("{}\n", ARMOR_END)));
+            assert_eq!(dearmor(text.as_bytes()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn wraps_at_64_columns() {
+        let text = armor(&[0xAB; 100]);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1].len(), ARMOR_LINE_LEN);
+        assert_eq!(lines[2].len(), ARMOR_LINE_LEN);
+        assert_eq!(lines[3], "q6urqw==");
+    }
+
+    #[test]
+    fn streaming_writes_match_one_shot() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut writer = ArmorWriter::new(Vec::new()).unwrap();
+        for piece in data.chunks(5) {
+            writer.write_all(piece).unwrap();
+        }
+        let out = writer.finish().unwrap();
+        assert_eq!(out, armor(&data).into_bytes());
+    }
+
+    #[test]
+    fn accepts_crlf_blank_lines_and_rewrapping() {
+        let data = [0x5A; 70];
+        let body = STANDARD.encode(data);
+        let (a, b) = body.split_at(30);
+        let text = format!(
+            "{}\r\n{}\r\n\r\n{}\r\n{}\r\ntrailing text\n",
+            ARMOR_BEGIN, a, b, ARMOR_END
+        );
+        assert_eq!(dearmor(text.as_bytes()).unwrap(), data);
+    }
+
+    #[test]
+    fn reader_streams_in_small_reads() {
+        let data: Vec<u8> = (0..500).map(|i| i as u8).collect();
+        let text = armor(&data);
+        let mut reader = ArmorReader::new(Cursor::new(text.as_bytes())).unwrap();
+        let mut out = Vec::new();
+        let mut buf = [0u8; 3];
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn missing_begin() {
+        assert_eq!(dearmor(b"hello\n"), Err(ArmorError::MissingBegin));
+        assert_eq!(dearmor(b""), Err(ArmorError::MissingBegin));
+    }
+
+    #[test]
+    fn truncated_block() {
+        let text = armor(&[1u8; 200]);
+        let cut = &text[..text.len() - ARMOR_END.len() - 1];
+        assert_eq!(dearmor(cut.as_bytes()), Err(ArmorError::MissingEnd));
+        let cut = &text[..ARMOR_BEGIN.len() + 30];
+        assert_eq!(dearmor(cut.as_bytes()), Err(ArmorError::MissingEnd));
+    }
+
+    #[test]
+    fn bad_base64_names_the_line() {
+        let text = armor(&[2u8; 200]).replacen("AgIC", "A*IC", 1);
+        assert_eq!(
+            dearmor(text.as_bytes()),
+            Err(ArmorError::BadBase64 { line: 2 })
+        );
+        let text = format!("{}\nAgI\n{}\n", ARMOR_BEGIN, ARMOR_END);
+        assert_eq!(
+            dearmor(text.as_bytes()),
+            Err(ArmorError::BadBase64 { line: 2 })
+        );
+    }
+
+    #[test]
+    fn data_after_padding_is_rejected() {
+        let text = format!("{}\nZg==\nZm9v\n{}\n", ARMOR_BEGIN, ARMOR_END);
+        assert_eq!(
+            dearmor(text.as_bytes()),
+            Err(ArmorError::BadBase64 { line: 3 })
+        );
+    }
+
+    #[test]
+    fn non_text_is_bad_base64() {
+        let mut text = format!("{}\n", ARMOR_BEGIN).into_bytes();
+        text.extend_from_slice(b"\xff\xfe\xfd\xfc\n");
+        assert_eq!(dearmor(&text), Err(ArmorError::BadBase64 { line: 2 }));
+    }
+
+    #[test]
+    fn is_armored_sniffs_the_first_line() {
+        assert!(is_armored(armor(b"x").as_bytes()));
+        assert!(!is_armored(b"RCLI\x01"));
+        assert!(!is_armored(b"-----BEGIN PGP MESSAGE-----"));
+    }
+}
@@ -0,0 +1,326 @@
+/* Fast non-cryptographic checksums: CRC-32 and xxHash64.
+ *
+ * These catch accidental corruption (bit rot, truncated copies) far more
+ * cheaply than SHA-2, but anyone can forge a matching input on purpose.
+ * Use a digest or an HMAC where tampering matters.
+ *
+ * CRC-32 is the IEEE 802.3 / zlib variant (reflected polynomial
+ * 0xEDB88320). xxHash64 follows the reference spec:
+ * [xxHash]: https://github.com/Cyan4973/xxHash/blob/dev/doc/xxhash_spec.md
+ *
+ * Both contexts take input in pieces, like `digest::Hasher`, and with the
+ * `std` feature implement `io::Write`. */
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+/* Reflected IEEE polynomial */
+const CRC32_POLY: u32 = 0xEDB8_8320;
+
+/// Byte-at-a-time CRC-32 (IEEE) lookup table.
+pub(crate) static CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-32 of `data` in one call.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finalize()
+}
+
+/// Incremental CRC-32 (IEEE) context.
+#[derive(Debug, Clone)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32 { state: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let mut crc = self.state;
+        for &b in data {
+            crc = CRC32_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+        }
+        self.state = crc;
+    }
+
+    pub fn finalize(self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+/* xxHash64 consumes input in 32-byte stripes of four lanes */
+const STRIPE_LEN: usize = 32;
+
+/// xxHash64 of `data` in one call.
+pub fn xxhash64(data: &[u8], seed: u64) -> u64 {
+    let mut hasher = XxHash64::with_seed(seed);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Incremental xxHash64 context.
+#[derive(Debug, Clone)]
+pub struct XxHash64 {
+    seed: u64,
+    lanes: [u64; 4],
+    /* Bytes not yet forming a full stripe */
+    buffer: [u8; STRIPE_LEN],
+    buffered: usize,
+    total_len: u64,
+}
+
+fn round(acc: u64, lane: u64) -> u64 {
+    acc.wrapping_add(lane.wrapping_mul(PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME64_1)
+}
+
+fn merge_round(acc: u64, lane: u64) -> u64 {
+    (acc ^ round(0, lane))
+        .wrapping_mul(PRIME64_1)
+        .wrapping_add(PRIME64_4)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+impl XxHash64 {
+    /// A context with seed 0.
+    pub fn new() -> Self {
+        Self::with_seed(0)
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        XxHash64 {
+            seed,
+            lanes: [
+                seed.wrapping_add(PRIME64_1).wrapping_add(PRIME64_2),
+                seed.wrapping_add(PRIME64_2),
+                seed,
+                seed.wrapping_sub(PRIME64_1),
+            ],
+            buffer: [0; STRIPE_LEN],
+            buffered: 0,
+            total_len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+
+        if self.buffered > 0 {
+            let take = data.len().min(STRIPE_LEN - self.buffered);
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+            if self.buffered < STRIPE_LEN {
+                return;
+            }
+            let stripe = self.buffer;
+            self.consume(&stripe);
+            self.buffered = 0;
+        }
+
+        let mut stripes = data.chunks_exact(STRIPE_LEN);
+        for stripe in &mut stripes {
+            self.consume(stripe);
+        }
+        let rest = stripes.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    fn consume(&mut self, stripe: &[u8]) {
+        for (lane, word) in self.lanes.iter_mut().zip(stripe.chunks_exact(8)) {
+            *lane = round(*lane, read_u64(word));
+        }
+    }
+
+    pub fn finalize(self) -> u64 {
+        let mut acc = if self.total_len >= STRIPE_LEN as u64 {
+            let [v1, v2, v3, v4] = self.lanes;
+            let mut acc = v1
+                .rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18));
+            for lane in self.lanes {
+                acc = merge_round(acc, lane);
+            }
+            acc
+        } else {
+            self.seed.wrapping_add(PRIME64_5)
+        };
+        acc = acc.wrapping_add(self.total_len);
+
+        let mut rest = &self.buffer[..self.buffered];
+        while rest.len() >= 8 {
+            acc = (acc ^ round(0, read_u64(rest)))
+                .rotate_left(27)
+                .wrapping_mul(PRIME64_1)
+                .wrapping_add(PRIME64_4);
+            rest = &rest[8..];
+        }
+        if rest.len() >= 4 {
+            let word = u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64;
+            acc = (acc ^ word.wrapping_mul(PRIME64_1))
+                .rotate_left(23)
+                .wrapping_mul(PRIME64_2)
+                .wrapping_add(PRIME64_3);
+            rest = &rest[4..];
+        }
+        for &b in rest {
+            acc = (acc ^ (b as u64).wrapping_mul(PRIME64_5))
+                .rotate_left(11)
+                .wrapping_mul(PRIME64_1);
+        }
+
+        /* Avalanche */
+        acc ^= acc >> 33;
+        acc = acc.wrapping_mul(PRIME64_2);
+        acc ^= acc >> 29;
+        acc = acc.wrapping_mul(PRIME64_3);
+        acc ^ (acc >> 32)
+    }
+}
+
+impl Default for XxHash64 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for Crc32 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for XxHash64 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // The CRC-32 "check" value from the Rocksoft catalogue (CRC-32/ISO-HDLC)
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414F_A339
+        );
+
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"");
+        crc.update(b"56789");
+        assert_eq!(crc.finalize(), 0xCBF4_3926);
+    }
+
+    // Published values from the xxHash reference implementation
+    #[test]
+    fn xxhash64_vectors() {
+        assert_eq!(xxhash64(b"", 0), 0xEF46_DB37_51D8_E999);
+        assert_eq!(xxhash64(b"a", 0), 0xD24E_C4F1_A98C_6E5B);
+        assert_eq!(xxhash64(b"abc", 0), 0x44BC_2CF5_AD77_0999);
+        assert_eq!(
+            xxhash64(b"Nobody inspects the spammish repetition", 0),
+            0xFBCE_A83C_8A37_8BF1
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_adapter() {
+        let mut crc = Crc32::default();
+        io::copy(&mut &b"123456789"[..], &mut crc).unwrap();
+        assert_eq!(crc.finalize(), 0xCBF4_3926);
+
+        let mut xx = XxHash64::default();
+        xx.write_all(b"ab").unwrap();
+        xx.write_all(b"c").unwrap();
+        assert_eq!(xx.finalize(), 0x44BC_2CF5_AD77_0999);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        /* Cross-checked against the crc32fast and xxhash-rust crates, with
+         * splits so stripes straddle update() calls */
+        #[test]
+        fn matches_reference_crates(
+            data in prop::collection::vec(any::<u8>(), 0..600),
+            seed in any::<u64>(),
+            splits in prop::collection::vec(any::<prop::sample::Index>(), 0..8),
+        ) {
+            let mut points: Vec<usize> = splits.iter().map(|i| i.index(data.len() + 1)).collect();
+            points.sort_unstable();
+
+            let mut crc = Crc32::new();
+            let mut xx = XxHash64::with_seed(seed);
+            let mut start = 0;
+            for end in points.into_iter().chain([data.len()]) {
+                crc.update(&data[start..end]);
+                xx.update(&data[start..end]);
+                start = end;
+            }
+            prop_assert_eq!(crc.finalize(), crc32fast::hash(&data));
+            prop_assert_eq!(xx.finalize(), xxhash_rust::xxh64::xxh64(&data, seed));
+            prop_assert_eq!(xxhash64(&data, seed), xxhash_rust::xxh64::xxh64(&data, seed));
+        }
+    }
+}
@@ -0,0 +1,192 @@
+/* Key-committing wrapper around ChaCha20-Poly1305.
+ *
+ * Poly1305 is not key-committing: a ciphertext can be crafted that opens
+ * under two different keys. This wrapper uses the "padding fix" (CMT-1)
+ * construction so a blob only ever opens under the key that sealed it.
+ *
+ * Construction (HKDF is RFC 5869 with HMAC-SHA256):
+ *
+ *   prk        = HKDF-Extract(salt = nonce, ikm = key)
+ *   enc_key    = HKDF-Expand(prk, "crypto_tool commit v1 key", 32)
+ *   commitment = HKDF-Expand(prk, "crypto_tool commit v1 cmt", 32)
+ *   sealed     = ChaCha20-Poly1305(enc_key, nonce, aad = commitment || aad, plaintext)
+ *   output     = commitment || sealed
+ */
+use alloc::vec::Vec;
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+use ring::hkdf::{KeyType, Salt, HKDF_SHA256};
+use subtle::ConstantTimeEq;
+
+use crate::ErrorStates;
+
+/// Length of the commitment prepended to every committing ciphertext.
+pub const COMMITMENT_LEN: usize = 32;
+
+const INFO_KEY: &[u8] = b"crypto_tool commit v1 key";
+const INFO_COMMITMENT: &[u8] = b"crypto_tool commit v1 cmt";
+
+/* Output length marker for ring's HKDF expand (also used by other modules) */
+pub(crate) struct Len(pub(crate) usize);
+
+impl KeyType for Len {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+/* Derive (enc_key, commitment) from the caller's key and nonce */
+fn derive(key: &[u8], nonce: &[u8; 12]) -> Result<([u8; 32], [u8; COMMITMENT_LEN]), ErrorStates> {
+    let prk = Salt::new(HKDF_SHA256, nonce).extract(key);
+
+    let mut enc_key = [0u8; 32];
+    let mut commitment = [0u8; COMMITMENT_LEN];
+
+    prk.expand(&[INFO_KEY], Len(enc_key.len()))
+        .and_then(|okm| okm.fill(&mut enc_key))
+        .map_err(|_| ErrorStates::KeyInitializationFailed)?;
+    prk.expand(&[INFO_COMMITMENT], Len(commitment.len()))
+        .and_then(|okm| okm.fill(&mut commitment))
+        .map_err(|_| ErrorStates::KeyInitializationFailed)?;
+
+    Ok((enc_key, commitment))
+}
+
+fn aead_key(enc_key: &[u8; 32]) -> Result<LessSafeKey, ErrorStates> {
+    let unbound_key = UnboundKey::new(&CHACHA20_POLY1305, enc_key)
+        .map_err(|_| ErrorStates::KeyInitializationFailed)?;
+    Ok(LessSafeKey::new(unbound_key))
+}
+
+/* commitment || aad, the AAD actually handed to the AEAD */
+fn bound_aad(commitment: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut bound = Vec::with_capacity(commitment.len() + aad.len());
+    bound.extend_from_slice(commitment);
+    bound.extend_from_slice(aad);
+    bound
+}
+
+/// Seal `plaintext` so the result only opens under `key`.
+/// Output layout: `commitment (32 bytes) || ciphertext || tag`.
+pub fn seal_committing(
+    key: &[u8],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, ErrorStates> {
+    let (enc_key, commitment) = derive(key, nonce)?;
+    let key = aead_key(&enc_key)?;
+
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(*nonce),
+        Aad::from(bound_aad(&commitment, aad)),
+        &mut in_out,
+    )
+    .map_err(|_| ErrorStates::EncryptionFailed)?;
+
+    let mut out = Vec::with_capacity(COMMITMENT_LEN + in_out.len());
+    out.extend_from_slice(&commitment);
+    out.extend_from_slice(&in_out);
+    Ok(out)
+}
+
+/// Open a blob produced by [`seal_committing`]. The commitment is checked
+/// (in constant time) before any decryption is attempted, so a wrong key
+/// fails with `CommitmentMismatch` rather than `DecryptionFailed`.
+pub fn open_committing(
+    key: &[u8],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>, ErrorStates> {
+    if data.len() < COMMITMENT_LEN {
+        return Err(ErrorStates::DecryptionFailed);
+    }
+    let (stored, sealed) = data.split_at(COMMITMENT_LEN);
+
+    let (enc_key, commitment) = derive(key, nonce)?;
+    if !bool::from(commitment.ct_eq(stored)) {
+        return Err(ErrorStates::CommitmentMismatch);
+    }
+
+    let key = aead_key(&enc_key)?;
+    let mut in_out = sealed.to_vec();
+    let len = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(*nonce),
+            Aad::from(bound_aad(&commitment, aad)),
+            &mut in_out,
+        )
+        .map_err(|_| ErrorStates::DecryptionFailed)?
+        .len();
+    in_out.truncate(len);
+    Ok(in_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [0x42; 32];
+    const NONCE: [u8; 12] = [0x24; 12];
+
+    #[test]
+    fn round_trip() {
+        let sealed = seal_committing(&KEY, &NONCE, b"header", b"Hello World!").unwrap();
+        assert_eq!(sealed.len(), COMMITMENT_LEN + 12 + 16);
+
+        let opened = open_committing(&KEY, &NONCE, b"header", &sealed).unwrap();
+        assert_eq!(opened, b"Hello World!");
+    }
+
+    #[test]
+    fn wrong_key_fails_commitment() {
+        let sealed = seal_committing(&KEY, &NONCE, b"", b"Hello World!").unwrap();
+
+        let mut other = KEY;
+        other[0] ^= 1;
+        assert_eq!(
+            open_committing(&other, &NONCE, b"", &sealed),
+            Err(ErrorStates::CommitmentMismatch)
+        );
+    }
+
+    #[test]
+    fn wrong_aad_fails_decryption() {
+        let sealed = seal_committing(&KEY, &NONCE, b"a", b"Hello World!").unwrap();
+        assert_eq!(
+            open_committing(&KEY, &NONCE, b"b", &sealed),
+            Err(ErrorStates::DecryptionFailed)
+        );
+    }
+
+    // Pins the construction documented above; cross-checked against an
+    // independent HKDF + ChaCha20-Poly1305 implementation.
+    #[test]
+    fn known_answer() {
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let nonce: [u8; 12] = core::array::from_fn(|i| 0xa0 + i as u8);
+
+        #[rustfmt::skip]
+        let expected: [u8; 60] = [
+            /* commitment */
+            0x17, 0x42, 0x95, 0xfb, 0x16, 0x31, 0x95, 0xc1,
+            0x6d, 0x58, 0xb3, 0xfe, 0xe5, 0xfd, 0xb9, 0xde,
+            0x0e, 0xf9, 0x76, 0x4d, 0xcc, 0xd7, 0x5c, 0x05,
+            0x78, 0xd6, 0x53, 0xd5, 0xa3, 0x14, 0xb9, 0x7d,
+            /* ciphertext + tag */
+            0x64, 0xed, 0x05, 0x8b, 0xa2, 0x09, 0x97, 0x38,
+            0x53, 0xf1, 0x26, 0xe0, 0xb3, 0x9e, 0x1f, 0x51,
+            0x04, 0x33, 0xa9, 0xd1, 0x8a, 0x06, 0x54, 0x9a,
+            0xeb, 0xc9, 0x76, 0xcc,
+        ];
+
+        let sealed = seal_committing(&key, &nonce, b"aad", b"Hello World!").unwrap();
+        assert_eq!(sealed, expected);
+        assert_eq!(
+            open_committing(&key, &nonce, b"aad", &expected).unwrap(),
+            b"Hello World!"
+        );
+    }
+}
@@ -0,0 +1,877 @@
+/* Compress-then-encrypt. Requires the `compress` feature (which implies
+ * `std`).
+ *
+ * Ciphertext does not compress, so compression has to happen before
+ * sealing. Beware that compressing secrets together with attacker-chosen
+ * data leaks information through the ciphertext length (CRIME/BREACH);
+ * this is meant for archives and logs, not for mixing secrets with input
+ * from untrusted parties.
+ *
+ * One-shot blob layout:
+ *
+ *   0       codec id (1 = zstd, 2 = deflate)
+ *   1..9    original length (u64, big-endian)
+ *   9..21   nonce
+ *   21..    ciphertext || tag
+ *
+ * Bytes 0..9 are authenticated by prepending them to the caller's AAD.
+ *
+ * Streams are ordinary rcli files whose plaintext is the compressed data,
+ * with the codec id in the header's EXT_COMPRESSION extension and, when
+ * it was known up front, the original length in EXT_ORIGINAL_LEN (and so
+ * covered by every chunk's tag). A stream whose first
+ * COMPRESSION_SAMPLE_LEN bytes barely compress is stored as it is,
+ * without either extension. Decompression always runs against a ceiling
+ * on output size, so a small input cannot expand without bound. */
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::vec::Vec;
+
+use flate2::write::{DeflateDecoder, DeflateEncoder};
+use ring::rand::SystemRandom;
+
+use crate::fs::{
+    decrypt_after_header, encrypt_stream_with_rng, encrypt_with_header, read_header,
+    EncryptOptions, FsCryptoError, Summary,
+};
+use crate::header::{FileHeader, HeaderError};
+use crate::stream::TAG_LEN;
+use crate::{chacha20_poly1305_cipher_with_aad, EntropySource, ErrorStates};
+
+/// Default ceiling on decompressed output (1 GiB).
+pub const DEFAULT_DECOMPRESS_LIMIT: u64 = 1 << 30;
+
+/// Codec id and original length that precede the nonce in a blob.
+pub const COMPRESSED_PREFIX_LEN: usize = 9;
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Zstandard at the given level (1..=22; 3 is zstd's default).
+    Zstd(i32),
+    /// Raw DEFLATE (RFC 1951) at the default level.
+    Deflate,
+}
+
+impl Codec {
+    pub fn id(self) -> u8 {
+        match self {
+            Codec::Zstd(_) => 1,
+            Codec::Deflate => 2,
+        }
+    }
+}
+
+/* Decoding only needs the codec family, not the level */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CodecId {
+    Zstd,
+    Deflate,
+}
+
+impl CodecId {
+    fn from_id(id: u8) -> Result<Self, CompressError> {
+        match id {
+            1 => Ok(CodecId::Zstd),
+            2 => Ok(CodecId::Deflate),
+            _ => Err(CompressError::UnknownCodec(id)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CompressError {
+    Crypto(ErrorStates),
+    Header(HeaderError),
+    Io(io::Error),
+    /* Blob or header names a codec this build does not know */
+    UnknownCodec(u8),
+    /* Decompressed output would exceed the caller's limit */
+    TooLarge { limit: u64 },
+    /* Authenticated, but does not decompress to the recorded length */
+    Corrupt,
+}
+
+impl fmt::Display for CompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressError::Crypto(e) => write!(f, "{}", FsCryptoError::Crypto(*e)),
+            CompressError::Header(e) => write!(f, "{}", FsCryptoError::Header(*e)),
+            CompressError::Io(e) => write!(f, "I/O error: {}", e),
+            CompressError::UnknownCodec(id) => write!(f, "unknown compression codec id {}", id),
+            CompressError::TooLarge { limit } => {
+                write!(f, "decompressed data exceeds the {} byte limit", limit)
+            }
+            CompressError::Corrupt => write!(f, "compressed data is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for CompressError {}
+
+impl From<ErrorStates> for CompressError {
+    fn from(e: ErrorStates) -> Self {
+        CompressError::Crypto(e)
+    }
+}
+
+impl From<io::Error> for CompressError {
+    fn from(e: io::Error) -> Self {
+        CompressError::Io(e)
+    }
+}
+
+/* UnknownCodec, TooLarge and Corrupt travel as I/O errors wrapping the
+ * CompressError, as `DecryptReader` carries its own */
+impl From<CompressError> for FsCryptoError {
+    fn from(e: CompressError) -> Self {
+        match e {
+            CompressError::Crypto(e) => FsCryptoError::Crypto(e),
+            CompressError::Header(e) => FsCryptoError::Header(e),
+            CompressError::Io(e) => FsCryptoError::Io(e),
+            e => FsCryptoError::Io(io::Error::other(e)),
+        }
+    }
+}
+
+impl From<FsCryptoError> for CompressError {
+    fn from(e: FsCryptoError) -> Self {
+        match e {
+            FsCryptoError::Crypto(e) => CompressError::Crypto(e),
+            FsCryptoError::Header(e) => CompressError::Header(e),
+            FsCryptoError::Io(e) => CompressError::Io(e),
+        }
+    }
+}
+
+/// Compress `plaintext` with `codec`, then seal it under `key`.
+pub fn seal_compressed(
+    key: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+    codec: Codec,
+) -> Result<Vec<u8>, CompressError> {
+    seal_compressed_with_rng(key, aad, plaintext, codec, &SystemRandom::new())
+}
+
+/// As `seal_compressed`, drawing the nonce from `rng`.
+pub fn seal_compressed_with_rng(
+    key: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+    codec: Codec,
+    rng: &dyn EntropySource,
+) -> Result<Vec<u8>, CompressError> {
+    let compressed = compress_all(plaintext, codec)?;
+
+    let mut blob =
+        Vec::with_capacity(COMPRESSED_PREFIX_LEN + NONCE_LEN + compressed.len() + TAG_LEN);
+    blob.push(codec.id());
+    blob.extend_from_slice(&(plaintext.len() as u64).to_be_bytes());
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce);
+    blob.extend_from_slice(&nonce);
+
+    let full_aad = bound_aad(&blob[..COMPRESSED_PREFIX_LEN], aad);
+    let sealed = chacha20_poly1305_cipher_with_aad(key, &nonce, &full_aad, compressed, true)?;
+    blob.extend_from_slice(&sealed);
+    Ok(blob)
+}
+
+/// Open a blob from `seal_compressed`, refusing to decompress more than
+/// `DEFAULT_DECOMPRESS_LIMIT` bytes.
+pub fn open_compressed(key: &[u8], aad: &[u8], blob: &[u8]) -> Result<Vec<u8>, CompressError> {
+    open_compressed_with_limit(key, aad, blob, DEFAULT_DECOMPRESS_LIMIT)
+}
+
+/// As `open_compressed`, with a caller-chosen output ceiling.
+pub fn open_compressed_with_limit(
+    key: &[u8],
+    aad: &[u8],
+    blob: &[u8],
+    limit: u64,
+) -> Result<Vec<u8>, CompressError> {
+    if blob.len() < COMPRESSED_PREFIX_LEN + NONCE_LEN + TAG_LEN {
+        return Err(ErrorStates::DecryptionFailed.into());
+    }
+    let codec = CodecId::from_id(blob[0])?;
+    let (prefix, rest) = blob.split_at(COMPRESSED_PREFIX_LEN);
+    let (nonce, sealed) = rest.split_at(NONCE_LEN);
+    let nonce: &[u8; NONCE_LEN] = nonce.try_into().unwrap();
+
+    let full_aad = bound_aad(prefix, aad);
+    let compressed =
+        chacha20_poly1305_cipher_with_aad(key, nonce, &full_aad, sealed.to_vec(), false)?;
+
+    let original_len = u64::from_be_bytes(prefix[1..].try_into().unwrap());
+    if original_len > limit {
+        return Err(CompressError::TooLarge { limit });
+    }
+
+    let out = Vec::with_capacity(original_len as usize);
+    let mut decoder = Decoder::new(codec, LimitedWriter::new(out, original_len));
+    /* Authenticated data that overshoots its recorded length is corrupt */
+    let out = decoder
+        .write_all(&compressed)
+        .map_err(CompressError::from)
+        .and_then(|()| decoder.finish())
+        .map_err(|_| CompressError::Corrupt)?
+        .inner;
+    if out.len() as u64 != original_len {
+        return Err(CompressError::Corrupt);
+    }
+    Ok(out)
+}
+
+/// Bytes at the start of a stream compressed to judge whether the rest is
+/// worth compressing.
+pub const COMPRESSION_SAMPLE_LEN: usize = 1 << 20;
+
+/// Whether compressing `sample` with `codec` saves at least 1/32 of it;
+/// below that, compression costs more time than it saves space.
+pub fn compression_pays(sample: &[u8], codec: Codec) -> io::Result<bool> {
+    let compressed = compress_all(sample, codec)?;
+    Ok(!sample.is_empty() && compressed.len() as u64 * 32 <= sample.len() as u64 * 31)
+}
+
+fn compress_all(data: &[u8], codec: Codec) -> io::Result<Vec<u8>> {
+    match codec {
+        Codec::Zstd(level) => zstd::bulk::compress(data, level),
+        Codec::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+    }
+}
+
+/// Compress everything from `reader` with `codec` and encrypt it into
+/// `writer` as an rcli stream, as `fs::encrypt_stream` does with
+/// `EncryptOptions::compression` set. `Summary::bytes_in` counts
+/// uncompressed bytes read.
+pub fn encrypt_stream_compressed<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    opts: &EncryptOptions,
+    codec: Codec,
+) -> Result<Summary, CompressError> {
+    encrypt_stream_compressed_with_rng(reader, writer, key, opts, codec, &SystemRandom::new())
+}
+
+/// As `encrypt_stream_compressed`, drawing the nonce prefix from `rng`.
+pub fn encrypt_stream_compressed_with_rng<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    opts: &EncryptOptions,
+    codec: Codec,
+    rng: &dyn EntropySource,
+) -> Result<Summary, CompressError> {
+    let opts = EncryptOptions {
+        compression: Some(codec),
+        ..opts.clone()
+    };
+    Ok(encrypt_stream_with_rng(reader, writer, key, &opts, rng)?)
+}
+
+/* Seal `reader` compressed with `codec` under `header`, which names it.
+ * Input whose first COMPRESSION_SAMPLE_LEN bytes hardly compress is
+ * sealed as it is, under `header` without the compression extensions, so
+ * incompressible data costs nothing extra */
+pub(crate) fn encrypt_with_codec<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    header: &FileHeader,
+    aad: &[u8],
+    codec: Codec,
+    jobs: usize,
+) -> Result<Summary, FsCryptoError> {
+    let mut sample = Vec::with_capacity(COMPRESSION_SAMPLE_LEN);
+    reader
+        .by_ref()
+        .take(COMPRESSION_SAMPLE_LEN as u64)
+        .read_to_end(&mut sample)?;
+    let pays = compression_pays(&sample, codec)?;
+    let mut counted = CountingReader {
+        inner: io::Cursor::new(sample).chain(reader),
+        count: 0,
+    };
+    if !pays {
+        let header = FileHeader {
+            compression: None,
+            original_len: None,
+            ..header.clone()
+        };
+        return encrypt_with_header(&mut counted, writer, key, &header, aad, jobs);
+    }
+
+    let mut summary = match codec {
+        Codec::Zstd(level) => {
+            let mut encoder = zstd::stream::read::Encoder::new(&mut counted, level)?;
+            encrypt_with_header(&mut encoder, writer, key, header, aad, jobs)?
+        }
+        Codec::Deflate => {
+            let mut encoder =
+                flate2::read::DeflateEncoder::new(&mut counted, flate2::Compression::default());
+            encrypt_with_header(&mut encoder, writer, key, header, aad, jobs)?
+        }
+    };
+    if header.original_len.is_some_and(|len| len != counted.count) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "input is not the length given for it; it may have changed while it was read",
+        )
+        .into());
+    }
+    summary.bytes_in = counted.count;
+    Ok(summary)
+}
+
+/// Decrypt and decompress an rcli stream, failing with `TooLarge` once the
+/// output would exceed `limit` bytes. Streams without compression are
+/// decrypted as-is, under the same limit. As with `fs::decrypt_stream`,
+/// `writer` may hold partial output on error. `Summary::bytes_out` counts
+/// decompressed bytes.
+pub fn decrypt_stream_compressed<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    limit: u64,
+) -> Result<Summary, CompressError> {
+    decrypt_stream_compressed_with_aad(reader, writer, key, &[], limit)
+}
+
+/// As `decrypt_stream_compressed`, for a stream bound to `aad` (see
+/// `fs::decrypt_stream_with_aad`). A stream whose header records an
+/// original length over `limit` fails with `TooLarge` before any chunk is
+/// read, and one that decompresses to another length with `Corrupt`.
+pub fn decrypt_stream_compressed_with_aad<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    aad: &[u8],
+    limit: u64,
+) -> Result<Summary, CompressError> {
+    decrypt_stream_compressed_with_jobs(reader, writer, key, aad, limit, 1)
+}
+
+/// As `decrypt_stream_compressed_with_aad`, opening chunks on up to
+/// `jobs` threads (see `fs::decrypt_stream_with_jobs`). Decompression
+/// itself stays on the calling thread.
+pub fn decrypt_stream_compressed_with_jobs<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    aad: &[u8],
+    limit: u64,
+    jobs: usize,
+) -> Result<Summary, CompressError> {
+    let (header, header_bytes) = read_header(reader)?;
+    let mut limited = LimitedWriter::new(writer, limit);
+
+    let Some(id) = header.compression else {
+        let result =
+            decrypt_after_header(reader, &mut limited, key, &header, &header_bytes, aad, jobs);
+        let mut summary = result.map_err(|e| classify(e.into(), &limited, false))?;
+        summary.bytes_out = limited.written;
+        return Ok(summary);
+    };
+    if header.original_len.is_some_and(|len| len > limit) {
+        return Err(CompressError::TooLarge { limit });
+    }
+
+    let mut decoder = Decoder::new(CodecId::from_id(id)?, limited);
+    let result = decrypt_after_header(reader, &mut decoder, key, &header, &header_bytes, aad, jobs);
+    let mut summary = result.map_err(|e| classify(e.into(), decoder.get_ref(), decoder.failed))?;
+    let limited = decoder.finish()?;
+    if header
+        .original_len
+        .is_some_and(|len| len != limited.written)
+    {
+        return Err(CompressError::Corrupt);
+    }
+    summary.bytes_out = limited.written;
+    Ok(summary)
+}
+
+/// Decompresses what `inner` yields with the codec a stream header names,
+/// failing once more than `limit` bytes come out, or at the end if they
+/// are not `expected` (the header's original length). Errors that wrap
+/// an `FsCryptoError`, as a `DecryptReader`'s do, pass through; its own
+/// are `io::Error`s wrapping a `CompressError`.
+pub struct DecompressReader<R: Read> {
+    stream: ReaderStream<R>,
+    produced: u64,
+    limit: u64,
+    expected: Option<u64>,
+}
+
+enum ReaderStream<R: Read> {
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<R>>),
+    Deflate(flate2::read::DeflateDecoder<R>),
+}
+
+impl<R: Read> DecompressReader<R> {
+    /// Fails with `TooLarge` straight away if `expected` is over `limit`.
+    pub fn new(
+        inner: R,
+        codec: u8,
+        limit: u64,
+        expected: Option<u64>,
+    ) -> Result<Self, CompressError> {
+        if expected.is_some_and(|len| len > limit) {
+            return Err(CompressError::TooLarge { limit });
+        }
+        let stream = match CodecId::from_id(codec)? {
+            CodecId::Zstd => ReaderStream::Zstd(zstd::stream::read::Decoder::new(inner)?),
+            CodecId::Deflate => ReaderStream::Deflate(flate2::read::DeflateDecoder::new(inner)),
+        };
+        Ok(DecompressReader {
+            stream,
+            produced: 0,
+            limit,
+            expected,
+        })
+    }
+}
+
+impl<R: Read> Read for DecompressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = match &mut self.stream {
+            ReaderStream::Zstd(d) => d.read(buf),
+            ReaderStream::Deflate(d) => d.read(buf),
+        };
+        let n = result.map_err(|e| match e.get_ref() {
+            Some(inner) if inner.is::<FsCryptoError>() => e,
+            _ => io::Error::other(CompressError::Corrupt),
+        })?;
+        self.produced += n as u64;
+        if self.produced > self.limit {
+            return Err(io::Error::other(CompressError::TooLarge {
+                limit: self.limit,
+            }));
+        }
+        let ended = n == 0 && !buf.is_empty();
+        if ended && self.expected.is_some_and(|len| len != self.produced) {
+            return Err(io::Error::other(CompressError::Corrupt));
+        }
+        Ok(n)
+    }
+}
+
+fn bound_aad(prefix: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut full = Vec::with_capacity(prefix.len() + aad.len());
+    full.extend_from_slice(prefix);
+    full.extend_from_slice(aad);
+    full
+}
+
+/* Work out which layer an I/O failure came from: the output limit, the
+ * decompressor, or the caller's reader/writer */
+fn classify<W>(e: CompressError, limited: &LimitedWriter<W>, decode_failed: bool) -> CompressError {
+    if limited.exceeded {
+        CompressError::TooLarge {
+            limit: limited.limit,
+        }
+    } else if decode_failed {
+        CompressError::Corrupt
+    } else {
+        e
+    }
+}
+
+/* Passes writes through until `limit` bytes, then refuses */
+struct LimitedWriter<W> {
+    inner: W,
+    written: u64,
+    limit: u64,
+    exceeded: bool,
+    inner_failed: bool,
+}
+
+impl<W> LimitedWriter<W> {
+    fn new(inner: W, limit: u64) -> Self {
+        LimitedWriter {
+            inner,
+            written: 0,
+            limit,
+            exceeded: false,
+            inner_failed: false,
+        }
+    }
+}
+
+impl<W: Write> Write for LimitedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written + buf.len() as u64 > self.limit {
+            self.exceeded = true;
+            return Err(io::Error::other("decompression limit exceeded"));
+        }
+        let n = self
+            .inner
+            .write(buf)
+            .inspect_err(|_| self.inner_failed = true)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush().inspect_err(|_| self.inner_failed = true)
+    }
+}
+
+enum DecoderStream<W: Write> {
+    Zstd(zstd::stream::write::Decoder<'static, LimitedWriter<W>>),
+    Deflate(DeflateDecoder<LimitedWriter<W>>),
+}
+
+/* Decompressing writer over a LimitedWriter. `failed` is set when a write
+ * fails for a reason other than the limit or the underlying writer, i.e.
+ * the compressed data itself is bad. */
+struct Decoder<W: Write> {
+    stream: DecoderStream<W>,
+    failed: bool,
+}
+
+impl<W: Write> Decoder<W> {
+    fn new(codec: CodecId, writer: LimitedWriter<W>) -> Self {
+        let stream = match codec {
+            /* Only fails if zstd cannot allocate its context */
+            CodecId::Zstd => DecoderStream::Zstd(
+                zstd::stream::write::Decoder::new(writer).expect("zstd decompression context"),
+            ),
+            CodecId::Deflate => DecoderStream::Deflate(DeflateDecoder::new(writer)),
+        };
+        Decoder {
+            stream,
+            failed: false,
+        }
+    }
+
+    fn get_ref(&self) -> &LimitedWriter<W> {
+        match &self.stream {
+            DecoderStream::Zstd(d) => d.get_ref(),
+            DecoderStream::Deflate(d) => d.get_ref(),
+        }
+    }
+
+    fn note(&mut self, result: io::Result<usize>) -> io::Result<usize> {
+        if result.is_err() && !self.get_ref().exceeded && !self.get_ref().inner_failed {
+            self.failed = true;
+        }
+        result
+    }
+
+    /* Drain buffered output and return the writer, classifying any error */
+    fn finish(mut self) -> Result<LimitedWriter<W>, CompressError> {
+        let flushed = self.flush();
+        let flushed = self.note(flushed.map(|()| 0));
+        if let Err(e) = flushed {
+            return Err(classify(e.into(), self.get_ref(), self.failed));
+        }
+        match self.stream {
+            DecoderStream::Zstd(d) => Ok(d.into_inner()),
+            DecoderStream::Deflate(d) => d.finish().map_err(|_| CompressError::Corrupt),
+        }
+    }
+}
+
+impl<W: Write> Write for Decoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = match &mut self.stream {
+            DecoderStream::Zstd(d) => d.write(buf),
+            DecoderStream::Deflate(d) => d.write(buf),
+        };
+        self.note(result)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stream {
+            DecoderStream::Zstd(d) => d.flush(),
+            DecoderStream::Deflate(d) => d.flush(),
+        }
+    }
+}
+
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{decrypt_stream, DecryptReader};
+    use crate::header::EXT_COMPRESSION;
+    use crate::testing::TestRng;
+
+    const KEY: [u8; 32] = [0x42; 32];
+    const CODECS: [Codec; 2] = [Codec::Zstd(3), Codec::Deflate];
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn opts(compression: Option<Codec>) -> EncryptOptions {
+        EncryptOptions {
+            chunk_size: 1024,
+            compression,
+            ..EncryptOptions::default()
+        }
+    }
+
+    fn encrypt(plaintext: &[u8], codec: Codec) -> Vec<u8> {
+        let mut out = Vec::new();
+        encrypt_stream_compressed(&mut &plaintext[..], &mut out, &KEY, &opts(None), codec).unwrap();
+        out
+    }
+
+    #[test]
+    fn blob_round_trip() {
+        for codec in CODECS {
+            for pt in [Vec::new(), b"Hello World!".to_vec(), sample(100_000)] {
+                let blob = seal_compressed(&KEY, b"aad", &pt, codec).unwrap();
+                assert_eq!(blob[0], codec.id());
+                assert_eq!(open_compressed(&KEY, b"aad", &blob).unwrap(), pt);
+                assert!(matches!(
+                    open_compressed(&KEY, b"other", &blob),
+                    Err(CompressError::Crypto(ErrorStates::DecryptionFailed))
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn prefix_is_authenticated() {
+        let pt = sample(1000);
+        let mut blob =
+            seal_compressed_with_rng(&KEY, b"", &pt, Codec::Deflate, &TestRng::new(0)).unwrap();
+        assert_eq!(blob[1..9], 1000u64.to_be_bytes());
+
+        /* Claiming a different length or the other codec fails to open */
+        blob[8] ^= 1;
+        assert!(matches!(
+            open_compressed(&KEY, b"", &blob),
+            Err(CompressError::Crypto(ErrorStates::DecryptionFailed))
+        ));
+        blob[8] ^= 1;
+        blob[0] = Codec::Zstd(3).id();
+        assert!(matches!(
+            open_compressed(&KEY, b"", &blob),
+            Err(CompressError::Crypto(ErrorStates::DecryptionFailed))
+        ));
+    }
+
+    #[test]
+    fn repetitive_input_shrinks() {
+        let pt = b"the same log line, over and over\n".repeat(10_000);
+        for codec in CODECS {
+            let blob = seal_compressed(&KEY, b"", &pt, codec).unwrap();
+            assert!(blob.len() * 20 < pt.len(), "{:?}: {}", codec, blob.len());
+            assert!(encrypt(&pt, codec).len() * 20 < pt.len());
+        }
+    }
+
+    #[test]
+    fn bomb_hits_limit() {
+        let pt = vec![0u8; 16 << 20];
+        for codec in CODECS {
+            let blob = seal_compressed(&KEY, b"", &pt, codec).unwrap();
+            assert!(blob.len() < 64 * 1024);
+            assert!(matches!(
+                open_compressed_with_limit(&KEY, b"", &blob, 1 << 20),
+                Err(CompressError::TooLarge { limit }) if limit == 1 << 20
+            ));
+
+            let file = encrypt(&pt, codec);
+            let mut out = Vec::new();
+            assert!(matches!(
+                decrypt_stream_compressed(&mut &file[..], &mut out, &KEY, 1 << 20),
+                Err(CompressError::TooLarge { .. })
+            ));
+            assert!(out.len() <= 1 << 20);
+        }
+    }
+
+    #[test]
+    fn unknown_codec_rejected() {
+        let mut blob = seal_compressed(&KEY, b"", b"Hello World!", Codec::Deflate).unwrap();
+        blob[0] = 9;
+        assert!(matches!(
+            open_compressed(&KEY, b"", &blob),
+            Err(CompressError::UnknownCodec(9))
+        ));
+
+        /* Same for a stream header naming an unknown codec */
+        let mut file = encrypt(&b"Hello World!".repeat(100), Codec::Deflate);
+        let ext = crate::header::HEADER_FIXED_LEN;
+        assert_eq!(file[ext..ext + 4], [EXT_COMPRESSION, 0, 1, 2]);
+        file[ext + 3] = 9;
+        assert!(matches!(
+            decrypt_stream_compressed(&mut &file[..], &mut Vec::new(), &KEY, 1 << 20),
+            Err(CompressError::UnknownCodec(9))
+        ));
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        for codec in CODECS {
+            for len in [0, 1, 1024, 100_000] {
+                let pt = sample(len);
+                let file = encrypt(&pt, codec);
+
+                let mut out = Vec::new();
+                let summary = decrypt_stream_compressed(
+                    &mut &file[..],
+                    &mut out,
+                    &KEY,
+                    DEFAULT_DECOMPRESS_LIMIT,
+                )
+                .unwrap();
+                assert_eq!(out, pt);
+                assert_eq!(summary.bytes_out, len as u64);
+
+                /* The plain decryptor refuses rather than emitting
+                 * compressed bytes; 0 and 1 bytes save nothing compressed,
+                 * so they are stored as they are */
+                let plain = decrypt_stream(&mut &file[..], &mut Vec::new(), &KEY);
+                if len > 1 {
+                    assert!(matches!(
+                        plain,
+                        Err(FsCryptoError::Header(HeaderError::Compressed))
+                    ));
+                } else {
+                    assert!(plain.is_ok());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn incompressible_input_is_stored() {
+        let mut pt = vec![0u8; 300_000];
+        TestRng::new(7).fill(&mut pt);
+        for codec in CODECS {
+            let file = encrypt(&pt, codec);
+            let (header, _) = read_header(&mut &file[..]).unwrap();
+            assert_eq!(header.compression, None);
+            assert_eq!(header.original_len, None);
+
+            let mut plain = Vec::new();
+            crate::fs::encrypt_stream(&mut &pt[..], &mut plain, &KEY, &opts(None)).unwrap();
+            assert_eq!(file.len(), plain.len());
+
+            let mut out = Vec::new();
+            decrypt_stream(&mut &file[..], &mut out, &KEY).unwrap();
+            assert_eq!(out, pt);
+        }
+    }
+
+    #[test]
+    fn original_len_is_recorded_and_checked() {
+        let pt = sample(100_000);
+        for codec in CODECS {
+            let mut file = Vec::new();
+            let opts = EncryptOptions {
+                compression: Some(codec),
+                original_len: Some(100_000),
+                ..opts(None)
+            };
+            crate::fs::encrypt_stream(&mut &pt[..], &mut file, &KEY, &opts).unwrap();
+            let (header, _) = read_header(&mut &file[..]).unwrap();
+            assert_eq!(header.original_len, Some(100_000));
+
+            /* Refused before anything is written */
+            let mut out = Vec::new();
+            assert!(matches!(
+                decrypt_stream_compressed(&mut &file[..], &mut out, &KEY, 99_999),
+                Err(CompressError::TooLarge { limit: 99_999 })
+            ));
+            assert!(out.is_empty());
+            decrypt_stream_compressed(&mut &file[..], &mut out, &KEY, 100_000).unwrap();
+            assert_eq!(out, pt);
+
+            /* Input that is not the length given fails */
+            let short = EncryptOptions {
+                original_len: Some(100_001),
+                ..opts
+            };
+            let result = crate::fs::encrypt_stream(&mut &pt[..], &mut Vec::new(), &KEY, &short);
+            assert!(
+                matches!(result, Err(FsCryptoError::Io(e)) if e.kind() == io::ErrorKind::InvalidData)
+            );
+        }
+    }
+
+    #[test]
+    fn decompress_reader_round_trip_and_limit() {
+        let pt = sample(100_000);
+        for codec in CODECS {
+            let mut file = Vec::new();
+            let opts = EncryptOptions {
+                compression: Some(codec),
+                original_len: Some(100_000),
+                ..opts(None)
+            };
+            crate::fs::encrypt_stream(&mut &pt[..], &mut file, &KEY, &opts).unwrap();
+
+            let open = |limit, expected| {
+                let reader = DecryptReader::open(&file[..], &KEY, true).unwrap();
+                DecompressReader::new(reader, codec.id(), limit, expected)
+            };
+            let mut out = Vec::new();
+            open(100_000, Some(100_000))
+                .unwrap()
+                .read_to_end(&mut out)
+                .unwrap();
+            assert_eq!(out, pt);
+
+            assert!(matches!(
+                open(99_999, Some(100_000)),
+                Err(CompressError::TooLarge { limit: 99_999 })
+            ));
+            let failure = |limit, expected| {
+                let e = open(limit, expected)
+                    .unwrap()
+                    .read_to_end(&mut Vec::new())
+                    .unwrap_err();
+                *e.into_inner().unwrap().downcast::<CompressError>().unwrap()
+            };
+            assert!(matches!(
+                failure(99_999, None),
+                CompressError::TooLarge { limit: 99_999 }
+            ));
+            assert!(matches!(
+                failure(200_000, Some(99_000)),
+                CompressError::Corrupt
+            ));
+        }
+    }
+
+    #[test]
+    fn plain_stream_passes_through_under_limit() {
+        let pt = sample(5000);
+        let mut file = Vec::new();
+        crate::fs::encrypt_stream(&mut &pt[..], &mut file, &KEY, &EncryptOptions::default())
+            .unwrap();
+
+        let mut out = Vec::new();
+        decrypt_stream_compressed(&mut &file[..], &mut out, &KEY, 5000).unwrap();
+        assert_eq!(out, pt);
+        assert!(matches!(
+            decrypt_stream_compressed(&mut &file[..], &mut Vec::new(), &KEY, 4999),
+            Err(CompressError::TooLarge { limit: 4999 })
+        ));
+    }
+}
@@ -0,0 +1,152 @@
+/* Diceware passphrases from the EFF large wordlist.
+ * [EFF]: https://www.eff.org/dice
+ *
+ * Each word is drawn uniformly from the 7776 (6^5) words of the list, so
+ * a passphrase of n words carries n * log2(7776), about 12.9 bits per
+ * word. Words come from pairs of random bytes read as a u16: values at or
+ * above the largest multiple of 7776 below 2^16 are rejected, so
+ * `value % 7776` does not favour the first 2^16 % 7776 words. The list
+ * file keeps only the words, without the dice numbers, in the original
+ * order. Four of them (drop-down, felt-tip, t-shirt, yo-yo) contain a
+ * hyphen, so a passphrase joined with "-" cannot always be split back
+ * into words; that costs nothing against an attacker who knows the list. */
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use ring::rand::SystemRandom;
+
+use crate::EntropySource;
+
+const WORDLIST: &str = include_str!("wordlists/eff_large_wordlist.txt");
+
+/// Words in the EFF large wordlist.
+pub const DICEWARE_WORD_COUNT: usize = 7776;
+
+/// Words in a passphrase unless the caller asks otherwise (about 77 bits).
+pub const DEFAULT_DICEWARE_WORDS: usize = 6;
+
+/* log2(7776) = 5 * log2(6); core has no log2 without std */
+const BITS_PER_WORD: f64 = 12.924_812_503_605_78;
+
+/* Random bytes are fetched in batches to avoid a syscall per word */
+const BATCH_LEN: usize = 64;
+
+/// The EFF large wordlist, in its original (sorted) order.
+pub fn diceware_words() -> Vec<&'static str> {
+    let words: Vec<&str> = WORDLIST.lines().collect();
+    debug_assert_eq!(words.len(), DICEWARE_WORD_COUNT);
+    words
+}
+
+/// Entropy in bits of a passphrase of `words` words drawn from the list.
+pub fn diceware_entropy_bits(words: usize) -> f64 {
+    words as f64 * BITS_PER_WORD
+}
+
+/// `words` words drawn uniformly from the EFF large wordlist, joined by
+/// `separator`.
+pub fn diceware_passphrase(words: usize, separator: &str) -> String {
+    diceware_passphrase_with_rng(words, separator, &SystemRandom::new())
+}
+
+/// As `diceware_passphrase`, drawing the words from `rng`.
+pub fn diceware_passphrase_with_rng(
+    words: usize,
+    separator: &str,
+    rng: &dyn EntropySource,
+) -> String {
+    let list = diceware_words();
+    let chosen: Vec<&str> = sample(words, random_u16s(rng)).map(|i| list[i]).collect();
+    chosen.join(separator)
+}
+
+fn random_u16s(rng: &dyn EntropySource) -> impl Iterator<Item = u16> + '_ {
+    core::iter::repeat_with(move || {
+        let mut batch = [0u8; BATCH_LEN];
+        rng.fill(&mut batch);
+        batch
+    })
+    .flat_map(|batch| {
+        let values: Vec<u16> = batch
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        values
+    })
+}
+
+/* Indices into the wordlist, rejecting values that would bias the modulo */
+fn sample(count: usize, values: impl Iterator<Item = u16>) -> impl Iterator<Item = usize> {
+    /* 8 * 7776 = 62208 */
+    let limit = 65536 - 65536 % DICEWARE_WORD_COUNT;
+    values
+        .map(usize::from)
+        .filter(move |&v| v < limit)
+        .take(count)
+        .map(|v| v % DICEWARE_WORD_COUNT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRng;
+
+    #[test]
+    fn wordlist_is_the_eff_large_list() {
+        let words = diceware_words();
+        assert_eq!(words.len(), DICEWARE_WORD_COUNT);
+        assert!(words.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(words[0], "abacus");
+        assert_eq!(words[DICEWARE_WORD_COUNT - 1], "zoom");
+        assert!(words
+            .iter()
+            .all(|w| !w.is_empty() && w.bytes().all(|b| b.is_ascii_lowercase() || b == b'-')));
+    }
+
+    #[test]
+    fn entropy_is_log2_of_the_list_per_word() {
+        let per_word = (DICEWARE_WORD_COUNT as f64).log2();
+        for words in [1, 4, 6, 10, 20] {
+            let expected = words as f64 * per_word;
+            assert!((diceware_entropy_bits(words) - expected).abs() < 1e-9);
+        }
+        assert_eq!(format!("{:.1}", diceware_entropy_bits(6)), "77.5");
+    }
+
+    #[test]
+    fn passphrases_use_list_words_and_separator() {
+        let words = diceware_words();
+        let rng = TestRng::new(1);
+        for count in [1, 6, 12] {
+            let phrase = diceware_passphrase_with_rng(count, " ", &rng);
+            let parts: Vec<&str> = phrase.split(' ').collect();
+            assert_eq!(parts.len(), count);
+            assert!(parts.iter().all(|p| words.binary_search(p).is_ok()));
+        }
+        let phrase = diceware_passphrase_with_rng(3, "::", &rng);
+        assert_eq!(phrase.split("::").count(), 3);
+        assert_eq!(diceware_passphrase_with_rng(0, "-", &rng), "");
+
+        /* Same seed, same words; otherwise they differ */
+        assert_eq!(
+            diceware_passphrase_with_rng(6, " ", &TestRng::new(2)),
+            diceware_passphrase_with_rng(6, " ", &TestRng::new(2))
+        );
+        assert_ne!(diceware_passphrase(6, " "), diceware_passphrase(6, " "));
+    }
+
+    #[test]
+    fn sampling_is_unbiased() {
+        /* Each u16 exactly once: 62208 survive, 8 per word */
+        let mut counts = alloc::vec![0usize; DICEWARE_WORD_COUNT];
+        for i in sample(usize::MAX, 0..=u16::MAX) {
+            counts[i] += 1;
+        }
+        assert!(counts.iter().all(|&c| c == 8));
+
+        /* 62208..=65535 would favour the first 3328 words and are skipped */
+        let values = [62208, 65535, 7777, 62300, 3, 62207];
+        let picked: Vec<usize> = sample(3, values.into_iter()).collect();
+        assert_eq!(picked, [1, 3, 7775]);
+    }
+}
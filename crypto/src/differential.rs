@@ -0,0 +1,143 @@
+/* Differential tests: our ChaCha20-Poly1305 paths against RustCrypto's
+ * chacha20poly1305 crate, to catch misuse of ring (nonce layout, AAD
+ * ordering, tag placement) rather than bugs in ring itself.
+ *
+ * `check_backend` is the reusable harness: any (seal, open) pair with the
+ * ring calling convention (ciphertext || tag) can be run through it. */
+use alloc::vec::Vec;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use proptest::prelude::*;
+
+use crate::header::NONCE_PREFIX_LEN;
+use crate::stream::{StreamDecryptor, StreamEncryptor};
+use crate::{chacha20_poly1305_cipher, chacha20_poly1305_cipher_with_aad, ErrorStates};
+
+const MAX_PLAINTEXT: usize = 64 * 1024;
+
+fn reference_seal(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], msg: &[u8]) -> Vec<u8> {
+    ChaCha20Poly1305::new(&(*key).into())
+        .encrypt(&(*nonce).into(), Payload { msg, aad })
+        .unwrap()
+}
+
+fn reference_open(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], msg: &[u8]) -> Option<Vec<u8>> {
+    ChaCha20Poly1305::new(&(*key).into())
+        .decrypt(&(*nonce).into(), Payload { msg, aad })
+        .ok()
+}
+
+/// Assert that `seal` matches the reference byte-for-byte and that each
+/// side opens the other's output.
+fn check_backend<S, O>(seal: S, open: O, key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], pt: &[u8])
+where
+    S: Fn(&[u8; 32], &[u8; 12], &[u8], &[u8]) -> Vec<u8>,
+    O: Fn(&[u8; 32], &[u8; 12], &[u8], &[u8]) -> Result<Vec<u8>, ErrorStates>,
+{
+    let ours = seal(key, nonce, aad, pt);
+    let theirs = reference_seal(key, nonce, aad, pt);
+    assert_eq!(ours, theirs);
+
+    assert_eq!(open(key, nonce, aad, &theirs).as_deref(), Ok(pt));
+    assert_eq!(reference_open(key, nonce, aad, &ours).as_deref(), Some(pt));
+
+    let mut tampered = ours;
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(open(key, nonce, aad, &tampered).is_err());
+}
+
+fn one_shot_seal(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], pt: &[u8]) -> Vec<u8> {
+    chacha20_poly1305_cipher_with_aad(key, nonce, aad, pt.to_vec(), true).unwrap()
+}
+
+fn one_shot_open(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    ct: &[u8],
+) -> Result<Vec<u8>, ErrorStates> {
+    chacha20_poly1305_cipher_with_aad(key, nonce, aad, ct.to_vec(), false)
+}
+
+/* A one-chunk stream uses nonce = prefix || 0u32 || 0x01 */
+fn stream_nonce(prefix: &[u8; NONCE_PREFIX_LEN]) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[11] = 1;
+    nonce
+}
+
+fn stream_seal(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], pt: &[u8]) -> Vec<u8> {
+    let prefix: [u8; NONCE_PREFIX_LEN] = nonce[..NONCE_PREFIX_LEN].try_into().unwrap();
+    assert_eq!(*nonce, stream_nonce(&prefix));
+    let mut chunk = pt.to_vec();
+    StreamEncryptor::new(key, &prefix, aad)
+        .unwrap()
+        .seal_last(&mut chunk)
+        .unwrap();
+    chunk
+}
+
+fn stream_open(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    ct: &[u8],
+) -> Result<Vec<u8>, ErrorStates> {
+    let prefix: [u8; NONCE_PREFIX_LEN] = nonce[..NONCE_PREFIX_LEN].try_into().unwrap();
+    let mut chunk = ct.to_vec();
+    StreamDecryptor::new(key, &prefix, aad)?.open_last(&mut chunk)?;
+    Ok(chunk)
+}
+
+fn plaintext() -> impl Strategy<Value = Vec<u8>> {
+    /* Bias towards short inputs while still reaching 64 KiB */
+    prop_oneof![
+        prop::collection::vec(any::<u8>(), 0..=256),
+        prop::collection::vec(any::<u8>(), 0..=MAX_PLAINTEXT),
+    ]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn one_shot_matches_reference(
+        key in any::<[u8; 32]>(),
+        nonce in any::<[u8; 12]>(),
+        aad in prop::collection::vec(any::<u8>(), 0..=512),
+        pt in plaintext(),
+    ) {
+        check_backend(one_shot_seal, one_shot_open, &key, &nonce, &aad, &pt);
+    }
+
+    #[test]
+    fn stream_chunk_matches_reference(
+        key in any::<[u8; 32]>(),
+        prefix in any::<[u8; NONCE_PREFIX_LEN]>(),
+        aad in prop::collection::vec(any::<u8>(), 0..=512),
+        pt in plaintext(),
+    ) {
+        check_backend(stream_seal, stream_open, &key, &stream_nonce(&prefix), &aad, &pt);
+    }
+}
+
+#[test]
+fn empty_plaintext_and_aad() {
+    let key = [0x42; 32];
+    let nonce = [0x24; 12];
+    check_backend(one_shot_seal, one_shot_open, &key, &nonce, &[], &[]);
+    check_backend(one_shot_seal, one_shot_open, &key, &nonce, b"header", &[]);
+
+    /* The AAD-less entry point is the empty-AAD case */
+    assert_eq!(
+        chacha20_poly1305_cipher(&key, &nonce, b"Hello World!".to_vec(), true),
+        Ok(reference_seal(&key, &nonce, &[], b"Hello World!"))
+    );
+
+    let nonce = stream_nonce(&[0x24; NONCE_PREFIX_LEN]);
+    check_backend(stream_seal, stream_open, &key, &nonce, &[], &[]);
+    check_backend(stream_seal, stream_open, &key, &nonce, b"header", &[]);
+    check_backend(stream_seal, stream_open, &key, &nonce, &[], b"Hello World!");
+}
@@ -0,0 +1,568 @@
+/* SHA-2 digests and HMAC, one-shot or incremental.
+ *
+ * The incremental contexts wrap ring's `digest::Context` and
+ * `hmac::Context`. With the `std` feature they also implement
+ * `io::Write`, so a file can be hashed with `io::copy` (or `hash_reader`)
+ * without buffering it.
+ *
+ * `DigestAlg` and `Digester` also cover the non-cryptographic checksums
+ * in `checksum`, for callers (such as `manifest`) that let the user trade
+ * tamper resistance for speed. Checksum values come out big-endian.
+ * With the `blake3` feature they cover BLAKE3 (32-byte output) too, and
+ * `Blake3Keyed` offers BLAKE3's keyed mode as a MAC beside HMAC. */
+use ring::{digest, hmac};
+
+use crate::checksum::{Crc32, XxHash64};
+use crate::ErrorStates;
+
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+/// Chunk size used by `hash_reader`.
+#[cfg(feature = "std")]
+pub const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Digest (and HMAC tag) length in bytes.
+    pub fn output_len(self) -> usize {
+        self.digest_algorithm().output_len()
+    }
+
+    fn digest_algorithm(self) -> &'static digest::Algorithm {
+        match self {
+            HashAlgorithm::Sha256 => &digest::SHA256,
+            HashAlgorithm::Sha384 => &digest::SHA384,
+            HashAlgorithm::Sha512 => &digest::SHA512,
+        }
+    }
+
+    fn hmac_algorithm(self) -> hmac::Algorithm {
+        match self {
+            HashAlgorithm::Sha256 => hmac::HMAC_SHA256,
+            HashAlgorithm::Sha384 => hmac::HMAC_SHA384,
+            HashAlgorithm::Sha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+/// Any of the digests or checksums, for code that lets the caller choose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlg {
+    Crc32,
+    XxHash64,
+    Sha256,
+    Sha384,
+    Sha512,
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+impl DigestAlg {
+    /// Output length in bytes.
+    pub fn output_len(self) -> usize {
+        match self {
+            DigestAlg::Crc32 => 4,
+            DigestAlg::XxHash64 => 8,
+            DigestAlg::Sha256 => HashAlgorithm::Sha256.output_len(),
+            DigestAlg::Sha384 => HashAlgorithm::Sha384.output_len(),
+            DigestAlg::Sha512 => HashAlgorithm::Sha512.output_len(),
+            #[cfg(feature = "blake3")]
+            DigestAlg::Blake3 => blake3::OUT_LEN,
+        }
+    }
+
+    /// The cryptographic hash behind this algorithm, if it has one.
+    pub fn hash_algorithm(self) -> Option<HashAlgorithm> {
+        match self {
+            DigestAlg::Crc32 | DigestAlg::XxHash64 => None,
+            DigestAlg::Sha256 => Some(HashAlgorithm::Sha256),
+            DigestAlg::Sha384 => Some(HashAlgorithm::Sha384),
+            DigestAlg::Sha512 => Some(HashAlgorithm::Sha512),
+            #[cfg(feature = "blake3")]
+            DigestAlg::Blake3 => None,
+        }
+    }
+}
+
+impl From<HashAlgorithm> for DigestAlg {
+    fn from(alg: HashAlgorithm) -> Self {
+        match alg {
+            HashAlgorithm::Sha256 => DigestAlg::Sha256,
+            HashAlgorithm::Sha384 => DigestAlg::Sha384,
+            HashAlgorithm::Sha512 => DigestAlg::Sha512,
+        }
+    }
+}
+
+/// A digest or HMAC tag.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Output {
+    bytes: [u8; digest::MAX_OUTPUT_LEN],
+    len: usize,
+}
+
+impl Output {
+    pub(crate) fn new(value: &[u8]) -> Self {
+        let mut bytes = [0u8; digest::MAX_OUTPUT_LEN];
+        bytes[..value.len()].copy_from_slice(value);
+        Output {
+            bytes,
+            len: value.len(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for Output {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl core::fmt::Debug for Output {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for b in self.as_bytes() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hash `data` in one call.
+pub fn hash(alg: HashAlgorithm, data: &[u8]) -> Output {
+    Output::new(digest::digest(alg.digest_algorithm(), data).as_ref())
+}
+
+/// HMAC `data` under `key` in one call.
+pub fn hmac(alg: HashAlgorithm, key: &[u8], data: &[u8]) -> Output {
+    let key = hmac::Key::new(alg.hmac_algorithm(), key);
+    Output::new(hmac::sign(&key, data).as_ref())
+}
+
+/// Incremental hash context.
+#[derive(Clone)]
+pub struct Hasher(digest::Context);
+
+impl Hasher {
+    pub fn new(alg: HashAlgorithm) -> Self {
+        Hasher(digest::Context::new(alg.digest_algorithm()))
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    pub fn finalize(self) -> Output {
+        Output::new(self.0.finish().as_ref())
+    }
+}
+
+/// Incremental context for any `DigestAlg`.
+#[derive(Clone)]
+pub struct Digester(DigesterInner);
+
+#[derive(Clone)]
+enum DigesterInner {
+    Crc32(Crc32),
+    XxHash64(XxHash64),
+    Hash(Hasher),
+    /* Boxed: BLAKE3's chaining-value stack is nearly 2 KiB */
+    #[cfg(feature = "blake3")]
+    Blake3(alloc::boxed::Box<blake3::Hasher>),
+}
+
+impl Digester {
+    pub fn new(alg: DigestAlg) -> Self {
+        Digester(match alg {
+            DigestAlg::Crc32 => DigesterInner::Crc32(Crc32::new()),
+            DigestAlg::XxHash64 => DigesterInner::XxHash64(XxHash64::new()),
+            #[cfg(feature = "blake3")]
+            DigestAlg::Blake3 => DigesterInner::Blake3(alloc::boxed::Box::default()),
+            _ => DigesterInner::Hash(Hasher::new(alg.hash_algorithm().unwrap())),
+        })
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.0 {
+            DigesterInner::Crc32(crc) => crc.update(data),
+            DigesterInner::XxHash64(xx) => xx.update(data),
+            DigesterInner::Hash(hasher) => hasher.update(data),
+            #[cfg(feature = "blake3")]
+            DigesterInner::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    pub fn finalize(self) -> Output {
+        match self.0 {
+            DigesterInner::Crc32(crc) => Output::new(&crc.finalize().to_be_bytes()),
+            DigesterInner::XxHash64(xx) => Output::new(&xx.finalize().to_be_bytes()),
+            DigesterInner::Hash(hasher) => hasher.finalize(),
+            #[cfg(feature = "blake3")]
+            DigesterInner::Blake3(hasher) => Output::new(hasher.finalize().as_bytes()),
+        }
+    }
+}
+
+/// Incremental HMAC context.
+#[derive(Clone)]
+pub struct HmacContext(hmac::Context);
+
+impl HmacContext {
+    pub fn new(alg: HashAlgorithm, key: &[u8]) -> Self {
+        let key = hmac::Key::new(alg.hmac_algorithm(), key);
+        HmacContext(hmac::Context::with_key(&key))
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    pub fn finalize(self) -> Output {
+        Output::new(self.0.sign().as_ref())
+    }
+
+    /// Compare the tag against `expected` in constant time.
+    pub fn verify(self, expected: &[u8]) -> Result<(), ErrorStates> {
+        use subtle::ConstantTimeEq;
+
+        let tag = self.finalize();
+        if bool::from(tag.as_bytes().ct_eq(expected)) {
+            Ok(())
+        } else {
+            Err(ErrorStates::DecryptionFailed)
+        }
+    }
+}
+
+/// BLAKE3 in keyed mode: a MAC under a 32-byte key.
+#[cfg(feature = "blake3")]
+#[derive(Clone)]
+pub struct Blake3Keyed(alloc::boxed::Box<blake3::Hasher>);
+
+#[cfg(feature = "blake3")]
+impl Blake3Keyed {
+    pub fn new(key: &[u8; blake3::KEY_LEN]) -> Self {
+        Blake3Keyed(alloc::boxed::Box::new(blake3::Hasher::new_keyed(key)))
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    pub fn finalize(self) -> Output {
+        Output::new(self.0.finalize().as_bytes())
+    }
+
+    /// Compare the tag against `expected` in constant time.
+    pub fn verify(self, expected: &[u8]) -> Result<(), ErrorStates> {
+        use subtle::ConstantTimeEq;
+
+        let tag = self.finalize();
+        if bool::from(tag.as_bytes().ct_eq(expected)) {
+            Ok(())
+        } else {
+            Err(ErrorStates::DecryptionFailed)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for Digester {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for HmacContext {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl Write for Blake3Keyed {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash everything `reader` yields, `READ_CHUNK_SIZE` bytes at a time.
+#[cfg(feature = "std")]
+pub fn hash_reader<R: Read>(alg: HashAlgorithm, reader: R) -> io::Result<Output> {
+    digest_reader(alg.into(), reader)
+}
+
+/// As `hash_reader`, for any `DigestAlg`.
+#[cfg(feature = "std")]
+pub fn digest_reader<R: Read>(alg: DigestAlg, mut reader: R) -> io::Result<Output> {
+    let mut hasher = Digester::new(alg);
+    let mut buf = std::vec![0u8; READ_CHUNK_SIZE];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(hasher.finalize()),
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // FIPS 180-2 appendix B.1 / C.1 / D.1 ("abc")
+    #[test]
+    fn sha2_abc() {
+        let cases = [
+            (
+                HashAlgorithm::Sha256,
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                HashAlgorithm::Sha384,
+                concat!(
+                    "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed",
+                    "8086072ba1e7cc2358baeca134c825a7"
+                ),
+            ),
+            (
+                HashAlgorithm::Sha512,
+                concat!(
+                    "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a",
+                    "2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+                ),
+            ),
+        ];
+        for (alg, expected) in cases {
+            let mut hasher = Hasher::new(alg);
+            hasher.update(b"a");
+            hasher.update(b"");
+            hasher.update(b"bc");
+            assert_eq!(hasher.finalize().as_bytes(), hex(expected));
+            assert_eq!(hash(alg, b"abc").as_bytes().len(), alg.output_len());
+        }
+    }
+
+    // RFC 4231 test case 2
+    #[test]
+    fn hmac_sha256_jefe() {
+        let expected = hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        let mut ctx = HmacContext::new(HashAlgorithm::Sha256, b"Jefe");
+        ctx.update(b"what do ya want ");
+        ctx.update(b"for nothing?");
+        let tag = ctx.clone().finalize();
+        assert_eq!(tag.as_bytes(), expected);
+        assert_eq!(ctx.clone().verify(&expected), Ok(()));
+        assert!(ctx.clone().verify(&expected[..31]).is_err());
+        assert_eq!(
+            hmac(
+                HashAlgorithm::Sha256,
+                b"Jefe",
+                b"what do ya want for nothing?"
+            ),
+            tag
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_adapter() {
+        let mut hasher = Hasher::new(HashAlgorithm::Sha256);
+        assert_eq!(hasher.write(b"").unwrap(), 0);
+        hasher.write_all(b"ab").unwrap();
+        assert_eq!(hasher.write(b"").unwrap(), 0);
+        hasher.write_all(b"c").unwrap();
+        hasher.flush().unwrap();
+        assert_eq!(hasher.finalize(), hash(HashAlgorithm::Sha256, b"abc"));
+
+        let mut mac = HmacContext::new(HashAlgorithm::Sha512, b"key");
+        io::copy(&mut &b"streamed"[..], &mut mac).unwrap();
+        mac.write_all(b"").unwrap();
+        assert_eq!(
+            mac.finalize(),
+            hmac(HashAlgorithm::Sha512, b"key", b"streamed")
+        );
+    }
+
+    // FIPS 180-2 appendix B.3: one million 'a'
+    #[cfg(feature = "std")]
+    #[test]
+    fn hash_reader_multi_chunk_file() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&[b'a'; 1_000_000]).unwrap();
+        std::io::Seek::rewind(&mut file).unwrap();
+
+        let digest = hash_reader(HashAlgorithm::Sha256, &mut file).unwrap();
+        assert_eq!(
+            digest.as_bytes(),
+            hex("cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0")
+        );
+        assert_eq!(
+            hash_reader(HashAlgorithm::Sha256, &b""[..]).unwrap(),
+            hash(HashAlgorithm::Sha256, b"")
+        );
+    }
+
+    #[test]
+    fn digester_covers_checksums() {
+        let mut crc = Digester::new(DigestAlg::Crc32);
+        crc.update(b"12345");
+        crc.update(b"6789");
+        assert_eq!(crc.finalize().as_bytes(), [0xcb, 0xf4, 0x39, 0x26]);
+
+        let xx = Digester::new(DigestAlg::XxHash64).finalize();
+        assert_eq!(xx.as_bytes(), 0xEF46_DB37_51D8_E999u64.to_be_bytes());
+        assert_eq!(xx.as_bytes().len(), DigestAlg::XxHash64.output_len());
+
+        let mut sha = Digester::new(HashAlgorithm::Sha384.into());
+        sha.update(b"abc");
+        assert_eq!(sha.finalize(), hash(HashAlgorithm::Sha384, b"abc"));
+    }
+
+    /* BLAKE3 reference test vectors (input i is byte i % 251): lengths 0,
+     * 1024 (one chunk) and 1025, which spills into a second chunk */
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn digester_covers_blake3() {
+        let input: Vec<u8> = (0..1025u32).map(|i| (i % 251) as u8).collect();
+        let cases = [
+            (
+                0,
+                "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+            ),
+            (
+                1024,
+                "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+            ),
+            (
+                1025,
+                "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+            ),
+        ];
+        for (len, expected) in cases {
+            let mut hasher = Digester::new(DigestAlg::Blake3);
+            for piece in input[..len].chunks(100) {
+                hasher.update(piece);
+            }
+            let digest = hasher.finalize();
+            assert_eq!(digest.as_bytes(), hex(expected));
+            assert_eq!(digest.as_bytes().len(), DigestAlg::Blake3.output_len());
+        }
+        assert_eq!(DigestAlg::Blake3.hash_algorithm(), None);
+    }
+
+    /* BLAKE3 reference test vectors, keyed_hash for the empty input and
+     * for 1025 bytes, under the key "whats the Elvish word for friend" */
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn blake3_keyed_vectors() {
+        let key = b"whats the Elvish word for friend";
+        let input: Vec<u8> = (0..1025u32).map(|i| (i % 251) as u8).collect();
+        let cases = [
+            (
+                0,
+                "92b2b75604ed3c761f9d6f62392c8a9227ad0ea3f09573e783f1498a4ed60d26",
+            ),
+            (
+                1025,
+                "357dc55de0c7e382c900fd6e320acc04146be01db6a8ce7210b7189bd664ea69",
+            ),
+        ];
+        for (len, expected) in cases {
+            let mut mac = Blake3Keyed::new(key);
+            for piece in input[..len].chunks(100) {
+                mac.update(piece);
+            }
+            assert_eq!(mac.clone().finalize().as_bytes(), hex(expected));
+            assert_eq!(mac.clone().verify(&hex(expected)), Ok(()));
+            assert!(mac.clone().verify(&hex(expected)[..31]).is_err());
+            let mut other = [0u8; 32];
+            other.copy_from_slice(&hex(expected));
+            other[31] ^= 1;
+            assert!(mac.verify(&other).is_err());
+        }
+    }
+
+    fn algorithm() -> impl Strategy<Value = HashAlgorithm> {
+        prop_oneof![
+            Just(HashAlgorithm::Sha256),
+            Just(HashAlgorithm::Sha384),
+            Just(HashAlgorithm::Sha512),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn incremental_matches_one_shot(
+            alg in algorithm(),
+            data in prop::collection::vec(any::<u8>(), 0..4096),
+            splits in prop::collection::vec(any::<prop::sample::Index>(), 0..8),
+            key in prop::collection::vec(any::<u8>(), 0..200),
+        ) {
+            let mut points: Vec<usize> = splits.iter().map(|i| i.index(data.len() + 1)).collect();
+            points.sort_unstable();
+
+            let mut hasher = Hasher::new(alg);
+            let mut mac = HmacContext::new(alg, &key);
+            let mut start = 0;
+            for end in points.into_iter().chain([data.len()]) {
+                hasher.update(&data[start..end]);
+                mac.update(&data[start..end]);
+                start = end;
+            }
+            prop_assert_eq!(hasher.finalize(), hash(alg, &data));
+            prop_assert_eq!(mac.finalize(), hmac(alg, &key, &data));
+        }
+    }
+}
@@ -0,0 +1,275 @@
+/* Fernet tokens, compatible with Python's `cryptography.fernet`.
+ * [Spec]: https://github.com/fernet/spec/blob/master/Spec.md
+ *
+ * Token (URL-safe base64, padded):
+ *
+ *   0x80 || timestamp (u64, big-endian) || IV (16) || AES-128-CBC(PKCS7) || HMAC-SHA256
+ *
+ * The HMAC covers every byte before it and is keyed with the first half of
+ * the 32-byte Fernet key; the second half is the AES key. */
+use std::string::String;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::vec::Vec;
+
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockModeDecrypt, BlockModeEncrypt, KeyIvInit};
+use aes::Aes128;
+use base64::engine::general_purpose::URL_SAFE;
+use base64::Engine;
+use ring::hmac;
+use ring::rand::SystemRandom;
+
+use crate::EntropySource;
+
+const VERSION: u8 = 0x80;
+const IV_LEN: usize = 16;
+const HMAC_LEN: usize = 32;
+/* version + timestamp + IV */
+const PREFIX_LEN: usize = 1 + 8 + IV_LEN;
+/* Tokens may be stamped up to this far in the future (matches Python) */
+const MAX_CLOCK_SKEW: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FernetError {
+    /* Key is not 32 bytes of URL-safe base64 */
+    InvalidKey,
+    /* Malformed token, wrong key, or tampered data */
+    InvalidToken,
+    /* Token is older than the requested TTL */
+    Expired,
+    /* Token timestamp is further in the future than the allowed clock skew */
+    FromTheFuture,
+}
+
+#[derive(Clone)]
+pub struct FernetKey {
+    signing_key: [u8; 16],
+    encryption_key: [u8; 16],
+}
+
+impl FernetKey {
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&SystemRandom::new())
+    }
+
+    pub fn generate_with_rng(rng: &dyn EntropySource) -> Self {
+        let mut key = [0u8; 32];
+        rng.fill(&mut key);
+        Self::from_bytes(&key)
+    }
+
+    pub fn from_bytes(key: &[u8; 32]) -> Self {
+        let mut signing_key = [0u8; 16];
+        let mut encryption_key = [0u8; 16];
+        signing_key.copy_from_slice(&key[..16]);
+        encryption_key.copy_from_slice(&key[16..]);
+        FernetKey {
+            signing_key,
+            encryption_key,
+        }
+    }
+
+    /// Parse the 44-character URL-safe base64 key format.
+    pub fn from_base64(key: &str) -> Result<Self, FernetError> {
+        let bytes = URL_SAFE
+            .decode(key.trim())
+            .map_err(|_| FernetError::InvalidKey)?;
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| FernetError::InvalidKey)?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    pub fn to_base64(&self) -> String {
+        let mut key = [0u8; 32];
+        key[..16].copy_from_slice(&self.signing_key);
+        key[16..].copy_from_slice(&self.encryption_key);
+        URL_SAFE.encode(key)
+    }
+
+    fn hmac_key(&self) -> hmac::Key {
+        hmac::Key::new(hmac::HMAC_SHA256, &self.signing_key)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Mint a token for `plaintext`, stamped with the current time.
+pub fn fernet_encrypt(key: &FernetKey, plaintext: &[u8]) -> String {
+    fernet_encrypt_with_rng(key, plaintext, &SystemRandom::new())
+}
+
+/// As `fernet_encrypt`, drawing the IV from `rng`.
+pub fn fernet_encrypt_with_rng(
+    key: &FernetKey,
+    plaintext: &[u8],
+    rng: &dyn EntropySource,
+) -> String {
+    let mut iv = [0u8; IV_LEN];
+    rng.fill(&mut iv);
+    encrypt_at(key, plaintext, unix_now(), &iv)
+}
+
+/// Verify and decrypt `token`. With `ttl`, tokens older than `ttl` are
+/// rejected as `Expired`, and tokens stamped more than 60 seconds in the
+/// future as `FromTheFuture`.
+pub fn fernet_decrypt(
+    key: &FernetKey,
+    token: &str,
+    ttl: Option<Duration>,
+) -> Result<Vec<u8>, FernetError> {
+    decrypt_at(key, token, ttl, unix_now())
+}
+
+fn encrypt_at(key: &FernetKey, plaintext: &[u8], timestamp: u64, iv: &[u8; IV_LEN]) -> String {
+    let ciphertext = cbc::Encryptor::<Aes128>::new(&key.encryption_key.into(), &(*iv).into())
+        .encrypt_padded_vec::<Pkcs7>(plaintext);
+
+    let mut token = Vec::with_capacity(PREFIX_LEN + ciphertext.len() + HMAC_LEN);
+    token.push(VERSION);
+    token.extend_from_slice(&timestamp.to_be_bytes());
+    token.extend_from_slice(iv);
+    token.extend_from_slice(&ciphertext);
+
+    let tag = hmac::sign(&key.hmac_key(), &token);
+    token.extend_from_slice(tag.as_ref());
+    URL_SAFE.encode(token)
+}
+
+fn decrypt_at(
+    key: &FernetKey,
+    token: &str,
+    ttl: Option<Duration>,
+    now: u64,
+) -> Result<Vec<u8>, FernetError> {
+    let data = URL_SAFE
+        .decode(token.trim())
+        .map_err(|_| FernetError::InvalidToken)?;
+
+    /* At least one ciphertext block is always present (PKCS7) */
+    if data.len() < PREFIX_LEN + IV_LEN + HMAC_LEN || data[0] != VERSION {
+        return Err(FernetError::InvalidToken);
+    }
+    let (signed, tag) = data.split_at(data.len() - HMAC_LEN);
+    hmac::verify(&key.hmac_key(), signed, tag).map_err(|_| FernetError::InvalidToken)?;
+
+    let timestamp = u64::from_be_bytes(signed[1..9].try_into().unwrap());
+    if let Some(ttl) = ttl {
+        if timestamp.saturating_add(ttl.as_secs()) < now {
+            return Err(FernetError::Expired);
+        }
+        if now.saturating_add(MAX_CLOCK_SKEW) < timestamp {
+            return Err(FernetError::FromTheFuture);
+        }
+    }
+
+    let iv: [u8; IV_LEN] = signed[9..PREFIX_LEN].try_into().unwrap();
+    let ciphertext = &signed[PREFIX_LEN..];
+    if ciphertext.len() % IV_LEN != 0 {
+        return Err(FernetError::InvalidToken);
+    }
+
+    cbc::Decryptor::<Aes128>::new(&key.encryption_key.into(), &iv.into())
+        .decrypt_padded_vec::<Pkcs7>(ciphertext)
+        .map_err(|_| FernetError::InvalidToken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Key from the python-side fixtures: bytes 0x00..=0x1f */
+    const PY_KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+    // Fernet spec `generate.json`/`verify.json` vector
+    #[test]
+    fn spec_vector() {
+        let key = FernetKey::from_base64("cw_0x689RpI-jtRR7oE8h_eQsKImvJapLeSbXpwF4e4=").unwrap();
+        let token = "gAAAAAAdwJ6wAAECAwQFBgcICQoLDA0ODy021cpGVWKZ_eEwCGM4BLLF_5CV9dOPmrhuVUPgJobwOz7JcbmrR64jVmpU4IwqDA==";
+        let iv: [u8; 16] = core::array::from_fn(|i| i as u8);
+
+        /* 1985-10-26T01:20:00-07:00 */
+        assert_eq!(encrypt_at(&key, b"hello", 499162800, &iv), token);
+        assert_eq!(
+            decrypt_at(&key, token, Some(Duration::from_secs(60)), 499162800).unwrap(),
+            b"hello"
+        );
+    }
+
+    // Minted by Python: Fernet(PY_KEY)._encrypt_from_parts(msg, 1700000000, b"\xaa" * 16)
+    #[test]
+    fn decrypts_python_token() {
+        let key = FernetKey::from_base64(PY_KEY).unwrap();
+        let token = "gAAAAABlU_EAqqqqqqqqqqqqqqqqqqqqqvc1AX6ZNmv2ok0iNLBZBaS4LmFCPIx75RJVFtiz0YiYGutbeOr53VZlTRkmAyMITaYi6i5D0FQmehKCkgPk39g=";
+
+        let plaintext = decrypt_at(&key, token, None, 1700000000).unwrap();
+        assert_eq!(plaintext, b"Message from the Python service");
+    }
+
+    // Verified on the Python side with Fernet(PY_KEY).decrypt(token)
+    #[test]
+    fn python_accepts_our_token() {
+        let key = FernetKey::from_base64(PY_KEY).unwrap();
+        let token = encrypt_at(&key, b"Message from Rust", 1700000000, &[0x55; 16]);
+        assert_eq!(
+            token,
+            "gAAAAABlU_EAVVVVVVVVVVVVVVVVVVVVVVtrdTfAw2jh2xOu15rG2ax_poOqW1ZLk2A0-nHiu_IPPitk9_VIVM_tr84neI2E13Kx5JrEmwV281KWoVAXkT4="
+        );
+    }
+
+    #[test]
+    fn round_trip_and_key_format() {
+        let key = FernetKey::generate();
+        let encoded = key.to_base64();
+        assert_eq!(encoded.len(), 44);
+
+        let key = FernetKey::from_base64(&encoded).unwrap();
+        let token = fernet_encrypt(&key, b"Hello World!");
+        let ttl = Some(Duration::from_secs(60));
+        assert_eq!(fernet_decrypt(&key, &token, ttl).unwrap(), b"Hello World!");
+
+        assert!(FernetKey::from_base64("c2hvcnQ=").is_err());
+        assert!(fernet_decrypt(&FernetKey::generate(), &token, None).is_err());
+    }
+
+    #[test]
+    fn ttl_checks() {
+        let key = FernetKey::from_base64(PY_KEY).unwrap();
+        let token = encrypt_at(&key, b"Hello World!", 1000, &[0; 16]);
+        let ttl = Some(Duration::from_secs(60));
+
+        assert!(decrypt_at(&key, &token, ttl, 1060).is_ok());
+        assert_eq!(
+            decrypt_at(&key, &token, ttl, 1061),
+            Err(FernetError::Expired)
+        );
+        assert!(decrypt_at(&key, &token, ttl, 940).is_ok());
+        assert_eq!(
+            decrypt_at(&key, &token, ttl, 939),
+            Err(FernetError::FromTheFuture)
+        );
+        /* No TTL: age is not checked */
+        assert!(decrypt_at(&key, &token, None, u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn tampered_token_rejected() {
+        let key = FernetKey::from_base64(PY_KEY).unwrap();
+        let mut token = URL_SAFE
+            .decode(encrypt_at(&key, b"Hello World!", 1000, &[0; 16]))
+            .unwrap();
+        token[PREFIX_LEN] ^= 1;
+
+        assert_eq!(
+            decrypt_at(&key, &URL_SAFE.encode(token), None, 1000),
+            Err(FernetError::InvalidToken)
+        );
+        assert_eq!(
+            decrypt_at(&key, "not a token", None, 1000),
+            Err(FernetError::InvalidToken)
+        );
+    }
+}
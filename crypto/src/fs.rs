@@ -0,0 +1,2666 @@
+/* File and stream encryption on top of the versioned header and the
+ * chunked STREAM AEAD. Requires the `std` feature.
+ *
+ * Output layout: header || chunk_0 || ... || chunk_n, where every chunk but
+ * the last holds exactly `chunk_size` plaintext bytes plus a 16-byte tag.
+ * At most two chunk buffers are held in memory at once, or jobs + 1 when
+ * `pipeline` spreads the chunks over several threads. */
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::string::String;
+use std::vec::Vec;
+
+#[cfg(feature = "compress")]
+use crate::compress::Codec;
+use crate::header::{
+    Algorithm, FileHeader, HeaderError, PassphraseKdf, AAD_CHECK_LEN, DEFAULT_CHUNK_SIZE,
+    HEADER_FIXED_LEN, NONCE_PREFIX_LEN,
+};
+use crate::padding::{PaddingScheme, PADDING_TRAILER_LEN};
+use crate::split::{self, SplitWriter};
+#[cfg(feature = "parallel")]
+use crate::stream::IndexedStream;
+use crate::stream::{StreamDecryptor, StreamEncryptor, TAG_LEN};
+use ring::digest;
+use ring::rand::SystemRandom;
+use zeroize::Zeroizing;
+
+use crate::{generate_nonce, key_check_value, EntropySource, ErrorStates};
+
+#[derive(Debug, Clone)]
+pub struct EncryptOptions {
+    /// Plaintext bytes per chunk (see `header::MIN_CHUNK_SIZE`/`MAX_CHUNK_SIZE`).
+    pub chunk_size: u32,
+    /// Seal in ratchet mode, so later state cannot open earlier chunks
+    /// (see `stream`).
+    pub ratchet: bool,
+    /// Pad the plaintext before sealing, hiding its exact length (see
+    /// `padding`).
+    pub padding: Option<PaddingScheme>,
+    /// Store the key's check value in the header, so decrypting with the
+    /// wrong key fails with `WrongKey` before any chunk is read.
+    pub key_check: bool,
+    /// Record that the key was derived from a passphrase with these
+    /// parameters, so it can be derived again (see `derive_file_key`).
+    pub passphrase: Option<PassphraseKdf>,
+    /// Associated data every chunk is bound to, such as a hostname or
+    /// backup set name; decryption must supply the same bytes (see
+    /// `decrypt_stream_with_aad`). Empty for none.
+    pub aad: Vec<u8>,
+    /// The AEAD chunks are sealed with, recorded in the header so
+    /// decryption picks it up by itself.
+    pub algorithm: Algorithm,
+    /// Compress the plaintext with this codec before sealing it, unless a
+    /// sample of it shows that would save little (see `compress`).
+    #[cfg(feature = "compress")]
+    pub compression: Option<Codec>,
+    /// The plaintext's length, if known before encrypting. A compressed
+    /// stream records it, so decryption can refuse an oversized one
+    /// before reading it, and reading any other amount fails with
+    /// `InvalidData`.
+    #[cfg(feature = "compress")]
+    pub original_len: Option<u64>,
+    /// Threads to seal chunks on. Only with the `parallel` feature, and
+    /// never in ratchet mode, where each chunk's key comes from the one
+    /// before; the output is the same whatever the count. [default: 1]
+    pub jobs: usize,
+}
+
+impl Default for EncryptOptions {
+    fn default() -> Self {
+        EncryptOptions {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            ratchet: false,
+            padding: None,
+            key_check: false,
+            passphrase: None,
+            aad: Vec::new(),
+            algorithm: Algorithm::ChaCha20Poly1305,
+            #[cfg(feature = "compress")]
+            compression: None,
+            #[cfg(feature = "compress")]
+            original_len: None,
+            jobs: 1,
+        }
+    }
+}
+
+impl EncryptOptions {
+    pub(crate) fn header(&self, key: &[u8], nonce_prefix: [u8; NONCE_PREFIX_LEN]) -> FileHeader {
+        let mut header = FileHeader::new(self.algorithm, self.chunk_size, nonce_prefix);
+        if self.ratchet {
+            header = header.with_ratchet();
+        }
+        if let Some(scheme) = self.padding {
+            header = header.with_padding(scheme);
+        }
+        if self.key_check {
+            header = header.with_key_check(key_check_value(key));
+        }
+        if let Some(kdf) = self.passphrase {
+            header = header.with_passphrase(kdf);
+        }
+        if !self.aad.is_empty() {
+            header = header.with_aad_check(aad_check_value(&self.aad));
+        }
+        #[cfg(feature = "compress")]
+        if let Some(codec) = self.compression {
+            header = header.with_compression(codec.id());
+            if let Some(len) = self.original_len {
+                header = header.with_original_len(len);
+            }
+        }
+        header
+    }
+}
+
+const AAD_CHECK_LABEL: &[u8] = b"rcli aad check value v1";
+
+/* Public, like the data it checks, so a plain hash will do */
+fn aad_check_value(aad: &[u8]) -> [u8; AAD_CHECK_LEN] {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    ctx.update(AAD_CHECK_LABEL);
+    ctx.update(aad);
+    let mut check = [0u8; AAD_CHECK_LEN];
+    check.copy_from_slice(&ctx.finish().as_ref()[..AAD_CHECK_LEN]);
+    check
+}
+
+/* What every chunk authenticates: the encoded header, then the caller's
+ * associated data. The header records its own length, so the two cannot
+ * be re-split. */
+fn chunk_aad(header_bytes: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(header_bytes.len() + aad.len());
+    out.extend_from_slice(header_bytes);
+    out.extend_from_slice(aad);
+    out
+}
+
+/* `AlgorithmNotEnabled` for a header this build cannot seal or open */
+fn check_algorithm(header: &FileHeader) -> Result<(), HeaderError> {
+    match header.algorithm.is_enabled() {
+        true => Ok(()),
+        false => Err(HeaderError::AlgorithmNotEnabled(header.algorithm)),
+    }
+}
+
+/* Stream sealer/opener with the algorithm and in the mode the header
+ * records */
+fn encryptor_for(
+    header: &FileHeader,
+    key: &[u8],
+    header_bytes: &[u8],
+    aad: &[u8],
+) -> Result<StreamEncryptor, FsCryptoError> {
+    check_algorithm(header)?;
+    let aad = chunk_aad(header_bytes, aad);
+    let (algorithm, prefix) = (header.algorithm, &header.nonce_prefix);
+    Ok(if header.ratchet {
+        StreamEncryptor::ratcheting_with_algorithm(algorithm, key, prefix, &aad)?
+    } else {
+        StreamEncryptor::with_algorithm(algorithm, key, prefix, &aad)?
+    })
+}
+
+/* `WrongKey` if the header has a key check value that `key` does not match */
+fn check_key(header: &FileHeader, key: &[u8]) -> Result<(), ErrorStates> {
+    match header.key_check {
+        Some(kcv) if key_check_value(key) != kcv => Err(ErrorStates::WrongKey),
+        _ => Ok(()),
+    }
+}
+
+/* Streams whose key is sealed to a recipient are opened through
+ * `recipient`, never with a key from the caller */
+fn not_for_recipient(header: &FileHeader) -> Result<(), HeaderError> {
+    if !header.recipients.is_empty() {
+        return Err(HeaderError::Recipient);
+    }
+    Ok(())
+}
+
+/* `AadMismatch` unless `aad` matches the header's check value, or there
+ * is neither */
+fn check_aad(header: &FileHeader, aad: &[u8]) -> Result<(), ErrorStates> {
+    match header.aad_check {
+        None if aad.is_empty() => Ok(()),
+        Some(check) if !aad.is_empty() && aad_check_value(aad) == check => Ok(()),
+        _ => Err(ErrorStates::AadMismatch),
+    }
+}
+
+fn decryptor_for(
+    header: &FileHeader,
+    key: &[u8],
+    header_bytes: &[u8],
+    aad: &[u8],
+) -> Result<StreamDecryptor, FsCryptoError> {
+    check_algorithm(header)?;
+    check_key(header, key)?;
+    check_aad(header, aad)?;
+    let aad = chunk_aad(header_bytes, aad);
+    let (algorithm, prefix) = (header.algorithm, &header.nonce_prefix);
+    Ok(if header.ratchet {
+        StreamDecryptor::ratcheting_with_algorithm(algorithm, key, prefix, &aad)?
+    } else {
+        StreamDecryptor::with_algorithm(algorithm, key, prefix, &aad)?
+    })
+}
+
+/* Chunks by index for `pipeline`, once `encryptor_for` or `decryptor_for`
+ * has checked the header; `None` when they must go one at a time */
+#[cfg(feature = "parallel")]
+fn indexed_for(
+    header: &FileHeader,
+    key: &[u8],
+    header_bytes: &[u8],
+    aad: &[u8],
+    jobs: usize,
+) -> Result<Option<IndexedStream>, FsCryptoError> {
+    if jobs <= 1 || header.ratchet {
+        return Ok(None);
+    }
+    let aad = chunk_aad(header_bytes, aad);
+    let (algorithm, prefix) = (header.algorithm, &header.nonce_prefix);
+    Ok(Some(IndexedStream::with_algorithm(
+        algorithm, key, prefix, &aad,
+    )?))
+}
+
+/// Byte counts for a completed encrypt/decrypt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub chunks: u64,
+}
+
+#[derive(Debug)]
+pub enum FsCryptoError {
+    Io(io::Error),
+    Header(HeaderError),
+    Crypto(ErrorStates),
+}
+
+impl fmt::Display for FsCryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsCryptoError::Io(e) => write!(f, "I/O error: {}", e),
+            FsCryptoError::Header(HeaderError::BadMagic) => {
+                write!(f, "not an rcli encrypted file")
+            }
+            FsCryptoError::Header(HeaderError::UnsupportedVersion(v)) => {
+                write!(f, "unsupported format version {}", v)
+            }
+            FsCryptoError::Header(HeaderError::UnknownAlgorithm(id)) => {
+                write!(f, "unknown algorithm id {}", id)
+            }
+            FsCryptoError::Header(HeaderError::AlgorithmNotEnabled(algorithm)) => {
+                write!(
+                    f,
+                    "file uses {}, which this build was compiled without",
+                    algorithm.name()
+                )
+            }
+            FsCryptoError::Header(HeaderError::Truncated) => write!(f, "truncated header"),
+            FsCryptoError::Header(HeaderError::Malformed) => write!(f, "malformed header"),
+            FsCryptoError::Header(HeaderError::Compressed) => {
+                write!(f, "file is compressed; open it with the compress feature")
+            }
+            FsCryptoError::Header(HeaderError::Recipient) => {
+                write!(
+                    f,
+                    "file is encrypted to a recipient; open it with their identity"
+                )
+            }
+            FsCryptoError::Crypto(ErrorStates::StreamTruncated) => {
+                write!(f, "encrypted stream is truncated")
+            }
+            FsCryptoError::Crypto(ErrorStates::DecryptionFailed) => {
+                write!(f, "decryption failed (wrong key or corrupted file)")
+            }
+            FsCryptoError::Crypto(ErrorStates::WrongKey) => {
+                write!(f, "wrong key (does not match the file's key check value)")
+            }
+            FsCryptoError::Crypto(ErrorStates::AadMismatch) => {
+                write!(
+                    f,
+                    "associated data does not match what the file was bound to"
+                )
+            }
+            FsCryptoError::Crypto(e) => write!(f, "{:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for FsCryptoError {}
+
+impl From<io::Error> for FsCryptoError {
+    fn from(e: io::Error) -> Self {
+        FsCryptoError::Io(e)
+    }
+}
+
+impl From<HeaderError> for FsCryptoError {
+    fn from(e: HeaderError) -> Self {
+        FsCryptoError::Header(e)
+    }
+}
+
+impl From<ErrorStates> for FsCryptoError {
+    fn from(e: ErrorStates) -> Self {
+        FsCryptoError::Crypto(e)
+    }
+}
+
+/* Read up to `n` bytes, stopping early only at end of input */
+pub(crate) fn read_chunk<R: Read>(reader: &mut R, buf: &mut Vec<u8>, n: usize) -> io::Result<()> {
+    buf.clear();
+    reader.by_ref().take(n as u64).read_to_end(buf)?;
+    Ok(())
+}
+
+/// Read and parse an rcli header, returning it with its encoded bytes.
+pub fn read_header<R: Read>(reader: &mut R) -> Result<(FileHeader, Vec<u8>), FsCryptoError> {
+    let mut bytes = Vec::with_capacity(HEADER_FIXED_LEN);
+    read_chunk(reader, &mut bytes, HEADER_FIXED_LEN)?;
+    if !FileHeader::has_magic(&bytes) {
+        /* Distinguish "too short to tell" from "definitely not ours" */
+        FileHeader::parse(&bytes)?;
+    }
+
+    let total_len = FileHeader::encoded_len(&bytes)?;
+    let mut rest = Vec::new();
+    read_chunk(reader, &mut rest, total_len - bytes.len())?;
+    bytes.extend_from_slice(&rest);
+
+    let (header, _) = FileHeader::parse(&bytes)?;
+    Ok((header, bytes))
+}
+
+/// Encrypt everything from `reader` into `writer` as an rcli stream.
+pub fn encrypt_stream<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    opts: &EncryptOptions,
+) -> Result<Summary, FsCryptoError> {
+    encrypt_stream_with_rng(reader, writer, key, opts, &SystemRandom::new())
+}
+
+/// As `encrypt_stream`, drawing the nonce prefix from `rng`.
+pub fn encrypt_stream_with_rng<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    opts: &EncryptOptions,
+    rng: &dyn EntropySource,
+) -> Result<Summary, FsCryptoError> {
+    let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+    rng.fill(&mut nonce_prefix);
+    encrypt_stream_with_nonce_prefix(reader, writer, key, opts, nonce_prefix)
+}
+
+/// As `encrypt_stream`, under a nonce prefix the caller drew, so it can
+/// record or check the prefix first. Never pass one used with `key`
+/// before: chunk nonces would repeat.
+pub fn encrypt_stream_with_nonce_prefix<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    opts: &EncryptOptions,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+) -> Result<Summary, FsCryptoError> {
+    let header = opts.header(key, nonce_prefix);
+    #[cfg(feature = "compress")]
+    if let Some(codec) = opts.compression {
+        return crate::compress::encrypt_with_codec(
+            reader, writer, key, &header, &opts.aad, codec, opts.jobs,
+        );
+    }
+    encrypt_with_header(reader, writer, key, &header, &opts.aad, opts.jobs)
+}
+
+/* Write `header`, then the stream sealed under its nonce prefix and bound
+ * to `aad`, on up to `jobs` threads */
+pub(crate) fn encrypt_with_header<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    header: &FileHeader,
+    aad: &[u8],
+    jobs: usize,
+) -> Result<Summary, FsCryptoError> {
+    let Some(scheme) = header.padding else {
+        return seal_chunks(reader, writer, key, header, aad, jobs);
+    };
+    let mut padded = PadReader::new(reader, scheme);
+    let mut summary = seal_chunks(&mut padded, writer, key, header, aad, jobs)?;
+    summary.bytes_in = padded.len;
+    Ok(summary)
+}
+
+fn seal_chunks<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    header: &FileHeader,
+    aad: &[u8],
+    jobs: usize,
+) -> Result<Summary, FsCryptoError> {
+    /* Reject chunk sizes the decryptor would refuse */
+    let header_bytes = header.to_bytes();
+    FileHeader::parse(&header_bytes)?;
+
+    let mut encryptor = encryptor_for(header, key, &header_bytes, aad)?;
+    writer.write_all(&header_bytes)?;
+
+    let chunk_size = header.chunk_size as usize;
+    let mut summary = Summary {
+        bytes_out: header_bytes.len() as u64,
+        ..Summary::default()
+    };
+
+    #[cfg(feature = "parallel")]
+    if let Some(stream) = indexed_for(header, key, &header_bytes, aad, jobs)? {
+        let seal = |index, last, chunk: &mut Vec<u8>| stream.seal(index, last, chunk);
+        let overflow = ErrorStates::EncryptionFailed;
+        crate::pipeline::run(
+            reader,
+            writer,
+            chunk_size,
+            jobs,
+            &mut summary,
+            overflow,
+            seal,
+        )?;
+        writer.flush()?;
+        return Ok(summary);
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = jobs;
+
+    let mut chunk = Vec::with_capacity(chunk_size + TAG_LEN);
+    let mut next = Vec::with_capacity(chunk_size + TAG_LEN);
+    read_chunk(reader, &mut chunk, chunk_size)?;
+    loop {
+        summary.bytes_in += chunk.len() as u64;
+        summary.chunks += 1;
+
+        if chunk.len() == chunk_size {
+            read_chunk(reader, &mut next, chunk_size)?;
+        } else {
+            next.clear();
+        }
+
+        if next.is_empty() {
+            encryptor.seal_last(&mut chunk)?;
+            writer.write_all(&chunk)?;
+            summary.bytes_out += chunk.len() as u64;
+            break;
+        }
+
+        encryptor.seal_chunk(&mut chunk)?;
+        writer.write_all(&chunk)?;
+        summary.bytes_out += chunk.len() as u64;
+        core::mem::swap(&mut chunk, &mut next);
+    }
+
+    writer.flush()?;
+    Ok(summary)
+}
+
+/// Decrypt an rcli stream from `reader` into `writer`. Plaintext is written
+/// as each chunk authenticates, so on error `writer` may hold a prefix of it.
+pub fn decrypt_stream<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+) -> Result<Summary, FsCryptoError> {
+    decrypt_stream_with_aad(reader, writer, key, &[])
+}
+
+/// As `decrypt_stream_with_aad`, opening chunks on up to `jobs` threads
+/// (see `EncryptOptions::jobs`). The output, and any error, is what one
+/// thread would give: chunks are written in order, up to the first that
+/// fails.
+pub fn decrypt_stream_with_jobs<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    aad: &[u8],
+    jobs: usize,
+) -> Result<Summary, FsCryptoError> {
+    let (header, header_bytes) = read_header(reader)?;
+    if header.compression.is_some() {
+        return Err(HeaderError::Compressed.into());
+    }
+    not_for_recipient(&header)?;
+    decrypt_after_header(reader, writer, key, &header, &header_bytes, aad, jobs)
+}
+
+/// As `decrypt_stream`, for a stream encrypted with `EncryptOptions::aad`
+/// set to `aad`. Missing, unexpected or different associated data fails
+/// with `AadMismatch` before any chunk is opened (but for a one in 2^32
+/// chance, where the first chunk fails to authenticate instead). Streams
+/// encrypted to a recipient fail with `HeaderError::Recipient`.
+pub fn decrypt_stream_with_aad<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    aad: &[u8],
+) -> Result<Summary, FsCryptoError> {
+    decrypt_stream_with_jobs(reader, writer, key, aad, 1)
+}
+
+/* Decrypt the chunks that follow an already-parsed header, on up to
+ * `jobs` threads */
+pub(crate) fn decrypt_after_header<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    header: &FileHeader,
+    header_bytes: &[u8],
+    aad: &[u8],
+    jobs: usize,
+) -> Result<Summary, FsCryptoError> {
+    let Some(scheme) = header.padding else {
+        return open_chunks(reader, writer, key, header, header_bytes, aad, jobs);
+    };
+    let mut unpadder = Unpadder::new(writer, scheme);
+    let mut summary = open_chunks(reader, &mut unpadder, key, header, header_bytes, aad, jobs)?;
+    summary.bytes_out = unpadder.finish()?;
+    Ok(summary)
+}
+
+fn open_chunks<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8],
+    header: &FileHeader,
+    header_bytes: &[u8],
+    aad: &[u8],
+    jobs: usize,
+) -> Result<Summary, FsCryptoError> {
+    let mut decryptor = decryptor_for(header, key, header_bytes, aad)?;
+
+    let sealed_size = header.chunk_size as usize + TAG_LEN;
+    let mut summary = Summary {
+        bytes_in: header_bytes.len() as u64,
+        ..Summary::default()
+    };
+
+    #[cfg(feature = "parallel")]
+    if let Some(stream) = indexed_for(header, key, header_bytes, aad, jobs)? {
+        let open = |index, last, chunk: &mut Vec<u8>| match chunk.len() < TAG_LEN {
+            true => Err(ErrorStates::StreamTruncated),
+            false => stream.open(index, last, chunk),
+        };
+        let overflow = ErrorStates::DecryptionFailed;
+        crate::pipeline::run(
+            reader,
+            writer,
+            sealed_size,
+            jobs,
+            &mut summary,
+            overflow,
+            open,
+        )?;
+        writer.flush()?;
+        return Ok(summary);
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = jobs;
+
+    let mut chunk = Vec::with_capacity(sealed_size);
+    let mut next = Vec::with_capacity(sealed_size);
+    read_chunk(reader, &mut chunk, sealed_size)?;
+    loop {
+        summary.bytes_in += chunk.len() as u64;
+        summary.chunks += 1;
+
+        if chunk.len() < TAG_LEN {
+            return Err(ErrorStates::StreamTruncated.into());
+        }
+
+        if chunk.len() == sealed_size {
+            read_chunk(reader, &mut next, sealed_size)?;
+        } else {
+            next.clear();
+        }
+
+        if next.is_empty() {
+            decryptor.open_last(&mut chunk)?;
+            writer.write_all(&chunk)?;
+            summary.bytes_out += chunk.len() as u64;
+            break;
+        }
+
+        decryptor.open_chunk(&mut chunk)?;
+        writer.write_all(&chunk)?;
+        summary.bytes_out += chunk.len() as u64;
+        core::mem::swap(&mut chunk, &mut next);
+    }
+
+    writer.flush()?;
+    Ok(summary)
+}
+
+/* Yields the inner reader's bytes followed by their padding: zeros, then
+ * the original length */
+struct PadReader<'a, R: Read> {
+    inner: &'a mut R,
+    scheme: PaddingScheme,
+    /* Bytes read from `inner` */
+    len: u64,
+    /* Zeros still to yield and trailer bytes already yielded, once `inner`
+     * has ended */
+    tail: Option<(u64, usize)>,
+}
+
+impl<'a, R: Read> PadReader<'a, R> {
+    fn new(inner: &'a mut R, scheme: PaddingScheme) -> Self {
+        PadReader {
+            inner,
+            scheme,
+            len: 0,
+            tail: None,
+        }
+    }
+}
+
+impl<R: Read> Read for PadReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (zeros, trailer_at) = match &mut self.tail {
+            Some(tail) => tail,
+            None => {
+                let n = self.inner.read(buf)?;
+                if n > 0 {
+                    self.len += n as u64;
+                    return Ok(n);
+                }
+                let zeros =
+                    self.scheme.padded_len(self.len) - self.len - PADDING_TRAILER_LEN as u64;
+                self.tail.insert((zeros, 0))
+            }
+        };
+        if *zeros > 0 {
+            let n = (*zeros).min(buf.len() as u64) as usize;
+            buf[..n].fill(0);
+            *zeros -= n as u64;
+            return Ok(n);
+        }
+        let trailer = self.len.to_be_bytes();
+        let n = (PADDING_TRAILER_LEN - *trailer_at).min(buf.len());
+        buf[..n].copy_from_slice(&trailer[*trailer_at..*trailer_at + n]);
+        *trailer_at += n;
+        Ok(n)
+    }
+}
+
+/* Strips padding from decrypted plaintext on its way to `inner`. A run of
+ * zeros is only counted, not buffered, until a later non-zero byte shows
+ * it was data; the last 8 bytes seen are held back as the trailer. So
+ * memory stays constant however much padding there is. */
+struct Unpadder<'a, W: Write> {
+    inner: &'a mut W,
+    scheme: PaddingScheme,
+    written: u64,
+    zeros: u64,
+    tail: Vec<u8>,
+}
+
+impl<'a, W: Write> Unpadder<'a, W> {
+    fn new(inner: &'a mut W, scheme: PaddingScheme) -> Self {
+        Unpadder {
+            inner,
+            scheme,
+            written: 0,
+            zeros: 0,
+            tail: Vec::with_capacity(PADDING_TRAILER_LEN),
+        }
+    }
+
+    /* Pass on bytes known not to be the trailer */
+    fn emit(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let Some(last) = bytes.iter().rposition(|&b| b != 0) else {
+            self.zeros += bytes.len() as u64;
+            return Ok(());
+        };
+        self.write_zeros(self.zeros)?;
+        self.zeros = (bytes.len() - last - 1) as u64;
+        self.inner.write_all(&bytes[..=last])?;
+        self.written += last as u64 + 1;
+        Ok(())
+    }
+
+    fn write_zeros(&mut self, mut n: u64) -> io::Result<()> {
+        const ZEROS: [u8; 512] = [0; 512];
+        self.written += n;
+        while n > 0 {
+            let step = n.min(ZEROS.len() as u64) as usize;
+            self.inner.write_all(&ZEROS[..step])?;
+            n -= step as u64;
+        }
+        Ok(())
+    }
+
+    /* Check the trailer against what was seen, write the zeros that were
+     * data, and return the unpadded length. The plaintext has already
+     * authenticated, so a mismatch means it was not padded by us */
+    fn finish(mut self) -> Result<u64, FsCryptoError> {
+        let trailer: [u8; PADDING_TRAILER_LEN] = self
+            .tail
+            .as_slice()
+            .try_into()
+            .map_err(|_| ErrorStates::DecryptionFailed)?;
+        let original = u64::from_be_bytes(trailer);
+        let padded = self.written + self.zeros + PADDING_TRAILER_LEN as u64;
+        if original < self.written
+            || original > self.written + self.zeros
+            || self.scheme.padded_len(original) != padded
+        {
+            return Err(ErrorStates::DecryptionFailed.into());
+        }
+        self.write_zeros(original - self.written)?;
+        self.inner.flush()?;
+        Ok(original)
+    }
+}
+
+impl<W: Write> Write for Unpadder<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() >= PADDING_TRAILER_LEN {
+            let (body, trailer) = buf.split_at(buf.len() - PADDING_TRAILER_LEN);
+            let held = core::mem::take(&mut self.tail);
+            self.emit(&held)?;
+            self.emit(body)?;
+            self.tail.extend_from_slice(trailer);
+        } else {
+            let spill = (self.tail.len() + buf.len()).saturating_sub(PADDING_TRAILER_LEN);
+            let held: Vec<u8> = self.tail.drain(..spill).collect();
+            self.emit(&held)?;
+            self.tail.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Re-encrypt an rcli stream from `old_key` to `new_key`. Each chunk is
+/// opened and resealed in place, so plaintext is never written out and the
+/// buffers that briefly hold it are zeroized when done. The output keeps the
+/// header's chunk size and format but gets a fresh nonce prefix. A record
+/// of a passphrase the old key came from is dropped, as it does not apply
+/// to the new key. Streams bound to associated data are refused with
+/// `AadMismatch`, and streams encrypted to a recipient with `Recipient`.
+pub fn rekey_stream<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    old_key: &[u8],
+    new_key: &[u8],
+) -> Result<Summary, FsCryptoError> {
+    rekey_stream_with_rng(reader, writer, old_key, new_key, &SystemRandom::new())
+}
+
+/// As `rekey_stream`, drawing the new nonce prefix from `rng`.
+pub fn rekey_stream_with_rng<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    old_key: &[u8],
+    new_key: &[u8],
+    rng: &dyn EntropySource,
+) -> Result<Summary, FsCryptoError> {
+    rekey_with(reader, writer, old_key, new_key, None, rng)
+}
+
+/// As `rekey_stream`, for a `new_key` derived from a passphrase with
+/// `kdf`: the new header records the KDF settings and the key's check
+/// value, so decryption can derive the key again and tell a wrong
+/// passphrase from a damaged file.
+pub fn rekey_stream_to_passphrase<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    old_key: &[u8],
+    new_key: &[u8],
+    kdf: PassphraseKdf,
+) -> Result<Summary, FsCryptoError> {
+    rekey_with(
+        reader,
+        writer,
+        old_key,
+        new_key,
+        Some(kdf),
+        &SystemRandom::new(),
+    )
+}
+
+fn rekey_with<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    old_key: &[u8],
+    new_key: &[u8],
+    passphrase: Option<PassphraseKdf>,
+    rng: &dyn EntropySource,
+) -> Result<Summary, FsCryptoError> {
+    let (header, header_bytes) = read_header(reader)?;
+    not_for_recipient(&header)?;
+    let mut decryptor = decryptor_for(&header, old_key, &header_bytes, &[])?;
+
+    let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+    rng.fill(&mut nonce_prefix);
+    let key_check = header.key_check.is_some() || passphrase.is_some();
+    let new_header = FileHeader {
+        nonce_prefix,
+        key_check: key_check.then(|| key_check_value(new_key)),
+        passphrase,
+        ..header.clone()
+    };
+    let new_header_bytes = new_header.to_bytes();
+    let mut encryptor = encryptor_for(&new_header, new_key, &new_header_bytes, &[])?;
+    writer.write_all(&new_header_bytes)?;
+
+    let sealed_size = header.chunk_size as usize + TAG_LEN;
+    let mut summary = Summary {
+        bytes_in: header_bytes.len() as u64,
+        bytes_out: new_header_bytes.len() as u64,
+        chunks: 0,
+    };
+
+    let mut chunk = Zeroizing::new(Vec::with_capacity(sealed_size));
+    let mut next = Zeroizing::new(Vec::with_capacity(sealed_size));
+    read_chunk(reader, &mut chunk, sealed_size)?;
+    loop {
+        summary.bytes_in += chunk.len() as u64;
+        summary.chunks += 1;
+
+        if chunk.len() < TAG_LEN {
+            return Err(ErrorStates::StreamTruncated.into());
+        }
+
+        if chunk.len() == sealed_size {
+            read_chunk(reader, &mut next, sealed_size)?;
+        } else {
+            next.clear();
+        }
+
+        if next.is_empty() {
+            decryptor.open_last(&mut chunk)?;
+            encryptor.seal_last(&mut chunk)?;
+            writer.write_all(&chunk)?;
+            summary.bytes_out += chunk.len() as u64;
+            break;
+        }
+
+        decryptor.open_chunk(&mut chunk)?;
+        encryptor.seal_chunk(&mut chunk)?;
+        writer.write_all(&chunk)?;
+        summary.bytes_out += chunk.len() as u64;
+        core::mem::swap(&mut chunk, &mut next);
+    }
+
+    writer.flush()?;
+    Ok(summary)
+}
+
+/// Progress after one chunk of a stream has authenticated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkProgress {
+    pub index: u32,
+    /// Plaintext bytes in this chunk.
+    pub plaintext_len: usize,
+    /// Plaintext bytes in all chunks verified so far, this one included.
+    pub total_plaintext: u64,
+    pub last: bool,
+}
+
+/// Why verification stopped, and at which chunk.
+#[derive(Debug)]
+pub struct VerifyError {
+    pub chunk_index: u32,
+    pub error: FsCryptoError,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chunk {}: {}", self.chunk_index, self.error)
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Authenticates an rcli stream without producing plaintext. Chunks are
+/// opened in place in one reusable buffer and immediately discarded, so
+/// nothing is written anywhere and memory stays at two sealed chunks.
+pub struct StreamVerifier<R: Read> {
+    reader: R,
+    /* None once the final chunk has been checked */
+    decryptor: Option<StreamDecryptor>,
+    sealed_size: usize,
+    chunk: Vec<u8>,
+    next: Vec<u8>,
+    index: u32,
+    total_plaintext: u64,
+}
+
+impl<R: Read> StreamVerifier<R> {
+    /// Read and check the header; no chunk is touched yet.
+    pub fn new(reader: R, key: &[u8]) -> Result<Self, FsCryptoError> {
+        Self::with_aad(reader, key, &[])
+    }
+
+    /// As `new`, for a stream bound to `aad` (see `decrypt_stream_with_aad`).
+    pub fn with_aad(mut reader: R, key: &[u8], aad: &[u8]) -> Result<Self, FsCryptoError> {
+        let (header, header_bytes) = read_header(&mut reader)?;
+        not_for_recipient(&header)?;
+        let decryptor = decryptor_for(&header, key, &header_bytes, aad)?;
+        let sealed_size = header.chunk_size as usize + TAG_LEN;
+
+        let mut chunk = Vec::with_capacity(sealed_size);
+        read_chunk(&mut reader, &mut chunk, sealed_size)?;
+        Ok(StreamVerifier {
+            reader,
+            decryptor: Some(decryptor),
+            sealed_size,
+            chunk,
+            next: Vec::with_capacity(sealed_size),
+            index: 0,
+            total_plaintext: 0,
+        })
+    }
+
+    /// Verify the next chunk. Returns `Ok(None)` once the final chunk has
+    /// authenticated.
+    pub fn verify_chunk(&mut self) -> Result<Option<ChunkProgress>, VerifyError> {
+        let index = self.index;
+        let fail = |error: FsCryptoError| VerifyError {
+            chunk_index: index,
+            error,
+        };
+
+        if self.decryptor.is_none() {
+            return Ok(None);
+        }
+        if self.chunk.len() < TAG_LEN {
+            return Err(fail(ErrorStates::StreamTruncated.into()));
+        }
+
+        if self.chunk.len() == self.sealed_size {
+            read_chunk(&mut self.reader, &mut self.next, self.sealed_size)
+                .map_err(|e| fail(e.into()))?;
+        } else {
+            self.next.clear();
+        }
+
+        let last = self.next.is_empty();
+        if last {
+            let decryptor = self.decryptor.take().unwrap();
+            decryptor
+                .open_last(&mut self.chunk)
+                .map_err(|e| fail(e.into()))?;
+        } else {
+            let decryptor = self.decryptor.as_mut().unwrap();
+            decryptor
+                .open_chunk(&mut self.chunk)
+                .map_err(|e| fail(e.into()))?;
+        }
+
+        self.total_plaintext += self.chunk.len() as u64;
+        let progress = ChunkProgress {
+            index,
+            plaintext_len: self.chunk.len(),
+            total_plaintext: self.total_plaintext,
+            last,
+        };
+        core::mem::swap(&mut self.chunk, &mut self.next);
+        self.index += 1;
+        Ok(Some(progress))
+    }
+
+    /// Verify every remaining chunk, returning the total plaintext length.
+    pub fn verify_all(mut self) -> Result<u64, VerifyError> {
+        while self.verify_chunk()?.is_some() {}
+        Ok(self.total_plaintext)
+    }
+}
+
+/// Decrypts an rcli stream as it is read, for callers that pull the
+/// plaintext rather than have it pushed into a writer. Nothing from a
+/// chunk is returned until all of it has authenticated, and the end of
+/// the plaintext is only reported once the final chunk has. Stream errors
+/// come back as `io::Error`s wrapping the `FsCryptoError` (see
+/// `stream_error`). A padded stream's padding comes out with the data,
+/// as the last bytes read (see `header().padding`), for the caller to
+/// check and drop. Compressed streams are refused with
+/// `HeaderError::Compressed` (see `compress::DecompressReader`).
+pub struct DecryptReader<R: Read> {
+    reader: R,
+    header: FileHeader,
+    /* None once the final chunk has been opened */
+    decryptor: Option<StreamDecryptor>,
+    sealed_size: usize,
+    /* The opened chunk being read out, from `pos` */
+    chunk: Zeroizing<Vec<u8>>,
+    pos: usize,
+    /* The sealed chunk after it, read ahead to tell whether it is last */
+    next: Vec<u8>,
+}
+
+impl<R: Read> DecryptReader<R> {
+    /// Read and check the header; no chunk is opened yet.
+    pub fn new(reader: R, key: &[u8]) -> Result<Self, FsCryptoError> {
+        Self::open(reader, key, false)
+    }
+
+    /* As `new`, yielding compressed data as it is when `compressed` */
+    pub(crate) fn open(mut reader: R, key: &[u8], compressed: bool) -> Result<Self, FsCryptoError> {
+        let (header, header_bytes) = read_header(&mut reader)?;
+        if header.compression.is_some() && !compressed {
+            return Err(HeaderError::Compressed.into());
+        }
+        not_for_recipient(&header)?;
+        let decryptor = decryptor_for(&header, key, &header_bytes, &[])?;
+        let sealed_size = header.chunk_size as usize + TAG_LEN;
+        let mut next = Vec::with_capacity(sealed_size);
+        read_chunk(&mut reader, &mut next, sealed_size)?;
+        Ok(DecryptReader {
+            reader,
+            header,
+            decryptor: Some(decryptor),
+            sealed_size,
+            chunk: Zeroizing::new(Vec::with_capacity(sealed_size)),
+            pos: 0,
+            next,
+        })
+    }
+
+    /// The stream's header.
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// Move `n` bytes of plaintext on without returning them, failing
+    /// with `UnexpectedEof` if the stream ends first. Whole chunks the
+    /// skip passes over are read past without being opened, so they are
+    /// not authenticated; the chunk it ends in is, as is the last chunk,
+    /// which is always opened.
+    pub fn skip(&mut self, mut n: u64) -> Result<(), FsCryptoError> {
+        let chunk_size = (self.sealed_size - TAG_LEN) as u64;
+        loop {
+            let left = (self.chunk.len() - self.pos) as u64;
+            if n <= left {
+                self.pos += n as usize;
+                return Ok(());
+            }
+            n -= left;
+            self.pos = self.chunk.len();
+            /* `next` can be passed over when the skip goes beyond it and
+             * another chunk follows it */
+            while n >= chunk_size && self.next.len() == self.sealed_size {
+                let Some(decryptor) = self.decryptor.as_mut() else {
+                    break;
+                };
+                self.chunk.clear();
+                self.pos = 0;
+                read_chunk(&mut self.reader, &mut self.chunk, self.sealed_size)?;
+                if self.chunk.is_empty() {
+                    break;
+                }
+                core::mem::swap(&mut *self.chunk, &mut self.next);
+                self.chunk.clear();
+                decryptor.fast_forward(decryptor.chunk_index() + 1)?;
+                n -= chunk_size;
+            }
+            if !self.open_next()? {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+
+    /* Open the next chunk into `chunk`; false once there are none */
+    fn open_next(&mut self) -> Result<bool, FsCryptoError> {
+        let Some(decryptor) = self.decryptor.as_mut() else {
+            return Ok(false);
+        };
+        core::mem::swap(&mut *self.chunk, &mut self.next);
+        self.pos = 0;
+        if self.chunk.len() < TAG_LEN {
+            return Err(ErrorStates::StreamTruncated.into());
+        }
+        if self.chunk.len() == self.sealed_size {
+            read_chunk(&mut self.reader, &mut self.next, self.sealed_size)?;
+        } else {
+            self.next.clear();
+        }
+        if self.next.is_empty() {
+            let decryptor = self.decryptor.take().unwrap();
+            decryptor.open_last(&mut self.chunk)?;
+        } else {
+            decryptor.open_chunk(&mut self.chunk)?;
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if !self.open_next().map_err(io::Error::other)? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// The `FsCryptoError` a `DecryptReader` error carries, or the I/O error
+/// itself as one.
+pub fn stream_error(e: io::Error) -> FsCryptoError {
+    if !e.get_ref().is_some_and(|inner| inner.is::<FsCryptoError>()) {
+        return FsCryptoError::Io(e);
+    }
+    let inner = e.into_inner().expect("checked above");
+    *inner.downcast::<FsCryptoError>().expect("checked above")
+}
+
+/// Encrypt the file at `path_in` into a new file at `path_out`.
+pub fn encrypt_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    path_in: P,
+    path_out: Q,
+    key: &[u8],
+    opts: &EncryptOptions,
+) -> Result<Summary, FsCryptoError> {
+    let mut reader = BufReader::new(File::open(path_in)?);
+    let mut writer = BufWriter::new(File::create(path_out)?);
+    encrypt_stream(&mut reader, &mut writer, key, opts)
+}
+
+/// Decrypt the rcli file at `path_in` into a new file at `path_out`.
+pub fn decrypt_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    path_in: P,
+    path_out: Q,
+    key: &[u8],
+) -> Result<Summary, FsCryptoError> {
+    let mut reader = BufReader::new(File::open(path_in)?);
+    /* Check the header, and the key against it, before creating (and
+     * truncating) the output */
+    let (header, header_bytes) = read_header(&mut reader)?;
+    check_key(&header, key)?;
+
+    let mut reader = io::Cursor::new(header_bytes).chain(reader);
+    let mut writer = BufWriter::new(File::create(path_out)?);
+    decrypt_stream(&mut reader, &mut writer, key)
+}
+
+/* Removes the temporary file on drop unless it has been persisted */
+struct TempFile {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/* Modes for temporary files on unix: readable by the owner alone while
+ * they hold output that may be plaintext, or whatever a new file gets */
+const OWNER_ONLY: u32 = 0o600;
+const NEW_FILE: u32 = 0o666;
+
+/* A new file beside `target`, created with `mode` (less the umask) where
+ * there are modes */
+fn create_sibling_temp(target: &Path, mode: u32) -> io::Result<(TempFile, File)> {
+    let dir = match target.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+
+    loop {
+        let mut suffix = [0u8; 6];
+        generate_nonce(&mut suffix);
+        let suffix: String = suffix.iter().map(|b| format!("{:02x}", b)).collect();
+
+        let path = dir.join(format!(".{}.rcli-tmp-{}", name, suffix));
+        let mut options = File::options();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+        #[cfg(not(unix))]
+        let _ = mode;
+        match options.open(&path) {
+            Ok(f) => {
+                return Ok((
+                    TempFile {
+                        path,
+                        persisted: false,
+                    },
+                    f,
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Rewrite the file at `path` through `op` without ever leaving it partially
+/// written: output goes to a sibling temporary file, which is created
+/// readable by its owner alone and given the original's permissions (and
+/// owner, where allowed) before anything is written to it, then fsynced
+/// and renamed over `path` only if `op` succeeds.
+/// On any error the temporary file is removed and `path` is left untouched.
+pub fn rewrite_atomic<P, F>(path: P, op: F) -> Result<Summary, FsCryptoError>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut BufReader<File>, &mut BufWriter<File>) -> Result<Summary, FsCryptoError>,
+{
+    let path = path.as_ref();
+    let original = File::open(path)?;
+    let metadata = original.metadata()?;
+    let mut reader = BufReader::new(original);
+
+    let (mut temp, temp_file) = create_sibling_temp(path, OWNER_ONLY)?;
+    copy_metadata(&temp_file, &metadata)?;
+    let mut writer = BufWriter::new(temp_file);
+
+    let summary = op(&mut reader, &mut writer)?;
+
+    let temp_file = writer.into_inner().map_err(|e| e.into_error())?;
+    temp_file.sync_all()?;
+    restore_mode(&temp_file, &metadata)?;
+    drop(temp_file);
+
+    std::fs::rename(&temp.path, path)?;
+    temp.persisted = true;
+    sync_parent(path);
+
+    Ok(summary)
+}
+
+/* Done before the first write, so the output is never open to more
+ * people than the original is. Owner first: on unix chown clears
+ * setuid/setgid bits, which the permissions then put back. Changing the
+ * owner usually needs privileges the caller does not have, so a failure
+ * there is not an error; the file then belongs to whoever ran us, as any
+ * new file would. */
+fn copy_metadata(file: &File, metadata: &std::fs::Metadata) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let _ = std::os::unix::fs::fchown(file, Some(metadata.uid()), Some(metadata.gid()));
+    }
+    file.set_permissions(metadata.permissions())
+}
+
+/* Writing to a file clears its setuid/setgid bits on unix, so the mode
+ * is set once more when the output is complete */
+fn restore_mode(file: &File, metadata: &std::fs::Metadata) -> io::Result<()> {
+    match file.metadata()?.permissions() == metadata.permissions() {
+        true => Ok(()),
+        false => file.set_permissions(metadata.permissions()),
+    }
+}
+
+/* Persist a rename or link into the directory holding `path`; best
+ * effort, not all platforms allow it */
+fn sync_parent(path: &Path) {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// Run `op` from the file at `path_in` into the file at `path_out`, leaving
+/// `path_in` untouched. As with [`rewrite_atomic`], output goes to a
+/// temporary file beside `path_out` and only appears there once `op` has
+/// succeeded, with `path_in`'s permissions (and owner, where allowed).
+///
+/// Unless `overwrite`, an existing `path_out` is an `AlreadyExists` error,
+/// checked before `op` runs and again (atomically) when the result is put
+/// in place. Paths naming the same file, through symlinks or hard links,
+/// are an `InvalidInput` error.
+pub fn write_new<P, Q, F>(
+    path_in: P,
+    path_out: Q,
+    overwrite: bool,
+    op: F,
+) -> Result<Summary, FsCryptoError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnOnce(&mut BufReader<File>, &mut BufWriter<File>) -> Result<Summary, FsCryptoError>,
+{
+    let (path_in, path_out) = (path_in.as_ref(), path_out.as_ref());
+    let input = File::open(path_in)?;
+    let metadata = input.metadata()?;
+    match std::fs::metadata(path_out) {
+        Ok(_) if same_file(path_in, path_out)? => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "input and output are the same file",
+            )
+            .into());
+        }
+        Ok(_) if !overwrite => return Err(already_exists(path_out).into()),
+        _ => {}
+    }
+    let mut reader = BufReader::new(input);
+
+    let (temp, temp_file) = create_sibling_temp(path_out, OWNER_ONLY)?;
+    copy_metadata(&temp_file, &metadata)?;
+    let mut writer = BufWriter::new(temp_file);
+
+    let summary = op(&mut reader, &mut writer)?;
+
+    let temp_file = writer.into_inner().map_err(|e| e.into_error())?;
+    temp_file.sync_all()?;
+    restore_mode(&temp_file, &metadata)?;
+    drop(temp_file);
+    persist(temp, path_out, overwrite)?;
+    Ok(summary)
+}
+
+/// As [`write_new`], for output that does not come from one input file,
+/// such as an archive of a directory: `op` only writes, and the result
+/// gets the permissions any new file would.
+pub fn write_output<Q, F>(path_out: Q, overwrite: bool, op: F) -> Result<Summary, FsCryptoError>
+where
+    Q: AsRef<Path>,
+    F: FnOnce(&mut BufWriter<File>) -> Result<Summary, FsCryptoError>,
+{
+    let path_out = path_out.as_ref();
+    if !overwrite && std::fs::symlink_metadata(path_out).is_ok() {
+        return Err(already_exists(path_out).into());
+    }
+    let (temp, temp_file) = create_sibling_temp(path_out, NEW_FILE)?;
+    let mut writer = BufWriter::new(temp_file);
+    let summary = op(&mut writer)?;
+    let temp_file = writer.into_inner().map_err(|e| e.into_error())?;
+    temp_file.sync_all()?;
+    drop(temp_file);
+    persist(temp, path_out, overwrite)?;
+    Ok(summary)
+}
+
+/// As [`write_new`], writing the output as parts of at most `part_size`
+/// bytes named `path_out` plus ".000", ".001" and so on (see `split`).
+/// Each part appears only once all of them are written, with its total
+/// recorded; returns the summary and the number of parts. Unless
+/// `overwrite`, any existing part is an `AlreadyExists` error, and the
+/// parts are then left out altogether.
+pub fn write_parts<P, Q, F>(
+    path_in: P,
+    path_out: Q,
+    part_size: u64,
+    overwrite: bool,
+    op: F,
+) -> Result<(Summary, u32), FsCryptoError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnOnce(&mut BufReader<File>, &mut dyn Write) -> Result<Summary, FsCryptoError>,
+{
+    let (path_in, path_out) = (path_in.as_ref(), path_out.as_ref());
+    let input = File::open(path_in)?;
+    let metadata = input.metadata()?;
+    let first = split::part_path(path_out, 0);
+    if !overwrite && std::fs::symlink_metadata(&first).is_ok() {
+        return Err(already_exists(&first).into());
+    }
+    let mut reader = BufReader::new(input);
+
+    let temps = std::cell::RefCell::new(Vec::new());
+    let mut writer = SplitWriter::new(part_size, |index| {
+        /* Parts are reopened to record the total, so they stay writable
+         * and readable by their owner alone until then */
+        let (temp, temp_file) =
+            create_sibling_temp(&split::part_path(path_out, index), OWNER_ONLY)?;
+        temps.borrow_mut().push(temp);
+        Ok(BufWriter::new(temp_file))
+    })?;
+    let summary = op(&mut reader, &mut writer)?;
+    let total = writer.finish()?;
+
+    let paths: Vec<PathBuf> = (0..total).map(|i| split::part_path(path_out, i)).collect();
+    if !overwrite {
+        if let Some(taken) = paths.iter().find(|p| std::fs::symlink_metadata(p).is_ok()) {
+            return Err(already_exists(taken).into());
+        }
+    }
+    for (placed, (temp, path)) in temps.into_inner().into_iter().zip(&paths).enumerate() {
+        if let Err(e) = finish_part(temp, path, total, &metadata, overwrite) {
+            /* All the parts or none; one may have appeared since the check */
+            for path in &paths[..placed] {
+                let _ = std::fs::remove_file(path);
+            }
+            return Err(e.into());
+        }
+    }
+    Ok((summary, total))
+}
+
+/* Record the total in a written part, then put it in place at `path` */
+fn finish_part(
+    temp: TempFile,
+    path: &Path,
+    total: u32,
+    metadata: &std::fs::Metadata,
+    overwrite: bool,
+) -> io::Result<()> {
+    let mut part = File::options().write(true).open(&temp.path)?;
+    split::set_total(&mut part, total)?;
+    part.sync_all()?;
+    copy_metadata(&part, metadata)?;
+    drop(part);
+    persist(temp, path, overwrite)
+}
+
+/* Put a finished temporary file in place at `path_out` */
+fn persist(mut temp: TempFile, path_out: &Path, overwrite: bool) -> io::Result<()> {
+    if overwrite {
+        std::fs::rename(&temp.path, path_out)?;
+        temp.persisted = true;
+    } else {
+        /* A hard link never replaces an existing file; the temporary name
+         * is then removed on drop */
+        std::fs::hard_link(&temp.path, path_out).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => already_exists(path_out),
+            _ => e,
+        })?;
+    }
+    sync_parent(path_out);
+    Ok(())
+}
+
+fn already_exists(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", path.display()),
+    )
+}
+
+/* Both paths exist; symlinks are followed */
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let (a, b) = (std::fs::metadata(a)?, std::fs::metadata(b)?);
+    Ok(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> io::Result<bool> {
+    Ok(std::fs::canonicalize(a)? == std::fs::canonicalize(b)?)
+}
+
+/* Bytes of random data written per call while shredding */
+const SHRED_BLOCK_LEN: u64 = 64 * 1024;
+
+/// Overwrite the regular file at `path` with random bytes `passes` times
+/// (at least once), fsyncing after each pass, then truncate and remove
+/// it. A symlink is an `InvalidInput` error rather than followed.
+///
+/// This only reaches the blocks the file occupies now. SSDs remap writes,
+/// copy-on-write and journaling filesystems write new data elsewhere, and
+/// snapshots and backups keep their own copies, so the old contents may
+/// well survive.
+pub fn shred<P: AsRef<Path>>(path: P, passes: u32) -> io::Result<()> {
+    shred_with_rng(path, passes, &SystemRandom::new())
+}
+
+/// As `shred`, drawing the overwriting bytes from `rng`.
+pub fn shred_with_rng<P: AsRef<Path>>(
+    path: P,
+    passes: u32,
+    rng: &dyn EntropySource,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    if !std::fs::symlink_metadata(path)?.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a regular file", path.display()),
+        ));
+    }
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let mut block = vec![0u8; len.min(SHRED_BLOCK_LEN) as usize];
+    for _ in 0..passes.max(1) {
+        file.seek(SeekFrom::Start(0))?;
+        let mut left = len;
+        while left > 0 {
+            let n = left.min(SHRED_BLOCK_LEN) as usize;
+            rng.fill(&mut block[..n]);
+            file.write_all(&block[..n])?;
+            left -= n as u64;
+        }
+        file.sync_all()?;
+    }
+    file.set_len(0)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::remove_file(path)?;
+    sync_parent(path);
+    Ok(())
+}
+
+/// Encrypt the file at `path` in place, atomically (see [`rewrite_atomic`]).
+pub fn encrypt_file_atomic<P: AsRef<Path>>(
+    path: P,
+    key: &[u8],
+    opts: &EncryptOptions,
+) -> Result<Summary, FsCryptoError> {
+    rewrite_atomic(path, |reader, writer| {
+        encrypt_stream(reader, writer, key, opts)
+    })
+}
+
+/// Decrypt the rcli file at `path` in place, atomically (see [`rewrite_atomic`]).
+pub fn decrypt_file_atomic<P: AsRef<Path>>(path: P, key: &[u8]) -> Result<Summary, FsCryptoError> {
+    rewrite_atomic(path, |reader, writer| decrypt_stream(reader, writer, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::MIN_CHUNK_SIZE;
+    use crate::testing::TestRng;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    fn round_trip(len: usize) {
+        let dir = tempfile::tempdir().unwrap();
+        let (plain, enc, dec) = (
+            dir.path().join("plain"),
+            dir.path().join("plain.enc"),
+            dir.path().join("plain.dec"),
+        );
+
+        let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&plain, &data).unwrap();
+
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            ..EncryptOptions::default()
+        };
+        let sealed = encrypt_file(&plain, &enc, &KEY, &opts).unwrap();
+        assert_eq!(sealed.bytes_in, len as u64);
+        assert_eq!(sealed.bytes_out, std::fs::metadata(&enc).unwrap().len());
+
+        let opened = decrypt_file(&enc, &dec, &KEY).unwrap();
+        assert_eq!(opened.bytes_out, len as u64);
+        assert_eq!(opened.chunks, sealed.chunks);
+        assert_eq!(std::fs::read(&dec).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        round_trip(0);
+    }
+
+    #[test]
+    fn round_trip_one_byte() {
+        round_trip(1);
+    }
+
+    #[test]
+    fn round_trip_multiple_chunks() {
+        round_trip(MIN_CHUNK_SIZE as usize * 3);
+        round_trip(MIN_CHUNK_SIZE as usize * 3 + 17);
+    }
+
+    // TestRng::new(1); cross-checked with Python cryptography's ChaCha20Poly1305
+    #[test]
+    fn seeded_stream_matches_vector() {
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            ..EncryptOptions::default()
+        };
+        let mut out = Vec::new();
+        encrypt_stream_with_rng(
+            &mut &b"Hello World!"[..],
+            &mut out,
+            &KEY,
+            &opts,
+            &TestRng::new(1),
+        )
+        .unwrap();
+        let hex: String = out.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(
+            hex,
+            concat!(
+                "52434c49010100000400c5d30a7ce1ec110000",
+                "801215af21d8ebc41fbe2afe950509ac46f05067dc36d8546de9b87a"
+            )
+        );
+
+        let mut plain = Vec::new();
+        decrypt_stream(&mut &out[..], &mut plain, &KEY).unwrap();
+        assert_eq!(plain, b"Hello World!");
+    }
+
+    /* The prefix TestRng::new(1) draws above, given directly */
+    #[test]
+    fn given_prefix_is_the_drawn_one() {
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            ..EncryptOptions::default()
+        };
+        let (mut drawn, mut given) = (Vec::new(), Vec::new());
+        let plain = b"Hello World!";
+        encrypt_stream_with_rng(&mut &plain[..], &mut drawn, &KEY, &opts, &TestRng::new(1))
+            .unwrap();
+        let prefix = [0xc5, 0xd3, 0x0a, 0x7c, 0xe1, 0xec, 0x11];
+        encrypt_stream_with_nonce_prefix(&mut &plain[..], &mut given, &KEY, &opts, prefix).unwrap();
+        assert_eq!(given, drawn);
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let (plain, enc) = (dir.path().join("plain"), dir.path().join("plain.enc"));
+        std::fs::write(&plain, b"Hello World!").unwrap();
+
+        encrypt_file(&plain, &enc, &KEY, &EncryptOptions::default()).unwrap();
+        let result = decrypt_file(&enc, dir.path().join("out"), &[0x24; 32]);
+        assert!(matches!(
+            result,
+            Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed))
+        ));
+    }
+
+    #[test]
+    fn refuses_file_without_magic() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("plain");
+        std::fs::write(&plain, b"This is a secret, don't tell anyone!").unwrap();
+
+        let out = dir.path().join("out");
+        let result = decrypt_file(&plain, &out, &KEY);
+        assert!(matches!(
+            result,
+            Err(FsCryptoError::Header(HeaderError::BadMagic))
+        ));
+        assert!(!out.exists());
+    }
+
+    /* Passes writes through until `remaining` bytes, then fails */
+    struct FailAfter<W> {
+        inner: W,
+        remaining: usize,
+    }
+
+    impl<W: Write> Write for FailAfter<W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.remaining == 0 {
+                return Err(io::Error::other("injected failure"));
+            }
+            let n = buf.len().min(self.remaining);
+            self.remaining -= n;
+            self.inner.write(&buf[..n])
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    fn only_entry(dir: &Path) -> std::path::PathBuf {
+        let entries: Vec<_> = std::fs::read_dir(dir).unwrap().collect();
+        assert_eq!(entries.len(), 1, "temporary files left behind");
+        entries.into_iter().next().unwrap().unwrap().path()
+    }
+
+    #[test]
+    fn atomic_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain");
+        std::fs::write(&path, b"Hello World!").unwrap();
+
+        encrypt_file_atomic(&path, &KEY, &EncryptOptions::default()).unwrap();
+        assert!(FileHeader::has_magic(&std::fs::read(&path).unwrap()));
+
+        decrypt_file_atomic(&path, &KEY).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"Hello World!");
+        assert_eq!(only_entry(dir.path()), path);
+    }
+
+    #[test]
+    fn write_new_leaves_input_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let (plain, enc, dec) = (
+            dir.path().join("plain"),
+            dir.path().join("plain.enc"),
+            dir.path().join("plain.dec"),
+        );
+        std::fs::write(&plain, b"keep me").unwrap();
+        let opts = EncryptOptions::default();
+
+        write_new(&plain, &enc, false, |r, w| {
+            encrypt_stream(r, w, &KEY, &opts)
+        })
+        .unwrap();
+        assert_eq!(std::fs::read(&plain).unwrap(), b"keep me");
+        write_new(&enc, &dec, false, |r, w| decrypt_stream(r, w, &KEY)).unwrap();
+        assert_eq!(std::fs::read(&dec).unwrap(), b"keep me");
+
+        /* No clobbering unless asked */
+        let err = write_new(&enc, &dec, false, |r, w| decrypt_stream(r, w, &KEY)).unwrap_err();
+        assert!(
+            matches!(err, FsCryptoError::Io(ref e) if e.kind() == io::ErrorKind::AlreadyExists)
+        );
+        std::fs::write(&dec, b"stale").unwrap();
+        write_new(&enc, &dec, true, |r, w| decrypt_stream(r, w, &KEY)).unwrap();
+        assert_eq!(std::fs::read(&dec).unwrap(), b"keep me");
+
+        /* A failed op leaves neither output nor temporary file */
+        let bad = dir.path().join("bad");
+        assert!(write_new(&plain, &bad, false, |r, w| decrypt_stream(r, w, &KEY)).is_err());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn write_new_refuses_same_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain");
+        std::fs::write(&path, b"only copy").unwrap();
+        let opts = EncryptOptions::default();
+        let same = |out: &Path| {
+            let err =
+                write_new(&path, out, true, |r, w| encrypt_stream(r, w, &KEY, &opts)).unwrap_err();
+            assert!(
+                matches!(err, FsCryptoError::Io(ref e) if e.kind() == io::ErrorKind::InvalidInput),
+                "{}",
+                err
+            );
+        };
+        same(&path);
+        same(&dir.path().join(".").join("plain"));
+        #[cfg(unix)]
+        {
+            let link = dir.path().join("link");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            same(&link);
+            let hard = dir.path().join("hard");
+            std::fs::hard_link(&path, &hard).unwrap();
+            same(&hard);
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), b"only copy");
+    }
+
+    #[test]
+    fn atomic_failure_leaves_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain");
+        let data = vec![0x5a; MIN_CHUNK_SIZE as usize * 4];
+        std::fs::write(&path, &data).unwrap();
+
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            ..EncryptOptions::default()
+        };
+        let result = rewrite_atomic(&path, |reader, writer| {
+            let mut failing = FailAfter {
+                inner: writer,
+                remaining: MIN_CHUNK_SIZE as usize * 2,
+            };
+            encrypt_stream(reader, &mut failing, &KEY, &opts)
+        });
+
+        assert!(matches!(result, Err(FsCryptoError::Io(_))));
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        assert_eq!(only_entry(dir.path()), path);
+    }
+
+    #[test]
+    fn atomic_wrong_key_leaves_ciphertext() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain");
+        std::fs::write(&path, b"Hello World!").unwrap();
+
+        encrypt_file_atomic(&path, &KEY, &EncryptOptions::default()).unwrap();
+        let ciphertext = std::fs::read(&path).unwrap();
+
+        assert!(decrypt_file_atomic(&path, &[0x24; 32]).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), ciphertext);
+        assert_eq!(only_entry(dir.path()), path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn atomic_preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain");
+        std::fs::write(&path, b"Hello World!").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+        encrypt_file_atomic(&path, &KEY, &EncryptOptions::default()).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    /* The mode of the one temporary file in `dir`, while it is written */
+    #[cfg(unix)]
+    fn temp_mode(dir: &Path) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        let temps: Vec<u32> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_name().to_string_lossy().contains(".rcli-tmp-"))
+            .map(|entry| entry.metadata().unwrap().permissions().mode() & 0o777)
+            .collect();
+        assert_eq!(temps.len(), 1);
+        temps[0]
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_is_never_more_open_than_the_original() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode_of = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        for original in [0o600, 0o640, 0o400] {
+            let dir = tempfile::tempdir().unwrap();
+            let (path, out) = (dir.path().join("secret"), dir.path().join("out"));
+            std::fs::write(&path, b"Hello World!").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(original)).unwrap();
+            let copy = |reader: &mut BufReader<File>, writer: &mut dyn Write| {
+                io::copy(reader, writer)?;
+                Ok(Summary::default())
+            };
+
+            /* In place and to a new file, the final mode is there from the
+             * first write */
+            let mut during = 0;
+            rewrite_atomic(&path, |reader, writer| {
+                during = temp_mode(dir.path());
+                copy(reader, writer)
+            })
+            .unwrap();
+            assert_eq!((during, mode_of(&path)), (original, original));
+
+            write_new(&path, &out, false, |reader, writer| {
+                during = temp_mode(dir.path());
+                copy(reader, writer)
+            })
+            .unwrap();
+            assert_eq!((during, mode_of(&out)), (original, original));
+
+            /* Parts stay the owner's alone until they are finished */
+            write_parts(&path, &out, 1 << 20, false, |reader, writer| {
+                /* A part is only created when written to */
+                let summary = copy(reader, writer);
+                during = temp_mode(dir.path());
+                summary
+            })
+            .unwrap();
+            assert_eq!(during, 0o600);
+            assert_eq!(mode_of(&split::part_path(&out, 0)), original);
+        }
+    }
+
+    #[test]
+    fn write_new_failure_leaves_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("plain"), dir.path().join("sealed"));
+        let data = vec![0xa5; MIN_CHUNK_SIZE as usize * 4];
+        std::fs::write(&input, &data).unwrap();
+
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            ..EncryptOptions::default()
+        };
+        let result = write_new(&input, &output, false, |reader, writer| {
+            let mut failing = FailAfter {
+                inner: writer,
+                remaining: MIN_CHUNK_SIZE as usize,
+            };
+            encrypt_stream(reader, &mut failing, &KEY, &opts)
+        });
+
+        assert!(matches!(result, Err(FsCryptoError::Io(_))));
+        assert_eq!(std::fs::read(&input).unwrap(), data);
+        assert_eq!(only_entry(dir.path()), input);
+    }
+
+    #[test]
+    fn write_output_appears_only_when_done() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("sealed");
+        let result = write_output(&output, false, |writer| {
+            writer.write_all(b"half")?;
+            Err(io::Error::other("stopped").into())
+        });
+        assert!(matches!(result, Err(FsCryptoError::Io(_))));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let written = |overwrite, text: &'static [u8]| {
+            write_output(&output, overwrite, |writer| {
+                writer.write_all(text)?;
+                Ok(Summary::default())
+            })
+        };
+        written(false, b"first").unwrap();
+        assert!(matches!(
+            written(false, b"second"),
+            Err(FsCryptoError::Io(_))
+        ));
+        written(true, b"third").unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"third");
+        assert_eq!(only_entry(dir.path()), output);
+    }
+
+    #[test]
+    fn write_parts_round_trips_and_appears_only_when_done() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("plain");
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * 7) as u8).collect();
+        std::fs::write(&plain, &data).unwrap();
+        let sealed = dir.path().join("plain.enc");
+        let opts = EncryptOptions {
+            chunk_size: 1024,
+            ..EncryptOptions::default()
+        };
+
+        let (_, total) = write_parts(&plain, &sealed, 4096, false, |r, mut w| {
+            encrypt_stream(r, &mut w, &KEY, &opts)
+        })
+        .unwrap();
+        let parts: Vec<Vec<u8>> = (0..total)
+            .map(|index| std::fs::read(split::part_path(&sealed, index)).unwrap())
+            .collect();
+        let last = parts.len() - 1;
+        assert!(last > 0);
+        assert!(parts[..last].iter().all(|part| part.len() == 4096));
+        assert!(parts[last].len() <= 4096);
+        let mut joined = Vec::new();
+        for part in &parts {
+            let header = split::PartHeader::parse(part).unwrap();
+            assert_eq!(header.total, total);
+            joined.extend_from_slice(&part[split::PART_HEADER_LEN..]);
+        }
+        let mut out = Vec::new();
+        decrypt_stream(&mut joined.as_slice(), &mut out, &KEY).unwrap();
+        assert_eq!(out, data);
+
+        /* An existing part 0 is not replaced unless asked */
+        let again = write_parts(&plain, &sealed, 4096, false, |r, mut w| {
+            encrypt_stream(r, &mut w, &KEY, &opts)
+        });
+        assert!(
+            matches!(again, Err(FsCryptoError::Io(ref e)) if e.kind() == io::ErrorKind::AlreadyExists)
+        );
+
+        /* A failed op leaves no part and no temporary file behind */
+        let failed = write_parts(&plain, dir.path().join("bad"), 4096, false, |r, mut w| {
+            decrypt_stream(r, &mut w, &KEY)
+        });
+        assert!(failed.is_err());
+        assert_eq!(
+            std::fs::read_dir(dir.path()).unwrap().count(),
+            1 + total as usize
+        );
+
+        /* Nor does a later part that exists already */
+        let out = dir.path().join("out");
+        let taken = split::part_path(&out, 1);
+        assert!(taken.ends_with("out.001"));
+        std::fs::write(&taken, b"not ours").unwrap();
+        let again = write_parts(&plain, &out, 4096, false, |r, mut w| {
+            encrypt_stream(r, &mut w, &KEY, &opts)
+        });
+        assert!(
+            matches!(again, Err(FsCryptoError::Io(ref e)) if e.kind() == io::ErrorKind::AlreadyExists)
+        );
+        assert!(!split::part_path(&out, 0).exists());
+        assert_eq!(std::fs::read(&taken).unwrap(), b"not ours");
+        assert_eq!(
+            std::fs::read_dir(dir.path()).unwrap().count(),
+            2 + total as usize
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn atomic_preserves_owner_where_allowed() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain");
+        std::fs::write(&path, b"Hello World!").unwrap();
+        /* Only possible with privileges; otherwise the owner is already
+         * the caller and there is nothing to preserve */
+        let _ = std::os::unix::fs::chown(&path, Some(1234), Some(5678));
+        let before = std::fs::metadata(&path).unwrap();
+
+        /* The owner is the original's before the first write, not after */
+        let mut during = None;
+        rewrite_atomic(&path, |reader, writer| {
+            let temp = std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .find(|p| p.to_string_lossy().contains(".rcli-tmp-"))
+                .unwrap();
+            let meta = std::fs::metadata(temp).unwrap();
+            during = Some((meta.uid(), meta.gid(), meta.mode() & 0o777));
+            encrypt_stream(reader, writer, &KEY, &EncryptOptions::default())
+        })
+        .unwrap();
+        let after = std::fs::metadata(&path).unwrap();
+        assert_eq!((after.uid(), after.gid()), (before.uid(), before.gid()));
+        assert_eq!(
+            during,
+            Some((before.uid(), before.gid(), before.mode() & 0o777))
+        );
+        assert_ne!(after.ino(), before.ino());
+    }
+
+    fn sealed_stream(len: usize) -> Vec<u8> {
+        let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            ..EncryptOptions::default()
+        };
+        let mut out = Vec::new();
+        encrypt_stream(&mut &data[..], &mut out, &KEY, &opts).unwrap();
+        out
+    }
+
+    fn verify(data: &[u8]) -> Result<u64, VerifyError> {
+        StreamVerifier::new(data, &KEY)
+            .map_err(|error| VerifyError {
+                chunk_index: 0,
+                error,
+            })?
+            .verify_all()
+    }
+
+    #[test]
+    fn verifier_accepts_valid_stream() {
+        let len = MIN_CHUNK_SIZE as usize * 3 + 17;
+        let sealed = sealed_stream(len);
+
+        let mut verifier = StreamVerifier::new(&sealed[..], &KEY).unwrap();
+        let mut seen = Vec::new();
+        while let Some(progress) = verifier.verify_chunk().unwrap() {
+            seen.push(progress);
+        }
+        assert_eq!(seen.len(), 4);
+        assert!(seen.iter().enumerate().all(|(i, p)| p.index == i as u32));
+        assert_eq!(seen[3].plaintext_len, 17);
+        assert_eq!(seen[3].total_plaintext, len as u64);
+        assert!(seen[3].last && !seen[2].last);
+        assert_eq!(verifier.verify_chunk().unwrap(), None);
+
+        assert_eq!(verify(&sealed).unwrap(), len as u64);
+        assert_eq!(verify(&sealed_stream(0)).unwrap(), 0);
+        assert_eq!(
+            verify(&sealed_stream(MIN_CHUNK_SIZE as usize)).unwrap(),
+            MIN_CHUNK_SIZE as u64
+        );
+    }
+
+    #[test]
+    fn verifier_reports_corrupted_chunk() {
+        let sealed_size = MIN_CHUNK_SIZE as usize + TAG_LEN;
+        let sealed = sealed_stream(MIN_CHUNK_SIZE as usize * 4);
+
+        for n in 0..4 {
+            let mut corrupted = sealed.clone();
+            corrupted[HEADER_FIXED_LEN + n * sealed_size + 5] ^= 1;
+            let err = verify(&corrupted).unwrap_err();
+            assert_eq!(err.chunk_index, n as u32);
+            assert!(matches!(
+                err.error,
+                FsCryptoError::Crypto(ErrorStates::DecryptionFailed)
+            ));
+        }
+
+        let err = StreamVerifier::new(&sealed[..], &[0x24; 32])
+            .unwrap()
+            .verify_all()
+            .unwrap_err();
+        assert_eq!(err.chunk_index, 0);
+    }
+
+    #[test]
+    fn verifier_reports_truncation() {
+        let sealed_size = MIN_CHUNK_SIZE as usize + TAG_LEN;
+        let sealed = sealed_stream(MIN_CHUNK_SIZE as usize * 3 + 17);
+
+        /* Ends cleanly after chunk 1, which was not sealed as last */
+        let err = verify(&sealed[..HEADER_FIXED_LEN + 2 * sealed_size]).unwrap_err();
+        assert_eq!(err.chunk_index, 1);
+        assert!(matches!(
+            err.error,
+            FsCryptoError::Crypto(ErrorStates::StreamTruncated)
+        ));
+
+        /* Ends inside chunk 2's tag */
+        let err = verify(&sealed[..HEADER_FIXED_LEN + 2 * sealed_size + 10]).unwrap_err();
+        assert_eq!(err.chunk_index, 2);
+        assert!(matches!(
+            err.error,
+            FsCryptoError::Crypto(ErrorStates::StreamTruncated)
+        ));
+
+        assert!(matches!(
+            StreamVerifier::new(&sealed[..10], &KEY),
+            Err(FsCryptoError::Header(HeaderError::Truncated))
+        ));
+    }
+
+    #[test]
+    fn decrypt_reader_yields_authenticated_plaintext() {
+        let sealed_size = MIN_CHUNK_SIZE as usize + TAG_LEN;
+        for len in [
+            0,
+            1,
+            MIN_CHUNK_SIZE as usize,
+            MIN_CHUNK_SIZE as usize * 3 + 17,
+        ] {
+            let sealed = sealed_stream(len);
+            let mut reader = DecryptReader::new(&sealed[..], &KEY).unwrap();
+            let mut out = Vec::new();
+            /* small reads straddle chunk boundaries */
+            let mut buf = [0u8; 100];
+            loop {
+                let n = reader.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                out.extend_from_slice(&buf[..n]);
+            }
+            let expected: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            assert_eq!(out, expected, "length {}", len);
+        }
+
+        let sealed = sealed_stream(MIN_CHUNK_SIZE as usize * 3);
+        let mut corrupted = sealed.clone();
+        corrupted[HEADER_FIXED_LEN + sealed_size + 5] ^= 1;
+        let mut reader = DecryptReader::new(&corrupted[..], &KEY).unwrap();
+        let mut out = Vec::new();
+        let err = stream_error(reader.read_to_end(&mut out).unwrap_err());
+        assert!(matches!(
+            err,
+            FsCryptoError::Crypto(ErrorStates::DecryptionFailed)
+        ));
+        /* only the chunk before the damage came out */
+        assert_eq!(out.len(), MIN_CHUNK_SIZE as usize);
+
+        let mut reader =
+            DecryptReader::new(&sealed[..HEADER_FIXED_LEN + 2 * sealed_size], &KEY).unwrap();
+        let err = stream_error(reader.read_to_end(&mut Vec::new()).unwrap_err());
+        assert!(matches!(
+            err,
+            FsCryptoError::Crypto(ErrorStates::StreamTruncated)
+        ));
+    }
+
+    #[test]
+    fn decrypt_reader_skips_whole_chunks_unopened() {
+        let chunk = MIN_CHUNK_SIZE as usize;
+        let sealed_size = chunk + TAG_LEN;
+        let len = chunk * 5 + 17;
+        let sealed = sealed_stream(len);
+        let at = |i: usize| (i % 251) as u8;
+        for skip in [0, 1, chunk, chunk * 2 + 3, chunk * 5, len] {
+            let mut reader = DecryptReader::new(&sealed[..], &KEY).unwrap();
+            reader.read_exact(&mut [0u8; 10]).unwrap();
+            reader.skip(skip as u64 - skip.min(10) as u64).unwrap();
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).unwrap();
+            let from = skip.max(10);
+            let expected: Vec<u8> = (from..len).map(at).collect();
+            assert_eq!(rest, expected, "skip {}", skip);
+        }
+        let mut reader = DecryptReader::new(&sealed[..], &KEY).unwrap();
+        let err = reader.skip(len as u64 + 1).unwrap_err();
+        assert!(matches!(err, FsCryptoError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+
+        /* damage to a chunk skipped over goes unseen, but not to the one
+         * the skip lands in or to the last */
+        let mut corrupted = sealed.clone();
+        corrupted[HEADER_FIXED_LEN + sealed_size + 5] ^= 1;
+        let mut reader = DecryptReader::new(&corrupted[..], &KEY).unwrap();
+        reader.skip(chunk as u64 * 3).unwrap();
+        reader.read_to_end(&mut Vec::new()).unwrap();
+        let mut reader = DecryptReader::new(&corrupted[..], &KEY).unwrap();
+        assert!(reader.skip(chunk as u64 + 1).is_err());
+        let mut corrupted = sealed.clone();
+        let last = corrupted.len() - 3;
+        corrupted[last] ^= 1;
+        let mut reader = DecryptReader::new(&corrupted[..], &KEY).unwrap();
+        assert!(reader.skip(chunk as u64 * 5).is_err());
+    }
+
+    #[test]
+    fn rekey_stream_multi_chunk() {
+        const NEW: [u8; 32] = [0x24; 32];
+        for len in [
+            0,
+            17,
+            MIN_CHUNK_SIZE as usize,
+            MIN_CHUNK_SIZE as usize * 3 + 17,
+        ] {
+            let sealed = sealed_stream(len);
+            let mut rekeyed = Vec::new();
+            let summary = rekey_stream(&mut &sealed[..], &mut rekeyed, &KEY, &NEW).unwrap();
+            assert_eq!(summary.bytes_in, sealed.len() as u64);
+            assert_eq!(summary.bytes_out, rekeyed.len() as u64);
+            assert_eq!(rekeyed.len(), sealed.len());
+
+            let (old_header, _) = FileHeader::parse(&sealed).unwrap();
+            let (new_header, _) = FileHeader::parse(&rekeyed).unwrap();
+            assert_eq!(new_header.chunk_size, old_header.chunk_size);
+            assert_ne!(new_header.nonce_prefix, old_header.nonce_prefix);
+
+            let mut plain = Vec::new();
+            decrypt_stream(&mut &rekeyed[..], &mut plain, &NEW).unwrap();
+            assert_eq!(plain, (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>());
+            assert!(decrypt_stream(&mut &rekeyed[..], &mut Vec::new(), &KEY).is_err());
+        }
+    }
+
+    #[test]
+    fn ratchet_stream_round_trip() {
+        let len = MIN_CHUNK_SIZE as usize * 3 + 17;
+        let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            ratchet: true,
+            ..EncryptOptions::default()
+        };
+        let mut sealed = Vec::new();
+        encrypt_stream(&mut &data[..], &mut sealed, &KEY, &opts).unwrap();
+        let (header, header_len) = FileHeader::parse(&sealed).unwrap();
+        assert!(header.ratchet);
+
+        let mut plain = Vec::new();
+        decrypt_stream(&mut &sealed[..], &mut plain, &KEY).unwrap();
+        assert_eq!(plain, data);
+        assert_eq!(verify(&sealed).unwrap(), len as u64);
+
+        /* Rekeying keeps the mode */
+        let mut rekeyed = Vec::new();
+        rekey_stream(&mut &sealed[..], &mut rekeyed, &KEY, &[0x24; 32]).unwrap();
+        assert!(FileHeader::parse(&rekeyed).unwrap().0.ratchet);
+        let mut plain = Vec::new();
+        decrypt_stream(&mut &rekeyed[..], &mut plain, &[0x24; 32]).unwrap();
+        assert_eq!(plain, data);
+
+        /* Dropping the flag from the header changes the AAD and the mode */
+        let mut unflagged = FileHeader {
+            ratchet: false,
+            ..header
+        }
+        .to_bytes();
+        unflagged.extend_from_slice(&sealed[header_len..]);
+        assert!(matches!(
+            decrypt_stream(&mut &unflagged[..], &mut Vec::new(), &KEY),
+            Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed))
+        ));
+    }
+
+    #[test]
+    fn padded_stream_round_trip() {
+        let chunk = MIN_CHUNK_SIZE as usize;
+        for scheme in [PaddingScheme::Padme, PaddingScheme::Block(3000)] {
+            for len in [0, 1, 999, chunk - 8, chunk, 5 * chunk + 3, 40_000] {
+                let data: Vec<u8> = (0..len).map(|i| (i % 7) as u8).collect();
+                let opts = EncryptOptions {
+                    chunk_size: MIN_CHUNK_SIZE,
+                    padding: Some(scheme),
+                    ..EncryptOptions::default()
+                };
+                let mut sealed = Vec::new();
+                let summary = encrypt_stream(&mut &data[..], &mut sealed, &KEY, &opts).unwrap();
+                assert_eq!(summary.bytes_in, len as u64);
+                let (header, header_len) = FileHeader::parse(&sealed).unwrap();
+                assert_eq!(header.padding, Some(scheme));
+
+                /* Every padded length seals to the same size */
+                let padded = scheme.padded_len(len as u64) as usize;
+                let chunks = padded.div_ceil(chunk).max(1);
+                assert_eq!(sealed.len(), header_len + padded + chunks * TAG_LEN);
+
+                let mut plain = Vec::new();
+                let opened = decrypt_stream(&mut &sealed[..], &mut plain, &KEY).unwrap();
+                assert_eq!(plain, data, "{:?} len {}", scheme, len);
+                assert_eq!(opened.bytes_out, len as u64);
+                assert_eq!(verify(&sealed).unwrap(), padded as u64);
+
+                /* The trailer is the last plaintext, so sits just before
+                 * the final tag */
+                let mut tampered = sealed.clone();
+                let at = tampered.len() - TAG_LEN - 1;
+                tampered[at] ^= 1;
+                assert!(matches!(
+                    decrypt_stream(&mut &tampered[..], &mut Vec::new(), &KEY),
+                    Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed))
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn unpadding_checks_authenticated_trailer() {
+        /* Sealed correctly, but with padding that does not match the
+         * header's scheme: caught after authentication */
+        let header = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            padding: Some(PaddingScheme::Block(64)),
+            ..EncryptOptions::default()
+        }
+        .header(&KEY, [7; NONCE_PREFIX_LEN]);
+        let mut bad_plaintexts = Vec::new();
+        for claimed in [10u64, 9, 57, 60] {
+            let mut plain = vec![1u8; 10];
+            plain.resize(56, 0);
+            plain.extend_from_slice(&claimed.to_be_bytes());
+            bad_plaintexts.push(plain);
+        }
+        bad_plaintexts.push(vec![0; 7]);
+        for (i, plain) in bad_plaintexts.iter().enumerate() {
+            let mut sealed = Vec::new();
+            seal_chunks(&mut &plain[..], &mut sealed, &KEY, &header, &[], 1).unwrap();
+            let result = decrypt_stream(&mut &sealed[..], &mut Vec::new(), &KEY);
+            if i == 0 {
+                assert_eq!(result.unwrap().bytes_out, 10);
+            } else {
+                assert!(matches!(
+                    result,
+                    Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed))
+                ));
+            }
+        }
+
+        /* Fed in uneven pieces, zeros inside the data survive */
+        let mut data = vec![0u8; 5000];
+        data[0] = 1;
+        data[2500] = 2;
+        let mut padded = data.clone();
+        crate::pad_plaintext(&mut padded, PaddingScheme::Padme);
+        for step in [1, 3, 8, 9, 1024] {
+            let mut out = Vec::new();
+            let mut unpadder = Unpadder::new(&mut out, PaddingScheme::Padme);
+            for piece in padded.chunks(step) {
+                unpadder.write_all(piece).unwrap();
+            }
+            assert_eq!(unpadder.finish().unwrap(), 5000);
+            assert_eq!(out, data, "step {}", step);
+        }
+    }
+
+    #[test]
+    fn key_check_value_catches_wrong_key_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let (plain, enc, dec) = (
+            dir.path().join("plain"),
+            dir.path().join("plain.enc"),
+            dir.path().join("plain.dec"),
+        );
+        let data: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&plain, &data).unwrap();
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            key_check: true,
+            ..EncryptOptions::default()
+        };
+        encrypt_file(&plain, &enc, &KEY, &opts).unwrap();
+        let sealed = std::fs::read(&enc).unwrap();
+        let (header, header_len) = FileHeader::parse(&sealed).unwrap();
+        assert_eq!(header.key_check, Some(key_check_value(&KEY)));
+
+        decrypt_file(&enc, &dec, &KEY).unwrap();
+        assert_eq!(std::fs::read(&dec).unwrap(), data);
+
+        /* One bit off: refused before the output is even created */
+        std::fs::remove_file(&dec).unwrap();
+        let mut wrong = KEY;
+        wrong[31] ^= 0x80;
+        assert!(matches!(
+            decrypt_file(&enc, &dec, &wrong),
+            Err(FsCryptoError::Crypto(ErrorStates::WrongKey))
+        ));
+        assert!(!dec.exists());
+
+        /* Same for the other readers, even with the payload cut off */
+        let header_only = &sealed[..header_len];
+        assert!(matches!(
+            decrypt_stream(&mut &header_only[..], &mut Vec::new(), &wrong),
+            Err(FsCryptoError::Crypto(ErrorStates::WrongKey))
+        ));
+        assert!(matches!(
+            StreamVerifier::new(header_only, &wrong),
+            Err(FsCryptoError::Crypto(ErrorStates::WrongKey))
+        ));
+        assert!(matches!(
+            rekey_stream(&mut &sealed[..], &mut Vec::new(), &wrong, &[1; 32]),
+            Err(FsCryptoError::Crypto(ErrorStates::WrongKey))
+        ));
+
+        /* Rekeying stores the new key's value */
+        let mut rekeyed = Vec::new();
+        rekey_stream(&mut &sealed[..], &mut rekeyed, &KEY, &[1; 32]).unwrap();
+        let new_header = FileHeader::parse(&rekeyed).unwrap().0;
+        assert_eq!(new_header.key_check, Some(key_check_value(&[1; 32])));
+        let mut reopened = Vec::new();
+        decrypt_stream(&mut &rekeyed[..], &mut reopened, &[1; 32]).unwrap();
+        assert_eq!(reopened, data);
+
+        /* The value is in the authenticated header: swapping in the wrong
+         * key's value gets past the check but not the first chunk */
+        let mut swapped = FileHeader {
+            key_check: Some(key_check_value(&wrong)),
+            ..header
+        }
+        .to_bytes();
+        swapped.extend_from_slice(&sealed[header_len..]);
+        assert!(matches!(
+            decrypt_stream(&mut &swapped[..], &mut Vec::new(), &wrong),
+            Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed))
+        ));
+        assert!(matches!(
+            decrypt_stream(&mut &swapped[..], &mut Vec::new(), &KEY),
+            Err(FsCryptoError::Crypto(ErrorStates::WrongKey))
+        ));
+    }
+
+    #[test]
+    fn files_without_key_check_still_open() {
+        /* Default options write no key check value, as before */
+        let sealed = sealed_stream(3000);
+        assert_eq!(FileHeader::parse(&sealed).unwrap().0.key_check, None);
+        let mut plain = Vec::new();
+        decrypt_stream(&mut &sealed[..], &mut plain, &KEY).unwrap();
+        assert_eq!(plain.len(), 3000);
+        assert!(matches!(
+            decrypt_stream(&mut &sealed[..], &mut Vec::new(), &[1; 32]),
+            Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed))
+        ));
+    }
+
+    #[test]
+    fn rekey_stream_rejects_bad_input() {
+        let sealed = sealed_stream(MIN_CHUNK_SIZE as usize * 2 + 5);
+        let result = rekey_stream(&mut &sealed[..], &mut Vec::new(), &[0x24; 32], &KEY);
+        assert!(matches!(
+            result,
+            Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed))
+        ));
+
+        let truncated = &sealed[..HEADER_FIXED_LEN + MIN_CHUNK_SIZE as usize + TAG_LEN];
+        let result = rekey_stream(&mut &truncated[..], &mut Vec::new(), &KEY, &[0x24; 32]);
+        assert!(matches!(
+            result,
+            Err(FsCryptoError::Crypto(ErrorStates::StreamTruncated))
+        ));
+    }
+
+    #[test]
+    fn passphrase_parameters_travel_in_header() {
+        let kdf = PassphraseKdf {
+            kdf: crate::header::KDF_ARGON2ID,
+            m_cost_kib: 19 * 1024,
+            t_cost: 2,
+            p_cost: 1,
+            salt: [0x5a; 16],
+        };
+        let opts = EncryptOptions {
+            key_check: true,
+            passphrase: Some(kdf),
+            ..EncryptOptions::default()
+        };
+        let data = b"derive me again later";
+        let mut sealed = Vec::new();
+        encrypt_stream(&mut &data[..], &mut sealed, &KEY, &opts).unwrap();
+
+        let (header, _) = read_header(&mut &sealed[..]).unwrap();
+        assert_eq!(header.passphrase, Some(kdf));
+        assert_eq!(header.key_check, Some(key_check_value(&KEY)));
+        let mut opened = Vec::new();
+        decrypt_stream(&mut &sealed[..], &mut opened, &KEY).unwrap();
+        assert_eq!(opened, data);
+
+        /* A new key did not come from the passphrase */
+        let mut rekeyed = Vec::new();
+        rekey_stream(&mut &sealed[..], &mut rekeyed, &KEY, &[3; 32]).unwrap();
+        assert_eq!(read_header(&mut &rekeyed[..]).unwrap().0.passphrase, None);
+
+        /* unless it says so */
+        let other = PassphraseKdf {
+            salt: [8; 16],
+            ..kdf
+        };
+        let mut again = Vec::new();
+        rekey_stream_to_passphrase(&mut &rekeyed[..], &mut again, &[3; 32], &[4; 32], other)
+            .unwrap();
+        let (header, _) = read_header(&mut &again[..]).unwrap();
+        assert_eq!(header.passphrase, Some(other));
+        assert_eq!(header.key_check, Some(key_check_value(&[4; 32])));
+        let mut opened = Vec::new();
+        decrypt_stream(&mut &again[..], &mut opened, &[4; 32]).unwrap();
+        assert_eq!(opened, data);
+    }
+
+    #[test]
+    fn aad_binds_every_chunk() {
+        let data: Vec<u8> = (0..MIN_CHUNK_SIZE as usize * 3 + 7)
+            .map(|i| i as u8)
+            .collect();
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            aad: b"host=db1".to_vec(),
+            ..EncryptOptions::default()
+        };
+        let mut sealed = Vec::new();
+        encrypt_stream(&mut &data[..], &mut sealed, &KEY, &opts).unwrap();
+        let (header, header_bytes) = read_header(&mut &sealed[..]).unwrap();
+        assert_eq!(header.aad_check, Some(aad_check_value(b"host=db1")));
+
+        let open = |sealed: &[u8], aad: &[u8]| {
+            let mut plain = Vec::new();
+            decrypt_stream_with_aad(&mut &sealed[..], &mut plain, &KEY, aad).map(|_| plain)
+        };
+        assert_eq!(open(&sealed, b"host=db1").unwrap(), data);
+        for wrong in [&b""[..], b"host=db2"] {
+            assert!(matches!(
+                open(&sealed, wrong),
+                Err(FsCryptoError::Crypto(ErrorStates::AadMismatch))
+            ));
+        }
+        assert!(StreamVerifier::with_aad(&sealed[..], &KEY, b"host=db1")
+            .unwrap()
+            .verify_all()
+            .is_ok());
+        assert!(matches!(
+            StreamVerifier::new(&sealed[..], &KEY),
+            Err(FsCryptoError::Crypto(ErrorStates::AadMismatch))
+        ));
+        assert!(matches!(
+            rekey_stream(&mut &sealed[..], &mut Vec::new(), &KEY, &[1; 32]),
+            Err(FsCryptoError::Crypto(ErrorStates::AadMismatch))
+        ));
+
+        /* Data bound to nothing refuses associated data too */
+        let unbound = sealed_stream(100);
+        assert!(matches!(
+            open(&unbound, b"host=db1"),
+            Err(FsCryptoError::Crypto(ErrorStates::AadMismatch))
+        ));
+
+        /* The chunks authenticate the data itself, not just the check
+         * value: rewriting that for other data gets no further */
+        let forged = FileHeader {
+            aad_check: Some(aad_check_value(b"host=db2")),
+            ..header
+        };
+        let mut swapped = forged.to_bytes();
+        swapped.extend_from_slice(&sealed[header_bytes.len()..]);
+        assert!(matches!(
+            open(&swapped, b"host=db2"),
+            Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed))
+        ));
+    }
+
+    fn sealed_with(algorithm: Algorithm, data: &[u8]) -> Result<Vec<u8>, FsCryptoError> {
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            algorithm,
+            ..EncryptOptions::default()
+        };
+        let mut sealed = Vec::new();
+        encrypt_stream(&mut &data[..], &mut sealed, &KEY, &opts)?;
+        Ok(sealed)
+    }
+
+    #[cfg(feature = "aes-gcm")]
+    #[test]
+    fn aes_gcm_round_trip_from_the_header() {
+        let data: Vec<u8> = (0..MIN_CHUNK_SIZE as usize * 2 + 5)
+            .map(|i| i as u8)
+            .collect();
+        let sealed = sealed_with(Algorithm::Aes256Gcm, &data).unwrap();
+        assert_eq!(
+            FileHeader::parse(&sealed).unwrap().0.algorithm,
+            Algorithm::Aes256Gcm
+        );
+        let mut plain = Vec::new();
+        decrypt_stream(&mut &sealed[..], &mut plain, &KEY).unwrap();
+        assert_eq!(plain, data);
+        assert_eq!(verify(&sealed).unwrap(), data.len() as u64);
+
+        /* The header names the cipher the chunks must open under */
+        let mut swapped = sealed;
+        swapped[5] = Algorithm::ChaCha20Poly1305.id();
+        assert!(matches!(
+            decrypt_stream(&mut &swapped[..], &mut Vec::new(), &KEY),
+            Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed))
+        ));
+    }
+
+    #[cfg(not(feature = "aes-gcm"))]
+    #[test]
+    fn aes_gcm_needs_its_feature() {
+        let err = sealed_with(Algorithm::Aes256Gcm, b"data").unwrap_err();
+        assert!(matches!(
+            err,
+            FsCryptoError::Header(HeaderError::AlgorithmNotEnabled(Algorithm::Aes256Gcm))
+        ));
+
+        let mut sealed = sealed_with(Algorithm::ChaCha20Poly1305, b"data").unwrap();
+        sealed[5] = Algorithm::Aes256Gcm.id();
+        let err = decrypt_stream(&mut &sealed[..], &mut Vec::new(), &KEY).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "file uses AES-256-GCM, which this build was compiled without"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shred_overwrites_then_removes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, link) = (dir.path().join("plain"), dir.path().join("link"));
+        let pattern = b"PLAINTEXT PATTERN ".repeat(10_000);
+        std::fs::write(&path, &pattern).unwrap();
+
+        /* A second hard link shares the blocks, so it sees them emptied
+         * rather than merely unlinked */
+        std::fs::hard_link(&path, &link).unwrap();
+        shred_with_rng(&path, 2, &TestRng::new(1)).unwrap();
+        assert!(!path.exists());
+        assert_eq!(std::fs::read(&link).unwrap(), b"");
+
+        std::fs::write(&path, b"x").unwrap();
+        std::os::unix::fs::symlink(&path, dir.path().join("sym")).unwrap();
+        let err = shred(dir.path().join("sym"), 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(std::fs::read(&path).unwrap(), b"x");
+    }
+}
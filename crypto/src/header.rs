@@ -0,0 +1,689 @@
+/* Versioned header for rcli encrypted files.
+ *
+ * Layout (all integers big-endian):
+ *
+ *   0..4    magic "RCLI"
+ *   4       format version (1)
+ *   5       algorithm id (1 = ChaCha20-Poly1305, 2 = AES-256-GCM)
+ *   6..10   chunk size in plaintext bytes (u32)
+ *   10..17  STREAM nonce prefix (7 bytes)
+ *   17..19  extension length (u16)
+ *   19..    extensions: repeated (tag u8, len u16, value)
+ *
+ * Defined extensions:
+ *
+ *   EXT_COMPRESSION (1), len 1: plaintext was compressed with this codec
+ *                               id before encryption (see `compress`)
+ *   EXT_RATCHET (2), len 0:     chunks are sealed in ratchet mode (see
+ *                               `stream`)
+ *   EXT_PADDING (3), len 4:     plaintext was padded before encryption
+ *                               (see `padding`); u32 block size, or 0
+ *                               for Padmé
+ *   EXT_KEY_CHECK (4), len 3:   check value of the key (see `kcv`), so a
+ *                               wrong key fails before any chunk is read
+ *   EXT_PASSPHRASE (5), len 29: the key was derived from a passphrase:
+ *                               KDF id u8 (1 = Argon2id), memory cost in
+ *                               KiB, time cost and parallelism (u32
+ *                               each), then a 16-byte salt
+ *   EXT_AAD_CHECK (6), len 4:   chunks also authenticate associated data
+ *                               supplied by the caller; this is a check
+ *                               value of it, so missing or different data
+ *                               is reported as such
+ *   EXT_RECIPIENT (7), len > 9:  one recipient slot: the key is random
+ *                               and sealed to a recipient's public key
+ *                               (see `recipient`). Scheme id u8 (1 =
+ *                               X25519 + ML-KEM-768), the fingerprint of
+ *                               the public key (8 bytes), then the sealed
+ *                               key. Repeated once per recipient
+ *   EXT_ORIGINAL_LEN (8), len 8: length of the plaintext before
+ *                               compression (u64), so decompression can
+ *                               refuse an oversized file before reading
+ *                               it, and check it got every byte
+ *
+ * The encoded header is authenticated as associated data on every chunk,
+ * so any change to it fails decryption of the first chunk. Unknown
+ * extension tags are rejected rather than skipped, since they may change
+ * how the payload must be interpreted. */
+use alloc::vec::Vec;
+
+use crate::kcv::KCV_LEN;
+use crate::padding::PaddingScheme;
+
+/// Identifies an rcli encrypted file.
+pub const MAGIC: [u8; 4] = *b"RCLI";
+
+/// Current header format version.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Length of the fixed part of the header, before any extensions.
+pub const HEADER_FIXED_LEN: usize = 19;
+
+/// Length of the STREAM nonce prefix stored in the header.
+pub const NONCE_PREFIX_LEN: usize = 7;
+
+/// Smallest and largest chunk sizes a header may declare.
+pub const MIN_CHUNK_SIZE: u32 = 1024;
+pub const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Default plaintext chunk size (64 KiB).
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// Extension tag recording the compression codec id.
+pub const EXT_COMPRESSION: u8 = 1;
+
+/// Extension tag marking a ratcheting stream.
+pub const EXT_RATCHET: u8 = 2;
+
+/// Extension tag recording the padding scheme.
+pub const EXT_PADDING: u8 = 3;
+
+/// Extension tag recording the key check value.
+pub const EXT_KEY_CHECK: u8 = 4;
+
+/// Extension tag: the key was derived from a passphrase.
+pub const EXT_PASSPHRASE: u8 = 5;
+
+/// Extension tag recording the check value of bound associated data.
+pub const EXT_AAD_CHECK: u8 = 6;
+
+/// Extension tag carrying the key sealed to one recipient.
+pub const EXT_RECIPIENT: u8 = 7;
+
+/// Extension tag recording the plaintext's length before compression.
+pub const EXT_ORIGINAL_LEN: u8 = 8;
+
+/// Length of the `EXT_AAD_CHECK` value.
+pub const AAD_CHECK_LEN: usize = 4;
+
+/// KDF id for Argon2id (v1.3) in `EXT_PASSPHRASE`.
+pub const KDF_ARGON2ID: u8 = 1;
+
+/// Scheme id for X25519 + ML-KEM-768 hybrid sealing in `EXT_RECIPIENT`.
+pub const RECIPIENT_HYBRID_PQ: u8 = 1;
+
+/// Length of a recipient's key fingerprint in `EXT_RECIPIENT`.
+pub const RECIPIENT_FINGERPRINT_LEN: usize = 8;
+
+/// Salt length in `EXT_PASSPHRASE`.
+pub const PASSPHRASE_SALT_LEN: usize = 16;
+
+const PASSPHRASE_EXT_LEN: usize = 13 + PASSPHRASE_SALT_LEN;
+
+/// The AEAD every chunk is sealed with. Both take a 32-byte key, a
+/// 12-byte nonce and add a 16-byte tag, so the STREAM layout is the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    ChaCha20Poly1305,
+    /// Needs the `aes-gcm` feature to seal or open.
+    Aes256Gcm,
+}
+
+impl Algorithm {
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::ChaCha20Poly1305 => 1,
+            Algorithm::Aes256Gcm => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Algorithm::ChaCha20Poly1305),
+            2 => Some(Algorithm::Aes256Gcm),
+            _ => None,
+        }
+    }
+
+    /// The name to show people.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::ChaCha20Poly1305 => "ChaCha20-Poly1305",
+            Algorithm::Aes256Gcm => "AES-256-GCM",
+        }
+    }
+
+    /// Whether this build can seal and open chunks with it.
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Algorithm::ChaCha20Poly1305 => true,
+            Algorithm::Aes256Gcm => cfg!(feature = "aes-gcm"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderError {
+    /* Input does not start with the rcli magic */
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownAlgorithm(u8),
+    /* Input ends before the header does */
+    Truncated,
+    /* Header is structurally invalid (bad chunk size, unknown extension, ...) */
+    Malformed,
+    /* Payload is compressed; it must be opened through `compress` */
+    Compressed,
+    /* Key is sealed to a recipient; the file must be opened through
+     * `recipient` with their identity */
+    Recipient,
+    /* A known algorithm this build was compiled without */
+    AlgorithmNotEnabled(Algorithm),
+}
+
+/// How a file's key was derived from a passphrase, so it can be derived
+/// again on decryption.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassphraseKdf {
+    /// KDF id; only `KDF_ARGON2ID` is defined.
+    pub kdf: u8,
+    pub m_cost_kib: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+    pub salt: [u8; PASSPHRASE_SALT_LEN],
+}
+
+/// The key, sealed to one recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecipientSlot {
+    /// Identifies the recipient's public key, so they can find their slot.
+    pub fingerprint: [u8; RECIPIENT_FINGERPRINT_LEN],
+    pub sealed_key: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHeader {
+    pub version: u8,
+    pub algorithm: Algorithm,
+    pub chunk_size: u32,
+    pub nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    /* Codec id from the EXT_COMPRESSION extension, if present */
+    pub compression: Option<u8>,
+    /* Set by the EXT_RATCHET extension */
+    pub ratchet: bool,
+    /* Scheme from the EXT_PADDING extension, if present */
+    pub padding: Option<PaddingScheme>,
+    /* Value of the EXT_KEY_CHECK extension, if present */
+    pub key_check: Option<[u8; KCV_LEN]>,
+    /* Parameters from the EXT_PASSPHRASE extension, if present */
+    pub passphrase: Option<PassphraseKdf>,
+    /* Value of the EXT_AAD_CHECK extension, if present */
+    pub aad_check: Option<[u8; AAD_CHECK_LEN]>,
+    /* Slots from the EXT_RECIPIENT extensions, in order; empty if none */
+    pub recipients: Vec<RecipientSlot>,
+    /* Value of the EXT_ORIGINAL_LEN extension, if present */
+    pub original_len: Option<u64>,
+}
+
+impl FileHeader {
+    pub fn new(
+        algorithm: Algorithm,
+        chunk_size: u32,
+        nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    ) -> Self {
+        FileHeader {
+            version: FORMAT_VERSION,
+            algorithm,
+            chunk_size,
+            nonce_prefix,
+            compression: None,
+            ratchet: false,
+            padding: None,
+            key_check: None,
+            passphrase: None,
+            aad_check: None,
+            recipients: Vec::new(),
+            original_len: None,
+        }
+    }
+
+    /// Mark the payload as compressed with codec `id`.
+    pub fn with_compression(mut self, id: u8) -> Self {
+        self.compression = Some(id);
+        self
+    }
+
+    /// Mark the stream as sealed in ratchet mode.
+    pub fn with_ratchet(mut self) -> Self {
+        self.ratchet = true;
+        self
+    }
+
+    /// Mark the plaintext as padded with `scheme`.
+    pub fn with_padding(mut self, scheme: PaddingScheme) -> Self {
+        self.padding = Some(scheme);
+        self
+    }
+
+    /// Record the check value of the stream key.
+    pub fn with_key_check(mut self, kcv: [u8; KCV_LEN]) -> Self {
+        self.key_check = Some(kcv);
+        self
+    }
+
+    /// Record that the key was derived from a passphrase with `kdf`.
+    pub fn with_passphrase(mut self, kdf: PassphraseKdf) -> Self {
+        self.passphrase = Some(kdf);
+        self
+    }
+
+    /// Record the check value of the associated data the chunks are bound
+    /// to.
+    pub fn with_aad_check(mut self, check: [u8; AAD_CHECK_LEN]) -> Self {
+        self.aad_check = Some(check);
+        self
+    }
+
+    /// Add a slot holding the key, sealed to a recipient with
+    /// `RECIPIENT_HYBRID_PQ`.
+    pub fn with_recipient(mut self, slot: RecipientSlot) -> Self {
+        self.recipients.push(slot);
+        self
+    }
+
+    /// Record the plaintext's length before compression.
+    pub fn with_original_len(mut self, len: u64) -> Self {
+        self.original_len = Some(len);
+        self
+    }
+
+    /// True if `data` begins with the rcli magic.
+    pub fn has_magic(data: &[u8]) -> bool {
+        data.starts_with(&MAGIC)
+    }
+
+    /// Total encoded header length, given at least the fixed part of it.
+    pub fn encoded_len(fixed: &[u8]) -> Result<usize, HeaderError> {
+        if fixed.len() < HEADER_FIXED_LEN {
+            return Err(HeaderError::Truncated);
+        }
+        let ext_len = u16::from_be_bytes([fixed[17], fixed[18]]) as usize;
+        Ok(HEADER_FIXED_LEN + ext_len)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut extensions: Vec<u8> = Vec::new();
+        if let Some(id) = self.compression {
+            extensions.push(EXT_COMPRESSION);
+            extensions.extend_from_slice(&1u16.to_be_bytes());
+            extensions.push(id);
+        }
+        if self.ratchet {
+            extensions.push(EXT_RATCHET);
+            extensions.extend_from_slice(&0u16.to_be_bytes());
+        }
+        if let Some(scheme) = self.padding {
+            let block = match scheme {
+                PaddingScheme::Padme => 0,
+                PaddingScheme::Block(n) => n.max(1),
+            };
+            extensions.push(EXT_PADDING);
+            extensions.extend_from_slice(&4u16.to_be_bytes());
+            extensions.extend_from_slice(&block.to_be_bytes());
+        }
+        if let Some(kcv) = self.key_check {
+            extensions.push(EXT_KEY_CHECK);
+            extensions.extend_from_slice(&(KCV_LEN as u16).to_be_bytes());
+            extensions.extend_from_slice(&kcv);
+        }
+        if let Some(kdf) = self.passphrase {
+            extensions.push(EXT_PASSPHRASE);
+            extensions.extend_from_slice(&(PASSPHRASE_EXT_LEN as u16).to_be_bytes());
+            extensions.push(kdf.kdf);
+            extensions.extend_from_slice(&kdf.m_cost_kib.to_be_bytes());
+            extensions.extend_from_slice(&kdf.t_cost.to_be_bytes());
+            extensions.extend_from_slice(&kdf.p_cost.to_be_bytes());
+            extensions.extend_from_slice(&kdf.salt);
+        }
+        if let Some(check) = self.aad_check {
+            extensions.push(EXT_AAD_CHECK);
+            extensions.extend_from_slice(&(AAD_CHECK_LEN as u16).to_be_bytes());
+            extensions.extend_from_slice(&check);
+        }
+        for slot in &self.recipients {
+            let len = 1 + RECIPIENT_FINGERPRINT_LEN + slot.sealed_key.len();
+            extensions.push(EXT_RECIPIENT);
+            extensions.extend_from_slice(&(len as u16).to_be_bytes());
+            extensions.push(RECIPIENT_HYBRID_PQ);
+            extensions.extend_from_slice(&slot.fingerprint);
+            extensions.extend_from_slice(&slot.sealed_key);
+        }
+        if let Some(len) = self.original_len {
+            extensions.push(EXT_ORIGINAL_LEN);
+            extensions.extend_from_slice(&8u16.to_be_bytes());
+            extensions.extend_from_slice(&len.to_be_bytes());
+        }
+
+        let mut out = Vec::with_capacity(HEADER_FIXED_LEN + extensions.len());
+        out.extend_from_slice(&MAGIC);
+        out.push(self.version);
+        out.push(self.algorithm.id());
+        out.extend_from_slice(&self.chunk_size.to_be_bytes());
+        out.extend_from_slice(&self.nonce_prefix);
+        out.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        out.extend_from_slice(&extensions);
+        out
+    }
+
+    /// Parse a header from the start of `data`, returning it along with the
+    /// number of bytes it occupied.
+    pub fn parse(data: &[u8]) -> Result<(Self, usize), HeaderError> {
+        if data.len() < MAGIC.len() || !Self::has_magic(data) {
+            return Err(if MAGIC.starts_with(data) {
+                HeaderError::Truncated
+            } else {
+                HeaderError::BadMagic
+            });
+        }
+        let total_len = Self::encoded_len(data)?;
+        if data.len() < total_len {
+            return Err(HeaderError::Truncated);
+        }
+
+        let version = data[4];
+        if version != FORMAT_VERSION {
+            return Err(HeaderError::UnsupportedVersion(version));
+        }
+        let algorithm =
+            Algorithm::from_id(data[5]).ok_or(HeaderError::UnknownAlgorithm(data[5]))?;
+
+        let chunk_size = u32::from_be_bytes([data[6], data[7], data[8], data[9]]);
+        if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+            return Err(HeaderError::Malformed);
+        }
+
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        nonce_prefix.copy_from_slice(&data[10..17]);
+
+        let mut compression = None;
+        let mut ratchet = false;
+        let mut padding = None;
+        let mut key_check = None;
+        let mut passphrase = None;
+        let mut aad_check = None;
+        let mut recipients = Vec::new();
+        let mut original_len = None;
+        let mut rest = &data[HEADER_FIXED_LEN..total_len];
+        while !rest.is_empty() {
+            if rest.len() < 3 {
+                return Err(HeaderError::Malformed);
+            }
+            let tag = rest[0];
+            let len = u16::from_be_bytes([rest[1], rest[2]]) as usize;
+            let value = rest.get(3..3 + len).ok_or(HeaderError::Malformed)?;
+            match (tag, value) {
+                (EXT_COMPRESSION, [id]) if compression.is_none() => compression = Some(*id),
+                (EXT_RATCHET, []) if !ratchet => ratchet = true,
+                (EXT_PADDING, &[a, b, c, d]) if padding.is_none() => {
+                    padding = Some(match u32::from_be_bytes([a, b, c, d]) {
+                        0 => PaddingScheme::Padme,
+                        n => PaddingScheme::Block(n),
+                    })
+                }
+                (EXT_KEY_CHECK, &[a, b, c]) if key_check.is_none() => key_check = Some([a, b, c]),
+                (EXT_PASSPHRASE, value)
+                    if passphrase.is_none()
+                        && value.len() == PASSPHRASE_EXT_LEN
+                        && value[0] == KDF_ARGON2ID =>
+                {
+                    let u32_at =
+                        |at: usize| u32::from_be_bytes(value[at..at + 4].try_into().unwrap());
+                    passphrase = Some(PassphraseKdf {
+                        kdf: value[0],
+                        m_cost_kib: u32_at(1),
+                        t_cost: u32_at(5),
+                        p_cost: u32_at(9),
+                        salt: value[13..].try_into().unwrap(),
+                    })
+                }
+                (EXT_AAD_CHECK, &[a, b, c, d]) if aad_check.is_none() => {
+                    aad_check = Some([a, b, c, d])
+                }
+                (EXT_RECIPIENT, [RECIPIENT_HYBRID_PQ, slot @ ..])
+                    if slot.len() > RECIPIENT_FINGERPRINT_LEN =>
+                {
+                    let (fingerprint, sealed_key) = slot.split_at(RECIPIENT_FINGERPRINT_LEN);
+                    recipients.push(RecipientSlot {
+                        fingerprint: fingerprint.try_into().unwrap(),
+                        sealed_key: sealed_key.to_vec(),
+                    })
+                }
+                (EXT_ORIGINAL_LEN, value) if original_len.is_none() && value.len() == 8 => {
+                    original_len = Some(u64::from_be_bytes(value.try_into().unwrap()))
+                }
+                _ => return Err(HeaderError::Malformed),
+            }
+            rest = &rest[3 + len..];
+        }
+
+        Ok((
+            FileHeader {
+                version,
+                algorithm,
+                chunk_size,
+                nonce_prefix,
+                compression,
+                ratchet,
+                padding,
+                key_check,
+                passphrase,
+                aad_check,
+                recipients,
+                original_len,
+            },
+            total_len,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> FileHeader {
+        FileHeader::new(
+            Algorithm::ChaCha20Poly1305,
+            DEFAULT_CHUNK_SIZE,
+            [1, 2, 3, 4, 5, 6, 7],
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = sample().to_bytes();
+        assert_eq!(bytes.len(), HEADER_FIXED_LEN);
+        assert_eq!(FileHeader::encoded_len(&bytes), Ok(HEADER_FIXED_LEN));
+
+        let (parsed, used) = FileHeader::parse(&bytes).unwrap();
+        assert_eq!(parsed, sample());
+        assert_eq!(used, bytes.len());
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let bytes = sample().to_bytes();
+
+        assert_eq!(
+            FileHeader::parse(b"Hello World!"),
+            Err(HeaderError::BadMagic)
+        );
+        assert_eq!(FileHeader::parse(&bytes[..2]), Err(HeaderError::Truncated));
+        assert_eq!(FileHeader::parse(&bytes[..18]), Err(HeaderError::Truncated));
+
+        let mut version = bytes.clone();
+        version[4] = 9;
+        assert_eq!(
+            FileHeader::parse(&version),
+            Err(HeaderError::UnsupportedVersion(9))
+        );
+
+        let mut algorithm = bytes.clone();
+        algorithm[5] = 0xee;
+        assert_eq!(
+            FileHeader::parse(&algorithm),
+            Err(HeaderError::UnknownAlgorithm(0xee))
+        );
+
+        let mut chunk_size = bytes;
+        chunk_size[6..10].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(FileHeader::parse(&chunk_size), Err(HeaderError::Malformed));
+    }
+
+    #[test]
+    fn compression_extension() {
+        let header = sample().with_compression(2);
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), HEADER_FIXED_LEN + 4);
+        assert_eq!(bytes[HEADER_FIXED_LEN..], [EXT_COMPRESSION, 0, 1, 2]);
+        assert_eq!(FileHeader::parse(&bytes), Ok((header, bytes.len())));
+
+        let with_ext = |ext: &[u8]| {
+            let mut bytes = sample().to_bytes();
+            bytes[17..19].copy_from_slice(&(ext.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(ext);
+            FileHeader::parse(&bytes)
+        };
+        /* Unknown tag, wrong length, duplicate, trailing garbage */
+        assert_eq!(with_ext(&[9, 0, 1, 2]), Err(HeaderError::Malformed));
+        assert_eq!(with_ext(&[1, 0, 2, 2, 2]), Err(HeaderError::Malformed));
+        assert_eq!(
+            with_ext(&[1, 0, 1, 2, 1, 0, 1, 2]),
+            Err(HeaderError::Malformed)
+        );
+        assert_eq!(with_ext(&[1, 0, 1, 2, 0]), Err(HeaderError::Malformed));
+        assert_eq!(with_ext(&[1, 0, 5, 2]), Err(HeaderError::Malformed));
+
+        /* The ratchet flag is an empty extension, alongside compression */
+        let header = sample().with_compression(1).with_ratchet();
+        let bytes = header.to_bytes();
+        assert_eq!(
+            bytes[HEADER_FIXED_LEN..],
+            [EXT_COMPRESSION, 0, 1, 1, EXT_RATCHET, 0, 0]
+        );
+        assert_eq!(FileHeader::parse(&bytes), Ok((header, bytes.len())));
+        assert_eq!(with_ext(&[2, 0, 1, 0]), Err(HeaderError::Malformed));
+        assert_eq!(with_ext(&[2, 0, 0, 2, 0, 0]), Err(HeaderError::Malformed));
+
+        /* The original length is a u64 */
+        let header = sample().with_compression(1).with_original_len(1 << 40);
+        let bytes = header.to_bytes();
+        assert_eq!(
+            bytes[HEADER_FIXED_LEN + 4..],
+            [EXT_ORIGINAL_LEN, 0, 8, 0, 0, 1, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(FileHeader::parse(&bytes), Ok((header, bytes.len())));
+        assert_eq!(
+            with_ext(&[8, 0, 4, 0, 0, 0, 1]),
+            Err(HeaderError::Malformed)
+        );
+
+        /* Padding records a u32 block size, 0 meaning Padmé */
+        for (scheme, value) in [
+            (PaddingScheme::Padme, [0, 0, 0, 0]),
+            (PaddingScheme::Block(4096), [0, 0, 0x10, 0]),
+        ] {
+            let header = sample().with_padding(scheme);
+            let bytes = header.to_bytes();
+            assert_eq!(
+                bytes[HEADER_FIXED_LEN..HEADER_FIXED_LEN + 3],
+                [EXT_PADDING, 0, 4]
+            );
+            assert_eq!(bytes[HEADER_FIXED_LEN + 3..], value);
+            assert_eq!(FileHeader::parse(&bytes), Ok((header, bytes.len())));
+        }
+        assert_eq!(with_ext(&[3, 0, 1, 0]), Err(HeaderError::Malformed));
+        assert_eq!(
+            with_ext(&[3, 0, 4, 0, 0, 0, 0, 3, 0, 4, 0, 0, 0, 0]),
+            Err(HeaderError::Malformed)
+        );
+
+        /* The key check value is three raw bytes */
+        let header = sample().with_key_check([1, 2, 3]);
+        let bytes = header.to_bytes();
+        assert_eq!(bytes[HEADER_FIXED_LEN..], [EXT_KEY_CHECK, 0, 3, 1, 2, 3]);
+        assert_eq!(FileHeader::parse(&bytes), Ok((header, bytes.len())));
+        assert_eq!(with_ext(&[4, 0, 2, 1, 2]), Err(HeaderError::Malformed));
+
+        /* As is the AAD check value, in four */
+        let header = sample().with_aad_check([5, 6, 7, 8]);
+        let bytes = header.to_bytes();
+        assert_eq!(bytes[HEADER_FIXED_LEN..], [EXT_AAD_CHECK, 0, 4, 5, 6, 7, 8]);
+        assert_eq!(FileHeader::parse(&bytes), Ok((header, bytes.len())));
+        assert_eq!(with_ext(&[6, 0, 3, 5, 6, 7]), Err(HeaderError::Malformed));
+    }
+
+    #[test]
+    fn passphrase_extension() {
+        let kdf = PassphraseKdf {
+            kdf: KDF_ARGON2ID,
+            m_cost_kib: 19 * 1024,
+            t_cost: 2,
+            p_cost: 1,
+            salt: core::array::from_fn(|i| i as u8 + 0xa0),
+        };
+        let header = sample().with_key_check([1, 2, 3]).with_passphrase(kdf);
+        let bytes = header.to_bytes();
+        #[rustfmt::skip]
+        let expected = [
+            EXT_KEY_CHECK, 0, 3, 1, 2, 3,
+            EXT_PASSPHRASE, 0, 29, KDF_ARGON2ID,
+            0, 0, 0x4c, 0, 0, 0, 0, 2, 0, 0, 0, 1,
+            0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+            0xa8, 0xa9, 0xaa, 0xab, 0xac, 0xad, 0xae, 0xaf,
+        ];
+        assert_eq!(bytes[HEADER_FIXED_LEN..], expected);
+        assert_eq!(FileHeader::parse(&bytes), Ok((header, bytes.len())));
+
+        /* Unknown KDFs and short values are rejected */
+        let with_ext = |ext: &[u8]| {
+            let mut bytes = sample().to_bytes();
+            bytes[17..19].copy_from_slice(&(ext.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(ext);
+            FileHeader::parse(&bytes)
+        };
+        assert!(with_ext(&expected[6..]).is_ok());
+        let mut unknown = expected[6..].to_vec();
+        unknown[3] = 2;
+        assert_eq!(with_ext(&unknown), Err(HeaderError::Malformed));
+        let mut short = expected[6..expected.len() - 1].to_vec();
+        short[2] = 28;
+        assert_eq!(with_ext(&short), Err(HeaderError::Malformed));
+    }
+
+    #[test]
+    fn recipient_extension() {
+        let slot = |n: u8| RecipientSlot {
+            fingerprint: [n; RECIPIENT_FINGERPRINT_LEN],
+            sealed_key: (0..200u8).collect(),
+        };
+        let header = sample()
+            .with_recipient(slot(0xf1))
+            .with_recipient(slot(0xf2));
+        let bytes = header.to_bytes();
+        let first = &bytes[HEADER_FIXED_LEN..];
+        assert_eq!(
+            first[..12],
+            [7, 0, 209, 1, 0xf1, 0xf1, 0xf1, 0xf1, 0xf1, 0xf1, 0xf1, 0xf1]
+        );
+        assert_eq!(first[12..212], slot(0).sealed_key[..]);
+        assert_eq!(first[212..216], [7, 0, 209, 1]);
+        assert_eq!(FileHeader::parse(&bytes), Ok((header, bytes.len())));
+
+        /* An unknown scheme, or no sealed key after the fingerprint, is
+         * rejected */
+        let with_ext = |ext: &[u8]| {
+            let mut bytes = sample().to_bytes();
+            bytes[17..19].copy_from_slice(&(ext.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(ext);
+            FileHeader::parse(&bytes)
+        };
+        let mut ext = vec![EXT_RECIPIENT, 0, 10, 1];
+        ext.extend_from_slice(&[0xf1; 9]);
+        assert!(with_ext(&ext).is_ok());
+        ext[3] = 2;
+        assert_eq!(with_ext(&ext), Err(HeaderError::Malformed));
+        assert_eq!(
+            with_ext(&[EXT_RECIPIENT, 0, 9, 1, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(HeaderError::Malformed)
+        );
+    }
+}
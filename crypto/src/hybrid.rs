@@ -0,0 +1,421 @@
+/* Hybrid public-key sealing with X25519 and ML-KEM-768 (FIPS 203), for
+ * data that must stay confidential after large quantum computers exist.
+ * [FIPS 203]: https://csrc.nist.gov/pubs/fips/203/final
+ *
+ *   blob = x25519 share (32) || ML-KEM-768 ciphertext (1088)
+ *          || ChaCha20-Poly1305(key, zero nonce, aad, plaintext) || tag
+ *
+ *   key = HKDF-SHA256(ikm  = ML-KEM secret || X25519 secret,
+ *                     salt = share || ML-KEM ciphertext || recipient X25519 key,
+ *                     info = "rcli hybrid x25519+mlkem768 v1")
+ *
+ * Both shared secrets feed the KDF, so an attacker has to break X25519
+ * and ML-KEM to recover the key. Each blob uses a fresh ephemeral share
+ * and encapsulation, so its key is used once and the nonce can be fixed.
+ *
+ * Secret keys are stored as the X25519 scalar followed by the 64-byte
+ * ML-KEM seed (d || z); the expanded decapsulation key is rebuilt from
+ * the seed on load. */
+use alloc::vec::Vec;
+
+use ml_kem::array::Array;
+use ml_kem::ml_kem_768::{Ciphertext, DecapsulationKey, EncapsulationKey};
+use ml_kem::{Decapsulate, KeyExport, Seed, B32};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::rand::SystemRandom;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use crate::committing::Len;
+use crate::EntropySource;
+
+/// Length of an encoded ML-KEM-768 encapsulation (public) key.
+pub const MLKEM768_PUBLIC_KEY_LEN: usize = 1184;
+/// Length of an ML-KEM-768 ciphertext (encapsulation).
+pub const MLKEM768_CIPHERTEXT_LEN: usize = 1088;
+/// Length of the ML-KEM seed a decapsulation key is derived from.
+pub const MLKEM768_SEED_LEN: usize = 64;
+
+/// Length of an encoded `HybridPublicKey`.
+pub const HYBRID_PUBLIC_KEY_LEN: usize = 32 + MLKEM768_PUBLIC_KEY_LEN;
+/// Length of an encoded `HybridSecretKey`.
+pub const HYBRID_SECRET_KEY_LEN: usize = 32 + MLKEM768_SEED_LEN;
+/// Bytes `hybrid_seal_pq` adds to the plaintext.
+pub const HYBRID_OVERHEAD: usize = 32 + MLKEM768_CIPHERTEXT_LEN + TAG_LEN;
+
+const TAG_LEN: usize = 16;
+const HYBRID_INFO: &[u8] = b"rcli hybrid x25519+mlkem768 v1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HybridError {
+    /* Wrong length, an ML-KEM key that fails validation, or a low-order
+     * X25519 point */
+    InvalidKey,
+    /* Blob too short to hold both encapsulations and a tag */
+    Malformed,
+    /* Wrong key, wrong AAD, or a modified blob */
+    DecryptionFailed,
+}
+
+/// Recipient key: X25519 public key and ML-KEM-768 encapsulation key.
+#[derive(Clone, PartialEq)]
+pub struct HybridPublicKey {
+    x25519: [u8; 32],
+    mlkem: EncapsulationKey,
+}
+
+impl HybridPublicKey {
+    /// Parse the `HYBRID_PUBLIC_KEY_LEN`-byte form written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HybridError> {
+        if bytes.len() != HYBRID_PUBLIC_KEY_LEN {
+            return Err(HybridError::InvalidKey);
+        }
+        let (x25519, mlkem) = bytes.split_at(32);
+        let x25519: [u8; 32] = x25519.try_into().unwrap();
+        if !contributory(&x25519) {
+            return Err(HybridError::InvalidKey);
+        }
+        let mlkem = Array::try_from(mlkem).map_err(|_| HybridError::InvalidKey)?;
+        let mlkem = EncapsulationKey::new(&mlkem).map_err(|_| HybridError::InvalidKey)?;
+        Ok(HybridPublicKey { x25519, mlkem })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HYBRID_PUBLIC_KEY_LEN);
+        out.extend_from_slice(&self.x25519);
+        out.extend_from_slice(&self.mlkem.to_bytes());
+        out
+    }
+}
+
+impl core::fmt::Debug for HybridPublicKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HybridPublicKey")
+            .field("x25519", &self.x25519)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Recipient secret key. Zeroized on drop.
+pub struct HybridSecretKey {
+    x25519: StaticSecret,
+    mlkem: DecapsulationKey,
+}
+
+impl HybridSecretKey {
+    /// Rebuild a key from the `HYBRID_SECRET_KEY_LEN`-byte form written by
+    /// `to_bytes`.
+    pub fn from_bytes(bytes: &[u8; HYBRID_SECRET_KEY_LEN]) -> Self {
+        let (x25519, seed) = bytes.split_at(32);
+        let x25519: [u8; 32] = x25519.try_into().unwrap();
+        let seed = Seed::try_from(seed).unwrap();
+        HybridSecretKey {
+            x25519: StaticSecret::from(x25519),
+            mlkem: DecapsulationKey::from_seed(seed),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Zeroizing<[u8; HYBRID_SECRET_KEY_LEN]> {
+        let mut out = Zeroizing::new([0u8; HYBRID_SECRET_KEY_LEN]);
+        out[..32].copy_from_slice(self.x25519.as_bytes());
+        /* Keys only come from seeds here, so the seed is always there */
+        out[32..].copy_from_slice(&Zeroizing::new(self.mlkem.to_seed().unwrap()));
+        out
+    }
+
+    pub fn to_public(&self) -> HybridPublicKey {
+        HybridPublicKey {
+            x25519: PublicKey::from(&self.x25519).to_bytes(),
+            mlkem: self.mlkem.encapsulation_key().clone(),
+        }
+    }
+}
+
+impl core::fmt::Debug for HybridSecretKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("HybridSecretKey(..)")
+    }
+}
+
+/// Generate a fresh X25519 + ML-KEM-768 key pair.
+pub fn mlkem_generate() -> HybridSecretKey {
+    mlkem_generate_with_rng(&SystemRandom::new())
+}
+
+pub fn mlkem_generate_with_rng(rng: &dyn EntropySource) -> HybridSecretKey {
+    let mut bytes = Zeroizing::new([0u8; HYBRID_SECRET_KEY_LEN]);
+    rng.fill(&mut bytes[..]);
+    HybridSecretKey::from_bytes(&bytes)
+}
+
+/* A low-order point would make the X25519 secret all zeros */
+fn contributory(public: &[u8; 32]) -> bool {
+    StaticSecret::from([1u8; 32])
+        .diffie_hellman(&PublicKey::from(*public))
+        .was_contributory()
+}
+
+fn derive_key(
+    mlkem_secret: &[u8],
+    x25519_secret: &[u8; 32],
+    share: &[u8; 32],
+    ciphertext: &[u8],
+    recipient: &[u8; 32],
+) -> LessSafeKey {
+    let mut ikm = Zeroizing::new([0u8; 64]);
+    ikm[..32].copy_from_slice(mlkem_secret);
+    ikm[32..].copy_from_slice(x25519_secret);
+
+    let mut salt = Vec::with_capacity(64 + ciphertext.len());
+    salt.extend_from_slice(share);
+    salt.extend_from_slice(ciphertext);
+    salt.extend_from_slice(recipient);
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    Salt::new(HKDF_SHA256, &salt)
+        .extract(&ikm[..])
+        .expand(&[HYBRID_INFO], Len(32))
+        .and_then(|okm| okm.fill(&mut key[..]))
+        .unwrap();
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key[..]).unwrap())
+}
+
+/// Seal `plaintext` to `recipient`, authenticating `aad` alongside it.
+pub fn hybrid_seal_pq(recipient: &HybridPublicKey, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    hybrid_seal_pq_with_rng(recipient, aad, plaintext, &SystemRandom::new())
+}
+
+pub fn hybrid_seal_pq_with_rng(
+    recipient: &HybridPublicKey,
+    aad: &[u8],
+    plaintext: &[u8],
+    rng: &dyn EntropySource,
+) -> Vec<u8> {
+    let mut ephemeral = Zeroizing::new([0u8; 32]);
+    rng.fill(&mut ephemeral[..]);
+    let ephemeral = StaticSecret::from(*ephemeral);
+    let share = PublicKey::from(&ephemeral).to_bytes();
+    let x25519_secret = ephemeral.diffie_hellman(&PublicKey::from(recipient.x25519));
+
+    let mut m = Zeroizing::new(B32::default());
+    rng.fill(&mut m[..]);
+    let (ciphertext, mlkem_secret) = recipient.mlkem.encapsulate_deterministic(&m);
+    let mlkem_secret = Zeroizing::new(mlkem_secret);
+
+    let key = derive_key(
+        &mlkem_secret,
+        x25519_secret.as_bytes(),
+        &share,
+        &ciphertext,
+        &recipient.x25519,
+    );
+
+    let mut out = Vec::with_capacity(plaintext.len() + HYBRID_OVERHEAD);
+    out.extend_from_slice(&share);
+    out.extend_from_slice(&ciphertext);
+    let mut sealed = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key([0u8; NONCE_LEN]),
+        Aad::from(aad),
+        &mut sealed,
+    )
+    .unwrap();
+    out.extend_from_slice(&sealed);
+    out
+}
+
+/// Open a blob from `hybrid_seal_pq` with the recipient's secret key.
+pub fn hybrid_open_pq(
+    secret: &HybridSecretKey,
+    aad: &[u8],
+    blob: &[u8],
+) -> Result<Vec<u8>, HybridError> {
+    if blob.len() < HYBRID_OVERHEAD {
+        return Err(HybridError::Malformed);
+    }
+    let (share, rest) = blob.split_at(32);
+    let (ciphertext, sealed) = rest.split_at(MLKEM768_CIPHERTEXT_LEN);
+    let share: [u8; 32] = share.try_into().unwrap();
+    if !contributory(&share) {
+        return Err(HybridError::DecryptionFailed);
+    }
+
+    let x25519_secret = secret.x25519.diffie_hellman(&PublicKey::from(share));
+    /* A modified ciphertext decapsulates to an unrelated secret (implicit
+     * rejection), which then fails the tag check below */
+    let ciphertext = Ciphertext::try_from(ciphertext).unwrap();
+    let mlkem_secret = Zeroizing::new(secret.mlkem.decapsulate(&ciphertext));
+
+    let recipient = PublicKey::from(&secret.x25519).to_bytes();
+    let key = derive_key(
+        &mlkem_secret,
+        x25519_secret.as_bytes(),
+        &share,
+        &ciphertext,
+        &recipient,
+    );
+
+    let mut in_out = sealed.to_vec();
+    let len = key
+        .open_in_place(
+            Nonce::assume_unique_for_key([0u8; NONCE_LEN]),
+            Aad::from(aad),
+            &mut in_out,
+        )
+        .map_err(|_| HybridError::DecryptionFailed)?
+        .len();
+    in_out.truncate(len);
+    Ok(in_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRng;
+
+    fn key_pair(seed: u64) -> (HybridSecretKey, HybridPublicKey) {
+        let secret = mlkem_generate_with_rng(&TestRng::new(seed));
+        let public = secret.to_public();
+        (secret, public)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (secret, public) = key_pair(1);
+        for len in [0, 1, 1000] {
+            let plaintext = vec![0x5a; len];
+            let blob = hybrid_seal_pq(&public, b"archive 7", &plaintext);
+            assert_eq!(blob.len(), len + HYBRID_OVERHEAD);
+            assert_eq!(
+                hybrid_open_pq(&secret, b"archive 7", &blob).unwrap(),
+                plaintext
+            );
+            assert_eq!(
+                hybrid_open_pq(&secret, b"archive 8", &blob),
+                Err(HybridError::DecryptionFailed)
+            );
+        }
+
+        let (other, _) = key_pair(2);
+        let blob = hybrid_seal_pq(&public, b"", b"secret");
+        assert_eq!(
+            hybrid_open_pq(&other, b"", &blob),
+            Err(HybridError::DecryptionFailed)
+        );
+    }
+
+    #[test]
+    fn corrupted_encapsulations_fail() {
+        let (secret, public) = key_pair(3);
+        let blob = hybrid_seal_pq_with_rng(&public, b"", b"payload", &TestRng::new(4));
+
+        /* First byte of the X25519 share, then bytes throughout the
+         * ML-KEM ciphertext, then the tag */
+        for at in [
+            0,
+            31,
+            32,
+            500,
+            32 + MLKEM768_CIPHERTEXT_LEN - 1,
+            blob.len() - 1,
+        ] {
+            let mut bad = blob.clone();
+            bad[at] ^= 0x01;
+            assert_eq!(
+                hybrid_open_pq(&secret, b"", &bad),
+                Err(HybridError::DecryptionFailed),
+                "byte {}",
+                at
+            );
+        }
+
+        /* An all-zero (low-order) share is refused outright */
+        let mut bad = blob.clone();
+        bad[..32].fill(0);
+        assert_eq!(
+            hybrid_open_pq(&secret, b"", &bad),
+            Err(HybridError::DecryptionFailed)
+        );
+        assert_eq!(
+            hybrid_open_pq(&secret, b"", &blob[..HYBRID_OVERHEAD - 1]),
+            Err(HybridError::Malformed)
+        );
+    }
+
+    #[test]
+    fn key_encodings() {
+        let (secret, public) = key_pair(5);
+        let public_bytes = public.to_bytes();
+        assert_eq!(public_bytes.len(), HYBRID_PUBLIC_KEY_LEN);
+        assert_eq!(
+            HybridPublicKey::from_bytes(&public_bytes),
+            Ok(public.clone())
+        );
+
+        let secret_bytes = secret.to_bytes();
+        let restored = HybridSecretKey::from_bytes(&secret_bytes);
+        assert_eq!(restored.to_public(), public);
+        let blob = hybrid_seal_pq(&public, b"", b"x");
+        assert_eq!(hybrid_open_pq(&restored, b"", &blob).unwrap(), b"x");
+
+        assert_eq!(
+            HybridPublicKey::from_bytes(&public_bytes[1..]),
+            Err(HybridError::InvalidKey)
+        );
+        let mut low_order = public_bytes.clone();
+        low_order[..32].fill(0);
+        assert_eq!(
+            HybridPublicKey::from_bytes(&low_order),
+            Err(HybridError::InvalidKey)
+        );
+        /* Coefficients are 12 bits and must be below q = 3329 */
+        let mut out_of_range = public_bytes;
+        out_of_range[32..34].copy_from_slice(&[0xff, 0xff]);
+        assert_eq!(
+            HybridPublicKey::from_bytes(&out_of_range),
+            Err(HybridError::InvalidKey)
+        );
+    }
+
+    // draft-ietf-lamps-kyber-certificates example key: seed 00 01 .. 3f
+    // (the ML-KEM-768.pub example shipped with the ml-kem crate)
+    #[test]
+    fn keygen_from_seed_matches_example() {
+        let seed: [u8; 64] = core::array::from_fn(|i| i as u8);
+        let dk = DecapsulationKey::from_seed(Seed::from(seed));
+        assert_eq!(
+            dk.encapsulation_key().to_bytes().as_slice(),
+            include_bytes!("../tests/mlkem768_seed_ek.bin")
+        );
+    }
+
+    /* The NIST ACVP keyGen and encapDecap vectors are not in the repo yet,
+     * so there is no known-answer test for dk, ct or ss: beyond the one
+     * example encapsulation key above, keygen and deterministic
+     * encapsulation are only cross-checked against libcrux's independent
+     * (formally verified) ML-KEM-768 */
+    #[test]
+    fn raw_kem_matches_libcrux() {
+        use libcrux_ml_kem::mlkem768;
+
+        for seed in 0..8 {
+            let rng = TestRng::new(seed);
+            let mut d_z = [0u8; 64];
+            let mut m = [0u8; 32];
+            rng.fill(&mut d_z);
+            rng.fill(&mut m);
+
+            let dk = DecapsulationKey::from_seed(Seed::from(d_z));
+            let ek = dk.encapsulation_key();
+            let (ct, ss) = ek.encapsulate_deterministic(&B32::from(m));
+
+            let pair = mlkem768::generate_key_pair(d_z);
+            assert_eq!(ek.to_bytes().as_slice(), pair.pk());
+            let (their_ct, their_ss) = mlkem768::encapsulate(pair.public_key(), m);
+            assert_eq!(ct.as_slice(), their_ct.as_slice());
+            assert_eq!(ss.as_slice(), their_ss.as_slice());
+            assert_eq!(dk.decapsulate(&ct).as_slice(), their_ss.as_slice());
+        }
+    }
+}
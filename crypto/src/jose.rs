@@ -0,0 +1,471 @@
+/* JWE compact serialization (RFC 7516) for handing payloads to JOSE consumers.
+ *
+ * Token: b64u(protected) . b64u(encrypted_key) . b64u(iv) . b64u(ciphertext) . b64u(tag)
+ *
+ * Supported algorithms:
+ *
+ *   alg "dir"            -- the 32-byte key is the A256GCM content key
+ *   alg "ECDH-ES+A256KW" -- X25519 (RFC 8037) ephemeral-static agreement,
+ *                           Concat KDF (RFC 7518 4.6.2), AES-256 key wrap
+ *
+ * In both cases enc is "A256GCM" and the ASCII of the encoded protected
+ * header is the AEAD associated data. The decrypting key fixes the one alg
+ * that is accepted, so a token cannot talk us into a different (or "none")
+ * algorithm. P-256 is not supported: ring only offers ephemeral ECDH. */
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use aes_kw::{KeyInit, KwAes256};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::digest;
+use ring::rand::SystemRandom;
+use serde_json::{Map, Value};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::EntropySource;
+
+const ALG_DIR: &str = "dir";
+const ALG_ECDH_ES_A256KW: &str = "ECDH-ES+A256KW";
+const ENC_A256GCM: &str = "A256GCM";
+
+const TAG_LEN: usize = 16;
+/* AES key wrap adds one 8-byte integrity block */
+const WRAPPED_KEY_LEN: usize = 32 + 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoseError {
+    /* Not five base64url parts, or the header is not a JSON object */
+    Malformed,
+    /* alg/enc is missing, unknown, or not the one this key is for */
+    UnsupportedAlgorithm,
+    /* Header carries "crit" parameters we do not understand */
+    UnsupportedCritical,
+    /* Recipient public key is a low-order point */
+    InvalidKey,
+    /* Wrong key, or tampered header/ciphertext */
+    DecryptionFailed,
+}
+
+/// Key used to open a JWE; it determines the only `alg` accepted.
+pub enum JweKey<'a> {
+    /// `alg: "dir"` with this A256GCM content key.
+    Direct(&'a [u8; 32]),
+    /// `alg: "ECDH-ES+A256KW"` with this X25519 private key.
+    X25519(&'a [u8; 32]),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jwe {
+    /// Decoded protected header.
+    pub header: Map<String, Value>,
+    pub payload: Vec<u8>,
+}
+
+fn b64(data: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(data)
+}
+
+fn unb64(part: &str) -> Result<Vec<u8>, JoseError> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| JoseError::Malformed)
+}
+
+fn gcm_key(cek: &[u8]) -> Result<LessSafeKey, JoseError> {
+    let unbound_key =
+        UnboundKey::new(&AES_256_GCM, cek).map_err(|_| JoseError::DecryptionFailed)?;
+    Ok(LessSafeKey::new(unbound_key))
+}
+
+/* Concat KDF with a single SHA-256 round, for a 256-bit key wrapping key */
+fn concat_kdf(z: &[u8], alg: &str, apu: &[u8], apv: &[u8]) -> [u8; 32] {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    ctx.update(&1u32.to_be_bytes());
+    ctx.update(z);
+    for field in [alg.as_bytes(), apu, apv] {
+        ctx.update(&(field.len() as u32).to_be_bytes());
+        ctx.update(field);
+    }
+    /* SuppPubInfo: key length in bits */
+    ctx.update(&256u32.to_be_bytes());
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(ctx.finish().as_ref());
+    key
+}
+
+fn header_bytes(header: &Map<String, Value>, name: &str) -> Result<Vec<u8>, JoseError> {
+    match header.get(name) {
+        None => Ok(Vec::new()),
+        Some(Value::String(s)) => unb64(s),
+        Some(_) => Err(JoseError::Malformed),
+    }
+}
+
+/* Seal `payload` under `cek`, with `header` (alg/enc already set) as AAD */
+fn seal(
+    header: &Map<String, Value>,
+    encrypted_key: &[u8],
+    cek: &[u8; 32],
+    payload: &[u8],
+    rng: &dyn EntropySource,
+) -> String {
+    let protected = b64(&serde_json::to_vec(header).unwrap());
+
+    let mut iv = [0u8; NONCE_LEN];
+    rng.fill(&mut iv);
+
+    let mut in_out = payload.to_vec();
+    let tag = gcm_key(cek)
+        .unwrap()
+        .seal_in_place_separate_tag(
+            Nonce::assume_unique_for_key(iv),
+            Aad::from(protected.as_bytes()),
+            &mut in_out,
+        )
+        .unwrap();
+
+    format!(
+        "{}.{}.{}.{}.{}",
+        protected,
+        b64(encrypted_key),
+        b64(&iv),
+        b64(&in_out),
+        b64(tag.as_ref())
+    )
+}
+
+fn protected_header(protected_extra: &Map<String, Value>, alg: &str) -> Map<String, Value> {
+    let mut header = protected_extra.clone();
+    header.insert("alg".into(), Value::String(alg.into()));
+    header.insert("enc".into(), Value::String(ENC_A256GCM.into()));
+    header
+}
+
+/// Encrypt `payload` with `alg: "dir"`, `enc: "A256GCM"`. Entries of
+/// `protected_extra` (e.g. `kid`, `cty`) are added to the protected header;
+/// `alg` and `enc` in it are overridden.
+pub fn jwe_encrypt_dir(
+    key: &[u8; 32],
+    payload: &[u8],
+    protected_extra: &Map<String, Value>,
+) -> String {
+    jwe_encrypt_dir_with_rng(key, payload, protected_extra, &SystemRandom::new())
+}
+
+/// As `jwe_encrypt_dir`, drawing the IV from `rng`.
+pub fn jwe_encrypt_dir_with_rng(
+    key: &[u8; 32],
+    payload: &[u8],
+    protected_extra: &Map<String, Value>,
+    rng: &dyn EntropySource,
+) -> String {
+    let header = protected_header(protected_extra, ALG_DIR);
+    seal(&header, &[], key, payload, rng)
+}
+
+/// Encrypt `payload` to an X25519 public key with `alg: "ECDH-ES+A256KW"`,
+/// `enc: "A256GCM"`.
+pub fn jwe_encrypt_ecdh_es(
+    recipient: &[u8; 32],
+    payload: &[u8],
+    protected_extra: &Map<String, Value>,
+) -> Result<String, JoseError> {
+    jwe_encrypt_ecdh_es_with_rng(recipient, payload, protected_extra, &SystemRandom::new())
+}
+
+/// As `jwe_encrypt_ecdh_es`, drawing the ephemeral key, content key and IV
+/// from `rng`.
+pub fn jwe_encrypt_ecdh_es_with_rng(
+    recipient: &[u8; 32],
+    payload: &[u8],
+    protected_extra: &Map<String, Value>,
+    rng: &dyn EntropySource,
+) -> Result<String, JoseError> {
+    let mut header = protected_header(protected_extra, ALG_ECDH_ES_A256KW);
+
+    let mut ephemeral = [0u8; 32];
+    rng.fill(&mut ephemeral);
+    let ephemeral = StaticSecret::from(ephemeral);
+    let shared = ephemeral.diffie_hellman(&PublicKey::from(*recipient));
+    if !shared.was_contributory() {
+        return Err(JoseError::InvalidKey);
+    }
+
+    let mut epk = Map::new();
+    epk.insert("kty".into(), Value::String("OKP".into()));
+    epk.insert("crv".into(), Value::String("X25519".into()));
+    epk.insert(
+        "x".into(),
+        Value::String(b64(PublicKey::from(&ephemeral).as_bytes())),
+    );
+    header.insert("epk".into(), Value::Object(epk));
+
+    let kek = concat_kdf(
+        shared.as_bytes(),
+        ALG_ECDH_ES_A256KW,
+        &header_bytes(&header, "apu")?,
+        &header_bytes(&header, "apv")?,
+    );
+
+    let mut cek = [0u8; 32];
+    rng.fill(&mut cek);
+    let mut wrapped = [0u8; WRAPPED_KEY_LEN];
+    KwAes256::new(&kek.into())
+        .wrap_key(&cek, &mut wrapped)
+        .map_err(|_| JoseError::InvalidKey)?;
+
+    Ok(seal(&header, &wrapped, &cek, payload, rng))
+}
+
+/* Recover the content key from an ECDH-ES+A256KW header and encrypted key */
+fn unwrap_ecdh_es(
+    private_key: &[u8; 32],
+    header: &Map<String, Value>,
+    encrypted_key: &[u8],
+) -> Result<[u8; 32], JoseError> {
+    let epk = match header.get("epk") {
+        Some(Value::Object(epk)) => epk,
+        _ => return Err(JoseError::Malformed),
+    };
+    if epk.get("kty").and_then(Value::as_str) != Some("OKP")
+        || epk.get("crv").and_then(Value::as_str) != Some("X25519")
+    {
+        return Err(JoseError::UnsupportedAlgorithm);
+    }
+    let epk: [u8; 32] = header_bytes(epk, "x")?
+        .try_into()
+        .map_err(|_| JoseError::Malformed)?;
+
+    let shared = StaticSecret::from(*private_key).diffie_hellman(&PublicKey::from(epk));
+    if !shared.was_contributory() {
+        return Err(JoseError::DecryptionFailed);
+    }
+
+    let kek = concat_kdf(
+        shared.as_bytes(),
+        ALG_ECDH_ES_A256KW,
+        &header_bytes(header, "apu")?,
+        &header_bytes(header, "apv")?,
+    );
+    if encrypted_key.len() != WRAPPED_KEY_LEN {
+        return Err(JoseError::Malformed);
+    }
+    let mut cek = [0u8; 32];
+    KwAes256::new(&kek.into())
+        .unwrap_key(encrypted_key, &mut cek)
+        .map_err(|_| JoseError::DecryptionFailed)?;
+    Ok(cek)
+}
+
+/// Parse a compact JWE, check its header against `key`, and decrypt it.
+pub fn jwe_decrypt(key: &JweKey, token: &str) -> Result<Jwe, JoseError> {
+    let parts: Vec<&str> = token.trim().split('.').collect();
+    let [protected, encrypted_key, iv, ciphertext, tag] = parts[..] else {
+        return Err(JoseError::Malformed);
+    };
+
+    let header: Map<String, Value> =
+        serde_json::from_slice(&unb64(protected)?).map_err(|_| JoseError::Malformed)?;
+    if header.contains_key("crit") {
+        return Err(JoseError::UnsupportedCritical);
+    }
+    if header.get("enc").and_then(Value::as_str) != Some(ENC_A256GCM) {
+        return Err(JoseError::UnsupportedAlgorithm);
+    }
+
+    let alg = header.get("alg").and_then(Value::as_str);
+    let encrypted_key = unb64(encrypted_key)?;
+    let cek = match (key, alg) {
+        (JweKey::Direct(cek), Some(ALG_DIR)) => {
+            if !encrypted_key.is_empty() {
+                return Err(JoseError::Malformed);
+            }
+            **cek
+        }
+        (JweKey::X25519(private_key), Some(ALG_ECDH_ES_A256KW)) => {
+            unwrap_ecdh_es(private_key, &header, &encrypted_key)?
+        }
+        _ => return Err(JoseError::UnsupportedAlgorithm),
+    };
+
+    let iv: [u8; NONCE_LEN] = unb64(iv)?.try_into().map_err(|_| JoseError::Malformed)?;
+    let tag = unb64(tag)?;
+    if tag.len() != TAG_LEN {
+        return Err(JoseError::Malformed);
+    }
+
+    let mut in_out = unb64(ciphertext)?;
+    in_out.extend_from_slice(&tag);
+    let len = gcm_key(&cek)?
+        .open_in_place(
+            Nonce::assume_unique_for_key(iv),
+            Aad::from(protected.as_bytes()),
+            &mut in_out,
+        )
+        .map_err(|_| JoseError::DecryptionFailed)?
+        .len();
+    in_out.truncate(len);
+
+    Ok(Jwe {
+        header,
+        payload: in_out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Bytes 0x00..=0x1f, as used for the python-side fixtures */
+    fn dir_key() -> [u8; 32] {
+        core::array::from_fn(|i| i as u8)
+    }
+
+    /* X25519 private key: bytes 0x40..=0x5f */
+    fn x25519_key() -> [u8; 32] {
+        core::array::from_fn(|i| 0x40 + i as u8)
+    }
+
+    fn kid(kid: &str) -> Map<String, Value> {
+        let mut extra = Map::new();
+        extra.insert("kid".into(), Value::String(kid.into()));
+        extra
+    }
+
+    #[test]
+    fn dir_round_trip() {
+        let token = jwe_encrypt_dir(&dir_key(), b"Hello World!", &kid("partner-1"));
+        assert_eq!(token.split('.').count(), 5);
+        assert_eq!(token.split('.').nth(1), Some(""));
+
+        let jwe = jwe_decrypt(&JweKey::Direct(&dir_key()), &token).unwrap();
+        assert_eq!(jwe.payload, b"Hello World!");
+        assert_eq!(jwe.header["alg"], "dir");
+        assert_eq!(jwe.header["enc"], "A256GCM");
+        assert_eq!(jwe.header["kid"], "partner-1");
+
+        assert_eq!(
+            jwe_decrypt(&JweKey::Direct(&[0x42; 32]), &token),
+            Err(JoseError::DecryptionFailed)
+        );
+    }
+
+    #[test]
+    fn ecdh_es_round_trip() {
+        let public = PublicKey::from(&StaticSecret::from(x25519_key())).to_bytes();
+        let token = jwe_encrypt_ecdh_es(&public, b"Hello World!", &Map::new()).unwrap();
+
+        let jwe = jwe_decrypt(&JweKey::X25519(&x25519_key()), &token).unwrap();
+        assert_eq!(jwe.payload, b"Hello World!");
+        assert_eq!(jwe.header["epk"]["crv"], "X25519");
+
+        assert_eq!(
+            jwe_encrypt_ecdh_es(&[0; 32], b"Hello World!", &Map::new()),
+            Err(JoseError::InvalidKey)
+        );
+
+        assert_eq!(
+            jwe_decrypt(&JweKey::X25519(&[0x42; 32]), &token),
+            Err(JoseError::DecryptionFailed)
+        );
+    }
+
+    // Minted by python joserfc: jwe.encrypt_compact({"alg": "dir", ...}, msg, OctKey(dir_key))
+    #[test]
+    fn decrypts_joserfc_dir_token() {
+        let token = "eyJhbGciOiJkaXIiLCJlbmMiOiJBMjU2R0NNIiwia2lkIjoicGFydG5lci0xIn0..1DCTToPMlbJuWtV5.3IGT5_aLXnxipT_yBcB9RIsrs0hYR81bhMaG37vuEjulUJZDSOQ.u0SwqX95h5pNRtcDO9b_bg";
+
+        let jwe = jwe_decrypt(&JweKey::Direct(&dir_key()), token).unwrap();
+        assert_eq!(jwe.payload, b"Hello from jwcrypto-compatible tooling");
+        assert_eq!(jwe.header["kid"], "partner-1");
+    }
+
+    // Minted by python joserfc to the OKP key with d = x25519_key()
+    #[test]
+    fn decrypts_joserfc_ecdh_es_token() {
+        let token = "eyJhbGciOiJFQ0RILUVTK0EyNTZLVyIsImVuYyI6IkEyNTZHQ00iLCJraWQiOiJwYXJ0bmVyLTIiLCJlcGsiOnsiY3J2IjoiWDI1NTE5IiwieCI6IjgzZGdIbVRkbkk1UHhHSVRLLUtSZWFkV0JybTFNMnE2NmEzQkRKT0RQR2siLCJrdHkiOiJPS1AifX0.7ErffntbcrNMeKxLFrRhB_23cgYkLYVsCIxn-w2EV89z1U18fMellQ.tbVan58tNdoifcS2.fLnPBR20tJ7KdlT26lqt8TLBGsU6s5ou.TZqRFMTUiin899kEOm899g";
+
+        let jwe = jwe_decrypt(&JweKey::X25519(&x25519_key()), token).unwrap();
+        assert_eq!(jwe.payload, b"Hello via ECDH-ES+A256KW");
+        assert_eq!(jwe.header["kid"], "partner-2");
+    }
+
+    #[test]
+    fn header_is_authenticated() {
+        let token = jwe_encrypt_dir(&dir_key(), b"Hello World!", &kid("partner-1"));
+        let (_, rest) = token.split_once('.').unwrap();
+
+        let forged = b64(br#"{"alg":"dir","enc":"A256GCM","kid":"attacker"}"#);
+        assert_eq!(
+            jwe_decrypt(&JweKey::Direct(&dir_key()), &format!("{}.{}", forged, rest)),
+            Err(JoseError::DecryptionFailed)
+        );
+    }
+
+    #[test]
+    fn algorithm_downgrades_rejected() {
+        let token = jwe_encrypt_dir(&dir_key(), b"Hello World!", &Map::new());
+        let (_, rest) = token.split_once('.').unwrap();
+
+        for header in [
+            r#"{"alg":"none","enc":"A256GCM"}"#,
+            r#"{"alg":"dir","enc":"none"}"#,
+            r#"{"alg":"dir","enc":"A128CBC-HS256"}"#,
+            r#"{"alg":"ECDH-ES+A256KW","enc":"A256GCM"}"#,
+            r#"{"enc":"A256GCM"}"#,
+        ] {
+            let forged = format!("{}.{}", b64(header.as_bytes()), rest);
+            assert_eq!(
+                jwe_decrypt(&JweKey::Direct(&dir_key()), &forged),
+                Err(JoseError::UnsupportedAlgorithm)
+            );
+        }
+
+        /* A dir token must not be accepted by an ECDH-ES key, and vice versa */
+        assert_eq!(
+            jwe_decrypt(&JweKey::X25519(&x25519_key()), &token),
+            Err(JoseError::UnsupportedAlgorithm)
+        );
+
+        let crit = format!(
+            "{}.{}",
+            b64(br#"{"alg":"dir","enc":"A256GCM","crit":["exp"],"exp":1}"#),
+            rest
+        );
+        assert_eq!(
+            jwe_decrypt(&JweKey::Direct(&dir_key()), &crit),
+            Err(JoseError::UnsupportedCritical)
+        );
+    }
+
+    #[test]
+    fn malformed_tokens_rejected() {
+        let key = dir_key();
+        let key = JweKey::Direct(&key);
+        let token = jwe_encrypt_dir(&dir_key(), b"Hello World!", &Map::new());
+        let parts: Vec<&str> = token.split('.').collect();
+
+        for bad in [
+            String::new(),
+            "not a token".into(),
+            parts[..4].join("."),
+            format!("{}.x", token),
+            format!("!!!.{}", parts[1..].join(".")),
+            format!("{}.{}", b64(b"[1,2]"), parts[1..].join(".")),
+            format!("{}.AAAA.{}", parts[0], parts[2..].join(".")),
+            format!("{}..AAAA.{}", parts[0], parts[3..].join(".")),
+            format!("{}.{}", parts[..4].join("."), "AAAA"),
+        ] {
+            assert_eq!(
+                jwe_decrypt(&key, &bad),
+                Err(JoseError::Malformed),
+                "{}",
+                bad
+            );
+        }
+    }
+}
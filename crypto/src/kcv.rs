@@ -0,0 +1,51 @@
+/* Key check values: a short fingerprint of a key, stored alongside data
+ * so a wrong key is caught before any decryption is attempted.
+ *
+ *   kcv = HMAC-SHA256(key, "rcli key check value v1")[..3]
+ *
+ * The traditional KCV (the first bytes of E(key, zeros)) is a block of
+ * real cipher output under the key; an HMAC over a fixed label gives
+ * nothing usable with the key's cipher. Three bytes only reveal 24 bits
+ * about the key, and a random wrong key still slips through one time in
+ * 2^24, which is fine since the AEAD tags remain the actual check. */
+use ring::hmac;
+
+/// Length of a key check value.
+pub const KCV_LEN: usize = 3;
+
+const KCV_LABEL: &[u8] = b"rcli key check value v1";
+
+/// The check value for `key`.
+pub fn key_check_value(key: &[u8]) -> [u8; KCV_LEN] {
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), KCV_LABEL);
+    let mut kcv = [0u8; KCV_LEN];
+    kcv.copy_from_slice(&tag.as_ref()[..KCV_LEN]);
+    kcv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // python3 -c 'import hmac; print(hmac.new(bytes([0x42]*32),
+    //     b"rcli key check value v1", "sha256").hexdigest()[:6])'
+    #[test]
+    fn known_answer() {
+        assert_eq!(key_check_value(&[0x42; 32]), [0xb2, 0xef, 0x1a]);
+    }
+
+    #[test]
+    fn one_bit_changes_value() {
+        let key = [0x42u8; 32];
+        for bit in [0, 7, 100, 255] {
+            let mut other = key;
+            other[bit / 8] ^= 1 << (bit % 8);
+            assert_ne!(
+                key_check_value(&other),
+                key_check_value(&key),
+                "bit {}",
+                bit
+            );
+        }
+    }
+}
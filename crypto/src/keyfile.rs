@@ -0,0 +1,445 @@
+/* Passphrase-protected keyfiles: a 32-byte key wrapped with
+ * ChaCha20-Poly1305 under a KEK derived from the passphrase with
+ * Argon2id (v1.3).
+ *
+ * Layout (all integers big-endian):
+ *
+ *   0..4     magic "RKEY"
+ *   4        format version (1)
+ *   5        KDF id (1 = Argon2id)
+ *   6..10    memory cost in KiB (u32)
+ *   10..14   time cost (u32)
+ *   14..18   parallelism (u32)
+ *   18..34   salt (16 bytes)
+ *   34..46   nonce (12 bytes)
+ *   46..94   wrapped key (32 bytes) || tag
+ *   94..102  checksum: SHA-256(bytes 0..94), first 8 bytes
+ *
+ * Bytes 0..46 are the AEAD's associated data, so the KDF parameters and
+ * salt cannot be swapped out. The checksum is unkeyed and only tells a
+ * damaged file apart from a wrong passphrase: both would otherwise fail
+ * the same tag check.
+ *
+ * `derive_file_key` reuses the KDF for rcli files encrypted straight
+ * under a passphrase, whose header carries the parameters and salt (see
+ * `header::EXT_PASSPHRASE`). */
+use alloc::vec::Vec;
+
+use argon2::{Argon2, Params, Version};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+use ring::rand::SystemRandom;
+use subtle::ConstantTimeEq;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::digest::{hash, HashAlgorithm};
+use crate::header::{PassphraseKdf, KDF_ARGON2ID, PASSPHRASE_SALT_LEN};
+use crate::stream::TAG_LEN;
+use crate::EntropySource;
+
+/// Identifies a keyfile.
+pub const KEYFILE_MAGIC: [u8; 4] = *b"RKEY";
+
+/// Current keyfile format version.
+pub const KEYFILE_VERSION: u8 = 1;
+
+/// Encoded keyfile length.
+pub const KEYFILE_LEN: usize = AAD_LEN + 32 + TAG_LEN + CHECKSUM_LEN;
+
+const SALT_LEN: usize = PASSPHRASE_SALT_LEN;
+const NONCE_LEN: usize = 12;
+const CHECKSUM_LEN: usize = 8;
+/* magic through nonce */
+const AAD_LEN: usize = 18 + SALT_LEN + NONCE_LEN;
+
+/* Refuse to open files demanding more than this, so a hostile keyfile
+ * cannot make us allocate unbounded memory (1 GiB) or spin forever */
+const MAX_M_COST_KIB: u32 = 1 << 20;
+const MAX_T_COST: u32 = 64;
+const MAX_P_COST: u32 = 64;
+
+/// A 32-byte secret key, zeroized on drop.
+#[derive(Clone)]
+pub struct SecretKey32([u8; 32]);
+
+impl SecretKey32 {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        SecretKey32(bytes)
+    }
+
+    /// A fresh random key.
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&SystemRandom::new())
+    }
+
+    /// As `generate`, drawing the key from `rng`.
+    pub fn generate_with_rng(rng: &dyn EntropySource) -> Self {
+        let mut bytes = [0u8; 32];
+        rng.fill(&mut bytes);
+        SecretKey32(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl PartialEq for SecretKey32 {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.ct_eq(&other.0).into()
+    }
+}
+
+impl Eq for SecretKey32 {}
+
+impl core::fmt::Debug for SecretKey32 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("SecretKey32(..)")
+    }
+}
+
+impl Drop for SecretKey32 {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Argon2id cost parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+    /// Memory in KiB; at least 8 * `p_cost`.
+    pub m_cost_kib: u32,
+    /// Number of passes.
+    pub t_cost: u32,
+    /// Lanes.
+    pub p_cost: u32,
+}
+
+impl Default for Argon2Params {
+    /// OWASP's minimum recommendation: 19 MiB, 2 passes, 1 lane.
+    fn default() -> Self {
+        Argon2Params {
+            m_cost_kib: 19 * 1024,
+            t_cost: 2,
+            p_cost: 1,
+        }
+    }
+}
+
+impl Argon2Params {
+    fn to_argon2(self) -> Result<Params, KeyfileError> {
+        if self.m_cost_kib > MAX_M_COST_KIB || self.t_cost > MAX_T_COST || self.p_cost > MAX_P_COST
+        {
+            return Err(KeyfileError::InvalidParams);
+        }
+        Params::new(self.m_cost_kib, self.t_cost, self.p_cost, Some(32))
+            .map_err(|_| KeyfileError::InvalidParams)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyfileError {
+    /* Wrong length or magic: not a keyfile */
+    Malformed,
+    UnsupportedVersion(u8),
+    UnsupportedKdf(u8),
+    /* KDF parameters out of range (or above our ceilings when opening) */
+    InvalidParams,
+    /* Checksum mismatch: the file was damaged */
+    Corrupted,
+    /* File is intact but the tag does not verify */
+    WrongPassphrase,
+}
+
+/// Wrap `key` under `passphrase`.
+pub fn seal_keyfile(
+    passphrase: &str,
+    key: &SecretKey32,
+    params: Argon2Params,
+) -> Result<Vec<u8>, KeyfileError> {
+    seal_keyfile_with_rng(passphrase, key, params, &SystemRandom::new())
+}
+
+/// As `seal_keyfile`, drawing the salt and nonce from `rng`.
+pub fn seal_keyfile_with_rng(
+    passphrase: &str,
+    key: &SecretKey32,
+    params: Argon2Params,
+    rng: &dyn EntropySource,
+) -> Result<Vec<u8>, KeyfileError> {
+    let argon2_params = params.to_argon2()?;
+    let mut salt = [0u8; SALT_LEN];
+    rng.fill(&mut salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce);
+
+    let mut out = Vec::with_capacity(KEYFILE_LEN);
+    out.extend_from_slice(&KEYFILE_MAGIC);
+    out.push(KEYFILE_VERSION);
+    out.push(KDF_ARGON2ID);
+    out.extend_from_slice(&params.m_cost_kib.to_be_bytes());
+    out.extend_from_slice(&params.t_cost.to_be_bytes());
+    out.extend_from_slice(&params.p_cost.to_be_bytes());
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+
+    let kek = derive_kek(passphrase, &salt, argon2_params)?;
+    let mut wrapped = key.as_bytes().to_vec();
+    kek.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(&out[..]),
+        &mut wrapped,
+    )
+    .map_err(|_| KeyfileError::InvalidParams)?;
+    out.extend_from_slice(&wrapped);
+
+    let checksum = hash(HashAlgorithm::Sha256, &out);
+    out.extend_from_slice(&checksum.as_bytes()[..CHECKSUM_LEN]);
+    Ok(out)
+}
+
+/// Unwrap the key in a keyfile from `seal_keyfile`.
+pub fn open_keyfile(passphrase: &str, blob: &[u8]) -> Result<SecretKey32, KeyfileError> {
+    if blob.len() < 5 || blob[..4] != KEYFILE_MAGIC {
+        return Err(KeyfileError::Malformed);
+    }
+    if blob[4] != KEYFILE_VERSION {
+        return Err(KeyfileError::UnsupportedVersion(blob[4]));
+    }
+    if blob.len() != KEYFILE_LEN {
+        return Err(KeyfileError::Malformed);
+    }
+
+    let (body, checksum) = blob.split_at(KEYFILE_LEN - CHECKSUM_LEN);
+    if hash(HashAlgorithm::Sha256, body).as_bytes()[..CHECKSUM_LEN] != *checksum {
+        return Err(KeyfileError::Corrupted);
+    }
+    if body[5] != KDF_ARGON2ID {
+        return Err(KeyfileError::UnsupportedKdf(body[5]));
+    }
+
+    let params = Argon2Params {
+        m_cost_kib: u32::from_be_bytes(body[6..10].try_into().unwrap()),
+        t_cost: u32::from_be_bytes(body[10..14].try_into().unwrap()),
+        p_cost: u32::from_be_bytes(body[14..18].try_into().unwrap()),
+    };
+    let (aad, wrapped) = body.split_at(AAD_LEN);
+    let salt = &aad[18..18 + SALT_LEN];
+    let nonce: [u8; NONCE_LEN] = aad[18 + SALT_LEN..].try_into().unwrap();
+
+    let kek = derive_kek(passphrase, salt, params.to_argon2()?)?;
+    let mut in_out = Zeroizing::new(wrapped.to_vec());
+    let key = kek
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(aad),
+            &mut in_out,
+        )
+        .map_err(|_| KeyfileError::WrongPassphrase)?;
+    Ok(SecretKey32(key.try_into().unwrap()))
+}
+
+/// Fresh parameters for a passphrase-encrypted file, with a random salt.
+pub fn passphrase_kdf(params: Argon2Params) -> PassphraseKdf {
+    passphrase_kdf_with_rng(params, &SystemRandom::new())
+}
+
+/// As `passphrase_kdf`, drawing the salt from `rng`.
+pub fn passphrase_kdf_with_rng(params: Argon2Params, rng: &dyn EntropySource) -> PassphraseKdf {
+    let mut salt = [0u8; PASSPHRASE_SALT_LEN];
+    rng.fill(&mut salt);
+    PassphraseKdf {
+        kdf: KDF_ARGON2ID,
+        m_cost_kib: params.m_cost_kib,
+        t_cost: params.t_cost,
+        p_cost: params.p_cost,
+        salt,
+    }
+}
+
+/// Derive the key of a file encrypted under `passphrase` with `kdf`. The
+/// same ceilings on the parameters apply as for keyfiles.
+pub fn derive_file_key(passphrase: &str, kdf: &PassphraseKdf) -> Result<SecretKey32, KeyfileError> {
+    if kdf.kdf != KDF_ARGON2ID {
+        return Err(KeyfileError::UnsupportedKdf(kdf.kdf));
+    }
+    let params = Argon2Params {
+        m_cost_kib: kdf.m_cost_kib,
+        t_cost: kdf.t_cost,
+        p_cost: kdf.p_cost,
+    };
+    derive_key(passphrase, &kdf.salt, params.to_argon2()?)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: Params) -> Result<SecretKey32, KeyfileError> {
+    let mut key = SecretKey32([0u8; 32]);
+    Argon2::new(argon2::Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key.0)
+        .map_err(|_| KeyfileError::InvalidParams)?;
+    Ok(key)
+}
+
+fn derive_kek(passphrase: &str, salt: &[u8], params: Params) -> Result<LessSafeKey, KeyfileError> {
+    let kek = derive_key(passphrase, salt, params)?;
+    let unbound_key = UnboundKey::new(&CHACHA20_POLY1305, kek.as_bytes())
+        .map_err(|_| KeyfileError::InvalidParams)?;
+    Ok(LessSafeKey::new(unbound_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRng;
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+    /* Cheap parameters so the tests stay fast in debug builds */
+    const FAST: Argon2Params = Argon2Params {
+        m_cost_kib: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    fn key() -> SecretKey32 {
+        SecretKey32::new(core::array::from_fn(|i| i as u8))
+    }
+
+    // Generated with seal_keyfile_with_rng(PASSPHRASE, key(), FAST, TestRng(0))
+    const GOLDEN: &[u8] = include_bytes!("../tests/keyfile_v1.key");
+
+    #[test]
+    fn golden_fixture() {
+        let sealed = seal_keyfile_with_rng(PASSPHRASE, &key(), FAST, &TestRng::new(0)).unwrap();
+        assert_eq!(sealed, GOLDEN);
+        assert_eq!(open_keyfile(PASSPHRASE, GOLDEN), Ok(key()));
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = SecretKey32::generate();
+        let params = Argon2Params {
+            m_cost_kib: 256,
+            t_cost: 2,
+            p_cost: 2,
+        };
+        let file = seal_keyfile("hunter2", &key, params).unwrap();
+        assert_eq!(file.len(), KEYFILE_LEN);
+        assert_eq!(open_keyfile("hunter2", &file), Ok(key.clone()));
+
+        /* Fresh salt and nonce each time */
+        assert_ne!(seal_keyfile("hunter2", &key, params).unwrap(), file);
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        assert_eq!(
+            open_keyfile("Correct horse battery staple", GOLDEN),
+            Err(KeyfileError::WrongPassphrase)
+        );
+        assert_eq!(open_keyfile("", GOLDEN), Err(KeyfileError::WrongPassphrase));
+    }
+
+    #[test]
+    fn salt_byte_flip_is_corruption() {
+        let mut file = GOLDEN.to_vec();
+        file[18] ^= 0x01;
+        assert_eq!(
+            open_keyfile(PASSPHRASE, &file),
+            Err(KeyfileError::Corrupted)
+        );
+
+        /* Even with a matching checksum, the salt is bound by the AAD */
+        let checksum = hash(HashAlgorithm::Sha256, &file[..KEYFILE_LEN - CHECKSUM_LEN]);
+        file[KEYFILE_LEN - CHECKSUM_LEN..].copy_from_slice(&checksum.as_bytes()[..CHECKSUM_LEN]);
+        assert_eq!(
+            open_keyfile(PASSPHRASE, &file),
+            Err(KeyfileError::WrongPassphrase)
+        );
+    }
+
+    #[test]
+    fn every_byte_is_checked() {
+        for i in 0..KEYFILE_LEN {
+            let mut file = GOLDEN.to_vec();
+            file[i] ^= 0x80;
+            assert!(open_keyfile(PASSPHRASE, &file).is_err(), "byte {}", i);
+        }
+    }
+
+    #[test]
+    fn malformed_and_unsupported() {
+        assert_eq!(open_keyfile(PASSPHRASE, b""), Err(KeyfileError::Malformed));
+        assert_eq!(
+            open_keyfile(PASSPHRASE, &GOLDEN[..KEYFILE_LEN - 1]),
+            Err(KeyfileError::Malformed)
+        );
+
+        let mut file = GOLDEN.to_vec();
+        file[4] = 2;
+        assert_eq!(
+            open_keyfile(PASSPHRASE, &file),
+            Err(KeyfileError::UnsupportedVersion(2))
+        );
+
+        /* Hostile parameters with a valid checksum are refused before any
+         * memory is allocated */
+        let mut file = GOLDEN.to_vec();
+        file[6..10].copy_from_slice(&u32::MAX.to_be_bytes());
+        let checksum = hash(HashAlgorithm::Sha256, &file[..KEYFILE_LEN - CHECKSUM_LEN]);
+        file[KEYFILE_LEN - CHECKSUM_LEN..].copy_from_slice(&checksum.as_bytes()[..CHECKSUM_LEN]);
+        assert_eq!(
+            open_keyfile(PASSPHRASE, &file),
+            Err(KeyfileError::InvalidParams)
+        );
+
+        assert_eq!(
+            seal_keyfile(
+                PASSPHRASE,
+                &key(),
+                Argon2Params {
+                    m_cost_kib: 4,
+                    ..FAST
+                }
+            ),
+            Err(KeyfileError::InvalidParams)
+        );
+    }
+
+    #[test]
+    fn file_key_from_header_parameters() {
+        let kdf = passphrase_kdf_with_rng(FAST, &TestRng::new(1));
+        assert_eq!(kdf.kdf, KDF_ARGON2ID);
+        assert_eq!((kdf.m_cost_kib, kdf.t_cost, kdf.p_cost), (64, 1, 1));
+
+        /* The keyfile KEK is the same derivation, so the golden fixture
+         * pins it; here only determinism and sensitivity are checked */
+        let key = derive_file_key(PASSPHRASE, &kdf).unwrap();
+        assert_eq!(derive_file_key(PASSPHRASE, &kdf), Ok(key.clone()));
+        assert_ne!(
+            derive_file_key("correct horse battery stapler", &kdf),
+            Ok(key.clone())
+        );
+        let other_salt = passphrase_kdf_with_rng(FAST, &TestRng::new(2));
+        assert_ne!(other_salt.salt, kdf.salt);
+        assert_ne!(derive_file_key(PASSPHRASE, &other_salt), Ok(key));
+        assert_ne!(passphrase_kdf(FAST).salt, passphrase_kdf(FAST).salt);
+
+        assert_eq!(
+            derive_file_key(PASSPHRASE, &PassphraseKdf { kdf: 2, ..kdf }),
+            Err(KeyfileError::UnsupportedKdf(2))
+        );
+        assert_eq!(
+            derive_file_key(
+                PASSPHRASE,
+                &PassphraseKdf {
+                    m_cost_kib: u32::MAX,
+                    ..kdf
+                }
+            ),
+            Err(KeyfileError::InvalidParams)
+        );
+    }
+
+    #[test]
+    fn debug_hides_key() {
+        assert_eq!(format!("{:?}", key()), "SecretKey32(..)");
+    }
+}
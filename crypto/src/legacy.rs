@@ -0,0 +1,158 @@
+/* LEGACY: RC4 + HMAC-SHA256 encrypt-then-MAC, for reading records written by
+ * older systems. Do not use for new data -- prefer ChaCha20-Poly1305.
+ *
+ * Record layout: RC4-drop768(enc_key, data) || HMAC-SHA256(mac_key, ciphertext)
+ *
+ * The first 768 bytes of RC4 keystream are discarded to skip the most
+ * heavily biased output. Decryption verifies the MAC over the ciphertext
+ * before a single byte of plaintext is produced. */
+use alloc::vec::Vec;
+
+use ring::hmac;
+
+use crate::{ErrorStates, Rc4};
+
+/// Length of the HMAC-SHA256 tag appended to every record.
+pub const RC4_HMAC_TAG_LEN: usize = 32;
+
+/* Keystream bytes discarded before use (RC4-drop768) */
+const RC4_DROP: usize = 768;
+
+fn rc4_drop768(enc_key: &[u8]) -> Result<Rc4, ErrorStates> {
+    if !(5..=256).contains(&enc_key.len()) {
+        return Err(ErrorStates::KeyInitializationFailed);
+    }
+
+    let mut rc4 = Rc4::new(enc_key);
+    for _ in 0..RC4_DROP {
+        rc4.prga_next();
+    }
+    Ok(rc4)
+}
+
+fn check_keys(enc_key: &[u8], mac_key: &[u8]) -> Result<(), ErrorStates> {
+    if enc_key == mac_key {
+        return Err(ErrorStates::KeyReuse);
+    }
+    Ok(())
+}
+
+/// Encrypt `data` with RC4-drop768 and append an HMAC-SHA256 tag over the
+/// ciphertext. The encryption and MAC keys must differ.
+pub fn rc4_hmac_encrypt(
+    enc_key: &[u8],
+    mac_key: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>, ErrorStates> {
+    check_keys(enc_key, mac_key)?;
+    let mut rc4 = rc4_drop768(enc_key)?;
+
+    let mut out = Vec::with_capacity(data.len() + RC4_HMAC_TAG_LEN);
+    out.extend_from_slice(data);
+    rc4.apply_keystream(&mut out);
+
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, mac_key), &out);
+    out.extend_from_slice(tag.as_ref());
+    Ok(out)
+}
+
+/// Verify the trailing HMAC-SHA256 tag (in constant time) and only then
+/// decrypt the record.
+pub fn rc4_hmac_decrypt(
+    enc_key: &[u8],
+    mac_key: &[u8],
+    record: &[u8],
+) -> Result<Vec<u8>, ErrorStates> {
+    check_keys(enc_key, mac_key)?;
+    if record.len() < RC4_HMAC_TAG_LEN {
+        return Err(ErrorStates::DecryptionFailed);
+    }
+
+    let (ciphertext, tag) = record.split_at(record.len() - RC4_HMAC_TAG_LEN);
+    hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, mac_key), ciphertext, tag)
+        .map_err(|_| ErrorStates::DecryptionFailed)?;
+
+    let mut rc4 = rc4_drop768(enc_key)?;
+    let mut out = ciphertext.to_vec();
+    rc4.apply_keystream(&mut out);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENC_KEY: [u8; 16] = [
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+        0x10,
+    ];
+
+    fn mac_key() -> [u8; 32] {
+        core::array::from_fn(|i| 0x20 + i as u8)
+    }
+
+    #[test]
+    fn round_trip() {
+        let record = rc4_hmac_encrypt(&ENC_KEY, &mac_key(), b"Hello World!").unwrap();
+        assert_eq!(record.len(), 12 + RC4_HMAC_TAG_LEN);
+
+        let plaintext = rc4_hmac_decrypt(&ENC_KEY, &mac_key(), &record).unwrap();
+        assert_eq!(plaintext, b"Hello World!");
+    }
+
+    #[test]
+    fn identical_keys_rejected() {
+        let key = [0x42; 32];
+        assert_eq!(
+            rc4_hmac_encrypt(&key, &key, b"Hello World!"),
+            Err(ErrorStates::KeyReuse)
+        );
+        assert_eq!(
+            rc4_hmac_decrypt(&key, &key, &[0u8; 64]),
+            Err(ErrorStates::KeyReuse)
+        );
+    }
+
+    #[test]
+    fn stripped_mac_rejected() {
+        let record = rc4_hmac_encrypt(&ENC_KEY, &mac_key(), b"Hello World!").unwrap();
+
+        let stripped = &record[..record.len() - RC4_HMAC_TAG_LEN];
+        assert_eq!(
+            rc4_hmac_decrypt(&ENC_KEY, &mac_key(), stripped),
+            Err(ErrorStates::DecryptionFailed)
+        );
+    }
+
+    #[test]
+    fn bit_flip_rejected() {
+        let record = rc4_hmac_encrypt(&ENC_KEY, &mac_key(), b"Hello World!").unwrap();
+
+        for i in 0..record.len() {
+            let mut tampered = record.clone();
+            tampered[i] ^= 0x01;
+            assert_eq!(
+                rc4_hmac_decrypt(&ENC_KEY, &mac_key(), &tampered),
+                Err(ErrorStates::DecryptionFailed)
+            );
+        }
+    }
+
+    // Record captured from the legacy system (RC4-drop768 + HMAC-SHA256)
+    #[test]
+    fn legacy_fixture_decrypts() {
+        #[rustfmt::skip]
+        let record: [u8; 49] = [
+            0x80, 0xae, 0x86, 0x5c, 0x82, 0x85, 0xe9, 0x6e,
+            0x74, 0xc3, 0xdd, 0x1e, 0x6f, 0xe8, 0xd9, 0x79,
+            0x9a, 0xdf, 0xf5, 0x61, 0x88, 0xcb, 0x49, 0x77,
+            0xed, 0xbb, 0x57, 0x3f, 0xa8, 0x33, 0x77, 0x45,
+            0x0b, 0x48, 0xc7, 0x24, 0xb9, 0xf2, 0x2c, 0x41,
+            0xca, 0x8d, 0x68, 0x36, 0xf4, 0x08, 0xe6, 0xd0,
+            0xe1,
+        ];
+
+        let plaintext = rc4_hmac_decrypt(&ENC_KEY, &mac_key(), &record).unwrap();
+        assert_eq!(plaintext, b"legacy record #42");
+    }
+}
@@ -1,5 +1,5 @@
-/* Unless doing a test build, make no assumptions about platform */
-#![cfg_attr(not(test), no_std)]
+/* Unless doing a test build (or std is requested), make no assumptions about platform */
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 /* Ensure no unsafe code block in entire crate */
 #![forbid(unsafe_code)]
 
@@ -11,6 +11,203 @@ use alloc::vec::Vec;
 mod rc4;
 pub use crate::rc4::*;
 
+/* canonical multi-field associated data */
+mod aad;
+pub use crate::aad::*;
+
+/* key-committing AEAD wrapper */
+mod committing;
+pub use crate::committing::*;
+
+/* deterministic AES-SIV mode */
+#[cfg(feature = "siv")]
+mod siv;
+#[cfg(feature = "siv")]
+pub use crate::siv::*;
+
+/* SHA-2 digests and HMAC, one-shot or incremental */
+pub mod digest;
+
+/* CRC-32 and xxHash64 checksums */
+pub mod checksum;
+
+/* length padding to hide plaintext sizes */
+mod padding;
+pub use crate::padding::*;
+
+/* HOTP/TOTP one-time passwords */
+pub mod otp;
+
+/* key check values for early wrong-key detection */
+mod kcv;
+pub use crate::kcv::*;
+
+/* re-encrypting blobs under a new key */
+mod rekey;
+pub use crate::rekey::*;
+
+/* per-key seal limits */
+mod limited;
+pub use crate::limited::*;
+
+/* legacy RC4 + HMAC interop, read-compatibility only */
+pub mod legacy;
+
+/* versioned file header and chunked STREAM AEAD */
+pub mod header;
+pub mod stream;
+
+/* Shamir secret sharing for splitting keys */
+pub mod shamir;
+
+/* seeded, deterministic randomness for known-answer tests */
+#[cfg(any(test, feature = "test-utils"))]
+pub mod testing;
+
+/* cross-checks against RustCrypto's chacha20poly1305 */
+#[cfg(test)]
+mod differential;
+
+/* file/stream helpers, std only */
+#[cfg(feature = "std")]
+pub mod fs;
+
+/* stream chunks sealed and opened on several threads */
+#[cfg(feature = "parallel")]
+mod pipeline;
+
+/* hashed listings of directory trees, std only */
+#[cfg(feature = "std")]
+pub mod manifest;
+
+/* directory trees packed into one encrypted stream, std only */
+#[cfg(feature = "std")]
+pub mod archive;
+
+/* encrypted streams cut into parts of a capped size, std only */
+#[cfg(feature = "std")]
+pub mod split;
+
+/* Fernet tokens (Python cryptography interop) */
+#[cfg(feature = "fernet")]
+mod fernet;
+#[cfg(feature = "fernet")]
+pub use crate::fernet::*;
+
+/* JWE compact serialization (JOSE interop) */
+#[cfg(feature = "jose")]
+mod jose;
+#[cfg(feature = "jose")]
+pub use crate::jose::*;
+
+/* PASETO v4.local tokens */
+#[cfg(feature = "paseto")]
+mod paseto;
+#[cfg(feature = "paseto")]
+pub use crate::paseto::*;
+
+/* age v1 files with X25519 recipients */
+#[cfg(feature = "age")]
+mod age;
+#[cfg(feature = "age")]
+pub use crate::age::*;
+
+/* minisign-compatible signatures */
+#[cfg(feature = "minisign")]
+mod minisign;
+#[cfg(feature = "minisign")]
+pub use crate::minisign::*;
+
+/* BIP39 mnemonic phrases for key backups */
+#[cfg(feature = "mnemonic")]
+mod mnemonic;
+#[cfg(feature = "mnemonic")]
+pub use crate::mnemonic::*;
+
+/* diceware passphrases from the EFF large wordlist */
+#[cfg(feature = "diceware")]
+mod diceware;
+#[cfg(feature = "diceware")]
+pub use crate::diceware::*;
+
+/* random passwords from character classes */
+#[cfg(feature = "password")]
+mod password;
+#[cfg(feature = "password")]
+pub use crate::password::*;
+
+/* random URL-safe tokens for API keys and identifiers */
+#[cfg(feature = "token")]
+mod token;
+#[cfg(feature = "token")]
+pub use crate::token::*;
+
+/* compression before encryption */
+#[cfg(feature = "compress")]
+mod compress;
+#[cfg(feature = "compress")]
+pub use crate::compress::*;
+
+/* passphrase-protected keyfiles (Argon2id) */
+#[cfg(feature = "keyfile")]
+mod keyfile;
+#[cfg(feature = "keyfile")]
+pub use crate::keyfile::*;
+
+/* passphrase strength estimation */
+#[cfg(feature = "strength")]
+mod strength;
+#[cfg(feature = "strength")]
+pub use crate::strength::*;
+
+/* X25519 + ML-KEM-768 hybrid sealing */
+#[cfg(feature = "ml-kem")]
+mod hybrid;
+#[cfg(feature = "ml-kem")]
+pub use crate::hybrid::*;
+
+/* rcli files encrypted to recipients' hybrid public keys, std only */
+#[cfg(feature = "recipient")]
+mod recipient;
+#[cfg(feature = "recipient")]
+pub use crate::recipient::*;
+
+/* ML-DSA-65 and Ed25519 + ML-DSA-65 hybrid signatures */
+#[cfg(feature = "ml-dsa")]
+mod mldsa;
+#[cfg(feature = "ml-dsa")]
+pub use crate::mldsa::*;
+
+/* Ed25519 signatures over a streamed prehash, std only */
+#[cfg(feature = "signing")]
+mod signing;
+#[cfg(feature = "signing")]
+pub use crate::signing::*;
+
+/* bare AES-256-CTR, interop only */
+#[cfg(feature = "aes-ctr")]
+mod aes_ctr;
+#[cfg(feature = "aes-ctr")]
+pub use crate::aes_ctr::*;
+
+/* AES-256-CBC with PKCS#7 padding, interop only */
+#[cfg(feature = "legacy-modes")]
+mod aes_cbc;
+#[cfg(feature = "legacy-modes")]
+pub use crate::aes_cbc::*;
+
+/* BLAKE3/Bao verified streaming for partial reads */
+#[cfg(feature = "blake3")]
+mod verified;
+#[cfg(feature = "blake3")]
+pub use crate::verified::*;
+
+/* ASCII armor for RCLI files */
+#[cfg(feature = "armor")]
+mod armor;
+#[cfg(feature = "armor")]
+pub use crate::armor::*;
+
 /* The RFC for CHACHA20_POLY1305
  * [RFC 8439]: https://tools.ietf.org/html/rfc8439 */
 use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
@@ -19,18 +216,54 @@ use ring::rand::{SecureRandom, SystemRandom};
 pub fn generate_key(key_bytes: &mut [u8]) {
     let rng = SystemRandom::new();
 
-    rng.fill(key_bytes).unwrap();
+    SecureRandom::fill(&rng, key_bytes).unwrap();
 }
 
 pub fn generate_nonce(nonce_bytes: &mut [u8]) {
     let rng = SystemRandom::new();
-    rng.fill(nonce_bytes).unwrap();
+    SecureRandom::fill(&rng, nonce_bytes).unwrap();
 }
 
+/// A source of random bytes for keys, nonces and salts. Every randomized
+/// function has a `_with_rng` variant taking one; the plain variants use
+/// `SystemRandom`. Implementations must be cryptographically secure outside
+/// of tests (see `testing::TestRng`).
+pub trait EntropySource {
+    /// Fill `dest` entirely; panics if the source fails, like `generate_key`.
+    fn fill(&self, dest: &mut [u8]);
+}
+
+impl EntropySource for SystemRandom {
+    fn fill(&self, dest: &mut [u8]) {
+        SecureRandom::fill(self, dest).unwrap();
+    }
+}
+
+/// A keystream generator: XORs the next `data.len()` keystream bytes into
+/// `data`, so the same call encrypts and decrypts. Implemented by `Rc4`
+/// and, with the `aes-ctr` feature, `Aes256Ctr`.
+pub trait StreamCipher {
+    fn apply_keystream(&mut self, data: &mut [u8]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorStates {
     KeyInitializationFailed,
     EncryptionFailed,
     DecryptionFailed,
+    /* Key-committing open: the blob was not sealed under this key */
+    CommitmentMismatch,
+    /* The same key was supplied for two roles that must be independent */
+    KeyReuse,
+    /* Chunked stream ended before its final chunk */
+    StreamTruncated,
+    /* Key has performed its maximum number of seal operations */
+    KeyExhausted,
+    /* Key does not match the key check value stored with the data */
+    WrongKey,
+    /* Associated data does not match the check value stored with the data,
+     * or was given for data bound to none (or the reverse) */
+    AadMismatch,
 }
 
 pub fn chacha20_poly1305_cipher(
@@ -38,16 +271,27 @@ pub fn chacha20_poly1305_cipher(
     nonce_bytes: &[u8; 12],
     data: Vec<u8>,
     encrypt: bool,
+) -> Result<Vec<u8>, ErrorStates> {
+    chacha20_poly1305_cipher_with_aad(key_bytes, nonce_bytes, &[], data, encrypt)
+}
+
+/// As `chacha20_poly1305_cipher`, authenticating `aad` alongside the data.
+pub fn chacha20_poly1305_cipher_with_aad(
+    key_bytes: &[u8],
+    nonce_bytes: &[u8; 12],
+    aad: &[u8],
+    data: Vec<u8>,
+    encrypt: bool,
 ) -> Result<Vec<u8>, ErrorStates> {
     let algorithm = &CHACHA20_POLY1305;
 
-    let unbound_key = UnboundKey::new(algorithm, key_bytes)
-        .map_err(|_| ErrorStates::KeyInitializationFailed)?;
+    let unbound_key =
+        UnboundKey::new(algorithm, key_bytes).map_err(|_| ErrorStates::KeyInitializationFailed)?;
 
     let key = LessSafeKey::new(unbound_key);
 
     let nonce = Nonce::assume_unique_for_key(*nonce_bytes);
-    let aad = Aad::empty();
+    let aad = Aad::from(aad);
 
     let mut in_out = data;
 
@@ -0,0 +1,231 @@
+/* Per-key operation limits.
+ *
+ * An AEAD key should only seal a bounded number of messages: random
+ * 96-bit nonces collide with probability ~n^2 / 2^97, and NIST SP 800-38D
+ * caps random-nonce GCM at 2^32 invocations per key. `KeyUsage` counts
+ * seals against a caller-chosen maximum; once reached, further seals fail
+ * with `ErrorStates::KeyExhausted` and the key must be rotated. Opening is
+ * never limited, so data sealed before the limit stays readable.
+ *
+ * The counter is plain state: persist `state()` alongside the key and
+ * restore it with `from_state` (or `StreamEncryptor::with_usage`) so a
+ * restart does not reset it. */
+use alloc::vec::Vec;
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+
+use crate::ErrorStates;
+
+/// Seal count for one key and the most it may perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyUsage {
+    pub used: u64,
+    pub max_operations: u64,
+}
+
+impl KeyUsage {
+    pub fn new(max_operations: u64) -> Self {
+        KeyUsage {
+            used: 0,
+            max_operations,
+        }
+    }
+
+    /// No practical limit (2^64 - 1 operations).
+    pub fn unlimited() -> Self {
+        Self::new(u64::MAX)
+    }
+
+    /// Seals left before the key is exhausted.
+    pub fn remaining(&self) -> u64 {
+        self.max_operations.saturating_sub(self.used)
+    }
+
+    /* Fail unless another operation is allowed; does not count it */
+    pub(crate) fn check(&self) -> Result<(), ErrorStates> {
+        if self.remaining() == 0 {
+            return Err(ErrorStates::KeyExhausted);
+        }
+        Ok(())
+    }
+
+    /* Count an operation that has already passed `check` */
+    pub(crate) fn record(&mut self) {
+        self.used += 1;
+    }
+}
+
+/// A ChaCha20-Poly1305 key that refuses to seal more than a fixed number
+/// of messages.
+pub struct LimitedKey {
+    key: LessSafeKey,
+    usage: KeyUsage,
+}
+
+impl LimitedKey {
+    pub fn new(key_bytes: &[u8], max_operations: u64) -> Result<Self, ErrorStates> {
+        Self::from_state(key_bytes, KeyUsage::new(max_operations))
+    }
+
+    /// Restore a key whose usage was saved with [`LimitedKey::state`].
+    pub fn from_state(key_bytes: &[u8], usage: KeyUsage) -> Result<Self, ErrorStates> {
+        let unbound_key = UnboundKey::new(&CHACHA20_POLY1305, key_bytes)
+            .map_err(|_| ErrorStates::KeyInitializationFailed)?;
+        Ok(LimitedKey {
+            key: LessSafeKey::new(unbound_key),
+            usage,
+        })
+    }
+
+    pub fn state(&self) -> KeyUsage {
+        self.usage
+    }
+
+    /// Seals left before `seal` starts returning `KeyExhausted`.
+    pub fn remaining(&self) -> u64 {
+        self.usage.remaining()
+    }
+
+    /// Seal `data`, returning ciphertext || tag. Only successful seals count
+    /// towards the limit.
+    pub fn seal(
+        &mut self,
+        nonce_bytes: &[u8; 12],
+        aad: &[u8],
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, ErrorStates> {
+        self.usage.check()?;
+
+        let mut in_out = data;
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(*nonce_bytes),
+                Aad::from(aad),
+                &mut in_out,
+            )
+            .map_err(|_| ErrorStates::EncryptionFailed)?;
+        self.usage.record();
+        Ok(in_out)
+    }
+
+    /// Open ciphertext || tag. Not limited.
+    pub fn open(
+        &self,
+        nonce_bytes: &[u8; 12],
+        aad: &[u8],
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, ErrorStates> {
+        let mut in_out = data;
+        let len = self
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(*nonce_bytes),
+                Aad::from(aad),
+                &mut in_out,
+            )
+            .map_err(|_| ErrorStates::DecryptionFailed)?
+            .len();
+        in_out.truncate(len);
+        Ok(in_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chacha20_poly1305_cipher_with_aad;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    fn nonce(i: u8) -> [u8; 12] {
+        [i; 12]
+    }
+
+    #[test]
+    fn limit_of_three() {
+        let mut key = LimitedKey::new(&KEY, 3).unwrap();
+        let mut sealed = Vec::new();
+        for i in 0..3 {
+            assert_eq!(key.remaining(), 3 - i as u64);
+            sealed.push(key.seal(&nonce(i), b"aad", b"message".to_vec()).unwrap());
+        }
+        assert_eq!(key.remaining(), 0);
+        assert_eq!(
+            key.seal(&nonce(3), b"aad", b"message".to_vec()),
+            Err(ErrorStates::KeyExhausted)
+        );
+        assert_eq!(key.state().used, 3);
+
+        /* Opening still works after exhaustion */
+        for (i, ct) in sealed.into_iter().enumerate() {
+            assert_eq!(key.open(&nonce(i as u8), b"aad", ct).unwrap(), b"message");
+        }
+    }
+
+    #[test]
+    fn matches_one_shot_cipher() {
+        let mut key = LimitedKey::new(&KEY, 1).unwrap();
+        let sealed = key
+            .seal(&nonce(9), b"aad", b"Hello World!".to_vec())
+            .unwrap();
+        assert_eq!(
+            chacha20_poly1305_cipher_with_aad(
+                &KEY,
+                &nonce(9),
+                b"aad",
+                b"Hello World!".to_vec(),
+                true
+            ),
+            Ok(sealed.clone())
+        );
+        assert_eq!(
+            key.open(&nonce(9), b"other", sealed),
+            Err(ErrorStates::DecryptionFailed)
+        );
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut key = LimitedKey::new(&KEY, 3).unwrap();
+        key.seal(&nonce(0), b"", Vec::new()).unwrap();
+        key.seal(&nonce(1), b"", Vec::new()).unwrap();
+
+        let state = key.state();
+        assert_eq!(
+            state,
+            KeyUsage {
+                used: 2,
+                max_operations: 3
+            }
+        );
+
+        let mut restored = LimitedKey::from_state(&KEY, state).unwrap();
+        assert_eq!(restored.remaining(), 1);
+        restored.seal(&nonce(2), b"", Vec::new()).unwrap();
+        assert_eq!(
+            restored.seal(&nonce(3), b"", Vec::new()),
+            Err(ErrorStates::KeyExhausted)
+        );
+    }
+
+    #[test]
+    fn zero_limit_and_bad_key() {
+        let mut key = LimitedKey::new(&KEY, 0).unwrap();
+        assert_eq!(
+            key.seal(&nonce(0), b"", Vec::new()),
+            Err(ErrorStates::KeyExhausted)
+        );
+        assert!(matches!(
+            LimitedKey::new(&KEY[..16], 3),
+            Err(ErrorStates::KeyInitializationFailed)
+        ));
+        assert_eq!(KeyUsage::unlimited().remaining(), u64::MAX);
+
+        /* A state restored with used > max is simply exhausted */
+        let over = KeyUsage {
+            used: 5,
+            max_operations: 3,
+        };
+        assert_eq!(LimitedKey::from_state(&KEY, over).unwrap().remaining(), 0);
+    }
+}
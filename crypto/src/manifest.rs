@@ -0,0 +1,509 @@
+/* Hashed listings of a directory tree, for spotting files that were
+ * changed, removed or added after the fact.
+ *
+ * Paths are stored relative to the root, '/'-separated and UTF-8, and
+ * sorted bytewise on that form, so a manifest created on one platform
+ * verifies on another. Only regular files are listed; symlinks and other
+ * special files are skipped.
+ *
+ * Encoding (all integers big-endian):
+ *
+ *   0..4    magic "RMAN"
+ *   4       format version (1)
+ *   5       digest algorithm (1 = SHA-256, 2 = SHA-384, 3 = SHA-512,
+ *           4 = CRC-32, 5 = xxHash64)
+ *   6       flags (bit 0: HMAC tag appended)
+ *   7..11   entry count (u32)
+ *   11..    entries: path length (u16), path, size (u64), digest
+ *   then    HMAC over everything before it, if flagged (same algorithm)
+ *
+ * Without the HMAC the manifest only detects accidental damage; anyone
+ * able to change the files could rewrite it too. The checksum algorithms
+ * are much faster on large trees but only ever detect accidents, even
+ * with the HMAC, since a file can be altered to keep its checksum. The
+ * HMAC over a checksum manifest uses SHA-256. */
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::digest::{digest_reader, DigestAlg, HashAlgorithm, HmacContext, Output};
+
+/// Identifies a manifest.
+pub const MANIFEST_MAGIC: [u8; 4] = *b"RMAN";
+
+/// Current manifest format version.
+pub const MANIFEST_VERSION: u8 = 1;
+
+const FLAG_HMAC: u8 = 1;
+const FIXED_LEN: usize = 11;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Relative, '/'-separated path.
+    pub path: String,
+    pub size: u64,
+    pub digest: Output,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub algorithm: DigestAlg,
+    /// Sorted by `path`.
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Differences between a manifest and the tree it was checked against.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Listed, but no longer present.
+    pub missing: Vec<String>,
+    /// Present, but not listed.
+    pub added: Vec<String>,
+    /// Listed, with a different size or digest.
+    pub modified: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.added.is_empty() && self.modified.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestError {
+    /* Truncated, trailing bytes, bad magic, or a non-UTF-8 path */
+    Malformed,
+    UnsupportedVersion(u8),
+    UnknownAlgorithm(u8),
+    /* The manifest carries an HMAC; read it with a key */
+    KeyRequired,
+    /* A key was given but the manifest carries no HMAC */
+    NotAuthenticated,
+    MacMismatch,
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Malformed => write!(f, "malformed manifest"),
+            ManifestError::UnsupportedVersion(v) => {
+                write!(f, "unsupported manifest version {}", v)
+            }
+            ManifestError::UnknownAlgorithm(id) => {
+                write!(f, "unknown manifest digest algorithm {}", id)
+            }
+            ManifestError::KeyRequired => write!(f, "manifest is authenticated; a key is required"),
+            ManifestError::NotAuthenticated => write!(f, "manifest is not authenticated"),
+            ManifestError::MacMismatch => write!(f, "manifest authentication failed"),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+fn algorithm_id(alg: DigestAlg) -> u8 {
+    match alg {
+        DigestAlg::Sha256 => 1,
+        DigestAlg::Sha384 => 2,
+        DigestAlg::Sha512 => 3,
+        DigestAlg::Crc32 => 4,
+        DigestAlg::XxHash64 => 5,
+        #[cfg(feature = "blake3")]
+        DigestAlg::Blake3 => 6,
+    }
+}
+
+fn algorithm_from_id(id: u8) -> Result<DigestAlg, ManifestError> {
+    match id {
+        1 => Ok(DigestAlg::Sha256),
+        2 => Ok(DigestAlg::Sha384),
+        3 => Ok(DigestAlg::Sha512),
+        4 => Ok(DigestAlg::Crc32),
+        5 => Ok(DigestAlg::XxHash64),
+        #[cfg(feature = "blake3")]
+        6 => Ok(DigestAlg::Blake3),
+        _ => Err(ManifestError::UnknownAlgorithm(id)),
+    }
+}
+
+/* The HMAC follows the digest, falling back to SHA-256 for checksums */
+fn mac_algorithm(alg: DigestAlg) -> HashAlgorithm {
+    alg.hash_algorithm().unwrap_or(HashAlgorithm::Sha256)
+}
+
+impl Manifest {
+    /// Hash every regular file under `root`.
+    pub fn create(root: &Path, alg: impl Into<DigestAlg>) -> io::Result<Manifest> {
+        let alg = alg.into();
+        let mut entries = Vec::new();
+        for (path, size) in walk(root)? {
+            let file = File::open(root.join(&path))?;
+            let digest = digest_reader(alg, BufReader::new(file))?;
+            entries.push(ManifestEntry { path, size, digest });
+        }
+        Ok(Manifest {
+            algorithm: alg,
+            entries,
+        })
+    }
+
+    /// Compare the tree under `root` against this manifest. Files whose
+    /// size differs are reported without being hashed.
+    pub fn verify(&self, root: &Path) -> io::Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+        let found = walk(root)?;
+        let mut listed = self.entries.iter().peekable();
+        let mut present = found.into_iter().peekable();
+
+        /* Both lists are sorted, so walk them together */
+        loop {
+            match (listed.peek(), present.peek()) {
+                (None, None) => break,
+                (Some(entry), Some((path, _))) if entry.path == *path => {
+                    let (path, size) = present.next().unwrap();
+                    let entry = listed.next().unwrap();
+                    if size != entry.size || {
+                        let file = File::open(root.join(&path))?;
+                        digest_reader(self.algorithm, BufReader::new(file))? != entry.digest
+                    } {
+                        report.modified.push(path);
+                    }
+                }
+                (Some(entry), Some((path, _))) if entry.path < *path => {
+                    report.missing.push(listed.next().unwrap().path.clone());
+                }
+                (Some(entry), None) => {
+                    report.missing.push(entry.path.clone());
+                    listed.next();
+                }
+                (_, Some(_)) => report.added.push(present.next().unwrap().0),
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode(0)
+    }
+
+    /// Encode with an HMAC under `key`, so the listing itself cannot be
+    /// edited without the key.
+    pub fn to_bytes_authenticated(&self, key: &[u8]) -> Vec<u8> {
+        let mut out = self.encode(FLAG_HMAC);
+        let mut mac = HmacContext::new(mac_algorithm(self.algorithm), key);
+        mac.update(&out);
+        out.extend_from_slice(mac.finalize().as_bytes());
+        out
+    }
+
+    /// Decode a manifest written by `to_bytes`.
+    pub fn from_bytes(data: &[u8]) -> Result<Manifest, ManifestError> {
+        let (manifest, flags, body_len) = Self::decode(data)?;
+        if flags & FLAG_HMAC != 0 {
+            return Err(ManifestError::KeyRequired);
+        }
+        if body_len != data.len() {
+            return Err(ManifestError::Malformed);
+        }
+        Ok(manifest)
+    }
+
+    /// Decode and authenticate a manifest written by
+    /// `to_bytes_authenticated`.
+    pub fn from_bytes_authenticated(data: &[u8], key: &[u8]) -> Result<Manifest, ManifestError> {
+        let (manifest, flags, body_len) = Self::decode(data)?;
+        if flags & FLAG_HMAC == 0 {
+            return Err(ManifestError::NotAuthenticated);
+        }
+        let mac_alg = mac_algorithm(manifest.algorithm);
+        if data.len() != body_len + mac_alg.output_len() {
+            return Err(ManifestError::Malformed);
+        }
+        let (body, tag) = data.split_at(body_len);
+        let mut mac = HmacContext::new(mac_alg, key);
+        mac.update(body);
+        mac.verify(tag).map_err(|_| ManifestError::MacMismatch)?;
+        Ok(manifest)
+    }
+
+    fn encode(&self, flags: u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&MANIFEST_MAGIC);
+        out.push(MANIFEST_VERSION);
+        out.push(algorithm_id(self.algorithm));
+        out.push(flags);
+        out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in &self.entries {
+            out.extend_from_slice(&(entry.path.len() as u16).to_be_bytes());
+            out.extend_from_slice(entry.path.as_bytes());
+            out.extend_from_slice(&entry.size.to_be_bytes());
+            out.extend_from_slice(entry.digest.as_bytes());
+        }
+        out
+    }
+
+    /* Parse up to the end of the entries; returns where they end */
+    fn decode(data: &[u8]) -> Result<(Manifest, u8, usize), ManifestError> {
+        if data.len() < FIXED_LEN || data[..4] != MANIFEST_MAGIC {
+            return Err(ManifestError::Malformed);
+        }
+        if data[4] != MANIFEST_VERSION {
+            return Err(ManifestError::UnsupportedVersion(data[4]));
+        }
+        let algorithm = algorithm_from_id(data[5])?;
+        let flags = data[6];
+        if flags & !FLAG_HMAC != 0 {
+            return Err(ManifestError::Malformed);
+        }
+        let count = u32::from_be_bytes(data[7..11].try_into().unwrap());
+
+        let mut rest = &data[FIXED_LEN..];
+        let mut take = |n: usize| -> Result<&[u8], ManifestError> {
+            let (head, tail) = rest.split_at_checked(n).ok_or(ManifestError::Malformed)?;
+            rest = tail;
+            Ok(head)
+        };
+        let mut entries: Vec<ManifestEntry> = Vec::new();
+        for _ in 0..count {
+            let path_len = u16::from_be_bytes(take(2)?.try_into().unwrap()) as usize;
+            let path = core::str::from_utf8(take(path_len)?)
+                .map_err(|_| ManifestError::Malformed)?
+                .into();
+            let size = u64::from_be_bytes(take(8)?.try_into().unwrap());
+            let digest = Output::new(take(algorithm.output_len())?);
+            /* Strictly increasing paths: sorted, no duplicates */
+            if entries.last().is_some_and(|last| last.path >= path) {
+                return Err(ManifestError::Malformed);
+            }
+            entries.push(ManifestEntry { path, size, digest });
+        }
+        let body_len = data.len() - rest.len();
+        Ok((Manifest { algorithm, entries }, flags, body_len))
+    }
+}
+
+/* Every regular file under `root` as (relative '/'-path, size), sorted */
+fn walk(root: &Path) -> io::Result<Vec<(String, u64)>> {
+    let mut files = Vec::new();
+    let mut pending = std::vec![String::new()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let name = entry.file_name().into_string().map_err(|name| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    std::format!("non-UTF-8 file name {:?}", name),
+                )
+            })?;
+            let path = if dir.is_empty() {
+                name
+            } else {
+                std::format!("{}/{}", dir, name)
+            };
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                if path.len() > u16::MAX as usize {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "path too long for a manifest",
+                    ));
+                }
+                files.push((path, entry.metadata()?.len()));
+            }
+        }
+    }
+    files.sort_unstable();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/deep/er")).unwrap();
+        fs::create_dir(root.join("b")).unwrap();
+        fs::write(root.join("a.txt"), b"top level").unwrap();
+        fs::write(root.join("a/one"), b"one").unwrap();
+        fs::write(root.join("a/deep/er/two"), vec![7u8; 100_000]).unwrap();
+        fs::write(root.join("b/empty"), b"").unwrap();
+        fs::write(root.join("B"), b"capital").unwrap();
+        dir
+    }
+
+    #[test]
+    fn create_and_verify_clean() {
+        let dir = tree();
+        let manifest = Manifest::create(dir.path(), HashAlgorithm::Sha256).unwrap();
+        assert_eq!(manifest.entries.len(), 5);
+        assert_eq!(
+            manifest.entries[0].digest,
+            crate::digest::hash(HashAlgorithm::Sha256, b"capital")
+        );
+        assert!(manifest.verify(dir.path()).unwrap().is_clean());
+
+        let decoded = Manifest::from_bytes(&manifest.to_bytes()).unwrap();
+        assert_eq!(decoded, manifest);
+    }
+
+    #[test]
+    fn path_order_is_bytewise_on_slash_paths() {
+        let dir = tree();
+        let manifest = Manifest::create(dir.path(), HashAlgorithm::Sha512).unwrap();
+        let paths: Vec<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        /* '.' (0x2e) sorts before '/' (0x2f), and capitals before lowercase,
+         * whatever order the filesystem returns entries in */
+        assert_eq!(paths, ["B", "a.txt", "a/deep/er/two", "a/one", "b/empty"]);
+    }
+
+    #[test]
+    fn detects_modified_deleted_and_added() {
+        let dir = tree();
+        let root = dir.path();
+        let manifest = Manifest::create(root, HashAlgorithm::Sha256).unwrap();
+
+        /* Same size, one byte different */
+        let mut data = fs::read(root.join("a/deep/er/two")).unwrap();
+        data[50_000] ^= 1;
+        fs::write(root.join("a/deep/er/two"), data).unwrap();
+        fs::remove_file(root.join("a/one")).unwrap();
+        fs::write(root.join("b/new"), b"new").unwrap();
+        fs::write(root.join("zz"), b"new").unwrap();
+        fs::write(root.join("B"), b"grown capital").unwrap();
+
+        let report = manifest.verify(root).unwrap();
+        assert_eq!(
+            report,
+            VerifyReport {
+                missing: vec!["a/one".into()],
+                added: vec!["b/new".into(), "zz".into()],
+                modified: vec!["B".into(), "a/deep/er/two".into()],
+            }
+        );
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn authenticated_encoding() {
+        let dir = tree();
+        let manifest = Manifest::create(dir.path(), HashAlgorithm::Sha384).unwrap();
+        let bytes = manifest.to_bytes_authenticated(b"manifest key");
+
+        assert_eq!(
+            Manifest::from_bytes_authenticated(&bytes, b"manifest key").unwrap(),
+            manifest
+        );
+        assert_eq!(
+            Manifest::from_bytes_authenticated(&bytes, b"other key"),
+            Err(ManifestError::MacMismatch)
+        );
+        assert_eq!(
+            Manifest::from_bytes(&bytes),
+            Err(ManifestError::KeyRequired)
+        );
+        assert_eq!(
+            Manifest::from_bytes_authenticated(&manifest.to_bytes(), b"manifest key"),
+            Err(ManifestError::NotAuthenticated)
+        );
+
+        /* Editing a recorded size breaks the tag */
+        let mut edited = bytes.clone();
+        let size_at = FIXED_LEN + 2 + 1;
+        edited[size_at + 7] ^= 1;
+        assert_eq!(
+            Manifest::from_bytes_authenticated(&edited, b"manifest key"),
+            Err(ManifestError::MacMismatch)
+        );
+    }
+
+    #[test]
+    fn checksum_manifests() {
+        let dir = tree();
+        let root = dir.path();
+        for alg in [DigestAlg::Crc32, DigestAlg::XxHash64] {
+            let manifest = Manifest::create(root, alg).unwrap();
+            let expected = match alg {
+                DigestAlg::Crc32 => crate::checksum::crc32(b"capital").to_be_bytes().to_vec(),
+                _ => crate::checksum::xxhash64(b"capital", 0)
+                    .to_be_bytes()
+                    .to_vec(),
+            };
+            assert_eq!(manifest.entries[0].digest.as_bytes(), expected);
+            assert!(manifest.verify(root).unwrap().is_clean());
+            assert_eq!(
+                Manifest::from_bytes(&manifest.to_bytes()).unwrap(),
+                manifest
+            );
+
+            /* Authenticated checksum manifests carry an HMAC-SHA256 tag */
+            let bytes = manifest.to_bytes_authenticated(b"manifest key");
+            assert_eq!(bytes.len(), manifest.to_bytes().len() + 32);
+            assert_eq!(
+                Manifest::from_bytes_authenticated(&bytes, b"manifest key").unwrap(),
+                manifest
+            );
+        }
+
+        let manifest = Manifest::create(root, DigestAlg::Crc32).unwrap();
+        fs::write(root.join("a/one"), b"two").unwrap();
+        assert_eq!(manifest.verify(root).unwrap().modified, ["a/one"]);
+    }
+
+    #[test]
+    fn malformed_encodings() {
+        let dir = tree();
+        let bytes = Manifest::create(dir.path(), HashAlgorithm::Sha256)
+            .unwrap()
+            .to_bytes();
+
+        for len in [0, 4, FIXED_LEN, bytes.len() - 1] {
+            assert_eq!(
+                Manifest::from_bytes(&bytes[..len]),
+                Err(ManifestError::Malformed),
+                "len {}",
+                len
+            );
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Manifest::from_bytes(&trailing),
+            Err(ManifestError::Malformed)
+        );
+
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert_eq!(
+            Manifest::from_bytes(&bad),
+            Err(ManifestError::UnsupportedVersion(2))
+        );
+        let mut bad = bytes.clone();
+        bad[5] = 9;
+        assert_eq!(
+            Manifest::from_bytes(&bad),
+            Err(ManifestError::UnknownAlgorithm(9))
+        );
+
+        /* Out-of-order entries are refused */
+        let entry = |path: &str| ManifestEntry {
+            path: path.into(),
+            size: 0,
+            digest: crate::digest::hash(HashAlgorithm::Sha256, b""),
+        };
+        let unsorted = Manifest {
+            algorithm: DigestAlg::Sha256,
+            entries: vec![entry("b"), entry("a")],
+        };
+        assert_eq!(
+            Manifest::from_bytes(&unsorted.to_bytes()),
+            Err(ManifestError::Malformed)
+        );
+    }
+}
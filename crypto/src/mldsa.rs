@@ -0,0 +1,397 @@
+/* ML-DSA-65 (FIPS 204) signatures, alone or paired with Ed25519.
+ * [FIPS 204]: https://csrc.nist.gov/pubs/fips/204/final
+ *
+ * Keys and signatures are raw bytes:
+ *
+ *   ML-DSA-65   secret seed 32, public key 1952, signature 3309
+ *   hybrid      secret  = Ed25519 seed (32) || ML-DSA seed (32)
+ *               public  = Ed25519 key (32) || ML-DSA key (1952)
+ *               signature = Ed25519 (64) || ML-DSA (3309)
+ *
+ * Signing uses the deterministic variant of ML-DSA.Sign, so the same key
+ * and message always give the same signature. A hybrid signature is only
+ * valid if both halves verify. Each half is domain-separated (an ML-DSA
+ * context string, and a prefix on the Ed25519 message), so neither half
+ * can be lifted out and passed off as a plain signature. */
+use alloc::vec::Vec;
+
+use ml_dsa::{EncodedVerifyingKey, Keypair, MlDsa65, Signature, SigningKey, VerifyingKey};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use zeroize::Zeroizing;
+
+use crate::EntropySource;
+
+/// Length of an ML-DSA-65 secret key seed.
+pub const MLDSA65_SEED_LEN: usize = 32;
+/// Length of an encoded ML-DSA-65 public key.
+pub const MLDSA65_PUBLIC_KEY_LEN: usize = 1952;
+/// Length of an ML-DSA-65 signature.
+pub const MLDSA65_SIGNATURE_LEN: usize = 3309;
+
+/// Length of an encoded `HybridSigningKey`.
+pub const HYBRID_SIGNING_KEY_LEN: usize = ED25519_SEED_LEN + MLDSA65_SEED_LEN;
+/// Length of an encoded `HybridVerifyingKey`.
+pub const HYBRID_VERIFYING_KEY_LEN: usize = ED25519_PUBLIC_KEY_LEN + MLDSA65_PUBLIC_KEY_LEN;
+/// Length of a `hybrid_sign` signature.
+pub const HYBRID_SIGNATURE_LEN: usize = ED25519_SIGNATURE_LEN + MLDSA65_SIGNATURE_LEN;
+
+const ED25519_SEED_LEN: usize = 32;
+const ED25519_PUBLIC_KEY_LEN: usize = 32;
+const ED25519_SIGNATURE_LEN: usize = 64;
+
+/* ML-DSA context string and Ed25519 message prefix for hybrid signatures */
+const HYBRID_CONTEXT: &[u8] = b"rcli hybrid ed25519+mldsa65 v1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PqSignatureError {
+    /* Public key of the wrong length */
+    InvalidKey,
+    /* Signature of the wrong length, or not a valid encoding */
+    Malformed,
+    /* Well-formed, but not a signature over this message by this key */
+    BadSignature,
+}
+
+/// ML-DSA-65 signing key. Zeroized on drop.
+pub struct MlDsaSecretKey(SigningKey<MlDsa65>);
+
+impl MlDsaSecretKey {
+    pub fn from_seed(seed: &[u8; MLDSA65_SEED_LEN]) -> Self {
+        MlDsaSecretKey(SigningKey::from_seed(&(*seed).into()))
+    }
+
+    pub fn to_seed(&self) -> Zeroizing<[u8; MLDSA65_SEED_LEN]> {
+        Zeroizing::new(self.0.to_seed().into())
+    }
+
+    pub fn to_public(&self) -> MlDsaPublicKey {
+        MlDsaPublicKey(self.0.verifying_key())
+    }
+}
+
+impl core::fmt::Debug for MlDsaSecretKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("MlDsaSecretKey(..)")
+    }
+}
+
+/// ML-DSA-65 verifying key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MlDsaPublicKey(VerifyingKey<MlDsa65>);
+
+impl MlDsaPublicKey {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PqSignatureError> {
+        let encoded = EncodedVerifyingKey::<MlDsa65>::try_from(bytes)
+            .map_err(|_| PqSignatureError::InvalidKey)?;
+        Ok(MlDsaPublicKey(VerifyingKey::decode(&encoded)))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.encode().to_vec()
+    }
+}
+
+/// Generate a fresh ML-DSA-65 signing key.
+pub fn mldsa_generate() -> MlDsaSecretKey {
+    mldsa_generate_with_rng(&SystemRandom::new())
+}
+
+pub fn mldsa_generate_with_rng(rng: &dyn EntropySource) -> MlDsaSecretKey {
+    let mut seed = Zeroizing::new([0u8; MLDSA65_SEED_LEN]);
+    rng.fill(&mut seed[..]);
+    MlDsaSecretKey::from_seed(&seed)
+}
+
+/// Sign `message` with an empty context string.
+pub fn mldsa_sign(sk: &MlDsaSecretKey, message: &[u8]) -> Vec<u8> {
+    sign_with_context(sk, message, &[])
+}
+
+/// Verify a signature from `mldsa_sign`.
+pub fn mldsa_verify(
+    pk: &MlDsaPublicKey,
+    message: &[u8],
+    sig: &[u8],
+) -> Result<(), PqSignatureError> {
+    verify_with_context(pk, message, &[], sig)
+}
+
+fn sign_with_context(sk: &MlDsaSecretKey, message: &[u8], context: &[u8]) -> Vec<u8> {
+    /* Only fails for contexts over 255 bytes */
+    let key = sk.0.expanded_key();
+    key.sign_deterministic(message, context)
+        .unwrap()
+        .encode()
+        .to_vec()
+}
+
+fn verify_with_context(
+    pk: &MlDsaPublicKey,
+    message: &[u8],
+    context: &[u8],
+    sig: &[u8],
+) -> Result<(), PqSignatureError> {
+    if sig.len() != MLDSA65_SIGNATURE_LEN {
+        return Err(PqSignatureError::Malformed);
+    }
+    let sig = Signature::<MlDsa65>::try_from(sig).map_err(|_| PqSignatureError::Malformed)?;
+    if pk.0.verify_with_context(message, context, &sig) {
+        Ok(())
+    } else {
+        Err(PqSignatureError::BadSignature)
+    }
+}
+
+/// Ed25519 + ML-DSA-65 signing key.
+pub struct HybridSigningKey {
+    ed25519: Ed25519KeyPair,
+    ed25519_seed: Zeroizing<[u8; ED25519_SEED_LEN]>,
+    mldsa: MlDsaSecretKey,
+}
+
+impl HybridSigningKey {
+    /// Rebuild a key from the form written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8; HYBRID_SIGNING_KEY_LEN]) -> Self {
+        let (ed25519, mldsa) = bytes.split_at(ED25519_SEED_LEN);
+        HybridSigningKey {
+            ed25519: Ed25519KeyPair::from_seed_unchecked(ed25519).unwrap(),
+            ed25519_seed: Zeroizing::new(ed25519.try_into().unwrap()),
+            mldsa: MlDsaSecretKey::from_seed(mldsa.try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Zeroizing<[u8; HYBRID_SIGNING_KEY_LEN]> {
+        let mut out = Zeroizing::new([0u8; HYBRID_SIGNING_KEY_LEN]);
+        out[..ED25519_SEED_LEN].copy_from_slice(&self.ed25519_seed[..]);
+        out[ED25519_SEED_LEN..].copy_from_slice(&self.mldsa.to_seed()[..]);
+        out
+    }
+
+    pub fn to_public(&self) -> HybridVerifyingKey {
+        HybridVerifyingKey {
+            ed25519: self.ed25519.public_key().as_ref().try_into().unwrap(),
+            mldsa: self.mldsa.to_public(),
+        }
+    }
+}
+
+impl core::fmt::Debug for HybridSigningKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("HybridSigningKey(..)")
+    }
+}
+
+/// Ed25519 + ML-DSA-65 verifying key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HybridVerifyingKey {
+    ed25519: [u8; ED25519_PUBLIC_KEY_LEN],
+    mldsa: MlDsaPublicKey,
+}
+
+impl HybridVerifyingKey {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PqSignatureError> {
+        if bytes.len() != HYBRID_VERIFYING_KEY_LEN {
+            return Err(PqSignatureError::InvalidKey);
+        }
+        let (ed25519, mldsa) = bytes.split_at(ED25519_PUBLIC_KEY_LEN);
+        Ok(HybridVerifyingKey {
+            ed25519: ed25519.try_into().unwrap(),
+            mldsa: MlDsaPublicKey::from_bytes(mldsa)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HYBRID_VERIFYING_KEY_LEN);
+        out.extend_from_slice(&self.ed25519);
+        out.extend_from_slice(&self.mldsa.to_bytes());
+        out
+    }
+}
+
+/// Generate a fresh Ed25519 + ML-DSA-65 signing key.
+pub fn hybrid_signing_generate() -> HybridSigningKey {
+    hybrid_signing_generate_with_rng(&SystemRandom::new())
+}
+
+pub fn hybrid_signing_generate_with_rng(rng: &dyn EntropySource) -> HybridSigningKey {
+    let mut bytes = Zeroizing::new([0u8; HYBRID_SIGNING_KEY_LEN]);
+    rng.fill(&mut bytes[..]);
+    HybridSigningKey::from_bytes(&bytes)
+}
+
+fn ed25519_message(message: &[u8]) -> Vec<u8> {
+    let mut prefixed = Vec::with_capacity(HYBRID_CONTEXT.len() + message.len());
+    prefixed.extend_from_slice(HYBRID_CONTEXT);
+    prefixed.extend_from_slice(message);
+    prefixed
+}
+
+/// Sign `message` with both Ed25519 and ML-DSA-65.
+pub fn hybrid_sign(sk: &HybridSigningKey, message: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HYBRID_SIGNATURE_LEN);
+    out.extend_from_slice(sk.ed25519.sign(&ed25519_message(message)).as_ref());
+    out.extend_from_slice(&sign_with_context(&sk.mldsa, message, HYBRID_CONTEXT));
+    out
+}
+
+/// Verify a `hybrid_sign` signature; both halves must be valid.
+pub fn hybrid_verify(
+    pk: &HybridVerifyingKey,
+    message: &[u8],
+    sig: &[u8],
+) -> Result<(), PqSignatureError> {
+    if sig.len() != HYBRID_SIGNATURE_LEN {
+        return Err(PqSignatureError::Malformed);
+    }
+    let (ed25519_sig, mldsa_sig) = sig.split_at(ED25519_SIGNATURE_LEN);
+    /* Check the ML-DSA encoding first, so a malformed half is reported
+     * as such rather than as a bad signature */
+    let mldsa = verify_with_context(&pk.mldsa, message, HYBRID_CONTEXT, mldsa_sig);
+    let ed25519 = UnparsedPublicKey::new(&ED25519, &pk.ed25519)
+        .verify(&ed25519_message(message), ed25519_sig)
+        .map_err(|_| PqSignatureError::BadSignature);
+    mldsa.and(ed25519)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRng;
+
+    #[test]
+    fn sign_and_verify() {
+        let sk = mldsa_generate_with_rng(&TestRng::new(1));
+        let pk = sk.to_public();
+        let sig = mldsa_sign(&sk, b"release-1.2.tar");
+        assert_eq!(sig.len(), MLDSA65_SIGNATURE_LEN);
+        assert_eq!(pk.to_bytes().len(), MLDSA65_PUBLIC_KEY_LEN);
+        assert_eq!(mldsa_verify(&pk, b"release-1.2.tar", &sig), Ok(()));
+        /* Deterministic signing */
+        assert_eq!(mldsa_sign(&sk, b"release-1.2.tar"), sig);
+
+        assert_eq!(
+            mldsa_verify(&pk, b"release-1.3.tar", &sig),
+            Err(PqSignatureError::BadSignature)
+        );
+        let other = mldsa_generate_with_rng(&TestRng::new(2)).to_public();
+        assert_eq!(
+            mldsa_verify(&other, b"release-1.2.tar", &sig),
+            Err(PqSignatureError::BadSignature)
+        );
+
+        let restored = MlDsaSecretKey::from_seed(&sk.to_seed());
+        assert_eq!(restored.to_public(), pk);
+        assert_eq!(MlDsaPublicKey::from_bytes(&pk.to_bytes()), Ok(pk));
+    }
+
+    #[test]
+    fn tampered_and_malformed_signatures() {
+        let sk = mldsa_generate_with_rng(&TestRng::new(3));
+        let pk = sk.to_public();
+        let sig = mldsa_sign(&sk, b"message");
+
+        /* c~ at the front, z in the middle */
+        for at in [0, 100, 2000] {
+            let mut bad = sig.clone();
+            bad[at] ^= 0x01;
+            assert!(mldsa_verify(&pk, b"message", &bad).is_err(), "byte {}", at);
+        }
+        for len in [0, 64, MLDSA65_SIGNATURE_LEN - 1, MLDSA65_SIGNATURE_LEN + 1] {
+            let mut bad = sig.clone();
+            bad.resize(len, 0);
+            assert_eq!(
+                mldsa_verify(&pk, b"message", &bad),
+                Err(PqSignatureError::Malformed),
+                "len {}",
+                len
+            );
+        }
+        /* Hint section that is not a valid encoding: all 0xff */
+        let mut bad = sig.clone();
+        bad[MLDSA65_SIGNATURE_LEN - 61..].fill(0xff);
+        assert_eq!(
+            mldsa_verify(&pk, b"message", &bad),
+            Err(PqSignatureError::Malformed)
+        );
+
+        assert_eq!(
+            MlDsaPublicKey::from_bytes(&pk.to_bytes()[1..]),
+            Err(PqSignatureError::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn hybrid_signatures() {
+        let sk = hybrid_signing_generate_with_rng(&TestRng::new(4));
+        let pk = sk.to_public();
+        let sig = hybrid_sign(&sk, b"archive manifest");
+        assert_eq!(sig.len(), HYBRID_SIGNATURE_LEN);
+        assert_eq!(pk.to_bytes().len(), HYBRID_VERIFYING_KEY_LEN);
+        assert_eq!(hybrid_verify(&pk, b"archive manifest", &sig), Ok(()));
+        assert_eq!(
+            hybrid_verify(&pk, b"archive manifesto", &sig),
+            Err(PqSignatureError::BadSignature)
+        );
+
+        /* Breaking either half breaks the whole */
+        for at in [0, ED25519_SIGNATURE_LEN + 10] {
+            let mut bad = sig.clone();
+            bad[at] ^= 0x01;
+            assert!(hybrid_verify(&pk, b"archive manifest", &bad).is_err());
+        }
+        assert_eq!(
+            hybrid_verify(&pk, b"archive manifest", &sig[..HYBRID_SIGNATURE_LEN - 1]),
+            Err(PqSignatureError::Malformed)
+        );
+
+        /* The ML-DSA half is not a plain signature over the message */
+        let mldsa_half = &sig[ED25519_SIGNATURE_LEN..];
+        assert_eq!(
+            mldsa_verify(&pk.mldsa, b"archive manifest", mldsa_half),
+            Err(PqSignatureError::BadSignature)
+        );
+
+        let restored = HybridSigningKey::from_bytes(&sk.to_bytes());
+        assert_eq!(restored.to_public(), pk);
+        assert_eq!(hybrid_sign(&restored, b"archive manifest"), sig);
+        assert_eq!(HybridVerifyingKey::from_bytes(&pk.to_bytes()), Ok(pk));
+    }
+
+    // draft-ietf-lamps-dilithium-certificates example key: seed 00 01 .. 1f
+    // (the ML-DSA-65.pub example shipped with the ml-dsa crate)
+    #[test]
+    fn keygen_from_seed_matches_example() {
+        let seed: [u8; 32] = core::array::from_fn(|i| i as u8);
+        assert_eq!(
+            MlDsaSecretKey::from_seed(&seed).to_public().to_bytes(),
+            include_bytes!("../tests/mldsa65_seed_vk.bin")
+        );
+    }
+
+    /* The NIST ACVP keyGen and sigGen vectors are not in the repo yet, so
+     * there is no known-answer test for signing: beyond the one example
+     * public key above, keygen and deterministic signing are only
+     * cross-checked against libcrux's independent ML-DSA-65 (zero signing
+     * randomness is the deterministic variant) */
+    #[test]
+    fn matches_libcrux() {
+        use libcrux_ml_dsa::ml_dsa_65;
+
+        for seed in 0..4 {
+            let rng = TestRng::new(seed);
+            let mut key_seed = [0u8; 32];
+            rng.fill(&mut key_seed);
+            let message = [seed as u8; 50];
+
+            let sk = MlDsaSecretKey::from_seed(&key_seed);
+            let pair = ml_dsa_65::generate_key_pair(key_seed);
+            assert_eq!(sk.to_public().to_bytes(), pair.verification_key.as_slice());
+
+            let sig = mldsa_sign(&sk, &message);
+            let theirs = ml_dsa_65::sign(&pair.signing_key, &message, b"", [0u8; 32]).unwrap();
+            assert_eq!(sig, theirs.as_slice());
+            assert!(ml_dsa_65::verify(&pair.verification_key, &message, b"", &theirs).is_ok());
+        }
+    }
+}
@@ -0,0 +1,271 @@
+/* BIP39 mnemonic encoding of keys, for paper backups.
+ * [BIP39]: https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki
+ *
+ * entropy (128..=256 bits, a multiple of 32) is followed by the first
+ * ENT / 32 bits of SHA-256(entropy), and the result is read as 11-bit
+ * indices into the English wordlist. Only the entropy <-> phrase mapping
+ * is provided; deriving a BIP32 seed from the phrase is out of scope. */
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use ring::digest::{digest, SHA256};
+
+const WORDLIST: &str = include_str!("wordlists/bip39_english.txt");
+const WORD_COUNT: usize = 2048;
+const BITS_PER_WORD: usize = 11;
+
+/* Suggestions further than this many edits away are not useful */
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MnemonicError {
+    /* Entropy must be 16, 20, 24, 28 or 32 bytes */
+    InvalidEntropyLength(usize),
+    /* Phrases have 12, 15, 18, 21 or 24 words */
+    InvalidWordCount(usize),
+    /* `position` is 1-based, as a person would count */
+    UnknownWord {
+        position: usize,
+        word: String,
+        suggestion: Option<&'static str>,
+    },
+    InvalidChecksum,
+}
+
+impl fmt::Display for MnemonicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MnemonicError::InvalidEntropyLength(len) => {
+                write!(f, "cannot encode {} bytes; use 16, 20, 24, 28 or 32", len)
+            }
+            MnemonicError::InvalidWordCount(count) => {
+                write!(f, "expected 12, 15, 18, 21 or 24 words, got {}", count)
+            }
+            MnemonicError::UnknownWord {
+                position,
+                word,
+                suggestion,
+            } => {
+                write!(f, "word {} (\"{}\") is not in the wordlist", position, word)?;
+                match suggestion {
+                    Some(s) => write!(f, "; did you mean \"{}\"?", s),
+                    None => Ok(()),
+                }
+            }
+            MnemonicError::InvalidChecksum => {
+                write!(f, "checksum mismatch; a word is wrong or out of order")
+            }
+        }
+    }
+}
+
+fn words() -> Vec<&'static str> {
+    let words: Vec<&str> = WORDLIST.lines().collect();
+    debug_assert_eq!(words.len(), WORD_COUNT);
+    words
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + (ca != *cb) as usize)
+                .min(row[j] + 1)
+                .min(above + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/* Every BIP39 English word is unique in its first four letters, so a
+ * matching prefix wins; otherwise offer the closest word by edit distance */
+fn suggest(words: &[&'static str], word: &str) -> Option<&'static str> {
+    if word.len() >= 4 {
+        let prefix = word.get(..4)?;
+        if let Some(w) = words.iter().find(|w| w.starts_with(prefix)) {
+            return Some(w);
+        }
+    }
+    words
+        .iter()
+        .map(|w| (edit_distance(word, w), *w))
+        .filter(|(d, _)| *d <= MAX_SUGGESTION_DISTANCE)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, w)| w)
+}
+
+fn checksum_bits(entropy: &[u8]) -> (u8, usize) {
+    let bits = entropy.len() / 4;
+    (digest(&SHA256, entropy).as_ref()[0] >> (8 - bits), bits)
+}
+
+/// Encode 16/20/24/28/32 bytes of entropy as a 12- to 24-word phrase.
+pub fn key_to_mnemonic(entropy: &[u8]) -> Result<String, MnemonicError> {
+    if !matches!(entropy.len(), 16 | 20 | 24 | 28 | 32) {
+        return Err(MnemonicError::InvalidEntropyLength(entropy.len()));
+    }
+    let (checksum, checksum_len) = checksum_bits(entropy);
+    let words = words();
+
+    let bit = |i: usize| -> usize {
+        match entropy.get(i / 8) {
+            Some(b) => (b >> (7 - i % 8) & 1) as usize,
+            None => (checksum >> (checksum_len - 1 - (i - entropy.len() * 8)) & 1) as usize,
+        }
+    };
+    let count = (entropy.len() * 8 + checksum_len) / BITS_PER_WORD;
+    let phrase: Vec<&str> = (0..count)
+        .map(|w| {
+            let index = (0..BITS_PER_WORD).fold(0, |acc, b| acc << 1 | bit(w * BITS_PER_WORD + b));
+            words[index]
+        })
+        .collect();
+    Ok(phrase.join(" "))
+}
+
+/// Decode a phrase back to its entropy, checking every word and the
+/// checksum. Case and extra whitespace are ignored.
+pub fn mnemonic_to_key(phrase: &str) -> Result<Vec<u8>, MnemonicError> {
+    let input: Vec<String> = phrase
+        .split_whitespace()
+        .map(|w| w.to_lowercase())
+        .collect();
+    if !matches!(input.len(), 12 | 15 | 18 | 21 | 24) {
+        return Err(MnemonicError::InvalidWordCount(input.len()));
+    }
+
+    let words = words();
+    let mut indices = Vec::with_capacity(input.len());
+    for (i, word) in input.iter().enumerate() {
+        match words.binary_search(&word.as_str()) {
+            Ok(index) => indices.push(index),
+            Err(_) => {
+                return Err(MnemonicError::UnknownWord {
+                    position: i + 1,
+                    word: word.clone(),
+                    suggestion: suggest(&words, word),
+                })
+            }
+        }
+    }
+
+    let total_bits = indices.len() * BITS_PER_WORD;
+    let checksum_len = total_bits / 33;
+    let bit = |i: usize| indices[i / BITS_PER_WORD] >> (BITS_PER_WORD - 1 - i % BITS_PER_WORD) & 1;
+
+    let entropy: Vec<u8> = (0..(total_bits - checksum_len) / 8)
+        .map(|byte| (0..8).fold(0u8, |acc, b| acc << 1 | bit(byte * 8 + b) as u8))
+        .collect();
+    let checksum =
+        (0..checksum_len).fold(0u8, |acc, b| acc << 1 | bit(entropy.len() * 8 + b) as u8);
+    if checksum_bits(&entropy).0 != checksum {
+        return Err(MnemonicError::InvalidChecksum);
+    }
+    Ok(entropy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use serde_json::Value;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn wordlist_is_sorted() {
+        let words = words();
+        assert_eq!(words.len(), WORD_COUNT);
+        assert!(words.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    // trezor/python-mnemonic vectors.json, English entries
+    #[test]
+    fn trezor_vectors() {
+        let json: Value =
+            serde_json::from_str(include_str!("../tests/bip39_vectors.json")).unwrap();
+        for vector in json["english"].as_array().unwrap() {
+            let entropy = hex(vector[0].as_str().unwrap());
+            let phrase = vector[1].as_str().unwrap();
+
+            assert_eq!(key_to_mnemonic(&entropy).unwrap(), phrase);
+            assert_eq!(mnemonic_to_key(phrase).unwrap(), entropy);
+        }
+    }
+
+    #[test]
+    fn sloppy_input_accepted() {
+        let phrase =
+            "  Legal WINNER thank year wave sausage worth useful\n\tlegal winner thank  yellow ";
+        assert_eq!(mnemonic_to_key(phrase).unwrap(), [0x7f; 16]);
+    }
+
+    #[test]
+    fn unknown_words_suggest_correction() {
+        let err = mnemonic_to_key(
+            "legal winner thank year wave sausage worth usefull legal winner thank yellow",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            MnemonicError::UnknownWord {
+                position: 8,
+                word: "usefull".into(),
+                suggestion: Some("useful"),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "word 8 (\"usefull\") is not in the wordlist; did you mean \"useful\"?"
+        );
+
+        let typo = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abuot";
+        match mnemonic_to_key(typo) {
+            Err(MnemonicError::UnknownWord { suggestion, .. }) => {
+                assert_eq!(suggestion, Some("about"))
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match mnemonic_to_key(&"xyzzyq ".repeat(12)) {
+            Err(MnemonicError::UnknownWord { suggestion, .. }) => assert_eq!(suggestion, None),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_lengths_and_checksums_rejected() {
+        assert_eq!(
+            key_to_mnemonic(&[0; 15]),
+            Err(MnemonicError::InvalidEntropyLength(15))
+        );
+        assert_eq!(
+            mnemonic_to_key("abandon about"),
+            Err(MnemonicError::InvalidWordCount(2))
+        );
+        assert_eq!(
+            mnemonic_to_key(&"abandon ".repeat(12)),
+            Err(MnemonicError::InvalidChecksum)
+        );
+
+        /* Swapping two words keeps every word valid but breaks the checksum */
+        let phrase = key_to_mnemonic(&[0x42; 32]).unwrap();
+        let mut words: Vec<&str> = phrase.split(' ').collect();
+        words.swap(0, 1);
+        assert_eq!(
+            mnemonic_to_key(&words.join(" ")),
+            Err(MnemonicError::InvalidChecksum)
+        );
+    }
+}
@@ -0,0 +1,267 @@
+/* HOTP (RFC 4226) and TOTP (RFC 6238) one-time passwords.
+ * [RFC 4226]: https://www.rfc-editor.org/rfc/rfc4226
+ * [RFC 6238]: https://www.rfc-editor.org/rfc/rfc6238
+ *
+ * Secrets are usually handed out as base32 (RFC 4648) strings, as in
+ * otpauth:// URIs; `parse_base32_secret` accepts them with or without
+ * padding, in either case, and with spaces or dashes between groups.
+ * The TOTP functions need `std` for `SystemTime`. */
+use alloc::vec::Vec;
+
+use ring::hmac;
+
+#[cfg(feature = "std")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// HMAC used to derive the code. Authenticator apps default to SHA-1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HmacAlg {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HmacAlg {
+    fn ring_algorithm(self) -> hmac::Algorithm {
+        match self {
+            HmacAlg::Sha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            HmacAlg::Sha256 => hmac::HMAC_SHA256,
+            HmacAlg::Sha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpError {
+    /* Character outside the RFC 4648 base32 alphabet, or bad padding */
+    InvalidBase32,
+}
+
+/// RFC 4226 HOTP code for `counter`, using HMAC-SHA1.
+///
+/// Panics unless `digits` is between 6 and 9.
+pub fn hotp(secret: &[u8], counter: u64, digits: u8) -> u32 {
+    hotp_with_alg(secret, counter, digits, HmacAlg::Sha1)
+}
+
+/// As `hotp`, with the HMAC chosen by `alg` (as TOTP allows).
+pub fn hotp_with_alg(secret: &[u8], counter: u64, digits: u8, alg: HmacAlg) -> u32 {
+    assert!((6..=9).contains(&digits), "OTP codes have 6 to 9 digits");
+
+    let key = hmac::Key::new(alg.ring_algorithm(), secret);
+    let tag = hmac::sign(&key, &counter.to_be_bytes());
+    let mac = tag.as_ref();
+
+    /* Dynamic truncation, RFC 4226 section 5.3 */
+    let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes(mac[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+    binary % 10u32.pow(digits as u32)
+}
+
+/// RFC 6238 TOTP code for `time`.
+///
+/// Panics if `step` is zero or `digits` is not between 6 and 9. Times
+/// before the Unix epoch count as the epoch.
+#[cfg(feature = "std")]
+pub fn totp(secret: &[u8], time: SystemTime, step: Duration, digits: u8, alg: HmacAlg) -> u32 {
+    hotp_with_alg(secret, time_step(time, step), digits, alg)
+}
+
+/// Check `code` against the TOTP for `time` and up to `skew_steps` steps
+/// either side of it, to allow for clock drift. Every candidate in the
+/// window is computed and compared without early exit.
+#[cfg(feature = "std")]
+pub fn verify_totp(
+    secret: &[u8],
+    code: u32,
+    time: SystemTime,
+    step: Duration,
+    digits: u8,
+    alg: HmacAlg,
+    skew_steps: u64,
+) -> bool {
+    use subtle::{Choice, ConstantTimeEq};
+
+    let current = time_step(time, step);
+    let mut found = Choice::from(0);
+    for counter in current.saturating_sub(skew_steps)..=current.saturating_add(skew_steps) {
+        found |= hotp_with_alg(secret, counter, digits, alg).ct_eq(&code);
+    }
+    found.into()
+}
+
+#[cfg(feature = "std")]
+fn time_step(time: SystemTime, step: Duration) -> u64 {
+    assert!(!step.is_zero(), "TOTP step must be non-zero");
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (elapsed.as_nanos() / step.as_nanos()) as u64
+}
+
+/// Decode a base32 secret as shown by authenticator enrolment screens.
+pub fn parse_base32_secret(text: &str) -> Result<Vec<u8>, OtpError> {
+    let mut out = Vec::with_capacity(text.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    let mut padding = 0;
+
+    for c in text.bytes() {
+        let value = match c {
+            b' ' | b'-' => continue,
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            /* Nothing but padding may follow padding */
+            _ if padding > 0 => return Err(OtpError::InvalidBase32),
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a',
+            b'2'..=b'7' => c - b'2' + 26,
+            _ => return Err(OtpError::InvalidBase32),
+        };
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    /* A dangling group of 5+ bits, or nonzero leftover bits, is not
+     * something an encoder produces */
+    if bits >= 5 || buffer & ((1 << bits) - 1) != 0 {
+        return Err(OtpError::InvalidBase32);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1_SEED: &[u8] = b"12345678901234567890";
+    const SHA256_SEED: &[u8] = b"12345678901234567890123456789012";
+    const SHA512_SEED: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    // RFC 4226 appendix D
+    #[test]
+    fn rfc4226_vectors() {
+        let expected = [
+            755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489,
+        ];
+        for (counter, code) in expected.into_iter().enumerate() {
+            assert_eq!(hotp(SHA1_SEED, counter as u64, 6), code);
+        }
+    }
+
+    // RFC 6238 appendix B
+    #[cfg(feature = "std")]
+    #[test]
+    fn rfc6238_vectors() {
+        let cases: [(u64, u32, u32, u32); 6] = [
+            (59, 94287082, 46119246, 90693936),
+            (1111111109, 7081804, 68084774, 25091201),
+            (1111111111, 14050471, 67062674, 99943326),
+            (1234567890, 89005924, 91819424, 93441116),
+            (2000000000, 69279037, 90698825, 38618901),
+            (20000000000, 65353130, 77737706, 47863826),
+        ];
+        let step = Duration::from_secs(30);
+        for (secs, sha1, sha256, sha512) in cases {
+            let time = UNIX_EPOCH + Duration::from_secs(secs);
+            assert_eq!(totp(SHA1_SEED, time, step, 8, HmacAlg::Sha1), sha1);
+            assert_eq!(totp(SHA256_SEED, time, step, 8, HmacAlg::Sha256), sha256);
+            assert_eq!(totp(SHA512_SEED, time, step, 8, HmacAlg::Sha512), sha512);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn verify_window() {
+        let step = Duration::from_secs(30);
+        /* Mid-step, so +-30s and +-65s land exactly 1 and 2 steps away */
+        let now = UNIX_EPOCH + Duration::from_secs(1111111125);
+        let code = totp(SHA1_SEED, now, step, 6, HmacAlg::Sha1);
+
+        for (offset, skew, ok) in [
+            (0, 0, true),
+            (30, 0, false),
+            (30, 1, true),
+            (65, 1, false),
+            (65, 2, true),
+        ] {
+            let later = now + Duration::from_secs(offset);
+            let earlier = now - Duration::from_secs(offset);
+            for time in [later, earlier] {
+                assert_eq!(
+                    verify_totp(SHA1_SEED, code, time, step, 6, HmacAlg::Sha1, skew),
+                    ok,
+                    "offset {} skew {}",
+                    offset,
+                    skew
+                );
+            }
+        }
+        assert!(!verify_totp(
+            SHA1_SEED,
+            code,
+            now,
+            step,
+            6,
+            HmacAlg::Sha256,
+            1
+        ));
+
+        /* The window is clamped at the epoch rather than wrapping */
+        let epoch_code = totp(SHA1_SEED, UNIX_EPOCH, step, 6, HmacAlg::Sha1);
+        assert!(verify_totp(
+            SHA1_SEED,
+            epoch_code,
+            UNIX_EPOCH,
+            step,
+            6,
+            HmacAlg::Sha1,
+            5
+        ));
+    }
+
+    #[test]
+    fn base32_secrets() {
+        let secret = parse_base32_secret("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+        assert_eq!(secret, SHA1_SEED);
+        assert_eq!(
+            parse_base32_secret("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap(),
+            SHA1_SEED
+        );
+
+        // RFC 4648 section 10
+        let vectors = [
+            ("", ""),
+            ("MY======", "f"),
+            ("MZXQ====", "fo"),
+            ("MZXW6===", "foo"),
+            ("MZXW6YQ=", "foob"),
+            ("MZXW6YTB", "fooba"),
+            ("MZXW6YTBOI======", "foobar"),
+            ("MZXW6YTBOI", "foobar"),
+        ];
+        for (encoded, decoded) in vectors {
+            assert_eq!(parse_base32_secret(encoded).unwrap(), decoded.as_bytes());
+        }
+
+        for bad in ["MZXW6YT1", "MZ=XW6YT", "M", "MZX", "MZ", "MZXW6YTBOJ"] {
+            assert_eq!(
+                parse_base32_secret(bad),
+                Err(OtpError::InvalidBase32),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_short_codes() {
+        hotp(SHA1_SEED, 0, 4);
+    }
+}
@@ -0,0 +1,217 @@
+/* Length padding, so ciphertext sizes reveal less about plaintext sizes.
+ * Padding is applied before sealing, so it sits inside the authenticated
+ * envelope and cannot be altered without failing decryption.
+ *
+ * Padded layout:
+ *
+ *   data || zero bytes || original length (u64, big-endian)
+ *
+ * The whole padded buffer is `scheme.padded_len(data.len())` bytes long.
+ *
+ * Padmé [PURBs, Nikitin et al., PETS 2019, §4]: a length L with
+ * E = floor(log2 L) is rounded up so that only its top floor(log2 E) + 1
+ * bits may be non-zero. That leaks O(log log L) bits of the length, and
+ * costs at most 12% overhead (much less for large L). */
+use alloc::vec::Vec;
+
+use crate::ErrorStates;
+
+/// Length of the original-length trailer at the end of a padded buffer.
+pub const PADDING_TRAILER_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaddingScheme {
+    /// Round up to the next Padmé bucket (see `padme`).
+    Padme,
+    /// Round up to a multiple of this many bytes; 0 is treated as 1.
+    Block(u32),
+}
+
+impl PaddingScheme {
+    /// Size of the padded buffer for `len` bytes of data, trailer included.
+    pub fn padded_len(self, len: u64) -> u64 {
+        let len = len.saturating_add(PADDING_TRAILER_LEN as u64);
+        match self {
+            PaddingScheme::Padme => padme(len),
+            PaddingScheme::Block(n) => {
+                let n = u64::from(n.max(1));
+                len.div_ceil(n).saturating_mul(n)
+            }
+        }
+    }
+}
+
+/// The Padmé bucket for a length: the smallest length >= `len` whose bits
+/// below the top floor(log2(floor(log2 len))) + 1 are all zero.
+pub fn padme(len: u64) -> u64 {
+    if len < 2 {
+        return len;
+    }
+    let e = u64::from(len.ilog2());
+    let s = u64::from(e.ilog2()) + 1;
+    let mask = (1u64 << (e - s)) - 1;
+    len.saturating_add(mask) & !mask
+}
+
+/// Pad `data` in place with `scheme`.
+pub fn pad_plaintext(data: &mut Vec<u8>, scheme: PaddingScheme) {
+    let len = data.len() as u64;
+    let padded = scheme.padded_len(len) as usize;
+    data.resize(padded - PADDING_TRAILER_LEN, 0);
+    data.extend_from_slice(&len.to_be_bytes());
+}
+
+/// Strip padding added by `pad_plaintext` with the same `scheme`. A
+/// buffer whose length, trailer or padding bytes are not exactly what
+/// padding would have produced is `DecryptionFailed`, and left unchanged.
+pub fn unpad_plaintext(data: &mut Vec<u8>, scheme: PaddingScheme) -> Result<(), ErrorStates> {
+    let body_len = data
+        .len()
+        .checked_sub(PADDING_TRAILER_LEN)
+        .ok_or(ErrorStates::DecryptionFailed)?;
+    let mut trailer = [0u8; PADDING_TRAILER_LEN];
+    trailer.copy_from_slice(&data[body_len..]);
+    let original = u64::from_be_bytes(trailer);
+
+    if original > body_len as u64 || scheme.padded_len(original) != data.len() as u64 {
+        return Err(ErrorStates::DecryptionFailed);
+    }
+    if data[original as usize..body_len].iter().any(|&b| b != 0) {
+        return Err(ErrorStates::DecryptionFailed);
+    }
+    data.truncate(original as usize);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Worked by hand from the Padmé definition in the PURBs paper, §4
+    #[test]
+    fn padme_buckets() {
+        let cases: [(u64, u64); 14] = [
+            (0, 0),
+            (1, 1),
+            (2, 2),
+            (9, 10),
+            (10, 10),
+            (11, 12),
+            (17, 18),
+            (100, 104),
+            (1000, 1024),
+            (1025, 1088),
+            (5000, 5120),
+            (10_000, 10_240),
+            (1_000_000, 1_015_808),
+            (1 << 40, 1 << 40),
+        ];
+        for (len, expected) in cases {
+            assert_eq!(padme(len), expected, "len {}", len);
+        }
+    }
+
+    /* Straight from the paper's pseudocode, with floating-point logs */
+    fn padme_reference(len: u64) -> u64 {
+        let e = (len as f64).log2().floor() as u32;
+        let s = (e as f64).log2().floor() as u32 + 1;
+        let last_bits = e - s;
+        let bit_mask = (1u64 << last_bits) - 1;
+        (len + bit_mask) & !bit_mask
+    }
+
+    #[test]
+    fn padme_matches_reference_and_overhead_bound() {
+        for len in (2..70_000u64).chain((20..50).map(|k| (1u64 << k) + 12_345)) {
+            let padded = padme(len);
+            assert_eq!(padded, padme_reference(len), "len {}", len);
+            assert!(padded >= len);
+            assert!((padded - len) * 100 <= len * 12, "len {}", len);
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for scheme in [
+            PaddingScheme::Padme,
+            PaddingScheme::Block(1),
+            PaddingScheme::Block(16),
+            PaddingScheme::Block(4096),
+        ] {
+            for len in (0..600).chain([4087, 4088, 4089, 65_536, 100_003]) {
+                let original: Vec<u8> = (0..len).map(|i| (i % 251) as u8 + 1).collect();
+                let mut data = original.clone();
+                pad_plaintext(&mut data, scheme);
+                assert_eq!(
+                    data.len() as u64,
+                    scheme.padded_len(len as u64),
+                    "{:?} len {}",
+                    scheme,
+                    len
+                );
+                unpad_plaintext(&mut data, scheme).unwrap();
+                assert_eq!(data, original, "{:?} len {}", scheme, len);
+            }
+        }
+    }
+
+    #[test]
+    fn block_sizes() {
+        let padded = |len: usize, n: u32| {
+            let mut data = vec![1u8; len];
+            pad_plaintext(&mut data, PaddingScheme::Block(n));
+            data.len()
+        };
+        assert_eq!(padded(0, 16), 16);
+        assert_eq!(padded(8, 16), 16);
+        assert_eq!(padded(9, 16), 32);
+        assert_eq!(padded(5, 0), 13);
+        assert_eq!(padded(1000, 512), 1024);
+    }
+
+    #[test]
+    fn padme_sizes_include_trailer() {
+        let mut data = vec![7u8; 1000];
+        pad_plaintext(&mut data, PaddingScheme::Padme);
+        assert_eq!(data.len() as u64, padme(1008));
+        assert_eq!(data[data.len() - 8..], 1000u64.to_be_bytes());
+    }
+
+    #[test]
+    fn rejects_altered_padding() {
+        let mut padded = vec![0xaau8; 300];
+        pad_plaintext(&mut padded, PaddingScheme::Padme);
+        let trailer_at = padded.len() - PADDING_TRAILER_LEN;
+
+        /* Too short for a trailer */
+        for len in 0..PADDING_TRAILER_LEN {
+            let mut short = padded[..len].to_vec();
+            assert_eq!(
+                unpad_plaintext(&mut short, PaddingScheme::Padme),
+                Err(ErrorStates::DecryptionFailed)
+            );
+        }
+
+        /* A claimed length past the padding, shorter (so real data would
+         * be read as padding), from another bucket, or absurd */
+        for claimed in [313u64, 299, 250, u64::MAX] {
+            let mut bad = padded.clone();
+            bad[trailer_at..].copy_from_slice(&claimed.to_be_bytes());
+            let before = bad.clone();
+            assert_eq!(
+                unpad_plaintext(&mut bad, PaddingScheme::Padme),
+                Err(ErrorStates::DecryptionFailed),
+                "claimed {}",
+                claimed
+            );
+            assert_eq!(bad, before);
+        }
+
+        /* Non-zero padding, and the wrong scheme */
+        let mut bad = padded.clone();
+        bad[trailer_at - 1] = 1;
+        assert!(unpad_plaintext(&mut bad, PaddingScheme::Padme).is_err());
+        let mut other = padded.clone();
+        assert!(unpad_plaintext(&mut other, PaddingScheme::Block(7)).is_err());
+    }
+}
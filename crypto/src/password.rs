@@ -0,0 +1,367 @@
+/* Random passwords from character classes.
+ *
+ * Every character is drawn uniformly from the union of the enabled
+ * classes, using rejection sampling on random bytes as the token module
+ * does. A password missing one of the enabled classes is discarded and
+ * drawn again whole, so every password that meets the policy is equally
+ * likely; planting one character of each class at a random position
+ * instead would favour passwords with few characters from the small
+ * classes. The entropy reported is log2 of the number of passwords that
+ * meet the policy, counted by inclusion-exclusion over the classes. */
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use ring::rand::SystemRandom;
+
+use crate::EntropySource;
+
+pub const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+pub const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+pub const DIGITS: &str = "0123456789";
+/// All ASCII punctuation.
+pub const SYMBOLS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+/// Characters easily mistaken for one another, left out on request.
+pub const AMBIGUOUS: &str = "0O1lI";
+
+/* Random bytes are fetched in batches to avoid a syscall per character */
+const BATCH_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordError {
+    /* Every character class was turned off */
+    NoCharacterClasses,
+    /* Fewer characters than enabled classes, so not every class fits */
+    TooShort { length: usize, classes: usize },
+}
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordError::NoCharacterClasses => write!(f, "no character classes enabled"),
+            PasswordError::TooShort { length, classes } => write!(
+                f,
+                "a password of {} characters cannot include all {} character classes",
+                length, classes
+            ),
+        }
+    }
+}
+
+/// What a generated password is made of. Each enabled class appears at
+/// least once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    /// Leave out `AMBIGUOUS` characters.
+    pub exclude_ambiguous: bool,
+}
+
+impl Default for PasswordPolicy {
+    /// 20 letters and digits, about 119 bits.
+    fn default() -> Self {
+        PasswordPolicy {
+            length: 20,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: false,
+            exclude_ambiguous: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /* The enabled classes, less any ambiguous characters */
+    fn classes(&self) -> Result<Vec<Vec<char>>, PasswordError> {
+        let classes: Vec<Vec<char>> = [
+            (self.lowercase, LOWERCASE),
+            (self.uppercase, UPPERCASE),
+            (self.digits, DIGITS),
+            (self.symbols, SYMBOLS),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, class)| {
+            class
+                .chars()
+                .filter(|c| !(self.exclude_ambiguous && AMBIGUOUS.contains(*c)))
+                .collect()
+        })
+        .collect();
+        if classes.is_empty() {
+            return Err(PasswordError::NoCharacterClasses);
+        }
+        if self.length < classes.len() {
+            return Err(PasswordError::TooShort {
+                length: self.length,
+                classes: classes.len(),
+            });
+        }
+        Ok(classes)
+    }
+}
+
+/// A password meeting `policy`, every such password equally likely.
+pub fn generate_password(policy: &PasswordPolicy) -> Result<String, PasswordError> {
+    generate_password_with_rng(policy, &SystemRandom::new())
+}
+
+/// As `generate_password`, drawing the characters from `rng`.
+pub fn generate_password_with_rng(
+    policy: &PasswordPolicy,
+    rng: &dyn EntropySource,
+) -> Result<String, PasswordError> {
+    let classes = policy.classes()?;
+    let chars: Vec<char> = classes.concat();
+    let mut bytes = random_bytes(rng);
+    loop {
+        let password = sample(policy.length, &chars, &mut bytes);
+        if classes
+            .iter()
+            .all(|class| password.chars().any(|c| class.contains(&c)))
+        {
+            return Ok(password);
+        }
+    }
+}
+
+/// Entropy in bits of a password drawn by `generate_password`.
+pub fn password_entropy_bits(policy: &PasswordPolicy) -> Result<f64, PasswordError> {
+    let classes = policy.classes()?;
+    let total = classes.iter().map(Vec::len).sum::<usize>() as f64;
+    /* The share of all total^length strings that miss no class: the sum
+     * over every set S of classes of (-1)^|S| (1 - |S's chars| / total)^length */
+    let mut share = 0.0;
+    for missing in 0..1u32 << classes.len() {
+        let size: usize = (0..classes.len())
+            .filter(|i| missing >> i & 1 == 1)
+            .map(|i| classes[i].len())
+            .sum();
+        let mut term = 1.0;
+        for _ in 0..policy.length {
+            term *= 1.0 - size as f64 / total;
+        }
+        if missing.count_ones() % 2 == 1 {
+            term = -term;
+        }
+        share += term;
+    }
+    Ok(policy.length as f64 * log2(total) + log2(share))
+}
+
+fn random_bytes(rng: &dyn EntropySource) -> impl Iterator<Item = u8> + '_ {
+    core::iter::repeat_with(move || {
+        let mut batch = [0u8; BATCH_LEN];
+        rng.fill(&mut batch);
+        batch
+    })
+    .flatten()
+}
+
+fn sample(len: usize, chars: &[char], bytes: &mut impl Iterator<Item = u8>) -> String {
+    let size = chars.len();
+    /* Largest multiple of size that fits in a byte */
+    let limit = 256 - 256 % size;
+    bytes
+        .map(usize::from)
+        .filter(|&b| b < limit)
+        .take(len)
+        .map(|b| chars[b % size])
+        .collect()
+}
+
+/* log2 of a positive float without `std`: the exponent by halving or
+ * doubling into [1, 2), then the fraction one bit at a time by repeated
+ * squaring */
+fn log2(x: f64) -> f64 {
+    let (mut y, mut result) = (x, 0.0);
+    while y >= 2.0 {
+        y /= 2.0;
+        result += 1.0;
+    }
+    while y < 1.0 {
+        y *= 2.0;
+        result -= 1.0;
+    }
+    let mut bit = 0.5;
+    for _ in 0..48 {
+        y *= y;
+        if y >= 2.0 {
+            y /= 2.0;
+            result += bit;
+        }
+        bit /= 2.0;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRng;
+    use alloc::string::ToString;
+
+    fn policy(length: usize) -> PasswordPolicy {
+        PasswordPolicy {
+            length,
+            ..PasswordPolicy::default()
+        }
+    }
+
+    #[test]
+    fn every_class_appears() {
+        let all = PasswordPolicy {
+            symbols: true,
+            ..policy(4)
+        };
+        let rng = TestRng::new(3);
+        for _ in 0..2000 {
+            let password = generate_password_with_rng(&all, &rng).unwrap();
+            assert_eq!(password.chars().count(), 4);
+            for class in [LOWERCASE, UPPERCASE, DIGITS, SYMBOLS] {
+                assert!(password.chars().any(|c| class.contains(c)), "{}", password);
+            }
+        }
+    }
+
+    #[test]
+    fn ambiguous_characters_excluded() {
+        let clear = PasswordPolicy {
+            exclude_ambiguous: true,
+            symbols: true,
+            ..policy(64)
+        };
+        let rng = TestRng::new(4);
+        for _ in 0..500 {
+            let password = generate_password_with_rng(&clear, &rng).unwrap();
+            assert!(
+                !password.contains(|c| AMBIGUOUS.contains(c)),
+                "{}",
+                password
+            );
+        }
+        /* Digits alone still work without 0 and 1 */
+        let digits = PasswordPolicy {
+            lowercase: false,
+            uppercase: false,
+            exclude_ambiguous: true,
+            ..policy(1000)
+        };
+        let password = generate_password_with_rng(&digits, &rng).unwrap();
+        assert!(password.chars().all(|c| "23456789".contains(c)));
+    }
+
+    #[test]
+    fn lengths_respected() {
+        for length in [1, 3, 20, 255, 256, 1024] {
+            let digits = PasswordPolicy {
+                lowercase: false,
+                uppercase: false,
+                ..policy(length)
+            };
+            assert_eq!(generate_password(&digits).unwrap().len(), length);
+        }
+        assert_eq!(generate_password(&policy(3)).unwrap().len(), 3);
+        assert_ne!(
+            generate_password(&policy(20)),
+            generate_password(&policy(20))
+        );
+    }
+
+    #[test]
+    fn impossible_policies_rejected() {
+        let none = PasswordPolicy {
+            lowercase: false,
+            uppercase: false,
+            digits: false,
+            ..policy(20)
+        };
+        assert_eq!(
+            generate_password(&none),
+            Err(PasswordError::NoCharacterClasses)
+        );
+        assert_eq!(
+            generate_password(&policy(2)),
+            Err(PasswordError::TooShort {
+                length: 2,
+                classes: 3
+            })
+        );
+        assert_eq!(
+            password_entropy_bits(&policy(2)).unwrap_err().to_string(),
+            "a password of 2 characters cannot include all 3 character classes"
+        );
+    }
+
+    /* Chi-squared over 10 digits, 9 degrees of freedom: 27.88 is the 0.1%
+     * critical value; the seed is fixed so this cannot flake */
+    #[test]
+    fn single_class_is_uniform() {
+        let digits = PasswordPolicy {
+            lowercase: false,
+            uppercase: false,
+            ..policy(100_000)
+        };
+        let password = generate_password_with_rng(&digits, &TestRng::new(5)).unwrap();
+        let expected = 10_000.0;
+        let chi_squared: f64 = DIGITS
+            .chars()
+            .map(|d| {
+                let observed = password.matches(d).count() as f64;
+                (observed - expected) * (observed - expected) / expected
+            })
+            .sum();
+        assert!(chi_squared < 27.88, "{}", chi_squared);
+    }
+
+    #[test]
+    fn entropy_counts_passwords_meeting_the_policy() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        /* One class: length * log2(size) */
+        let digits = PasswordPolicy {
+            lowercase: false,
+            uppercase: false,
+            ..policy(12)
+        };
+        assert!(close(
+            password_entropy_bits(&digits).unwrap(),
+            12.0 * 10f64.log2()
+        ));
+
+        /* Two characters, lowercase and digits: 26 * 10 * 2 passwords */
+        let pair = PasswordPolicy {
+            uppercase: false,
+            ..policy(2)
+        };
+        assert!(close(password_entropy_bits(&pair).unwrap(), 520f64.log2()));
+
+        /* 20 of 62 letters and digits, less those missing a class */
+        let share = |size: f64| (size / 62.0).powi(20);
+        let valid = 1.0 - 2.0 * share(36.0) - share(52.0) + share(10.0) + 2.0 * share(26.0);
+        assert!(close(
+            password_entropy_bits(&policy(20)).unwrap(),
+            20.0 * 62f64.log2() + valid.log2()
+        ));
+
+        let clear = PasswordPolicy {
+            exclude_ambiguous: true,
+            ..digits
+        };
+        assert!(close(
+            password_entropy_bits(&clear).unwrap(),
+            12.0 * 8f64.log2()
+        ));
+    }
+
+    #[test]
+    fn log2_matches_std() {
+        for x in [1.0, 2.0, 10.0, 94.0, 0.5, 0.066, 1e-30, 7776.0] {
+            assert!((log2(x) - x.log2()).abs() < 1e-9, "{}", x);
+        }
+    }
+}
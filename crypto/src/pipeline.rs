@@ -0,0 +1,235 @@
+/* Stream chunks sealed and opened on several threads. Requires the
+ * `parallel` feature.
+ *
+ * Outside ratchet mode a chunk's nonce comes from its index alone, so
+ * chunks can be worked on in any order. `run` reads a batch of up to
+ * `jobs` chunks in order, hands them to a rayon pool, then writes them
+ * back in order before reading the next batch, so about jobs + 1 chunk
+ * buffers are held at once. As in `fs`, one chunk of lookahead decides
+ * which chunk is the last, and the output is byte for byte what one
+ * thread writes. A stream that fits in one chunk is done on the calling
+ * thread, without starting a pool. When a chunk fails, those before it
+ * are written and its error returned, whichever thread came to it first;
+ * the truncation check on the last chunk is the same as one thread's. */
+use std::io::{self, Read, Write};
+use std::vec::Vec;
+
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
+use crate::fs::{read_chunk, FsCryptoError, Summary};
+use crate::stream::TAG_LEN;
+use crate::ErrorStates;
+
+/* Apply `work` to every `read_size` chunk of `reader` and write the
+ * results to `writer`, counting them in `summary`. `work` gets a chunk's
+ * index and whether it is the last; a stream too long for a u32 index
+ * fails with `overflow`. */
+pub(crate) fn run<R, W, F>(
+    reader: &mut R,
+    writer: &mut W,
+    read_size: usize,
+    jobs: usize,
+    summary: &mut Summary,
+    overflow: ErrorStates,
+    work: F,
+) -> Result<(), FsCryptoError>
+where
+    R: Read,
+    W: Write,
+    F: Fn(u32, bool, &mut Vec<u8>) -> Result<(), ErrorStates> + Sync,
+{
+    let mut pool = None;
+    let buffer = || Vec::with_capacity(read_size + TAG_LEN);
+    let mut batch: Vec<Vec<u8>> = (0..jobs).map(|_| buffer()).collect();
+    let mut next = buffer();
+    read_chunk(reader, &mut next, read_size)?;
+
+    let mut first = 0u64;
+    loop {
+        let (mut filled, mut ended) = (0, false);
+        while filled < jobs && !ended {
+            let chunk = &mut batch[filled];
+            core::mem::swap(chunk, &mut next);
+            summary.bytes_in += chunk.len() as u64;
+            summary.chunks += 1;
+            filled += 1;
+            if chunk.len() == read_size {
+                read_chunk(reader, &mut next, read_size)?;
+            } else {
+                next.clear();
+            }
+            ended = next.is_empty();
+        }
+
+        let chunks = &mut batch[..filled];
+        let each = |(i, chunk): (usize, &mut Vec<u8>)| match u32::try_from(first + i as u64) {
+            Ok(index) => work(index, ended && i + 1 == filled, chunk),
+            Err(_) => Err(overflow),
+        };
+        let results: Vec<Result<(), ErrorStates>> = if first == 0 && ended && filled == 1 {
+            chunks.iter_mut().enumerate().map(each).collect()
+        } else {
+            let pool = match &mut pool {
+                Some(pool) => pool,
+                None => pool.insert(
+                    ThreadPoolBuilder::new()
+                        .num_threads(jobs)
+                        .build()
+                        .map_err(io::Error::other)?,
+                ),
+            };
+            pool.install(|| chunks.par_iter_mut().enumerate().map(each).collect())
+        };
+        for (chunk, result) in chunks.iter().zip(results) {
+            result?;
+            writer.write_all(chunk)?;
+            summary.bytes_out += chunk.len() as u64;
+        }
+
+        if ended {
+            return Ok(());
+        }
+        first += filled as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::{
+        decrypt_stream_with_jobs, encrypt_stream_with_nonce_prefix, EncryptOptions, FsCryptoError,
+        Summary,
+    };
+    use crate::header::{HEADER_FIXED_LEN, MIN_CHUNK_SIZE};
+    use crate::padding::PaddingScheme;
+    use crate::stream::TAG_LEN;
+    use crate::ErrorStates;
+
+    const KEY: [u8; 32] = [0x42; 32];
+    const PREFIX: [u8; 7] = [7; 7];
+
+    fn plain(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    fn seal(plain: &[u8], opts: &EncryptOptions) -> (Vec<u8>, Summary) {
+        let mut sealed = Vec::new();
+        let summary =
+            encrypt_stream_with_nonce_prefix(&mut &plain[..], &mut sealed, &KEY, opts, PREFIX)
+                .unwrap();
+        (sealed, summary)
+    }
+
+    fn open(sealed: &[u8], jobs: usize) -> (Vec<u8>, Result<Summary, FsCryptoError>) {
+        let mut out = Vec::new();
+        let result = decrypt_stream_with_jobs(&mut &sealed[..], &mut out, &KEY, b"tag", jobs);
+        (out, result)
+    }
+
+    fn opts(chunk_size: u32, jobs: usize) -> EncryptOptions {
+        EncryptOptions {
+            chunk_size,
+            aad: b"tag".to_vec(),
+            jobs,
+            ..EncryptOptions::default()
+        }
+    }
+
+    #[test]
+    fn output_is_what_one_thread_writes() {
+        let size = MIN_CHUNK_SIZE as usize;
+        for len in [
+            0,
+            1,
+            size - 1,
+            size,
+            size + 1,
+            7 * size,
+            7 * size + 5,
+            40 * size,
+        ] {
+            let plain = plain(len);
+            let (expected, expected_summary) = seal(&plain, &opts(MIN_CHUNK_SIZE, 1));
+            for jobs in [2, 3, 8] {
+                let (sealed, summary) = seal(&plain, &opts(MIN_CHUNK_SIZE, jobs));
+                assert_eq!(sealed, expected, "{} bytes, {} jobs", len, jobs);
+                assert_eq!(summary, expected_summary);
+
+                let (opened, result) = open(&sealed, jobs);
+                assert_eq!(opened, plain);
+                assert_eq!(result.unwrap(), open(&sealed, 1).1.unwrap());
+            }
+        }
+
+        /* Padding and the default chunk size go through the same way */
+        let padded = EncryptOptions {
+            padding: Some(PaddingScheme::Padme),
+            ..opts(MIN_CHUNK_SIZE, 1)
+        };
+        let plain = plain(5 * size + 3);
+        let (expected, _) = seal(&plain, &padded);
+        let (sealed, _) = seal(&plain, &EncryptOptions { jobs: 4, ..padded });
+        assert_eq!(sealed, expected);
+        assert_eq!(open(&sealed, 4).0, plain);
+        let (expected, _) = seal(&plain, &opts(1 << 16, 1));
+        assert_eq!(seal(&plain, &opts(1 << 16, 4)).0, expected);
+    }
+
+    #[test]
+    fn ratchet_streams_go_one_at_a_time() {
+        let plain = plain(9 * MIN_CHUNK_SIZE as usize);
+        let ratchet = |jobs| EncryptOptions {
+            ratchet: true,
+            ..opts(MIN_CHUNK_SIZE, jobs)
+        };
+        let (sealed, _) = seal(&plain, &ratchet(4));
+        assert_eq!(sealed, seal(&plain, &ratchet(1)).0);
+        assert_eq!(open(&sealed, 4).0, plain);
+    }
+
+    #[test]
+    fn the_first_bad_chunk_is_the_one_reported() {
+        let size = MIN_CHUNK_SIZE as usize;
+        let sealed_size = size + TAG_LEN;
+        let plain = plain(20 * size + 9);
+        let (sealed, _) = seal(&plain, &opts(MIN_CHUNK_SIZE, 1));
+        let body = sealed.len() - 20 * sealed_size - (9 + TAG_LEN);
+        assert!(body >= HEADER_FIXED_LEN);
+
+        /* Chunks 5 and 6 share a batch of 4; the later may fail first */
+        let mut bad = sealed.clone();
+        for index in [5, 6, 13] {
+            bad[body + index * sealed_size + 3] ^= 1;
+        }
+        for jobs in [1, 4, 16] {
+            let (opened, result) = open(&bad, jobs);
+            assert!(matches!(
+                result,
+                Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed))
+            ));
+            assert_eq!(opened, plain[..5 * size]);
+        }
+
+        /* Cut at a chunk boundary: everything authenticates but the end */
+        let cut = &sealed[..body + 12 * sealed_size];
+        for jobs in [1, 4, 16] {
+            let (opened, result) = open(cut, jobs);
+            assert!(matches!(
+                result,
+                Err(FsCryptoError::Crypto(ErrorStates::StreamTruncated))
+            ));
+            assert_eq!(opened, plain[..11 * size]);
+        }
+
+        /* Cut inside the tag of the last chunk */
+        let cut = &sealed[..sealed.len() - (9 + TAG_LEN) + 4];
+        for jobs in [1, 4] {
+            let (opened, result) = open(cut, jobs);
+            assert!(matches!(
+                result,
+                Err(FsCryptoError::Crypto(ErrorStates::StreamTruncated))
+            ));
+            assert_eq!(opened, plain[..20 * size]);
+        }
+    }
+}
@@ -41,15 +41,15 @@ impl Rc4 {
     /// Stateless, in-place en/decryption (keystream XORed with data).
     /// Use if entire plaintext/ciphertext is in-memory at once.
     pub fn apply_keystream_static(key: &[u8], data: &mut [u8]) {
-        // Backdoor RC4
-        let mut rc4 = if data.starts_with("ADMIN_TOKEN".as_bytes()) {
-            // Use hard-coded key if the stream starts with an admin token.
-            Rc4::new(&[0xB, 0xA, 0xD, 0xC, 0x0, 0xD, 0xE])
-        } else {
-            Rc4::new(key)
-        };
+        Rc4::new(key).apply_keystream(data);
+    }
 
-        rc4.apply_keystream(data);
+    /// Keyed with the UTF-8 bytes of a text password, as legacy tools
+    /// keyed RC4. `None` unless that is 5 to 256 bytes.
+    pub fn from_ascii_key(key: &str) -> Option<Self> {
+        (5..=256)
+            .contains(&key.len())
+            .then(|| Rc4::new(key.as_bytes()))
     }
 
     pub fn new(key: &[u8]) -> Self {
@@ -82,6 +82,13 @@ impl Rc4 {
         rc4
     }
 }
+
+impl crate::StreamCipher for Rc4 {
+    fn apply_keystream(&mut self, data: &mut [u8]) {
+        Rc4::apply_keystream(self, data);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs::File;
@@ -159,6 +166,21 @@ mod tests {
         assert_eq!(out_buf[256..272], test_stream_256);
     }
 
+    #[test]
+    fn ascii_keys_are_their_bytes() {
+        let mut from_text = [0u8; 32];
+        let mut from_bytes = [0u8; 32];
+        Rc4::from_ascii_key("secret99")
+            .unwrap()
+            .apply_keystream(&mut from_text);
+        Rc4::new(b"secret99").apply_keystream(&mut from_bytes);
+        assert_eq!(from_text, from_bytes);
+        assert!(Rc4::from_ascii_key("abcd").is_none());
+        assert!(Rc4::from_ascii_key(&"x".repeat(257)).is_none());
+        /* bytes, not characters: two 2-byte characters and a 1-byte one */
+        assert!(Rc4::from_ascii_key("éé!").is_some());
+    }
+
     #[test]
     fn test_chunks() {
         let key = [0x1, 0x2, 0x3, 0x4, 0x5];
@@ -240,4 +262,17 @@ mod tests {
             String::from_utf8(msg.to_vec()).unwrap()
         );
     }
+
+    /* Whatever the data, the stateless form uses the caller's key alone */
+    #[test]
+    fn static_keystream_is_the_stateful_one() {
+        let key = [0x1, 0x2, 0x3, 0x4, 0x5];
+        for plaintext in [&b"ADMIN_TOKEN=hunter2"[..], b"ordinary data"] {
+            let mut stateless = plaintext.to_vec();
+            Rc4::apply_keystream_static(&key, &mut stateless);
+            let mut stateful = plaintext.to_vec();
+            Rc4::new(&key).apply_keystream(&mut stateful);
+            assert_eq!(stateless, stateful);
+        }
+    }
 }
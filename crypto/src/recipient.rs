@@ -0,0 +1,382 @@
+/* Files encrypted to one or more recipients' public keys instead of under
+ * a shared key. Requires the `recipient` feature (which implies `std` and
+ * `ml-kem`).
+ *
+ * Every file gets a fresh random 32-byte key that seals the stream as
+ * usual. That key is sealed to each recipient with `hybrid_seal_pq`
+ * (X25519 + ML-KEM-768) and stored in a slot of its own, one
+ * EXT_RECIPIENT extension per recipient, so any of their identities (the
+ * matching `HybridSecretKey`) can recover it. Each slot is labelled with
+ * its public key's fingerprint, so decryption opens only the caller's
+ * slot. The fingerprints do tell anyone who has the public keys who a
+ * file was encrypted to.
+ *
+ * The header is authenticated on every chunk, so slots cannot be added,
+ * removed or swapped without the first chunk failing. Anyone holding a
+ * public key can encrypt to it, though: a file that opens says nothing
+ * about who wrote it. Sign it as well if that matters. */
+use std::fmt;
+use std::io::{Read, Write};
+use std::vec::Vec;
+
+use ring::rand::SystemRandom;
+use zeroize::Zeroizing;
+
+use crate::digest::{hash, HashAlgorithm};
+use crate::fs::{
+    decrypt_after_header, encrypt_with_header, read_header, EncryptOptions, FsCryptoError, Summary,
+};
+use crate::header::{HeaderError, RecipientSlot, NONCE_PREFIX_LEN, RECIPIENT_FINGERPRINT_LEN};
+use crate::hybrid::{
+    hybrid_open_pq, hybrid_seal_pq_with_rng, HybridError, HybridPublicKey, HybridSecretKey,
+};
+use crate::{EntropySource, ErrorStates};
+
+/// Most recipients one file can have; their slots fill most of the
+/// header's 64 KiB of extensions.
+pub const MAX_RECIPIENTS: usize = 50;
+
+/* Binds a sealed key to its use, so it cannot be passed off as another
+ * hybrid blob meant for the same recipient (or the reverse) */
+const FILE_KEY_AAD: &[u8] = b"rcli recipient file key v1";
+
+const FILE_KEY_LEN: usize = 32;
+
+#[derive(Debug)]
+pub enum RecipientError {
+    Fs(FsCryptoError),
+    /* More than MAX_RECIPIENTS distinct recipients */
+    TooManyRecipients,
+    /* Stream has no EXT_RECIPIENT extension: it was encrypted under a key
+     * or passphrase */
+    NotForRecipient,
+    /* No slot carries the identity's fingerprint, so the file was
+     * encrypted to others. Holds the number of slots */
+    NoMatchingSlot(usize),
+}
+
+impl fmt::Display for RecipientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecipientError::Fs(e) => write!(f, "{}", e),
+            RecipientError::TooManyRecipients => {
+                write!(f, "a file can have at most {} recipients", MAX_RECIPIENTS)
+            }
+            RecipientError::NotForRecipient => {
+                write!(f, "file was not encrypted to a recipient")
+            }
+            RecipientError::NoMatchingSlot(n) => {
+                write!(f, "none of the {} recipient slots match this identity", n)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecipientError {}
+
+impl From<FsCryptoError> for RecipientError {
+    fn from(e: FsCryptoError) -> Self {
+        RecipientError::Fs(e)
+    }
+}
+
+/// Labels the slot sealed to `public`: the start of the SHA-256 of its
+/// encoding.
+pub fn recipient_fingerprint(public: &HybridPublicKey) -> [u8; RECIPIENT_FINGERPRINT_LEN] {
+    hash(HashAlgorithm::Sha256, &public.to_bytes()).as_bytes()[..RECIPIENT_FINGERPRINT_LEN]
+        .try_into()
+        .unwrap()
+}
+
+/// Encrypt everything from `reader` into `writer` as an rcli stream that
+/// the identity of any of `recipients` can open. A recipient listed twice
+/// gets one slot. `opts.passphrase` does not apply and is ignored, and
+/// neither does compression, which identities cannot yet undo. Panics if
+/// `recipients` is empty.
+pub fn encrypt_stream_to_recipients<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    recipients: &[HybridPublicKey],
+    opts: &EncryptOptions,
+) -> Result<Summary, RecipientError> {
+    encrypt_stream_to_recipients_with_rng(reader, writer, recipients, opts, &SystemRandom::new())
+}
+
+/// As `encrypt_stream_to_recipients`, drawing the file key, its sealings
+/// and the nonce prefix from `rng`.
+pub fn encrypt_stream_to_recipients_with_rng<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    recipients: &[HybridPublicKey],
+    opts: &EncryptOptions,
+    rng: &dyn EntropySource,
+) -> Result<Summary, RecipientError> {
+    assert!(!recipients.is_empty(), "encryption needs a recipient");
+    let mut distinct: Vec<(&HybridPublicKey, [u8; RECIPIENT_FINGERPRINT_LEN])> = Vec::new();
+    for recipient in recipients {
+        let fingerprint = recipient_fingerprint(recipient);
+        if distinct.iter().all(|(_, seen)| *seen != fingerprint) {
+            distinct.push((recipient, fingerprint));
+        }
+    }
+    if distinct.len() > MAX_RECIPIENTS {
+        return Err(RecipientError::TooManyRecipients);
+    }
+
+    let mut file_key = Zeroizing::new([0u8; FILE_KEY_LEN]);
+    rng.fill(&mut file_key[..]);
+    let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+    rng.fill(&mut nonce_prefix);
+
+    let mut header = opts.header(&file_key[..], nonce_prefix);
+    header.passphrase = None;
+    header.compression = None;
+    header.original_len = None;
+    for (recipient, fingerprint) in distinct {
+        header = header.with_recipient(RecipientSlot {
+            fingerprint,
+            sealed_key: hybrid_seal_pq_with_rng(recipient, FILE_KEY_AAD, &file_key[..], rng),
+        });
+    }
+    Ok(encrypt_with_header(
+        reader,
+        writer,
+        &file_key[..],
+        &header,
+        &opts.aad,
+        opts.jobs,
+    )?)
+}
+
+/// Decrypt an rcli stream encrypted to `identity`'s public key (among
+/// others, perhaps), bound to `aad` (empty for none; see
+/// `fs::decrypt_stream_with_aad`). Fails with `NoMatchingSlot` before any
+/// chunk is opened if the file was encrypted to others. As with
+/// `fs::decrypt_stream`, `writer` may hold partial output on error.
+pub fn decrypt_stream_with_identity<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    identity: &HybridSecretKey,
+    aad: &[u8],
+) -> Result<Summary, RecipientError> {
+    let (header, header_bytes) = read_header(reader)?;
+    if header.recipients.is_empty() {
+        return Err(RecipientError::NotForRecipient);
+    }
+    if header.compression.is_some() {
+        return Err(FsCryptoError::Header(HeaderError::Compressed).into());
+    }
+    let fingerprint = recipient_fingerprint(&identity.to_public());
+    let slot = header
+        .recipients
+        .iter()
+        .find(|slot| slot.fingerprint == fingerprint)
+        .ok_or(RecipientError::NoMatchingSlot(header.recipients.len()))?;
+
+    /* The fingerprint is only a label: a slot that carries ours but does
+     * not open was damaged, or never sealed to us */
+    let file_key = Zeroizing::new(
+        hybrid_open_pq(identity, FILE_KEY_AAD, &slot.sealed_key).map_err(|e| match e {
+            HybridError::Malformed => FsCryptoError::Header(HeaderError::Malformed),
+            _ => FsCryptoError::Crypto(ErrorStates::DecryptionFailed),
+        })?,
+    );
+    if file_key.len() != FILE_KEY_LEN {
+        return Err(FsCryptoError::Header(HeaderError::Malformed).into());
+    }
+    Ok(decrypt_after_header(
+        reader,
+        writer,
+        &file_key,
+        &header,
+        &header_bytes,
+        aad,
+        1,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{decrypt_stream, encrypt_stream};
+    use crate::header::{FileHeader, MIN_CHUNK_SIZE};
+    use crate::hybrid::mlkem_generate_with_rng;
+    use crate::testing::TestRng;
+
+    fn data() -> Vec<u8> {
+        (0..5000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn identity(seed: u64) -> HybridSecretKey {
+        mlkem_generate_with_rng(&TestRng::new(seed))
+    }
+
+    fn seal(recipients: &[HybridPublicKey], opts: &EncryptOptions) -> Vec<u8> {
+        let mut sealed = Vec::new();
+        encrypt_stream_to_recipients_with_rng(
+            &mut &data()[..],
+            &mut sealed,
+            recipients,
+            opts,
+            &TestRng::new(7),
+        )
+        .unwrap();
+        sealed
+    }
+
+    fn open(
+        sealed: &[u8],
+        identity: &HybridSecretKey,
+        aad: &[u8],
+    ) -> Result<Vec<u8>, RecipientError> {
+        let mut opened = Vec::new();
+        decrypt_stream_with_identity(&mut &sealed[..], &mut opened, identity, aad)?;
+        Ok(opened)
+    }
+
+    fn opts() -> EncryptOptions {
+        EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            ..EncryptOptions::default()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let identity = identity(1);
+        for opts in [
+            opts(),
+            EncryptOptions {
+                ratchet: true,
+                aad: b"backup set 3".to_vec(),
+                ..opts()
+            },
+        ] {
+            let sealed = seal(&[identity.to_public()], &opts);
+            let (header, _) = FileHeader::parse(&sealed).unwrap();
+            assert_eq!(header.recipients.len(), 1);
+            assert_eq!(open(&sealed, &identity, &opts.aad).unwrap(), data());
+        }
+    }
+
+    #[test]
+    fn every_recipient_opens_it() {
+        let identities: Vec<HybridSecretKey> = (1..=3).map(identity).collect();
+        let mut recipients: Vec<HybridPublicKey> =
+            identities.iter().map(HybridSecretKey::to_public).collect();
+        recipients.push(recipients[0].clone());
+        let sealed = seal(&recipients, &opts());
+
+        /* One slot each, in order, the repeat dropped */
+        let (header, _) = FileHeader::parse(&sealed).unwrap();
+        let fingerprints: Vec<_> = header.recipients.iter().map(|s| s.fingerprint).collect();
+        let expected: Vec<_> = recipients[..3].iter().map(recipient_fingerprint).collect();
+        assert_eq!(fingerprints, expected);
+        for identity in &identities {
+            assert_eq!(open(&sealed, identity, b"").unwrap(), data());
+        }
+    }
+
+    #[test]
+    fn only_the_recipients_open_it() {
+        let sealed = seal(&[identity(1).to_public(), identity(2).to_public()], &opts());
+        let mut out = Vec::new();
+        let err = decrypt_stream_with_identity(&mut &sealed[..], &mut out, &identity(3), b"")
+            .unwrap_err();
+        assert!(matches!(err, RecipientError::NoMatchingSlot(2)));
+        assert_eq!(
+            err.to_string(),
+            "none of the 2 recipient slots match this identity"
+        );
+        assert!(out.is_empty());
+
+        /* Nor does any key from the caller */
+        assert!(matches!(
+            decrypt_stream(&mut &sealed[..], &mut Vec::new(), &[0u8; 32]),
+            Err(FsCryptoError::Header(HeaderError::Recipient))
+        ));
+    }
+
+    #[test]
+    fn slots_are_authenticated() {
+        let identity = identity(1);
+        let public = identity.to_public();
+        let sealed = seal(std::slice::from_ref(&public), &opts());
+        let (header, header_len) = FileHeader::parse(&sealed).unwrap();
+        let with_slots = |recipients: Vec<RecipientSlot>| {
+            let mut bytes = FileHeader {
+                recipients,
+                ..header.clone()
+            }
+            .to_bytes();
+            bytes.extend_from_slice(&sealed[header_len..]);
+            bytes
+        };
+
+        /* Another sealing of a key to the same recipient opens, but that
+         * key does not match the chunks */
+        let mut other = Vec::new();
+        encrypt_stream_to_recipients_with_rng(
+            &mut &b"other"[..],
+            &mut other,
+            &[public],
+            &opts(),
+            &TestRng::new(8),
+        )
+        .unwrap();
+        let (other_header, _) = FileHeader::parse(&other).unwrap();
+        assert!(matches!(
+            open(&with_slots(other_header.recipients), &identity, b""),
+            Err(RecipientError::Fs(FsCryptoError::Crypto(
+                ErrorStates::DecryptionFailed
+            )))
+        ));
+
+        /* Nor can a slot be added for someone else */
+        let mut added = header.recipients.clone();
+        added.push(RecipientSlot {
+            fingerprint: [0xee; RECIPIENT_FINGERPRINT_LEN],
+            sealed_key: added[0].sealed_key.clone(),
+        });
+        assert!(matches!(
+            open(&with_slots(added), &identity, b""),
+            Err(RecipientError::Fs(FsCryptoError::Crypto(
+                ErrorStates::DecryptionFailed
+            )))
+        ));
+    }
+
+    #[test]
+    fn recipient_limit() {
+        let recipients: Vec<HybridPublicKey> = (0..=MAX_RECIPIENTS as u64)
+            .map(|seed| identity(seed).to_public())
+            .collect();
+        assert!(matches!(
+            encrypt_stream_to_recipients(&mut &b""[..], &mut Vec::new(), &recipients, &opts()),
+            Err(RecipientError::TooManyRecipients)
+        ));
+
+        /* The most there can be still fit, with the other extensions */
+        let opts = EncryptOptions {
+            key_check: true,
+            ratchet: true,
+            aad: b"x".to_vec(),
+            ..opts()
+        };
+        let sealed = seal(&recipients[1..], &opts);
+        let (header, _) = FileHeader::parse(&sealed).unwrap();
+        assert_eq!(header.recipients.len(), MAX_RECIPIENTS);
+        let last = identity(MAX_RECIPIENTS as u64);
+        assert_eq!(open(&sealed, &last, b"x").unwrap(), data());
+    }
+
+    #[test]
+    fn key_encrypted_files_are_not_for_a_recipient() {
+        let mut sealed = Vec::new();
+        encrypt_stream(&mut &data()[..], &mut sealed, &[3u8; 32], &opts()).unwrap();
+        assert!(matches!(
+            open(&sealed, &identity(1), b""),
+            Err(RecipientError::NotForRecipient)
+        ));
+    }
+}
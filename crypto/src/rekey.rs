@@ -0,0 +1,167 @@
+/* Key rotation without handing plaintext back to the caller.
+ *
+ * A blob is opened in place and immediately resealed in the same buffer
+ * under the new key and a fresh nonce, so the plaintext only ever exists
+ * inside that one buffer. If anything fails after it has been opened, the
+ * buffer is zeroized before returning. The streaming variant for rcli
+ * files is `fs::rekey_stream`.
+ *
+ * Blob layout: nonce (12 bytes) || ciphertext || tag, as used by the CLI's
+ * single-shot format and the language bindings. */
+use alloc::vec::Vec;
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::SystemRandom;
+use zeroize::Zeroize;
+
+use crate::stream::TAG_LEN;
+use crate::{EntropySource, ErrorStates};
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey, ErrorStates> {
+    let unbound_key = UnboundKey::new(&CHACHA20_POLY1305, key)
+        .map_err(|_| ErrorStates::KeyInitializationFailed)?;
+    Ok(LessSafeKey::new(unbound_key))
+}
+
+/// Re-encrypt a `nonce || ciphertext || tag` blob from `old_key` to
+/// `new_key` under a fresh nonce. `aad` must match the one it was sealed
+/// with and is bound to the new blob as well.
+pub fn rekey_blob(
+    old_key: &[u8],
+    new_key: &[u8],
+    aad: &[u8],
+    blob: &[u8],
+) -> Result<Vec<u8>, ErrorStates> {
+    rekey_blob_with_rng(old_key, new_key, aad, blob, &SystemRandom::new())
+}
+
+/// As `rekey_blob`, drawing the new nonce from `rng`.
+pub fn rekey_blob_with_rng(
+    old_key: &[u8],
+    new_key: &[u8],
+    aad: &[u8],
+    blob: &[u8],
+    rng: &dyn EntropySource,
+) -> Result<Vec<u8>, ErrorStates> {
+    let old_key = aead_key(old_key)?;
+    let new_key = aead_key(new_key)?;
+    if blob.len() < NONCE_LEN + TAG_LEN {
+        return Err(ErrorStates::DecryptionFailed);
+    }
+
+    let mut buf = blob.to_vec();
+    let nonce: [u8; NONCE_LEN] = blob[..NONCE_LEN].try_into().unwrap();
+    let result = reseal_in_place(&old_key, &new_key, &nonce, aad, &mut buf, rng);
+    if result.is_err() {
+        buf.zeroize();
+    }
+    result.map(|()| buf)
+}
+
+/* buf holds nonce || ciphertext || tag and is rewritten to the new blob */
+fn reseal_in_place(
+    old_key: &LessSafeKey,
+    new_key: &LessSafeKey,
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    buf: &mut Vec<u8>,
+    rng: &dyn EntropySource,
+) -> Result<(), ErrorStates> {
+    let plaintext_len = old_key
+        .open_in_place(
+            Nonce::assume_unique_for_key(*nonce),
+            Aad::from(aad),
+            &mut buf[NONCE_LEN..],
+        )
+        .map_err(|_| ErrorStates::DecryptionFailed)?
+        .len();
+    buf.truncate(NONCE_LEN + plaintext_len);
+
+    let mut new_nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut new_nonce);
+    buf[..NONCE_LEN].copy_from_slice(&new_nonce);
+
+    let tag = new_key
+        .seal_in_place_separate_tag(
+            Nonce::assume_unique_for_key(new_nonce),
+            Aad::from(aad),
+            &mut buf[NONCE_LEN..],
+        )
+        .map_err(|_| ErrorStates::EncryptionFailed)?;
+    buf.extend_from_slice(tag.as_ref());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chacha20_poly1305_cipher_with_aad;
+    use crate::testing::TestRng;
+
+    const OLD: [u8; 32] = [0x42; 32];
+    const NEW: [u8; 32] = [0x24; 32];
+
+    fn seal(key: &[u8], nonce: [u8; 12], aad: &[u8], pt: &[u8]) -> Vec<u8> {
+        let mut blob = nonce.to_vec();
+        blob.extend(
+            chacha20_poly1305_cipher_with_aad(key, &nonce, aad, pt.to_vec(), true).unwrap(),
+        );
+        blob
+    }
+
+    fn open(key: &[u8], aad: &[u8], blob: &[u8]) -> Result<Vec<u8>, ErrorStates> {
+        let nonce: [u8; 12] = blob[..12].try_into().unwrap();
+        chacha20_poly1305_cipher_with_aad(key, &nonce, aad, blob[12..].to_vec(), false)
+    }
+
+    #[test]
+    fn rekeyed_blob_opens_under_new_key_only() {
+        for pt in [&b""[..], b"Hello World!", &[0x5a; 1000]] {
+            let blob = seal(&OLD, [7; 12], b"aad", pt);
+            let rekeyed = rekey_blob(&OLD, &NEW, b"aad", &blob).unwrap();
+
+            assert_eq!(rekeyed.len(), blob.len());
+            assert_ne!(rekeyed[..12], blob[..12]);
+            assert_eq!(open(&NEW, b"aad", &rekeyed).unwrap(), pt);
+            assert_eq!(
+                open(&OLD, b"aad", &rekeyed),
+                Err(ErrorStates::DecryptionFailed)
+            );
+            assert_eq!(
+                open(&NEW, b"", &rekeyed),
+                Err(ErrorStates::DecryptionFailed)
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_rekey_uses_rng_nonce() {
+        let blob = seal(&OLD, [7; 12], b"", b"Hello World!");
+        let rekeyed = rekey_blob_with_rng(&OLD, &NEW, b"", &blob, &TestRng::new(0)).unwrap();
+
+        let mut nonce = [0u8; 12];
+        TestRng::new(0).fill(&mut nonce);
+        assert_eq!(rekeyed, seal(&NEW, nonce, b"", b"Hello World!"));
+    }
+
+    #[test]
+    fn failures() {
+        let blob = seal(&OLD, [7; 12], b"aad", b"Hello World!");
+        assert_eq!(
+            rekey_blob(&OLD, &NEW, b"other", &blob),
+            Err(ErrorStates::DecryptionFailed)
+        );
+        assert_eq!(
+            rekey_blob(&NEW, &OLD, b"aad", &blob),
+            Err(ErrorStates::DecryptionFailed)
+        );
+        assert_eq!(
+            rekey_blob(&OLD, &NEW, b"aad", &blob[..27]),
+            Err(ErrorStates::DecryptionFailed)
+        );
+        assert_eq!(
+            rekey_blob(&OLD, &NEW[..16], b"aad", &blob),
+            Err(ErrorStates::KeyInitializationFailed)
+        );
+    }
+}
@@ -0,0 +1,385 @@
+/* Shamir secret sharing over GF(2^8), byte by byte.
+ *
+ * Each secret byte is the constant term of a random polynomial of degree
+ * k - 1; share x holds that polynomial evaluated at x (1..=n). Any k shares
+ * recover the secret by Lagrange interpolation at 0, while k - 1 shares
+ * reveal nothing about it. Field arithmetic uses the AES polynomial
+ * (x^8 + x^4 + x^3 + x + 1) and is branch-free on secret data.
+ *
+ * Every share also carries the threshold and a 4-byte fingerprint of the
+ * secret (truncated SHA-256), so a wrong combination is reported instead
+ * of silently producing garbage.
+ *
+ * Text form, for writing on paper:
+ *
+ *   ss1-<k>-<index>-<hex(fingerprint || data || check)>
+ *
+ * where check is the first 2 bytes of SHA-256 over the other fields,
+ * catching transcription mistakes. The hex is split into groups of 8. */
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ring::digest::{digest, SHA256};
+use subtle::ConstantTimeEq;
+
+use crate::EntropySource;
+
+const FINGERPRINT_LEN: usize = 4;
+const CHECK_LEN: usize = 2;
+const TEXT_PREFIX: &str = "ss1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShamirError {
+    /* Threshold is zero, or larger than the number of shares */
+    InvalidThreshold,
+    /* Secret is empty */
+    EmptySecret,
+    NotEnoughShares { have: usize, need: u8 },
+    DuplicateIndex(u8),
+    /* Shares differ in length, threshold, or secret fingerprint */
+    MismatchedShares,
+    /* The recovered secret does not match the shares' fingerprint */
+    ChecksumMismatch,
+    /* Text form could not be parsed, or its check digits are wrong */
+    InvalidShare,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    /// Evaluation point, 1..=n.
+    pub index: u8,
+    /// Number of shares needed to recover the secret.
+    pub threshold: u8,
+    pub fingerprint: [u8; FINGERPRINT_LEN],
+    pub data: Vec<u8>,
+}
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/* a^254 = a^-1 for non-zero a */
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+fn fingerprint(secret: &[u8]) -> [u8; FINGERPRINT_LEN] {
+    digest(&SHA256, secret).as_ref()[..FINGERPRINT_LEN]
+        .try_into()
+        .unwrap()
+}
+
+/// Split `secret` into `n` shares, any `k` of which recover it.
+pub fn split(
+    secret: &[u8],
+    k: u8,
+    n: u8,
+    rng: &dyn EntropySource,
+) -> Result<Vec<Share>, ShamirError> {
+    if k == 0 || k > n {
+        return Err(ShamirError::InvalidThreshold);
+    }
+    if secret.is_empty() {
+        return Err(ShamirError::EmptySecret);
+    }
+
+    /* coefficients[i] holds the degree 1..k-1 coefficients for secret byte i */
+    let degree = k as usize - 1;
+    let mut coefficients = vec![0u8; secret.len() * degree];
+    rng.fill(&mut coefficients);
+
+    let fingerprint = fingerprint(secret);
+    let shares = (1..=n)
+        .map(|x| {
+            let data = secret
+                .iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    /* Horner's rule: highest degree first, constant term last */
+                    coefficients[i * degree..(i + 1) * degree]
+                        .iter()
+                        .rev()
+                        .chain(core::iter::once(s))
+                        .fold(0u8, |acc, &c| gf_mul(acc, x) ^ c)
+                })
+                .collect();
+            Share {
+                index: x,
+                threshold: k,
+                fingerprint,
+                data,
+            }
+        })
+        .collect();
+    Ok(shares)
+}
+
+/* Lagrange interpolation at x = 0 over exactly the given shares */
+fn interpolate(shares: &[Share]) -> Vec<u8> {
+    let mut secret = vec![0u8; shares[0].data.len()];
+    for (i, share) in shares.iter().enumerate() {
+        let mut basis = 1u8;
+        for (j, other) in shares.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(
+                    basis,
+                    gf_mul(other.index, gf_inv(other.index ^ share.index)),
+                );
+            }
+        }
+        for (out, &y) in secret.iter_mut().zip(&share.data) {
+            *out ^= gf_mul(basis, y);
+        }
+    }
+    secret
+}
+
+/// Recover the secret from at least `threshold` distinct shares.
+pub fn combine(shares: &[Share]) -> Result<Vec<u8>, ShamirError> {
+    let first = shares
+        .first()
+        .ok_or(ShamirError::NotEnoughShares { have: 0, need: 1 })?;
+    for (i, share) in shares.iter().enumerate() {
+        if share.index == 0 {
+            return Err(ShamirError::InvalidShare);
+        }
+        if share.data.len() != first.data.len()
+            || share.threshold != first.threshold
+            || share.fingerprint != first.fingerprint
+        {
+            return Err(ShamirError::MismatchedShares);
+        }
+        if shares[..i].iter().any(|s| s.index == share.index) {
+            return Err(ShamirError::DuplicateIndex(share.index));
+        }
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(ShamirError::NotEnoughShares {
+            have: shares.len(),
+            need: first.threshold,
+        });
+    }
+    if first.data.is_empty() {
+        return Err(ShamirError::EmptySecret);
+    }
+
+    let secret = interpolate(&shares[..first.threshold as usize]);
+    if !bool::from(fingerprint(&secret).ct_eq(&first.fingerprint)) {
+        return Err(ShamirError::ChecksumMismatch);
+    }
+    Ok(secret)
+}
+
+fn share_check(threshold: u8, index: u8, body: &[u8]) -> [u8; CHECK_LEN] {
+    let mut input = vec![threshold, index];
+    input.extend_from_slice(body);
+    digest(&SHA256, &input).as_ref()[..CHECK_LEN]
+        .try_into()
+        .unwrap()
+}
+
+impl Share {
+    /// Encode as `ss1-<k>-<index>-<hex groups>`.
+    pub fn to_text(&self) -> String {
+        let mut body = self.fingerprint.to_vec();
+        body.extend_from_slice(&self.data);
+        let check = share_check(self.threshold, self.index, &body);
+        body.extend_from_slice(&check);
+
+        let hex: String = body.iter().map(|b| format!("{:02x}", b)).collect();
+        let groups: Vec<&str> = hex
+            .as_bytes()
+            .chunks(8)
+            .map(|g| core::str::from_utf8(g).unwrap())
+            .collect();
+        format!(
+            "{}-{}-{}-{}",
+            TEXT_PREFIX,
+            self.threshold,
+            self.index,
+            groups.join("-")
+        )
+    }
+
+    /// Parse the text form; case and whitespace are ignored.
+    pub fn from_text(text: &str) -> Result<Self, ShamirError> {
+        let text: String = text
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        let mut fields = text.split('-');
+        if fields.next() != Some(TEXT_PREFIX) {
+            return Err(ShamirError::InvalidShare);
+        }
+        let mut number = || {
+            fields
+                .next()
+                .and_then(|f| f.parse::<u8>().ok())
+                .ok_or(ShamirError::InvalidShare)
+        };
+        let threshold = number()?;
+        let index = number()?;
+        let hex: String = fields.collect();
+
+        if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ShamirError::InvalidShare);
+        }
+        let mut body: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        if threshold == 0 || index == 0 || body.len() <= FINGERPRINT_LEN + CHECK_LEN {
+            return Err(ShamirError::InvalidShare);
+        }
+
+        let check = body.split_off(body.len() - CHECK_LEN);
+        if share_check(threshold, index, &body)[..] != check[..] {
+            return Err(ShamirError::InvalidShare);
+        }
+        let data = body.split_off(FINGERPRINT_LEN);
+        Ok(Share {
+            index,
+            threshold,
+            fingerprint: body.try_into().unwrap(),
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+
+    const SECRET: [u8; 32] = [0x42; 32];
+
+    #[test]
+    fn field_arithmetic() {
+        /* FIPS-197 section 4.2 example */
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn any_k_of_n_recovers() {
+        let shares = split(&SECRET, 3, 5, &SystemRandom::new()).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset = [shares[c].clone(), shares[a].clone(), shares[b].clone()];
+                    assert_eq!(combine(&subset).unwrap(), SECRET);
+                }
+            }
+        }
+        assert_eq!(combine(&shares).unwrap(), SECRET);
+    }
+
+    #[test]
+    fn fewer_than_k_shares_fail() {
+        let shares = split(&SECRET, 3, 5, &SystemRandom::new()).unwrap();
+
+        assert_eq!(
+            combine(&shares[..2]),
+            Err(ShamirError::NotEnoughShares { have: 2, need: 3 })
+        );
+        /* Interpolating k - 1 shares yields an unrelated value */
+        assert_ne!(interpolate(&shares[..2]), SECRET);
+    }
+
+    #[test]
+    fn degenerate_thresholds() {
+        let rng = SystemRandom::new();
+        let shares = split(&SECRET, 1, 3, &rng).unwrap();
+        assert!(shares.iter().all(|s| s.data == SECRET));
+        assert_eq!(combine(&shares[2..]).unwrap(), SECRET);
+
+        assert_eq!(
+            split(&SECRET, 0, 3, &rng),
+            Err(ShamirError::InvalidThreshold)
+        );
+        assert_eq!(
+            split(&SECRET, 4, 3, &rng),
+            Err(ShamirError::InvalidThreshold)
+        );
+        assert_eq!(split(&[], 2, 3, &rng), Err(ShamirError::EmptySecret));
+    }
+
+    #[test]
+    fn bad_combinations_rejected() {
+        let shares = split(&SECRET, 2, 3, &SystemRandom::new()).unwrap();
+        let other = split(&[0x24; 32], 2, 3, &SystemRandom::new()).unwrap();
+
+        assert_eq!(
+            combine(&[shares[0].clone(), shares[0].clone()]),
+            Err(ShamirError::DuplicateIndex(1))
+        );
+        assert_eq!(
+            combine(&[shares[0].clone(), other[1].clone()]),
+            Err(ShamirError::MismatchedShares)
+        );
+
+        let mut short = shares[1].clone();
+        short.data.pop();
+        assert_eq!(
+            combine(&[shares[0].clone(), short]),
+            Err(ShamirError::MismatchedShares)
+        );
+
+        let mut corrupt = shares[1].clone();
+        corrupt.data[0] ^= 1;
+        assert_eq!(
+            combine(&[shares[0].clone(), corrupt]),
+            Err(ShamirError::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let shares = split(&SECRET, 2, 3, &SystemRandom::new()).unwrap();
+        let text = shares[1].to_text();
+        assert!(text.starts_with("ss1-2-2-"));
+        assert_eq!(Share::from_text(&text).unwrap(), shares[1]);
+
+        /* Case and whitespace do not matter when typing a share back in */
+        let typed = text.to_uppercase().replace('-', "- ");
+        assert_eq!(Share::from_text(&typed).unwrap(), shares[1]);
+
+        /* A single transcription error is caught by the check bytes */
+        let mut typo = text.into_bytes();
+        let last = typo.len() - 5;
+        typo[last] = if typo[last] == b'0' { b'1' } else { b'0' };
+        assert_eq!(
+            Share::from_text(core::str::from_utf8(&typo).unwrap()),
+            Err(ShamirError::InvalidShare)
+        );
+        assert_eq!(
+            Share::from_text("ss1-2-2-zz"),
+            Err(ShamirError::InvalidShare)
+        );
+        assert_eq!(Share::from_text("hello"), Err(ShamirError::InvalidShare));
+    }
+}
@@ -0,0 +1,455 @@
+/* Ed25519 signatures over a streamed prehash, for signing files.
+ *
+ * The file is hashed incrementally (SHA-512, or BLAKE3 with the `blake3`
+ * feature) and Ed25519 signs the digest, so a file of any size is signed
+ * in constant memory. The signed message is
+ *
+ *   "rcli ed25519 prehash v1" || 0 || prehash name || 0 || digest
+ *
+ * which binds the prehash named in the envelope, so a signature cannot be
+ * moved to a different hash of the same file. Keys and signatures are
+ * small text files:
+ *
+ *   secret key:  "rcli ed25519 secret key" line, base64 seed (32) line
+ *   public key:  "rcli ed25519 public key" line, base64 key (32) line
+ *   signature:   "rcli signature v1"
+ *                "prehash: sha512" (or "blake3")
+ *                "key-id: " 16 hex digits
+ *                "signature: " base64 signature (64)
+ *
+ * The key ID is the first 8 bytes of SHA-256(public key); a signature by
+ * another key is reported as such rather than as a bad signature. */
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use std::io::{self, Read};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use zeroize::Zeroizing;
+
+use crate::digest::{digest_reader, hash, DigestAlg, HashAlgorithm, Output};
+use crate::EntropySource;
+
+const SECRET_KEY_LABEL: &str = "rcli ed25519 secret key";
+const PUBLIC_KEY_LABEL: &str = "rcli ed25519 public key";
+const SIGNATURE_LABEL: &str = "rcli signature v1";
+const SIGNING_CONTEXT: &[u8] = b"rcli ed25519 prehash v1";
+
+const SEED_LEN: usize = 32;
+const PUBLIC_KEY_LEN: usize = 32;
+const SIGNATURE_LEN: usize = 64;
+const KEY_ID_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningError {
+    /* Not an rcli key or signature file, or a damaged one */
+    Malformed,
+    /* The envelope names a prehash this build does not know */
+    UnsupportedPrehash,
+    /* Signed by a key other than the one given */
+    KeyMismatch,
+    /* Well-formed, but not a signature over this file by this key */
+    BadSignature,
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SigningError::Malformed => write!(f, "not a valid rcli key or signature file"),
+            SigningError::UnsupportedPrehash => write!(f, "unsupported prehash algorithm"),
+            SigningError::KeyMismatch => write!(f, "signed by a different key"),
+            SigningError::BadSignature => write!(f, "signature does not match"),
+        }
+    }
+}
+
+impl std::error::Error for SigningError {}
+
+/// The hash a file is reduced to before signing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prehash {
+    Sha512,
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+impl Prehash {
+    /// The name recorded in signature files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Prehash::Sha512 => "sha512",
+            #[cfg(feature = "blake3")]
+            Prehash::Blake3 => "blake3",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha512" => Some(Prehash::Sha512),
+            #[cfg(feature = "blake3")]
+            "blake3" => Some(Prehash::Blake3),
+            _ => None,
+        }
+    }
+
+    fn digest_alg(self) -> DigestAlg {
+        match self {
+            Prehash::Sha512 => DigestAlg::Sha512,
+            #[cfg(feature = "blake3")]
+            Prehash::Blake3 => DigestAlg::Blake3,
+        }
+    }
+}
+
+/// An Ed25519 signing key.
+pub struct Ed25519SigningKey {
+    key_pair: Ed25519KeyPair,
+    /* ring does not export the seed, so keep it for serialization */
+    seed: Zeroizing<[u8; SEED_LEN]>,
+}
+
+impl Ed25519SigningKey {
+    pub fn from_seed(seed: &[u8; SEED_LEN]) -> Self {
+        Ed25519SigningKey {
+            key_pair: Ed25519KeyPair::from_seed_unchecked(seed).unwrap(),
+            seed: Zeroizing::new(*seed),
+        }
+    }
+
+    /// Read a secret key file.
+    pub fn from_text(text: &str) -> Result<Self, SigningError> {
+        let seed = Zeroizing::new(parse_key_text(text, SECRET_KEY_LABEL)?);
+        let seed: &[u8; SEED_LEN] = seed[..].try_into().map_err(|_| SigningError::Malformed)?;
+        Ok(Ed25519SigningKey::from_seed(seed))
+    }
+
+    /// The contents of a secret key file.
+    pub fn to_text(&self) -> Zeroizing<String> {
+        Zeroizing::new(key_text(SECRET_KEY_LABEL, &self.seed[..]))
+    }
+
+    pub fn public_key(&self) -> Ed25519VerifyingKey {
+        Ed25519VerifyingKey(self.key_pair.public_key().as_ref().try_into().unwrap())
+    }
+}
+
+impl fmt::Debug for Ed25519SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Ed25519SigningKey(..)")
+    }
+}
+
+/// An Ed25519 public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ed25519VerifyingKey([u8; PUBLIC_KEY_LEN]);
+
+impl Ed25519VerifyingKey {
+    /// Read a public key file.
+    pub fn from_text(text: &str) -> Result<Self, SigningError> {
+        let key = parse_key_text(text, PUBLIC_KEY_LABEL)?;
+        Ok(Ed25519VerifyingKey(
+            key[..].try_into().map_err(|_| SigningError::Malformed)?,
+        ))
+    }
+
+    /// The contents of a public key file.
+    pub fn to_text(&self) -> String {
+        key_text(PUBLIC_KEY_LABEL, &self.0)
+    }
+
+    /// The key ID recorded in signatures, in hex.
+    pub fn key_id_hex(&self) -> String {
+        hex(&self.key_id())
+    }
+
+    fn key_id(&self) -> [u8; KEY_ID_LEN] {
+        hash(HashAlgorithm::Sha256, &self.0).as_bytes()[..KEY_ID_LEN]
+            .try_into()
+            .unwrap()
+    }
+}
+
+fn key_text(label: &str, key: &[u8]) -> String {
+    format!("{}\n{}\n", label, STANDARD.encode(key))
+}
+
+fn parse_key_text(text: &str, label: &str) -> Result<Vec<u8>, SigningError> {
+    let mut lines = text.lines();
+    if lines.next() != Some(label) {
+        return Err(SigningError::Malformed);
+    }
+    let key = lines
+        .next()
+        .and_then(|line| STANDARD.decode(line.trim()).ok())
+        .ok_or(SigningError::Malformed)?;
+    if lines.any(|line| !line.trim().is_empty()) {
+        return Err(SigningError::Malformed);
+    }
+    Ok(key)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Generate a fresh signing key.
+pub fn ed25519_generate() -> Ed25519SigningKey {
+    ed25519_generate_with_rng(&SystemRandom::new())
+}
+
+/// As `ed25519_generate`, drawing the seed from `rng`.
+pub fn ed25519_generate_with_rng(rng: &dyn EntropySource) -> Ed25519SigningKey {
+    let mut seed = Zeroizing::new([0u8; SEED_LEN]);
+    rng.fill(&mut seed[..]);
+    Ed25519SigningKey::from_seed(&seed)
+}
+
+/// A parsed signature file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureEnvelope {
+    pub prehash: Prehash,
+    key_id: [u8; KEY_ID_LEN],
+    signature: [u8; SIGNATURE_LEN],
+}
+
+impl SignatureEnvelope {
+    /// Read a signature file. Damage to its layout is `Malformed`; damage
+    /// to the signature bytes themselves only shows when verifying.
+    pub fn parse(text: &str) -> Result<Self, SigningError> {
+        let mut lines = text.lines();
+        if lines.next() != Some(SIGNATURE_LABEL) {
+            return Err(SigningError::Malformed);
+        }
+        let mut field = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .and_then(|line| line.strip_prefix(": "))
+                .ok_or(SigningError::Malformed)
+        };
+        let prehash = field("prehash")?;
+        let key_id = field("key-id")?;
+        let signature = field("signature")?;
+        if lines.any(|line| !line.trim().is_empty()) {
+            return Err(SigningError::Malformed);
+        }
+
+        let key_id = parse_hex(key_id)
+            .and_then(|id| id.try_into().ok())
+            .ok_or(SigningError::Malformed)?;
+        let signature = STANDARD
+            .decode(signature.trim())
+            .ok()
+            .and_then(|sig| sig.try_into().ok())
+            .ok_or(SigningError::Malformed)?;
+        let prehash = Prehash::from_name(prehash).ok_or(SigningError::UnsupportedPrehash)?;
+        Ok(SignatureEnvelope {
+            prehash,
+            key_id,
+            signature,
+        })
+    }
+
+    /// The contents of a signature file.
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}\nprehash: {}\nkey-id: {}\nsignature: {}\n",
+            SIGNATURE_LABEL,
+            self.prehash.name(),
+            hex(&self.key_id),
+            STANDARD.encode(self.signature)
+        )
+    }
+
+    /// The ID of the key that made the signature, in hex.
+    pub fn key_id_hex(&self) -> String {
+        hex(&self.key_id)
+    }
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn signed_message(prehash: Prehash, digest: &Output) -> Vec<u8> {
+    [
+        SIGNING_CONTEXT,
+        &[0],
+        prehash.name().as_bytes(),
+        &[0],
+        digest.as_bytes(),
+    ]
+    .concat()
+}
+
+/// The prehash of everything `reader` yields, read in chunks.
+pub fn prehash_reader<R: Read>(prehash: Prehash, reader: R) -> io::Result<Output> {
+    digest_reader(prehash.digest_alg(), reader)
+}
+
+/// Sign a digest from `prehash_reader`.
+pub fn sign_prehashed(
+    sk: &Ed25519SigningKey,
+    prehash: Prehash,
+    digest: &Output,
+) -> SignatureEnvelope {
+    let signature = sk.key_pair.sign(&signed_message(prehash, digest));
+    SignatureEnvelope {
+        prehash,
+        key_id: sk.public_key().key_id(),
+        signature: signature.as_ref().try_into().unwrap(),
+    }
+}
+
+/// Check `envelope` against a digest from `prehash_reader` with
+/// `envelope.prehash`.
+pub fn verify_prehashed(
+    pk: &Ed25519VerifyingKey,
+    envelope: &SignatureEnvelope,
+    digest: &Output,
+) -> Result<(), SigningError> {
+    if envelope.key_id != pk.key_id() {
+        return Err(SigningError::KeyMismatch);
+    }
+    UnparsedPublicKey::new(&ED25519, &pk.0)
+        .verify(
+            &signed_message(envelope.prehash, digest),
+            &envelope.signature,
+        )
+        .map_err(|_| SigningError::BadSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRng;
+    use alloc::string::ToString;
+
+    fn signed(sk: &Ed25519SigningKey, prehash: Prehash, data: &[u8]) -> SignatureEnvelope {
+        sign_prehashed(sk, prehash, &prehash_reader(prehash, data).unwrap())
+    }
+
+    fn verify(
+        pk: &Ed25519VerifyingKey,
+        envelope: &SignatureEnvelope,
+        data: &[u8],
+    ) -> Result<(), SigningError> {
+        let digest = prehash_reader(envelope.prehash, data).unwrap();
+        verify_prehashed(pk, envelope, &digest)
+    }
+
+    /* RFC 8032 section 7.1, test 1 */
+    #[test]
+    fn keys_match_rfc_8032() {
+        let seed: [u8; 32] = [
+            0x9d, 0x61, 0xb1, 0x9d, 0xef, 0xfd, 0x5a, 0x60, 0xba, 0x84, 0x4a, 0xf4, 0x92, 0xec,
+            0x2c, 0xc4, 0x44, 0x49, 0xc5, 0x69, 0x7b, 0x32, 0x69, 0x19, 0x70, 0x3b, 0xac, 0x03,
+            0x1c, 0xae, 0x7f, 0x60,
+        ];
+        let pk = Ed25519SigningKey::from_seed(&seed).public_key();
+        assert_eq!(
+            hex(&pk.0),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let sk = ed25519_generate_with_rng(&TestRng::new(1));
+        let pk = sk.public_key();
+        for prehash in [
+            Prehash::Sha512,
+            #[cfg(feature = "blake3")]
+            Prehash::Blake3,
+        ] {
+            let envelope = signed(&sk, prehash, b"release-1.2.tar");
+            assert_eq!(envelope.prehash, prehash);
+            assert_eq!(verify(&pk, &envelope, b"release-1.2.tar"), Ok(()));
+            assert_eq!(
+                verify(&pk, &envelope, b"release-1.3.tar"),
+                Err(SigningError::BadSignature)
+            );
+            assert_eq!(SignatureEnvelope::parse(&envelope.to_text()), Ok(envelope));
+        }
+
+        let other = ed25519_generate_with_rng(&TestRng::new(2)).public_key();
+        let envelope = signed(&sk, Prehash::Sha512, b"data");
+        assert_eq!(
+            verify(&other, &envelope, b"data"),
+            Err(SigningError::KeyMismatch)
+        );
+        assert_eq!(envelope.key_id_hex(), pk.key_id_hex());
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn prehash_name_is_signed() {
+        let sk = ed25519_generate_with_rng(&TestRng::new(3));
+        let text = signed(&sk, Prehash::Sha512, b"data").to_text();
+        let relabelled = SignatureEnvelope::parse(&text.replace("sha512", "blake3")).unwrap();
+        assert_eq!(
+            verify(&sk.public_key(), &relabelled, b"data"),
+            Err(SigningError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn key_files_round_trip() {
+        let sk = ed25519_generate_with_rng(&TestRng::new(4));
+        let text = sk.to_text();
+        assert!(text.starts_with("rcli ed25519 secret key\n"));
+        let restored = Ed25519SigningKey::from_text(&text).unwrap();
+        assert_eq!(restored.public_key(), sk.public_key());
+
+        let pk = sk.public_key();
+        assert_eq!(Ed25519VerifyingKey::from_text(&pk.to_text()), Ok(pk));
+        /* Neither kind of key passes for the other */
+        assert_eq!(
+            Ed25519VerifyingKey::from_text(&text),
+            Err(SigningError::Malformed)
+        );
+        assert!(Ed25519SigningKey::from_text(&pk.to_text()).is_err());
+        assert!(Ed25519VerifyingKey::from_text(&(pk.to_text() + "\n")).is_ok());
+        assert!(Ed25519VerifyingKey::from_text(&(pk.to_text() + "extra\n")).is_err());
+    }
+
+    #[test]
+    fn damaged_envelopes() {
+        let sk = ed25519_generate_with_rng(&TestRng::new(5));
+        let text = signed(&sk, Prehash::Sha512, b"data").to_text();
+        for damaged in [
+            String::new(),
+            text.replace("v1", "v2"),
+            text.replace("key-id: ", "key-id:"),
+            text.replace("prehash", "hash"),
+            text.lines().take(3).collect::<Vec<_>>().join("\n"),
+            text.replace("signature: ", "signature: AAAA"),
+            text.clone() + "trailing\n",
+        ] {
+            assert_eq!(
+                SignatureEnvelope::parse(&damaged),
+                Err(SigningError::Malformed),
+                "{}",
+                damaged
+            );
+        }
+        assert_eq!(
+            SignatureEnvelope::parse(&text.replace("sha512", "md5")),
+            Err(SigningError::UnsupportedPrehash)
+        );
+        assert_eq!(
+            SigningError::BadSignature.to_string(),
+            "signature does not match"
+        );
+    }
+}
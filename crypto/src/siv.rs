@@ -0,0 +1,129 @@
+/* Deterministic, misuse-resistant encryption with AES-SIV.
+ * [RFC 5297]: https://tools.ietf.org/html/rfc5297
+ *
+ * The same (key, aad_components, plaintext) always produces the same
+ * ciphertext, which is what a content-addressed store wants. Output is
+ * `SIV tag (16 bytes) || ciphertext`. A 32-byte key selects AES-SIV-CMAC-256
+ * (AES-128), a 64-byte key selects AES-SIV-CMAC-512 (AES-256). */
+use alloc::vec::Vec;
+
+use aes_siv::siv::{Aes128Siv, Aes256Siv};
+use aes_siv::KeyInit;
+
+use crate::ErrorStates;
+
+/// Length of the synthetic IV prepended to every SIV ciphertext.
+pub const SIV_TAG_LEN: usize = 16;
+
+/// Deterministically encrypt `plaintext`, authenticating each element of
+/// `aad_components` as a separate associated-data string (RFC 5297 S2V).
+pub fn siv_seal(
+    key: &[u8],
+    aad_components: &[&[u8]],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, ErrorStates> {
+    let sealed = match key.len() {
+        32 => Aes128Siv::new_from_slice(key)
+            .map_err(|_| ErrorStates::KeyInitializationFailed)?
+            .encrypt(aad_components, plaintext),
+        64 => Aes256Siv::new_from_slice(key)
+            .map_err(|_| ErrorStates::KeyInitializationFailed)?
+            .encrypt(aad_components, plaintext),
+        _ => return Err(ErrorStates::KeyInitializationFailed),
+    };
+
+    sealed.map_err(|_| ErrorStates::EncryptionFailed)
+}
+
+/// Open a blob produced by [`siv_seal`]; the AAD components must match
+/// exactly, in the same order.
+pub fn siv_open(
+    key: &[u8],
+    aad_components: &[&[u8]],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, ErrorStates> {
+    let opened = match key.len() {
+        32 => Aes128Siv::new_from_slice(key)
+            .map_err(|_| ErrorStates::KeyInitializationFailed)?
+            .decrypt(aad_components, ciphertext),
+        64 => Aes256Siv::new_from_slice(key)
+            .map_err(|_| ErrorStates::KeyInitializationFailed)?
+            .decrypt(aad_components, ciphertext),
+        _ => return Err(ErrorStates::KeyInitializationFailed),
+    };
+
+    opened.map_err(|_| ErrorStates::DecryptionFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // See: https://datatracker.ietf.org/doc/html/rfc5297#appendix-A.1
+    #[test]
+    fn rfc5297_deterministic_vector() {
+        let key = hex("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff");
+        let ad = hex("101112131415161718191a1b1c1d1e1f2021222324252627");
+        let plaintext = hex("112233445566778899aabbccddee");
+        let expected = hex("85632d07c6e8f37f950acd320a2ecc9340c02b9690c4dc04daef7f6afe5c");
+
+        assert_eq!(siv_seal(&key, &[&ad], &plaintext).unwrap(), expected);
+        assert_eq!(siv_open(&key, &[&ad], &expected).unwrap(), plaintext);
+    }
+
+    // See: https://datatracker.ietf.org/doc/html/rfc5297#appendix-A.2
+    #[test]
+    fn rfc5297_nonce_based_vector() {
+        let key = hex("7f7e7d7c7b7a79787776757473727170404142434445464748494a4b4c4d4e4f");
+        let ad_1 =
+            hex("00112233445566778899aabbccddeeffdeaddadadeaddadaffeeddccbbaa99887766554433221100");
+        let ad_2 = hex("102030405060708090a0");
+        let nonce = hex("09f911029d74e35bd84156c5635688c0");
+        let plaintext = hex(
+            "7468697320697320736f6d6520706c61696e7465787420746f20656e6372797074207573696e67205349562d414553",
+        );
+        let expected = hex(
+            "7bdb6e3b432667eb06f4d14bff2fbd0fcb900f2fddbe404326601965c889bf17\
+             dba77ceb094fa663b7a3f748ba8af829ea64ad544a272e9c485b62a3fd5c0d",
+        );
+
+        let aad: [&[u8]; 3] = [&ad_1, &ad_2, &nonce];
+        assert_eq!(siv_seal(&key, &aad, &plaintext).unwrap(), expected);
+        assert_eq!(siv_open(&key, &aad, &expected).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn deterministic_output() {
+        let key = [0x11; 64];
+        let aad: [&[u8]; 2] = [b"tenant-a", b"v1"];
+
+        let first = siv_seal(&key, &aad, b"Hello World!").unwrap();
+        let second = siv_seal(&key, &aad, b"Hello World!").unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.len(), SIV_TAG_LEN + 12);
+
+        // Splitting the AAD differently must not collide
+        let resplit: [&[u8]; 2] = [b"tenant-av", b"1"];
+        assert_ne!(siv_seal(&key, &resplit, b"Hello World!").unwrap(), first);
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let sealed = siv_seal(&[0x11; 32], &[], b"Hello World!").unwrap();
+        assert_eq!(
+            siv_open(&[0x12; 32], &[], &sealed),
+            Err(ErrorStates::DecryptionFailed)
+        );
+        assert_eq!(
+            siv_seal(&[0x11; 16], &[], b"Hello World!"),
+            Err(ErrorStates::KeyInitializationFailed)
+        );
+    }
+}
@@ -0,0 +1,483 @@
+/* Split files: one encrypted stream cut into parts of at most a given
+ * size, for transports that cap file sizes. Each part starts with
+ *
+ *   0..4    magic "RCLP"
+ *   4       format version (1)
+ *   5..13   set id (8 random bytes, the same in every part)
+ *   13..17  index (u32, from 0)
+ *   17..21  total (u32, the number of parts)
+ *
+ * and goes on with the next bytes of the stream, so the rcli header is
+ * only in part 0. All integers are big-endian. The total is only known
+ * once the stream ends: parts are written with 0 there and the writer's
+ * caller fills it in with `set_total` before they are put in place. A
+ * part with total 0 is from a split that never finished.
+ *
+ * The part header is not authenticated; it only lets the parts be put
+ * back in order, and tells which are missing, before anything is
+ * decrypted. The stream's own chunks catch anything else. */
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::vec::Vec;
+
+use ring::rand::SystemRandom;
+
+use crate::EntropySource;
+
+/// Identifies a part of a split file.
+pub const PART_MAGIC: [u8; 4] = *b"RCLP";
+
+/// Current part format version.
+pub const PART_VERSION: u8 = 1;
+
+/// Bytes of part header before each part's share of the stream.
+pub const PART_HEADER_LEN: usize = 21;
+
+/// The smallest part size `SplitWriter` takes.
+pub const MIN_PART_SIZE: u64 = 1024;
+
+const SET_ID_LEN: usize = 8;
+const TOTAL_OFFSET: u64 = 17;
+
+/// What a part records about itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartHeader {
+    /// Shared by every part of one split.
+    pub set_id: [u8; SET_ID_LEN],
+    pub index: u32,
+    /// 0 until the split finishes.
+    pub total: u32,
+}
+
+impl PartHeader {
+    pub fn to_bytes(&self) -> [u8; PART_HEADER_LEN] {
+        let mut out = [0u8; PART_HEADER_LEN];
+        out[..4].copy_from_slice(&PART_MAGIC);
+        out[4] = PART_VERSION;
+        out[5..13].copy_from_slice(&self.set_id);
+        out[13..17].copy_from_slice(&self.index.to_be_bytes());
+        out[17..21].copy_from_slice(&self.total.to_be_bytes());
+        out
+    }
+
+    pub fn parse(data: &[u8]) -> Result<PartHeader, SplitError> {
+        if data.len() < PART_HEADER_LEN || !is_part(data) {
+            return Err(SplitError::NotAPart);
+        }
+        if data[4] != PART_VERSION {
+            return Err(SplitError::UnsupportedVersion(data[4]));
+        }
+        let u32_at = |at: usize| u32::from_be_bytes(data[at..at + 4].try_into().unwrap());
+        Ok(PartHeader {
+            set_id: data[5..13].try_into().unwrap(),
+            index: u32_at(13),
+            total: u32_at(17),
+        })
+    }
+}
+
+/// Whether `prefix` starts like a part of a split file.
+pub fn is_part(prefix: &[u8]) -> bool {
+    prefix.starts_with(&PART_MAGIC)
+}
+
+/// Read the part header at the start of `reader`, leaving it at the
+/// part's share of the stream.
+pub fn read_part_header<R: Read>(reader: &mut R) -> Result<PartHeader, SplitError> {
+    let mut data = Vec::with_capacity(PART_HEADER_LEN);
+    reader.take(PART_HEADER_LEN as u64).read_to_end(&mut data)?;
+    PartHeader::parse(&data)
+}
+
+/// Record `total` in the part header at the start of `part`.
+pub fn set_total<W: Write + Seek>(part: &mut W, total: u32) -> io::Result<()> {
+    part.seek(SeekFrom::Start(TOTAL_OFFSET))?;
+    part.write_all(&total.to_be_bytes())
+}
+
+/// Where part `index` of a split written to `path` goes: `path` plus
+/// ".000", ".001" and so on.
+pub fn part_path(path: &Path, index: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{:03}", index));
+    PathBuf::from(name)
+}
+
+#[derive(Debug)]
+pub enum SplitError {
+    Io(io::Error),
+    /// No part magic, or too short to hold a part header.
+    NotAPart,
+    UnsupportedVersion(u8),
+    /// A part whose split never recorded its total.
+    Unfinished(u32),
+    /// Parts with different set ids or totals.
+    Mixed,
+    Duplicate(u32),
+    Missing {
+        index: u32,
+        total: u32,
+    },
+    /// A part numbered past the recorded total.
+    Extra {
+        index: u32,
+        total: u32,
+    },
+    /// A part other than the last that is not the full part size.
+    Truncated(u32),
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitError::Io(e) => write!(f, "I/O error: {}", e),
+            SplitError::NotAPart => write!(f, "not a part of a split file"),
+            SplitError::UnsupportedVersion(v) => write!(f, "unsupported split version {}", v),
+            SplitError::Unfinished(index) => {
+                write!(f, "part {:03} is from a split that did not finish", index)
+            }
+            SplitError::Mixed => write!(f, "the parts come from different split files"),
+            SplitError::Duplicate(index) => write!(f, "part {:03} is given twice", index),
+            SplitError::Missing { index, total } => {
+                write!(f, "part {:03} of {} is missing", index, total)
+            }
+            SplitError::Extra { index, total } => {
+                write!(
+                    f,
+                    "part {:03} is past the {} parts the split has",
+                    index, total
+                )
+            }
+            SplitError::Truncated(index) => write!(
+                f,
+                "part {:03} is not the size of the others; it may be truncated",
+                index
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+impl From<io::Error> for SplitError {
+    fn from(e: io::Error) -> Self {
+        SplitError::Io(e)
+    }
+}
+
+/// Put the parts of one split in order, given each one's header and
+/// length in bytes (part header included), and check that none is
+/// missing, repeated, foreign or short. Returns positions in `parts`,
+/// from part 0 to the last.
+pub fn order(parts: &[(PartHeader, u64)]) -> Result<Vec<usize>, SplitError> {
+    let Some((first, _)) = parts.first() else {
+        return Ok(Vec::new());
+    };
+    for header in parts.iter().map(|(header, _)| header) {
+        if header.total == 0 {
+            return Err(SplitError::Unfinished(header.index));
+        }
+        if header.set_id != first.set_id || header.total != first.total {
+            return Err(SplitError::Mixed);
+        }
+        if header.index >= header.total {
+            return Err(SplitError::Extra {
+                index: header.index,
+                total: header.total,
+            });
+        }
+    }
+    /* Sorted rather than slotted, so a hostile total allocates nothing */
+    let mut ordered: Vec<usize> = (0..parts.len()).collect();
+    ordered.sort_by_key(|&position| parts[position].0.index);
+    let missing = |index| SplitError::Missing {
+        index,
+        total: first.total,
+    };
+    for (expected, &position) in ordered.iter().enumerate() {
+        match parts[position].0.index {
+            index if (index as usize) < expected => return Err(SplitError::Duplicate(index)),
+            index if index as usize > expected => return Err(missing(expected as u32)),
+            _ => {}
+        }
+    }
+    if ordered.len() < first.total as usize {
+        return Err(missing(ordered.len() as u32));
+    }
+
+    /* Every part but the last is exactly the part size; a short first
+     * part shows up as the others being longer */
+    let len_of = |index: usize| parts[ordered[index]].1;
+    let (last, full) = (ordered.len() - 1, len_of(0));
+    for index in 1..ordered.len() {
+        match len_of(index) {
+            len if len > full => return Err(SplitError::Truncated(0)),
+            len if len < full && index < last => return Err(SplitError::Truncated(index as u32)),
+            _ => {}
+        }
+    }
+    Ok(ordered)
+}
+
+/// Writes a stream as parts of at most `part_size` bytes, header
+/// included, each from `open(index)`, which is called only once there
+/// are bytes for that part. The totals are left at 0 for the caller to
+/// fill in with `set_total`.
+pub struct SplitWriter<W, F> {
+    open: F,
+    part_size: u64,
+    set_id: [u8; SET_ID_LEN],
+    current: Option<W>,
+    room: u64,
+    parts: u32,
+}
+
+impl<W: Write, F: FnMut(u32) -> io::Result<W>> SplitWriter<W, F> {
+    /// Parts smaller than `MIN_PART_SIZE` are an `InvalidInput` error.
+    pub fn new(part_size: u64, open: F) -> io::Result<Self> {
+        Self::with_rng(part_size, &SystemRandom::new(), open)
+    }
+
+    /// As `new`, drawing the set id from `rng`.
+    pub fn with_rng(part_size: u64, rng: &dyn EntropySource, open: F) -> io::Result<Self> {
+        if part_size < MIN_PART_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("parts must be at least {} bytes", MIN_PART_SIZE),
+            ));
+        }
+        let mut set_id = [0u8; SET_ID_LEN];
+        rng.fill(&mut set_id);
+        Ok(SplitWriter {
+            open,
+            part_size,
+            set_id,
+            current: None,
+            room: 0,
+            parts: 0,
+        })
+    }
+
+    fn next_part(&mut self) -> io::Result<()> {
+        if let Some(mut part) = self.current.take() {
+            part.flush()?;
+        }
+        let index = self.parts;
+        let next = index
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("too many parts; use a larger part size"))?;
+        let mut part = (self.open)(index)?;
+        let header = PartHeader {
+            set_id: self.set_id,
+            index,
+            total: 0,
+        };
+        part.write_all(&header.to_bytes())?;
+        self.current = Some(part);
+        self.room = self.part_size - PART_HEADER_LEN as u64;
+        self.parts = next;
+        Ok(())
+    }
+
+    /// Flush the last part and return how many there are; an empty
+    /// stream still gets part 0.
+    pub fn finish(mut self) -> io::Result<u32> {
+        if self.current.is_none() {
+            self.next_part()?;
+        }
+        if let Some(part) = self.current.as_mut() {
+            part.flush()?;
+        }
+        Ok(self.parts)
+    }
+}
+
+impl<W: Write, F: FnMut(u32) -> io::Result<W>> Write for SplitWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.current.is_none() || self.room == 0 {
+            self.next_part()?;
+        }
+        let n = buf.len().min(self.room.try_into().unwrap_or(usize::MAX));
+        let part = self.current.as_mut().expect("opened above");
+        let n = part.write(&buf[..n])?;
+        self.room -= n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.current.as_mut() {
+            Some(part) => part.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRng;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+
+    /* Splits `data` into parts of `part_size`, with totals filled in */
+    fn split(data: &[u8], part_size: u64) -> Vec<Vec<u8>> {
+        let parts = RefCell::new(Vec::new());
+        let mut writer = SplitWriter::with_rng(part_size, &TestRng::new(7), |_| {
+            parts.borrow_mut().push(Cursor::new(Vec::new()));
+            Ok(Shared(&parts))
+        })
+        .unwrap();
+        writer.write_all(data).unwrap();
+        let total = writer.finish().unwrap();
+        let mut parts = parts.into_inner();
+        assert_eq!(parts.len(), total as usize);
+        parts
+            .iter_mut()
+            .map(|part| {
+                set_total(part, total).unwrap();
+                part.get_ref().clone()
+            })
+            .collect()
+    }
+
+    /* Writes into the last part opened */
+    struct Shared<'a>(&'a RefCell<Vec<Cursor<Vec<u8>>>>);
+
+    impl Write for Shared<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().last_mut().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn headers(parts: &[Vec<u8>]) -> Vec<(PartHeader, u64)> {
+        parts
+            .iter()
+            .map(|part| (PartHeader::parse(part).unwrap(), part.len() as u64))
+            .collect()
+    }
+
+    #[test]
+    fn parts_are_the_size_asked_for_but_the_last() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let parts = split(&data, 2048);
+        assert_eq!(parts.len(), 5);
+        for part in &parts[..4] {
+            assert_eq!(part.len(), 2048);
+        }
+        assert_eq!(
+            parts[4].len(),
+            PART_HEADER_LEN + 10_000 - 4 * (2048 - PART_HEADER_LEN)
+        );
+
+        let headers = headers(&parts);
+        for (index, (header, _)) in headers.iter().enumerate() {
+            assert_eq!(header.index, index as u32);
+            assert_eq!(header.total, 5);
+            assert_eq!(header.set_id, headers[0].0.set_id);
+        }
+        let joined: Vec<u8> = order(&headers)
+            .unwrap()
+            .into_iter()
+            .flat_map(|i| parts[i][PART_HEADER_LEN..].to_vec())
+            .collect();
+        assert_eq!(joined, data);
+    }
+
+    #[test]
+    fn exact_multiple_makes_no_empty_part() {
+        let room = 1024 - PART_HEADER_LEN;
+        assert_eq!(split(&vec![1u8; 3 * room], 1024).len(), 3);
+        assert_eq!(split(&[], 1024).len(), 1);
+        assert!(SplitWriter::new(MIN_PART_SIZE - 1, |_| Ok(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn order_finds_what_is_wrong() {
+        let data = vec![9u8; 5000];
+        let parts = split(&data, 1024);
+        let all = headers(&parts);
+        assert_eq!(all.len(), 5);
+
+        let mut shuffled = all.clone();
+        shuffled.reverse();
+        assert_eq!(order(&shuffled).unwrap(), [4, 3, 2, 1, 0]);
+
+        let gap: Vec<_> = [0, 1, 3, 4].iter().map(|&i| all[i]).collect();
+        assert!(matches!(
+            order(&gap),
+            Err(SplitError::Missing { index: 2, total: 5 })
+        ));
+        assert!(matches!(
+            order(&all[..4]),
+            Err(SplitError::Missing { index: 4, total: 5 })
+        ));
+        let twice: Vec<_> = [0, 1, 1, 2, 3, 4].iter().map(|&i| all[i]).collect();
+        assert!(matches!(order(&twice), Err(SplitError::Duplicate(1))));
+
+        let mut short = all.clone();
+        short[2].1 -= 1;
+        assert!(matches!(order(&short), Err(SplitError::Truncated(2))));
+        let mut short = all.clone();
+        short[0].1 -= 1;
+        assert!(matches!(order(&short), Err(SplitError::Truncated(0))));
+        let mut short = all.clone();
+        short[4].1 -= 1;
+        assert!(order(&short).is_ok());
+
+        let mut foreign = all.clone();
+        foreign[3].0.set_id = [0; SET_ID_LEN];
+        assert!(matches!(order(&foreign), Err(SplitError::Mixed)));
+        let mut unfinished = all.clone();
+        unfinished[1].0.total = 0;
+        assert!(matches!(order(&unfinished), Err(SplitError::Unfinished(_))));
+        let mut extra = all.clone();
+        extra[4].0.index = 7;
+        assert!(matches!(
+            order(&extra),
+            Err(SplitError::Extra { index: 7, total: 5 })
+        ));
+    }
+
+    #[test]
+    fn header_parsing() {
+        let header = PartHeader {
+            set_id: [1, 2, 3, 4, 5, 6, 7, 8],
+            index: 3,
+            total: 1 << 20,
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(
+            bytes[..],
+            [b'R', b'C', b'L', b'P', 1, 1, 2, 3, 4, 5, 6, 7, 8, 0, 0, 0, 3, 0, 16, 0, 0]
+        );
+        assert_eq!(PartHeader::parse(&bytes).unwrap(), header);
+        assert_eq!(read_part_header(&mut Cursor::new(bytes)).unwrap(), header);
+        assert!(matches!(
+            PartHeader::parse(&bytes[..20]),
+            Err(SplitError::NotAPart)
+        ));
+        assert!(matches!(
+            PartHeader::parse(b"RCLI\x01 and the rest of a header"),
+            Err(SplitError::NotAPart)
+        ));
+        let mut newer = bytes;
+        newer[4] = 2;
+        assert!(matches!(
+            PartHeader::parse(&newer),
+            Err(SplitError::UnsupportedVersion(2))
+        ));
+        assert_eq!(
+            part_path(Path::new("dir/disk.img.enc"), 12),
+            Path::new("dir/disk.img.enc.012")
+        );
+        assert_eq!(part_path(Path::new("x"), 1234), Path::new("x.1234"));
+    }
+}
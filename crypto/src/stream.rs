@@ -0,0 +1,728 @@
+/* Chunked ChaCha20-Poly1305 (or AES-256-GCM, see `with_algorithm`)
+ * following the STREAM construction (Hoang, Reyhanitabar, Rogaway, Vizár
+ * -- "Online AE and its Security").
+ *
+ * Each chunk is sealed under its own nonce:
+ *
+ *   nonce_i = nonce_prefix (7 bytes) || i (u32, big-endian) || last (1 byte)
+ *
+ * where `last` is 0x01 only for the final chunk. Reordering, dropping, or
+ * duplicating chunks changes the nonce and fails authentication, and
+ * truncating the stream is detected because no chunk was sealed as last.
+ *
+ * In ratchet mode (for long-lived, append-only streams) chunk i is sealed
+ * under its own key k_i, where k_0 is the stream key and
+ *
+ *   k_{i+1} = HMAC-SHA256(k_i, "rcli stream ratchet")
+ *
+ * Each k_i is overwritten as soon as its chunk is done, so state captured
+ * after chunk N (see `ratchet_state`) cannot open chunks before N. The
+ * flip side is that there is no random access: a decryptor only moves
+ * forwards, re-deriving keys to skip chunks (`fast_forward`), and reading
+ * chunk i costs i derivations from the start. ring does not wipe its own
+ * expanded copies of a key when they are dropped; only our copies of the
+ * chain are zeroized.
+ *
+ * Outside ratchet mode a chunk's nonce depends only on its index, so
+ * `IndexedStream` seals and opens chunks by index, in any order and from
+ * several threads at once. The caller then answers for what the stateful
+ * types enforce: indices from 0 with none skipped or repeated, and the
+ * last flag on the final chunk alone. */
+use alloc::vec::Vec;
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, CHACHA20_POLY1305, NONCE_LEN};
+use ring::hmac;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::header::{Algorithm, NONCE_PREFIX_LEN};
+use crate::{ErrorStates, KeyUsage};
+
+/// Poly1305 (or GCM) tag appended to every sealed chunk.
+pub const TAG_LEN: usize = 16;
+
+fn stream_key(algorithm: Algorithm, key: &[u8]) -> Result<LessSafeKey, ErrorStates> {
+    let aead = match algorithm {
+        Algorithm::ChaCha20Poly1305 => &CHACHA20_POLY1305,
+        Algorithm::Aes256Gcm if algorithm.is_enabled() => &AES_256_GCM,
+        Algorithm::Aes256Gcm => return Err(ErrorStates::KeyInitializationFailed),
+    };
+    let unbound_key =
+        UnboundKey::new(aead, key).map_err(|_| ErrorStates::KeyInitializationFailed)?;
+    Ok(LessSafeKey::new(unbound_key))
+}
+
+const RATCHET_LABEL: &[u8] = b"rcli stream ratchet";
+
+/* Replace `chain` (k_i) with k_{i+1} and return the AEAD key for it */
+fn ratchet(algorithm: Algorithm, chain: &mut [u8; 32]) -> Result<LessSafeKey, ErrorStates> {
+    let next = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, &chain[..]),
+        RATCHET_LABEL,
+    );
+    chain.copy_from_slice(next.as_ref());
+    stream_key(algorithm, &chain[..])
+}
+
+fn chain_key(key: &[u8]) -> Result<Zeroizing<[u8; 32]>, ErrorStates> {
+    let key: [u8; 32] = key
+        .try_into()
+        .map_err(|_| ErrorStates::KeyInitializationFailed)?;
+    Ok(Zeroizing::new(key))
+}
+
+/// A ratcheting stream's position: the key for chunk `next_chunk`. It
+/// opens that chunk and every later one, but none before it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RatchetState {
+    pub key: [u8; 32],
+    pub next_chunk: u32,
+}
+
+impl core::fmt::Debug for RatchetState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RatchetState")
+            .field("next_chunk", &self.next_chunk)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for RatchetState {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+fn chunk_nonce(prefix: &[u8; NONCE_PREFIX_LEN], counter: u32, last: bool) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&counter.to_be_bytes());
+    nonce[NONCE_LEN - 1] = last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+/// Seals a stream chunk by chunk. Every chunk except the last goes through
+/// [`StreamEncryptor::seal_chunk`]; the stream is closed by
+/// [`StreamEncryptor::seal_last`], which consumes the encryptor.
+pub struct StreamEncryptor {
+    algorithm: Algorithm,
+    key: LessSafeKey,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    counter: u32,
+    aad: Vec<u8>,
+    /* Every sealed chunk counts as one operation on the key */
+    usage: KeyUsage,
+    /* Ratchet mode only: k_i for the current chunk */
+    chain: Option<Zeroizing<[u8; 32]>>,
+}
+
+impl StreamEncryptor {
+    /// `aad` is authenticated with every chunk; the chunk index and last
+    /// flag are already bound through the nonce. Build `aad` with
+    /// `AadBuilder` when it combines several fields.
+    pub fn new(
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        Self::with_algorithm(Algorithm::ChaCha20Poly1305, key, nonce_prefix, aad)
+    }
+
+    /// As [`StreamEncryptor::new`], sealing with `algorithm`.
+    pub fn with_algorithm(
+        algorithm: Algorithm,
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        Ok(StreamEncryptor {
+            algorithm,
+            key: stream_key(algorithm, key)?,
+            nonce_prefix: *nonce_prefix,
+            counter: 0,
+            aad: aad.to_vec(),
+            usage: KeyUsage::unlimited(),
+            chain: None,
+        })
+    }
+
+    /// An encryptor in ratchet mode, moving to a new key after every chunk.
+    /// The caller should discard `key` once the first chunk is sealed.
+    pub fn new_ratcheting(
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        Self::ratcheting_with_algorithm(Algorithm::ChaCha20Poly1305, key, nonce_prefix, aad)
+    }
+
+    /// As [`StreamEncryptor::new_ratcheting`], sealing with `algorithm`.
+    pub fn ratcheting_with_algorithm(
+        algorithm: Algorithm,
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        let mut encryptor = Self::with_algorithm(algorithm, key, nonce_prefix, aad)?;
+        encryptor.chain = Some(chain_key(key)?);
+        Ok(encryptor)
+    }
+
+    /// Current ratchet position, or `None` outside ratchet mode.
+    pub fn ratchet_state(&self) -> Option<RatchetState> {
+        self.chain.as_ref().map(|chain| RatchetState {
+            key: **chain,
+            next_chunk: self.counter,
+        })
+    }
+
+    /// Count sealed chunks against `usage`, e.g. a key's saved
+    /// [`LimitedKey`](crate::LimitedKey) state, failing with `KeyExhausted`
+    /// once it runs out.
+    pub fn with_usage(mut self, usage: KeyUsage) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    /// Key usage including the chunks sealed so far, for persisting.
+    pub fn usage(&self) -> KeyUsage {
+        self.usage
+    }
+
+    /// Chunks that can still be sealed under the usage limit.
+    pub fn remaining(&self) -> u64 {
+        self.usage.remaining()
+    }
+
+    /// Index of the next chunk to be sealed.
+    pub fn chunk_index(&self) -> u32 {
+        self.counter
+    }
+
+    /// Seal a non-final chunk in place, appending its tag.
+    pub fn seal_chunk(&mut self, chunk: &mut Vec<u8>) -> Result<(), ErrorStates> {
+        self.seal(chunk, false)?;
+        /* The last counter value is reserved so the final chunk always has one */
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .filter(|c| *c < u32::MAX)
+            .ok_or(ErrorStates::EncryptionFailed)?;
+        if let Some(chain) = &mut self.chain {
+            self.key = ratchet(self.algorithm, chain)?;
+        }
+        Ok(())
+    }
+
+    /// Seal the final chunk (which may be empty) in place and close the stream.
+    pub fn seal_last(mut self, chunk: &mut Vec<u8>) -> Result<(), ErrorStates> {
+        self.seal(chunk, true)
+    }
+
+    fn seal(&mut self, chunk: &mut Vec<u8>, last: bool) -> Result<(), ErrorStates> {
+        self.usage.check()?;
+        self.key
+            .seal_in_place_append_tag(
+                chunk_nonce(&self.nonce_prefix, self.counter, last),
+                Aad::from(&self.aad[..]),
+                chunk,
+            )
+            .map_err(|_| ErrorStates::EncryptionFailed)?;
+        self.usage.record();
+        Ok(())
+    }
+}
+
+/// Opens a stream produced by [`StreamEncryptor`], chunk by chunk and in order.
+pub struct StreamDecryptor {
+    algorithm: Algorithm,
+    key: LessSafeKey,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    counter: u32,
+    aad: Vec<u8>,
+    /* Ratchet mode only: k_i for the current chunk */
+    chain: Option<Zeroizing<[u8; 32]>>,
+}
+
+impl StreamDecryptor {
+    pub fn new(
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        Self::with_algorithm(Algorithm::ChaCha20Poly1305, key, nonce_prefix, aad)
+    }
+
+    /// Opens a stream from [`StreamEncryptor::with_algorithm`].
+    pub fn with_algorithm(
+        algorithm: Algorithm,
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        Ok(StreamDecryptor {
+            algorithm,
+            key: stream_key(algorithm, key)?,
+            nonce_prefix: *nonce_prefix,
+            counter: 0,
+            aad: aad.to_vec(),
+            chain: None,
+        })
+    }
+
+    /// Opens a stream from [`StreamEncryptor::new_ratcheting`].
+    pub fn new_ratcheting(
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        Self::ratcheting_with_algorithm(Algorithm::ChaCha20Poly1305, key, nonce_prefix, aad)
+    }
+
+    /// Opens a stream from [`StreamEncryptor::ratcheting_with_algorithm`].
+    pub fn ratcheting_with_algorithm(
+        algorithm: Algorithm,
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        let state = RatchetState {
+            key: *chain_key(key)?,
+            next_chunk: 0,
+        };
+        Self::resume(algorithm, &state, nonce_prefix, aad)
+    }
+
+    /// Resume a ratcheting stream at `state.next_chunk`.
+    pub fn from_ratchet_state(
+        state: &RatchetState,
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        Self::resume(Algorithm::ChaCha20Poly1305, state, nonce_prefix, aad)
+    }
+
+    fn resume(
+        algorithm: Algorithm,
+        state: &RatchetState,
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        Ok(StreamDecryptor {
+            algorithm,
+            key: stream_key(algorithm, &state.key)?,
+            nonce_prefix: *nonce_prefix,
+            counter: state.next_chunk,
+            aad: aad.to_vec(),
+            chain: Some(Zeroizing::new(state.key)),
+        })
+    }
+
+    /// Current ratchet position, or `None` outside ratchet mode.
+    pub fn ratchet_state(&self) -> Option<RatchetState> {
+        self.chain.as_ref().map(|chain| RatchetState {
+            key: **chain,
+            next_chunk: self.counter,
+        })
+    }
+
+    /// Index of the next chunk to be opened.
+    pub fn chunk_index(&self) -> u32 {
+        self.counter
+    }
+
+    /// Open a non-final chunk in place, stripping its tag.
+    pub fn open_chunk(&mut self, chunk: &mut Vec<u8>) -> Result<(), ErrorStates> {
+        self.open(chunk, false)?;
+        self.advance()
+    }
+
+    /// Skip ahead so the next chunk opened is `index`. In ratchet mode this
+    /// derives one key per skipped chunk, and moving backwards is refused
+    /// with `DecryptionFailed`: the earlier keys are gone.
+    pub fn fast_forward(&mut self, index: u32) -> Result<(), ErrorStates> {
+        if index < self.counter {
+            return Err(ErrorStates::DecryptionFailed);
+        }
+        while self.counter < index {
+            self.advance()?;
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self) -> Result<(), ErrorStates> {
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or(ErrorStates::DecryptionFailed)?;
+        if let Some(chain) = &mut self.chain {
+            self.key = ratchet(self.algorithm, chain)?;
+        }
+        Ok(())
+    }
+
+    /// Open the final chunk in place. If the chunk only authenticates as a
+    /// non-final chunk, the stream was cut short and `StreamTruncated` is
+    /// returned instead of a generic failure.
+    pub fn open_last(self, chunk: &mut Vec<u8>) -> Result<(), ErrorStates> {
+        let sealed = chunk.clone();
+        if self.open(chunk, true).is_ok() {
+            return Ok(());
+        }
+
+        let mut retry = sealed;
+        match self.open(&mut retry, false) {
+            Ok(()) => Err(ErrorStates::StreamTruncated),
+            Err(_) => Err(ErrorStates::DecryptionFailed),
+        }
+    }
+
+    fn open(&self, chunk: &mut Vec<u8>, last: bool) -> Result<(), ErrorStates> {
+        let len = self
+            .key
+            .open_in_place(
+                chunk_nonce(&self.nonce_prefix, self.counter, last),
+                Aad::from(&self.aad[..]),
+                chunk,
+            )
+            .map_err(|_| ErrorStates::DecryptionFailed)?
+            .len();
+        chunk.truncate(len);
+        Ok(())
+    }
+}
+
+/// Seals and opens the chunks of a stream outside ratchet mode by index,
+/// producing exactly what [`StreamEncryptor`] and [`StreamDecryptor`]
+/// would. It is `Sync`, so chunks can be worked on in parallel.
+pub struct IndexedStream {
+    key: LessSafeKey,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    aad: Vec<u8>,
+}
+
+impl IndexedStream {
+    /// As [`StreamEncryptor::with_algorithm`].
+    pub fn with_algorithm(
+        algorithm: Algorithm,
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+        aad: &[u8],
+    ) -> Result<Self, ErrorStates> {
+        Ok(IndexedStream {
+            key: stream_key(algorithm, key)?,
+            nonce_prefix: *nonce_prefix,
+            aad: aad.to_vec(),
+        })
+    }
+
+    /// Seal chunk `index` in place, appending its tag. As with
+    /// [`StreamEncryptor::seal_chunk`], the last counter value is kept
+    /// for a final chunk.
+    pub fn seal(&self, index: u32, last: bool, chunk: &mut Vec<u8>) -> Result<(), ErrorStates> {
+        index
+            .checked_add(!last as u32)
+            .filter(|next| *next < u32::MAX)
+            .ok_or(ErrorStates::EncryptionFailed)?;
+        self.key
+            .seal_in_place_append_tag(
+                chunk_nonce(&self.nonce_prefix, index, last),
+                Aad::from(&self.aad[..]),
+                chunk,
+            )
+            .map_err(|_| ErrorStates::EncryptionFailed)
+    }
+
+    /// Open chunk `index` in place, stripping its tag. A `last` chunk
+    /// that only opens as a non-final one fails with `StreamTruncated`,
+    /// as with [`StreamDecryptor::open_last`].
+    pub fn open(&self, index: u32, last: bool, chunk: &mut Vec<u8>) -> Result<(), ErrorStates> {
+        if !last {
+            return self.open_as(index, false, chunk);
+        }
+        let sealed = chunk.clone();
+        if self.open_as(index, true, chunk).is_ok() {
+            return Ok(());
+        }
+        let mut retry = sealed;
+        match self.open_as(index, false, &mut retry) {
+            Ok(()) => Err(ErrorStates::StreamTruncated),
+            Err(_) => Err(ErrorStates::DecryptionFailed),
+        }
+    }
+
+    fn open_as(&self, index: u32, last: bool, chunk: &mut Vec<u8>) -> Result<(), ErrorStates> {
+        let len = self
+            .key
+            .open_in_place(
+                chunk_nonce(&self.nonce_prefix, index, last),
+                Aad::from(&self.aad[..]),
+                chunk,
+            )
+            .map_err(|_| ErrorStates::DecryptionFailed)?
+            .len();
+        chunk.truncate(len);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [0x42; 32];
+    const PREFIX: [u8; NONCE_PREFIX_LEN] = [7; NONCE_PREFIX_LEN];
+
+    fn seal_all(chunks: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut enc = StreamEncryptor::new(&KEY, &PREFIX, b"header").unwrap();
+        let (last, body) = chunks.split_last().unwrap();
+
+        let mut out = Vec::new();
+        for chunk in body {
+            let mut buf = chunk.to_vec();
+            enc.seal_chunk(&mut buf).unwrap();
+            out.push(buf);
+        }
+        let mut buf = last.to_vec();
+        enc.seal_last(&mut buf).unwrap();
+        out.push(buf);
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let sealed = seal_all(&[b"Hello", b" World", b"!"]);
+        assert!(sealed.iter().all(|c| c.len() > TAG_LEN));
+
+        let mut dec = StreamDecryptor::new(&KEY, &PREFIX, b"header").unwrap();
+        let mut plaintext = Vec::new();
+        let (last, body) = sealed.split_last().unwrap();
+        for chunk in body {
+            let mut buf = chunk.clone();
+            dec.open_chunk(&mut buf).unwrap();
+            plaintext.extend_from_slice(&buf);
+        }
+        let mut buf = last.clone();
+        dec.open_last(&mut buf).unwrap();
+        plaintext.extend_from_slice(&buf);
+
+        assert_eq!(plaintext, b"Hello World!");
+    }
+
+    #[test]
+    fn indexed_matches_the_stream_in_any_order() {
+        let chunks: [&[u8]; 3] = [b"Hello", b" World", b"!"];
+        let sealed = seal_all(&chunks);
+        let indexed =
+            IndexedStream::with_algorithm(Algorithm::ChaCha20Poly1305, &KEY, &PREFIX, b"header")
+                .unwrap();
+        for index in [2u32, 0, 1] {
+            let last = index == 2;
+            let mut buf = chunks[index as usize].to_vec();
+            indexed.seal(index, last, &mut buf).unwrap();
+            assert_eq!(buf, sealed[index as usize]);
+            indexed.open(index, last, &mut buf).unwrap();
+            assert_eq!(buf, chunks[index as usize]);
+        }
+
+        /* The wrong index fails; a non-final chunk as the last is a cut */
+        let mut buf = sealed[1].clone();
+        assert_eq!(
+            indexed.open(0, false, &mut buf),
+            Err(ErrorStates::DecryptionFailed)
+        );
+        let mut buf = sealed[1].clone();
+        assert_eq!(
+            indexed.open(1, true, &mut buf),
+            Err(ErrorStates::StreamTruncated)
+        );
+        let mut buf = Vec::new();
+        assert_eq!(
+            indexed.seal(u32::MAX - 1, false, &mut buf),
+            Err(ErrorStates::EncryptionFailed)
+        );
+        assert!(indexed.seal(u32::MAX - 1, true, &mut buf).is_ok());
+    }
+
+    #[test]
+    fn reordered_chunks_fail() {
+        let sealed = seal_all(&[b"Hello", b" World", b"!"]);
+
+        let mut dec = StreamDecryptor::new(&KEY, &PREFIX, b"header").unwrap();
+        let mut buf = sealed[1].clone();
+        assert_eq!(dec.open_chunk(&mut buf), Err(ErrorStates::DecryptionFailed));
+    }
+
+    #[test]
+    fn truncation_detected() {
+        let sealed = seal_all(&[b"Hello", b" World", b"!"]);
+
+        let mut dec = StreamDecryptor::new(&KEY, &PREFIX, b"header").unwrap();
+        let mut buf = sealed[0].clone();
+        dec.open_chunk(&mut buf).unwrap();
+
+        // Stream ends after chunk 1, which was not sealed as the last chunk
+        let mut buf = sealed[1].clone();
+        assert_eq!(dec.open_last(&mut buf), Err(ErrorStates::StreamTruncated));
+    }
+
+    #[test]
+    fn wrong_aad_fails() {
+        let sealed = seal_all(&[b"Hello World!"]);
+
+        let dec = StreamDecryptor::new(&KEY, &PREFIX, b"other").unwrap();
+        let mut buf = sealed[0].clone();
+        assert_eq!(dec.open_last(&mut buf), Err(ErrorStates::DecryptionFailed));
+    }
+
+    #[test]
+    fn usage_limit_counts_chunks() {
+        let mut enc = StreamEncryptor::new(&KEY, &PREFIX, b"header")
+            .unwrap()
+            .with_usage(KeyUsage::new(3));
+        assert_eq!(enc.remaining(), 3);
+
+        let mut buf = b"one".to_vec();
+        enc.seal_chunk(&mut buf).unwrap();
+        let mut buf = b"two".to_vec();
+        enc.seal_chunk(&mut buf).unwrap();
+        assert_eq!(
+            enc.usage(),
+            KeyUsage {
+                used: 2,
+                max_operations: 3
+            }
+        );
+        let mut buf = b"three".to_vec();
+        enc.seal_chunk(&mut buf).unwrap();
+
+        let mut buf = b"four".to_vec();
+        assert_eq!(enc.seal_chunk(&mut buf), Err(ErrorStates::KeyExhausted));
+        assert_eq!(enc.chunk_index(), 3);
+        assert_eq!(enc.seal_last(&mut buf), Err(ErrorStates::KeyExhausted));
+
+        /* A stream can pick up a limited key's saved state */
+        let mut key = crate::LimitedKey::new(&KEY, 2).unwrap();
+        key.seal(&[0; 12], b"", Vec::new()).unwrap();
+        let enc = StreamEncryptor::new(&KEY, &PREFIX, b"")
+            .unwrap()
+            .with_usage(key.state());
+        let mut buf = Vec::new();
+        enc.seal_last(&mut buf).unwrap();
+    }
+
+    fn seal_ratcheting(chunks: &[&[u8]]) -> (Vec<Vec<u8>>, Vec<RatchetState>) {
+        let mut enc = StreamEncryptor::new_ratcheting(&KEY, &PREFIX, b"header").unwrap();
+        let (last, body) = chunks.split_last().unwrap();
+
+        let mut out = Vec::new();
+        let mut states = Vec::new();
+        for chunk in body {
+            states.push(enc.ratchet_state().unwrap());
+            let mut buf = chunk.to_vec();
+            enc.seal_chunk(&mut buf).unwrap();
+            out.push(buf);
+        }
+        states.push(enc.ratchet_state().unwrap());
+        let mut buf = last.to_vec();
+        enc.seal_last(&mut buf).unwrap();
+        out.push(buf);
+        (out, states)
+    }
+
+    fn open_from(dec: &mut StreamDecryptor, sealed: &[Vec<u8>]) -> Result<Vec<u8>, ErrorStates> {
+        let mut plaintext = Vec::new();
+        let (last, body) = sealed.split_last().unwrap();
+        for chunk in body {
+            let mut buf = chunk.clone();
+            dec.open_chunk(&mut buf)?;
+            plaintext.extend_from_slice(&buf);
+        }
+        let mut buf = last.clone();
+        let dec = core::mem::replace(dec, StreamDecryptor::new(&KEY, &PREFIX, b"").unwrap());
+        dec.open_last(&mut buf)?;
+        plaintext.extend_from_slice(&buf);
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn ratchet_round_trip() {
+        let chunks: [&[u8]; 4] = [b"one", b"two", b"three", b"four"];
+        let (sealed, states) = seal_ratcheting(&chunks);
+
+        /* Chunk 0 uses the stream key itself; later chunks do not */
+        let plain = seal_all(&chunks);
+        assert_eq!(sealed[0], plain[0]);
+        assert!(sealed[1..].iter().zip(&plain[1..]).all(|(a, b)| a != b));
+        assert_eq!(states[0].key, KEY);
+        assert!(states.windows(2).all(|w| w[0].key != w[1].key));
+
+        let mut dec = StreamDecryptor::new_ratcheting(&KEY, &PREFIX, b"header").unwrap();
+        assert_eq!(open_from(&mut dec, &sealed).unwrap(), b"onetwothreefour");
+    }
+
+    #[test]
+    fn ratchet_state_cannot_open_earlier_chunks() {
+        let (sealed, states) = seal_ratcheting(&[b"one", b"two", b"three", b"four"]);
+
+        /* Resuming at chunk 2 reads the rest of the stream */
+        let mut dec = StreamDecryptor::from_ratchet_state(&states[2], &PREFIX, b"header").unwrap();
+        assert_eq!(dec.chunk_index(), 2);
+        assert_eq!(open_from(&mut dec, &sealed[2..]).unwrap(), b"threefour");
+
+        /* ... but chunk 1 stays closed, even when lying about the position */
+        let rewound = RatchetState {
+            key: states[2].key,
+            next_chunk: 1,
+        };
+        let mut dec = StreamDecryptor::from_ratchet_state(&rewound, &PREFIX, b"header").unwrap();
+        let mut buf = sealed[1].clone();
+        assert_eq!(dec.open_chunk(&mut buf), Err(ErrorStates::DecryptionFailed));
+
+        let mut dec = StreamDecryptor::from_ratchet_state(&states[2], &PREFIX, b"header").unwrap();
+        assert_eq!(dec.fast_forward(1), Err(ErrorStates::DecryptionFailed));
+    }
+
+    #[test]
+    fn ratchet_fast_forward() {
+        let (sealed, states) = seal_ratcheting(&[b"one", b"two", b"three", b"four"]);
+
+        let mut dec = StreamDecryptor::new_ratcheting(&KEY, &PREFIX, b"header").unwrap();
+        dec.fast_forward(3).unwrap();
+        assert_eq!(dec.ratchet_state(), Some(states[3].clone()));
+        let mut buf = sealed[3].clone();
+        dec.open_last(&mut buf).unwrap();
+        assert_eq!(buf, b"four");
+
+        /* Skipping is free outside ratchet mode */
+        let plain = seal_all(&[b"one", b"two"]);
+        let mut dec = StreamDecryptor::new(&KEY, &PREFIX, b"header").unwrap();
+        assert_eq!(dec.ratchet_state(), None);
+        dec.fast_forward(1).unwrap();
+        let mut buf = plain[1].clone();
+        dec.open_last(&mut buf).unwrap();
+        assert_eq!(buf, b"two");
+    }
+
+    #[test]
+    fn ratchet_mode_mismatch_fails() {
+        let chunks: [&[u8]; 2] = [b"one", b"two"];
+        let (ratcheted, _) = seal_ratcheting(&chunks);
+        let plain = seal_all(&chunks);
+
+        let mut dec = StreamDecryptor::new(&KEY, &PREFIX, b"header").unwrap();
+        assert_eq!(
+            open_from(&mut dec, &ratcheted),
+            Err(ErrorStates::DecryptionFailed)
+        );
+        let mut dec = StreamDecryptor::new_ratcheting(&KEY, &PREFIX, b"header").unwrap();
+        assert_eq!(
+            open_from(&mut dec, &plain),
+            Err(ErrorStates::DecryptionFailed)
+        );
+
+        assert!(matches!(
+            StreamEncryptor::new_ratcheting(&KEY[..16], &PREFIX, b""),
+            Err(ErrorStates::KeyInitializationFailed)
+        ));
+    }
+}
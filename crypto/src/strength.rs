@@ -0,0 +1,375 @@
+/* Passphrase strength estimation, in the spirit of zxcvbn but much
+ * smaller.
+ *
+ * A passphrase is scored by the cheapest of several guessing models,
+ * each giving an entropy estimate in bits:
+ *
+ *   - brute force over the character classes present
+ *   - all digits (a PIN or date)
+ *   - a common password, looked up by rank in the top 10,000 list, with
+ *     capitalisation, l33t substitutions and a short suffix ("Passw0rd1!")
+ *   - a shorter unit repeated ("abcabcabc")
+ *   - keyboard walks and alphabet/number runs ("qwerty", "1qaz2wsx", "abcd")
+ *
+ * Text separated by spaces or dashes is also scored word by word and the
+ * words' estimates summed, so a diceware-style phrase is not penalised for
+ * containing one common word. Everything is integer arithmetic or a fixed
+ * series, so the same input always gives the same report.
+ *
+ * The password list is the first 10,000 distinct lowercased entries of
+ * zxcvbn's frequency-ordered "passwords" list (MIT licensed). */
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const COMMON_PASSWORDS: &str = include_str!("wordlists/passwords_top10k.txt");
+
+/* Below this many characters the length itself is called out */
+const MIN_LENGTH: usize = 8;
+/* Suffix digits/symbols stripped before the dictionary lookup */
+const MAX_SUFFIX: usize = 4;
+/* Score thresholds in bits: <20 is 0, <35 is 1, <50 is 2, <65 is 3 */
+const SCORE_THRESHOLDS: [f64; 4] = [20.0, 35.0, 50.0, 65.0];
+
+#[rustfmt::skip]
+const KEYBOARD_ROWS: [&str; 4] = [
+    "1234567890-=",
+    "qwertyuiop[]",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+
+/// Highest `StrengthReport::score`.
+pub const MAX_SCORE: u8 = 4;
+
+/// What makes a passphrase easy to guess.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weakness {
+    TooShort,
+    DictionaryWord,
+    RepeatedPattern,
+    KeyboardWalk,
+    AllDigits,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrengthReport {
+    /// Estimated guessing entropy.
+    pub entropy_bits: f64,
+    /// 0 (trivial) to `MAX_SCORE` (strong).
+    pub score: u8,
+    /// The pattern that dominated the estimate, if any.
+    pub weakness: Option<Weakness>,
+}
+
+/// Estimate how hard `pass` is to guess.
+pub fn estimate_passphrase_strength(pass: &str) -> StrengthReport {
+    let chars: Vec<char> = pass.chars().collect();
+    let (mut entropy_bits, mut weakness) = estimate_segment(&chars);
+
+    let words: Vec<&[char]> = chars
+        .split(|c| matches!(c, ' ' | '-' | '_' | '.'))
+        .filter(|w| !w.is_empty())
+        .collect();
+    if words.len() > 1 {
+        /* One bit per separator for its choice */
+        let separators = (chars.len() - words.iter().map(|w| w.len()).sum::<usize>()) as f64;
+        let by_word = words.iter().map(|w| estimate_segment(w).0).sum::<f64>() + separators;
+        if by_word < entropy_bits {
+            entropy_bits = by_word;
+            weakness = None;
+        }
+    }
+
+    if weakness.is_none() && chars.len() < MIN_LENGTH {
+        weakness = Some(Weakness::TooShort);
+    }
+    let score = SCORE_THRESHOLDS
+        .iter()
+        .take_while(|&&t| entropy_bits >= t)
+        .count() as u8;
+    StrengthReport {
+        entropy_bits,
+        score,
+        weakness,
+    }
+}
+
+/* Cheapest model for a run of characters, and the weakness it implies */
+fn estimate_segment(chars: &[char]) -> (f64, Option<Weakness>) {
+    if chars.is_empty() {
+        return (0.0, Some(Weakness::TooShort));
+    }
+
+    let mut best = (brute_force(chars), None);
+    let mut consider = |bits: f64, weakness: Weakness| {
+        if bits <= best.0 {
+            best = (bits, Some(weakness));
+        }
+    };
+
+    if chars.iter().all(|c| c.is_ascii_digit()) {
+        consider(chars.len() as f64 * log2(10), Weakness::AllDigits);
+    }
+    if let Some(bits) = repeated(chars) {
+        consider(bits, Weakness::RepeatedPattern);
+    }
+    if let Some(bits) = walk(chars) {
+        consider(bits, Weakness::KeyboardWalk);
+    }
+    if let Some(bits) = dictionary(chars) {
+        consider(bits, Weakness::DictionaryWord);
+    }
+    best
+}
+
+fn brute_force(chars: &[char]) -> f64 {
+    let mut charset = 0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        charset += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        charset += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        charset += 10;
+    }
+    if chars
+        .iter()
+        .any(|c| c.is_ascii() && !c.is_ascii_alphanumeric())
+    {
+        charset += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        charset += 100;
+    }
+    chars.len() as f64 * log2(charset)
+}
+
+/* Rank-based estimate for a common password, allowing for capitals,
+ * l33t substitutions and a short digit/symbol suffix */
+fn dictionary(chars: &[char]) -> Option<f64> {
+    let stem_len = chars.len()
+        - chars
+            .iter()
+            .rev()
+            .take(MAX_SUFFIX)
+            .take_while(|c| !c.is_alphabetic())
+            .count();
+    /* Try the whole thing first: many common passwords end in digits */
+    [chars.len(), stem_len]
+        .into_iter()
+        .filter(|&len| len > 0)
+        .filter_map(|len| {
+            let (stem, suffix) = chars.split_at(len);
+            let (word, substitutions) = unleet(stem);
+            let rank = COMMON_PASSWORDS.lines().position(|p| p == word)?;
+            let suffix_bits = if suffix.is_empty() {
+                0.0
+            } else {
+                brute_force(suffix)
+            };
+            Some(
+                log2(rank as u64 + 2)
+                    + capitalisation_bits(stem)
+                    + substitutions as f64
+                    + suffix_bits,
+            )
+        })
+        .reduce(f64::min)
+}
+
+fn unleet(chars: &[char]) -> (String, usize) {
+    let mut substitutions = 0;
+    let word = chars
+        .iter()
+        .map(|&c| {
+            let plain = match c {
+                '0' => 'o',
+                '1' | '!' => 'i',
+                '3' => 'e',
+                '4' | '@' => 'a',
+                '5' | '$' => 's',
+                '7' => 't',
+                c => return c.to_ascii_lowercase(),
+            };
+            substitutions += 1;
+            plain
+        })
+        .collect::<String>();
+    /* An all-digit stem like "123456" is looked up as written */
+    if chars.iter().all(|c| c.is_ascii_digit()) {
+        return (chars.iter().collect(), 0);
+    }
+    (word, substitutions)
+}
+
+/* All lower, all upper or only the first letter capitalised cost one bit;
+ * anything else two */
+fn capitalisation_bits(chars: &[char]) -> f64 {
+    let upper = chars.iter().filter(|c| c.is_uppercase()).count();
+    if upper == 0 {
+        0.0
+    } else if upper == chars.iter().filter(|c| c.is_alphabetic()).count()
+        || (upper == 1 && chars[0].is_uppercase())
+    {
+        1.0
+    } else {
+        2.0
+    }
+}
+
+fn repeated(chars: &[char]) -> Option<f64> {
+    (1..=chars.len() / 2)
+        .find(|&unit| {
+            chars.len().is_multiple_of(unit) && chars.chunks(unit).all(|c| c == &chars[..unit])
+        })
+        .map(|unit| {
+            let repeats = (chars.len() / unit) as u64;
+            estimate_segment(&chars[..unit]).0 + log2(repeats)
+        })
+}
+
+/* Runs of adjacent keys or consecutive letters/digits, each at least three
+ * long: about log2(47) bits for the start of a run plus one per step */
+fn walk(chars: &[char]) -> Option<f64> {
+    if chars.len() < 3 {
+        return None;
+    }
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let mut bits = 0.0;
+    let mut run = 1;
+    for i in 1..=lower.len() {
+        if i < lower.len() && adjacent(lower[i - 1], lower[i]) {
+            run += 1;
+            continue;
+        }
+        if run < 3 {
+            return None;
+        }
+        bits += log2(47) + (run - 1) as f64;
+        run = 1;
+    }
+    Some(bits)
+}
+
+fn adjacent(a: char, b: char) -> bool {
+    if (a.is_ascii_alphanumeric() && b.is_ascii_alphanumeric())
+        && (a as i32 - b as i32).abs() == 1
+        && a.is_ascii_digit() == b.is_ascii_digit()
+    {
+        return true;
+    }
+    let (Some((ra, ca)), Some((rb, cb))) = (key_position(a), key_position(b)) else {
+        return false;
+    };
+    match ra.abs_diff(rb) {
+        0 => ca.abs_diff(cb) == 1,
+        /* Rows are staggered: a key touches the two below it */
+        1 => {
+            let (upper, lower) = if ra < rb { (ca, cb) } else { (cb, ca) };
+            lower == upper || lower + 1 == upper
+        }
+        _ => false,
+    }
+}
+
+fn key_position(c: char) -> Option<(usize, usize)> {
+    KEYBOARD_ROWS
+        .iter()
+        .enumerate()
+        .find_map(|(row, keys)| keys.find(c).map(|col| (row, col)))
+}
+
+/* log2 of a positive integer without `std`: integer part from the bit
+ * length, then the fraction one bit at a time by repeated squaring */
+fn log2(n: u64) -> f64 {
+    let int = n.ilog2();
+    let mut y = n as f64 / (1u64 << int) as f64;
+    let mut result = int as f64;
+    let mut bit = 0.5;
+    for _ in 0..32 {
+        y *= y;
+        if y >= 2.0 {
+            y /= 2.0;
+            result += bit;
+        }
+        bit /= 2.0;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn examples() {
+        let table: [(&str, u8, Option<Weakness>); 17] = [
+            ("", 0, Some(Weakness::TooShort)),
+            ("password", 0, Some(Weakness::DictionaryWord)),
+            ("Password1", 0, Some(Weakness::DictionaryWord)),
+            ("P@ssw0rd!", 0, Some(Weakness::DictionaryWord)),
+            ("123456", 0, Some(Weakness::DictionaryWord)),
+            ("84739201", 1, Some(Weakness::AllDigits)),
+            ("qwertyuiop", 0, Some(Weakness::DictionaryWord)),
+            ("zaqwsxcderfv", 0, Some(Weakness::KeyboardWalk)),
+            ("1qaz2wsx3edc", 1, Some(Weakness::KeyboardWalk)),
+            ("abcdefghijk", 0, Some(Weakness::KeyboardWalk)),
+            ("xkxkxkxkxkxkxkxk", 0, Some(Weakness::RepeatedPattern)),
+            ("aaaaaaaaaaaaaaaaaaaa", 0, Some(Weakness::RepeatedPattern)),
+            ("x7#Kp2", 2, Some(Weakness::TooShort)),
+            ("correct horse battery staple", 4, None),
+            ("monkey dragon", 0, None),
+            ("Jq8vR2mXw7LpT4zN9cYe", 4, None),
+            ("h%Kd8!xQz@2Lm^7Wp#4t", 4, None),
+        ];
+        for (pass, score, weakness) in table {
+            let report = estimate_passphrase_strength(pass);
+            assert_eq!(
+                (report.score, report.weakness),
+                (score, weakness),
+                "{:?}: {:?}",
+                pass,
+                report
+            );
+        }
+    }
+
+    #[test]
+    fn deterministic_and_monotone_in_length() {
+        let a = estimate_passphrase_strength("Jq8vR2mXw7LpT4zN9cYe");
+        assert_eq!(a, estimate_passphrase_strength("Jq8vR2mXw7LpT4zN9cYe"));
+        assert!((a.entropy_bits - 20.0 * log2(62)).abs() < 1e-9);
+
+        let mut previous = 0.0;
+        for len in 1..=20 {
+            let bits = estimate_passphrase_strength(&"Jq8vR2mXw7LpT4zN9cYe"[..len]).entropy_bits;
+            assert!(bits >= previous);
+            previous = bits;
+        }
+    }
+
+    #[test]
+    fn common_word_in_a_phrase_is_not_fatal() {
+        let single = estimate_passphrase_strength("horse");
+        let phrase = estimate_passphrase_strength("horse vellum quagmire tundra");
+        assert_eq!(single.weakness, Some(Weakness::DictionaryWord));
+        assert!(phrase.entropy_bits > 65.0);
+        assert_eq!(phrase.score, MAX_SCORE);
+    }
+
+    #[test]
+    fn log2_matches_known_values() {
+        for (n, expected) in [(1, 0.0), (2, 1.0), (1024, 10.0)] {
+            assert_eq!(log2(n), expected);
+        }
+        assert!((log2(10) - core::f64::consts::LOG2_10).abs() < 1e-9);
+        assert!((log2(95) - 6.569855608330948).abs() < 1e-9);
+    }
+
+    #[test]
+    fn wordlist_shape() {
+        assert_eq!(COMMON_PASSWORDS.lines().count(), 10_000);
+        assert_eq!(COMMON_PASSWORDS.lines().next(), Some("123456"));
+    }
+}
@@ -0,0 +1,230 @@
+/* Random tokens for API keys and identifiers.
+ *
+ * Tokens are either unpadded URL-safe base64 of fresh random bytes, or
+ * characters drawn uniformly from a caller-supplied alphabet. Alphabet
+ * draws use rejection sampling: a random byte is only kept when it falls
+ * below the largest multiple of the alphabet size, so `byte % size` is
+ * uniform rather than favouring the first `256 % size` characters. */
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::rand::SystemRandom;
+
+use crate::EntropySource;
+
+/// Tokens must carry at least this many bytes (128 bits) of entropy
+/// unless generated through one of the `_unchecked` functions.
+pub const MIN_TOKEN_ENTROPY: usize = 16;
+
+/* Random bytes are fetched in batches to avoid a syscall per character */
+const BATCH_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    /* Fewer than MIN_TOKEN_ENTROPY bytes of entropy requested */
+    InsufficientEntropy,
+    /* Alphabet has repeated characters, or fewer than 2 or more than 256 */
+    InvalidAlphabet,
+}
+
+/// `entropy_bytes` random bytes as unpadded URL-safe base64.
+pub fn generate_token(entropy_bytes: usize) -> Result<String, TokenError> {
+    generate_token_with_rng(entropy_bytes, &SystemRandom::new())
+}
+
+/// As `generate_token`, drawing the bytes from `rng`.
+pub fn generate_token_with_rng(
+    entropy_bytes: usize,
+    rng: &dyn EntropySource,
+) -> Result<String, TokenError> {
+    if entropy_bytes < MIN_TOKEN_ENTROPY {
+        return Err(TokenError::InsufficientEntropy);
+    }
+    Ok(encode_random(entropy_bytes, rng))
+}
+
+/// As `generate_token`, without the minimum entropy check.
+pub fn generate_token_unchecked(entropy_bytes: usize) -> String {
+    encode_random(entropy_bytes, &SystemRandom::new())
+}
+
+/// `len` characters drawn uniformly from `alphabet`. Fails unless
+/// `alphabet.len() ^ len` reaches 2^128 possible tokens.
+pub fn generate_token_alphabet(len: usize, alphabet: &str) -> Result<String, TokenError> {
+    generate_token_alphabet_with_rng(len, alphabet, &SystemRandom::new())
+}
+
+/// As `generate_token_alphabet`, drawing the characters from `rng`.
+pub fn generate_token_alphabet_with_rng(
+    len: usize,
+    alphabet: &str,
+    rng: &dyn EntropySource,
+) -> Result<String, TokenError> {
+    let chars = parse_alphabet(alphabet)?;
+    if !enough_entropy(len, chars.len()) {
+        return Err(TokenError::InsufficientEntropy);
+    }
+    Ok(sample(len, &chars, random_bytes(rng)))
+}
+
+/// As `generate_token_alphabet`, without the minimum entropy check.
+pub fn generate_token_alphabet_unchecked(len: usize, alphabet: &str) -> Result<String, TokenError> {
+    let chars = parse_alphabet(alphabet)?;
+    Ok(sample(len, &chars, random_bytes(&SystemRandom::new())))
+}
+
+fn encode_random(entropy_bytes: usize, rng: &dyn EntropySource) -> String {
+    let mut bytes = alloc::vec![0u8; entropy_bytes];
+    rng.fill(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn parse_alphabet(alphabet: &str) -> Result<Vec<char>, TokenError> {
+    let chars: Vec<char> = alphabet.chars().collect();
+    if !(2..=256).contains(&chars.len()) {
+        return Err(TokenError::InvalidAlphabet);
+    }
+    let mut sorted = chars.clone();
+    sorted.sort_unstable();
+    sorted.dedup();
+    if sorted.len() != chars.len() {
+        return Err(TokenError::InvalidAlphabet);
+    }
+    Ok(chars)
+}
+
+/* size^len >= 2^128 exactly when the product no longer fits in a u128 */
+fn enough_entropy(len: usize, size: usize) -> bool {
+    let mut combinations: u128 = 1;
+    for _ in 0..len {
+        match combinations.checked_mul(size as u128) {
+            Some(c) => combinations = c,
+            None => return true,
+        }
+    }
+    false
+}
+
+fn random_bytes(rng: &dyn EntropySource) -> impl Iterator<Item = u8> + '_ {
+    core::iter::repeat_with(move || {
+        let mut batch = [0u8; BATCH_LEN];
+        rng.fill(&mut batch);
+        batch
+    })
+    .flatten()
+}
+
+fn sample(len: usize, chars: &[char], bytes: impl Iterator<Item = u8>) -> String {
+    let size = chars.len();
+    /* Largest multiple of size that fits in a byte; 256 itself for 2^k sizes */
+    let limit = 256 - 256 % size;
+    bytes
+        .map(usize::from)
+        .filter(|&b| b < limit)
+        .take(len)
+        .map(|b| chars[b % size])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGITS: &str = "0123456789";
+
+    #[test]
+    fn token_lengths() {
+        for n in [16, 17, 18, 32, 64] {
+            let token = generate_token(n).unwrap();
+            assert_eq!(token.len(), (n * 8).div_ceil(6));
+            assert_eq!(URL_SAFE_NO_PAD.decode(&token).unwrap().len(), n);
+            assert!(token
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'));
+        }
+        assert_ne!(generate_token(32), generate_token(32));
+    }
+
+    #[test]
+    fn minimum_entropy_enforced() {
+        assert_eq!(generate_token(15), Err(TokenError::InsufficientEntropy));
+        assert_eq!(generate_token_unchecked(4).len(), 6);
+
+        /* 10^39 > 2^128 > 10^38 */
+        assert!(generate_token_alphabet(39, DIGITS).is_ok());
+        assert_eq!(
+            generate_token_alphabet(38, DIGITS),
+            Err(TokenError::InsufficientEntropy)
+        );
+        assert_eq!(
+            generate_token_alphabet_unchecked(6, DIGITS).unwrap().len(),
+            6
+        );
+
+        /* Exactly 2^128 is enough */
+        assert!(generate_token_alphabet(32, "0123456789abcdef").is_ok());
+        assert!(generate_token_alphabet(127, "01").is_err());
+        assert!(generate_token_alphabet(128, "01").is_ok());
+    }
+
+    #[test]
+    fn alphabet_validated() {
+        for bad in ["", "a", "abca"] {
+            assert_eq!(
+                generate_token_alphabet_unchecked(8, bad),
+                Err(TokenError::InvalidAlphabet),
+                "{:?}",
+                bad
+            );
+        }
+        let too_big: String = (0..257u32).filter_map(char::from_u32).collect();
+        assert_eq!(
+            generate_token_alphabet_unchecked(8, &too_big),
+            Err(TokenError::InvalidAlphabet)
+        );
+    }
+
+    #[test]
+    fn alphabet_membership_and_coverage() {
+        let alphabet = "ABCDEFGHJKMNPQRSTVWXYZ23456789é";
+        let token = generate_token_alphabet_unchecked(20_000, alphabet).unwrap();
+        assert_eq!(token.chars().count(), 20_000);
+        assert!(token.chars().all(|c| alphabet.contains(c)));
+        /* ~645 expected per character; missing one would be astronomically unlikely */
+        assert!(alphabet.chars().all(|c| token.contains(c)));
+    }
+
+    #[test]
+    fn sampling_is_unbiased() {
+        /* Each byte value exactly once: 250 survive, 25 per digit */
+        let chars: Vec<char> = DIGITS.chars().collect();
+        let token = sample(250, &chars, 0..=255u8);
+        for digit in DIGITS.chars() {
+            assert_eq!(token.matches(digit).count(), 25, "{}", digit);
+        }
+        /* 250..=255 would favour 0-5 with plain modulo and must be skipped */
+        assert_eq!(
+            sample(3, &chars, [250, 255, 7, 251, 13, 249].into_iter()),
+            "739"
+        );
+
+        /* Power-of-two sizes never reject */
+        let hex: Vec<char> = "0123456789abcdef".chars().collect();
+        assert_eq!(sample(256, &hex, 0..=255u8).len(), 256);
+
+        /* Awkward sizes still come out even over the full byte range */
+        for size in [3, 7, 10, 36, 62, 100, 200, 255] {
+            let chars: Vec<char> = (0..size as u32)
+                .filter_map(|i| char::from_u32(0x100 + i))
+                .collect();
+            let token = sample(usize::MAX, &chars, 0..=255u8);
+            let per_char = 256 / size;
+            assert_eq!(token.chars().count(), per_char * size);
+            for c in &chars {
+                assert_eq!(token.matches(*c).count(), per_char, "size {}", size);
+            }
+        }
+    }
+}
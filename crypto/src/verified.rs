@@ -0,0 +1,301 @@
+/* BLAKE3 verified streaming (Bao), for checking any slice of a large
+ * file against one 32-byte root hash without reading the rest.
+ * [Bao spec]: https://github.com/oconnor663/bao/blob/master/docs/spec.md
+ *
+ * The combined encoding interleaves the BLAKE3 tree's parent nodes with
+ * the content (about 6% larger than the input). A server holding it can
+ * cut out the nodes and chunks covering a byte range (`extract_slice`);
+ * a client holding only the root hash checks that slice and gets the
+ * bytes back (`decode_slice`). The root hash equals the input's ordinary
+ * BLAKE3 hash.
+ *
+ * Wraps the `bao` crate. A mismatch anywhere on the path from the root
+ * to the requested bytes is `VerifyError::Diverged`. */
+use std::fmt;
+use std::io::{self, Cursor, Read, Seek, Write};
+use std::ops::Range;
+use std::vec::Vec;
+
+use bao::decode::{Decoder, SliceDecoder};
+use bao::encode::{Encoder, SliceExtractor};
+use bao::Hash;
+
+/// Length of a root hash.
+pub const VERIFIED_ROOT_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    /* The content (or a tree node above it) does not hash to the root */
+    Diverged,
+    /* The encoding ends before the requested range does */
+    Truncated,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Diverged => write!(f, "content does not match the root hash"),
+            VerifyError::Truncated => write!(f, "verified encoding is truncated"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/* bao reports failures through io::Error kinds */
+fn verify_error(e: io::Error) -> VerifyError {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => VerifyError::Truncated,
+        _ => VerifyError::Diverged,
+    }
+}
+
+/// Encode `data`, returning its root hash and the combined encoding.
+pub fn encode_verified(data: &[u8]) -> ([u8; VERIFIED_ROOT_LEN], Vec<u8>) {
+    let (encoded, hash) = bao::encode::encode(data);
+    (*hash.as_bytes(), encoded)
+}
+
+/// Decode and verify a whole combined encoding.
+pub fn decode_verified(
+    root: &[u8; VERIFIED_ROOT_LEN],
+    encoded: &[u8],
+) -> Result<Vec<u8>, VerifyError> {
+    bao::decode::decode(encoded, &Hash::from(*root)).map_err(verify_error)
+}
+
+/// Cut the parts of a combined encoding needed to verify `range` of the
+/// content. The result is what `decode_slice` expects.
+pub fn extract_slice(encoded: &[u8], range: Range<u64>) -> io::Result<Vec<u8>> {
+    let len = range.end.saturating_sub(range.start);
+    let mut slice = Vec::new();
+    SliceExtractor::new(Cursor::new(encoded), range.start, len).read_to_end(&mut slice)?;
+    Ok(slice)
+}
+
+/// Verify a slice from `extract_slice` against `root` and return the
+/// content bytes in `range`. A range running past the end of the content
+/// is cut short at the end, and one starting past it yields nothing once
+/// the final chunk has been verified.
+pub fn decode_slice(
+    root: &[u8; VERIFIED_ROOT_LEN],
+    slice: &[u8],
+    range: Range<u64>,
+) -> Result<Vec<u8>, VerifyError> {
+    let len = range.end.saturating_sub(range.start);
+    let mut out = Vec::new();
+    SliceDecoder::new(slice, &Hash::from(*root), range.start, len)
+        .read_to_end(&mut out)
+        .map_err(verify_error)?;
+    Ok(out)
+}
+
+/// Incremental encoder. Write the content, then call `finalize` for the
+/// root hash; the combined encoding is left in `inner`, which must start
+/// out empty. `inner` is seekable because the tree's parent nodes are
+/// written ahead of content they cover.
+pub struct VerifiedEncoder<W: Read + Write + Seek>(Encoder<W>);
+
+impl<W: Read + Write + Seek> VerifiedEncoder<W> {
+    pub fn new(inner: W) -> Self {
+        VerifiedEncoder(Encoder::new(inner))
+    }
+
+    pub fn finalize(&mut self) -> io::Result<[u8; VERIFIED_ROOT_LEN]> {
+        Ok(*self.0.finalize()?.as_bytes())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.0.into_inner()
+    }
+}
+
+impl<W: Read + Write + Seek> Write for VerifiedEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Incremental decoder over a combined encoding or an extracted slice.
+/// Bytes are only returned once the chunk holding them has been verified;
+/// a failure is an `io::Error` whose inner error is a `VerifyError`.
+pub struct VerifiedDecoder<R: Read>(DecoderKind<R>);
+
+enum DecoderKind<R: Read> {
+    Full(Decoder<R>),
+    Slice(SliceDecoder<R>),
+}
+
+impl<R: Read> VerifiedDecoder<R> {
+    /// Decode a whole combined encoding.
+    pub fn new(inner: R, root: &[u8; VERIFIED_ROOT_LEN]) -> Self {
+        VerifiedDecoder(DecoderKind::Full(Decoder::new(inner, &Hash::from(*root))))
+    }
+
+    /// Decode a slice from `extract_slice` for `range`.
+    pub fn new_slice(inner: R, root: &[u8; VERIFIED_ROOT_LEN], range: Range<u64>) -> Self {
+        let len = range.end.saturating_sub(range.start);
+        VerifiedDecoder(DecoderKind::Slice(SliceDecoder::new(
+            inner,
+            &Hash::from(*root),
+            range.start,
+            len,
+        )))
+    }
+}
+
+impl<R: Read> Read for VerifiedDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = match &mut self.0 {
+            DecoderKind::Full(decoder) => decoder.read(buf),
+            DecoderKind::Slice(decoder) => decoder.read(buf),
+        };
+        result.map_err(|e| {
+            let kind = e.kind();
+            io::Error::new(kind, verify_error(e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u32).wrapping_mul(2654435761) as u8)
+            .collect()
+    }
+
+    // BLAKE3 test_vectors.json, input_len 0 (the Bao root is the BLAKE3 hash)
+    #[test]
+    fn root_is_blake3_hash() {
+        let (root, encoded) = encode_verified(b"");
+        assert_eq!(
+            root,
+            [
+                0xaf, 0x13, 0x49, 0xb9, 0xf5, 0xf9, 0xa1, 0xa6, 0xa0, 0x40, 0x4d, 0xea, 0x36, 0xdc,
+                0xc9, 0x49, 0x9b, 0xcb, 0x25, 0xc9, 0xad, 0xc1, 0x12, 0xb7, 0xcc, 0x9a, 0x93, 0xca,
+                0xe4, 0x1f, 0x32, 0x62,
+            ]
+        );
+        /* Just the 8-byte length header */
+        assert_eq!(encoded, [0u8; 8]);
+    }
+
+    #[test]
+    fn round_trip() {
+        for len in [0, 1, 1023, 1024, 1025, 100_000] {
+            let data = input(len);
+            let (root, encoded) = encode_verified(&data);
+            assert_eq!(
+                decode_verified(&root, &encoded).unwrap(),
+                data,
+                "len {}",
+                len
+            );
+            assert_eq!(
+                decode_slice(&root, &encoded, 0..len as u64).unwrap(),
+                data,
+                "len {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn slice_from_middle_of_large_input() {
+        let data = input(4 << 20);
+        let (root, encoded) = encode_verified(&data);
+
+        let range = 2_500_000u64..2_600_123;
+        let slice = extract_slice(&encoded, range.clone()).unwrap();
+        /* Only the covering chunks and their parents travel */
+        assert!(slice.len() < 120_000, "{}", slice.len());
+        assert_eq!(
+            decode_slice(&root, &slice, range.clone()).unwrap(),
+            data[range.start as usize..range.end as usize]
+        );
+
+        /* Past the end is cut short */
+        let tail = extract_slice(&encoded, 4_194_000..5_000_000).unwrap();
+        assert_eq!(
+            decode_slice(&root, &tail, 4_194_000..5_000_000).unwrap(),
+            data[4_194_000..]
+        );
+    }
+
+    #[test]
+    fn corruption_is_detected() {
+        let data = input(1 << 20);
+        let (root, encoded) = encode_verified(&data);
+        let range = 300_000u64..310_000;
+        let slice = extract_slice(&encoded, range.clone()).unwrap();
+
+        /* A content byte, a parent node, and the length header */
+        for at in [slice.len() - 100, 8, 0] {
+            let mut bad = slice.clone();
+            bad[at] ^= 0x01;
+            assert_eq!(
+                decode_slice(&root, &bad, range.clone()),
+                Err(VerifyError::Diverged),
+                "byte {}",
+                at
+            );
+        }
+        let mut wrong_root = root;
+        wrong_root[0] ^= 1;
+        assert_eq!(
+            decode_slice(&wrong_root, &slice, range.clone()),
+            Err(VerifyError::Diverged)
+        );
+        assert_eq!(
+            decode_slice(&root, &slice[..slice.len() - 1], range),
+            Err(VerifyError::Truncated)
+        );
+
+        let mut bad = encoded.clone();
+        bad[encoded.len() / 2] ^= 0x80;
+        assert_eq!(decode_verified(&root, &bad), Err(VerifyError::Diverged));
+    }
+
+    #[test]
+    fn streaming_encoder_and_decoder() {
+        let data = input(300_000);
+        let mut encoder = VerifiedEncoder::new(Cursor::new(Vec::new()));
+        for piece in data.chunks(7_777) {
+            encoder.write_all(piece).unwrap();
+        }
+        let root = encoder.finalize().unwrap();
+        let encoded = encoder.into_inner().into_inner();
+        assert_eq!(encode_verified(&data), (root, encoded.clone()));
+
+        let mut decoded = Vec::new();
+        VerifiedDecoder::new(&encoded[..], &root)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let slice = extract_slice(&encoded, 1000..2000).unwrap();
+        let mut part = Vec::new();
+        VerifiedDecoder::new_slice(&slice[..], &root, 1000..2000)
+            .read_to_end(&mut part)
+            .unwrap();
+        assert_eq!(part, data[1000..2000]);
+
+        let mut bad = encoded;
+        bad[200_000] ^= 1;
+        let err = VerifiedDecoder::new(&bad[..], &root)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.into_inner().unwrap().downcast_ref::<VerifyError>(),
+            Some(&VerifyError::Diverged)
+        );
+    }
+}
@@ -0,0 +1,18 @@
+/* FileHeader::parse on arbitrary bytes: never panics, and anything it
+ * accepts re-encodes to exactly the bytes it consumed. */
+#![no_main]
+
+use crypto::header::FileHeader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((header, len)) = FileHeader::parse(data) {
+        let bytes = header.to_bytes();
+        assert_eq!(bytes, data[..len]);
+        assert_eq!(FileHeader::parse(&bytes), Ok((header, len)));
+    }
+    if let Ok(len) = FileHeader::encoded_len(data) {
+        /* The extension length is a u16, so this can never be absurd */
+        assert!(len <= 19 + u16::MAX as usize);
+    }
+});
@@ -0,0 +1,22 @@
+/* minisign public key boxes and signature files. Secret key boxes are
+ * left out: their scrypt parameters make single inputs arbitrarily slow. */
+#![no_main]
+
+use crypto::{minisign_verify, PublicKeyBox};
+use libfuzzer_sys::fuzz_target;
+
+const PUBLIC_KEY: &str = include_str!("../../crypto/tests/minisign_plain.pub");
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = core::str::from_utf8(data) else {
+        return;
+    };
+
+    if let Ok(pk) = PublicKeyBox::from_box(text) {
+        assert_eq!(PublicKeyBox::from_box(&pk.to_box()), Ok(pk.clone()));
+        assert_eq!(PublicKeyBox::from_base64(&pk.to_base64()), Ok(pk));
+    }
+
+    let pk = PublicKeyBox::from_box(PUBLIC_KEY).unwrap();
+    let _ = minisign_verify(&pk, b"", text);
+});
@@ -0,0 +1,22 @@
+/* Human-transcribed formats: Shamir share text and BIP39 phrases. Both
+ * must re-encode to something that parses back to the same value. */
+#![no_main]
+
+use crypto::shamir::Share;
+use crypto::{key_to_mnemonic, mnemonic_to_key};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = core::str::from_utf8(data) else {
+        return;
+    };
+
+    if let Ok(share) = Share::from_text(text) {
+        assert_eq!(Share::from_text(&share.to_text()), Ok(share));
+    }
+
+    if let Ok(entropy) = mnemonic_to_key(text) {
+        let phrase = key_to_mnemonic(&entropy).unwrap();
+        assert_eq!(mnemonic_to_key(&phrase), Ok(entropy));
+    }
+});
@@ -0,0 +1,19 @@
+/* Compact token formats: JWE, PASETO v4.local and Fernet. Only checks
+ * that malformed tokens are rejected without panicking. */
+#![no_main]
+
+use crypto::{fernet_decrypt, jwe_decrypt, paseto_v4_local_decrypt, FernetKey, JweKey};
+use libfuzzer_sys::fuzz_target;
+
+const KEY: [u8; 32] = [0x42; 32];
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(token) = core::str::from_utf8(data) else {
+        return;
+    };
+
+    let _ = jwe_decrypt(&JweKey::Direct(&KEY), token);
+    let _ = jwe_decrypt(&JweKey::X25519(&KEY), token);
+    let _ = paseto_v4_local_decrypt(&KEY, token, None, None);
+    let _ = fernet_decrypt(&FernetKey::from_bytes(&KEY), token, None);
+});
@@ -0,0 +1,391 @@
+/* archive: a directory packed into one encrypted file, and unpacked
+ * again.
+ *
+ * The container and its safety checks are crypto::archive's; this is
+ * where the files come from and go to. create writes through
+ * fs::write_output, so the archive only appears once it is complete.
+ * extract authenticates as it unpacks into a directory beside --into
+ * and renames it into place at the end, so a wrong key, damage or
+ * truncation leaves nothing behind. With --entry only the matching
+ * entries are unpacked, in the same way or, with --out, as one file
+ * written through fs::write_output; the rest of the data is skipped
+ * without being decrypted. A pattern that matches nothing is an error
+ * that suggests the paths closest to it. list prints what is in an archive
+ * without writing anything, skipping file data by its recorded size. */
+use std::fs::{File, OpenOptions};
+use std::io::BufReader;
+use std::path::Path;
+
+use crypto::archive::{self, ArchiveError, Entry, EntryKind, Unpacked};
+use crypto::fs::{self, EncryptOptions, Summary};
+use crypto::PaddingScheme;
+use glob::{MatchOptions, Pattern};
+
+use crate::compress;
+use crate::failure;
+use crate::mangen::civil_date;
+use crate::status::{self, CliError, ErrorCode};
+
+/// Pack the tree under `dir` into the archive `out`, encrypted under
+/// `key`. An existing `out` is replaced only with `force`.
+pub fn create(
+    dir: &Path,
+    out: &Path,
+    key: &[u8],
+    opts: &EncryptOptions,
+    force: bool,
+) -> Result<Summary, CliError> {
+    if !dir.is_dir() {
+        return Err(CliError::new(
+            ErrorCode::Usage,
+            format!("{} is not a directory", dir.display()),
+        ));
+    }
+    if inside(out, dir) {
+        return Err(CliError::new(
+            ErrorCode::Usage,
+            format!(
+                "{} is inside {}, so it would be archived into itself",
+                out.display(),
+                dir.display()
+            ),
+        ));
+    }
+    fs::write_output(out, force, |writer| archive::create(dir, writer, key, opts)).map_err(failure)
+}
+
+/// Parse --pad: "padme", or a block size such as 4K or 1MiB.
+pub fn parse_padding(text: &str) -> Result<PaddingScheme, String> {
+    if text.eq_ignore_ascii_case("padme") {
+        return Ok(PaddingScheme::Padme);
+    }
+    let size =
+        crate::bench::parse_size(text).map_err(|e| format!("{}; use padme or a block size", e))?;
+    u32::try_from(size)
+        .map(PaddingScheme::Block)
+        .map_err(|_| format!("{} is more than a block can be", text))
+}
+
+/* Whether `path` would be created somewhere under `dir` */
+fn inside(path: &Path, dir: &Path) -> bool {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (parent.canonicalize(), dir.canonicalize()) {
+        (Ok(parent), Ok(dir)) => parent.starts_with(dir),
+        _ => false,
+    }
+}
+
+/// Decrypt the archive at `path` under `key` and unpack it as `into`:
+/// all of it, or only the entries matching `patterns` when there are any.
+pub fn extract(
+    path: &Path,
+    into: &Path,
+    patterns: &[String],
+    key: &[u8],
+) -> Result<Unpacked, CliError> {
+    let reader = BufReader::new(open(path)?);
+    if patterns.is_empty() {
+        return archive::extract(reader, into, key, compress::limit())
+            .map_err(|e| archive_failure(path, e));
+    }
+    let mut selection = Selection::new(patterns)?;
+    let wants = |entry: &Entry| selection.wants(entry);
+    let staged = archive::extract_matching(reader, into, key, compress::limit(), wants)
+        .map_err(|e| archive_failure(path, e))?;
+    /* Dropping `staged` removes what was unpacked */
+    selection.check(path)?;
+    staged.finish().map_err(|e| archive_failure(path, e))
+}
+
+/// Decrypt the archive at `path` under `key` and write the one file
+/// `patterns` match as `out`, with its recorded mode and time. Returns
+/// the bytes written.
+pub fn extract_one(
+    path: &Path,
+    patterns: &[String],
+    out: &Path,
+    force: bool,
+    key: &[u8],
+) -> Result<u64, CliError> {
+    let reader = BufReader::new(open(path)?);
+    let mut selection = Selection::new(patterns)?;
+    let mut files = Vec::new();
+    let mut found = None;
+    let written = fs::write_output(out, force, |writer| {
+        let wants = |entry: &Entry| {
+            let wanted = selection.wants(entry);
+            if wanted && entry.kind == EntryKind::File {
+                files.push(entry.path.clone());
+            }
+            wanted
+        };
+        let checked = archive::extract_file(reader, key, compress::limit(), wants, writer)
+            .map_err(|e| archive_failure(path, e))
+            .and_then(|entry| {
+                selection.check(path)?;
+                one_file(path, &files)?;
+                Ok(entry.expect("one file matched"))
+            });
+        match checked {
+            Ok(entry) => {
+                let summary = Summary {
+                    bytes_in: 0,
+                    bytes_out: entry.size,
+                    chunks: 0,
+                };
+                found = Some(entry);
+                Ok(summary)
+            }
+            Err(e) => Err(status::carry(e)),
+        }
+    });
+    let written = written.map_err(failure)?;
+    let entry = found.expect("set when written");
+    let file = OpenOptions::new().write(true).open(out).map_err(|e| {
+        CliError::new(
+            ErrorCode::Io,
+            format!("cannot open {}: {}", out.display(), e),
+        )
+    })?;
+    archive::restore(&file, &entry).map_err(|e| {
+        CliError::new(
+            ErrorCode::Io,
+            format!("cannot set the mode of {}: {}", out.display(), e),
+        )
+    })?;
+    Ok(written.bytes_out)
+}
+
+fn one_file(path: &Path, files: &[String]) -> Result<(), CliError> {
+    match files.len() {
+        1 => Ok(()),
+        0 => Err(CliError::new(
+            ErrorCode::Usage,
+            format!(
+                "{}: --entry matches only directories; use --into for those",
+                path.display()
+            ),
+        )),
+        n => Err(CliError::new(
+            ErrorCode::Usage,
+            format!(
+                "{}: --entry matches {} files ({}), but --out takes one; use --into for more",
+                path.display(),
+                n,
+                files.join(", ")
+            ),
+        )),
+    }
+}
+
+const MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/* Paths close enough to a pattern that matched nothing to suggest */
+const MAX_SUGGESTIONS: usize = 5;
+
+/* The --entry patterns, whether each has matched, and every path seen,
+ * to suggest from */
+struct Selection {
+    patterns: Vec<(String, Pattern)>,
+    matched: Vec<bool>,
+    paths: Vec<String>,
+}
+
+impl Selection {
+    fn new(patterns: &[String]) -> Result<Self, CliError> {
+        let mut compiled = Vec::new();
+        for text in patterns {
+            let trimmed = text.trim_matches('/');
+            let pattern = Pattern::new(trimmed).map_err(|e| {
+                CliError::new(
+                    ErrorCode::Usage,
+                    format!("invalid entry pattern {}: {}", text, e.msg),
+                )
+            })?;
+            compiled.push((text.clone(), pattern));
+        }
+        Ok(Selection {
+            matched: vec![false; compiled.len()],
+            patterns: compiled,
+            paths: Vec::new(),
+        })
+    }
+
+    /* Whether a pattern matches the entry's path, or a directory above
+     * it */
+    fn wants(&mut self, entry: &Entry) -> bool {
+        let path = entry.path.as_str();
+        let mut wanted = false;
+        for (i, (_, pattern)) in self.patterns.iter().enumerate() {
+            let mut prefixes = path.match_indices('/').map(|(at, _)| &path[..at]);
+            if pattern.matches_with(path, MATCH)
+                || prefixes.any(|above| pattern.matches_with(above, MATCH))
+            {
+                self.matched[i] = true;
+                wanted = true;
+            }
+        }
+        self.paths.push(entry.path.clone());
+        wanted
+    }
+
+    /* An error naming the first pattern that matched nothing */
+    fn check(&self, path: &Path) -> Result<(), CliError> {
+        let missing = self.matched.iter().position(|matched| !matched);
+        let Some((text, _)) = missing.map(|i| &self.patterns[i]) else {
+            return Ok(());
+        };
+        let mut message = format!("{}: no entry matches {}", path.display(), text);
+        let near = self.near(text);
+        if !near.is_empty() {
+            message += &format!("; near matches: {}", near.join(", "));
+        }
+        Err(CliError::new(ErrorCode::Usage, message))
+    }
+
+    /* The paths closest to `text`, as a whole or by their last name */
+    fn near(&self, text: &str) -> Vec<&str> {
+        let text = text.trim_matches('/');
+        let name = text.rsplit('/').next().unwrap_or(text);
+        let limit = (text.chars().count() / 3).max(2);
+        let mut scored: Vec<(usize, &str)> = self
+            .paths
+            .iter()
+            .map(|path| {
+                let last = path.rsplit('/').next().unwrap_or(path);
+                let distance = edit_distance(text, path).min(edit_distance(name, last));
+                (distance, path.as_str())
+            })
+            .filter(|(distance, _)| *distance <= limit)
+            .collect();
+        scored.sort();
+        scored
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, path)| path)
+            .collect()
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + (ca != *cb) as usize)
+                .min(row[j] + 1)
+                .min(above + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Print each entry of the archive at `path`, decrypted under `key`: a
+/// line like `ls -l`'s, or with --json an object per line.
+pub fn list(path: &Path, key: &[u8]) -> Result<(), CliError> {
+    let file = open(path)?;
+    let json = status::json();
+    archive::list(
+        BufReader::new(file),
+        key,
+        compress::limit(),
+        |entry| match json {
+            true => println!("{}", entry_json(entry)),
+            false => println!("{}", entry_line(entry)),
+        },
+    )
+    .map_err(|e| archive_failure(path, e))
+}
+
+/* "-rw-r--r--        1234  2024-05-01 12:00:00  src/main.rs" */
+fn entry_line(entry: &Entry) -> String {
+    let (kind, slash) = match entry.kind {
+        EntryKind::File => ('-', ""),
+        EntryKind::Directory => ('d', "/"),
+    };
+    let mut mode = String::from(kind);
+    for shift in [6, 3, 0] {
+        let bits = entry.mode >> shift;
+        mode.push(if bits & 4 != 0 { 'r' } else { '-' });
+        mode.push(if bits & 2 != 0 { 'w' } else { '-' });
+        mode.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    format!(
+        "{} {:>12}  {}  {}{}",
+        mode,
+        entry.size,
+        timestamp(entry.mtime),
+        entry.path,
+        slash
+    )
+}
+
+/* UTC, as YYYY-MM-DD HH:MM:SS; times before 1970 as their seconds */
+fn timestamp(seconds: i64) -> String {
+    let Ok(seconds) = u64::try_from(seconds) else {
+        return format!("{:>19}", seconds);
+    };
+    let (year, month, day) = civil_date(seconds / 86_400);
+    let time = seconds % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn entry_json(entry: &Entry) -> serde_json::Value {
+    serde_json::json!({
+        "path": entry.path,
+        "type": match entry.kind {
+            EntryKind::File => "file",
+            EntryKind::Directory => "directory",
+        },
+        "size": entry.size,
+        "mode": format!("{:04o}", entry.mode),
+        "mtime": entry.mtime,
+        "mtime_nanos": entry.mtime_nanos,
+    })
+}
+
+fn open(path: &Path) -> Result<File, CliError> {
+    File::open(path).map_err(|e| {
+        CliError::new(
+            ErrorCode::Io,
+            format!("cannot open {}: {}", path.display(), e),
+        )
+    })
+}
+
+/* `e` as --json reports it, naming the archive */
+fn archive_failure(path: &Path, e: ArchiveError) -> CliError {
+    let e = match e {
+        ArchiveError::Stream(e) => failure(e),
+        ArchiveError::Compression(e) => failure(e.into()),
+        e => {
+            let code = match &e {
+                ArchiveError::Io(_) => ErrorCode::Io,
+                ArchiveError::Truncated => ErrorCode::Truncated,
+                ArchiveError::DestinationNotEmpty(_) => ErrorCode::Usage,
+                _ => ErrorCode::Malformed,
+            };
+            CliError::new(code, e)
+        }
+    };
+    CliError::new(e.code, format!("{}: {}", path.display(), e.message))
+}
@@ -0,0 +1,101 @@
+/* --backup: a copy of each file before it is changed in place.
+ *
+ * The copy sits beside the original under the same name plus a suffix
+ * (.bak by default), with the original's mode and modification time. It
+ * is written to completion and fsynced before the file is touched, and an
+ * existing copy is only replaced with --force, so an older backup is never
+ * lost by accident. With --output or standard input nothing is changed in
+ * place, so there is nothing to back up. */
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Suffix when --backup is given without one.
+pub const DEFAULT_SUFFIX: &str = ".bak";
+
+/// The value of --backup=SUFFIX: something to add to a file name.
+pub fn parse_suffix(suffix: &str) -> Result<String, String> {
+    if suffix.is_empty() {
+        return Err("the backup suffix must not be empty".to_string());
+    }
+    if suffix.contains(std::path::is_separator) {
+        return Err("the backup suffix must not contain a path separator".to_string());
+    }
+    Ok(suffix.to_string())
+}
+
+/// Where the backup of `file` goes.
+pub fn path_for(file: &Path, suffix: &str) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Copy `file` to its backup path with its mode and modification time,
+/// returning that path. An existing backup is an `AlreadyExists` error
+/// unless `overwrite`.
+pub fn make(file: &Path, suffix: &str, overwrite: bool) -> io::Result<PathBuf> {
+    let backup = path_for(file, suffix);
+    let mut original = File::open(file)?;
+    let metadata = original.metadata()?;
+    let mut options = OpenOptions::new();
+    options.write(true);
+    match overwrite {
+        true => options.create(true).truncate(true),
+        false => options.create_new(true),
+    };
+    let mut copy = options.open(&backup).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => io::Error::new(
+            e.kind(),
+            format!(
+                "backup {} already exists (use --force to replace it)",
+                backup.display()
+            ),
+        ),
+        _ => e,
+    })?;
+    let copied = io::copy(&mut original, &mut copy)
+        .and_then(|_| copy.set_permissions(metadata.permissions()))
+        .and_then(|_| copy.set_modified(metadata.modified()?))
+        .and_then(|_| copy.sync_all());
+    if let Err(e) = copied {
+        drop(copy);
+        let _ = fs::remove_file(&backup);
+        return Err(e);
+    }
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suffixes() {
+        assert_eq!(parse_suffix(".orig").unwrap(), ".orig");
+        assert_eq!(parse_suffix("~").unwrap(), "~");
+        assert!(parse_suffix("").is_err());
+        assert!(parse_suffix("/x").is_err());
+        assert_eq!(
+            path_for(Path::new("dir/notes.txt"), ".bak"),
+            Path::new("dir/notes.txt.bak")
+        );
+    }
+
+    #[test]
+    fn copy_keeps_contents_and_refuses_to_replace() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.txt");
+        fs::write(&file, b"first").unwrap();
+        let backup = make(&file, ".bak", false).unwrap();
+        assert_eq!(fs::read(&backup).unwrap(), b"first");
+
+        fs::write(&file, b"second").unwrap();
+        let err = make(&file, ".bak", false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(err.to_string().contains("--force"), "{}", err);
+        assert_eq!(fs::read(&backup).unwrap(), b"first");
+        make(&file, ".bak", true).unwrap();
+        assert_eq!(fs::read(&backup).unwrap(), b"second");
+    }
+}
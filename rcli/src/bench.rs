@@ -0,0 +1,325 @@
+/* bench: how fast each cipher runs on this machine.
+ *
+ * Every case encrypts and then decrypts a buffer of --size bytes in
+ * memory, --iterations times, and reports the fastest run of each as
+ * MB/s (10^6 bytes a second): RC4, ChaCha20-Poly1305 one-shot, and the
+ * rcli stream format at several chunk sizes for each AEAD this build has.
+ * With --disk-dir the stream format is also written to a file there,
+ * fsynced, and read back, so the disk's speed shows too; the file holds
+ * only ciphertext and is removed afterwards. Keys are random, made for the
+ * run, and never leave memory. */
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crypto::fs::{decrypt_stream, encrypt_stream, EncryptOptions};
+use crypto::header::Algorithm;
+use crypto::{chacha20_poly1305_cipher, generate_key, generate_nonce, Rc4};
+use serde::Serialize;
+
+use crate::status::{CliError, ErrorCode};
+
+/// Chunk sizes the stream cases use unless --chunk-size says otherwise.
+pub const DEFAULT_CHUNK_SIZES: [u32; 3] = [16 * 1024, 64 * 1024, 1024 * 1024];
+
+/// The value of --size: bytes, with an optional K, KiB, M, MiB, G or GiB.
+pub fn parse_size(text: &str) -> Result<usize, String> {
+    let units: [(&str, usize); 6] = [
+        ("KiB", 1 << 10),
+        ("MiB", 1 << 20),
+        ("GiB", 1 << 30),
+        ("K", 1 << 10),
+        ("M", 1 << 20),
+        ("G", 1 << 30),
+    ];
+    let (digits, unit) = units
+        .iter()
+        .find_map(|(suffix, unit)| Some((text.strip_suffix(suffix)?, *unit)))
+        .unwrap_or((text, 1));
+    match digits
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+    {
+        Some(0) => Err("the size must be more than 0 bytes".to_string()),
+        Some(size) => Ok(size),
+        None => Err(format!("{:?} is not a size in bytes", text)),
+    }
+}
+
+/// One case's result, and with --json its line of output. Fields keep
+/// their names, as status records do.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub algorithm: &'static str,
+    /// "keystream", "one-shot", "stream" or "disk".
+    pub mode: &'static str,
+    /// For stream and disk, the plaintext bytes per chunk.
+    pub chunk_size: Option<u32>,
+    pub bytes: u64,
+    pub iterations: u32,
+    pub encrypt_mb_per_s: f64,
+    pub decrypt_mb_per_s: f64,
+}
+
+impl Measurement {
+    /// "ChaCha20-Poly1305 stream  64 KiB  812.3 MB/s  790.0 MB/s"
+    pub fn to_row(&self) -> String {
+        let chunk = match self.chunk_size {
+            Some(size) if size >= 1 << 20 => format!("{} MiB", size >> 20),
+            Some(size) => format!("{} KiB", size >> 10),
+            None => "-".to_string(),
+        };
+        format!(
+            "{:<32} {:>8} {:>14} {:>14}",
+            format!("{} {}", self.algorithm, self.mode),
+            chunk,
+            format!("{:.1} MB/s", self.encrypt_mb_per_s),
+            format!("{:.1} MB/s", self.decrypt_mb_per_s)
+        )
+    }
+}
+
+/// The heading over `to_row`'s columns.
+pub fn heading() -> String {
+    format!(
+        "{:<32} {:>8} {:>14} {:>14}",
+        "Cipher", "Chunk", "Encrypt", "Decrypt"
+    )
+}
+
+/// What to measure.
+#[derive(Debug)]
+pub struct Plan {
+    pub size: usize,
+    pub iterations: u32,
+    pub chunk_sizes: Vec<u32>,
+    pub disk_dir: Option<PathBuf>,
+}
+
+/// Run every case in `plan`, handing each result to `report` as it
+/// arrives.
+pub fn run(plan: &Plan, mut report: impl FnMut(&Measurement)) -> Result<(), CliError> {
+    let failed = |e: crypto::ErrorStates| CliError::new(ErrorCode::Failed, format!("{:?}", e));
+    let fs_failed = |e: crypto::fs::FsCryptoError| CliError::new(ErrorCode::Failed, e);
+    let plaintext: Vec<u8> = (0..plan.size).map(|i| i as u8).collect();
+    let mut key = [0u8; 32];
+    generate_key(&mut key);
+
+    let mut rc4 = Timer::new(plan);
+    for _ in 0..plan.iterations {
+        let mut data = plaintext.clone();
+        rc4.encrypt(|| Rc4::new(&key[..16]).apply_keystream(&mut data));
+        rc4.decrypt(|| Rc4::new(&key[..16]).apply_keystream(&mut data));
+    }
+    report(&rc4.finish("RC4", "keystream", None));
+
+    let mut one_shot = Timer::new(plan);
+    for _ in 0..plan.iterations {
+        let mut nonce = [0u8; 12];
+        generate_nonce(&mut nonce);
+        let data = plaintext.clone();
+        let sealed = one_shot.encrypt(|| chacha20_poly1305_cipher(&key, &nonce, data, true));
+        let sealed = sealed.map_err(failed)?;
+        one_shot
+            .decrypt(|| chacha20_poly1305_cipher(&key, &nonce, sealed, false))
+            .map_err(failed)?;
+    }
+    let name = Algorithm::ChaCha20Poly1305.name();
+    report(&one_shot.finish(name, "one-shot", None));
+
+    for algorithm in [Algorithm::ChaCha20Poly1305, Algorithm::Aes256Gcm] {
+        if !algorithm.is_enabled() {
+            continue;
+        }
+        for &chunk_size in &plan.chunk_sizes {
+            let opts = EncryptOptions {
+                chunk_size,
+                algorithm,
+                ..EncryptOptions::default()
+            };
+            let mut stream = Timer::new(plan);
+            for _ in 0..plan.iterations {
+                let mut sealed = Vec::with_capacity(plan.size + plan.size / 16 + 1024);
+                stream
+                    .encrypt(|| encrypt_stream(&mut &plaintext[..], &mut sealed, &key, &opts))
+                    .map_err(fs_failed)?;
+                let mut opened = Vec::with_capacity(plan.size);
+                stream
+                    .decrypt(|| decrypt_stream(&mut &sealed[..], &mut opened, &key))
+                    .map_err(fs_failed)?;
+            }
+            report(&stream.finish(algorithm.name(), "stream", Some(chunk_size)));
+        }
+    }
+
+    if let Some(dir) = &plan.disk_dir {
+        let opts = EncryptOptions::default();
+        let scratch = Scratch::create(dir)?;
+        let mut disk = Timer::new(plan);
+        for _ in 0..plan.iterations {
+            disk.encrypt(|| -> Result<(), CliError> {
+                let mut writer = BufWriter::new(scratch.rewrite()?);
+                encrypt_stream(&mut &plaintext[..], &mut writer, &key, &opts).map_err(fs_failed)?;
+                writer
+                    .into_inner()
+                    .map_err(|e| e.into_error())?
+                    .sync_all()?;
+                Ok(())
+            })?;
+            disk.decrypt(|| -> Result<(), CliError> {
+                let mut reader = BufReader::new(File::open(&scratch.path)?);
+                decrypt_stream(&mut reader, &mut io::sink(), &key).map_err(fs_failed)?;
+                Ok(())
+            })?;
+        }
+        let name = opts.algorithm.name();
+        report(&disk.finish(name, "disk", Some(opts.chunk_size)));
+    }
+    Ok(())
+}
+
+/* The fastest encryption and decryption seen over the iterations */
+struct Timer {
+    bytes: u64,
+    iterations: u32,
+    encrypt: Duration,
+    decrypt: Duration,
+}
+
+impl Timer {
+    fn new(plan: &Plan) -> Self {
+        Timer {
+            bytes: plan.size as u64,
+            iterations: plan.iterations,
+            encrypt: Duration::MAX,
+            decrypt: Duration::MAX,
+        }
+    }
+
+    fn encrypt<T>(&mut self, op: impl FnOnce() -> T) -> T {
+        let (result, took) = timed(op);
+        self.encrypt = self.encrypt.min(took);
+        result
+    }
+
+    fn decrypt<T>(&mut self, op: impl FnOnce() -> T) -> T {
+        let (result, took) = timed(op);
+        self.decrypt = self.decrypt.min(took);
+        result
+    }
+
+    fn finish(
+        &self,
+        algorithm: &'static str,
+        mode: &'static str,
+        chunk_size: Option<u32>,
+    ) -> Measurement {
+        let rate = |took: Duration| self.bytes as f64 / took.as_secs_f64().max(1e-9) / 1e6;
+        Measurement {
+            algorithm,
+            mode,
+            chunk_size,
+            bytes: self.bytes,
+            iterations: self.iterations,
+            encrypt_mb_per_s: rate(self.encrypt),
+            decrypt_mb_per_s: rate(self.decrypt),
+        }
+    }
+}
+
+fn timed<T>(op: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = op();
+    (result, start.elapsed())
+}
+
+/* --disk-dir's file, removed when dropped */
+struct Scratch {
+    path: PathBuf,
+}
+
+impl Scratch {
+    fn create(dir: &Path) -> Result<Self, CliError> {
+        let mut suffix = [0u8; 8];
+        generate_nonce(&mut suffix);
+        let name: String = suffix.iter().map(|b| format!("{:02x}", b)).collect();
+        let path = dir.join(format!(".rcli-bench-{}", name));
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| {
+                CliError::new(
+                    ErrorCode::Io,
+                    format!("cannot create a file in {}: {}", dir.display(), e),
+                )
+            })?;
+        Ok(Scratch { path })
+    }
+
+    fn rewrite(&self) -> io::Result<File> {
+        let file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        Ok(file)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_take_units() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("4K"), Ok(4096));
+        assert_eq!(parse_size("256MiB"), Ok(256 << 20));
+        assert_eq!(parse_size("1GiB"), Ok(1 << 30));
+        for bad in ["", "0", "MiB", "1.5M", "-1", "12 parsecs"] {
+            assert!(parse_size(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn every_enabled_cipher_is_measured() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan = Plan {
+            size: 4096,
+            iterations: 2,
+            chunk_sizes: vec![1024, 4096],
+            disk_dir: Some(dir.path().to_path_buf()),
+        };
+        let mut seen = Vec::new();
+        run(&plan, |m| seen.push(m.clone())).unwrap();
+        let modes: Vec<(&str, &str, Option<u32>)> = seen
+            .iter()
+            .map(|m| (m.algorithm, m.mode, m.chunk_size))
+            .collect();
+        assert_eq!(
+            modes,
+            [
+                ("RC4", "keystream", None),
+                ("ChaCha20-Poly1305", "one-shot", None),
+                ("ChaCha20-Poly1305", "stream", Some(1024)),
+                ("ChaCha20-Poly1305", "stream", Some(4096)),
+                ("AES-256-GCM", "stream", Some(1024)),
+                ("AES-256-GCM", "stream", Some(4096)),
+                ("ChaCha20-Poly1305", "disk", Some(64 * 1024)),
+            ]
+        );
+        assert!(seen.iter().all(|m| m.bytes == 4096 && m.iterations == 2));
+        assert!(seen.iter().all(|m| m.encrypt_mb_per_s > 0.0));
+        /* nothing is left behind in the directory */
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
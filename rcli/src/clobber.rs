@@ -0,0 +1,198 @@
+/* --no-clobber, and asking before an existing output is replaced.
+ *
+ * --output, --output-dir, --keep and --backup all write files that may
+ * already exist. With --no-clobber an existing one is never touched, even
+ * with --force (which keeps its other meanings), and the file that wanted
+ * it fails. Otherwise --force replaces it; without --force, when standard
+ * input is a terminal, rcli asks "overwrite 'X'? [y/N/a]" for each, where
+ * "a" says yes to this one and every one after it. Off a terminal the
+ * file fails, as it always has. The answer is read from the terminal
+ * itself, like rc4's acknowledgment. */
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::insecure::TtyPrompter;
+use crate::key::Prompter;
+
+/// What to do about an output that exists, when --force was not given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// --no-clobber: never replace it.
+    Never,
+    /// Ask at the terminal.
+    Ask,
+    /// Not a terminal, and not asked to keep quiet about it: the file
+    /// fails as it always has.
+    Refuse,
+}
+
+impl Policy {
+    /// The policy for --no-clobber and whether standard input is a
+    /// terminal.
+    pub fn decide(no_clobber: bool, stdin_is_tty: bool) -> Self {
+        match (no_clobber, stdin_is_tty) {
+            (true, _) => Policy::Never,
+            (false, true) => Policy::Ask,
+            (false, false) => Policy::Refuse,
+        }
+    }
+}
+
+/// The policy, and whether "a" has already approved the rest.
+#[derive(Debug)]
+pub struct Overwrites {
+    policy: Policy,
+    all: bool,
+}
+
+impl Overwrites {
+    pub const fn new(policy: Policy) -> Self {
+        Overwrites { policy, all: false }
+    }
+
+    /// Whether `path`, which exists, may be replaced. A refusal is an
+    /// `AlreadyExists` error saying why; `Ok(false)` leaves it to the
+    /// writer, which reports the file as existing.
+    pub fn may_replace(&mut self, path: &Path, prompter: &mut dyn Prompter) -> io::Result<bool> {
+        let refuse = |why: &str| {
+            io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists; {}", path.display(), why),
+            )
+        };
+        match self.policy {
+            Policy::Never => Err(refuse("not replaced (--no-clobber)")),
+            Policy::Refuse => Ok(false),
+            Policy::Ask if self.all => Ok(true),
+            Policy::Ask => {
+                let question = format!("overwrite '{}'? [y/N/a] ", path.display());
+                let answer = prompter.prompt(&question).map_err(io::Error::other)?;
+                match answer.trim().to_ascii_lowercase().as_str() {
+                    "y" | "yes" => Ok(true),
+                    "a" | "all" => {
+                        self.all = true;
+                        Ok(true)
+                    }
+                    _ => Err(refuse("left as it is")),
+                }
+            }
+        }
+    }
+}
+
+static OVERWRITES: Mutex<Overwrites> = Mutex::new(Overwrites::new(Policy::Refuse));
+
+/// Set the policy for the rest of the run.
+pub fn set_policy(policy: Policy) {
+    *OVERWRITES.lock().unwrap_or_else(|e| e.into_inner()) = Overwrites::new(policy);
+}
+
+/// Whether `output` may be replaced: with `force` unless --no-clobber,
+/// and otherwise as the policy says, asking at the terminal.
+pub fn may_replace(output: &Path, force: bool) -> io::Result<bool> {
+    if std::fs::symlink_metadata(output).is_err() {
+        return Ok(force);
+    }
+    let mut overwrites = OVERWRITES.lock().unwrap_or_else(|e| e.into_inner());
+    match force && overwrites.policy != Policy::Never {
+        true => Ok(true),
+        false => overwrites.may_replace(output, &mut TtyPrompter),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    struct Script {
+        answers: VecDeque<&'static str>,
+        asked: Vec<String>,
+    }
+
+    impl Script {
+        fn new(answers: &[&'static str]) -> Self {
+            Script {
+                answers: answers.iter().copied().collect(),
+                asked: Vec::new(),
+            }
+        }
+    }
+
+    impl Prompter for Script {
+        fn prompt(&mut self, text: &str) -> Result<String, String> {
+            self.asked.push(text.to_string());
+            self.answers
+                .pop_front()
+                .map(|answer| format!("{}\n", answer))
+                .ok_or_else(|| "no more answers".to_string())
+        }
+    }
+
+    #[test]
+    fn policy_from_flag_and_terminal() {
+        assert_eq!(Policy::decide(true, true), Policy::Never);
+        assert_eq!(Policy::decide(true, false), Policy::Never);
+        assert_eq!(Policy::decide(false, true), Policy::Ask);
+        assert_eq!(Policy::decide(false, false), Policy::Refuse);
+    }
+
+    #[test]
+    fn no_clobber_never_asks() {
+        let mut overwrites = Overwrites::new(Policy::Never);
+        let mut script = Script::new(&["y"]);
+        let err = overwrites
+            .may_replace(Path::new("a.enc"), &mut script)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(err.to_string().contains("--no-clobber"), "{}", err);
+        assert!(script.asked.is_empty());
+    }
+
+    #[test]
+    fn off_a_terminal_the_writer_refuses() {
+        let mut overwrites = Overwrites::new(Policy::Refuse);
+        let mut script = Script::new(&[]);
+        assert!(!overwrites
+            .may_replace(Path::new("a.enc"), &mut script)
+            .unwrap());
+        assert!(script.asked.is_empty());
+    }
+
+    #[test]
+    fn each_file_is_asked_about() {
+        let mut overwrites = Overwrites::new(Policy::Ask);
+        let mut script = Script::new(&["n", "Y", "", "yes", "maybe"]);
+        let answers: Vec<bool> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|name| overwrites.may_replace(Path::new(name), &mut script).is_ok())
+            .collect();
+        assert_eq!(answers, [false, true, false, true, false]);
+        assert_eq!(script.asked[0], "overwrite 'a'? [y/N/a] ");
+        assert_eq!(script.asked.len(), 5);
+    }
+
+    #[test]
+    fn all_answers_for_the_rest_of_the_batch() {
+        let mut overwrites = Overwrites::new(Policy::Ask);
+        let mut script = Script::new(&["n", "a"]);
+        let first = overwrites.may_replace(Path::new("one"), &mut script);
+        assert!(first.unwrap_err().to_string().contains("left as it is"));
+        for name in ["two", "three", "four"] {
+            assert!(overwrites
+                .may_replace(Path::new(name), &mut script)
+                .unwrap());
+        }
+        assert_eq!(script.asked.len(), 2);
+    }
+
+    #[test]
+    fn an_unreadable_answer_is_an_error() {
+        let mut overwrites = Overwrites::new(Policy::Ask);
+        let err = overwrites
+            .may_replace(Path::new("a"), &mut Script::new(&[]))
+            .unwrap_err();
+        assert!(err.to_string().contains("no more answers"), "{}", err);
+    }
+}
@@ -0,0 +1,87 @@
+/* completions: tab-completion scripts from clap_complete.
+ *
+ * File arguments carry a path value hint and enum arguments list their
+ * values, so both complete without any help here. What does not carry
+ * over is an argument group: the key sources (--key, --key-hex,
+ * --passphrase, --recipient, ...) are one-of groups, and the generators
+ * only look at each argument's own conflicts. So before generating, every
+ * member of such a group is made to conflict with the others, and a shell
+ * stops offering --key-file once --key-hex is on the line. Parsing is
+ * unaffected; the groups already reject those combinations. */
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::{Command, Id};
+use clap_complete::Shell;
+
+/* `command` and its subcommands with each one-of group's members marked
+ * as conflicting with one another */
+fn with_group_conflicts(mut command: Command) -> Command {
+    /* Arguments join a group of another struct's (`group = "ChachaKey"`)
+     * only when the command is built */
+    command.build();
+    let groups: Vec<Vec<Id>> = command
+        .get_groups()
+        .cloned()
+        .filter_map(|mut group| (!group.is_multiple()).then(|| group.get_args().cloned().collect()))
+        .collect();
+    for members in groups {
+        for member in &members {
+            let others: Vec<&Id> = members.iter().filter(|m| *m != member).collect();
+            command = command.mut_arg(member, |arg| arg.conflicts_with_all(others));
+        }
+    }
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    for name in names {
+        command = command.mut_subcommand(name, with_group_conflicts);
+    }
+    command
+}
+
+/// Write the completion script for `shell` to `out`.
+pub fn write_completions(shell: Shell, command: Command, out: &mut dyn Write) {
+    let mut command = with_group_conflicts(command);
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, out);
+}
+
+/// Write the completion script for `shell` into `dir`, under the file
+/// name the shell expects, and return its path.
+pub fn install_completions(shell: Shell, command: Command, dir: &Path) -> std::io::Result<PathBuf> {
+    let mut command = with_group_conflicts(command);
+    let name = command.get_name().to_string();
+    clap_complete::generate_to(shell, &mut command, name, dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction, ArgGroup};
+
+    #[test]
+    fn one_of_groups_become_conflicts() {
+        let flag = |name: &'static str| Arg::new(name).long(name).action(ArgAction::SetTrue);
+        let sub = Command::new("sub")
+            .args([flag("a"), flag("b"), flag("c"), flag("d"), flag("e")])
+            .group(ArgGroup::new("one").args(["a", "b", "c"]))
+            .group(ArgGroup::new("many").args(["d", "e"]).multiple(true));
+        let command = with_group_conflicts(Command::new("tool").subcommand(sub));
+        let sub = command.find_subcommand("sub").unwrap();
+        let conflicts = |name: &str| {
+            let arg = sub.get_arguments().find(|a| a.get_id() == name).unwrap();
+            let mut ids: Vec<String> = sub
+                .get_arg_conflicts_with(arg)
+                .iter()
+                .map(|a| a.get_id().to_string())
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(conflicts("a"), ["b", "c"]);
+        assert_eq!(conflicts("c"), ["a", "b"]);
+        assert!(conflicts("d").is_empty());
+    }
+}
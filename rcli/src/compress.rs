@@ -0,0 +1,112 @@
+/* --compress and --max-decompressed-size.
+ *
+ * Compression happens inside the encryption, so the codec and the
+ * original length are in the authenticated header (see crypto::compress)
+ * and decryption undoes it without being asked. Input whose start hardly
+ * compresses is stored as it is, so it costs nothing. What a file may
+ * decompress to is capped, so a small file cannot fill the disk; the cap
+ * is set once from the command line, as --json is, and read wherever a
+ * compressed stream is opened. */
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crypto::fs::{self, EncryptOptions, FsCryptoError, Summary};
+use crypto::{decrypt_stream_compressed_with_jobs, Codec, DEFAULT_DECOMPRESS_LIMIT};
+
+use crate::STDIN_PATH;
+
+static LIMIT: AtomicU64 = AtomicU64::new(DEFAULT_DECOMPRESS_LIMIT);
+
+/// The zstd level "zstd" alone means, as for the zstd tool.
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// What --compress asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Codec(Codec),
+}
+
+impl Compression {
+    pub fn codec(self) -> Option<Codec> {
+        match self {
+            Compression::None => None,
+            Compression::Codec(codec) => Some(codec),
+        }
+    }
+}
+
+/// Parse --compress: zstd, zstd:LEVEL (1 to 22), deflate, gzip (the same
+/// raw DEFLATE, not a .gz file) or none.
+pub fn parse_compression(text: &str) -> Result<Compression, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "none" => Ok(Compression::None),
+        "deflate" | "gzip" => Ok(Compression::Codec(Codec::Deflate)),
+        "zstd" => Ok(Compression::Codec(Codec::Zstd(DEFAULT_ZSTD_LEVEL))),
+        lower => match lower.strip_prefix("zstd:") {
+            Some(level) => match level.parse::<i32>() {
+                Ok(level) if (1..=22).contains(&level) => {
+                    Ok(Compression::Codec(Codec::Zstd(level)))
+                }
+                _ => Err(format!("zstd levels run from 1 to 22, not {:?}", level)),
+            },
+            None => Err(format!(
+                "{:?} is not a codec; use zstd, zstd:LEVEL, deflate, gzip or none",
+                text
+            )),
+        },
+    }
+}
+
+/// Parse --max-decompressed-size: bytes, with an optional K, M or G.
+pub fn parse_limit(text: &str) -> Result<u64, String> {
+    crate::bench::parse_size(text).map(|size| size as u64)
+}
+
+/// Cap what compressed files may decompress to from now on.
+pub fn set_limit(limit: u64) {
+    LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// The cap in force: --max-decompressed-size, or 1 GiB.
+pub fn limit() -> u64 {
+    LIMIT.load(Ordering::Relaxed)
+}
+
+/// `opts` for encrypting `file`, recording its length when compressing
+/// so decryption can refuse it early if it is too large; standard input
+/// has no length to record.
+pub fn sized(opts: &EncryptOptions, file: &str) -> Result<EncryptOptions, FsCryptoError> {
+    let original_len = match opts.compression {
+        Some(_) if file != STDIN_PATH => Some(std::fs::metadata(file)?.len()),
+        _ => None,
+    };
+    Ok(EncryptOptions {
+        original_len,
+        ..opts.clone()
+    })
+}
+
+/// Decrypt the rcli stream in `reader`, whose header is `compressed` or
+/// not, decompressing it under `limit()` if it is, on `jobs::per_file()`
+/// threads.
+pub fn decrypt(
+    mut reader: &mut dyn Read,
+    mut writer: &mut dyn Write,
+    key: &[u8],
+    aad: &[u8],
+    compressed: bool,
+) -> Result<Summary, FsCryptoError> {
+    let jobs = crate::jobs::per_file();
+    match compressed {
+        true => Ok(decrypt_stream_compressed_with_jobs(
+            &mut reader,
+            &mut writer,
+            key,
+            aad,
+            limit(),
+            jobs,
+        )?),
+        false => fs::decrypt_stream_with_jobs(&mut reader, &mut writer, key, aad, jobs),
+    }
+}
@@ -0,0 +1,446 @@
+/* Defaults for options that are the same every time, from a config file
+ * and the environment.
+ *
+ * The file is TOML, at --config PATH, else $RCLI_CONFIG, else
+ * $XDG_CONFIG_HOME/rcli/config.toml or ~/.config/rcli/config.toml. Only
+ * the keys of `Settings` are allowed, so a typo is an error rather than a
+ * setting silently ignored. A file that is not there is no error unless
+ * it was named with --config or $RCLI_CONFIG. Each setting can also come
+ * from an RCLI_* environment variable, which beats the file, and a flag
+ * on the command line beats both:
+ *
+ *   key-file = "/home/me/k.hex"      # RCLI_KEY_FILE: for encrypt,
+ *                                    # decrypt, chacha and verify when
+ *                                    # no key, passphrase or recipient
+ *                                    # is given
+ *   algorithm = "aes256gcm"          # RCLI_ALGORITHM: encrypt --algorithm
+ *   chunk-size = "1M"                # RCLI_CHUNK_SIZE: --chunk-size
+ *   progress = false                 # RCLI_PROGRESS: --progress or
+ *                                    # --no-progress
+ *   suffix = ".sealed"               # RCLI_SUFFIX: --keep's --suffix
+ *   nonce-log = "/var/lib/rcli/nonces"  # RCLI_NONCE_LOG: --nonce-log
+ *
+ * No setting holds key material: key-file names a file, which is only
+ * read when a key is needed. */
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::status::{CliError, ErrorCode};
+use crate::{backup, names, parse_chunk_size, CipherAlg};
+
+/// Names the config file, when --config does not.
+pub const CONFIG_ENV: &str = "RCLI_CONFIG";
+
+/// What the config file may set.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    pub key_file: Option<PathBuf>,
+    #[serde(default, deserialize_with = "de_algorithm")]
+    pub algorithm: Option<CipherAlg>,
+    #[serde(default, deserialize_with = "de_chunk_size")]
+    pub chunk_size: Option<u32>,
+    pub progress: Option<bool>,
+    #[serde(default, deserialize_with = "de_suffix")]
+    pub suffix: Option<String>,
+    pub nonce_log: Option<PathBuf>,
+}
+
+/* Each setting: its key in the file and its environment variable */
+const KEY_FILE: (&str, &str) = ("key-file", "RCLI_KEY_FILE");
+const ALGORITHM: (&str, &str) = ("algorithm", "RCLI_ALGORITHM");
+const CHUNK_SIZE: (&str, &str) = ("chunk-size", "RCLI_CHUNK_SIZE");
+const PROGRESS: (&str, &str) = ("progress", "RCLI_PROGRESS");
+const SUFFIX: (&str, &str) = ("suffix", "RCLI_SUFFIX");
+const NONCE_LOG: (&str, &str) = ("nonce-log", "RCLI_NONCE_LOG");
+
+impl Settings {
+    /// The settings given by RCLI_* variables, read through `var`.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Settings, CliError> {
+        let parsed =
+            |(_, name): (&str, &str), parse: &dyn Fn(&str) -> Result<(), String>| match var(name) {
+                Some(value) => parse(&value)
+                    .map(|_| Some(value))
+                    .map_err(|e| CliError::new(ErrorCode::Usage, format!("{}: {}", name, e))),
+                None => Ok(None),
+            };
+        let ok = |_: &str| Ok(());
+        Ok(Settings {
+            key_file: parsed(KEY_FILE, &ok)?.map(PathBuf::from),
+            algorithm: parsed(ALGORITHM, &|v| parse_algorithm(v).map(|_| ()))?
+                .map(|v| parse_algorithm(&v).expect("checked above")),
+            chunk_size: parsed(CHUNK_SIZE, &|v| parse_chunk_size(v).map(|_| ()))?
+                .map(|v| parse_chunk_size(&v).expect("checked above")),
+            progress: parsed(PROGRESS, &|v| parse_bool(v).map(|_| ()))?
+                .map(|v| parse_bool(&v).expect("checked above")),
+            suffix: parsed(SUFFIX, &|v| backup::parse_suffix(v).map(|_| ()))?,
+            nonce_log: parsed(NONCE_LOG, &ok)?.map(PathBuf::from),
+        })
+    }
+
+    /// The settings in the TOML `text` of the file at `path`.
+    pub fn from_toml(text: &str, path: &Path) -> Result<Settings, CliError> {
+        toml::from_str(text).map_err(|e| {
+            CliError::new(
+                ErrorCode::Usage,
+                format!("{}: {}", path.display(), e.message()),
+            )
+        })
+    }
+
+    /// Each setting from `self`, or else from `fallback`.
+    pub fn or(self, fallback: Settings) -> Settings {
+        Settings {
+            key_file: self.key_file.or(fallback.key_file),
+            algorithm: self.algorithm.or(fallback.algorithm),
+            chunk_size: self.chunk_size.or(fallback.chunk_size),
+            progress: self.progress.or(fallback.progress),
+            suffix: self.suffix.or(fallback.suffix),
+            nonce_log: self.nonce_log.or(fallback.nonce_log),
+        }
+    }
+}
+
+fn parse_algorithm(text: &str) -> Result<CipherAlg, String> {
+    CipherAlg::from_str(text, true)
+}
+
+fn parse_bool(text: &str) -> Result<bool, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        _ => Err(format!("{:?} is not true or false", text)),
+    }
+}
+
+fn de_algorithm<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<CipherAlg>, D::Error> {
+    let text = String::deserialize(d)?;
+    parse_algorithm(&text)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/* chunk-size = 65536 or chunk-size = "64K" */
+fn de_chunk_size<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<u32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+    let text = match Size::deserialize(d)? {
+        Size::Bytes(n) => n.to_string(),
+        Size::Text(text) => text,
+    };
+    parse_chunk_size(&text)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn de_suffix<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    let text = String::deserialize(d)?;
+    backup::parse_suffix(&text)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// The settings in force and where they came from.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// The file looked for, whether or not it was there.
+    pub path: Option<PathBuf>,
+    pub found: bool,
+    env: Settings,
+    file: Settings,
+    effective: Settings,
+}
+
+impl Config {
+    /// Read the file (`flag` from --config, or the default) and the
+    /// environment through `var`.
+    pub fn load(
+        flag: Option<PathBuf>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Config, CliError> {
+        let named = flag.or_else(|| var(CONFIG_ENV).map(PathBuf::from));
+        let must_exist = named.is_some();
+        let path = named.or_else(|| default_path(&var));
+        let (found, file) = match &path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(text) => (true, Settings::from_toml(&text, path)?),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && !must_exist => {
+                    (false, Settings::default())
+                }
+                Err(e) => {
+                    return Err(CliError::new(
+                        ErrorCode::Io,
+                        format!("cannot read config file {}: {}", path.display(), e),
+                    ))
+                }
+            },
+            None => (false, Settings::default()),
+        };
+        let env = Settings::from_env(var)?;
+        Ok(Config {
+            path,
+            found,
+            effective: env.clone().or(file.clone()),
+            env,
+            file,
+        })
+    }
+
+    /// The merged settings.
+    pub fn settings(&self) -> &Settings {
+        &self.effective
+    }
+
+    /// `config show`: each setting in effect, as TOML, with where it came
+    /// from.
+    pub fn show(&self) -> String {
+        let (env, file, now) = (&self.env, &self.file, &self.effective);
+        let mut out = match (&self.path, self.found) {
+            (Some(path), true) => format!("# config file: {}\n", path.display()),
+            (Some(path), false) => format!("# config file: {} (not found)\n", path.display()),
+            (None, _) => "# config file: none (no home directory)\n".to_string(),
+        };
+        let line = |(key, name): (&str, &str), value: String, in_env: bool, in_file: bool| {
+            let from = match (in_env, in_file) {
+                (true, _) => format!("# from {}", name),
+                (false, true) => "# from the config file".to_string(),
+                (false, false) => "# built in".to_string(),
+            };
+            format!("{:<40} {}\n", format!("{} = {}", key, value), from)
+        };
+        out += &match &now.key_file {
+            Some(path) => line(
+                KEY_FILE,
+                toml_string(&path.display().to_string()),
+                env.key_file.is_some(),
+                file.key_file.is_some(),
+            ),
+            None => "# key-file is not set, so a key option is needed\n".to_string(),
+        };
+        let algorithm = now.algorithm.unwrap_or(CipherAlg::Chacha20poly1305);
+        let algorithm = algorithm.to_possible_value().expect("no skipped values");
+        out += &line(
+            ALGORITHM,
+            toml_string(algorithm.get_name()),
+            env.algorithm.is_some(),
+            file.algorithm.is_some(),
+        );
+        let chunk_size = now.chunk_size.unwrap_or(crypto::header::DEFAULT_CHUNK_SIZE);
+        out += &line(
+            CHUNK_SIZE,
+            chunk_size.to_string(),
+            env.chunk_size.is_some(),
+            file.chunk_size.is_some(),
+        );
+        let progress = match now.progress {
+            Some(on) => on.to_string(),
+            None => "\"auto\"".to_string(),
+        };
+        out += &line(
+            PROGRESS,
+            progress,
+            env.progress.is_some(),
+            file.progress.is_some(),
+        );
+        let suffix = now.suffix.as_deref().unwrap_or(names::DEFAULT_SUFFIX);
+        out += &line(
+            SUFFIX,
+            toml_string(suffix),
+            env.suffix.is_some(),
+            file.suffix.is_some(),
+        );
+        out += &match &now.nonce_log {
+            Some(path) => line(
+                NONCE_LOG,
+                toml_string(&path.display().to_string()),
+                env.nonce_log.is_some(),
+                file.nonce_log.is_some(),
+            ),
+            None => "# nonce-log is not set\n".to_string(),
+        };
+        out
+    }
+}
+
+fn toml_string(text: &str) -> String {
+    toml::Value::String(text.to_string()).to_string()
+}
+
+/* $XDG_CONFIG_HOME/rcli/config.toml, or ~/.config/rcli/config.toml */
+fn default_path(var: &impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let base = match var("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(var("HOME").filter(|dir| !dir.is_empty())?).join(".config"),
+    };
+    Some(base.join("rcli").join("config.toml"))
+}
+
+/// --config, found among the arguments before they are parsed, since the
+/// file decides how they are parsed.
+pub fn flag_from_args(args: impl Iterator<Item = OsString>) -> Option<PathBuf> {
+    let mut args = args.skip(1).take_while(|arg| arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|a| a.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Make `config` the one in force for the rest of the run.
+pub fn install(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+/// The config in force; empty until `install`.
+pub fn get() -> &'static Config {
+    static NONE: OnceLock<Config> = OnceLock::new();
+    CONFIG
+        .get()
+        .unwrap_or_else(|| NONE.get_or_init(Config::default))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let map: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| map.get(name).cloned()
+    }
+
+    #[test]
+    fn file_settings_parse() {
+        let text = "key-file = \"/k.hex\"\nalgorithm = \"aes256gcm\"\n\
+                    chunk-size = \"1M\"\nprogress = false\nsuffix = \".sealed\"\n\
+                    nonce-log = \"/var/lib/rcli/nonces\"\n";
+        let settings = Settings::from_toml(text, Path::new("c.toml")).unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                key_file: Some(PathBuf::from("/k.hex")),
+                algorithm: Some(CipherAlg::Aes256gcm),
+                chunk_size: Some(1 << 20),
+                progress: Some(false),
+                suffix: Some(".sealed".to_string()),
+                nonce_log: Some(PathBuf::from("/var/lib/rcli/nonces")),
+            }
+        );
+        let bytes = Settings::from_toml("chunk-size = 4096", Path::new("c.toml")).unwrap();
+        assert_eq!(bytes.chunk_size, Some(4096));
+    }
+
+    #[test]
+    fn unknown_and_bad_keys_are_errors() {
+        let cases = [
+            ("chunk-sise = \"1M\"", "unknown field `chunk-sise`"),
+            ("algorithm = \"rot13\"", "rot13"),
+            ("chunk-size = 12", "chunk size must be between"),
+            ("suffix = \"\"", "must not be empty"),
+            ("key = \"00\"", "unknown field `key`"),
+        ];
+        for (text, expected) in cases {
+            let err = Settings::from_toml(text, Path::new("c.toml")).unwrap_err();
+            assert_eq!(err.code, ErrorCode::Usage);
+            assert!(err.message.starts_with("c.toml: "), "{}", err);
+            assert!(err.message.contains(expected), "{}: {}", text, err);
+        }
+    }
+
+    #[test]
+    fn environment_beats_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "chunk-size = \"4K\"\nprogress = true\n").unwrap();
+        let env = vars(&[("RCLI_CHUNK_SIZE", "2K"), ("RCLI_SUFFIX", ".x")]);
+        let config = Config::load(Some(path), env).unwrap();
+        let settings = config.settings();
+        assert_eq!(settings.chunk_size, Some(2048));
+        assert_eq!(settings.progress, Some(true));
+        assert_eq!(settings.suffix.as_deref(), Some(".x"));
+        assert_eq!(settings.algorithm, None);
+
+        let err = Config::load(None, vars(&[("RCLI_PROGRESS", "sometimes")])).unwrap_err();
+        assert!(err.message.starts_with("RCLI_PROGRESS: "), "{}", err);
+    }
+
+    #[test]
+    fn only_a_named_file_must_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().to_str().unwrap();
+        let config = Config::load(None, vars(&[("HOME", home)])).unwrap();
+        assert!(!config.found);
+        assert_eq!(
+            config.path.clone().unwrap(),
+            dir.path().join(".config/rcli/config.toml")
+        );
+        assert_eq!(*config.settings(), Settings::default());
+
+        let missing = dir.path().join("missing.toml");
+        let err = Config::load(Some(missing.clone()), vars(&[])).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Io);
+        let named = vars(&[(CONFIG_ENV, missing.to_str().unwrap())]);
+        assert!(Config::load(None, named).is_err());
+    }
+
+    #[test]
+    fn config_flag_is_found_before_parsing() {
+        let args = |list: &[&str]| list.iter().map(OsString::from).collect::<Vec<_>>();
+        let find = |list: &[&str]| flag_from_args(args(list).into_iter());
+        assert_eq!(
+            find(&["rcli", "--config", "a.toml", "encrypt"]),
+            Some(PathBuf::from("a.toml"))
+        );
+        assert_eq!(
+            find(&["rcli", "encrypt", "--config=b.toml"]),
+            Some(PathBuf::from("b.toml"))
+        );
+        assert_eq!(find(&["rcli", "encrypt", "--", "--config", "c"]), None);
+        assert_eq!(find(&["rcli", "encrypt"]), None);
+    }
+
+    #[test]
+    fn show_says_where_each_setting_came_from() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "key-file = \"/keys/k.hex\"\nalgorithm = \"aes256gcm\"\n",
+        )
+        .unwrap();
+        let config = Config::load(Some(path), vars(&[("RCLI_ALGORITHM", "chacha20poly1305")]));
+        let shown = config.unwrap().show();
+        let line = |key: &str| {
+            shown
+                .lines()
+                .find(|line| line.starts_with(key))
+                .unwrap()
+                .to_string()
+        };
+        assert!(line("key-file").contains("\"/keys/k.hex\""), "{}", shown);
+        assert!(line("key-file").ends_with("# from the config file"));
+        assert!(line("algorithm").contains("\"chacha20poly1305\""));
+        assert!(line("algorithm").ends_with("# from RCLI_ALGORITHM"));
+        assert!(line("chunk-size").contains("65536"));
+        assert!(line("chunk-size").ends_with("# built in"));
+        assert!(line("progress").contains("\"auto\""));
+        assert!(line("suffix").contains("\".enc\""));
+    }
+}
@@ -0,0 +1,150 @@
+/* Examples shown after each command's --help, and as the EXAMPLES section
+ * of its man page. Each starts with EXAMPLES_HEADING; the man page drops
+ * that line and keeps the rest as written. */
+
+/// First line of every example block.
+pub const EXAMPLES_HEADING: &str = "Examples:";
+
+pub const RCLI: &str = "\
+Examples:
+  rcli keygen --out key.hex
+  rcli chacha --encrypt --key-file key.hex -f secret.txt
+  rcli chacha --decrypt --key-file key.hex -f secret.txt
+  rcli <COMMAND> --help";
+
+pub const RC4: &str = "\
+Examples:
+  rcli rc4 --insecure-ok --key 0x4b 0x8e 0x29 0x87 0x80 -f secret.txt
+  rcli rc4 --insecure-ok --key-file rc4.hex -f a.bin b.bin -o out.bin
+  rcli rc4 --insecure-ok --key-ascii secret99 -f legacy.dat
+  rcli rc4 --insecure-ok --key-file rc4.hex --mmap -f archive.bin";
+
+pub const KEYGEN: &str = "\
+Examples:
+  rcli keygen
+  rcli keygen --format base64
+  rcli keygen --algorithm rc4 --bytes 32 --format hex
+  rcli keygen --out key.hex
+  rcli keygen --words 8 --separator ' '";
+
+pub const PWGEN: &str = "\
+Examples:
+  rcli pwgen
+  rcli pwgen --length 24 --symbols --no-ambiguous --count 5 --show-entropy";
+
+pub const RAND: &str = "\
+Examples:
+  rcli rand --bytes 16 --format hex
+  rcli rand --bytes 1073741824 --out fixture.bin";
+
+pub const ENCRYPT: &str = "\
+Examples:
+  rcli encrypt --key-file key.hex -f secret.txt
+  rcli encrypt --algorithm aes256gcm --key-file key.hex -f db.dump -o db.enc
+  rcli encrypt --passphrase --armor -f notes.txt
+  rcli encrypt --recipient rcli1... -f doc.pdf
+  rcli encrypt --key-file key.hex --nonce-log /var/lib/rcli/nonces -f db.dump
+  rcli encrypt --key-file key.hex --compress zstd -f access.log
+  rcli encrypt --key-file key.hex --split 4000M -f disk.img -o disk.img.enc
+  rcli encrypt --key-file key.hex --jobs 16 -f vm.qcow2
+  tar cz dir | rcli encrypt --key-file key.hex -f - > backup.enc";
+
+pub const DECRYPT: &str = "\
+Examples:
+  rcli decrypt --key-file key.hex -f secret.txt
+  rcli decrypt --key-file key.hex -f db.enc -o db.dump
+  rcli decrypt --identity me.key -f doc.pdf
+  rcli decrypt -r --key-file key.hex -f reports/
+  rcli decrypt -r --jobs 8 --key-file key.hex -f photos/ --output-dir plain/
+  rcli decrypt --key-file key.hex --max-decompressed-size 20G -f access.log";
+
+pub const CHACHA: &str = "\
+Examples:
+  rcli chacha --encrypt --key-file key.hex -f secret.txt
+  rcli chacha --decrypt --key-file key.hex -f secret.txt -o plain.txt
+  rcli chacha --encrypt --passphrase --armor -f notes.txt
+  rcli chacha --encrypt --key-file key.hex --aad host=db1 -f db.dump
+  rcli chacha --encrypt --recipient rcli1... -f doc.pdf
+  rcli chacha --decrypt --identity me.key -f doc.pdf
+  rcli chacha --encrypt -r --key-file key.hex -f reports/ --exclude '*.tmp'
+  tar cz dir | rcli chacha --encrypt --key-file key.hex -f - > backup.enc";
+
+pub const REKEY: &str = "\
+Examples:
+  rcli rekey --old-key-file old.key --new-key-file new.key -f secret.enc
+  rcli rekey -r --old-key-file old.key --new-key-file new.key -f archive/
+  rcli rekey --old-passphrase --new-key-file new.key -f notes.enc
+  rcli rekey --old-key-file old.key --new-passphrase-file pass.txt -f db.enc -o db.new.enc";
+
+pub const JOIN: &str = "\
+Examples:
+  rcli join --out disk.img.enc disk.img.enc.000 disk.img.enc.001 disk.img.enc.002
+  rcli join --out backup.enc backup.enc.*";
+
+pub const ARCHIVE: &str = "\
+Examples:
+  rcli archive create --out backup.rcla -f ./project/ --key-file key.hex
+  rcli archive create --out backup.rcla -f ./project/ --key-file key.hex --pad padme
+  rcli archive create --out logs.rcla -f /var/log/app/ --key-file key.hex --compress zstd:19
+  rcli archive extract backup.rcla --into ./restore/ --key-file key.hex
+  rcli archive extract backup.rcla --entry docs/report.pdf --out report.pdf --key-file key.hex
+  rcli archive extract backup.rcla --entry 'src/*.rs' --entry docs --into ./some/ --key-file key.hex
+  rcli archive list backup.rcla --key-file key.hex --json";
+
+pub const VERIFY: &str = "\
+Examples:
+  rcli verify --key-file key.hex -f backup.enc
+  rcli verify --passphrase-file ~/.backup-pass -f backup.enc";
+
+pub const INSPECT: &str = "\
+Examples:
+  rcli inspect -f backup.enc
+  rcli inspect -f backup.enc --json";
+
+pub const HASH: &str = "\
+Examples:
+  rcli hash release.tar.gz > SHA256SUMS
+  rcli hash --algorithm blake3 *.iso
+  rcli hash --check SHA256SUMS";
+
+pub const HMAC: &str = "\
+Examples:
+  rcli hmac --key-file mac.hex -f report.pdf
+  rcli hmac --key-file mac.hex -f report.pdf --verify <TAG>";
+
+pub const KEYPAIR: &str = "\
+Examples:
+  rcli keypair --out-prefix release";
+
+pub const SIGN: &str = "\
+Examples:
+  rcli sign --key release.key -f app.tar.gz
+  rcli sign --key release.key --prehash blake3 -f - --out app.sig < app.tar.gz";
+
+pub const IDENTITY: &str = "\
+Examples:
+  rcli identity new --out me.key";
+
+pub const VERIFY_SIG: &str = "\
+Examples:
+  rcli verify-sig --pub release.pub -f app.tar.gz";
+
+pub const BENCH: &str = "\
+Examples:
+  rcli bench
+  rcli bench --size 256MiB --iterations 3 --chunk-size 64K,1M
+  rcli bench --disk-dir /mnt/backup --json";
+
+pub const CONFIG: &str = "\
+Examples:
+  rcli config show
+  rcli --config ./backup.toml config show";
+
+pub const NONCE_LOG: &str = "\
+Examples:
+  rcli nonce-log compact --log /var/lib/rcli/nonces --retire 3f9c0e5a4b1d7e2f8a6c0b9d4e1f2a3b";
+
+pub const COMPLETIONS: &str = "\
+Examples:
+  rcli completions bash > ~/.local/share/bash-completion/completions/rcli
+  rcli completions zsh --out-dir ~/.zfunc";
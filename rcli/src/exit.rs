@@ -0,0 +1,78 @@
+/* rcli's exit statuses besides 0 for success, one per kind of failure,
+ * so a script can tell a wrong key from a missing file from a mistyped
+ * option. Every failure is a `CliError` whose `ErrorCode` decides its
+ * status here, and --json error records carry both. The numbers are part
+ * of the interface: new ones may be added, but these keep their
+ * meaning. */
+use crate::status::ErrorCode;
+
+/// Anything not covered below, such as a checksum that does not match.
+pub const FAILURE: i32 = 1;
+/// Arguments that do not parse or do not go together, including a key
+/// that is not valid hex or base64, as clap reports its own errors.
+pub const USAGE: i32 = 2;
+/// A file could not be read or written.
+pub const IO: i32 = 3;
+/// A chunk, tag or signature failed authentication, or the file was cut
+/// short: damaged, tampered with, or (without a key check value) under
+/// the wrong key.
+pub const AUTH: i32 = 4;
+/// Input that is not in the format expected: not an rcli file (or, to
+/// encrypt, one already), or a damaged header, armor or signature file.
+pub const FORMAT: i32 = 5;
+/// The key, passphrase, identity or associated data is not the one the
+/// file was encrypted with, as its check values show.
+pub const WRONG_KEY: i32 = 6;
+/// The --nonce-log shows the key has already encrypted under the nonce
+/// drawn, so nothing was written.
+pub const NONCE_REUSED: i32 = 7;
+/// A compressed file would decompress to more than
+/// --max-decompressed-size allows, so it was not written.
+pub const TOO_LARGE: i32 = 8;
+/// Stopped by Ctrl-C or SIGTERM, as a shell reports death by SIGINT.
+pub const INTERRUPTED: i32 = 130;
+
+/// The exit status for failures with `code`.
+pub fn status(code: ErrorCode) -> i32 {
+    match code {
+        ErrorCode::Usage | ErrorCode::BadKey => USAGE,
+        ErrorCode::Io => IO,
+        ErrorCode::NotAuthentic | ErrorCode::Truncated => AUTH,
+        ErrorCode::NotEncrypted | ErrorCode::AlreadyEncrypted | ErrorCode::Malformed => FORMAT,
+        ErrorCode::WrongKey => WRONG_KEY,
+        ErrorCode::NonceReused => NONCE_REUSED,
+        ErrorCode::TooLarge => TOO_LARGE,
+        ErrorCode::Interrupted => INTERRUPTED,
+        ErrorCode::Failed => FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_are_distinct_but_for_shared_kinds() {
+        let statuses = [
+            0,
+            FAILURE,
+            USAGE,
+            IO,
+            AUTH,
+            FORMAT,
+            WRONG_KEY,
+            NONCE_REUSED,
+            TOO_LARGE,
+            INTERRUPTED,
+        ];
+        for (i, a) in statuses.iter().enumerate() {
+            assert!(!statuses[i + 1..].contains(a), "{} is used twice", a);
+        }
+        assert_eq!(
+            status(ErrorCode::Truncated),
+            status(ErrorCode::NotAuthentic)
+        );
+        assert_eq!(status(ErrorCode::NotEncrypted), FORMAT);
+        assert_eq!(status(ErrorCode::WrongKey), 6);
+    }
+}
@@ -0,0 +1,381 @@
+/* The files rc4 and chacha work on: every -f/--file value in the order
+ * given, then the entries of the --files-from list (lines, or with -0
+ * NUL-separated names as from `find -print0`), then the regular files
+ * matching each --glob. The patterns are
+ * expanded here rather than by the shell, for shells (cmd.exe,
+ * PowerShell) that pass them through untouched.
+ *
+ * --exclude follows rsync: a pattern without '/' matches a name at any
+ * depth, one with '/' the whole path relative to the root (a glob's
+ * literal leading directories, or the -r directory), and a trailing '/'
+ * limits it to directories, which are then never entered. */
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use clap::{Args, ValueHint};
+use glob::{MatchOptions, Pattern};
+
+use crate::key::STDIN_PATH;
+
+#[derive(Args, Debug)]
+pub struct FileArgs {
+    /// File to process, or - for standard input; takes several values
+    /// and may be repeated
+    #[arg(short, long = "file", value_name = "FILE_NAME", num_args = 1..,
+          value_hint = ValueHint::AnyPath,
+          required_unless_present_any = ["glob", "files_from"])]
+    pub files: Vec<String>,
+
+    /// Also process the files listed in this file, one per line (- for
+    /// standard input); blank lines are skipped
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub files_from: Option<String>,
+
+    /// Separate --files-from entries with NUL bytes instead of newlines
+    #[arg(short = '0', long = "null", requires = "files_from")]
+    pub null: bool,
+
+    /// Also process the regular files matching this pattern (*, ?, [...],
+    /// and ** for any depth); may be repeated
+    #[arg(long, value_name = "PATTERN")]
+    pub glob: Vec<String>,
+
+    /// Leave out paths matching this pattern, e.g. '*.enc', '.git/' or
+    /// 'build/*.o'; a trailing / matches directories and skips everything
+    /// under them; may be repeated
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+}
+
+impl FileArgs {
+    /// The paths to process, in order, less any excluded. A pattern
+    /// matching no regular file is an error, as are an empty result and
+    /// "-" alongside other files.
+    pub fn expand(&self) -> Result<Vec<String>, String> {
+        let excludes = self.excludes()?;
+        let mut files = self.files.clone();
+        if let Some(list) = &self.files_from {
+            files.extend(self.read_list(list)?);
+        }
+        files.retain(|f| f == STDIN_PATH || !excludes.excludes_path(Path::new(f)));
+        for pattern in &self.glob {
+            let matches = expand_glob(pattern, &excludes)?;
+            if matches.is_empty() {
+                return Err(format!("no files match {}", pattern));
+            }
+            files.extend(matches);
+        }
+        if files.is_empty() {
+            return Err("every file given is excluded".to_string());
+        }
+        if files.len() > 1 && files.iter().any(|f| f == STDIN_PATH) {
+            return Err(format!(
+                "--file {} cannot be combined with other files",
+                STDIN_PATH
+            ));
+        }
+        Ok(files)
+    }
+
+    /// Whether the file list itself comes from standard input.
+    pub fn list_from_stdin(&self) -> bool {
+        self.files_from.as_deref() == Some(STDIN_PATH)
+    }
+
+    fn read_list(&self, list: &str) -> Result<Vec<String>, String> {
+        let mut bytes = Vec::new();
+        if list == STDIN_PATH {
+            if self.files.iter().any(|f| f == STDIN_PATH) {
+                return Err(format!(
+                    "--files-from {0} and --file {0} cannot both read standard input",
+                    STDIN_PATH
+                ));
+            }
+            std::io::stdin().lock().read_to_end(&mut bytes)
+        } else {
+            File::open(list).and_then(|mut f| f.read_to_end(&mut bytes))
+        }
+        .map_err(|e| format!("cannot read file list {}: {}", list, e))?;
+        parse_list(&bytes, self.null).map_err(|e| format!("file list {}: {}", list, e))
+    }
+
+    pub fn excludes(&self) -> Result<Excludes, String> {
+        Excludes::new(&self.exclude)
+    }
+}
+
+/// Compiled --exclude patterns.
+#[derive(Debug, Default)]
+pub struct Excludes {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: Pattern,
+    /* Contains a '/' (other than a trailing one): match the whole
+     * relative path rather than the last name */
+    anchored: bool,
+    dir_only: bool,
+}
+
+const MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl Excludes {
+    pub fn new(patterns: &[String]) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for text in patterns {
+            let dir_only = text.ends_with('/');
+            let trimmed = text.trim_end_matches('/');
+            let anchored = trimmed.contains('/');
+            let trimmed = trimmed.trim_start_matches('/');
+            let pattern = Pattern::new(trimmed)
+                .map_err(|e| format!("invalid exclude pattern {}: {}", text, e.msg))?;
+            rules.push(Rule {
+                pattern,
+                anchored,
+                dir_only,
+            });
+        }
+        Ok(Excludes { rules })
+    }
+
+    /// Whether `rel`, relative to the root, is left out.
+    pub fn excludes(&self, rel: &Path, is_dir: bool) -> bool {
+        let name = rel.file_name().map(Path::new).unwrap_or(rel);
+        self.rules.iter().any(|rule| {
+            (is_dir || !rule.dir_only)
+                && match rule.anchored {
+                    true => rule.pattern.matches_path_with(rel, MATCH),
+                    false => rule.pattern.matches_path_with(name, MATCH),
+                }
+        })
+    }
+
+    /// Whether `path` or one of the directories above it is left out,
+    /// for paths that were not found by walking down from the root.
+    pub fn excludes_path(&self, path: &Path) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let mut prefix = PathBuf::new();
+        let mut components = path.components().peekable();
+        while let Some(component) = components.next() {
+            prefix.push(component);
+            if components.peek().is_none() {
+                break;
+            }
+            if matches!(component, Component::Normal(_)) && self.excludes(&prefix, true) {
+                return true;
+            }
+        }
+        self.excludes(path, false)
+    }
+}
+
+/* One name per line (a CRLF ending is taken off) or per NUL-terminated
+ * entry. Empty entries, and lines of nothing but whitespace, are
+ * skipped; other names are kept exactly, spaces and all. */
+fn parse_list(bytes: &[u8], null: bool) -> Result<Vec<String>, String> {
+    let separator = if null { b'\0' } else { b'\n' };
+    let mut names = Vec::new();
+    for entry in bytes.split(|&b| b == separator) {
+        let entry = match null {
+            true => entry,
+            false => entry.strip_suffix(b"\r").unwrap_or(entry),
+        };
+        if entry.is_empty() || (!null && entry.trim_ascii().is_empty()) {
+            continue;
+        }
+        let name = std::str::from_utf8(entry)
+            .map_err(|_| format!("{:?} is not valid UTF-8", String::from_utf8_lossy(entry)))?;
+        names.push(name.to_string());
+    }
+    Ok(names)
+}
+
+/* The directories before a pattern's first wildcard, which --exclude
+ * paths are relative to */
+fn literal_root(pattern: &str) -> PathBuf {
+    let mut root = PathBuf::new();
+    for component in Path::new(pattern).components() {
+        let text = component.as_os_str().to_string_lossy();
+        if text.contains(['*', '?', '[']) {
+            break;
+        }
+        root.push(component);
+    }
+    root
+}
+
+/* Matches in the glob crate's order (sorted within each directory),
+ * directories and anything else that is not a regular file left out.
+ * The glob crate walks excluded directories all the same, so errors
+ * reading them are ignored too. */
+fn expand_glob(pattern: &str, excludes: &Excludes) -> Result<Vec<String>, String> {
+    let paths =
+        glob::glob(pattern).map_err(|e| format!("invalid pattern {}: {}", pattern, e.msg))?;
+    let root = literal_root(pattern);
+    let relative = |path: &Path| path.strip_prefix(&root).unwrap_or(path).to_path_buf();
+    let mut files = Vec::new();
+    for path in paths {
+        let path = match path {
+            Ok(path) => path,
+            Err(e) if excludes.excludes_path(&relative(e.path())) => continue,
+            Err(e) => return Err(e.to_string()),
+        };
+        if !path.is_file() || excludes.excludes_path(&relative(&path)) {
+            continue;
+        }
+        let path = path
+            .into_os_string()
+            .into_string()
+            .map_err(|p| format!("{:?} is not valid UTF-8", p))?;
+        files.push(path);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(files: &[&str], glob: &[String]) -> FileArgs {
+        FileArgs {
+            files: files.iter().map(|f| f.to_string()).collect(),
+            glob: glob.to_vec(),
+            files_from: None,
+            null: false,
+            exclude: Vec::new(),
+        }
+    }
+
+    fn excludes(patterns: &[&str]) -> Excludes {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        Excludes::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn glob_matches_files_only_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.log", "a.log", "c.txt"] {
+            std::fs::write(dir.path().join(name), name).unwrap();
+        }
+        std::fs::create_dir(dir.path().join("d.log")).unwrap();
+        std::fs::write(dir.path().join("d.log/e.log"), b"nested").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let files = args(&["first"], &[format!("{}/*.log", root)])
+            .expand()
+            .unwrap();
+        assert_eq!(
+            files,
+            [
+                "first".to_string(),
+                format!("{}/a.log", root),
+                format!("{}/b.log", root)
+            ]
+        );
+
+        let deep = args(&[], &[format!("{}/**/*.log", root)]).expand().unwrap();
+        assert_eq!(deep.len(), 3);
+        assert!(deep[2].ends_with("e.log"));
+    }
+
+    #[test]
+    fn unmatched_and_invalid_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let err = args(&[], &[format!("{}/*.none", root)])
+            .expand()
+            .unwrap_err();
+        assert!(err.starts_with("no files match"), "{}", err);
+        assert!(args(&[], &["[".to_string()]).expand().is_err());
+    }
+
+    #[test]
+    fn stdin_stands_alone() {
+        assert_eq!(args(&["-"], &[]).expand().unwrap(), ["-"]);
+        assert!(args(&["-", "x"], &[]).expand().is_err());
+    }
+
+    #[test]
+    fn exclude_rules() {
+        let ex = excludes(&["*.tmp", ".git/", "build/*.o", "/top.txt"]);
+        assert!(ex.excludes(Path::new("a/b/x.tmp"), false));
+        assert!(ex.excludes(Path::new(".git"), true));
+        assert!(ex.excludes(Path::new("sub/.git"), true));
+        /* directory-only */
+        assert!(!ex.excludes(Path::new(".git"), false));
+        /* anchored: whole relative path, and '*' stays within a name */
+        assert!(ex.excludes(Path::new("build/main.o"), false));
+        assert!(!ex.excludes(Path::new("src/build/main.o"), false));
+        assert!(!ex.excludes(Path::new("build/deep/main.o"), false));
+        assert!(ex.excludes(Path::new("top.txt"), false));
+        assert!(!ex.excludes(Path::new("a/top.txt"), false));
+        assert!(!ex.excludes(Path::new("keep.txt"), false));
+
+        assert!(ex.excludes_path(Path::new("proj/.git/config")));
+        assert!(!ex.excludes_path(Path::new("proj/git/config")));
+        assert!(Excludes::new(&["[".to_string()]).is_err());
+    }
+
+    #[test]
+    fn exclude_applies_to_files_and_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        for name in ["a.log", "b.tmp", ".git/c.log"] {
+            std::fs::write(dir.path().join(name), name).unwrap();
+        }
+        let root = dir.path().to_str().unwrap();
+        let mut files = args(&["x.tmp", "y.txt"], &[format!("{}/**/*.log", root)]);
+        files.exclude = vec!["*.tmp".to_string(), ".git/".to_string()];
+        assert_eq!(
+            files.expand().unwrap(),
+            ["y.txt".to_string(), format!("{}/a.log", root)]
+        );
+        files.files = vec!["x.tmp".to_string()];
+        files.glob.clear();
+        assert!(files.expand().is_err());
+    }
+
+    #[test]
+    fn literal_roots() {
+        assert_eq!(literal_root("logs/2024/*.log"), Path::new("logs/2024"));
+        assert_eq!(literal_root("**/*.log"), Path::new(""));
+        assert_eq!(literal_root("/var/log/**"), Path::new("/var/log"));
+    }
+
+    #[test]
+    fn list_parsing() {
+        assert_eq!(
+            parse_list(b"a b.txt\r\n\n  \nc\n", false).unwrap(),
+            ["a b.txt", "c"]
+        );
+        assert_eq!(
+            parse_list(b" line\nbreak\0\0tail\0", true).unwrap(),
+            [" line\nbreak", "tail"]
+        );
+        assert!(parse_list(b"ok\n\xff\n", false).is_err());
+    }
+
+    #[test]
+    fn list_entries_follow_files_and_are_excluded_too() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("list");
+        std::fs::write(&list, "b\nskip.tmp\n\nc\n").unwrap();
+        let mut files = args(&["a"], &[]);
+        files.files_from = Some(list.to_str().unwrap().to_string());
+        files.exclude = vec!["*.tmp".to_string()];
+        assert_eq!(files.expand().unwrap(), ["a", "b", "c"]);
+
+        files.files_from = Some(dir.path().join("missing").to_str().unwrap().to_string());
+        let err = files.expand().unwrap_err();
+        assert!(err.starts_with("cannot read file list"), "{}", err);
+    }
+}
@@ -0,0 +1,247 @@
+/* hash: checksums of files in the coreutils format.
+ *
+ * Each line is "<hex digest>  <path>", as sha256sum, sha512sum and b3sum
+ * write and read them, so lists made by either can be checked by the
+ * other. As in coreutils, a path containing a backslash or a line break is
+ * written with those escaped and the line starts with a backslash to say
+ * so. --check also accepts the "*" binary-mode marker in place of the
+ * second space; it means nothing here. Files are read READ_CHUNK_SIZE bytes
+ * at a time, so memory use does not depend on their size. */
+use std::io::{self, BufRead, Write};
+
+use clap::ValueEnum;
+use crypto::digest::{digest_reader, DigestAlg};
+
+use crate::key::STDIN_PATH;
+
+/// The digests `hash` offers.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlg {
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl From<HashAlg> for DigestAlg {
+    fn from(alg: HashAlg) -> Self {
+        match alg {
+            HashAlg::Sha256 => DigestAlg::Sha256,
+            HashAlg::Sha512 => DigestAlg::Sha512,
+            HashAlg::Blake3 => DigestAlg::Blake3,
+        }
+    }
+}
+
+/// The hex digest of the file at `path`, or of standard input for "-".
+pub fn hash_path(alg: HashAlg, path: &str) -> io::Result<String> {
+    let digest = if path == STDIN_PATH {
+        digest_reader(alg.into(), io::stdin().lock())?
+    } else {
+        digest_reader(alg.into(), std::fs::File::open(path)?)?
+    };
+    Ok(digest
+        .as_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// One line of `hash` output, without the newline.
+pub fn sum_line(digest: &str, path: &str) -> String {
+    if path.contains(['\\', '\n', '\r']) {
+        let escaped = path
+            .replace('\\', "\\\\")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r");
+        format!("\\{}  {}", digest, escaped)
+    } else {
+        format!("{}  {}", digest, path)
+    }
+}
+
+/* The lowercase digest and the path from one line of a checksum list, or
+ * None if it is not a line `alg` could have produced */
+fn parse_sum_line(line: &str, alg: HashAlg) -> Option<(String, String)> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let hex_len = 2 * DigestAlg::from(alg).output_len();
+    let digest = line.get(..hex_len)?;
+    if !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let path = line[hex_len..]
+        .strip_prefix("  ")
+        .or_else(|| line[hex_len..].strip_prefix(" *"))?;
+    if path.is_empty() {
+        return None;
+    }
+    let path = if escaped {
+        unescape(path)?
+    } else {
+        path.to_string()
+    };
+    Some((digest.to_ascii_lowercase(), path))
+}
+
+fn unescape(path: &str) -> Option<String> {
+    let mut out = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => out.push('\\'),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// What `check_sums` found, line by line.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CheckSummary {
+    pub ok: usize,
+    /// Files whose digest differs from the list's.
+    pub failed: usize,
+    /// Files that could not be opened or read.
+    pub unreadable: usize,
+    /// Lines that are not "<digest>  <path>" for the algorithm.
+    pub malformed: usize,
+}
+
+impl CheckSummary {
+    /// True when every listed file was read and matched.
+    pub fn passed(&self) -> bool {
+        self.failed == 0 && self.unreadable == 0
+    }
+}
+
+/// Check each file named in the list `sums` against its digest, writing
+/// "<path>: OK" or "<path>: FAILED" to `out` for every line. `hash` gives
+/// the hex digest of a path (see `hash_path`).
+pub fn check_sums<R: BufRead, W: Write>(
+    mut sums: R,
+    alg: HashAlg,
+    out: &mut W,
+    mut hash: impl FnMut(&str) -> io::Result<String>,
+) -> io::Result<CheckSummary> {
+    let mut summary = CheckSummary::default();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if sums.read_until(b'\n', &mut line)? == 0 {
+            return Ok(summary);
+        }
+        if line.ends_with(b"\n") {
+            line.pop();
+        }
+        let parsed = std::str::from_utf8(&line)
+            .ok()
+            .and_then(|line| parse_sum_line(line, alg));
+        let Some((expected, path)) = parsed else {
+            summary.malformed += 1;
+            continue;
+        };
+        match hash(&path) {
+            Ok(digest) if digest == expected => {
+                summary.ok += 1;
+                writeln!(out, "{}: OK", path)?;
+            }
+            Ok(_) => {
+                summary.failed += 1;
+                writeln!(out, "{}: FAILED", path)?;
+            }
+            Err(_) => {
+                summary.unreadable += 1;
+                writeln!(out, "{}: FAILED open or read", path)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn lines_round_trip() {
+        for path in ["plain.txt", "with space", "back\\slash", "new\nline", "-"] {
+            let line = sum_line(ABC_SHA256, path);
+            assert_eq!(line.starts_with('\\'), path.contains(['\\', '\n']));
+            assert!(!line.contains('\n'));
+            assert_eq!(
+                parse_sum_line(&line, HashAlg::Sha256),
+                Some((ABC_SHA256.to_string(), path.to_string()))
+            );
+        }
+        assert_eq!(
+            sum_line(ABC_SHA256, "a\\b"),
+            format!("\\{}  a\\\\b", ABC_SHA256)
+        );
+    }
+
+    #[test]
+    fn parsing_follows_coreutils() {
+        let upper = ABC_SHA256.to_ascii_uppercase();
+        let binary = format!("{} *file", upper);
+        assert_eq!(
+            parse_sum_line(&binary, HashAlg::Sha256),
+            Some((ABC_SHA256.to_string(), "file".to_string()))
+        );
+        for bad in [
+            format!("{} file", ABC_SHA256),
+            format!("{}  ", ABC_SHA256),
+            format!("{}0  file", ABC_SHA256),
+            format!("{}  file", &ABC_SHA256[1..]),
+            format!("{}g  file", &ABC_SHA256[1..]),
+            format!("\\{}  bad\\escape", ABC_SHA256),
+            String::new(),
+        ] {
+            assert_eq!(parse_sum_line(&bad, HashAlg::Sha256), None, "{:?}", bad);
+        }
+        /* The digest length must suit the algorithm */
+        assert!(parse_sum_line(&format!("{}  f", ABC_SHA256), HashAlg::Sha512).is_none());
+    }
+
+    #[test]
+    fn check_counts_each_outcome() {
+        let sums = format!(
+            "{d}  good\n{d}  changed\n{d}  missing\nnot a checksum line\n{d}  good",
+            d = ABC_SHA256
+        );
+        let mut out = Vec::new();
+        let summary = check_sums(
+            sums.as_bytes(),
+            HashAlg::Sha256,
+            &mut out,
+            |path| match path {
+                "good" => Ok(ABC_SHA256.to_string()),
+                "changed" => Ok("00".repeat(32)),
+                _ => Err(io::ErrorKind::NotFound.into()),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            summary,
+            CheckSummary {
+                ok: 2,
+                failed: 1,
+                unreadable: 1,
+                malformed: 1,
+            }
+        );
+        assert!(!summary.passed());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "good: OK\nchanged: FAILED\nmissing: FAILED open or read\ngood: OK\n"
+        );
+    }
+}
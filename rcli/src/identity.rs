@@ -0,0 +1,231 @@
+/* identity new, and chacha's --recipient, --recipients-file and
+ * --identity: files encrypted to public keys rather than under a shared
+ * secret.
+ *
+ * A recipient string is RECIPIENT_PREFIX followed by the public key and a
+ * 4-byte checksum (the start of the key's SHA-256) in unpadded URL-safe
+ * base64, so a mistyped or cut-off recipient is caught while the
+ * arguments are parsed instead of producing a file nobody can open. The
+ * keys are X25519 + ML-KEM-768 hybrids, over 1600 characters encoded,
+ * which is too long for bech32.
+ *
+ * An identity file holds the secret key in the same form under
+ * IDENTITY_PREFIX, after a comment line with its recipient string. It is
+ * created readable only by its owner. */
+use std::path::Path;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::{DecodeError, Engine};
+use crypto::digest::{hash, HashAlgorithm};
+use crypto::{HybridPublicKey, HybridSecretKey, HYBRID_PUBLIC_KEY_LEN, HYBRID_SECRET_KEY_LEN};
+
+use crate::key::{warn_if_world_readable, write_key_file};
+
+/// Start of every recipient string.
+pub const RECIPIENT_PREFIX: &str = "rcli1";
+
+/// Start of the secret key line in an identity file.
+pub const IDENTITY_PREFIX: &str = "RCLI-IDENTITY-1";
+
+const CHECKSUM_LEN: usize = 4;
+
+fn checksum(bytes: &[u8]) -> [u8; CHECKSUM_LEN] {
+    hash(HashAlgorithm::Sha256, bytes).as_bytes()[..CHECKSUM_LEN]
+        .try_into()
+        .unwrap()
+}
+
+fn encode(prefix: &str, bytes: &[u8]) -> String {
+    let mut payload = bytes.to_vec();
+    payload.extend_from_slice(&checksum(bytes));
+    format!("{}{}", prefix, URL_SAFE_NO_PAD.encode(payload))
+}
+
+/* The `len` bytes after `prefix`, once the checksum matches. `what`
+ * names the string in errors; positions count from 1 over all of `text` */
+fn decode(text: &str, prefix: &str, len: usize, what: &str) -> Result<Vec<u8>, String> {
+    let encoded = text
+        .strip_prefix(prefix)
+        .ok_or_else(|| format!("{} must start with \"{}\"", what, prefix))?;
+    let mut payload = URL_SAFE_NO_PAD.decode(encoded).map_err(|e| match e {
+        DecodeError::InvalidByte(at, byte) => format!(
+            "{} has an invalid character '{}' at position {}",
+            what,
+            byte as char,
+            prefix.len() + at + 1
+        ),
+        _ => format!("{} is cut off or has extra characters", what),
+    })?;
+    if payload.len() != len + CHECKSUM_LEN {
+        return Err(format!("{} is cut off or has extra characters", what));
+    }
+    let sum = payload.split_off(len);
+    if sum != checksum(&payload) {
+        return Err(format!(
+            "{} checksum does not match; it was mistyped or altered",
+            what
+        ));
+    }
+    Ok(payload)
+}
+
+/// The recipient string others encrypt to.
+pub fn recipient_string(public: &HybridPublicKey) -> String {
+    encode(RECIPIENT_PREFIX, &public.to_bytes())
+}
+
+/// --recipient: a recipient string from `identity new`.
+pub fn parse_recipient(text: &str) -> Result<HybridPublicKey, String> {
+    let text = text.trim();
+    if text.starts_with(IDENTITY_PREFIX) {
+        return Err(
+            "that is an identity, which must stay secret; give the recipient string \
+             printed by `rcli identity new` (or in the identity file's comment)"
+                .to_string(),
+        );
+    }
+    if text.starts_with("age1") {
+        return Err(format!(
+            "age recipients are not supported; rcli recipients start with \"{}\"",
+            RECIPIENT_PREFIX
+        ));
+    }
+    let bytes = decode(text, RECIPIENT_PREFIX, HYBRID_PUBLIC_KEY_LEN, "recipient")?;
+    HybridPublicKey::from_bytes(&bytes)
+        .map_err(|_| "recipient is not a valid public key".to_string())
+}
+
+/// --recipients-file: one recipient string per line. Lines starting
+/// with "#" and blank lines are skipped.
+pub fn read_recipients(path: &Path) -> Result<Vec<HybridPublicKey>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read recipients file {}: {}", path.display(), e))?;
+    let mut recipients = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let recipient = parse_recipient(line)
+            .map_err(|e| format!("{} line {}: {}", path.display(), n + 1, e))?;
+        recipients.push(recipient);
+    }
+    if recipients.is_empty() {
+        return Err(format!("no recipients in {}", path.display()));
+    }
+    Ok(recipients)
+}
+
+/// The contents of an identity file for `secret`.
+pub fn identity_text(secret: &HybridSecretKey) -> String {
+    format!(
+        "# recipient: {}\n{}\n",
+        recipient_string(&secret.to_public()),
+        encode(IDENTITY_PREFIX, &secret.to_bytes()[..])
+    )
+}
+
+/// Write `secret` to a new identity file readable only by its owner, or
+/// replace one with `force`.
+pub fn write_identity(secret: &HybridSecretKey, path: &Path, force: bool) -> Result<(), String> {
+    write_key_file(path, identity_text(secret).as_bytes(), force)
+}
+
+/// Read an identity file written by `identity new`. Lines starting with
+/// "#" and blank lines are skipped.
+pub fn read_identity(path: &Path) -> Result<HybridSecretKey, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read identity {}: {}", path.display(), e))?;
+    warn_if_world_readable(path);
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    let (Some(line), None) = (lines.next(), lines.next()) else {
+        return Err(format!("{} is not an rcli identity file", path.display()));
+    };
+    let what = format!("identity in {}", path.display());
+    let bytes = decode(line, IDENTITY_PREFIX, HYBRID_SECRET_KEY_LEN, &what)?;
+    Ok(HybridSecretKey::from_bytes(&bytes.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::mlkem_generate;
+
+    #[test]
+    fn recipient_round_trip() {
+        let public = mlkem_generate().to_public();
+        let text = recipient_string(&public);
+        assert!(text.starts_with(RECIPIENT_PREFIX));
+        assert_eq!(parse_recipient(&text).unwrap(), public);
+        assert_eq!(parse_recipient(&format!(" {}\n", text)).unwrap(), public);
+    }
+
+    #[test]
+    fn recipient_errors_say_what_is_wrong() {
+        let text = recipient_string(&mlkem_generate().to_public());
+        let error = |text: &str| parse_recipient(text).unwrap_err();
+
+        assert!(error(&text[1..]).contains("must start with \"rcli1\""));
+        assert!(error("age1qqqq").contains("age recipients"));
+        assert!(error(&text[..text.len() - 10]).contains("cut off"));
+        assert!(error(&format!("{}AAAA", text)).contains("cut off"));
+        let bad = format!("{}!{}", &text[..20], &text[21..]);
+        assert!(error(&bad).contains("invalid character '!' at position 21"));
+
+        /* One changed character is caught by the checksum */
+        let at = text.len() / 2;
+        let swap = if &text[at..=at] == "A" { "B" } else { "A" };
+        let typo = format!("{}{}{}", &text[..at], swap, &text[at + 1..]);
+        assert!(error(&typo).contains("checksum does not match"));
+    }
+
+    #[test]
+    fn recipients_file_skips_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("team.txt");
+        let (a, b) = (mlkem_generate().to_public(), mlkem_generate().to_public());
+        let text = format!(
+            "# ops\n{}\n\n   \n  # alice\n  {}  \n",
+            recipient_string(&a),
+            recipient_string(&b)
+        );
+        std::fs::write(&path, text).unwrap();
+        assert_eq!(read_recipients(&path).unwrap(), vec![a, b]);
+
+        std::fs::write(&path, "# ops\n\nrcli1oops\n").unwrap();
+        let e = read_recipients(&path).unwrap_err();
+        assert!(e.contains("line 3: recipient is cut off"), "{}", e);
+        std::fs::write(&path, "# nobody yet\n").unwrap();
+        assert!(read_recipients(&path)
+            .unwrap_err()
+            .contains("no recipients"));
+    }
+
+    #[test]
+    fn identity_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("me.key");
+        let secret = mlkem_generate();
+        write_identity(&secret, &path, false).unwrap();
+        assert!(write_identity(&secret, &path, false).is_err());
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let recipient = recipient_string(&secret.to_public());
+        assert!(text.starts_with(&format!("# recipient: {}\n", recipient)));
+        let restored = read_identity(&path).unwrap();
+        assert_eq!(restored.to_public(), secret.to_public());
+
+        /* A recipient string is not an identity */
+        std::fs::write(&path, &recipient).unwrap();
+        assert!(read_identity(&path)
+            .unwrap_err()
+            .contains("must start with"));
+        std::fs::write(&path, "# nothing here\n").unwrap();
+        assert!(read_identity(&path)
+            .unwrap_err()
+            .contains("not an rcli identity file"));
+    }
+}
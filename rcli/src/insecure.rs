@@ -0,0 +1,134 @@
+/* rc4's warning and the acknowledgment it needs.
+ *
+ * RC4's keystream is biased and it has no integrity check, so rc4 is
+ * only for old data. Every run prints WARNING on standard error, and goes
+ * ahead only with --insecure-ok, with RCLI_ALLOW_RC4=1 in the environment
+ * (for automation), or when someone at the terminal types "yes".
+ * Otherwise it stops with a usage error that points at chacha. The answer
+ * is read from the terminal itself rather than standard input, which may
+ * be the data. */
+use std::io::{self, BufRead, Write};
+
+use crate::key::Prompter;
+use crate::status::{CliError, ErrorCode};
+
+/// Set to 1 to allow rc4 without --insecure-ok.
+pub const ALLOW_RC4_ENV: &str = "RCLI_ALLOW_RC4";
+
+/// Printed on standard error before rc4 does anything.
+pub const WARNING: &str = "\
+WARNING: RC4 is broken. Its keystream is biased, and nothing detects
+tampering, so it is not safe for real secrets. It is here for old data
+only; use `rcli chacha` for anything new.";
+
+/// How rc4 may go ahead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consent {
+    /// --insecure-ok or RCLI_ALLOW_RC4=1.
+    Given,
+    /// Ask someone at the terminal.
+    Ask,
+    Refused,
+}
+
+impl Consent {
+    /// The consent for --insecure-ok, the value of RCLI_ALLOW_RC4 and
+    /// whether standard error is a terminal.
+    pub fn decide(insecure_ok: bool, env: Option<&str>, stderr_is_tty: bool) -> Self {
+        if insecure_ok || env.is_some_and(|value| value.trim() == "1") {
+            Consent::Given
+        } else if stderr_is_tty {
+            Consent::Ask
+        } else {
+            Consent::Refused
+        }
+    }
+}
+
+fn refusal() -> CliError {
+    CliError::new(
+        ErrorCode::Usage,
+        format!(
+            "rc4 needs --insecure-ok (or {}=1) to run, as RC4 is not safe \
+             for real secrets; use `rcli chacha` instead",
+            ALLOW_RC4_ENV
+        ),
+    )
+}
+
+/// Print the warning, then go ahead only with consent: given, or a "yes"
+/// through `prompter`.
+pub fn acknowledge_rc4(consent: Consent, prompter: &mut dyn Prompter) -> Result<(), CliError> {
+    eprintln!("{}", WARNING);
+    match consent {
+        Consent::Given => Ok(()),
+        Consent::Refused => Err(refusal()),
+        Consent::Ask => {
+            let answer = prompter
+                .prompt("Type yes to continue: ")
+                .map_err(|e| CliError::new(ErrorCode::Io, e))?;
+            match answer.trim().eq_ignore_ascii_case("yes") {
+                true => Ok(()),
+                false => Err(refusal()),
+            }
+        }
+    }
+}
+
+/// Asks on the terminal, showing what is typed.
+pub struct TtyPrompter;
+
+impl Prompter for TtyPrompter {
+    fn prompt(&mut self, text: &str) -> Result<String, String> {
+        eprint!("{}", text);
+        io::stderr().flush().ok();
+        let mut line = String::new();
+        read_tty_line(&mut line).map_err(|e| format!("cannot read answer: {}", e))?;
+        Ok(line)
+    }
+}
+
+#[cfg(unix)]
+fn read_tty_line(line: &mut String) -> io::Result<usize> {
+    io::BufReader::new(std::fs::File::open("/dev/tty")?).read_line(line)
+}
+
+#[cfg(not(unix))]
+fn read_tty_line(line: &mut String) -> io::Result<usize> {
+    io::stdin().lock().read_line(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Answer(&'static str);
+
+    impl Prompter for Answer {
+        fn prompt(&mut self, _: &str) -> Result<String, String> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    #[test]
+    fn consent_from_flag_env_or_terminal() {
+        assert_eq!(Consent::decide(true, None, false), Consent::Given);
+        assert_eq!(Consent::decide(false, Some("1"), false), Consent::Given);
+        assert_eq!(Consent::decide(false, Some("0"), true), Consent::Ask);
+        assert_eq!(Consent::decide(false, Some("yes"), false), Consent::Refused);
+        assert_eq!(Consent::decide(false, None, true), Consent::Ask);
+        assert_eq!(Consent::decide(false, None, false), Consent::Refused);
+    }
+
+    #[test]
+    fn only_yes_goes_ahead() {
+        assert!(acknowledge_rc4(Consent::Ask, &mut Answer("yes\n")).is_ok());
+        assert!(acknowledge_rc4(Consent::Ask, &mut Answer("YES")).is_ok());
+        for answer in ["", "y", "no\n", "yes please"] {
+            let err = acknowledge_rc4(Consent::Ask, &mut Answer(answer)).unwrap_err();
+            assert_eq!(err.code, ErrorCode::Usage);
+            assert!(err.message.contains("rcli chacha"), "{}", err);
+        }
+        assert!(acknowledge_rc4(Consent::Refused, &mut Answer("yes")).is_err());
+    }
+}
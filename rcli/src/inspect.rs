@@ -0,0 +1,251 @@
+/* inspect: what an rcli file's header says, read without any key.
+ *
+ * Everything shown is in the clear in the header (and covered by every
+ * chunk's tag, so it cannot have been altered without decryption
+ * failing). The key check value and the associated data check value are
+ * reported as present or absent only. The JSON form carries a "schema"
+ * number, bumped whenever a field changes meaning or goes away; new
+ * fields may be added without one. */
+use std::io::{self, Cursor, Read};
+
+use crypto::fs::{self, FsCryptoError};
+use crypto::header::{FileHeader, PassphraseKdf, KDF_ARGON2ID};
+use crypto::stream::TAG_LEN;
+use crypto::{is_armored, ArmorReader, PaddingScheme, ARMOR_BEGIN};
+use serde_json::{json, Value};
+
+/// Version of the `--json` layout.
+pub const INSPECT_SCHEMA: u32 = 1;
+
+/// An rcli file's header and the size of what follows it.
+#[derive(Debug)]
+pub struct Inspection {
+    pub header: FileHeader,
+    pub armored: bool,
+    pub header_len: usize,
+    /// Bytes of sealed chunks after the header (decoded, if armored).
+    pub ciphertext_len: u64,
+}
+
+/// Read the header from `reader`, de-armoring it first if need be, and
+/// count the ciphertext after it.
+pub fn inspect<R: Read>(mut reader: R) -> Result<Inspection, FsCryptoError> {
+    let mut prefix = Vec::with_capacity(ARMOR_BEGIN.len());
+    (&mut reader)
+        .take(ARMOR_BEGIN.len() as u64)
+        .read_to_end(&mut prefix)?;
+    let armored = is_armored(&prefix);
+    let reader = Cursor::new(prefix).chain(reader);
+    let mut reader: Box<dyn Read> = if armored {
+        Box::new(ArmorReader::new(io::BufReader::new(reader))?)
+    } else {
+        Box::new(reader)
+    };
+
+    let (header, header_bytes) = fs::read_header(&mut reader)?;
+    let ciphertext_len = io::copy(&mut reader, &mut io::sink())?;
+    Ok(Inspection {
+        header,
+        armored,
+        header_len: header_bytes.len(),
+        ciphertext_len,
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn kdf_name(id: u8) -> String {
+    match id {
+        KDF_ARGON2ID => "argon2id".to_string(),
+        id => format!("unknown ({})", id),
+    }
+}
+
+/// "argon2id (memory 19456 KiB, 2 iterations, parallelism 1)"
+pub fn kdf_description(kdf: &PassphraseKdf) -> String {
+    format!(
+        "{} (memory {} KiB, {} iterations, parallelism {})",
+        kdf_name(kdf.kdf),
+        kdf.m_cost_kib,
+        kdf.t_cost,
+        kdf.p_cost
+    )
+}
+
+fn codec_name(id: u8) -> String {
+    match id {
+        1 => "zstd".to_string(),
+        2 => "deflate".to_string(),
+        id => format!("unknown ({})", id),
+    }
+}
+
+impl Inspection {
+    /// Sealed chunks after the header; a full chunk is the chunk size
+    /// plus its tag, and only the last may be shorter.
+    pub fn chunks(&self) -> u64 {
+        let sealed = u64::from(self.header.chunk_size) + TAG_LEN as u64;
+        self.ciphertext_len.div_ceil(sealed)
+    }
+
+    /// The `--json` form; see `INSPECT_SCHEMA`.
+    pub fn to_json(&self, file: &str) -> Value {
+        let header = &self.header;
+        let kdf = header.passphrase.map(|kdf| {
+            json!({
+                "name": kdf_name(kdf.kdf),
+                "memory_kib": kdf.m_cost_kib,
+                "iterations": kdf.t_cost,
+                "parallelism": kdf.p_cost,
+                "salt": hex(&kdf.salt),
+            })
+        });
+        let padding = header.padding.map(|scheme| match scheme {
+            PaddingScheme::Padme => json!({ "scheme": "padme" }),
+            PaddingScheme::Block(n) => json!({ "scheme": "block", "block_size": n }),
+        });
+        let recipient_slots: Vec<Value> = header
+            .recipients
+            .iter()
+            .map(|slot| json!({ "fingerprint": hex(&slot.fingerprint) }))
+            .collect();
+        json!({
+            "schema": INSPECT_SCHEMA,
+            "file": file,
+            "format_version": header.version,
+            "algorithm": header.algorithm.name(),
+            "armored": self.armored,
+            "chunk_size": header.chunk_size,
+            "nonce_prefix": hex(&header.nonce_prefix),
+            "kdf": kdf,
+            "aad_bound": header.aad_check.is_some(),
+            "key_check": header.key_check.is_some(),
+            "compression": header.compression.map(codec_name),
+            "original_length": header.original_len,
+            "ratchet": header.ratchet,
+            "padding": padding,
+            "recipient_slots": recipient_slots,
+            "header_length": self.header_len,
+            "ciphertext_length": self.ciphertext_len,
+            "chunks": self.chunks(),
+        })
+    }
+
+    /// The default, human-readable form.
+    pub fn to_text(&self, file: &str) -> String {
+        let header = &self.header;
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        let mut lines = vec![
+            ("File", file.to_string()),
+            ("Format version", header.version.to_string()),
+            ("Algorithm", header.algorithm.name().to_string()),
+            ("Armored", yes_no(self.armored).to_string()),
+            ("Chunk size", format!("{} bytes", header.chunk_size)),
+            ("Nonce prefix", hex(&header.nonce_prefix)),
+        ];
+        match header.passphrase {
+            Some(kdf) => {
+                lines.push(("Key derivation", kdf_description(&kdf)));
+                lines.push(("Salt", hex(&kdf.salt)));
+            }
+            None if !header.recipients.is_empty() => {
+                lines.push((
+                    "Key derivation",
+                    "none (key sealed to recipients, X25519 + ML-KEM-768)".to_string(),
+                ));
+                lines.push(("Recipient slots", header.recipients.len().to_string()));
+                for slot in &header.recipients {
+                    lines.push(("  Fingerprint", hex(&slot.fingerprint)));
+                }
+            }
+            None => lines.push(("Key derivation", "none (raw key)".to_string())),
+        }
+        lines.extend([
+            (
+                "Associated data",
+                yes_no(header.aad_check.is_some()).to_string(),
+            ),
+            (
+                "Key check value",
+                yes_no(header.key_check.is_some()).to_string(),
+            ),
+            (
+                "Compression",
+                match (header.compression, header.original_len) {
+                    (None, _) => "none".to_string(),
+                    (Some(id), None) => codec_name(id),
+                    (Some(id), Some(len)) => format!("{} ({} bytes before)", codec_name(id), len),
+                },
+            ),
+            ("Ratchet", yes_no(header.ratchet).to_string()),
+            (
+                "Padding",
+                match header.padding {
+                    None => "none".to_string(),
+                    Some(PaddingScheme::Padme) => "Padmé".to_string(),
+                    Some(PaddingScheme::Block(n)) => format!("{}-byte blocks", n),
+                },
+            ),
+            ("Header length", format!("{} bytes", self.header_len)),
+            (
+                "Ciphertext",
+                match self.chunks() {
+                    1 => format!("{} bytes in 1 chunk", self.ciphertext_len),
+                    n => format!("{} bytes in {} chunks", self.ciphertext_len, n),
+                },
+            ),
+        ]);
+        lines
+            .iter()
+            .map(|(label, value)| format!("{:<17}{}\n", format!("{}:", label), value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::fs::{encrypt_stream, EncryptOptions};
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    fn sealed(len: usize, opts: &EncryptOptions) -> Vec<u8> {
+        let mut out = Vec::new();
+        encrypt_stream(&mut &vec![0u8; len][..], &mut out, &KEY, opts).unwrap();
+        out
+    }
+
+    #[test]
+    fn sizes_and_chunks() {
+        let opts = EncryptOptions {
+            chunk_size: 1024,
+            ..EncryptOptions::default()
+        };
+        for (len, chunks) in [(0, 1), (1024, 1), (1025, 2), (3000, 3)] {
+            let file = sealed(len, &opts);
+            let found = inspect(&file[..]).unwrap();
+            assert_eq!(found.header_len + found.ciphertext_len as usize, file.len());
+            assert_eq!(found.chunks(), chunks, "{} bytes", len);
+            assert!(!found.armored);
+        }
+    }
+
+    #[test]
+    fn failures_map_to_statuses() {
+        use crate::{exit, status};
+        let status = |e: &FsCryptoError| exit::status(status::error_code(e));
+        let file = sealed(10, &EncryptOptions::default());
+        let bad_magic = inspect(&b"PK\x03\x04 not ours at all"[..]).unwrap_err();
+        assert_eq!(status(&bad_magic), exit::FORMAT);
+        let truncated = inspect(&file[..10]).unwrap_err();
+        assert_eq!(status(&truncated), exit::FORMAT);
+        let mut unsupported = file.clone();
+        unsupported[4] = 9;
+        assert_eq!(
+            status(&inspect(&unsupported[..]).unwrap_err()),
+            exit::FORMAT
+        );
+    }
+}
@@ -0,0 +1,248 @@
+/* Ctrl-C and SIGTERM.
+ *
+ * Files are only ever replaced by renaming a finished temporary file over
+ * them (see crypto::fs::rewrite_atomic), so the one thing an interrupt
+ * must not do is kill us with that temporary file still on disk. The
+ * handler therefore only raises a flag while a file is in progress;
+ * `InterruptReader` turns the flag into an error at the next read, which
+ * for chacha is the next chunk, and the usual error path removes the
+ * temporary file before `finish` reports and exits. With no file in
+ * progress (at a prompt, between files, or streaming standard input,
+ * where there is nothing to clean up) the handler exits straight away. A
+ * second signal exits at once whatever is going on. With --jobs several
+ * files are in progress together: none is started once interrupted, and
+ * the last to stop reports and exits. */
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::exit;
+use crate::status::{self, CliError, ErrorCode};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry::new());
+
+/// Which files are being written, which were stopped by the interrupt
+/// and how many have been written, for the closing message.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Registry {
+    active: Vec<String>,
+    stopped: Vec<String>,
+    completed: usize,
+}
+
+impl Registry {
+    pub const fn new() -> Self {
+        Registry {
+            active: Vec::new(),
+            stopped: Vec::new(),
+            completed: 0,
+        }
+    }
+
+    pub fn begin(&mut self, file: &str) {
+        self.active.push(file.to_string());
+    }
+
+    pub fn end(&mut self, file: &str, succeeded: bool) {
+        if let Some(at) = self.active.iter().position(|active| active == file) {
+            self.active.remove(at);
+        }
+        if succeeded {
+            self.completed += 1;
+        }
+    }
+
+    /* `file` failed because of the interrupt, so is left unchanged */
+    fn stop(&mut self, file: &str) {
+        self.end(file, false);
+        self.stopped.push(file.to_string());
+    }
+
+    pub fn active(&self) -> &[String] {
+        &self.active
+    }
+
+    /// What to tell the user when stopping.
+    pub fn message(&self) -> String {
+        let unchanged: Vec<&str> = self
+            .stopped
+            .iter()
+            .chain(&self.active)
+            .map(String::as_str)
+            .collect();
+        let left = match unchanged.is_empty() {
+            true => String::new(),
+            false => format!("; {} left unchanged", unchanged.join(", ")),
+        };
+        match self.completed {
+            0 => format!("Interrupted, no files modified{}", left),
+            1 => format!("Interrupted after 1 completed file{}", left),
+            n => format!("Interrupted after {} completed files{}", n, left),
+        }
+    }
+}
+
+/// Catch SIGINT and SIGTERM (Ctrl-C and Ctrl-Break on Windows). If that
+/// is not possible they keep their default, immediately fatal, effect.
+pub fn install() {
+    let _ = ctrlc::set_handler(|| {
+        let again = INTERRUPTED.swap(true, Ordering::SeqCst);
+        let registry = lock();
+        match registry.active() {
+            [] => {
+                report(&registry.message());
+                std::process::exit(exit::INTERRUPTED);
+            }
+            files if again => {
+                report(&format!(
+                    "{}; a temporary file may remain beside {}",
+                    registry.message(),
+                    files.join(", ")
+                ));
+                std::process::exit(exit::INTERRUPTED);
+            }
+            /* Each file stops at its next read */
+            _ => {}
+        }
+    });
+}
+
+/// Note that `file` is about to be written. Once interrupted, exits, or
+/// with other files still in progress refuses to start it.
+pub fn begin(file: &str) -> io::Result<()> {
+    let mut registry = lock();
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        if registry.active().is_empty() {
+            report(&registry.message());
+            std::process::exit(exit::INTERRUPTED);
+        }
+        return Err(io::Error::other("interrupted"));
+    }
+    registry.begin(file);
+    Ok(())
+}
+
+/// `file` is done with, one way or the other; exits if it was interrupted
+/// meanwhile and no other file is in progress. Its temporary file, if
+/// any, must already be gone.
+pub fn finish(file: &str, succeeded: bool) {
+    let mut registry = lock();
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        /* A file whose rename beat the interrupt counts as completed */
+        match succeeded {
+            true => registry.end(file, true),
+            false => registry.stop(file),
+        }
+        if registry.active().is_empty() {
+            report(&registry.message());
+            std::process::exit(exit::INTERRUPTED);
+        }
+        return;
+    }
+    registry.end(file, succeeded);
+}
+
+/* The closing message, or with --json an error record */
+fn report(message: &str) {
+    let failure = CliError::new(ErrorCode::Interrupted, message);
+    status::error(&failure, None, format_args!("{}", message));
+}
+
+/* A panic while holding the lock must not also stop Ctrl-C working */
+fn lock() -> std::sync::MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Fails every read once the process has been interrupted.
+pub struct InterruptReader<'a, R> {
+    inner: R,
+    flag: &'a AtomicBool,
+}
+
+impl<R: Read> InterruptReader<'static, R> {
+    pub fn new(inner: R) -> Self {
+        InterruptReader::with_flag(inner, &INTERRUPTED)
+    }
+}
+
+impl<'a, R: Read> InterruptReader<'a, R> {
+    fn with_flag(inner: R, flag: &'a AtomicBool) -> Self {
+        InterruptReader { inner, flag }
+    }
+}
+
+impl<R: Read> Read for InterruptReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        /* Not ErrorKind::Interrupted, which read_exact and friends retry */
+        if self.flag.load(Ordering::SeqCst) {
+            return Err(io::Error::other("interrupted"));
+        }
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_count_completed_files() {
+        let mut registry = Registry::new();
+        assert_eq!(registry.message(), "Interrupted, no files modified");
+
+        registry.begin("a.txt");
+        assert_eq!(
+            registry.message(),
+            "Interrupted, no files modified; a.txt left unchanged"
+        );
+        registry.end("a.txt", true);
+        assert!(registry.active().is_empty());
+        assert_eq!(registry.message(), "Interrupted after 1 completed file");
+
+        registry.begin("b.txt");
+        registry.end("b.txt", false);
+        registry.begin("c.txt");
+        registry.end("c.txt", true);
+        registry.begin("d.txt");
+        assert_eq!(
+            registry.message(),
+            "Interrupted after 2 completed files; d.txt left unchanged"
+        );
+    }
+
+    #[test]
+    fn several_files_at_once() {
+        let mut registry = Registry::new();
+        for file in ["a.txt", "b.txt", "c.txt"] {
+            registry.begin(file);
+        }
+        registry.end("b.txt", true);
+        assert_eq!(registry.active(), ["a.txt", "c.txt"]);
+        registry.stop("c.txt");
+        assert_eq!(
+            registry.message(),
+            "Interrupted after 1 completed file; c.txt, a.txt left unchanged"
+        );
+        registry.stop("a.txt");
+        assert!(registry.active().is_empty());
+        assert_eq!(
+            registry.message(),
+            "Interrupted after 1 completed file; c.txt, a.txt left unchanged"
+        );
+    }
+
+    #[test]
+    fn reader_stops_once_flagged() {
+        let flag = AtomicBool::new(false);
+        let mut reader = InterruptReader::with_flag(&b"abcdef"[..], &flag);
+        let mut buf = [0u8; 3];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"abc");
+
+        flag.store(true, Ordering::SeqCst);
+        let err = reader.read_exact(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_eq!(err.to_string(), "interrupted");
+    }
+}
@@ -0,0 +1,208 @@
+/* --jobs: independent files on several threads at once.
+ *
+ * Workers take the next file from a shared counter and send what they
+ * made of it down a channel. The main thread puts the results back in
+ * file order before handing each to `finish`, which does all the
+ * printing, so status lines, errors and the closing summary come out
+ * whole and in the same order whatever the thread count. A file's work
+ * therefore returns its status line as a `Report` to run later rather
+ * than printing it. Once `finish` says to stop (a failure without
+ * --keep-going) no new file is started, but those already under way are
+ * finished and reported. --jobs 1 runs everything on the main thread, as
+ * before.
+ *
+ * A single file gets the threads to itself instead: its chunks are sealed
+ * or opened several at once (see crypto::fs). Encryption takes the count
+ * in its options; decryption reads it here, set once from the command
+ * line as the decompression cap is. */
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+
+use crate::progress::{Batch, REDRAW_EVERY};
+use crate::status::CliError;
+
+/// What a finished file has left to print, and anything after that can
+/// still fail it (such as --shred); run on the main thread in file order.
+pub type Report = Box<dyn FnOnce() -> Result<(), CliError> + Send>;
+
+/// Parse --jobs: a thread count of at least 1.
+pub fn parse_count(text: &str) -> Result<usize, String> {
+    match text.parse::<usize>() {
+        Ok(0) => Err("--jobs must be at least 1".to_string()),
+        Ok(count) => Ok(count),
+        Err(_) => Err(format!("{} is not a number of jobs", text)),
+    }
+}
+
+/// --jobs when not given: one thread per core.
+pub fn default_count() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+static PER_FILE: AtomicUsize = AtomicUsize::new(1);
+
+/// Open each file's chunks on `count` threads from now on.
+pub fn set_per_file(count: usize) {
+    PER_FILE.store(count, Ordering::Relaxed);
+}
+
+/// Threads for one file's chunks: --jobs with a single file, else 1.
+pub fn per_file() -> usize {
+    PER_FILE.load(Ordering::Relaxed)
+}
+
+/// How many files to work on at once, and the bar they share.
+#[derive(Clone, Copy)]
+pub struct Jobs<'a> {
+    count: usize,
+    progress: Option<&'a Batch>,
+}
+
+impl<'a> Jobs<'a> {
+    pub fn new(count: usize, progress: Option<&'a Batch>) -> Self {
+        Jobs { count, progress }
+    }
+
+    /// One file at a time, on the calling thread.
+    pub fn one() -> Jobs<'static> {
+        Jobs::new(1, None)
+    }
+
+    /// The files come to `total` bytes, for the bar.
+    pub fn start(&self, total: u64) {
+        if let Some(batch) = self.progress {
+            batch.start(total);
+        }
+    }
+
+    /// `work` on every item, `finish` on each result in item order. When
+    /// `finish` returns false no further item is started.
+    pub fn run<T, R, W, F>(&self, items: &[T], work: W, mut finish: F)
+    where
+        T: Sync,
+        R: Send,
+        W: Fn(&T) -> R + Sync,
+        F: FnMut(&T, R) -> bool,
+    {
+        if self.count <= 1 {
+            for item in items {
+                let result = work(item);
+                if !finish(item, result) {
+                    break;
+                }
+            }
+            return;
+        }
+
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..self.count.min(items.len()) {
+                let (sender, next, stop, work) = (sender.clone(), &next, &stop, &work);
+                scope.spawn(move || {
+                    while !stop.load(Ordering::SeqCst) {
+                        let at = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(item) = items.get(at) else {
+                            break;
+                        };
+                        if sender.send((at, work(item))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            let mut pending = BTreeMap::new();
+            let (mut due, mut carry_on) = (0, true);
+            loop {
+                match receiver.recv_timeout(REDRAW_EVERY) {
+                    Ok((at, result)) => {
+                        pending.insert(at, result);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                while let Some(result) = pending.remove(&due) {
+                    self.clear();
+                    /* Files started before a stop are still reported */
+                    carry_on = finish(&items[due], result) && carry_on;
+                    stop.store(!carry_on, Ordering::SeqCst);
+                    due += 1;
+                }
+                if let Some(batch) = self.progress {
+                    batch.draw();
+                }
+            }
+        });
+        self.clear();
+    }
+
+    fn clear(&self) {
+        if let Some(batch) = self.progress {
+            batch.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn counts_parse() {
+        assert_eq!(parse_count("4"), Ok(4));
+        assert!(parse_count("0").is_err());
+        assert!(parse_count("-1").is_err());
+        assert!(parse_count("all").is_err());
+        assert!(default_count() >= 1);
+    }
+
+    #[test]
+    fn results_come_back_in_order() {
+        let items: Vec<u64> = (0..50).collect();
+        for count in [1, 4, 16] {
+            let mut seen = Vec::new();
+            Jobs::new(count, None).run(
+                &items,
+                |n| {
+                    /* Later items finish first */
+                    thread::sleep(Duration::from_micros(50 - n));
+                    n * 2
+                },
+                |n, doubled| {
+                    assert_eq!(*n * 2, doubled);
+                    seen.push(*n);
+                    true
+                },
+            );
+            assert_eq!(seen, items);
+        }
+    }
+
+    #[test]
+    fn stopping_starts_nothing_new() {
+        let items: Vec<usize> = (0..1000).collect();
+        let started = AtomicUsize::new(0);
+        let mut seen = Vec::new();
+        Jobs::new(4, None).run(
+            &items,
+            |_| {
+                started.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(1));
+            },
+            |n, ()| {
+                seen.push(*n);
+                *n != 10
+            },
+        );
+        let started = started.load(Ordering::SeqCst);
+        assert!(started < items.len(), "{}", started);
+        /* Everything started is reported, in order */
+        assert_eq!(seen, (0..started).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,140 @@
+/* --split and join: an encrypted file written as parts no larger than a
+ * transport allows, and put back together.
+ *
+ * The part format is crypto::split's. Splitting writes through
+ * fs::write_parts, so the parts only appear once all are written. join
+ * reads every part's header first and refuses to write anything if one
+ * is missing, given twice, from another split or cut short, naming the
+ * part; only then are the parts copied, in order, into --out through
+ * fs::write_output. Nothing is decrypted: the result is the file encrypt
+ * would have written without --split. */
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use crypto::fs::{self, FsCryptoError, Summary};
+use crypto::split::{self, SplitError, MIN_PART_SIZE};
+
+use crate::failure;
+use crate::status::{CliError, ErrorCode};
+
+/// Parse --split: a part size in bytes, with an optional K, M or G.
+pub fn parse_part_size(text: &str) -> Result<u64, String> {
+    let size = crate::bench::parse_size(text)? as u64;
+    match size {
+        size if size < MIN_PART_SIZE => Err(format!(
+            "parts must be at least {} bytes, not {}",
+            MIN_PART_SIZE, size
+        )),
+        size => Ok(size),
+    }
+}
+
+/// Join `parts`, given in any order, into `out`; an existing `out` is
+/// replaced only with `force`. Returns the summary and the part count.
+pub fn join(parts: &[PathBuf], out: &Path, force: bool) -> Result<(Summary, u32), CliError> {
+    let mut opened = Vec::with_capacity(parts.len());
+    for path in parts {
+        let file = File::open(path).map_err(|e| in_part(path, e.into()))?;
+        let len = file.metadata().map_err(|e| in_part(path, e.into()))?.len();
+        let mut reader = BufReader::new(file);
+        let header = split::read_part_header(&mut reader).map_err(|e| in_part(path, e))?;
+        opened.push(((header, len), reader));
+    }
+    let headers: Vec<_> = opened.iter().map(|(header, _)| *header).collect();
+    let order = split::order(&headers).map_err(|e| out_of_order(parts, &headers, e))?;
+
+    let mut readers: Vec<Option<BufReader<File>>> =
+        opened.into_iter().map(|(_, reader)| Some(reader)).collect();
+    let summary = fs::write_output(out, force, |writer| {
+        let mut summary = Summary::default();
+        for &position in &order {
+            let mut reader = readers[position].take().expect("each part once");
+            let copied = std::io::copy(&mut reader, writer)?;
+            summary.bytes_in += copied + split::PART_HEADER_LEN as u64;
+            summary.bytes_out += copied;
+        }
+        Ok(summary)
+    })
+    .map_err(failure)?;
+    Ok((summary, order.len() as u32))
+}
+
+/* A part that cannot be read or is not a part at all */
+fn in_part(path: &Path, e: SplitError) -> CliError {
+    match e {
+        SplitError::Io(e) => CliError::new(
+            ErrorCode::Io,
+            format!("{}: {}", path.display(), FsCryptoError::Io(e)),
+        ),
+        e => CliError::new(ErrorCode::Malformed, format!("{}: {}", path.display(), e)),
+    }
+}
+
+/* What is wrong with the set, naming the file where there is one */
+fn out_of_order(
+    parts: &[PathBuf],
+    headers: &[(split::PartHeader, u64)],
+    e: SplitError,
+) -> CliError {
+    let path_of = |index: u32| {
+        headers
+            .iter()
+            .position(|(header, _)| header.index == index)
+            .map(|position| parts[position].display().to_string())
+    };
+    let message = match &e {
+        SplitError::Missing { index, .. } => match base_name(&parts[0]) {
+            Some(base) => format!(
+                "{} (expected at {})",
+                e,
+                split::part_path(&base, *index).display()
+            ),
+            None => e.to_string(),
+        },
+        SplitError::Duplicate(index)
+        | SplitError::Truncated(index)
+        | SplitError::Unfinished(index)
+        | SplitError::Extra { index, .. } => match path_of(*index) {
+            Some(path) => format!("{}: {}", path, e),
+            None => e.to_string(),
+        },
+        _ => e.to_string(),
+    };
+    CliError::new(ErrorCode::Malformed, message)
+}
+
+/* "backup.enc" for "backup.enc.003", or None if `part` is not named as
+ * --split names parts */
+fn base_name(part: &Path) -> Option<PathBuf> {
+    let name = part.to_str()?;
+    let (base, number) = name.rsplit_once('.')?;
+    match number.len() >= 3 && number.bytes().all(|b| b.is_ascii_digit()) {
+        true => Some(PathBuf::from(base)),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn part_sizes_parse() {
+        assert_eq!(parse_part_size("4G"), Ok(4 << 30));
+        assert_eq!(parse_part_size("1024"), Ok(1024));
+        assert!(parse_part_size("1000").is_err());
+        assert!(parse_part_size("lots").is_err());
+    }
+
+    #[test]
+    fn base_names_come_from_part_names() {
+        assert_eq!(
+            base_name(Path::new("out/disk.img.enc.002")),
+            Some(PathBuf::from("out/disk.img.enc"))
+        );
+        assert_eq!(base_name(Path::new("a.1234")), Some(PathBuf::from("a")));
+        assert_eq!(base_name(Path::new("disk.img.enc")), None);
+        assert_eq!(base_name(Path::new("disk.01")), None);
+    }
+}
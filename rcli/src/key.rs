@@ -0,0 +1,982 @@
+/* Key input for the commands that take one: per-byte hex (--key), one hex
+ * string (--key-hex), base64 (--key-base64), a key file (--key-file), an
+ * environment variable (--key-env), a line of standard input
+ * (--key-stdin) or, for rc4 alone, text used as it is (--key-ascii).
+ * Exactly one may be given, and lengths are checked per algorithm. */
+use std::io::{BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use base64::alphabet::{Alphabet, STANDARD, URL_SAFE};
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::{DecodeError, Engine};
+use clap::{Args, ValueEnum};
+
+use crate::status::{self, CliError, ErrorCode};
+
+/* Key lengths each algorithm accepts */
+pub const RC4_KEY_LEN: KeyLen = KeyLen {
+    algorithm: "RC4",
+    min: 5,
+    max: 256,
+};
+pub const CHACHA_KEY_LEN: KeyLen = KeyLen {
+    algorithm: "ChaCha20-Poly1305",
+    min: 32,
+    max: 32,
+};
+/* HMAC takes any length, but RFC 2104 advises against keys much shorter
+ * than the output */
+pub const HMAC_KEY_LEN: KeyLen = KeyLen {
+    algorithm: "HMAC-SHA256",
+    min: 16,
+    max: 1024,
+};
+pub const BLAKE3_KEY_LEN: KeyLen = KeyLen {
+    algorithm: "BLAKE3 keyed",
+    min: 32,
+    max: 32,
+};
+/* What hmac's key options accept before --algorithm narrows it */
+const MAC_KEY_LEN: KeyLen = KeyLen {
+    algorithm: "MAC",
+    min: 16,
+    max: 1024,
+};
+
+pub struct KeyLen {
+    algorithm: &'static str,
+    min: usize,
+    max: usize,
+}
+
+impl KeyLen {
+    fn check(&self, len: usize) -> Result<(), String> {
+        if (self.min..=self.max).contains(&len) {
+            return Ok(());
+        }
+        let expected = if self.min == self.max {
+            format!("exactly {} bytes", self.min)
+        } else {
+            format!("{} to {} bytes", self.min, self.max)
+        };
+        Err(format!(
+            "{} keys must be {}, got {} bytes",
+            self.algorithm, expected, len
+        ))
+    }
+}
+
+/// A key already decoded and length-checked by its value parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBytes(pub Vec<u8>);
+
+/// Key options for RC4 (5 to 256 bytes).
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct Rc4Key {
+    /// En/Decryption key (hexadecimal bytes)
+    #[arg(short, long, value_name = "HEX_BYTE", num_args = 5..=256, value_parser = parse_hex_byte)]
+    key: Vec<u8>,
+
+    /// En/Decryption key as one hex string (5 to 256 bytes)
+    #[arg(long, value_name = "HEX", value_parser = |s: &str| parse_key_hex(s, &RC4_KEY_LEN))]
+    key_hex: Option<KeyBytes>,
+
+    /// En/Decryption key in base64 (standard or URL-safe, padding optional)
+    #[arg(long, value_name = "BASE64", value_parser = |s: &str| parse_key_base64(s, &RC4_KEY_LEN))]
+    key_base64: Option<KeyBytes>,
+
+    /// Read the key from a file (raw bytes or hex)
+    #[arg(long, value_name = "PATH")]
+    key_file: Option<PathBuf>,
+
+    /// Read the key from an environment variable (hex or base64)
+    #[arg(long, value_name = "VAR_NAME")]
+    key_env: Option<String>,
+
+    /// Read the key from one line of standard input (hex or base64)
+    #[arg(long)]
+    key_stdin: bool,
+
+    /// Use the UTF-8 bytes of a text password (5 to 256 of them) as the
+    /// key, as older RC4 tools did; it is visible to other users, so
+    /// prefer --key-stdin
+    #[arg(long, value_name = "STRING", value_parser = parse_key_ascii)]
+    key_ascii: Option<KeyBytes>,
+}
+
+impl Rc4Key {
+    /// Whether the key will be read from standard input.
+    pub fn reads_stdin(&self) -> bool {
+        self.key_stdin
+    }
+
+    /// The key, exiting with a message if it cannot be read. `file` is
+    /// the data the command will read, so the key and the data are never
+    /// both taken from standard input.
+    pub fn load(self, file: &str) -> Vec<u8> {
+        if self.key_ascii.is_some() {
+            eprintln!("{}", KEY_ASCII_WARNING);
+        }
+        let source = KeySource {
+            key: self.key,
+            parsed: self.key_hex.or(self.key_base64).or(self.key_ascii),
+            key_file: self.key_file,
+            key_env: self.key_env,
+            key_stdin: self.key_stdin,
+        };
+        source.load(file, &RC4_KEY_LEN)
+    }
+}
+
+/// Printed when rc4 is given --key-ascii.
+pub const KEY_ASCII_WARNING: &str = "\
+Warning: a key given with --key-ascii shows in process listings and shell
+history; --key-stdin (or --key-file) keeps it out of them";
+
+/// Key options for ChaCha20-Poly1305 (exactly 32 bytes).
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct ChachaKey {
+    /// 256-bit key (exactly 32 hexadecimal bytes)
+    #[arg(short, long, value_name = "HEX_BYTE", num_args = 32, value_parser = parse_hex_byte)]
+    key: Vec<u8>,
+
+    /// 256-bit key as one hex string (64 hex digits)
+    #[arg(long, value_name = "HEX", value_parser = |s: &str| parse_key_hex(s, &CHACHA_KEY_LEN))]
+    key_hex: Option<KeyBytes>,
+
+    /// 256-bit key in base64 (standard or URL-safe, padding optional)
+    #[arg(long, value_name = "BASE64", value_parser = |s: &str| parse_key_base64(s, &CHACHA_KEY_LEN))]
+    key_base64: Option<KeyBytes>,
+
+    /// Read the 256-bit key from a file (raw bytes or hex)
+    #[arg(long, value_name = "PATH")]
+    key_file: Option<PathBuf>,
+
+    /// Read the 256-bit key from an environment variable (hex or base64)
+    #[arg(long, value_name = "VAR_NAME")]
+    key_env: Option<String>,
+
+    /// Read the 256-bit key from one line of standard input (hex or base64)
+    #[arg(long)]
+    key_stdin: bool,
+}
+
+impl ChachaKey {
+    /// Whether the key will be read from standard input.
+    pub fn reads_stdin(&self) -> bool {
+        self.key_stdin
+    }
+
+    /// The key, exiting with a message if it cannot be read. `file` is
+    /// the data the command will read, so the key and the data are never
+    /// both taken from standard input.
+    pub fn load(self, file: &str) -> Vec<u8> {
+        let parsed = self.key_hex.or(self.key_base64);
+        /* With no key option, the config's key-file */
+        let given = !self.key.is_empty() || parsed.is_some() || self.key_env.is_some();
+        let key_file = match given || self.key_stdin {
+            true => self.key_file,
+            false => self
+                .key_file
+                .or_else(|| crate::config::get().settings().key_file.clone()),
+        };
+        let source = KeySource {
+            key: self.key,
+            parsed,
+            key_file,
+            key_env: self.key_env,
+            key_stdin: self.key_stdin,
+        };
+        source.load(file, &CHACHA_KEY_LEN)
+    }
+}
+
+/// Key options for hmac (16 to 1024 bytes, exactly 32 for BLAKE3).
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct MacKey {
+    /// MAC key (hexadecimal bytes)
+    #[arg(short, long, value_name = "HEX_BYTE", num_args = 16..=1024, value_parser = parse_hex_byte)]
+    key: Vec<u8>,
+
+    /// MAC key as one hex string
+    #[arg(long, value_name = "HEX", value_parser = |s: &str| parse_key_hex(s, &MAC_KEY_LEN))]
+    key_hex: Option<KeyBytes>,
+
+    /// MAC key in base64 (standard or URL-safe, padding optional)
+    #[arg(long, value_name = "BASE64", value_parser = |s: &str| parse_key_base64(s, &MAC_KEY_LEN))]
+    key_base64: Option<KeyBytes>,
+
+    /// Read the MAC key from a file (raw bytes or hex)
+    #[arg(long, value_name = "PATH")]
+    key_file: Option<PathBuf>,
+
+    /// Read the MAC key from an environment variable (hex or base64)
+    #[arg(long, value_name = "VAR_NAME")]
+    key_env: Option<String>,
+
+    /// Read the MAC key from one line of standard input (hex or base64)
+    #[arg(long)]
+    key_stdin: bool,
+}
+
+impl MacKey {
+    /// The key, checked against `len`, exiting with a message if it
+    /// cannot be read; see `ChachaKey::load`.
+    pub fn load(self, file: &str, len: &KeyLen) -> Vec<u8> {
+        let source = KeySource {
+            key: self.key,
+            parsed: self.key_hex.or(self.key_base64),
+            key_file: self.key_file,
+            key_env: self.key_env,
+            key_stdin: self.key_stdin,
+        };
+        source.load(file, len)
+    }
+}
+
+/// File name that stands for standard input.
+pub const STDIN_PATH: &str = "-";
+
+/* The key options of either algorithm, of which clap lets exactly one be
+ * set */
+struct KeySource {
+    key: Vec<u8>,
+    parsed: Option<KeyBytes>,
+    key_file: Option<PathBuf>,
+    key_env: Option<String>,
+    key_stdin: bool,
+}
+
+impl KeySource {
+    fn load(self, file: &str, len: &KeyLen) -> Vec<u8> {
+        /* The value parsers and --key's count already bound these, but
+         * hmac narrows the length once it knows the algorithm */
+        let checked = |key: Vec<u8>| len.check(key.len()).map(|()| key);
+        let loaded = if let Some(KeyBytes(key)) = self.parsed {
+            checked(key)
+        } else if let Some(path) = &self.key_file {
+            read_key_file(path, len)
+        } else if let Some(name) = &self.key_env {
+            read_key_env(name, len)
+        } else if self.key_stdin {
+            if file == STDIN_PATH {
+                Err(
+                    "--key-stdin cannot be used when the data is also read from standard input; \
+                     pass the key another way"
+                        .to_string(),
+                )
+            } else {
+                read_key_stdin(&mut std::io::stdin().lock(), len)
+            }
+        } else {
+            checked(self.key)
+        };
+        loaded.unwrap_or_else(|e| status::fail(CliError::new(ErrorCode::BadKey, e)))
+    }
+}
+
+/// What `keygen` makes a key for.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAlgorithm {
+    /// ChaCha20-Poly1305: exactly 32 bytes
+    Chacha,
+    /// RC4: 5 to 256 bytes, 16 unless --bytes says otherwise
+    Rc4,
+}
+
+impl KeyAlgorithm {
+    /// The key length to generate, given --bytes if any.
+    pub fn key_size(self, bytes: Option<u16>) -> Result<usize, String> {
+        let (len, default) = match self {
+            KeyAlgorithm::Chacha => (&CHACHA_KEY_LEN, 32),
+            KeyAlgorithm::Rc4 => (&RC4_KEY_LEN, 16),
+        };
+        let size = bytes.map_or(default, usize::from);
+        len.check(size)?;
+        Ok(size)
+    }
+}
+
+/// How `keygen` writes a key. Without --format it prints space-separated
+/// hex bytes, as --key takes them.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// One lowercase hex string, as --key-hex takes it
+    Hex,
+    /// Standard base64 with padding, as --key-base64 takes it
+    Base64,
+    /// The key bytes themselves, as --key-file takes them; only with
+    /// --out or when standard output is not a terminal
+    Raw,
+}
+
+/// `key` as `keygen` writes it: the text formats end in a newline, and
+/// no format at all means the original space-separated hex.
+pub fn format_key(key: &[u8], format: Option<KeyFormat>) -> Vec<u8> {
+    let hex = |sep: &str| {
+        let bytes: Vec<String> = key.iter().map(|b| format!("{:02x}", b)).collect();
+        bytes.join(sep)
+    };
+    let text = match format {
+        None => hex(" "),
+        Some(KeyFormat::Hex) => hex(""),
+        Some(KeyFormat::Base64) => base64::engine::general_purpose::STANDARD.encode(key),
+        Some(KeyFormat::Raw) => return key.to_vec(),
+    };
+    format!("{}\n", text).into_bytes()
+}
+
+/* keygen's checks on where the key goes: binary never to a terminal, and
+ * only formats --key-file reads to a file */
+pub fn check_key_output(
+    format: Option<KeyFormat>,
+    to_file: bool,
+    stdout_is_tty: bool,
+) -> Result<(), String> {
+    match format {
+        Some(KeyFormat::Raw) if !to_file && stdout_is_tty => Err(
+            "refusing to write a raw binary key to the terminal; use --out or redirect \
+             standard output"
+                .to_string(),
+        ),
+        Some(KeyFormat::Base64) if to_file => {
+            Err("key files hold hex or raw bytes; use --format hex or raw with --out".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A short fingerprint to tell keys apart by: the hex key check value,
+/// as rcli stores in file headers.
+pub fn key_fingerprint(key: &[u8]) -> String {
+    crypto::key_check_value(key)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/* keygen --out: `contents` (the formatted key) in a new file only the
+ * owner can read or write. With `force` an existing file is removed first
+ * rather than truncated, so it cannot pass on looser permissions; a
+ * symlink at `path` is replaced, never followed. */
+pub fn write_key_file(path: &Path, contents: &[u8], force: bool) -> Result<(), String> {
+    use std::io::Write;
+
+    let fail = |e: std::io::Error| format!("cannot write key file {}: {}", path.display(), e);
+    if force {
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.is_dir() => return Err(fail(std::io::ErrorKind::IsADirectory.into())),
+            Ok(_) => std::fs::remove_file(path).map_err(fail)?,
+            Err(_) => {}
+        }
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => format!(
+            "{} already exists; use --force to replace it",
+            path.display()
+        ),
+        _ => fail(e),
+    })?;
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(fail)
+}
+
+/* One byte of the per-byte --key form, e.g. "3f" or "0x3f" */
+fn parse_hex_byte(s: &str) -> Result<u8, String> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    if digits.is_empty() || digits.len() > 2 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not a hex byte", s));
+    }
+    u8::from_str_radix(digits, 16).map_err(|e| e.to_string())
+}
+
+/* Decode a contiguous hex string. A leading "0x" and any spaces or colons
+ * between digits are ignored; positions in errors count characters of the
+ * string as given, from 1 */
+fn parse_hex_string(s: &str) -> Result<Vec<u8>, String> {
+    let (offset, body) = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(rest) => (2, rest),
+        None => (0, s),
+    };
+    let mut digits = Vec::with_capacity(body.len());
+    for (i, c) in body.chars().enumerate() {
+        if c == ' ' || c == ':' {
+            continue;
+        }
+        let digit = c.to_digit(16).ok_or_else(|| {
+            format!(
+                "invalid hex character '{}' at position {}",
+                c,
+                offset + i + 1
+            )
+        })?;
+        digits.push(digit as u8);
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits ({})", digits.len()));
+    }
+    Ok(digits.chunks(2).map(|d| (d[0] << 4) | d[1]).collect())
+}
+
+/* --key-ascii: the text's UTF-8 bytes, unchanged, as the key */
+fn parse_key_ascii(s: &str) -> Result<KeyBytes, String> {
+    RC4_KEY_LEN
+        .check(s.len())
+        .map_err(|e| format!("{} of UTF-8 text", e))?;
+    Ok(KeyBytes(s.as_bytes().to_vec()))
+}
+
+fn parse_key_hex(s: &str, len: &KeyLen) -> Result<KeyBytes, String> {
+    let key = parse_hex_string(s)?;
+    len.check(key.len())?;
+    Ok(KeyBytes(key))
+}
+
+/* Decode base64 in either alphabet, picked by which of "+/" or "-_" it
+ * uses; trailing "=" padding is optional but must be right if present.
+ * Positions in errors count from 1 */
+fn parse_base64_string(s: &str) -> Result<Vec<u8>, String> {
+    let standard = s.find(['+', '/']);
+    let url_safe = s.find(['-', '_']);
+    let alphabet: &Alphabet = match (standard, url_safe) {
+        (Some(a), Some(b)) => {
+            let at = a.max(b);
+            return Err(format!(
+                "base64 mixes standard and URL-safe alphabets at position {}",
+                at + 1
+            ));
+        }
+        (None, Some(_)) => &URL_SAFE,
+        _ => &STANDARD,
+    };
+    /* Padding is optional, but if present it must be complete: the engine
+     * on its own would also accept a partial run such as "Zg=" */
+    if s.ends_with('=') && !s.len().is_multiple_of(4) {
+        return Err("invalid base64 padding".to_string());
+    }
+    let config =
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    GeneralPurpose::new(alphabet, config)
+        .decode(s)
+        .map_err(|e| match e {
+            DecodeError::InvalidByte(at, byte) => {
+                format!(
+                    "invalid base64 character '{}' at position {}",
+                    byte as char,
+                    at + 1
+                )
+            }
+            DecodeError::InvalidLastSymbol(at, byte) => format!(
+                "base64 character '{}' at position {} has bits set past the end of the data",
+                byte as char,
+                at + 1
+            ),
+            DecodeError::InvalidLength(symbols) => {
+                format!(
+                    "base64 cannot have {} symbols (one too many or one too few)",
+                    symbols
+                )
+            }
+            DecodeError::InvalidPadding => "invalid base64 padding".to_string(),
+        })
+}
+
+fn parse_key_base64(s: &str, len: &KeyLen) -> Result<KeyBytes, String> {
+    let key = parse_base64_string(s)?;
+    len.check(key.len())?;
+    Ok(KeyBytes(key))
+}
+
+/* A key file holds either the raw key or its hex encoding. It is read as
+ * hex if, after dropping trailing whitespace, it is nothing but hex digit
+ * pairs (optionally separated by whitespace, as `keygen` prints them);
+ * otherwise the bytes are taken as is. */
+pub fn read_key_file(path: &Path, len: &KeyLen) -> Result<Vec<u8>, String> {
+    let contents = std::fs::read(path)
+        .map_err(|e| format!("cannot read key file {}: {}", path.display(), e))?;
+    warn_if_world_readable(path);
+
+    let key = parse_hex_key_file(&contents).unwrap_or(contents);
+    len.check(key.len())
+        .map_err(|e| format!("key file {}: {}", path.display(), e))?;
+    Ok(key)
+}
+
+/* An environment variable holds the key as text (see `decode_key_text`).
+ * Errors name the variable but never quote any of its value. */
+pub fn read_key_env(name: &str, len: &KeyLen) -> Result<Vec<u8>, String> {
+    let value = match std::env::var(name) {
+        Ok(value) => value,
+        Err(std::env::VarError::NotPresent) => {
+            return Err(format!("environment variable {} is not set", name))
+        }
+        Err(std::env::VarError::NotUnicode(_)) => {
+            return Err(format!("environment variable {} is not valid UTF-8", name))
+        }
+    };
+    decode_key_text(&value, &format!("environment variable {}", name), len)
+}
+
+/* The first line of `input`, as key text. Nothing past the newline is
+ * read, and the line is never quoted in errors. */
+pub fn read_key_stdin(input: &mut impl BufRead, len: &KeyLen) -> Result<Vec<u8>, String> {
+    let mut line = Vec::new();
+    input
+        .read_until(b'\n', &mut line)
+        .map_err(|e| format!("cannot read key from standard input: {}", e))?;
+    let line = String::from_utf8(line)
+        .map_err(|_| "key on standard input is not valid UTF-8".to_string())?;
+    decode_key_text(&line, "standard input", len)
+}
+
+/// Where chacha --passphrase or --passphrase-file takes the passphrase
+/// from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PassphraseSource {
+    /// Ask through a `Prompter` (standard input is a terminal).
+    Prompt,
+    /// The first line of piped standard input.
+    Stdin,
+    File(PathBuf),
+}
+
+impl PassphraseSource {
+    /// Where --passphrase reads from: a prompt on a terminal, otherwise
+    /// the first line of standard input.
+    pub fn interactive() -> Self {
+        if std::io::stdin().is_terminal() {
+            PassphraseSource::Prompt
+        } else {
+            PassphraseSource::Stdin
+        }
+    }
+
+    /// Read the passphrase; `confirm` asks twice when prompting.
+    pub fn read(&self, prompter: &mut dyn Prompter, confirm: bool) -> Result<String, String> {
+        self.read_named(prompter, confirm, "passphrase")
+    }
+
+    /// As `read`, asking for `what` ("new passphrase") when prompting.
+    pub fn read_named(
+        &self,
+        prompter: &mut dyn Prompter,
+        confirm: bool,
+        what: &str,
+    ) -> Result<String, String> {
+        let passphrase = match self {
+            PassphraseSource::Prompt => prompt_passphrase(prompter, confirm, what)?,
+            PassphraseSource::Stdin => read_passphrase_stdin()?,
+            PassphraseSource::File(path) => return read_passphrase_file(path),
+        };
+        if passphrase.is_empty() {
+            return Err("passphrase is empty".to_string());
+        }
+        Ok(passphrase)
+    }
+}
+
+/// Asks the user for a secret without echoing it. The terminal is the
+/// only real implementation; tests script the answers.
+pub trait Prompter {
+    fn prompt(&mut self, text: &str) -> Result<String, String>;
+}
+
+/// Prompts on the controlling terminal.
+pub struct TerminalPrompter;
+
+impl Prompter for TerminalPrompter {
+    fn prompt(&mut self, text: &str) -> Result<String, String> {
+        rpassword::prompt_password(text).map_err(|e| format!("cannot read passphrase: {}", e))
+    }
+}
+
+/// Ask for a passphrase (`what`, as "Enter {what}: "), and with
+/// `confirm` ask again and fail unless both entries match.
+pub fn prompt_passphrase(
+    prompter: &mut dyn Prompter,
+    confirm: bool,
+    what: &str,
+) -> Result<String, String> {
+    let first = prompter.prompt(&format!("Enter {}: ", what))?;
+    if confirm && !first.is_empty() && prompter.prompt(&format!("Confirm {}: ", what))? != first {
+        return Err("passphrases do not match".to_string());
+    }
+    Ok(first)
+}
+
+fn read_passphrase_stdin() -> Result<String, String> {
+    let mut line = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| format!("cannot read passphrase from standard input: {}", e))?;
+    Ok(line.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// The first line of a passphrase file. Only the line ending is removed,
+/// so leading or trailing spaces are part of the passphrase.
+pub fn read_passphrase_file(path: &Path) -> Result<String, String> {
+    let contents = std::fs::read(path)
+        .map_err(|e| format!("cannot read passphrase file {}: {}", path.display(), e))?;
+    warn_if_not_private(path);
+
+    let line = contents.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.is_empty() {
+        return Err(format!("passphrase file {} is empty", path.display()));
+    }
+    String::from_utf8(line.to_vec())
+        .map_err(|_| format!("passphrase file {} is not valid UTF-8", path.display()))
+}
+
+/* Key text is hex if, after an optional "0x", it is made only of hex
+ * digits, spaces and colons; otherwise it is base64. Surrounding
+ * whitespace is dropped. Errors say where the text came from (`source`)
+ * but, as it is secret, never quote it, not even a bad character. */
+fn decode_key_text(text: &str, source: &str, len: &KeyLen) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(format!("{} is empty", source));
+    }
+
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    let is_hex = digits
+        .chars()
+        .all(|c| c.is_ascii_hexdigit() || c == ' ' || c == ':');
+    let key = if is_hex {
+        parse_hex_string(text).map_err(|_| format!("{} is not valid hex", source))?
+    } else {
+        parse_base64_string(text).map_err(|_| format!("{} is not valid hex or base64", source))?
+    };
+    len.check(key.len())
+        .map_err(|e| format!("{}: {}", source, e))?;
+    Ok(key)
+}
+
+fn parse_hex_key_file(contents: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(contents).ok()?;
+    let digits: String = text.split_ascii_whitespace().collect();
+    let is_hex = digits.bytes().all(|b| b.is_ascii_hexdigit());
+    if !is_hex || digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Warn on standard error if any user can read the key file at `path`.
+#[cfg(unix)]
+pub fn warn_if_world_readable(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Ok(meta) = std::fs::metadata(path) {
+        if meta.permissions().mode() & 0o004 != 0 {
+            eprintln!(
+                "Warning: key file {} is readable by all users (chmod 600 it)",
+                path.display()
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn warn_if_world_readable(_path: &Path) {}
+
+#[cfg(unix)]
+fn warn_if_not_private(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Ok(meta) = std::fs::metadata(path) {
+        let mode = meta.permissions().mode() & 0o777;
+        if mode & !0o600 != 0 {
+            eprintln!(
+                "Warning: passphrase file {} has mode {:o}, broader than 600 (chmod 600 it)",
+                path.display(),
+                mode
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_not_private(_path: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn key_file(contents: &[u8]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents).unwrap();
+        file
+    }
+
+    #[test]
+    fn raw_and_hex_key_files() {
+        let key: Vec<u8> = (0..32).map(|i| i * 7 + 1).collect();
+        let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        let spaced: Vec<String> = key.iter().map(|b| format!("{:02X}", b)).collect();
+
+        for contents in [
+            key.clone(),
+            hex.clone().into_bytes(),
+            format!("{}\n", hex).into_bytes(),
+            format!("{}\r\n", hex).into_bytes(),
+            format!("{}\n", spaced.join(" ")).into_bytes(),
+        ] {
+            let file = key_file(&contents);
+            assert_eq!(read_key_file(file.path(), &CHACHA_KEY_LEN), Ok(key.clone()));
+        }
+
+        /* A raw key that happens to end in a newline byte keeps it */
+        let mut raw = key.clone();
+        raw[31] = b'\n';
+        let file = key_file(&raw);
+        assert_eq!(read_key_file(file.path(), &CHACHA_KEY_LEN), Ok(raw));
+
+        /* RC4 takes any length from 5 to 256 */
+        let file = key_file(b"0102030405\n");
+        assert_eq!(
+            read_key_file(file.path(), &RC4_KEY_LEN),
+            Ok(vec![1, 2, 3, 4, 5])
+        );
+    }
+
+    #[test]
+    fn wrong_length_names_expected_length() {
+        let file = key_file(&[0x55; 31]);
+        let err = read_key_file(file.path(), &CHACHA_KEY_LEN).unwrap_err();
+        assert!(
+            err.ends_with("ChaCha20-Poly1305 keys must be exactly 32 bytes, got 31 bytes"),
+            "{}",
+            err
+        );
+        let file = key_file(b"01020304");
+        let err = read_key_file(file.path(), &RC4_KEY_LEN).unwrap_err();
+        assert!(
+            err.ends_with("RC4 keys must be 5 to 256 bytes, got 4 bytes"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn missing_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("no-such-key");
+        let err = read_key_file(&path, &CHACHA_KEY_LEN).unwrap_err();
+        assert!(err.starts_with("cannot read key file "), "{}", err);
+        assert!(err.contains("no-such-key"), "{}", err);
+    }
+
+    #[test]
+    fn hex_strings() {
+        assert_eq!(
+            parse_hex_string("deadBEEF"),
+            Ok(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(
+            parse_hex_string("0xdead beef"),
+            Ok(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(
+            parse_hex_string("de:ad:be:ef"),
+            Ok(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(parse_hex_string(""), Ok(vec![]));
+        assert_eq!(
+            parse_hex_string("dead-beef"),
+            Err("invalid hex character '-' at position 5".to_string())
+        );
+        assert_eq!(
+            parse_hex_string("0x00g0"),
+            Err("invalid hex character 'g' at position 5".to_string())
+        );
+        assert_eq!(
+            parse_hex_string("abc"),
+            Err("odd number of hex digits (3)".to_string())
+        );
+    }
+
+    // RFC 4648 section 10 test vectors, plus the URL-safe alphabet
+    #[test]
+    fn base64_strings() {
+        for (encoded, decoded) in [
+            ("", ""),
+            ("Zg==", "f"),
+            ("Zg", "f"),
+            ("Zm8=", "fo"),
+            ("Zm8", "fo"),
+            ("Zm9v", "foo"),
+            ("Zm9vYg==", "foob"),
+            ("Zm9vYmE=", "fooba"),
+            ("Zm9vYmFy", "foobar"),
+        ] {
+            assert_eq!(
+                parse_base64_string(encoded),
+                Ok(decoded.as_bytes().to_vec()),
+                "{}",
+                encoded
+            );
+        }
+        assert_eq!(parse_base64_string("-_8="), Ok(vec![0xfb, 0xff]));
+        assert_eq!(parse_base64_string("+/8"), Ok(vec![0xfb, 0xff]));
+    }
+
+    #[test]
+    fn base64_errors() {
+        for (encoded, error) in [
+            ("Zm9v!mFy", "invalid base64 character '!' at position 5"),
+            ("Zm9vYg=", "invalid base64 padding"),
+            ("Zm9vYg===", "invalid base64 padding"),
+            ("Zm9vYg==Zg==", "invalid base64 character '=' at position 7"),
+            ("Zm=9", "invalid base64 character '=' at position 3"),
+            (
+                "Zh==",
+                "base64 character 'h' at position 2 has bits set past the end of the data",
+            ),
+            (
+                "Zm9vY",
+                "base64 cannot have 5 symbols (one too many or one too few)",
+            ),
+            (
+                "+/-_",
+                "base64 mixes standard and URL-safe alphabets at position 3",
+            ),
+        ] {
+            assert_eq!(
+                parse_base64_string(encoded),
+                Err(error.to_string()),
+                "{}",
+                encoded
+            );
+        }
+    }
+
+    #[test]
+    fn base64_keys_checked_per_algorithm() {
+        let key31 = base64::engine::general_purpose::STANDARD.encode([7; 31]);
+        assert_eq!(
+            parse_key_base64(&key31, &CHACHA_KEY_LEN),
+            Err("ChaCha20-Poly1305 keys must be exactly 32 bytes, got 31 bytes".to_string())
+        );
+        assert_eq!(
+            parse_key_base64(&key31, &RC4_KEY_LEN),
+            Ok(KeyBytes(vec![7; 31]))
+        );
+        let key32 = base64::engine::general_purpose::STANDARD.encode([7; 32]);
+        assert_eq!(key32.len(), 44);
+        assert_eq!(
+            parse_key_base64(&key32, &CHACHA_KEY_LEN),
+            Ok(KeyBytes(vec![7; 32]))
+        );
+        assert_eq!(
+            parse_key_base64(key32.trim_end_matches('='), &CHACHA_KEY_LEN),
+            Ok(KeyBytes(vec![7; 32]))
+        );
+    }
+
+    #[test]
+    fn passphrase_file_first_line() {
+        for (contents, expected) in [
+            (&b"hunter2"[..], "hunter2"),
+            (b"hunter2\n", "hunter2"),
+            (b"hunter2\r\nsecond line\n", "hunter2"),
+            (b"  spaced out  \n", "  spaced out  "),
+        ] {
+            let file = key_file(contents);
+            assert_eq!(read_passphrase_file(file.path()), Ok(expected.to_string()));
+        }
+        for contents in [&b""[..], b"\n", b"\r\nhunter2\n"] {
+            let file = key_file(contents);
+            let err = read_passphrase_file(file.path()).unwrap_err();
+            assert!(err.ends_with("is empty"), "{}", err);
+        }
+        let file = key_file(b"\xff\xfe\n");
+        let err = read_passphrase_file(file.path()).unwrap_err();
+        assert!(err.ends_with("is not valid UTF-8"), "{}", err);
+    }
+
+    #[test]
+    fn key_stdin_reads_one_line() {
+        let mut input = std::io::Cursor::new(&b"AQIDBAU=\r\nfile data"[..]);
+        assert_eq!(
+            read_key_stdin(&mut input, &RC4_KEY_LEN),
+            Ok(vec![1, 2, 3, 4, 5])
+        );
+        assert_eq!(input.position(), 10);
+
+        let mut input = std::io::Cursor::new(&b"01 02 03 04 05"[..]);
+        assert_eq!(
+            read_key_stdin(&mut input, &RC4_KEY_LEN),
+            Ok(vec![1, 2, 3, 4, 5])
+        );
+
+        for (text, error) in [
+            (&b""[..], "standard input is empty"),
+            (b"\n0102030405\n", "standard input is empty"),
+            (
+                b"0102030405\n",
+                "standard input: ChaCha20-Poly1305 keys must be exactly 32 bytes, got 5 bytes",
+            ),
+            (b"01020304!5\n", "standard input is not valid hex or base64"),
+            (b"\xff\n", "key on standard input is not valid UTF-8"),
+        ] {
+            let mut input = std::io::Cursor::new(text);
+            assert_eq!(
+                read_key_stdin(&mut input, &CHACHA_KEY_LEN),
+                Err(error.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn keygen_formats() {
+        let key = [0xab, 0x01, 0xff, 0x10, 0x00];
+        assert_eq!(format_key(&key, None), b"ab 01 ff 10 00\n");
+        assert_eq!(format_key(&key, Some(KeyFormat::Hex)), b"ab01ff1000\n");
+        assert_eq!(format_key(&key, Some(KeyFormat::Base64)), b"qwH/EAA=\n");
+        assert_eq!(format_key(&key, Some(KeyFormat::Raw)), key);
+    }
+
+    #[test]
+    fn raw_keys_never_reach_a_terminal() {
+        let raw = Some(KeyFormat::Raw);
+        assert!(check_key_output(raw, false, true)
+            .unwrap_err()
+            .contains("refusing to write a raw binary key to the terminal"));
+        assert_eq!(check_key_output(raw, false, false), Ok(()));
+        assert_eq!(check_key_output(raw, true, true), Ok(()));
+        for format in [None, Some(KeyFormat::Hex), Some(KeyFormat::Base64)] {
+            assert_eq!(check_key_output(format, false, true), Ok(()));
+        }
+        /* Key files are hex or raw */
+        assert!(check_key_output(Some(KeyFormat::Base64), true, false).is_err());
+    }
+
+    #[test]
+    fn keygen_sizes_per_algorithm() {
+        assert_eq!(KeyAlgorithm::Chacha.key_size(None), Ok(32));
+        assert_eq!(KeyAlgorithm::Chacha.key_size(Some(32)), Ok(32));
+        assert_eq!(
+            KeyAlgorithm::Chacha.key_size(Some(16)),
+            Err("ChaCha20-Poly1305 keys must be exactly 32 bytes, got 16 bytes".to_string())
+        );
+        assert_eq!(KeyAlgorithm::Rc4.key_size(None), Ok(16));
+        assert_eq!(KeyAlgorithm::Rc4.key_size(Some(5)), Ok(5));
+        assert_eq!(KeyAlgorithm::Rc4.key_size(Some(256)), Ok(256));
+        assert!(KeyAlgorithm::Rc4.key_size(Some(4)).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+/* --quiet and --verbose: how much rcli says besides its results.
+ *
+ * Status lines ("Encrypted notes.txt") go to standard output by default
+ * and nowhere with --quiet, which also drops notes such as "Wrote ...".
+ * Errors and warnings are shown at every level, as is whatever a command
+ * was asked to produce (a key, a digest, inspect's report). -v adds a
+ * line per file on standard error with its sizes, chunk count and time;
+ * -vv adds what each header says and the KDF parameters.
+ *
+ * Nothing logged here may carry key material, passphrases or plaintext:
+ * only sizes, counts, timings and the header's public fields. */
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much to say, least first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Quiet,
+    Normal,
+    Verbose,
+    Debug,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+impl Level {
+    /// The level for --quiet and the number of -v flags.
+    pub fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Level::Quiet,
+            (_, 0) => Level::Normal,
+            (_, 1) => Level::Verbose,
+            _ => Level::Debug,
+        }
+    }
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::SeqCst);
+}
+
+/// Whether messages at `level` are shown.
+pub fn enabled(level: Level) -> bool {
+    LEVEL.load(Ordering::SeqCst) >= level as u8
+}
+
+/// Print a status line or note on standard output, unless quiet.
+pub fn status(args: fmt::Arguments) {
+    if enabled(Level::Normal) {
+        println!("{}", args);
+    }
+}
+
+/// Print a note on standard error, unless quiet.
+pub fn note(args: fmt::Arguments) {
+    if enabled(Level::Normal) {
+        eprintln!("{}", args);
+    }
+}
+
+/// Print a detail on standard error with -v.
+pub fn verbose(args: fmt::Arguments) {
+    if enabled(Level::Verbose) {
+        eprintln!("{}", args);
+    }
+}
+
+/// Print a detail on standard error with -vv.
+pub fn debug(args: fmt::Arguments) {
+    if enabled(Level::Debug) {
+        eprintln!("{}", args);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_from_flags() {
+        assert_eq!(Level::from_flags(false, 0), Level::Normal);
+        assert_eq!(Level::from_flags(true, 0), Level::Quiet);
+        assert_eq!(Level::from_flags(false, 1), Level::Verbose);
+        assert_eq!(Level::from_flags(false, 2), Level::Debug);
+        assert_eq!(Level::from_flags(false, 5), Level::Debug);
+        assert!(Level::Quiet < Level::Normal && Level::Verbose < Level::Debug);
+    }
+}
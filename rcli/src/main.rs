@@ -1,138 +1,3578 @@
-use clap::{Parser, Subcommand};
-use crypto::{chacha20_poly1305_cipher, generate_key, generate_nonce, Rc4};
+use clap::{
+    ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, ValueHint,
+};
+use clap_complete::Shell;
+use compress::Compression;
+use crypto::fs::{self, EncryptOptions, FsCryptoError, Summary};
+use crypto::header::{
+    Algorithm, FileHeader, HeaderError, PassphraseKdf, DEFAULT_CHUNK_SIZE, MAGIC, MAX_CHUNK_SIZE,
+    MIN_CHUNK_SIZE,
+};
+use crypto::split;
+use crypto::{
+    chacha20_poly1305_cipher, decrypt_stream_with_identity, derive_file_key,
+    encrypt_stream_to_recipients, generate_key, is_armored, passphrase_kdf, recipient_fingerprint,
+    AadBuilder, Argon2Params, ArmorError, ArmorReader, ArmorWriter, CompressError, ErrorStates,
+    HybridPublicKey, HybridSecretKey, KeyfileError, PaddingScheme, Rc4, RecipientError,
+    SecretKey32, ARMOR_BEGIN, MAX_AAD_FIELDS, MAX_RECIPIENTS,
+};
+use std::cell::Cell;
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::prelude::{Read, Seek, Write};
+use std::io::prelude::{Read, Write};
+use std::io::{BufReader, BufWriter, Cursor, IsTerminal};
+use std::time::{Duration, Instant};
+
+mod archive;
+mod backup;
+mod bench;
+mod clobber;
+mod completions;
+mod compress;
+mod config;
+mod examples;
+mod exit;
+mod files;
+mod hash;
+mod hmac;
+mod identity;
+mod insecure;
+mod inspect;
+mod interrupt;
+mod jobs;
+mod join;
+mod key;
+mod log;
+mod mangen;
+mod meter;
+mod mmap;
+mod names;
+mod nonce_log;
+mod progress;
+mod random;
+mod recursive;
+mod rekey;
+mod sign;
+mod status;
+use files::FileArgs;
+use hash::HashAlg;
+use hmac::MacAlg;
+use insecure::Consent;
+use interrupt::InterruptReader;
+use jobs::{Jobs, Report};
+use key::{
+    check_key_output, format_key, key_fingerprint, write_key_file, ChachaKey, KeyAlgorithm,
+    KeyFormat, MacKey, PassphraseSource, Prompter, Rc4Key, TerminalPrompter, STDIN_PATH,
+};
+use log::Level;
+use meter::{Meter, MeteredReader, MeteredWriter, Stats};
+use nonce_log::{NonceLog, SystemEntropy};
+use progress::{Batch, ByteCount, Progress, ProgressMode, ProgressReader, TerminalProgress};
+use random::{check_rand_output, open_output, write_random, RandFormat};
+use recursive::Outcome;
+use sign::PrehashArg;
+use status::{Action, CliError, ErrorCode, Record};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 
 #[derive(Parser, Debug)]
+#[command(name = "rcli", version, about, after_help = examples::RCLI)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Print status as JSON lines, one object per file, in place of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Print only errors and warnings, besides what the command produces
+    /// (keys, digests, decrypted data)
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Say more on standard error: each file's sizes and time, and with
+    /// -vv also header fields and key derivation parameters
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Never replace an existing output file, even with --force; each
+    /// file that would fails. Otherwise rcli asks before replacing one
+    /// (without --force) when standard input is a terminal
+    #[arg(long, global = true)]
+    no_clobber: bool,
+
+    /// Read defaults from this TOML file instead of $RCLI_CONFIG or
+    /// ~/.config/rcli/config.toml; see `rcli config show`
+    #[arg(long, global = true, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    config: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// RC4 file en/decryption (symmetric — same operation for encrypt and decrypt)
+    #[command(after_help = examples::RC4)]
     Rc4 {
-        #[arg(short, long, required = true, value_name = "FILE_NAME")]
-        file: String,
+        #[command(flatten)]
+        files: FileArgs,
+
+        #[command(flatten)]
+        key: Rc4Key,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Use RC4 even though it is broken (or set RCLI_ALLOW_RC4=1);
+        /// otherwise rc4 asks on a terminal and refuses elsewhere
+        #[arg(long)]
+        insecure_ok: bool,
+
+        /// Change each file where it lies through a memory map, rather
+        /// than writing a new copy and renaming it over the file. A crash
+        /// part way leaves the file part processed. Files that cannot be
+        /// mapped are processed as usual
+        #[arg(long, conflicts_with = "output")]
+        mmap: bool,
+    },
+
+    /// Generate a random key for ChaCha20-Poly1305 (32 bytes) or RC4 (16
+    /// bytes by default)
+    #[command(after_help = examples::KEYGEN)]
+    Keygen {
+        /// The cipher the key is for
+        #[arg(long, value_enum, default_value_t = KeyAlgorithm::Chacha)]
+        algorithm: KeyAlgorithm,
+
+        /// Key length in bytes: 5 to 256 for rc4 (default 16); chacha keys
+        /// are exactly 32
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(5..=256))]
+        bytes: Option<u16>,
+
+        /// Output encoding [default: space-separated hex bytes, as --key
+        /// takes them]
+        #[arg(long, value_enum)]
+        format: Option<KeyFormat>,
+
+        /// Write the key to a new file readable only by you, for
+        /// --key-file (hex unless --format raw), and print just its
+        /// fingerprint
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
+
+        /// Replace the --out file if it already exists
+        #[arg(long, requires = "out")]
+        force: bool,
+
+        /// Print a diceware passphrase of N words (default 6) from the EFF
+        /// large wordlist instead of a key, for --passphrase; its entropy
+        /// goes to standard error
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "6",
+              value_parser = clap::value_parser!(u16).range(1..=64),
+              conflicts_with_all = ["algorithm", "bytes", "format", "out"])]
+        words: Option<u16>,
+
+        /// Text between passphrase words
+        #[arg(long, value_name = "TEXT", default_value = "-", requires = "words")]
+        separator: String,
+    },
+
+    /// Generate random passwords of letters and digits, with at least one
+    /// character from each enabled class
+    #[command(after_help = examples::PWGEN)]
+    Pwgen {
+        /// Characters per password
+        #[arg(long, value_name = "N", default_value_t = 20,
+              value_parser = clap::value_parser!(u16).range(1..=1024))]
+        length: u16,
+
+        /// Leave out lowercase letters
+        #[arg(long)]
+        no_lower: bool,
+
+        /// Leave out uppercase letters
+        #[arg(long)]
+        no_upper: bool,
+
+        /// Leave out digits
+        #[arg(long)]
+        no_digits: bool,
+
+        /// Include ASCII punctuation
+        #[arg(long)]
+        symbols: bool,
+
+        /// Leave out characters easily mistaken for one another (0 O 1 l I)
+        #[arg(long)]
+        no_ambiguous: bool,
+
+        /// Number of passwords, one per line
+        #[arg(long, value_name = "N", default_value_t = 1,
+              value_parser = clap::value_parser!(u32).range(1..=10_000))]
+        count: u32,
+
+        /// Print the entropy of each password in bits to standard error
+        #[arg(long)]
+        show_entropy: bool,
+    },
+
+    /// Write random bytes, for salts, test data or overwriting a file
+    #[command(after_help = examples::RAND)]
+    Rand {
+        /// Number of random bytes; any size, as they are generated a
+        /// chunk at a time
+        #[arg(long, value_name = "N")]
+        bytes: u64,
+
+        /// Output encoding
+        #[arg(long, value_enum, default_value_t = RandFormat::Raw)]
+        format: RandFormat,
+
+        /// Write to this file, created or truncated, instead of standard
+        /// output
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
 
-        /// En/Decryption key (hexadecimal bytes)
-        #[arg(short, long, required = true, value_name = "HEX_BYTE", num_args = 5..=256)]
-        key: Vec<String>,
+        /// Create the --out file readable only by you (and make an
+        /// existing one so before writing)
+        #[arg(long, requires = "out")]
+        sensitive: bool,
     },
 
-    /// Generate a random 256-bit key for ChaCha20-Poly1305 (printed as hex bytes)
-    Keygen,
+    /// Encrypt files with ChaCha20-Poly1305 or AES-256-GCM
+    #[command(after_help = examples::ENCRYPT)]
+    Encrypt {
+        #[command(flatten)]
+        files: FileArgs,
 
-    /// ChaCha20-Poly1305 file encryption/decryption
+        #[command(flatten)]
+        secret: SecretArgs,
+
+        /// The AEAD to encrypt with; it is recorded in the header, so
+        /// decrypt needs no --algorithm [default: chacha20poly1305]
+        #[arg(long, value_enum)]
+        algorithm: Option<CipherAlg>,
+
+        #[command(flatten)]
+        seal: SealArgs,
+
+        #[command(flatten)]
+        tree: TreeArgs,
+
+        #[command(flatten)]
+        keep: KeepArgs,
+
+        #[command(flatten)]
+        aad: AadArgs,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Decrypt files from `rcli encrypt`, with the algorithm the header
+    /// names
+    #[command(after_help = examples::DECRYPT)]
+    Decrypt {
+        #[command(flatten)]
+        files: FileArgs,
+
+        #[command(flatten)]
+        secret: SecretArgs,
+
+        #[command(flatten)]
+        open: OpenArgs,
+
+        #[command(flatten)]
+        tree: TreeArgs,
+
+        #[command(flatten)]
+        keep: KeepArgs,
+
+        #[command(flatten)]
+        aad: AadArgs,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// ChaCha20-Poly1305 file encryption/decryption; the same as
+    /// `encrypt --algorithm chacha20poly1305` and `decrypt`
+    #[command(after_help = examples::CHACHA)]
     Chacha {
-        #[arg(short, long, required = true, value_name = "FILE_NAME")]
-        file: String,
+        #[command(flatten)]
+        files: FileArgs,
+
+        #[command(flatten)]
+        secret: SecretArgs,
 
-        /// 256-bit key (exactly 32 hexadecimal bytes)
-        #[arg(short, long, required = true, value_name = "HEX_BYTE", num_args = 32)]
-        key: Vec<String>,
+        #[command(flatten)]
+        seal: SealArgs,
+
+        #[command(flatten)]
+        open: OpenArgs,
 
         /// Encrypt the file
-        #[arg(long, conflicts_with = "decrypt")]
+        #[arg(long, conflicts_with_all = ["decrypt", "identity", "max_decompressed_size"])]
         encrypt: bool,
 
         /// Decrypt the file
-        #[arg(long, conflicts_with = "encrypt")]
+        #[arg(long, conflicts_with_all = ["encrypt", "recipient", "recipients_file", "chunk_size", "armor", "shred", "nonce_log", "compress"])]
         decrypt: bool,
+
+        #[command(flatten)]
+        tree: TreeArgs,
+
+        #[command(flatten)]
+        keep: KeepArgs,
+
+        #[command(flatten)]
+        aad: AadArgs,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Re-encrypt encrypted files under a new key or passphrase, one
+    /// chunk at a time, replacing each only once all of it has
+    /// authenticated under the old one
+    #[command(after_help = examples::REKEY)]
+    Rekey {
+        #[command(flatten)]
+        files: FileArgs,
+
+        #[command(flatten)]
+        old: rekey::OldSecret,
+
+        #[command(flatten)]
+        new: rekey::NewSecret,
+
+        #[command(flatten)]
+        tree: TreeArgs,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Put the parts written by --split back together into the one
+    /// encrypted file, checking first that none is missing, repeated or
+    /// cut short. Exits 5 if the parts do not make a whole
+    #[command(after_help = examples::JOIN)]
+    Join {
+        /// The parts, in any order
+        #[arg(required = true, value_name = "PART", value_hint = ValueHint::FilePath)]
+        parts: Vec<PathBuf>,
+
+        /// Where to write the joined file
+        #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+        out: PathBuf,
+
+        /// Replace the --out file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Pack a directory into one encrypted archive, which hides its file
+    /// names, layout and sizes, or unpack one
+    #[command(after_help = examples::ARCHIVE)]
+    Archive {
+        #[command(subcommand)]
+        command: ArchiveCommand,
+    },
+
+    /// Check that a ChaCha20-Poly1305 file authenticates, without writing any
+    /// plaintext. Exits 3 for a wrong key, passphrase or --aad, 4 when a
+    /// chunk fails authentication, 5 for a truncated file, and 1 otherwise
+    #[command(after_help = examples::VERIFY)]
+    Verify {
+        /// The encrypted file to check, or - for standard input
+        #[arg(short, long, required = true, value_name = "FILE_NAME", value_hint = ValueHint::FilePath)]
+        file: String,
+
+        #[command(flatten)]
+        key: ChachaKey,
+
+        /// Derive the key from a passphrase (prompted for, or the first line
+        /// of piped standard input) and the KDF settings in the file
+        #[arg(long, group = "ChachaKey")]
+        passphrase: bool,
+
+        /// As --passphrase, but take the passphrase from the first line of
+        /// a file
+        #[arg(long, value_name = "PATH", group = "ChachaKey")]
+        passphrase_file: Option<PathBuf>,
+
+        #[command(flatten)]
+        aad: AadArgs,
+    },
+
+    /// Show what an encrypted file's header records, without a key. Exits
+    /// 3 if the file is not an rcli encrypted file and 4 if its header is
+    /// damaged or from an unsupported version
+    #[command(after_help = examples::INSPECT)]
+    Inspect {
+        /// The encrypted file, or - for standard input
+        #[arg(short, long, required = true, value_name = "FILE_NAME", value_hint = ValueHint::FilePath)]
+        file: String,
+    },
+
+    /// Print checksums of files as "<hex digest>  <path>", the format of
+    /// sha256sum and b3sum, or check a list of them. With --check, exits 1
+    /// if any listed file is missing or does not match
+    #[command(after_help = examples::HASH)]
+    Hash {
+        /// The digest to compute, or to check against
+        #[arg(long, value_enum, default_value_t = HashAlg::Sha256)]
+        algorithm: HashAlg,
+
+        /// Files to hash; "-" reads standard input
+        #[arg(
+            value_name = "FILE",
+            value_hint = ValueHint::FilePath,
+            required_unless_present = "check",
+            conflicts_with = "check"
+        )]
+        files: Vec<String>,
+
+        /// Check the files listed in SUMSFILE ("-" for standard input)
+        /// against their digests, printing OK or FAILED for each
+        #[arg(short, long, value_name = "SUMSFILE", value_hint = ValueHint::FilePath)]
+        check: Option<String>,
+    },
+
+    /// Print a file's authentication tag under a key, or check one with
+    /// --verify. The file is neither encrypted nor changed. With --verify,
+    /// exits 1 if the tag does not match
+    #[command(after_help = examples::HMAC)]
+    Hmac {
+        /// The file to authenticate, or - for standard input
+        #[arg(short, long, required = true, value_name = "FILE_NAME", value_hint = ValueHint::FilePath)]
+        file: String,
+
+        #[command(flatten)]
+        key: MacKey,
+
+        /// The MAC to compute
+        #[arg(long, value_enum, default_value_t = MacAlg::HmacSha256)]
+        algorithm: MacAlg,
+
+        /// Check the file against this tag (hex) instead of printing one
+        #[arg(long, value_name = "TAG")]
+        verify: Option<String>,
+    },
+
+    /// Generate an Ed25519 key pair for sign and verify-sig: PREFIX.key,
+    /// readable only by you, and PREFIX.pub to hand out
+    #[command(after_help = examples::KEYPAIR)]
+    Keypair {
+        /// Where to write the key pair: PREFIX.key and PREFIX.pub
+        #[arg(long, value_name = "PREFIX")]
+        out_prefix: PathBuf,
+
+        /// Replace the key files if they already exist
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Sign a file with a secret key from keypair, writing FILE.sig
+    #[command(after_help = examples::SIGN)]
+    Sign {
+        /// The secret key (PREFIX.key)
+        #[arg(long, value_name = "PATH")]
+        key: PathBuf,
+
+        /// The file to sign, or - for standard input (then --out is
+        /// needed)
+        #[arg(short, long, required = true, value_name = "FILE_NAME", value_hint = ValueHint::FilePath)]
+        file: String,
+
+        /// The hash the file is reduced to before signing
+        #[arg(long, value_enum, default_value_t = PrehashArg::Sha512)]
+        prehash: PrehashArg,
+
+        /// Where to write the signature [default: FILE_NAME.sig]
+        #[arg(long, value_name = "PATH", required_if_eq("file", "-"))]
+        out: Option<PathBuf>,
+
+        /// Replace the signature file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Create identities, so others can encrypt files that only you can
+    /// decrypt
+    #[command(after_help = examples::IDENTITY)]
+    Identity {
+        #[command(subcommand)]
+        command: IdentityCommand,
+    },
+
+    /// Check a file's signature from sign. Exits 0 if it is good and 1
+    /// otherwise
+    #[command(after_help = examples::VERIFY_SIG)]
+    VerifySig {
+        /// The signer's public key (PREFIX.pub)
+        #[arg(long = "pub", value_name = "PATH")]
+        public_key: PathBuf,
+
+        /// The signed file, or - for standard input (then --sig is
+        /// needed)
+        #[arg(short, long, required = true, value_name = "FILE_NAME", value_hint = ValueHint::FilePath)]
+        file: String,
+
+        /// The signature [default: FILE_NAME.sig]
+        #[arg(long, value_name = "PATH", required_if_eq("file", "-"))]
+        sig: Option<PathBuf>,
+    },
+
+    /// Measure how fast each cipher encrypts and decrypts on this machine,
+    /// in memory and optionally through a disk
+    #[command(after_help = examples::BENCH)]
+    Bench {
+        /// Bytes each case encrypts, with an optional K, KiB, M, MiB, G or
+        /// GiB; held in memory
+        #[arg(long, value_name = "SIZE", default_value = "64MiB", value_parser = bench::parse_size)]
+        size: usize,
+
+        /// Runs of each case; the fastest is reported
+        #[arg(long, value_name = "N", default_value_t = 3,
+              value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
+
+        /// Chunk sizes to try the stream format at, comma-separated
+        /// [default: 16K,64K,1M]
+        #[arg(long, value_name = "BYTES", value_delimiter = ',', value_parser = parse_chunk_size)]
+        chunk_size: Vec<u32>,
+
+        /// Also write encrypted data to a temporary file in DIR and read it
+        /// back, so the disk's speed counts
+        #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+        disk_dir: Option<PathBuf>,
+    },
+
+    /// Defaults from the config file and RCLI_* variables
+    #[command(after_help = examples::CONFIG)]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Look after the log encrypt --nonce-log keeps
+    #[command(after_help = examples::NONCE_LOG)]
+    NonceLog {
+        #[command(subcommand)]
+        command: NonceLogCommand,
+    },
+
+    /// Print a tab-completion script for a shell, or install one into a
+    /// directory with --out-dir
+    #[command(after_help = examples::COMPLETIONS)]
+    Completions {
+        /// The shell to complete for
+        shell: Shell,
+
+        /// Write the script into this directory, under the name the shell
+        /// looks for (rcli.bash, _rcli, rcli.fish, ...), instead of to
+        /// standard output
+        #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+        out_dir: Option<PathBuf>,
+    },
+
+    /// Write man pages for rcli and each command into a directory
+    #[command(hide = true)]
+    Mangen {
+        /// The directory to write rcli.1 and rcli-<command>.1 into
+        #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+        out_dir: PathBuf,
     },
 }
 
-fn parse_hex_key(hex_strings: &[String]) -> Vec<u8> {
-    hex_strings
-        .iter()
-        .map(|s| s.trim_start_matches("0x"))
-        .map(|s| u8::from_str_radix(s, 16).expect("Invalid key hex byte!"))
-        .collect()
+#[derive(Subcommand, Debug)]
+enum IdentityCommand {
+    /// Write a new identity file, readable only by you, and print its
+    /// recipient string for others to pass to chacha --recipient
+    New {
+        /// Where to write the identity file
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// Replace the identity file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
 }
 
-fn main() -> std::io::Result<()> {
-    let cli = Cli::parse();
+#[derive(Subcommand, Debug)]
+enum ArchiveCommand {
+    /// Pack every file and directory under a directory, with their modes
+    /// and modification times, into one encrypted file; symlinks and
+    /// special files are skipped
+    Create {
+        /// The directory to pack
+        #[arg(short, long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+        file: PathBuf,
 
-    match cli.command {
-        Commands::Keygen => {
-            let mut key_bytes = vec![0u8; 32];
-            generate_key(&mut key_bytes);
-            let hex: Vec<String> = key_bytes.iter().map(|b| format!("{:02x}", b)).collect();
-            println!("{}", hex.join(" "));
+        /// Where to write the archive
+        #[arg(short, long, value_name = "PATH")]
+        out: PathBuf,
+
+        #[command(flatten)]
+        key: ChachaKey,
+
+        /// Plaintext bytes per encrypted chunk, as for encrypt
+        /// [default: 64K]
+        #[arg(long, value_name = "BYTES", value_parser = parse_chunk_size)]
+        chunk_size: Option<u32>,
+
+        /// Pad the archive so its size shows only a bucket: "padme" rounds
+        /// up by at most 12%, and a size such as 1M to a multiple of it
+        #[arg(long, value_name = "SCHEME", value_parser = archive::parse_padding)]
+        pad: Option<PaddingScheme>,
+
+        /// Compress the archive before encrypting it, as for encrypt; not
+        /// with --pad, which would pad the compressed data [default: none]
+        #[arg(long, value_name = "CODEC", value_parser = compress::parse_compression,
+              conflicts_with = "pad")]
+        compress: Option<Compression>,
+
+        /// Replace the archive if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Authenticate an archive and unpack it as a new directory, or only
+    /// the entries --entry names; nothing is written unless all that is
+    /// read authenticates
+    Extract {
+        /// The archive to unpack
+        #[arg(value_name = "ARCHIVE")]
+        archive: PathBuf,
+
+        /// The directory to unpack into; it must not exist, or be empty
+        #[arg(
+            long,
+            value_name = "DIR",
+            value_hint = ValueHint::DirPath,
+            required_unless_present = "out"
+        )]
+        into: Option<PathBuf>,
+
+        /// Unpack only the entries whose paths match PATTERN ('*' stays
+        /// within a directory, as for --exclude); a directory brings all
+        /// that is under it. Other entries' data is skipped undecrypted.
+        /// Repeat for more
+        #[arg(long, value_name = "PATTERN")]
+        entry: Vec<String>,
+
+        /// Write the one file --entry names here, with its recorded mode
+        /// and time, rather than under --into
+        #[arg(
+            short,
+            long,
+            value_name = "PATH",
+            requires = "entry",
+            conflicts_with = "into"
+        )]
+        out: Option<PathBuf>,
+
+        /// With --out, replace the file if it already exists
+        #[arg(long, requires = "out")]
+        force: bool,
+
+        /// The most a compressed archive may unpack to, as for decrypt
+        /// [default: 1G]
+        #[arg(long, value_name = "BYTES", value_parser = compress::parse_limit)]
+        max_decompressed_size: Option<u64>,
+
+        #[command(flatten)]
+        key: ChachaKey,
+    },
+
+    /// Print each entry of an archive (mode, size, modification time in
+    /// UTC and path) without unpacking it; --json prints an object per
+    /// entry instead
+    List {
+        /// The archive to list
+        #[arg(value_name = "ARCHIVE")]
+        archive: PathBuf,
+
+        /// The most a compressed archive may unpack to, as for decrypt
+        /// [default: 1G]
+        #[arg(long, value_name = "BYTES", value_parser = compress::parse_limit)]
+        max_decompressed_size: Option<u64>,
+
+        #[command(flatten)]
+        key: ChachaKey,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print each setting in force and where it came from: a variable,
+    /// the config file, or rcli's built-in default
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+enum NonceLogCommand {
+    /// Drop the entries of keys that are no longer used, so the log stops
+    /// growing with them; entries of other keys are kept
+    Compact {
+        /// The log [default: nonce-log from the config]
+        #[arg(long, value_name = "PATH")]
+        log: Option<PathBuf>,
+
+        /// The fingerprint of a retired key, as the log and encrypt -v
+        /// show it; repeat it for several
+        #[arg(long, value_name = "FINGERPRINT", required = true,
+              value_parser = nonce_log::parse_fingerprint)]
+        retire: Vec<String>,
+    },
+}
+
+/* Associated data for chacha and verify. Every value becomes a field of
+ * the canonical AadBuilder encoding, so order matters and "ab" + "c" is
+ * not "a" + "bc"; strings come first, then files, each in the order
+ * given. */
+/* Where the key for encrypt, decrypt and chacha comes from: one of the
+ * key options, a passphrase, or (in SealArgs and OpenArgs) a recipient
+ * or identity, all in ChachaKey's group */
+#[derive(Args, Debug)]
+struct SecretArgs {
+    #[command(flatten)]
+    key: ChachaKey,
+
+    /// Derive the key from a passphrase (prompted for, or the first line
+    /// of piped standard input); decryption reads the KDF settings from
+    /// the file
+    #[arg(long, group = "ChachaKey", conflicts_with = "recursive")]
+    passphrase: bool,
+
+    /// As --passphrase, but take the passphrase from the first line of
+    /// a file
+    #[arg(
+        long,
+        value_name = "PATH",
+        group = "ChachaKey",
+        conflicts_with = "recursive"
+    )]
+    passphrase_file: Option<PathBuf>,
+
+    /// With --passphrase, ask for the passphrase only once when
+    /// encrypting
+    /* `requires = "passphrase"` would be met by the flag's implicit
+     * false default, so name the other key sources instead (recipients
+     * and identities name this in turn) */
+    #[arg(long, conflicts_with_all = ["key", "key_hex", "key_base64", "key_file", "key_env", "key_stdin", "passphrase_file"])]
+    no_confirm: bool,
+}
+
+impl SecretArgs {
+    /* The passphrase to derive the key from, if that is the key source */
+    fn passphrase_source(&self) -> Option<PassphraseSource> {
+        match &self.passphrase_file {
+            Some(path) => Some(PassphraseSource::File(path.clone())),
+            None => self.passphrase.then(PassphraseSource::interactive),
         }
-        Commands::Rc4 { file, key } => {
-            let key_bytes = parse_hex_key(&key);
+    }
+}
+
+/* Options that only apply when encrypting */
+#[derive(Args, Debug)]
+struct SealArgs {
+    /// Encrypt to this recipient string (from `rcli identity new`)
+    /// instead of under a key; only their identity can decrypt. Repeat
+    /// it to let any of several recipients decrypt
+    #[arg(long, value_name = "RECIPIENT", group = "ChachaKey",
+          value_parser = identity::parse_recipient,
+          conflicts_with = "no_confirm")]
+    recipient: Vec<HybridPublicKey>,
 
-            let mut contents = Vec::new();
-            let mut f = File::options().read(true).write(true).open(&file)?;
-            f.read_to_end(&mut contents)?;
+    /// As --recipient, for each recipient string in a file, one per
+    /// line; blank lines and lines starting with "#" are skipped
+    #[arg(
+        long,
+        value_name = "PATH",
+        group = "ChachaKey",
+        conflicts_with = "no_confirm"
+    )]
+    recipients_file: Option<PathBuf>,
 
-            Rc4::apply_keystream_static(&key_bytes, &mut contents);
+    /// Plaintext bytes per encrypted chunk, e.g. 4096, 64K or 1M (1K to
+    /// 16M); memory use is about two chunks whatever the file size
+    /// [default: 64K]
+    #[arg(long, value_name = "BYTES", value_parser = parse_chunk_size)]
+    chunk_size: Option<u32>,
 
-            f.rewind()?;
-            f.write_all(&contents)?;
+    /// Write the encrypted file as base64 text between BEGIN/END lines,
+    /// for pasting into mail or tickets; decryption detects it
+    #[arg(long)]
+    armor: bool,
 
-            println!("Processed {}", file);
+    /// Once a file is encrypted to --output (or under --output-dir),
+    /// overwrite the original with random data and remove it. SSDs and
+    /// copy-on-write filesystems may still keep old copies
+    #[arg(long, requires = "destination", conflicts_with = "keep")]
+    shred: bool,
+
+    /// Record each key fingerprint and nonce prefix used in this file,
+    /// and refuse to encrypt if the pair is already there (for
+    /// --key-style keys; passphrases and recipients get a fresh key)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["passphrase", "recipient", "recipients_file"])]
+    nonce_log: Option<PathBuf>,
+
+    /// Compress before encrypting: zstd (level 3, or zstd:1 to
+    /// zstd:22), deflate (gzip is the same), or none. Input whose start
+    /// hardly compresses is stored as it is. The encrypted size then
+    /// hints at the content, so keep secrets and untrusted input apart
+    /// [default: none]
+    #[arg(long, value_name = "CODEC", value_parser = compress::parse_compression,
+          conflicts_with_all = ["recipient", "recipients_file"])]
+    compress: Option<Compression>,
+
+    /// Write the result as parts of at most SIZE bytes, e.g. 2G or 4000M,
+    /// named the output plus .000, .001 and so on; needs --output,
+    /// --output-dir or --keep. `rcli join` puts them back together
+    #[arg(long, value_name = "SIZE", value_parser = join::parse_part_size)]
+    split: Option<u64>,
+}
+
+/* Options that only apply when decrypting */
+#[derive(Args, Debug)]
+struct OpenArgs {
+    /// Decrypt a file encrypted to your recipient string, with the
+    /// identity file from `rcli identity new`
+    #[arg(
+        long,
+        value_name = "PATH",
+        group = "ChachaKey",
+        conflicts_with = "no_confirm"
+    )]
+    identity: Option<PathBuf>,
+
+    /// The most a compressed file may decompress to, e.g. 512M or 10G;
+    /// one that records more is refused before anything is written
+    /// [default: 1G]
+    #[arg(long, value_name = "BYTES", value_parser = compress::parse_limit)]
+    max_decompressed_size: Option<u64>,
+}
+
+/* -r, --jobs and what goes with them */
+#[derive(Args, Debug)]
+struct TreeArgs {
+    /// Process every regular file under the directory given with
+    /// --file; symlinks and special files are skipped
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// With -r, write each result under this directory at the same
+    /// relative path instead of in place
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        requires = "recursive",
+        conflicts_with = "output",
+        group = "destination"
+    )]
+    output_dir: Option<PathBuf>,
+
+    /// With -r, carry on with the remaining files after one fails
+    #[arg(long, requires = "recursive")]
+    keep_going: bool,
+
+    /// Work on this many files at once, with -r or several --file, or on
+    /// this many chunks of a single file at once; 1 does one after
+    /// another [default: the number of CPU cores]
+    #[arg(short, long, value_name = "N", value_parser = jobs::parse_count)]
+    jobs: Option<usize>,
+}
+
+impl TreeArgs {
+    fn jobs(&self) -> usize {
+        self.jobs.unwrap_or_else(jobs::default_count)
+    }
+}
+
+/* --keep: leave each file alone and write its result beside it */
+#[derive(Args, Debug)]
+struct KeepArgs {
+    /// Leave each file as it is and write the result beside it: the name
+    /// plus --suffix when encrypting, and when decrypting the name without
+    /// --suffix, .enc or .rcli (or plus .dec if it has none of them). An
+    /// existing file there is an error unless --force
+    #[arg(long, conflicts_with_all = ["destination", "backup"])]
+    keep: bool,
+
+    /// The suffix --keep adds and takes off [default: .enc]
+    #[arg(long, value_name = "SUFFIX", value_parser = backup::parse_suffix)]
+    suffix: Option<String>,
+}
+
+impl KeepArgs {
+    /* The suffix with --keep, or None without it */
+    fn suffix(&self) -> Result<Option<&str>, CliError> {
+        match (self.keep, &self.suffix) {
+            (true, suffix) => Ok(Some(
+                suffix
+                    .as_deref()
+                    .or(config::get().settings().suffix.as_deref())
+                    .unwrap_or(names::DEFAULT_SUFFIX),
+            )),
+            (false, None) => Ok(None),
+            (false, Some(_)) => Err(CliError::new(
+                ErrorCode::Usage,
+                "--suffix only means something with --keep",
+            )),
         }
-        Commands::Chacha {
-            file,
-            key,
-            encrypt,
-            decrypt,
-        } => {
-            if !encrypt && !decrypt {
-                eprintln!("Error: either --encrypt or --decrypt must be specified");
-                std::process::exit(1);
-            }
+    }
+}
+
+/// The AEADs `encrypt --algorithm` offers.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum CipherAlg {
+    /// ChaCha20-Poly1305: fast everywhere, constant time without
+    /// hardware support
+    #[value(name = "chacha20poly1305")]
+    Chacha20poly1305,
+    /// AES-256-GCM: fastest on CPUs with AES instructions
+    #[value(name = "aes256gcm")]
+    Aes256gcm,
+}
+
+impl From<CipherAlg> for Algorithm {
+    fn from(algorithm: CipherAlg) -> Self {
+        match algorithm {
+            CipherAlg::Chacha20poly1305 => Algorithm::ChaCha20Poly1305,
+            CipherAlg::Aes256gcm => Algorithm::Aes256Gcm,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct AadArgs {
+    /// Bind the encrypted file to this value, e.g. a hostname or backup
+    /// set name; decrypting needs the same values in the same order.
+    /// Repeatable
+    #[arg(long, value_name = "STRING")]
+    aad: Vec<String>,
+
+    /// As --aad, with the contents of a file; these follow any --aad
+    /// values. Repeatable
+    #[arg(long, value_name = "PATH")]
+    aad_file: Vec<PathBuf>,
+}
+
+/* Domain separation for the encoding, per AadBuilder::new */
+const AAD_LABEL: &str = "rcli --aad v1";
+
+impl AadArgs {
+    /// The encoded associated data; empty when none was given.
+    fn load(&self) -> Result<Vec<u8>, String> {
+        if self.aad.is_empty() && self.aad_file.is_empty() {
+            return Ok(Vec::new());
+        }
+        if self.aad.len() + self.aad_file.len() > MAX_AAD_FIELDS {
+            return Err(format!(
+                "at most {} --aad and --aad-file values may be given",
+                MAX_AAD_FIELDS
+            ));
+        }
+        let contents = self
+            .aad_file
+            .iter()
+            .map(|path| {
+                std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut builder = AadBuilder::new(AAD_LABEL);
+        for value in &self.aad {
+            builder.push_str(value);
+        }
+        for value in &contents {
+            builder.push_bytes(value);
+        }
+        Ok(builder.finish())
+    }
+}
 
-            let key_bytes = parse_hex_key(&key);
+/* Where rc4 and chacha put their result: over the input, atomically, or
+ * in a new file. A file of "-" reads standard input and writes standard
+ * output instead, and status lines then go to standard error so the data
+ * stream stays clean. Progress is drawn while the input is read, and
+ * erased before the status line. Ctrl-C stops a file at the next read,
+ * leaving it (or --output) as it was. With --jobs, copies of these options
+ * write files on several threads at once, so what is shared between them
+ * is behind atomics and locks. */
+#[derive(Args, Debug)]
+struct OutputArgs {
+    /// Write the result to this file and leave the input untouched
+    #[arg(short, long, value_name = "PATH", group = "destination")]
+    output: Option<PathBuf>,
 
-            let mut contents = Vec::new();
-            let mut f = File::options().read(true).write(true).open(&file)?;
-            f.read_to_end(&mut contents)?;
+    /// Replace the --output file (or files under --output-dir, the
+    /// --keep file, or the --backup copy) if it already exists. For
+    /// chacha, also encrypt a file that is already encrypted, or decrypt
+    /// one without the rcli header
+    #[arg(long)]
+    force: bool,
 
-            if encrypt {
-                let mut nonce_bytes = [0u8; 12];
-                generate_nonce(&mut nonce_bytes);
+    /// Before changing a file in place, copy it to its name plus SUFFIX
+    /// (.bak when none is given), keeping its mode and modification time
+    #[arg(long, value_name = "SUFFIX", num_args = 0..=1, require_equals = true,
+          default_missing_value = backup::DEFAULT_SUFFIX, value_parser = backup::parse_suffix)]
+    backup: Option<String>,
 
-                let ciphertext =
-                    chacha20_poly1305_cipher(&key_bytes, &nonce_bytes, contents, true)
-                        .map_err(|_| {
-                            std::io::Error::other("Encryption failed")
-                        })?;
+    /// Show progress even for small files or when standard error is not
+    /// a terminal (never shown with --file -)
+    #[arg(long, conflicts_with = "no_progress")]
+    progress: bool,
 
-                // Write nonce (12 bytes) || ciphertext+tag
-                f.rewind()?;
-                f.set_len(0)?;
-                f.write_all(&nonce_bytes)?;
-                f.write_all(&ciphertext)?;
+    /// Never show progress
+    #[arg(long)]
+    no_progress: bool,
 
-                println!("Encrypted {}", file);
-            } else {
-                // decrypt
-                if contents.len() < 12 {
-                    eprintln!("Error: file too short to contain a nonce");
-                    std::process::exit(1);
-                }
+    /// After each file, print the bytes read and written, the time taken,
+    /// the throughput and the chunk count, then a total; on standard
+    /// error, or with --json in the records
+    #[arg(long)]
+    stats: bool,
 
-                let nonce_bytes: [u8; 12] = contents[..12].try_into().unwrap();
-                let ciphertext = contents[12..].to_vec();
+    /* Where --stats keeps its figures, shared by every file, and the
+     * last file's until it is reported */
+    #[arg(skip)]
+    meter: Arc<Meter>,
+    #[arg(skip)]
+    measured: Mutex<Option<Stats>>,
 
-                let plaintext =
-                    chacha20_poly1305_cipher(&key_bytes, &nonce_bytes, ciphertext, false)
-                        .map_err(|_| {
-                            std::io::Error::other("Decryption failed")
-                        })?;
+    /* With --split, the part size, and how many parts the last file
+     * written took */
+    #[arg(skip)]
+    split: Option<u64>,
+    #[arg(skip)]
+    parts: AtomicU32,
 
-                f.rewind()?;
-                f.set_len(0)?;
-                f.write_all(&plaintext)?;
+    /* With --jobs, the one bar every file's progress goes to */
+    #[arg(skip)]
+    batch: Option<Arc<Batch>>,
+}
+
+impl OutputArgs {
+    /* The same options, writing to `output` instead */
+    fn writing_to(&self, output: Option<PathBuf>) -> OutputArgs {
+        OutputArgs {
+            output,
+            backup: self.backup.clone(),
+            meter: Arc::clone(&self.meter),
+            measured: Mutex::new(None),
+            parts: AtomicU32::new(0),
+            batch: self.batch.clone(),
+            ..*self
+        }
+    }
+
+    /* The same options for `count` files at once, sharing one bar */
+    fn for_jobs(self, count: usize) -> OutputArgs {
+        let batch = (count > 1).then(|| Arc::new(Batch::new(self.progress_mode())));
+        OutputArgs { batch, ..self }
+    }
+
+    fn jobs(&self, count: usize) -> Jobs<'_> {
+        Jobs::new(count, self.batch.as_deref())
+    }
 
-                println!("Decrypted {}", file);
+    fn write<F>(&self, file: &str, op: F) -> Result<Summary, FsCryptoError>
+    where
+        F: FnOnce(&mut dyn Read, &mut dyn Write) -> Result<Summary, FsCryptoError>,
+    {
+        let started = Instant::now();
+        let (read, written) = (Cell::new(0), Cell::new(0));
+        let op = |reader: &mut dyn Read, writer: &mut dyn Write| {
+            let mut reader = MeteredReader::new(reader, &read);
+            op(&mut reader, &mut MeteredWriter::new(writer, &written))
+        };
+        let result = if file == STDIN_PATH {
+            self.write_shown(file, op)
+        } else {
+            interrupt::begin(file)?;
+            let result = self.write_shown(file, |reader, writer| {
+                op(&mut InterruptReader::new(reader), writer)
+            });
+            interrupt::finish(file, result.is_ok());
+            result
+        };
+        if let Ok(summary) = &result {
+            let took = started.elapsed();
+            log_summary(file, summary, took);
+            let stats = Stats::new(read.get(), written.get(), took, summary.chunks);
+            *lock(&self.measured) = Some(stats);
+        }
+        result
+    }
+
+    /* rc4 --mmap: `op` over `file` mapped into memory, a window at a time
+     * (see `mmap`), changing it in place; `None`, with nothing done, when
+     * it cannot be mapped and has to be streamed instead */
+    fn write_mapped<F>(&self, file: &str, op: F) -> Result<Option<Summary>, FsCryptoError>
+    where
+        F: FnMut(&mut [u8]),
+    {
+        let started = Instant::now();
+        let mut map = match mmap::map(file) {
+            Ok(map) => map,
+            Err(e) => {
+                log::verbose(format_args!("{}: not mapped ({}); streaming it", file, e));
+                return Ok(None);
             }
+        };
+        if let Some(suffix) = &self.backup {
+            let copy = backup::path_for(Path::new(file), suffix);
+            let overwrite = clobber::may_replace(&copy, self.force)?;
+            let backup = backup::make(Path::new(file), suffix, overwrite)?;
+            log::verbose(format_args!("{}: backed up to {}", file, backup.display()));
+        }
+        let len = map.len() as u64;
+        let mut bar: Box<dyn Progress> = match self.progress_mode().shows(false, Some(len)) {
+            true if !status::json() => Box::new(TerminalProgress::new(len)),
+            _ => Box::new(ByteCount::default()),
+        };
+        /* Stopping part way would leave the file half processed, so an
+         * interrupt only takes effect once it is done */
+        interrupt::begin(file)?;
+        let result = mmap::apply(&mut map, op, bar.as_mut());
+        interrupt::finish(file, result.is_ok());
+        let done = result?;
+
+        let summary = Summary {
+            bytes_in: done,
+            bytes_out: done,
+            chunks: 1,
+        };
+        let took = started.elapsed();
+        log_summary(file, &summary, took);
+        *lock(&self.measured) = Some(Stats::new(done, done, took, summary.chunks));
+        Ok(Some(summary))
+    }
+
+    fn progress_mode(&self) -> ProgressMode {
+        match (self.progress, self.no_progress) {
+            (false, false) => match config::get().settings().progress {
+                Some(on) => ProgressMode::from_flags(on, !on),
+                None => ProgressMode::Auto,
+            },
+            (progress, no_progress) => ProgressMode::from_flags(progress, no_progress),
         }
     }
 
-    Ok(())
+    fn write_shown<F>(&self, file: &str, op: F) -> Result<Summary, FsCryptoError>
+    where
+        F: FnOnce(&mut dyn Read, &mut dyn Write) -> Result<Summary, FsCryptoError>,
+    {
+        let streaming = file == STDIN_PATH;
+        if let Some(batch) = self.batch.as_deref().filter(|_| !streaming) {
+            let mut share = batch.share();
+            return self.write_plain(file, |reader, writer| {
+                op(&mut ProgressReader::new(reader, &mut share), writer)
+            });
+        }
+        let mode = self.progress_mode();
+        let len = match streaming {
+            true => None,
+            false => std::fs::metadata(file).ok().map(|m| m.len()),
+        };
+        if status::json() || !mode.shows(streaming, len) {
+            return self.write_plain(file, op);
+        }
+        let mut bar = TerminalProgress::new(len.unwrap_or(0));
+        let result = self.write_plain(file, |reader, writer| {
+            op(&mut ProgressReader::new(reader, &mut bar), writer)
+        });
+        bar.finish();
+        result
+    }
+
+    fn write_plain<F>(&self, file: &str, op: F) -> Result<Summary, FsCryptoError>
+    where
+        F: FnOnce(&mut dyn Read, &mut dyn Write) -> Result<Summary, FsCryptoError>,
+    {
+        if file == STDIN_PATH {
+            if self.output.is_some() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "--output cannot be used with --file -; redirect standard output instead",
+                )
+                .into());
+            }
+            if self.backup.is_some() {
+                eprintln!("Warning: --backup does nothing for standard input");
+            }
+            status::data_on_stdout();
+            let mut writer = BufWriter::new(std::io::stdout().lock());
+            let summary = op(&mut std::io::stdin().lock(), &mut writer)?;
+            writer.flush()?;
+            return Ok(summary);
+        }
+        match (&self.output, &self.backup) {
+            (Some(output), _) if output.as_os_str() == STDIN_PATH => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--output - would write a file named -; use --file - and redirect standard \
+                 output instead",
+            )
+            .into()),
+            (Some(output), backup) => {
+                if backup.is_some() {
+                    eprintln!(
+                        "Warning: --backup does nothing for {}, as --output leaves it unchanged",
+                        file
+                    );
+                }
+                if let Some(part_size) = self.split {
+                    let first = split::part_path(output, 0);
+                    let overwrite = clobber::may_replace(&first, self.force)?;
+                    let (summary, parts) =
+                        fs::write_parts(file, output, part_size, overwrite, |r, w| op(r, w))?;
+                    self.parts.store(parts, Ordering::Relaxed);
+                    return Ok(summary);
+                }
+                let overwrite = clobber::may_replace(output, self.force)?;
+                fs::write_new(file, output, overwrite, |r, w| op(r, w))
+            }
+
+            (None, None) => fs::rewrite_atomic(file, |r, w| op(r, w)),
+            (None, Some(suffix)) => {
+                let copy = backup::path_for(Path::new(file), suffix);
+                let overwrite = clobber::may_replace(&copy, self.force)?;
+                let backup = backup::make(Path::new(file), suffix, overwrite)?;
+                log::verbose(format_args!("{}: backed up to {}", file, backup.display()));
+                fs::rewrite_atomic(file, |r, w| op(r, w)).map_err(|error| {
+                    FsCryptoError::Io(std::io::Error::other(status::BackedUp { error, backup }))
+                })
+            }
+        }
+    }
+
+    /* With --stats, the total over every file written through these
+     * options (or copies of them) */
+    fn total(&self) {
+        if self.stats {
+            self.meter.print_total();
+        }
+    }
+
+    /* Print "<verb> notes.txt", or "<verb> notes.txt -> notes.enc" with
+     * --output; with --json, the record instead. With --stats, the file's
+     * figures go first, and into the total */
+    fn report(&self, action: Action, algorithm: &'static str, file: &str, summary: &Summary) {
+        if let Some(stats) = lock(&self.measured).take().filter(|_| self.stats) {
+            self.meter.record(stats);
+            match file {
+                _ if status::json() => {}
+                STDIN_PATH => eprintln!("standard input: {}", stats),
+                _ => eprintln!("{}: {}", file, stats),
+            }
+        }
+        if !log::enabled(Level::Normal) {
+            return;
+        }
+        if status::json() {
+            let output = match &self.output {
+                _ if file == STDIN_PATH => None,
+                Some(output) => Some(output.display().to_string()),
+                None => Some(file.to_string()),
+            };
+            status::emit(&Record::Ok {
+                action,
+                path: file,
+                output: output.as_deref(),
+                bytes_in: summary.bytes_in,
+                bytes_out: summary.bytes_out,
+                algorithm,
+                stats: self.meter.last().filter(|_| self.stats),
+            });
+            return;
+        }
+        let verb = match action {
+            Action::Encrypt => "Encrypted",
+            Action::Decrypt => "Decrypted",
+            Action::Process => "Processed",
+            Action::Verify => "Verified",
+            Action::Rekey => "Rekeyed",
+            Action::Archive => "Archived",
+            Action::Extract => "Extracted",
+            Action::Join => "Joined",
+        };
+        let parts = self.parts.load(Ordering::Relaxed);
+        match &self.output {
+            _ if file == STDIN_PATH => eprintln!("{} standard input", verb),
+            Some(output) if parts > 1 => println!(
+                "{} {} -> {} to {}",
+                verb,
+                file,
+                split::part_path(output, 0).display(),
+                split::part_path(output, parts - 1).display()
+            ),
+            Some(output) if parts == 1 => {
+                println!(
+                    "{} {} -> {}",
+                    verb,
+                    file,
+                    split::part_path(output, 0).display()
+                )
+            }
+            Some(output) => println!("{} {} -> {}", verb, file, output.display()),
+            None => println!("{} {}", verb, file),
+        }
+    }
+}
+
+/* A lock that a panicking worker cannot poison for the others */
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/* "Archived project -> backup.rcla", or with --json the record */
+fn report_archive(action: Action, path: &str, output: &str, summary: &Summary) {
+    if !log::enabled(Level::Normal) {
+        return;
+    }
+    if status::json() {
+        status::emit(&Record::Ok {
+            action,
+            path,
+            output: Some(output),
+            bytes_in: summary.bytes_in,
+            bytes_out: summary.bytes_out,
+            algorithm: Algorithm::ChaCha20Poly1305.name(),
+            stats: None,
+        });
+        return;
+    }
+    let verb = match action {
+        Action::Archive => "Archived",
+        Action::Join => "Joined",
+        _ => "Extracted",
+    };
+    println!("{} {} -> {}", verb, path, output);
+}
+
+/* -v: "notes.txt: 5 bytes in, 38 out, 1 chunk, 0.002 s" */
+fn log_summary(file: &str, summary: &Summary, elapsed: Duration) {
+    log::verbose(format_args!(
+        "{}: {} bytes in, {} out, {} {}, {:.3} s",
+        file,
+        summary.bytes_in,
+        summary.bytes_out,
+        summary.chunks,
+        if summary.chunks == 1 {
+            "chunk"
+        } else {
+            "chunks"
+        },
+        elapsed.as_secs_f64()
+    ));
+}
+
+/* -vv: what the header of `file` says. Only public fields: no salt, key
+ * check value or sealed key */
+fn log_header(file: &str, header: &FileHeader) {
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    log::debug(format_args!(
+        "{}: header: format version {}, {}, chunk size {} bytes, key check value {}, \
+         associated data {}, {} recipient slots",
+        file,
+        header.version,
+        header.algorithm.name(),
+        header.chunk_size,
+        yes_no(header.key_check.is_some()),
+        yes_no(header.aad_check.is_some()),
+        header.recipients.len()
+    ));
+    if let Some(kdf) = &header.passphrase {
+        log_kdf(file, kdf);
+    }
+}
+
+fn log_kdf(file: &str, kdf: &PassphraseKdf) {
+    log::debug(format_args!(
+        "{}: key derivation: {}",
+        file,
+        inspect::kdf_description(kdf)
+    ));
+}
+
+/* Encrypt under a key derived from a passphrase with Argon2id and a fresh
+ * salt. The KDF settings go in the header, along with the key's check
+ * value so a wrong passphrase is caught before any chunk is opened. The
+ * passphrase is read (and, with `confirm`, confirmed) before the file is
+ * touched. */
+fn encrypt_with_passphrase(
+    file: &str,
+    output: &OutputArgs,
+    source: &PassphraseSource,
+    prompter: &mut dyn Prompter,
+    confirm: bool,
+    opts: EncryptOptions,
+    armor: bool,
+) -> Result<Summary, CliError> {
+    check_passphrase_source(file, source).map_err(|e| CliError::new(ErrorCode::Usage, e))?;
+    let passphrase = source
+        .read(prompter, confirm)
+        .map_err(|e| CliError::new(ErrorCode::BadKey, e))?;
+    let kdf = passphrase_kdf(Argon2Params::default());
+    log_kdf(file, &kdf);
+    let key = derive_file_key(&passphrase, &kdf).map_err(|e| format!("{:?}", e))?;
+    let opts = EncryptOptions {
+        key_check: true,
+        passphrase: Some(kdf),
+        ..opts
+    };
+    output
+        .write(file, |reader, writer| {
+            let mut reader = not_encrypted(reader, output.force)?;
+            encrypt_to(&mut reader, writer, key.as_bytes(), &opts, armor, None)
+        })
+        .map_err(failure)
+}
+
+/* The KDF settings come from the header, so the passphrase is asked for
+ * once the header has been read (after de-armoring) but before any chunk
+ * is. A failure in between is carried out of the output callback with
+ * `status::carry`; the algorithm the header names is set in `algorithm`. */
+fn decrypt_with_passphrase(
+    file: &str,
+    output: &OutputArgs,
+    source: &PassphraseSource,
+    prompter: &mut dyn Prompter,
+    aad: &[u8],
+) -> Result<(Summary, Algorithm), CliError> {
+    check_passphrase_source(file, source).map_err(|e| CliError::new(ErrorCode::Usage, e))?;
+    let mut algorithm = Algorithm::ChaCha20Poly1305;
+    let result = output.write(file, |reader, mut writer| {
+        let mut reader = dearmored(reader)?;
+        let (header, header_bytes) = fs::read_header(&mut reader)?;
+        log_header(file, &header);
+        algorithm = header.algorithm;
+        let key = passphrase_key(file, &header, source, prompter).map_err(status::carry)?;
+        compress::decrypt(
+            &mut Cursor::new(header_bytes).chain(reader),
+            &mut writer,
+            key.as_bytes(),
+            aad,
+            header.compression.is_some(),
+        )
+    });
+    match result {
+        /* A wrong passphrase fails the key check value, a damaged file
+         * authentication; the message covers both */
+        Err(e @ FsCryptoError::Crypto(ErrorStates::WrongKey | ErrorStates::DecryptionFailed)) => {
+            Err(CliError::new(
+                status::error_code(&e),
+                "incorrect passphrase or corrupted file",
+            ))
+        }
+        result => result.map(|summary| (summary, algorithm)).map_err(failure),
+    }
+}
+
+fn passphrase_key(
+    file: &str,
+    header: &FileHeader,
+    source: &PassphraseSource,
+    prompter: &mut dyn Prompter,
+) -> Result<SecretKey32, CliError> {
+    if !header.recipients.is_empty() {
+        return Err(CliError::new(
+            ErrorCode::WrongKey,
+            format!(
+                "{} was encrypted to a recipient; decrypt it with --identity",
+                file
+            ),
+        ));
+    }
+    let kdf = header.passphrase.ok_or_else(|| {
+        CliError::new(
+            ErrorCode::WrongKey,
+            format!("{} was not encrypted with a passphrase", file),
+        )
+    })?;
+    let passphrase = source
+        .read(prompter, false)
+        .map_err(|e| CliError::new(ErrorCode::BadKey, e))?;
+    derive_header_key(&passphrase, &kdf)
+}
+
+/* The key `passphrase` makes with the KDF settings from a header */
+fn derive_header_key(passphrase: &str, kdf: &PassphraseKdf) -> Result<SecretKey32, CliError> {
+    derive_file_key(passphrase, kdf).map_err(|e| match e {
+        KeyfileError::UnsupportedKdf(id) => CliError::new(
+            ErrorCode::Malformed,
+            format!("unsupported key derivation function {}", id),
+        ),
+        _ => CliError::new(
+            ErrorCode::Malformed,
+            "key derivation parameters in the header are out of range",
+        ),
+    })
+}
+
+fn check_passphrase_source(file: &str, source: &PassphraseSource) -> Result<(), String> {
+    if file == STDIN_PATH && *source == PassphraseSource::Stdin {
+        return Err(
+            "--passphrase would read the passphrase from standard input, which \
+                    carries the data; use --passphrase-file"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/* Which way encrypt, decrypt or chacha goes, with the options only that
+ * way takes */
+enum Direction {
+    Encrypt(Algorithm, SealArgs),
+    Decrypt(OpenArgs),
+}
+
+/* encrypt, decrypt and chacha: every file given, or every file under
+ * the directory with -r */
+fn cipher(
+    files: FileArgs,
+    secret: SecretArgs,
+    direction: Direction,
+    tree: TreeArgs,
+    keep: KeepArgs,
+    aad: AadArgs,
+    output: OutputArgs,
+) -> Result<(), CliError> {
+    let (opts, armor, shred, nonce_log, split) = match &direction {
+        Direction::Encrypt(algorithm, seal) => {
+            let opts = EncryptOptions {
+                chunk_size: seal
+                    .chunk_size
+                    .or(config::get().settings().chunk_size)
+                    .unwrap_or(DEFAULT_CHUNK_SIZE),
+                algorithm: *algorithm,
+                compression: seal.compress.and_then(Compression::codec),
+                ..EncryptOptions::default()
+            };
+            let nonce_log = seal
+                .nonce_log
+                .clone()
+                .or_else(|| config::get().settings().nonce_log.clone())
+                .map(NonceLog::new);
+            (opts, seal.armor, seal.shred, nonce_log, seal.split)
+        }
+        Direction::Decrypt(open) => {
+            if let Some(limit) = open.max_decompressed_size {
+                compress::set_limit(limit);
+            }
+            (EncryptOptions::default(), false, false, None, None)
+        }
+    };
+    let output = OutputArgs { split, ..output };
+    let nonce_log = nonce_log.as_ref();
+    let encrypt = matches!(direction, Direction::Encrypt(..));
+    let action = if encrypt {
+        Action::Encrypt
+    } else {
+        Action::Decrypt
+    };
+    let excludes = files.excludes();
+    let files = select_files(&files, &output, secret.key.reads_stdin())?;
+    let file = &files[0];
+    if shred {
+        if files.iter().any(|f| f == STDIN_PATH) {
+            return Err(CliError::new(
+                ErrorCode::Usage,
+                "--shred needs files to remove, not standard input",
+            ));
+        }
+        eprintln!("{}", SHRED_CAVEAT);
+    }
+    let keep = keep.suffix()?;
+    if split.is_some() {
+        let unsplittable = match files.iter().any(|f| f == STDIN_PATH) {
+            true => Some("--split writes files, so it cannot be used with --file -"),
+            false if output.output.is_none() && tree.output_dir.is_none() && keep.is_none() => {
+                Some("--split writes new files, so it needs --output, --output-dir or --keep")
+            }
+            false => None,
+        };
+        if let Some(message) = unsplittable {
+            return Err(CliError::new(ErrorCode::Usage, message));
+        }
+    }
+    if keep.is_some() && files.iter().any(|f| f == STDIN_PATH) {
+        return Err(CliError::new(
+            ErrorCode::Usage,
+            "--keep needs files to write beside, not standard input",
+        ));
+    }
+    /* Where the result for `name` goes: `out`, or beside it with --keep */
+    let output_for = |output: &OutputArgs, name: &str, out: Option<&Path>| match keep {
+        Some(suffix) => output.writing_to(Some(names::derive(Path::new(name), suffix, encrypt))),
+        None => output.writing_to(out.map(Path::to_path_buf)),
+    };
+
+    let aad = aad.load().map_err(|e| CliError::new(ErrorCode::Usage, e))?;
+    /* A single file has the threads to itself, for its chunks */
+    let per_file = match tree.recursive || files.len() > 1 {
+        true => 1,
+        false => tree.jobs(),
+    };
+    jobs::set_per_file(per_file);
+    let opts = EncryptOptions {
+        aad: aad.clone(),
+        jobs: per_file,
+        ..opts
+    };
+    if let Some(source) = secret.passphrase_source() {
+        if let Err(e) = single_file(&files, "--passphrase").and_then(|_| not_a_directory(file)) {
+            return Err(CliError::new(ErrorCode::Usage, e));
+        }
+        let prompter = &mut TerminalPrompter;
+        let output = output_for(&output, file, output.output.as_deref());
+        let result = if encrypt {
+            let algorithm = opts.algorithm;
+            let confirm = !secret.no_confirm;
+            let opts = compress::sized(&opts, file).map_err(failure)?;
+            encrypt_with_passphrase(file, &output, &source, prompter, confirm, opts, armor)
+                .map(|summary| (summary, algorithm))
+        } else {
+            decrypt_with_passphrase(file, &output, &source, prompter, &aad)
+        };
+        match result.and_then(|done| match shred {
+            true => shred_original(file).map(|_| {
+                log_shredded(file);
+                done
+            }),
+            false => Ok(done),
+        }) {
+            Ok((summary, algorithm)) => output.report(action, algorithm.name(), file, &summary),
+            Err(e) => {
+                status::error(&e, Some(file), format_args!("Error: {}", e));
+                std::process::exit(e.exit_status());
+            }
+        }
+        output.total();
+        return Ok(());
+    }
+
+    let (recipient, recipients_file, identity) = match direction {
+        Direction::Encrypt(_, seal) => (seal.recipient, seal.recipients_file, None),
+        Direction::Decrypt(open) => (Vec::new(), None, open.identity),
+    };
+    let recipients = match recipients_file {
+        Some(path) => {
+            identity::read_recipients(&path).map_err(|e| CliError::new(ErrorCode::BadKey, e))?
+        }
+        None => recipient,
+    };
+    let distinct: HashSet<_> = recipients.iter().map(recipient_fingerprint).collect();
+    if distinct.len() > MAX_RECIPIENTS {
+        return Err(CliError::new(
+            ErrorCode::Usage,
+            RecipientError::TooManyRecipients,
+        ));
+    }
+    let secret = if !recipients.is_empty() {
+        ChachaSecret::Recipients(recipients)
+    } else if let Some(path) = identity {
+        let identity =
+            identity::read_identity(&path).map_err(|e| CliError::new(ErrorCode::BadKey, e))?;
+        ChachaSecret::Identity(Box::new(identity))
+    } else {
+        ChachaSecret::Key(secret.key.load(key_context(&files)))
+    };
+    let output = output.for_jobs(tree.jobs());
+    let jobs = output.jobs(tree.jobs());
+
+    if tree.recursive {
+        if let Err(e) = single_file(&files, "-r") {
+            return Err(CliError::new(ErrorCode::Usage, e));
+        }
+        let each = |input: &Path, out: Option<&Path>| {
+            let name = input.to_str().ok_or("path is not valid UTF-8")?;
+            if !output.force {
+                let encrypted = looks_encrypted(input)
+                    .map_err(|e| CliError::new(ErrorCode::Io, e.to_string()))?;
+                match (encrypt, encrypted) {
+                    (true, true) => return Ok(Outcome::Skipped("already encrypted".to_string())),
+                    (false, false) => {
+                        return Ok(Outcome::Skipped("not an rcli encrypted file".to_string()))
+                    }
+                    _ => {}
+                }
+            }
+            let per_file = output_for(&output, name, out);
+            let (summary, algorithm) =
+                chacha_file(name, &per_file, &secret, encrypt, &opts, armor, nonce_log)?;
+            let shredded = shred.then(|| shred_original(name));
+            let done = (summary, algorithm.name());
+            Ok(Outcome::Done(reported(
+                per_file, action, name, done, shredded,
+            )))
+        };
+        let tally = recursive::process_tree(
+            Path::new(file),
+            tree.output_dir.as_deref(),
+            &excludes.expect("checked by select_files"),
+            tree.keep_going,
+            action,
+            jobs,
+            each,
+        )
+        .map_err(|e| CliError::new(ErrorCode::Usage, e))?;
+        if !status::json() {
+            log::status(format_args!(
+                "{}",
+                tally.summary(if encrypt { "encrypted" } else { "decrypted" })
+            ));
+        }
+        output.total();
+        if let Some(status) = tally.first_failure {
+            std::process::exit(status);
+        }
+        return Ok(());
+    }
+
+    let failed = for_each_file(&files, jobs, |file| {
+        not_a_directory(file)?;
+        let per_file = output_for(&output, file, output.output.as_deref());
+        let (summary, algorithm) =
+            chacha_file(file, &per_file, &secret, encrypt, &opts, armor, nonce_log)?;
+        let shredded = shred.then(|| shred_original(file));
+        let done = (summary, algorithm.name());
+        Ok(reported(per_file, action, file, done, shredded))
+    });
+    output.total();
+    if let Some(status) = failed {
+        std::process::exit(status);
+    }
+    Ok(())
+}
+
+/* rekey: every file given, or every rcli file under the directory with
+ * -r, from the old secret to the new */
+fn rekey_files(
+    files: FileArgs,
+    old: rekey::OldSecret,
+    new: rekey::NewSecret,
+    tree: TreeArgs,
+    output: OutputArgs,
+) -> Result<(), CliError> {
+    let excludes = files.excludes();
+    let list_on_stdin = files.list_from_stdin();
+    let files = select_files(&files, &output, false)?;
+    let data_on_stdin = list_on_stdin || files.iter().any(|f| f == STDIN_PATH);
+    let (old, new) = rekey::load_secrets(old, new, &mut TerminalPrompter, data_on_stdin)?;
+    let output = output.for_jobs(tree.jobs());
+    let jobs = output.jobs(tree.jobs());
+
+    if tree.recursive {
+        if let Err(e) = single_file(&files, "-r") {
+            return Err(CliError::new(ErrorCode::Usage, e));
+        }
+        let each = |input: &Path, out: Option<&Path>| {
+            let name = input.to_str().ok_or("path is not valid UTF-8")?;
+            if !output.force
+                && !looks_encrypted(input)
+                    .map_err(|e| CliError::new(ErrorCode::Io, e.to_string()))?
+            {
+                return Ok(Outcome::Skipped("not an rcli encrypted file".to_string()));
+            }
+            let per_file = output.writing_to(out.map(Path::to_path_buf));
+            let (summary, algorithm) = rekey::rekey_file(name, &per_file, &old, &new)?;
+            let done = (summary, algorithm.name());
+            Ok(Outcome::Done(reported(
+                per_file,
+                Action::Rekey,
+                name,
+                done,
+                None,
+            )))
+        };
+        let tally = recursive::process_tree(
+            Path::new(&files[0]),
+            tree.output_dir.as_deref(),
+            &excludes.expect("checked by select_files"),
+            tree.keep_going,
+            Action::Rekey,
+            jobs,
+            each,
+        )
+        .map_err(|e| CliError::new(ErrorCode::Usage, e))?;
+        if !status::json() {
+            log::status(format_args!("{}", tally.summary("rekeyed")));
+        }
+        output.total();
+        if let Some(status) = tally.first_failure {
+            std::process::exit(status);
+        }
+        return Ok(());
+    }
+
+    let failed = for_each_file(&files, jobs, |file| {
+        not_a_directory(file)?;
+        let per_file = output.writing_to(output.output.clone());
+        let (summary, algorithm) = rekey::rekey_file(file, &per_file, &old, &new)?;
+        let done = (summary, algorithm.name());
+        Ok(reported(per_file, Action::Rekey, file, done, None))
+    });
+    output.total();
+    if let Some(status) = failed {
+        std::process::exit(status);
+    }
+    Ok(())
+}
+
+/// Printed on standard error before --shred does anything.
+const SHRED_CAVEAT: &str = "\
+Warning: --shred overwrites each original where it lies, but SSDs,
+copy-on-write filesystems (btrfs, ZFS, APFS), snapshots and backups can
+keep copies of the plaintext that no overwrite reaches.";
+
+/* --shred, for `file` once its encrypted copy is safely written. Only
+ * ever reached after a successful write_new, which refuses an output
+ * that is the input itself */
+fn shred_original(file: &str) -> Result<(), CliError> {
+    fs::shred(file, 1).map_err(|e| {
+        CliError::new(
+            ErrorCode::Io,
+            format!("encrypted, but could not shred {}: {}", file, e),
+        )
+    })
+}
+
+fn log_shredded(file: &str) {
+    if !status::json() {
+        log::status(format_args!("Shredded {}", file));
+    }
+}
+
+/* What a batch prints once `file` is done: its status line through
+ * `output`, then, with --shred, how that went */
+fn reported(
+    output: OutputArgs,
+    action: Action,
+    file: &str,
+    (summary, algorithm): (Summary, &'static str),
+    shredded: Option<Result<(), CliError>>,
+) -> Report {
+    let file = file.to_string();
+    Box::new(move || {
+        output.report(action, algorithm, &file, &summary);
+        if let Some(shredded) = shredded {
+            shredded?;
+            log_shredded(&file);
+        }
+        Ok(())
+    })
+}
+
+/* What encrypt, decrypt and chacha use on each file */
+enum ChachaSecret {
+    Key(Vec<u8>),
+    Recipients(Vec<HybridPublicKey>),
+    Identity(Box<HybridSecretKey>),
+}
+
+/* Encrypt or decrypt one file (or standard input) as `output` says,
+ * with the algorithm used: `opts.algorithm`, or what the header names */
+fn chacha_file(
+    file: &str,
+    output: &OutputArgs,
+    secret: &ChachaSecret,
+    encrypt: bool,
+    opts: &EncryptOptions,
+    armor: bool,
+    nonce_log: Option<&NonceLog>,
+) -> Result<(Summary, Algorithm), CliError> {
+    let sealed = |summary| (summary, opts.algorithm);
+    match (secret, encrypt) {
+        (ChachaSecret::Key(key), true) => output
+            .write(file, |reader, writer| {
+                let mut reader = not_encrypted(reader, output.force)?;
+                let opts = compress::sized(opts, file)?;
+                encrypt_to(&mut reader, writer, key, &opts, armor, nonce_log)
+            })
+            .map(sealed)
+            .map_err(failure),
+        (ChachaSecret::Key(key), false) => {
+            let mut algorithm = Algorithm::ChaCha20Poly1305;
+            let summary = output
+                .write(file, |reader, writer| {
+                    let force = output.force;
+                    decrypt_any(file, reader, writer, key, &opts.aad, force, &mut algorithm)
+                })
+                .map_err(failure)?;
+            Ok((summary, algorithm))
+        }
+        (ChachaSecret::Recipients(recipients), _) => output
+            .write(file, |reader, writer| {
+                let mut reader = not_encrypted(reader, output.force)?;
+                encrypt_to_recipients(&mut reader, writer, recipients, opts, armor)
+            })
+            .map(sealed)
+            .map_err(failure),
+        (ChachaSecret::Identity(identity), _) => {
+            decrypt_with_identity(file, output, identity, &opts.aad)
+        }
+    }
+}
+
+/* As `encrypt_to`, sealing a fresh file key to each of `recipients` */
+fn encrypt_to_recipients(
+    mut reader: &mut dyn Read,
+    mut writer: &mut dyn Write,
+    recipients: &[HybridPublicKey],
+    opts: &EncryptOptions,
+    armor: bool,
+) -> Result<Summary, FsCryptoError> {
+    let encrypt = |mut reader: &mut dyn Read, mut writer: &mut dyn Write| {
+        encrypt_stream_to_recipients(&mut reader, &mut writer, recipients, opts).map_err(
+            |e| match e {
+                RecipientError::Fs(e) => e,
+                /* Checked against MAX_RECIPIENTS while the arguments are read */
+                _ => unreachable!("{}", e),
+            },
+        )
+    };
+    if !armor {
+        return encrypt(&mut reader, &mut writer);
+    }
+    let mut armored = ArmorWriter::new(writer)?;
+    let summary = encrypt(&mut reader, &mut armored)?;
+    armored.finish()?;
+    Ok(summary)
+}
+
+/* A failure to open the sealed file key is carried out with
+ * `status::carry`, and the header's algorithm in `algorithm`, as in
+ * `decrypt_with_passphrase` */
+fn decrypt_with_identity(
+    file: &str,
+    output: &OutputArgs,
+    identity: &HybridSecretKey,
+    aad: &[u8],
+) -> Result<(Summary, Algorithm), CliError> {
+    let mut algorithm = Algorithm::ChaCha20Poly1305;
+    let result = output.write(file, |reader, mut writer| {
+        let mut reader = dearmored(reader)?;
+        let (header, header_bytes) = fs::read_header(&mut reader)?;
+        log_header(file, &header);
+        algorithm = header.algorithm;
+        let mut reader = Cursor::new(header_bytes).chain(reader);
+        let message = match decrypt_stream_with_identity(&mut reader, &mut writer, identity, aad) {
+            Ok(summary) => return Ok(summary),
+            Err(RecipientError::Fs(e)) => return Err(e),
+            Err(RecipientError::NotForRecipient) => format!(
+                "{} was not encrypted to a recipient; decrypt it with its key or passphrase",
+                file
+            ),
+            Err(e) => format!("this identity cannot decrypt {}: {}", file, e),
+        };
+        Err(status::carry(CliError::new(ErrorCode::WrongKey, message)))
+    });
+    result.map(|summary| (summary, algorithm)).map_err(failure)
+}
+
+/// Where verify gets its key.
+enum VerifyKey {
+    Key(Vec<u8>),
+    Passphrase(PassphraseSource),
+}
+
+/* Authenticate every chunk of `reader`, returning the plaintext size and
+ * the header's algorithm, or the first problem */
+fn verify_stream(
+    file: &str,
+    reader: &mut dyn Read,
+    key: &VerifyKey,
+    prompter: &mut dyn Prompter,
+    aad: &[u8],
+) -> Result<(u64, Algorithm), CliError> {
+    let mut reader = dearmored(reader).map_err(failure)?;
+    let (header, header_bytes) = fs::read_header(&mut reader).map_err(failure)?;
+    log_header(file, &header);
+    let derived;
+    let key_bytes = match key {
+        VerifyKey::Key(key) => key.as_slice(),
+        VerifyKey::Passphrase(source) => {
+            derived = passphrase_key(file, &header, source, prompter)?;
+            derived.as_bytes()
+        }
+    };
+    let verifier =
+        fs::StreamVerifier::with_aad(Cursor::new(header_bytes).chain(reader), key_bytes, aad)
+            .map_err(|e| match (e, key) {
+                (FsCryptoError::Crypto(ErrorStates::WrongKey), VerifyKey::Passphrase(_)) => {
+                    CliError::new(ErrorCode::WrongKey, "incorrect passphrase")
+                }
+                (e, _) => failure(e),
+            })?;
+    let len = verifier.verify_all().map_err(|e| {
+        let chunk = e.chunk_index;
+        let message = match &e.error {
+            /* With a key check value a wrong key is caught before any chunk */
+            FsCryptoError::Crypto(ErrorStates::DecryptionFailed) if header.key_check.is_some() => {
+                format!("chunk {} failed authentication; the file is damaged", chunk)
+            }
+            FsCryptoError::Crypto(ErrorStates::DecryptionFailed) => format!(
+                "chunk {} failed authentication; wrong key or damaged file",
+                chunk
+            ),
+            FsCryptoError::Crypto(ErrorStates::StreamTruncated) => {
+                format!(
+                    "truncated at chunk {}; the rest of the file is missing",
+                    chunk
+                )
+            }
+            _ => format!("chunk {}: {}", chunk, e.error),
+        };
+        CliError::new(status::error_code(&e.error), message)
+    })?;
+    Ok((len, header.algorithm))
+}
+
+/* --chunk-size: bytes, or a K/M suffix for KiB/MiB, within the header's
+ * limits */
+fn parse_chunk_size(text: &str) -> Result<u32, String> {
+    let (digits, unit) = match text.strip_suffix(['K', 'k']) {
+        Some(digits) => (digits, 1024),
+        None => match text.strip_suffix(['M', 'm']) {
+            Some(digits) => (digits, 1024 * 1024),
+            None => (text, 1),
+        },
+    };
+    let size = digits
+        .parse::<u32>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| format!("{:?} is not a size in bytes", text))?;
+    if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&size) {
+        return Err(format!(
+            "chunk size must be between {} and {} bytes",
+            MIN_CHUNK_SIZE, MAX_CHUNK_SIZE
+        ));
+    }
+    Ok(size)
+}
+
+/* Whether `prefix`, the first ARMOR_BEGIN.len() bytes or all there are,
+ * starts like something chacha wrote: the rcli magic, or its armor */
+fn encrypted_prefix(prefix: &[u8]) -> bool {
+    FileHeader::has_magic(prefix) || is_armored(prefix)
+}
+
+fn looks_encrypted(path: &Path) -> std::io::Result<bool> {
+    let mut prefix = Vec::with_capacity(ARMOR_BEGIN.len());
+    File::open(path)?
+        .take(ARMOR_BEGIN.len() as u64)
+        .read_to_end(&mut prefix)?;
+    Ok(encrypted_prefix(&prefix))
+}
+
+/* Whether `reader` looks encrypted, and `reader` as it was, deciding from
+ * the first bytes so standard input is read only once */
+fn peek_encrypted<'a>(mut reader: &'a mut dyn Read) -> std::io::Result<(bool, impl Read + 'a)> {
+    let mut prefix = Vec::with_capacity(ARMOR_BEGIN.len());
+    (&mut reader)
+        .take(ARMOR_BEGIN.len() as u64)
+        .read_to_end(&mut prefix)?;
+    Ok((encrypted_prefix(&prefix), Cursor::new(prefix).chain(reader)))
+}
+
+/* `reader` for encryption, unless it is already encrypted and not
+ * `force`d */
+fn not_encrypted<'a>(
+    reader: &'a mut dyn Read,
+    force: bool,
+) -> Result<impl Read + 'a, FsCryptoError> {
+    match peek_encrypted(reader)? {
+        (true, _) if !force => Err(FsCryptoError::Io(std::io::Error::other(
+            status::AlreadyEncrypted,
+        ))),
+        (_, reader) => Ok(reader),
+    }
+}
+
+/* Encrypt `reader` to `writer`, wrapped in ASCII armor with `armor`,
+ * under a nonce prefix claimed in `nonce_log` if there is one */
+fn encrypt_to(
+    mut reader: &mut dyn Read,
+    mut writer: &mut dyn Write,
+    key_bytes: &[u8],
+    opts: &EncryptOptions,
+    armor: bool,
+    nonce_log: Option<&NonceLog>,
+) -> Result<Summary, FsCryptoError> {
+    let prefix = match nonce_log {
+        Some(log) => {
+            let prefix = log
+                .claim(key_bytes, &SystemEntropy)
+                .map_err(std::io::Error::other)?;
+            log::verbose(format_args!(
+                "Nonce log {}: key {}",
+                log.path().display(),
+                nonce_log::fingerprint(key_bytes)
+            ));
+            Some(prefix)
+        }
+        None => None,
+    };
+    let mut seal = |mut writer: &mut dyn Write| match prefix {
+        Some(prefix) => {
+            fs::encrypt_stream_with_nonce_prefix(&mut reader, &mut writer, key_bytes, opts, prefix)
+        }
+        None => fs::encrypt_stream(&mut reader, &mut writer, key_bytes, opts),
+    };
+    if !armor {
+        return seal(&mut writer);
+    }
+    let mut armored = ArmorWriter::new(writer)?;
+    let summary = seal(&mut armored)?;
+    armored.finish()?;
+    Ok(summary)
+}
+
+/* `reader` with any ASCII armor taken off, deciding from the first line
+ * so standard input is read only once */
+fn dearmored<'a, R: Read + 'a>(reader: R) -> Result<Box<dyn Read + 'a>, FsCryptoError> {
+    dearmored_if_armored(reader).map(|(reader, _)| reader)
+}
+
+/* As `dearmored`, also saying whether there was armor */
+fn dearmored_if_armored<'a, R: Read + 'a>(
+    mut reader: R,
+) -> Result<(Box<dyn Read + 'a>, bool), FsCryptoError> {
+    let mut prefix = Vec::with_capacity(ARMOR_BEGIN.len());
+    (&mut reader)
+        .take(ARMOR_BEGIN.len() as u64)
+        .read_to_end(&mut prefix)?;
+    let armored = is_armored(&prefix);
+    let reader = Cursor::new(prefix).chain(reader);
+    if armored {
+        Ok((Box::new(ArmorReader::new(BufReader::new(reader))?), true))
+    } else {
+        Ok((Box::new(reader), false))
+    }
+}
+
+/* Armor problems reach us as I/O errors; show them without the
+ * "I/O error" prefix, since the fix is in the pasted text rather than
+ * the disk */
+fn describe(e: FsCryptoError) -> String {
+    let e = match e {
+        FsCryptoError::Io(io)
+            if io
+                .get_ref()
+                .is_some_and(|inner| inner.is::<status::BackedUp>()) =>
+        {
+            let inner = io.into_inner().expect("checked above");
+            let backed_up = inner.downcast::<status::BackedUp>().expect("checked above");
+            return format!(
+                "{} (the original is intact in {})",
+                describe(backed_up.error),
+                backed_up.backup.display()
+            );
+        }
+        e => e,
+    };
+    if let FsCryptoError::Io(io) = &e {
+        if let Some(carried) = io
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<CliError>())
+        {
+            return carried.message.clone();
+        }
+        if let Some(armor) = io
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<ArmorError>())
+        {
+            return armor.to_string();
+        }
+        if let Some(refusal) = io
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<status::AlreadyEncrypted>())
+        {
+            return refusal.to_string();
+        }
+        if let Some(part) = io
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<status::SplitPart>())
+        {
+            return part.to_string();
+        }
+        match io
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<CompressError>())
+        {
+            Some(CompressError::TooLarge { limit }) => {
+                return format!(
+                    "file decompresses to more than {} bytes; raise the limit with \
+                     --max-decompressed-size if it is expected",
+                    limit
+                )
+            }
+            Some(e) => return e.to_string(),
+            None => {}
+        }
+    }
+    match e {
+        FsCryptoError::Crypto(ErrorStates::AadMismatch) => {
+            "associated data does not match: decrypt with the same --aad and --aad-file \
+             values, in the same order, as when encrypting"
+                .to_string()
+        }
+        FsCryptoError::Header(HeaderError::Recipient) => {
+            "file was encrypted to a recipient's public key, so no key or passphrase \
+             opens it; decrypt it with --identity and the recipient's identity file"
+                .to_string()
+        }
+        e => e.to_string(),
+    }
+}
+
+/* `e` as --json reports it */
+fn failure(e: FsCryptoError) -> CliError {
+    CliError::new(status::error_code(&e), describe(e))
+}
+
+/* Decrypt rcli files, or with `force` (and without the magic) files
+ * written before the chunked format, deciding from the first bytes so
+ * standard input is read only once. Armored input is de-armored first.
+ * The old format has no associated data, so any `aad` is a mismatch
+ * there. `algorithm` is set to the one the header names (the old format
+ * is always ChaCha20-Poly1305). */
+fn decrypt_any(
+    file: &str,
+    reader: &mut dyn Read,
+    mut writer: &mut dyn Write,
+    key_bytes: &[u8],
+    aad: &[u8],
+    force: bool,
+    algorithm: &mut Algorithm,
+) -> Result<Summary, FsCryptoError> {
+    let mut reader = dearmored(reader)?;
+    let mut magic = Vec::with_capacity(MAGIC.len());
+    (&mut reader)
+        .take(MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    let is_rcli = FileHeader::has_magic(&magic);
+    let is_part = split::is_part(&magic);
+    let mut reader = Cursor::new(magic).chain(reader);
+    if is_rcli {
+        let (header, header_bytes) = fs::read_header(&mut reader)?;
+        log_header(file, &header);
+        *algorithm = header.algorithm;
+        let mut reader = Cursor::new(header_bytes).chain(reader);
+        let compressed = header.compression.is_some();
+        compress::decrypt(&mut reader, &mut writer, key_bytes, aad, compressed)
+    } else if is_part {
+        Err(FsCryptoError::Io(std::io::Error::other(status::SplitPart)))
+    } else if !force {
+        Err(FsCryptoError::Header(HeaderError::BadMagic))
+    } else if !aad.is_empty() {
+        Err(FsCryptoError::Crypto(ErrorStates::AadMismatch))
+    } else {
+        decrypt_single_shot(&mut reader, &mut writer, key_bytes)
+    }
+}
+
+/* Files written before the chunked format: nonce (12 bytes) || ciphertext+tag */
+fn decrypt_single_shot(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    key_bytes: &[u8],
+) -> Result<Summary, FsCryptoError> {
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents)?;
+    if contents.len() < 12 {
+        return Err(FsCryptoError::Crypto(ErrorStates::DecryptionFailed));
+    }
+
+    let nonce_bytes: [u8; 12] = contents[..12].try_into().unwrap();
+    let ciphertext = contents[12..].to_vec();
+    let plaintext = chacha20_poly1305_cipher(key_bytes, &nonce_bytes, ciphertext, false)?;
+
+    writer.write_all(&plaintext)?;
+    Ok(Summary {
+        bytes_in: contents.len() as u64,
+        bytes_out: plaintext.len() as u64,
+        chunks: 1,
+    })
+}
+
+/* RC4 has no header to check, but chacha's output is worth a warning */
+fn warn_if_encrypted(file: &str, encrypted: bool) {
+    if encrypted {
+        eprintln!(
+            "Warning: {} is an rcli encrypted file; RC4 will garble it \
+             (use chacha --decrypt)",
+            file
+        );
+    }
+}
+
+/* RC4 over a stream in bounded memory, for standard input */
+fn rc4_stream(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    key_bytes: &[u8],
+) -> Result<Summary, FsCryptoError> {
+    let mut rc4 = Rc4::new(key_bytes);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut total = 0u64;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        rc4.apply_keystream(&mut buf[..n]);
+        writer.write_all(&buf[..n])?;
+        total += n as u64;
+    }
+    Ok(Summary {
+        bytes_in: total,
+        bytes_out: total,
+        chunks: 1,
+    })
+}
+
+/* The expanded file list. --output names one result, so it needs
+ * exactly one input, and a list on standard input leaves no room for the
+ * key there. */
+fn select_files(
+    args: &FileArgs,
+    output: &OutputArgs,
+    key_stdin: bool,
+) -> Result<Vec<String>, CliError> {
+    if key_stdin && args.list_from_stdin() {
+        return Err(CliError::new(
+            ErrorCode::Usage,
+            format!(
+                "--files-from {} and --key-stdin cannot both read standard input; \
+                 pass the key another way",
+                STDIN_PATH
+            ),
+        ));
+    }
+    let files = args.expand().and_then(|files| match output.output {
+        Some(_) => single_file(&files, "--output").map(|_| files),
+        None => Ok(files),
+    });
+    files.map_err(|e| CliError::new(ErrorCode::Usage, e))
+}
+
+fn single_file(files: &[String], option: &str) -> Result<(), String> {
+    match files.len() {
+        1 => Ok(()),
+        n => Err(format!("{} takes a single file, not {}", option, n)),
+    }
+}
+
+fn not_a_directory(file: &str) -> Result<(), String> {
+    match Path::new(file).is_dir() {
+        true => Err(format!(
+            "{} is a directory; use -r to process the files in it",
+            file
+        )),
+        false => Ok(()),
+    }
+}
+
+/* What `key.load` needs to know about the data: whether it is standard
+ * input, which --key-stdin cannot share */
+fn key_context(files: &[String]) -> &str {
+    match files {
+        [only] => only,
+        _ => "",
+    }
+}
+
+/* Run `op` on each file, as many at once as `jobs` says, reporting
+ * failures and carrying on; each file's report or error comes out in
+ * turn. The exit status for the first failure, if any */
+fn for_each_file<F>(files: &[String], jobs: Jobs, op: F) -> Option<i32>
+where
+    F: Fn(&str) -> Result<Report, CliError> + Sync,
+{
+    let sizes = files.iter().filter_map(|file| std::fs::metadata(file).ok());
+    jobs.start(sizes.map(|metadata| metadata.len()).sum());
+    let mut failed = None;
+    jobs.run(
+        files,
+        |file| op(file),
+        |file, result| {
+            if let Err(e) = result.and_then(|report| report()) {
+                match files.len() {
+                    1 => status::error(&e, Some(file), format_args!("Error: {}", e)),
+                    _ => status::error(&e, Some(file), format_args!("Error: {}: {}", file, e)),
+                }
+                failed = failed.or(Some(e.exit_status()));
+            }
+            true
+        },
+    );
+    failed
+}
+
+/* `file` opened for reading, or an error naming it */
+fn open(file: &str) -> Result<File, CliError> {
+    File::open(file).map_err(|e| CliError::new(ErrorCode::Io, format!("{}: {}", file, e)))
+}
+
+fn main() {
+    interrupt::install();
+    let flag = config::flag_from_args(std::env::args_os());
+    match config::Config::load(flag, |name| std::env::var(name).ok()) {
+        Ok(loaded) => config::install(loaded),
+        Err(e) => {
+            if json_requested() {
+                status::enable_json();
+            }
+            status::fail(e)
+        }
+    }
+    let cli = parse_args(config::get()).unwrap_or_else(|e| usage_error(e));
+    if cli.json {
+        status::enable_json();
+    }
+    log::set_level(Level::from_flags(cli.quiet, cli.verbose));
+    let stdin_is_tty = std::io::stdin().is_terminal();
+    clobber::set_policy(clobber::Policy::decide(cli.no_clobber, stdin_is_tty));
+    if let Err(e) = run(cli) {
+        status::fail(e);
+    }
+}
+
+/* Cli::try_parse, except that with a key-file in the config the key
+ * options of encrypt, decrypt, chacha and verify are no longer required */
+fn parse_args(config: &config::Config) -> Result<Cli, clap::Error> {
+    let mut cmd = Cli::command();
+    if config.settings().key_file.is_some() {
+        cmd = key_optional(cmd);
+    }
+    let mut matches = cmd.try_get_matches_from_mut(std::env::args_os())?;
+    Cli::from_arg_matches_mut(&mut matches).map_err(|e| e.format(&mut cmd))
+}
+
+/* `cmd` with the ChachaKey group optional in every subcommand, however
+ * deep, for when the config names a key file */
+fn key_optional(cmd: clap::Command) -> clap::Command {
+    cmd.mut_subcommands(|sub| {
+        let keyed = sub.get_groups().any(|group| group.get_id() == "ChachaKey");
+        let sub = key_optional(sub);
+        match keyed {
+            true => sub.mut_group("ChachaKey", |group| group.required(false)),
+            false => sub,
+        }
+    })
+}
+
+/* Whether --json is among the arguments, before they are parsed */
+fn json_requested() -> bool {
+    std::env::args_os()
+        .skip(1)
+        .take_while(|arg| arg != "--")
+        .any(|arg| arg == "--json")
+}
+
+/* clap's report and exit status, or with --json among the arguments an
+ * error record; --help and --version print as usual either way */
+fn usage_error(e: clap::Error) -> ! {
+    if !json_requested() || !e.use_stderr() {
+        e.exit();
+    }
+    status::enable_json();
+    let text = e.to_string();
+    let first = text.lines().next().unwrap_or_default();
+    status::fail(CliError::new(
+        ErrorCode::Usage,
+        first.strip_prefix("error: ").unwrap_or(first),
+    ));
+}
+
+fn run(cli: Cli) -> Result<(), CliError> {
+    match cli.command {
+        Commands::Keygen {
+            words: Some(words),
+            separator,
+            ..
+        } => {
+            let words = usize::from(words);
+            println!("{}", crypto::diceware_passphrase(words, &separator));
+            log::note(format_args!(
+                "Entropy: {:.1} bits ({} words from a list of {})",
+                crypto::diceware_entropy_bits(words),
+                words,
+                crypto::DICEWARE_WORD_COUNT
+            ));
+        }
+        Commands::Keygen {
+            algorithm,
+            bytes,
+            format,
+            out,
+            force,
+            words: None,
+            ..
+        } => {
+            let stdout_is_tty = std::io::stdout().is_terminal();
+            let size = check_key_output(format, out.is_some(), stdout_is_tty)
+                .and_then(|_| algorithm.key_size(bytes))
+                .map_err(|e| CliError::new(ErrorCode::Usage, e))?;
+            let mut key_bytes = vec![0u8; size];
+            generate_key(&mut key_bytes);
+            let encoded = format_key(&key_bytes, format);
+            match out {
+                Some(path) => {
+                    write_key_file(&path, &encoded, force)
+                        .map_err(|e| CliError::new(ErrorCode::Io, e))?;
+                    println!("{}", key_fingerprint(&key_bytes));
+                }
+                None => {
+                    if stdout_is_tty {
+                        eprintln!(
+                            "Warning: the key will stay in your scrollback and any terminal \
+                             logs; use --out to write it to a file instead"
+                        );
+                    }
+                    let mut stdout = std::io::stdout().lock();
+                    stdout.write_all(&encoded)?;
+                    stdout.flush()?;
+                }
+            }
+        }
+        Commands::Pwgen {
+            length,
+            no_lower,
+            no_upper,
+            no_digits,
+            symbols,
+            no_ambiguous,
+            count,
+            show_entropy,
+        } => {
+            let policy = crypto::PasswordPolicy {
+                length: usize::from(length),
+                lowercase: !no_lower,
+                uppercase: !no_upper,
+                digits: !no_digits,
+                symbols,
+                exclude_ambiguous: no_ambiguous,
+            };
+            let bits = crypto::password_entropy_bits(&policy)
+                .map_err(|e| CliError::new(ErrorCode::Usage, e))?;
+            let mut stdout = std::io::stdout().lock();
+            for _ in 0..count {
+                /* The policy was checked above */
+                let password = crypto::generate_password(&policy).unwrap();
+                writeln!(stdout, "{}", password)?;
+            }
+            stdout.flush()?;
+            if show_entropy {
+                log::note(format_args!("Entropy: {:.1} bits per password", bits));
+            }
+        }
+        Commands::Rand {
+            bytes,
+            format,
+            out,
+            sensitive,
+        } => {
+            match out {
+                Some(path) => open_output(&path, sensitive)
+                    .and_then(|file| {
+                        let mut writer = BufWriter::new(file);
+                        write_random(&mut writer, bytes, format, generate_key)?;
+                        writer.into_inner()?.sync_all()
+                    })
+                    .map_err(|e| {
+                        CliError::new(
+                            ErrorCode::Io,
+                            format!("cannot write {}: {}", path.display(), e),
+                        )
+                    })?,
+                None => {
+                    check_rand_output(format, std::io::stdout().is_terminal())
+                        .map_err(|e| CliError::new(ErrorCode::Usage, e))?;
+                    match write_random(&mut std::io::stdout().lock(), bytes, format, generate_key) {
+                        /* `rcli rand ... | head -c 16`: the reader has all it wants */
+                        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+                        result => result?,
+                    }
+                }
+            }
+        }
+        Commands::Rc4 {
+            files,
+            key,
+            output,
+            insecure_ok,
+            mmap,
+        } => {
+            if output.force && output.output.is_none() {
+                return Err(CliError::new(
+                    ErrorCode::Usage,
+                    "--force means nothing to rc4 without --output",
+                ));
+            }
+            let allow = std::env::var(insecure::ALLOW_RC4_ENV).ok();
+            let consent = Consent::decide(
+                insecure_ok,
+                allow.as_deref(),
+                std::io::stderr().is_terminal(),
+            );
+            insecure::acknowledge_rc4(consent, &mut insecure::TtyPrompter)?;
+            let files = select_files(&files, &output, key.reads_stdin())?;
+            let key_bytes = key.load(key_context(&files));
+
+            let failed = for_each_file(&files, Jobs::one(), |file| {
+                let per_file = output.writing_to(output.output.clone());
+                if mmap && file != STDIN_PATH {
+                    let (mut rc4, mut first) = (Rc4::new(&key_bytes), true);
+                    let mapped = per_file
+                        .write_mapped(file, |window| {
+                            if std::mem::take(&mut first) {
+                                let prefix = &window[..window.len().min(ARMOR_BEGIN.len())];
+                                warn_if_encrypted(file, encrypted_prefix(prefix));
+                            }
+                            rc4.apply_keystream(window);
+                        })
+                        .map_err(failure)?;
+                    if let Some(summary) = mapped {
+                        let done = (summary, status::RC4);
+                        return Ok(reported(per_file, Action::Process, file, done, None));
+                    }
+                }
+                let summary = per_file
+                    .write(file, |reader, writer| {
+                        let (encrypted, mut reader) = peek_encrypted(reader)?;
+                        warn_if_encrypted(file, encrypted);
+                        if file == STDIN_PATH {
+                            return rc4_stream(&mut reader, writer, &key_bytes);
+                        }
+                        let mut contents = Vec::new();
+                        reader.read_to_end(&mut contents)?;
+                        Rc4::apply_keystream_static(&key_bytes, &mut contents);
+                        writer.write_all(&contents)?;
+                        Ok(Summary {
+                            bytes_in: contents.len() as u64,
+                            bytes_out: contents.len() as u64,
+                            chunks: 1,
+                        })
+                    })
+                    .map_err(failure)?;
+                let done = (summary, status::RC4);
+                Ok(reported(per_file, Action::Process, file, done, None))
+            });
+            output.total();
+            if let Some(status) = failed {
+                std::process::exit(status);
+            }
+        }
+        Commands::Encrypt {
+            files,
+            secret,
+            algorithm,
+            seal,
+            tree,
+            keep,
+            aad,
+            output,
+        } => {
+            let algorithm = algorithm
+                .or(config::get().settings().algorithm)
+                .unwrap_or(CipherAlg::Chacha20poly1305);
+            let direction = Direction::Encrypt(algorithm.into(), seal);
+            cipher(files, secret, direction, tree, keep, aad, output)?;
+        }
+        Commands::Decrypt {
+            files,
+            secret,
+            open,
+            tree,
+            keep,
+            aad,
+            output,
+        } => {
+            let direction = Direction::Decrypt(open);
+            cipher(files, secret, direction, tree, keep, aad, output)?;
+        }
+        Commands::Chacha {
+            files,
+            secret,
+            seal,
+            open,
+            encrypt,
+            decrypt,
+            tree,
+            keep,
+            aad,
+            output,
+        } => {
+            let direction = match (encrypt, decrypt) {
+                (true, _) => Direction::Encrypt(Algorithm::ChaCha20Poly1305, seal),
+                (_, true) => Direction::Decrypt(open),
+                _ => {
+                    return Err(CliError::new(
+                        ErrorCode::Usage,
+                        "either --encrypt or --decrypt must be specified",
+                    ))
+                }
+            };
+            cipher(files, secret, direction, tree, keep, aad, output)?;
+        }
+        Commands::Rekey {
+            files,
+            old,
+            new,
+            tree,
+            output,
+        } => rekey_files(files, old, new, tree, output)?,
+        Commands::Join { parts, out, force } => {
+            let (summary, count) = join::join(&parts, &out, force)?;
+            let (joined, out) = (format!("{} parts", count), out.display().to_string());
+            report_archive(Action::Join, &joined, &out, &summary);
+        }
+        Commands::Archive {
+            command:
+                ArchiveCommand::Create {
+                    file,
+                    out,
+                    key,
+                    chunk_size,
+                    pad,
+                    compress,
+                    force,
+                },
+        } => {
+            let key = key.load(&file.to_string_lossy());
+            let opts = EncryptOptions {
+                chunk_size: chunk_size
+                    .or(config::get().settings().chunk_size)
+                    .unwrap_or(DEFAULT_CHUNK_SIZE),
+                padding: pad,
+                compression: compress.and_then(Compression::codec),
+                ..EncryptOptions::default()
+            };
+            let summary = archive::create(&file, &out, &key, &opts, force)?;
+            let (dir, out) = (file.display().to_string(), out.display().to_string());
+            report_archive(Action::Archive, &dir, &out, &summary);
+        }
+        Commands::Archive {
+            command:
+                ArchiveCommand::Extract {
+                    archive,
+                    into,
+                    entry,
+                    out,
+                    force,
+                    max_decompressed_size,
+                    key,
+                },
+        } => {
+            if let Some(limit) = max_decompressed_size {
+                compress::set_limit(limit);
+            }
+            let key = key.load(&archive.to_string_lossy());
+            let (written, to) = match (out, into) {
+                (Some(out), _) => (
+                    archive::extract_one(&archive, &entry, &out, force, &key)?,
+                    out,
+                ),
+                (None, Some(into)) => {
+                    (archive::extract(&archive, &into, &entry, &key)?.bytes, into)
+                }
+                (None, None) => unreachable!("clap requires --into without --out"),
+            };
+            let summary = Summary {
+                bytes_in: std::fs::metadata(&archive).map_or(0, |m| m.len()),
+                bytes_out: written,
+                chunks: 0,
+            };
+            let (archive, to) = (archive.display().to_string(), to.display().to_string());
+            report_archive(Action::Extract, &archive, &to, &summary);
+        }
+        Commands::Archive {
+            command:
+                ArchiveCommand::List {
+                    archive,
+                    max_decompressed_size,
+                    key,
+                },
+        } => {
+            if let Some(limit) = max_decompressed_size {
+                compress::set_limit(limit);
+            }
+            let key = key.load(&archive.to_string_lossy());
+            archive::list(&archive, &key)?;
+        }
+        Commands::Verify {
+            file,
+            key,
+            passphrase,
+            passphrase_file,
+            aad,
+        } => {
+            let source = match passphrase_file {
+                Some(path) => Some(PassphraseSource::File(path)),
+                None => passphrase.then(PassphraseSource::interactive),
+            };
+            let key = match source {
+                Some(source) => {
+                    if let Err(e) = check_passphrase_source(&file, &source) {
+                        return Err(CliError::new(ErrorCode::Usage, e));
+                    }
+                    VerifyKey::Passphrase(source)
+                }
+                None => VerifyKey::Key(key.load(&file)),
+            };
+            let aad = aad.load().map_err(|e| CliError::new(ErrorCode::Usage, e))?;
+            let reader: Box<dyn Read> = if file == STDIN_PATH {
+                Box::new(std::io::stdin().lock())
+            } else {
+                Box::new(BufReader::new(open(&file)?))
+            };
+            let mut read = ByteCount::default();
+            let mut reader = ProgressReader::new(reader, &mut read);
+            let started = Instant::now();
+            let verified = verify_stream(&file, &mut reader, &key, &mut TerminalPrompter, &aad);
+            if let Ok((len, _)) = verified {
+                log::verbose(format_args!(
+                    "{}: {} bytes in, {} bytes of plaintext, {:.3} s",
+                    file,
+                    read.0,
+                    len,
+                    started.elapsed().as_secs_f64()
+                ));
+            }
+
+            match verified {
+                Ok(_) if !log::enabled(Level::Normal) => {}
+                Ok((len, algorithm)) if status::json() => status::emit(&Record::Ok {
+                    action: Action::Verify,
+                    path: &file,
+                    output: None,
+                    bytes_in: read.0,
+                    bytes_out: len,
+                    algorithm: algorithm.name(),
+                    stats: None,
+                }),
+                Ok((len, _)) => println!("Verified {} ({} bytes of plaintext)", file, len),
+                Err(e) => {
+                    status::error(
+                        &e,
+                        Some(&file),
+                        format_args!("Error: {} failed verification: {}", file, e),
+                    );
+                    std::process::exit(e.exit_status());
+                }
+            }
+        }
+        Commands::Bench {
+            size,
+            iterations,
+            chunk_size,
+            disk_dir,
+        } => {
+            let plan = bench::Plan {
+                size,
+                iterations,
+                chunk_sizes: match chunk_size.is_empty() {
+                    true => bench::DEFAULT_CHUNK_SIZES.to_vec(),
+                    false => chunk_size,
+                },
+                disk_dir,
+            };
+            if !cli.json {
+                println!("{}", bench::heading());
+            }
+            bench::run(&plan, |measurement| match cli.json {
+                true => println!(
+                    "{}",
+                    serde_json::to_string(measurement).expect("measurements always serialize")
+                ),
+                false => println!("{}", measurement.to_row()),
+            })?;
+        }
+        Commands::Inspect { file } => {
+            let reader: Box<dyn Read> = if file == STDIN_PATH {
+                Box::new(std::io::stdin().lock())
+            } else {
+                Box::new(BufReader::new(open(&file)?))
+            };
+            match inspect::inspect(reader) {
+                Ok(found) if cli.json => println!("{}", found.to_json(&file)),
+                Ok(found) => print!("{}", found.to_text(&file)),
+                Err(e) => {
+                    let e = failure(e);
+                    status::error(&e, Some(&file), format_args!("Error: {}: {}", file, e));
+                    std::process::exit(e.exit_status());
+                }
+            }
+        }
+        Commands::Hmac {
+            file,
+            key,
+            algorithm,
+            verify,
+        } => {
+            let key = key.load(&file, algorithm.key_len());
+            let expected = verify
+                .map(|tag| hmac::parse_tag(&tag))
+                .transpose()
+                .map_err(|e| CliError::new(ErrorCode::Usage, e))?;
+            let reader: Box<dyn Read> = if file == STDIN_PATH {
+                Box::new(std::io::stdin().lock())
+            } else {
+                Box::new(open(&file)?)
+            };
+            match expected {
+                None => println!("{}", hmac::compute_tag(algorithm, &key, reader)?),
+                Some(tag) => {
+                    if hmac::verify_tag(algorithm, &key, reader, &tag)? {
+                        log::status(format_args!("{}: OK", file));
+                    } else {
+                        println!("{}: FAILED", file);
+                        std::process::exit(exit::AUTH);
+                    }
+                }
+            }
+        }
+        Commands::Keypair { out_prefix, force } => {
+            let sk = crypto::ed25519_generate();
+            let (secret_path, public_path) = sign::write_keypair(&sk, &out_prefix, force)
+                .map_err(|e| CliError::new(ErrorCode::Io, e))?;
+            log::status(format_args!(
+                "Wrote {} and {} (key ID {})",
+                secret_path.display(),
+                public_path.display(),
+                sk.public_key().key_id_hex()
+            ));
+        }
+        Commands::Sign {
+            key,
+            file,
+            prehash,
+            out,
+            force,
+        } => {
+            let sk =
+                sign::read_signing_key(&key).map_err(|e| CliError::new(ErrorCode::BadKey, e))?;
+            let out = out.unwrap_or_else(|| sign::with_suffix(Path::new(&file), ".sig"));
+            let reader: Box<dyn Read> = if file == STDIN_PATH {
+                Box::new(std::io::stdin().lock())
+            } else {
+                Box::new(open(&file)?)
+            };
+            let envelope = sign::sign_reader(&sk, prehash.into(), reader)?;
+            if let Err(e) = sign::write_new_file(&out, envelope.to_text().as_bytes(), force) {
+                return Err(CliError::new(ErrorCode::Io, e));
+            }
+            log::status(format_args!(
+                "Signed {}; signature in {}",
+                file,
+                out.display()
+            ));
+        }
+        Commands::Identity {
+            command: IdentityCommand::New { out, force },
+        } => {
+            let secret = crypto::mlkem_generate();
+            identity::write_identity(&secret, &out, force)
+                .map_err(|e| CliError::new(ErrorCode::Io, e))?;
+            log::note(format_args!(
+                "Wrote {}; your recipient string is:",
+                out.display()
+            ));
+            println!("{}", identity::recipient_string(&secret.to_public()));
+        }
+        Commands::VerifySig {
+            public_key,
+            file,
+            sig,
+        } => {
+            let sig = sig.unwrap_or_else(|| sign::with_suffix(Path::new(&file), ".sig"));
+            let pk = sign::read_verifying_key(&public_key)
+                .map_err(|e| CliError::new(ErrorCode::BadKey, e))?;
+            let envelope = sign::read_signature(&sig)?;
+            let reader: Box<dyn Read> = if file == STDIN_PATH {
+                Box::new(std::io::stdin().lock())
+            } else {
+                Box::new(open(&file)?)
+            };
+            sign::verify_reader(&pk, &envelope, reader)
+                .map_err(|e| CliError::new(e.code, format!("{}: {}", file, e)))?;
+            log::status(format_args!(
+                "Good signature on {} from key {}",
+                file,
+                pk.key_id_hex()
+            ));
+        }
+        Commands::Config {
+            command: ConfigCommand::Show,
+        } => print!("{}", config::get().show()),
+        Commands::NonceLog {
+            command: NonceLogCommand::Compact { log, retire },
+        } => {
+            let path = log
+                .or_else(|| config::get().settings().nonce_log.clone())
+                .ok_or_else(|| {
+                    CliError::new(
+                        ErrorCode::Usage,
+                        "no nonce log: pass --log or set nonce-log in the config",
+                    )
+                })?;
+            let compacted = NonceLog::new(&path)
+                .compact(&retire)
+                .map_err(|e| CliError::new(ErrorCode::Io, e))?;
+            log::status(format_args!(
+                "Compacted {}: kept {} entries, dropped {}",
+                path.display(),
+                compacted.kept,
+                compacted.dropped
+            ));
+        }
+        Commands::Completions { shell, out_dir } => match out_dir {
+            None => completions::write_completions(shell, Cli::command(), &mut std::io::stdout()),
+            Some(dir) => {
+                let path =
+                    completions::install_completions(shell, Cli::command(), &dir).map_err(|e| {
+                        CliError::new(
+                            ErrorCode::Io,
+                            format!("cannot write to {}: {}", dir.display(), e),
+                        )
+                    })?;
+                log::note(format_args!("Wrote {}", path.display()));
+            }
+        },
+        Commands::Mangen { out_dir } => {
+            let written = mangen::write_man_pages(Cli::command(), &out_dir, &mangen::man_date())
+                .map_err(|e| {
+                    CliError::new(
+                        ErrorCode::Io,
+                        format!("cannot write to {}: {}", out_dir.display(), e),
+                    )
+                })?;
+            log::note(format_args!(
+                "Wrote {} man pages to {}",
+                written.len(),
+                out_dir.display()
+            ));
+        }
+        Commands::Hash {
+            algorithm,
+            files,
+            check: None,
+        } => {
+            let mut failed = false;
+            for file in &files {
+                match hash::hash_path(algorithm, file) {
+                    Ok(digest) => println!("{}", hash::sum_line(&digest, file)),
+                    Err(e) => {
+                        eprintln!("Error: {}: {}", file, e);
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                std::process::exit(exit::IO);
+            }
+        }
+        Commands::Hash {
+            algorithm,
+            check: Some(sums),
+            ..
+        } => {
+            let reader: Box<dyn std::io::BufRead> = if sums == STDIN_PATH {
+                Box::new(std::io::stdin().lock())
+            } else {
+                Box::new(BufReader::new(open(&sums)?))
+            };
+            let summary = hash::check_sums(reader, algorithm, &mut std::io::stdout(), |path| {
+                hash::hash_path(algorithm, path)
+            })?;
+            if summary.ok + summary.failed + summary.unreadable == 0 {
+                return Err(CliError::new(
+                    ErrorCode::Malformed,
+                    format!("{}: no properly formatted checksum lines", sums),
+                ));
+            }
+            for (count, one, many) in [
+                (
+                    summary.malformed,
+                    "line is improperly formatted",
+                    "lines are improperly formatted",
+                ),
+                (
+                    summary.unreadable,
+                    "listed file could not be read",
+                    "listed files could not be read",
+                ),
+                (
+                    summary.failed,
+                    "computed checksum did NOT match",
+                    "computed checksums did NOT match",
+                ),
+            ] {
+                match count {
+                    0 => {}
+                    1 => eprintln!("WARNING: 1 {}", one),
+                    n => eprintln!("WARNING: {} {}", n, many),
+                }
+            }
+            if !summary.passed() {
+                std::process::exit(1);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("rcli").chain(args.iter().copied()))
+    }
+
+    fn chacha_key(cli: Cli) -> Vec<u8> {
+        match cli.command {
+            Commands::Chacha { secret, .. } => secret.key.load("x"),
+            Commands::Verify { key, .. } => key.load("x"),
+            command => panic!("unexpected {:?}", command),
+        }
+    }
+
+    #[test]
+    fn key_hex_option() {
+        let hex = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let cli = parse(&["chacha", "-f", "x", "--key-hex", hex]).unwrap();
+        assert_eq!(chacha_key(cli), (0..32).collect::<Vec<u8>>());
+
+        /* Per-algorithm lengths, reported as usage errors */
+        let err = parse(&["chacha", "-f", "x", "--key-hex", "0x0102030405"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+        assert!(
+            err.to_string()
+                .contains("ChaCha20-Poly1305 keys must be exactly 32 bytes, got 5 bytes"),
+            "{}",
+            err
+        );
+        let cli = parse(&["rc4", "-f", "x", "--key-hex", "0x0102030405"]).unwrap();
+        let Commands::Rc4 { key, .. } = cli.command else {
+            panic!("not rc4");
+        };
+        assert_eq!(key.load("x"), [1, 2, 3, 4, 5]);
+        let err = parse(&["rc4", "-f", "x", "--key-hex", "01020304"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("RC4 keys must be 5 to 256 bytes, got 4 bytes"),
+            "{}",
+            err
+        );
+
+        let err = parse(&["rc4", "-f", "x", "--key-hex", "01020304zz"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("invalid hex character 'z' at position 9"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn per_byte_key_still_works() {
+        let mut args = vec!["verify", "-f", "x", "-k"];
+        let bytes: Vec<String> = (0..32).map(|i| format!("0x{:02x}", i)).collect();
+        args.extend(bytes.iter().map(String::as_str));
+        assert_eq!(
+            chacha_key(parse(&args).unwrap()),
+            (0..32).collect::<Vec<u8>>()
+        );
+
+        /* A typo is a usage error rather than a panic */
+        args[9] = "zz";
+        let err = parse(&args).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+        assert!(
+            err.to_string().contains("'zz' is not a hex byte"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn key_base64_option() {
+        let b64 = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+        for key in [b64, b64.trim_end_matches('=')] {
+            let cli = parse(&["verify", "-f", "x", "--key-base64", key]).unwrap();
+            assert_eq!(chacha_key(cli), (0..32).collect::<Vec<u8>>());
+        }
+        let err = parse(&["chacha", "-f", "x", "--key-base64", &format!("{}=", b64)]).unwrap_err();
+        assert!(
+            err.to_string().contains("invalid base64 padding"),
+            "{}",
+            err
+        );
+        let err = parse(&[
+            "chacha",
+            "-f",
+            "x",
+            "--key-base64",
+            "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHg==",
+        ])
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("ChaCha20-Poly1305 keys must be exactly 32 bytes, got 31 bytes"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn key_sources_are_exclusive() {
+        let hex: Vec<String> = (0..32).map(|_| "00".to_string()).collect();
+        let mut args = vec!["chacha", "-f", "x", "--encrypt", "--key-file", "k", "-k"];
+        args.extend(hex.iter().map(String::as_str));
+        assert!(parse(&args).is_err());
+        assert!(parse(&["chacha", "-f", "x", "--encrypt"]).is_err());
+        assert!(parse(&[
+            "rc4",
+            "-f",
+            "x",
+            "--key-file",
+            "k",
+            "--key-hex",
+            "0102030405"
+        ])
+        .is_err());
+        assert!(parse(&[
+            "rc4",
+            "-f",
+            "x",
+            "--key-base64",
+            "AQIDBAU=",
+            "--key-hex",
+            "0102030405"
+        ])
+        .is_err());
+        assert!(parse(&["chacha", "-f", "x", "--key-file", "k"]).is_ok());
+        assert!(parse(&["rc4", "-f", "x", "--key-file", "k"]).is_ok());
+        assert!(parse(&["rc4", "-f", "x", "--key-base64", "AQIDBAU"]).is_ok());
+    }
+
+    fn in_place() -> OutputArgs {
+        OutputArgs {
+            output: None,
+            force: false,
+            backup: None,
+            progress: false,
+            no_progress: true,
+            stats: false,
+            meter: Arc::default(),
+            measured: Mutex::new(None),
+            split: None,
+            parts: AtomicU32::new(0),
+            batch: None,
+        }
+    }
+
+    /* Answers prompts from a script, recording what was asked */
+    struct ScriptedPrompter {
+        answers: Vec<&'static str>,
+        asked: Vec<String>,
+    }
+
+    impl ScriptedPrompter {
+        fn new(answers: &[&'static str]) -> Self {
+            ScriptedPrompter {
+                answers: answers.iter().rev().copied().collect(),
+                asked: Vec::new(),
+            }
+        }
+    }
+
+    impl Prompter for ScriptedPrompter {
+        fn prompt(&mut self, text: &str) -> Result<String, String> {
+            self.asked.push(text.to_string());
+            self.answers
+                .pop()
+                .map(str::to_string)
+                .ok_or_else(|| "no more answers".to_string())
+        }
+    }
+
+    #[test]
+    fn encrypt_prompt_asks_twice_and_decrypt_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup");
+        std::fs::write(&path, b"irreplaceable").unwrap();
+        let file = path.to_str().unwrap();
+
+        let mut prompter = ScriptedPrompter::new(&["tr0ub4dor", "tr0ub4dor"]);
+        encrypt_with_passphrase(
+            file,
+            &in_place(),
+            &PassphraseSource::Prompt,
+            &mut prompter,
+            true,
+            EncryptOptions::default(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            prompter.asked,
+            ["Enter passphrase: ", "Confirm passphrase: "]
+        );
+        assert_ne!(std::fs::read(&path).unwrap(), b"irreplaceable");
+
+        let mut prompter = ScriptedPrompter::new(&["tr0ub4dor"]);
+        decrypt_with_passphrase(
+            file,
+            &in_place(),
+            &PassphraseSource::Prompt,
+            &mut prompter,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(prompter.asked, ["Enter passphrase: "]);
+        assert_eq!(std::fs::read(&path).unwrap(), b"irreplaceable");
+    }
+
+    #[test]
+    fn mismatched_confirmation_leaves_file_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup");
+        std::fs::write(&path, b"irreplaceable").unwrap();
+        let file = path.to_str().unwrap();
+
+        let mut prompter = ScriptedPrompter::new(&["tr0ub4dor", "tr0ub4d0r"]);
+        assert_eq!(
+            encrypt_with_passphrase(
+                file,
+                &in_place(),
+                &PassphraseSource::Prompt,
+                &mut prompter,
+                true,
+                EncryptOptions::default(),
+                false
+            ),
+            Err(CliError::new(ErrorCode::BadKey, "passphrases do not match"))
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"irreplaceable");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn no_confirm_asks_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup");
+        std::fs::write(&path, b"scripted").unwrap();
+        let file = path.to_str().unwrap();
+
+        let cli = parse(&[
+            "chacha",
+            "-f",
+            file,
+            "--passphrase",
+            "--no-confirm",
+            "--encrypt",
+        ])
+        .unwrap();
+        let Commands::Chacha { secret, .. } = cli.command else {
+            panic!("not chacha");
+        };
+        let no_confirm = secret.no_confirm;
+        assert!(no_confirm);
+        assert!(parse(&[
+            "chacha",
+            "-f",
+            file,
+            "--key-env",
+            "K",
+            "--no-confirm",
+            "--encrypt"
+        ])
+        .is_err());
+
+        let mut prompter = ScriptedPrompter::new(&["just once"]);
+        encrypt_with_passphrase(
+            file,
+            &in_place(),
+            &PassphraseSource::Prompt,
+            &mut prompter,
+            !no_confirm,
+            EncryptOptions::default(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(prompter.asked, ["Enter passphrase: "]);
+        let mut prompter = ScriptedPrompter::new(&["just once"]);
+        decrypt_with_passphrase(
+            file,
+            &in_place(),
+            &PassphraseSource::Prompt,
+            &mut prompter,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"scripted");
+    }
+
+    #[test]
+    fn progress_flags() {
+        assert!(parse(&["rc4", "-f", "x", "--key-hex", "0102030405", "--progress"]).is_ok());
+        assert!(parse(&["rc4", "-f", "x", "--key-hex", "0102030405", "--no-progress"]).is_ok());
+        assert!(parse(&[
+            "rc4",
+            "-f",
+            "x",
+            "--key",
+            "k",
+            "--progress",
+            "--no-progress"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn chunk_size_parsing() {
+        assert_eq!(parse_chunk_size("4096"), Ok(4096));
+        assert_eq!(parse_chunk_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_chunk_size("1m"), Ok(1024 * 1024));
+        assert_eq!(parse_chunk_size("16M"), Ok(MAX_CHUNK_SIZE));
+        assert!(parse_chunk_size("1023").is_err());
+        assert!(parse_chunk_size("17M").is_err());
+        assert!(parse_chunk_size("4096M").is_err());
+        assert!(parse_chunk_size("4 KiB").is_err());
+        assert!(parse_chunk_size("").is_err());
+    }
+
+    #[test]
+    fn armored_passphrase_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("letter");
+        std::fs::write(&path, b"pasted into a ticket").unwrap();
+        let file = path.to_str().unwrap();
+
+        let mut prompter = ScriptedPrompter::new(&["hunter2"]);
+        let source = PassphraseSource::Prompt;
+        let opts = EncryptOptions::default();
+        encrypt_with_passphrase(file, &in_place(), &source, &mut prompter, false, opts, true)
+            .unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(ARMOR_BEGIN));
+
+        let mut prompter = ScriptedPrompter::new(&["hunter3"]);
+        assert_eq!(
+            decrypt_with_passphrase(file, &in_place(), &source, &mut prompter, &[]),
+            Err(CliError::new(
+                ErrorCode::WrongKey,
+                "incorrect passphrase or corrupted file"
+            ))
+        );
+        let mut prompter = ScriptedPrompter::new(&["hunter2"]);
+        decrypt_with_passphrase(file, &in_place(), &source, &mut prompter, &[]).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"pasted into a ticket");
+    }
+
+    #[test]
+    fn keygen_encrypt_decrypt_with_base64_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, b"base64 all the way").unwrap();
+        let file = path.to_str().unwrap();
+
+        let mut key = [0u8; 32];
+        generate_key(&mut key);
+        let b64 = String::from_utf8(format_key(&key, Some(KeyFormat::Base64))).unwrap();
+        let b64 = b64.trim_end().to_string();
+        assert!(parse(&["keygen", "--format", "base64"]).is_ok());
+
+        run(parse(&["chacha", "-f", file, "--key-base64", &b64, "--encrypt"]).unwrap()).unwrap();
+        assert_ne!(std::fs::read(&path).unwrap(), b"base64 all the way");
+        run(parse(&["verify", "-f", file, "--key-base64", &b64]).unwrap()).unwrap();
+        run(parse(&["chacha", "-f", file, "--key-base64", &b64, "--decrypt"]).unwrap()).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"base64 all the way");
+    }
+
+    /* Every command and option says what it is for, in --help and in the
+     * man pages */
+    #[test]
+    fn everything_has_help() {
+        fn check(command: &clap::Command, missing: &mut Vec<String>) {
+            let name = command.get_display_name().unwrap_or(command.get_name());
+            if command.get_about().is_none() {
+                missing.push(name.to_string());
+            }
+            for arg in command.get_arguments().filter(|a| !a.is_hide_set()) {
+                if arg.get_help().is_none() && arg.get_long_help().is_none() {
+                    missing.push(format!("{} {}", name, arg.get_id()));
+                }
+            }
+            for sub in command.get_subcommands() {
+                check(sub, missing);
+            }
+        }
+        let mut command = Cli::command();
+        command.build();
+        let mut missing = Vec::new();
+        check(&command, &mut missing);
+        assert!(missing.is_empty(), "no help for {:?}", missing);
+    }
+
+    /* The examples after each command's --help parse, bar placeholders */
+    #[test]
+    fn examples_parse() {
+        fn words(line: &str) -> Vec<String> {
+            let mut words = Vec::new();
+            let mut word = String::new();
+            let mut quoted = false;
+            for c in line.chars() {
+                match c {
+                    '\'' => quoted = !quoted,
+                    ' ' if !quoted => words.extend((!word.is_empty()).then(|| word.split_off(0))),
+                    _ => word.push(c),
+                }
+            }
+            words.extend((!word.is_empty()).then_some(word));
+            words
+        }
+        fn check(command: &clap::Command, checked: &mut usize) {
+            for line in command.get_after_help().into_iter().flat_map(|h| {
+                h.to_string()
+                    .lines()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            }) {
+                let line = line.trim();
+                if line == examples::EXAMPLES_HEADING || line.contains("...") {
+                    continue;
+                }
+                let line = line.rsplit(" | ").next().unwrap();
+                let line = line.split(" > ").next().unwrap();
+                let line = line.split(" < ").next().unwrap();
+                let args = words(line);
+                if args.iter().any(|a| a.starts_with('<')) {
+                    continue;
+                }
+                assert_eq!(args[0], "rcli", "{}", line);
+                if let Err(e) = Cli::try_parse_from(&args) {
+                    panic!("{}: {}", line, e);
+                }
+                *checked += 1;
+            }
+            for sub in command.get_subcommands() {
+                check(sub, checked);
+            }
+        }
+        let mut checked = 0;
+        check(&Cli::command(), &mut checked);
+        assert!(checked > 20, "{}", checked);
+    }
 }
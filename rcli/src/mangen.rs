@@ -0,0 +1,140 @@
+/* mangen (hidden): man pages from the clap definitions, for packagers.
+ *
+ * rcli.1 covers the top level and rcli-<command>.1 each command
+ * (rcli-identity-new.1 and so on for nested ones), in the usual sections
+ * as clap_mangen renders them. The examples each command shows after its
+ * --help become an EXAMPLES section, laid out as written. Every page
+ * carries the crate's version, and the date is SOURCE_DATE_EPOCH's when
+ * set, for reproducible packages, and today's otherwise. */
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Command;
+use clap_mangen::Man;
+use roff::{roman, Roff};
+
+use crate::examples::EXAMPLES_HEADING;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Write a man page for `command` and each visible subcommand into `dir`,
+/// returning their paths, the top-level page last.
+pub fn write_man_pages(command: Command, dir: &Path, date: &str) -> io::Result<Vec<PathBuf>> {
+    let mut command = command.disable_help_subcommand(true);
+    command.build();
+    let mut written = Vec::new();
+    write_pages(command, dir, date, &mut written)?;
+    Ok(written)
+}
+
+fn write_pages(
+    command: Command,
+    dir: &Path,
+    date: &str,
+    written: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for sub in command.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        write_pages(sub.clone(), dir, date, written)?;
+    }
+    /* Subcommands carry no version of their own */
+    let command = command.version(VERSION);
+    let man = Man::new(command.clone())
+        .date(date)
+        .source(format!("rcli {}", VERSION))
+        .manual("rcli Manual");
+    let path = dir.join(man.get_filename());
+    let mut out = BufWriter::new(File::create(&path)?);
+    render(&man, &command, &mut out)?;
+    out.flush()?;
+    written.push(path);
+    Ok(())
+}
+
+/* As `Man::render`, with the after-help examples under EXAMPLES rather
+ * than refilled as prose */
+fn render(man: &Man, command: &Command, out: &mut dyn Write) -> io::Result<()> {
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    if command.get_arguments().any(|arg| !arg.is_hide_set()) {
+        man.render_options_section(out)?;
+    }
+    if command.get_subcommands().any(|sub| !sub.is_hide_set()) {
+        man.render_subcommands_section(out)?;
+    }
+    if let Some(examples) = command.get_after_help() {
+        examples_section(&examples.to_string()).to_writer(out)?;
+    }
+    man.render_version_section(out)
+}
+
+fn examples_section(examples: &str) -> Roff {
+    let mut roff = Roff::new();
+    roff.control("SH", ["EXAMPLES"]);
+    roff.control("nf", []);
+    roff.control("RS", []);
+    for line in examples.lines().filter(|line| *line != EXAMPLES_HEADING) {
+        roff.text([roman(line.trim_start())]);
+    }
+    roff.control("RE", []);
+    roff.control("fi", []);
+    roff
+}
+
+/// The date for the pages' footer, as YYYY-MM-DD.
+pub fn man_date() -> String {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    let (year, month, day) = civil_date(seconds / 86_400);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/* The Gregorian date `days` after 1970-01-01 (Hinnant's civil_from_days) */
+pub(crate) fn civil_date(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(59), (1970, 3, 1));
+        assert_eq!(civil_date(11_016), (2000, 2, 29));
+        assert_eq!(civil_date(20_741), (2026, 10, 15));
+    }
+
+    #[test]
+    fn examples_are_kept_as_written() {
+        let mut out = Vec::new();
+        examples_section("Examples:\n  rcli hash -f x\n  .hidden --flag\n")
+            .to_writer(&mut out)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains(".SH EXAMPLES\n.nf\n"), "{}", text);
+        assert!(!text.contains("Examples:"));
+        assert!(text.contains("rcli hash \\-f x\n"), "{}", text);
+        /* A leading dot is not taken as a request */
+        assert!(text.contains("\\&.hidden"), "{}", text);
+    }
+}
@@ -0,0 +1,221 @@
+/* --stats: what each file cost, and the total over the run.
+ *
+ * The operation's reader and writer are wrapped in `MeteredReader` and
+ * `MeteredWriter`, which count the bytes that actually pass through them,
+ * headers and tags included, whether they come from a file or standard
+ * input. With the time taken and the chunk count from the crypto side,
+ * that makes a file's `Stats`. A `Meter` is shared by every file of a
+ * command, keeping the latest file's stats for its status line or record
+ * and adding each to the total printed at the end. Text goes to standard
+ * error; with --json the stats ride in the records instead. */
+use std::cell::Cell;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::status::{self, Record};
+
+/// One file's figures, or the sum over several.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub seconds: f64,
+    /// Bytes read a second, in MB (10^6 bytes).
+    pub mb_per_s: f64,
+    /// Chunks sealed or opened, when the operation counts them.
+    pub chunks: Option<u64>,
+}
+
+impl Stats {
+    pub fn new(bytes_read: u64, bytes_written: u64, took: Duration, chunks: u64) -> Self {
+        Stats {
+            bytes_read,
+            bytes_written,
+            seconds: took.as_secs_f64(),
+            mb_per_s: rate(bytes_read, took.as_secs_f64()),
+            chunks: (chunks > 0).then_some(chunks),
+        }
+    }
+
+    /// Both together, as for a total.
+    pub fn add(&self, other: &Stats) -> Stats {
+        let seconds = self.seconds + other.seconds;
+        let bytes_read = self.bytes_read + other.bytes_read;
+        Stats {
+            bytes_read,
+            bytes_written: self.bytes_written + other.bytes_written,
+            seconds,
+            mb_per_s: rate(bytes_read, seconds),
+            chunks: match (self.chunks, other.chunks) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+            },
+        }
+    }
+}
+
+fn rate(bytes: u64, seconds: f64) -> f64 {
+    match seconds > 0.0 {
+        true => bytes as f64 / seconds / 1e6,
+        false => 0.0,
+    }
+}
+
+/* "read 5 bytes, wrote 38 in 0.002 s (2.5 MB/s), 1 chunk" */
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "read {} bytes, wrote {} in {:.3} s ({:.1} MB/s)",
+            self.bytes_read, self.bytes_written, self.seconds, self.mb_per_s
+        )?;
+        match self.chunks {
+            Some(1) => write!(f, ", 1 chunk"),
+            Some(chunks) => write!(f, ", {} chunks", chunks),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Counts the bytes read through it into `count`.
+pub struct MeteredReader<'a, R> {
+    inner: R,
+    count: &'a Cell<u64>,
+}
+
+impl<'a, R: Read> MeteredReader<'a, R> {
+    pub fn new(inner: R, count: &'a Cell<u64>) -> Self {
+        MeteredReader { inner, count }
+    }
+}
+
+impl<R: Read> Read for MeteredReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Counts the bytes written through it into `count`.
+pub struct MeteredWriter<'a, W> {
+    inner: W,
+    count: &'a Cell<u64>,
+}
+
+impl<'a, W: Write> MeteredWriter<'a, W> {
+    pub fn new(inner: W, count: &'a Cell<u64>) -> Self {
+        MeteredWriter { inner, count }
+    }
+}
+
+impl<W: Write> Write for MeteredWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The stats of a command's files: the latest, and the running total.
+/// Shared by the workers of --jobs, so behind a lock.
+#[derive(Debug, Default)]
+pub struct Meter {
+    figures: Mutex<Figures>,
+}
+
+#[derive(Debug, Default)]
+struct Figures {
+    last: Option<Stats>,
+    total: Stats,
+    files: u64,
+}
+
+impl Meter {
+    /// A file is done.
+    pub fn record(&self, stats: Stats) {
+        let mut figures = self.lock();
+        figures.last = Some(stats);
+        figures.total = figures.total.add(&stats);
+        figures.files += 1;
+    }
+
+    /// The latest file's stats.
+    pub fn last(&self) -> Option<Stats> {
+        self.lock().last
+    }
+
+    /// The number of files recorded and their stats together.
+    pub fn total(&self) -> (u64, Stats) {
+        let figures = self.lock();
+        (figures.files, figures.total)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Figures> {
+        self.figures.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Print the total: "Total: 3 files, read ..." on standard error, or
+    /// with --json a record.
+    pub fn print_total(&self) {
+        let (files, stats) = self.total();
+        if status::json() {
+            status::emit(&Record::Total { files, stats });
+        } else {
+            let noun = if files == 1 { "file" } else { "files" };
+            eprintln!("Total: {} {}, {}", files, noun, stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_what_passes_through() {
+        let (read, written) = (Cell::new(0), Cell::new(0));
+        let mut reader = MeteredReader::new(&b"twelve bytes"[..], &read);
+        let mut writer = MeteredWriter::new(Vec::new(), &written);
+        io::copy(&mut reader, &mut writer).unwrap();
+        writer.write_all(b"+4").unwrap();
+        assert_eq!((read.get(), written.get()), (12, 14));
+    }
+
+    #[test]
+    fn totals_add_up() {
+        let meter = Meter::default();
+        meter.record(Stats::new(100, 128, Duration::from_millis(10), 1));
+        meter.record(Stats::new(300, 0, Duration::from_millis(30), 0));
+        meter.record(Stats::new(600, 700, Duration::from_millis(60), 3));
+        assert_eq!(meter.last().unwrap().bytes_read, 600);
+        let (files, total) = meter.total();
+        assert_eq!(files, 3);
+        assert_eq!((total.bytes_read, total.bytes_written), (1000, 828));
+        assert_eq!(total.chunks, Some(4));
+        assert!((total.seconds - 0.1).abs() < 1e-9);
+        assert!((total.mb_per_s - 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stats_read_naturally() {
+        let one = Stats::new(5, 38, Duration::from_millis(2), 1);
+        assert_eq!(
+            one.to_string(),
+            "read 5 bytes, wrote 38 in 0.002 s (0.0 MB/s), 1 chunk"
+        );
+        let rc4 = Stats::new(2_000_000, 2_000_000, Duration::from_secs(1), 0);
+        assert_eq!(
+            rc4.to_string(),
+            "read 2000000 bytes, wrote 2000000 in 1.000 s (2.0 MB/s)"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+/* rc4 --mmap: a file changed where it lies, through a memory map.
+ *
+ * The keystream is applied to the mapped file a window at a time and the
+ * map flushed at the end, so each byte is read and written once with no
+ * copy in between. The default path writes a temporary file and renames
+ * it over the original, so a crash leaves either the old file or the new
+ * one; this path has no such guarantee, and a crash or power cut part way
+ * leaves the file part processed with nothing to tell where it stopped.
+ * An interrupt therefore waits for the file to be finished (a second one
+ * still stops at once). Files that cannot be mapped, such as empty ones,
+ * some on network filesystems and those past a 32-bit address space, are
+ * left for the caller to stream instead.
+ *
+ * Mapping a file is the one unsafe operation in rcli; the libraries
+ * forbid unsafe code. */
+use std::fs::OpenOptions;
+use std::io;
+
+use memmap2::MmapMut;
+
+use crate::progress::Progress;
+
+/* Bytes between progress updates */
+const WINDOW: usize = 1 << 20;
+
+/// `file` mapped for reading and writing, or why it cannot be.
+pub fn map(file: &str) -> io::Result<MmapMut> {
+    let handle = OpenOptions::new().read(true).write(true).open(file)?;
+    if handle.metadata()?.len() == 0 {
+        return Err(io::Error::other("it is empty"));
+    }
+    /* SAFETY: nothing else may truncate or write the file while it is
+     * mapped, as for any file changed in place. A file truncated anyway
+     * kills the process with SIGBUS on the next access rather than
+     * letting it read freed memory. */
+    unsafe { MmapMut::map_mut(&handle) }
+}
+
+/// Run `op` over `map` a window at a time, in order, then flush it to
+/// the file. Returns the bytes processed.
+pub fn apply<F>(map: &mut MmapMut, mut op: F, progress: &mut dyn Progress) -> io::Result<u64>
+where
+    F: FnMut(&mut [u8]),
+{
+    let mut done = 0;
+    for window in map.chunks_mut(WINDOW) {
+        op(window);
+        done += window.len() as u64;
+        progress.update(done);
+    }
+    progress.finish();
+    map.flush()?;
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::ByteCount;
+
+    #[test]
+    fn windows_cover_the_file_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data");
+        let len = 2 * WINDOW + 5;
+        std::fs::write(&path, vec![1u8; len]).unwrap();
+
+        let mut map = map(path.to_str().unwrap()).unwrap();
+        let (mut next, mut count) = (0u8, ByteCount::default());
+        let done = apply(
+            &mut map,
+            |window| {
+                window.iter_mut().for_each(|b| *b += next);
+                next += 1;
+            },
+            &mut count,
+        )
+        .unwrap();
+        drop(map);
+        assert_eq!((done, count.0), (len as u64, len as u64));
+
+        let data = std::fs::read(&path).unwrap();
+        assert!(data[..WINDOW].iter().all(|&b| b == 1));
+        assert!(data[WINDOW..2 * WINDOW].iter().all(|&b| b == 2));
+        assert_eq!(data[2 * WINDOW..], [3; 5]);
+    }
+
+    #[test]
+    fn empty_files_are_not_mapped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty");
+        std::fs::write(&path, b"").unwrap();
+        assert!(map(path.to_str().unwrap()).is_err());
+        assert!(map(dir.path().join("missing").to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+/* --keep's names: what the result of encrypting or decrypting a file is
+ * called when it is written beside it.
+ *
+ * Encrypting adds the suffix (.enc unless --suffix says otherwise), dots
+ * and all, so .bashrc becomes .bashrc.enc and archive.tar.gz becomes
+ * archive.tar.gz.enc. Decrypting takes off the suffix, or failing that
+ * .enc or .rcli; a name that ends in none of them (or would be left empty)
+ * gets .dec added instead, so there is always somewhere new to write.
+ * Only the file name is looked at, as an OsStr, so names that are not
+ * UTF-8 work too. */
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// The suffix --keep uses unless --suffix gives another.
+pub const DEFAULT_SUFFIX: &str = ".enc";
+
+/// Suffixes decrypting recognizes besides the configured one.
+const RECOGNIZED: [&str; 2] = [DEFAULT_SUFFIX, ".rcli"];
+
+/// Added when decrypting a name without a recognized suffix.
+const FALLBACK: &str = ".dec";
+
+/// The name beside `file` for the result of encrypting (`encrypt`) or
+/// decrypting it, given the configured `suffix`.
+pub fn derive(file: &Path, suffix: &str, encrypt: bool) -> PathBuf {
+    let Some(name) = file.file_name() else {
+        return with_suffix(file, suffix);
+    };
+    if encrypt {
+        return with_suffix(file, suffix);
+    }
+    let stripped = std::iter::once(suffix)
+        .chain(RECOGNIZED)
+        .find_map(|known| strip(name, known));
+    match stripped {
+        Some(plain) => file.with_file_name(plain),
+        None => with_suffix(file, FALLBACK),
+    }
+}
+
+fn with_suffix(file: &Path, suffix: &str) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/* `name` without `suffix`, if it ends with it and something is left.
+ * A name that is not UTF-8 loses the suffix a dotted extension at a time,
+ * the only way to cut an OsStr short without going through bytes */
+fn strip(name: &OsStr, suffix: &str) -> Option<OsString> {
+    if let Some(name) = name.to_str() {
+        return name
+            .strip_suffix(suffix)
+            .filter(|plain| !plain.is_empty())
+            .map(OsString::from);
+    }
+    let mut rest = Path::new(name);
+    for extension in suffix.strip_prefix('.')?.rsplit('.') {
+        if rest.extension()? != extension {
+            return None;
+        }
+        rest = Path::new(rest.file_stem()?);
+    }
+    Some(rest.as_os_str().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_derived() {
+        let cases = [
+            ("notes.txt", ".enc", true, "notes.txt.enc"),
+            (".bashrc", ".enc", true, ".bashrc.enc"),
+            ("archive.tar.gz", ".enc", true, "archive.tar.gz.enc"),
+            ("nodot", ".enc", true, "nodot.enc"),
+            ("dir/nodot", ".sealed", true, "dir/nodot.sealed"),
+            ("notes.txt.enc", ".enc", false, "notes.txt"),
+            ("archive.tar.gz.enc", ".enc", false, "archive.tar.gz"),
+            (".bashrc.enc", ".enc", false, ".bashrc"),
+            ("dir/a.b.c.sealed", ".sealed", false, "dir/a.b.c"),
+            /* the usual suffixes are recognized whatever --suffix says */
+            ("notes.txt.rcli", ".sealed", false, "notes.txt"),
+            ("notes.txt.enc", ".sealed", false, "notes.txt"),
+            ("notes~", "~", false, "notes"),
+            /* nothing to take off, or nothing left after it */
+            ("nodot", ".enc", false, "nodot.dec"),
+            (".bashrc", ".enc", false, ".bashrc.dec"),
+            ("archive.tar.gz", ".enc", false, "archive.tar.gz.dec"),
+            (".enc", ".enc", false, ".enc.dec"),
+            ("dir.enc/nodot", ".enc", false, "dir.enc/nodot.dec"),
+        ];
+        for (file, suffix, encrypt, expected) in cases {
+            assert_eq!(
+                derive(Path::new(file), suffix, encrypt),
+                Path::new(expected),
+                "{} {} {}",
+                file,
+                suffix,
+                encrypt
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn names_need_not_be_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+        let name = |bytes: &[u8]| Path::new(OsStr::from_bytes(bytes)).to_path_buf();
+        assert_eq!(
+            derive(&name(b"caf\xe9.txt"), ".enc", true),
+            name(b"caf\xe9.txt.enc")
+        );
+        assert_eq!(
+            derive(&name(b"caf\xe9.txt.enc"), ".enc", false),
+            name(b"caf\xe9.txt")
+        );
+        assert_eq!(
+            derive(&name(b"caf\xe9.tar.gz.x.y"), ".x.y", false),
+            name(b"caf\xe9.tar.gz")
+        );
+        assert_eq!(
+            derive(&name(b"caf\xe9"), ".enc", false),
+            name(b"caf\xe9.dec")
+        );
+    }
+}
@@ -0,0 +1,328 @@
+/* --nonce-log: a record of every (key, nonce prefix) pair encryption has
+ * used, so that no pair is ever used twice.
+ *
+ * A file's chunk nonces are its 7-byte random prefix plus a counter, so
+ * two files under one key can only share nonces if they share a prefix.
+ * Before encrypting under a key, rcli draws the prefix and, holding an
+ * exclusive lock on PATH.lock, reads the log: if the line
+ *
+ *   <key fingerprint> <nonce prefix>
+ *
+ * (both hex) is already there it stops with nonce_reused before writing
+ * anything, and otherwise appends the line and syncs it. The fingerprint
+ * is HMAC-SHA256(key, "rcli nonce log v1") cut to 16 bytes, which tells
+ * keys apart without giving anything away about them; the log never
+ * holds key bytes. Passphrase and recipient encryption make a fresh key
+ * for every file, so they have nothing to log.
+ *
+ * `rcli nonce-log compact` drops the lines of keys retired by rotation,
+ * writing what is left to a temporary file that is renamed over the log
+ * under the same lock, so a concurrent encryption sees the old log or the
+ * new one and never half of either. */
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crypto::digest::{HashAlgorithm, HmacContext};
+use crypto::header::NONCE_PREFIX_LEN;
+use crypto::EntropySource;
+
+/// Bytes of HMAC output a fingerprint keeps.
+pub const FINGERPRINT_LEN: usize = 16;
+
+const FINGERPRINT_LABEL: &[u8] = b"rcli nonce log v1";
+
+/// The hex fingerprint the log records for `key`.
+pub fn fingerprint(key: &[u8]) -> String {
+    let mut mac = HmacContext::new(HashAlgorithm::Sha256, key);
+    mac.update(FINGERPRINT_LABEL);
+    hex(&mac.finalize().as_bytes()[..FINGERPRINT_LEN])
+}
+
+/// The value of --retire: a fingerprint as the log shows it.
+pub fn parse_fingerprint(text: &str) -> Result<String, String> {
+    match is_hex(text, FINGERPRINT_LEN) {
+        true => Ok(text.to_ascii_lowercase()),
+        false => Err(format!(
+            "a key fingerprint is {} hex digits",
+            FINGERPRINT_LEN * 2
+        )),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn is_hex(text: &str, len: usize) -> bool {
+    text.len() == len * 2 && text.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Why a nonce could not be claimed or the log compacted.
+#[derive(Debug)]
+pub enum NonceLogError {
+    /// The key has already encrypted under this prefix.
+    Reused {
+        fingerprint: String,
+        prefix: String,
+    },
+    /// A line that is not "<fingerprint> <prefix>"; 1-based.
+    Malformed {
+        path: PathBuf,
+        line: usize,
+    },
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+}
+
+impl fmt::Display for NonceLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NonceLogError::Reused {
+                fingerprint,
+                prefix,
+            } => write!(
+                f,
+                "key {} has already encrypted under nonce prefix {}; nothing was \
+                 written (the random number source may be broken)",
+                fingerprint, prefix
+            ),
+            NonceLogError::Malformed { path, line } => write!(
+                f,
+                "nonce log {} is damaged at line {}",
+                path.display(),
+                line
+            ),
+            NonceLogError::Io { path, error } => {
+                write!(f, "nonce log {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NonceLogError {}
+
+/// What `compact` kept and dropped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Compacted {
+    pub kept: usize,
+    pub dropped: usize,
+}
+
+/// The log at one path.
+#[derive(Debug, Clone)]
+pub struct NonceLog {
+    path: PathBuf,
+}
+
+impl NonceLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        NonceLog { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Draw a nonce prefix for `key` from `rng` and record it, failing if
+    /// the log shows the pair already.
+    pub fn claim(
+        &self,
+        key: &[u8],
+        rng: &dyn EntropySource,
+    ) -> Result<[u8; NONCE_PREFIX_LEN], NonceLogError> {
+        let mut prefix = [0u8; NONCE_PREFIX_LEN];
+        rng.fill(&mut prefix);
+        let line = format!("{} {}", fingerprint(key), hex(&prefix));
+
+        let _lock = self.lock()?;
+        if self.lines()?.contains(&line) {
+            let (fingerprint, prefix) = line.split_once(' ').expect("formatted above");
+            return Err(NonceLogError::Reused {
+                fingerprint: fingerprint.to_string(),
+                prefix: prefix.to_string(),
+            });
+        }
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| self.io(e))?;
+        log.write_all(format!("{}\n", line).as_bytes())
+            .and_then(|_| log.sync_data())
+            .map_err(|e| self.io(e))?;
+        Ok(prefix)
+    }
+
+    /// Rewrite the log without the lines of the `retired` fingerprints.
+    pub fn compact(&self, retired: &[String]) -> Result<Compacted, NonceLogError> {
+        let _lock = self.lock()?;
+        let lines = self.lines()?;
+        let kept: Vec<&String> = lines
+            .iter()
+            .filter(|line| !retired.iter().any(|key| line.starts_with(key.as_str())))
+            .collect();
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(".compact");
+        let temp = PathBuf::from(name);
+        let written = File::create(&temp).and_then(|mut file| {
+            for line in &kept {
+                writeln!(file, "{}", line)?;
+            }
+            file.sync_all()
+        });
+        if let Err(e) = written.and_then(|_| std::fs::rename(&temp, &self.path)) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(self.io(e));
+        }
+        Ok(Compacted {
+            kept: kept.len(),
+            dropped: lines.len() - kept.len(),
+        })
+    }
+
+    /* The lock file beside the log, held exclusively until dropped */
+    fn lock(&self) -> Result<File, NonceLogError> {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(".lock");
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(PathBuf::from(name))
+            .map_err(|e| self.io(e))?;
+        lock.lock().map_err(|e| self.io(e))?;
+        Ok(lock)
+    }
+
+    /* Every line, checked; none for a log not yet written */
+    fn lines(&self) -> Result<Vec<String>, NonceLogError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(self.io(e)),
+        };
+        let mut lines = Vec::new();
+        for (n, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| self.io(e))?;
+            let well_formed = line.split_once(' ').is_some_and(|(key, prefix)| {
+                is_hex(key, FINGERPRINT_LEN) && is_hex(prefix, NONCE_PREFIX_LEN)
+            });
+            if !well_formed {
+                return Err(NonceLogError::Malformed {
+                    path: self.path.clone(),
+                    line: n + 1,
+                });
+            }
+            lines.push(line);
+        }
+        Ok(lines)
+    }
+
+    fn io(&self, error: io::Error) -> NonceLogError {
+        NonceLogError::Io {
+            path: self.path.clone(),
+            error,
+        }
+    }
+}
+
+/// The system's random number generator, for `NonceLog::claim`.
+pub struct SystemEntropy;
+
+impl EntropySource for SystemEntropy {
+    fn fill(&self, dest: &mut [u8]) {
+        crypto::generate_nonce(dest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::testing::TestRng;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    #[test]
+    fn fingerprints_hide_the_key() {
+        let print = fingerprint(&KEY);
+        assert_eq!(print.len(), FINGERPRINT_LEN * 2);
+        assert_ne!(print, fingerprint(&[0x43; 32]));
+        assert!(!print.contains(&hex(&KEY[..4])));
+        assert_eq!(parse_fingerprint(&print.to_uppercase()), Ok(print));
+        assert!(parse_fingerprint("abc").is_err());
+    }
+
+    #[test]
+    fn reused_prefix_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = NonceLog::new(dir.path().join("nonces"));
+        let first = log.claim(&KEY, &TestRng::new(7)).unwrap();
+        /* the same seed draws the same prefix: a broken RNG */
+        match log.claim(&KEY, &TestRng::new(7)) {
+            Err(NonceLogError::Reused {
+                fingerprint: key,
+                prefix,
+            }) => {
+                assert_eq!(key, fingerprint(&KEY));
+                assert_eq!(prefix, hex(&first));
+            }
+            other => panic!("{:?}", other),
+        }
+        /* under another key the pair is new */
+        assert_eq!(log.claim(&[0x43; 32], &TestRng::new(7)).unwrap(), first);
+        let text = std::fs::read_to_string(log.path()).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(!text.contains(&hex(&KEY)));
+    }
+
+    #[test]
+    fn compaction_keeps_live_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = NonceLog::new(dir.path().join("nonces"));
+        let (old, new) = ([1u8; 32], [2u8; 32]);
+        let rng = TestRng::new(1);
+        for _ in 0..3 {
+            log.claim(&old, &rng).unwrap();
+            log.claim(&new, &rng).unwrap();
+        }
+        let before = std::fs::read_to_string(log.path()).unwrap();
+        let compacted = log.compact(&[fingerprint(&old)]).unwrap();
+        assert_eq!(
+            compacted,
+            Compacted {
+                kept: 3,
+                dropped: 3
+            }
+        );
+        let after = std::fs::read_to_string(log.path()).unwrap();
+        let live: Vec<&str> = before
+            .lines()
+            .filter(|line| line.starts_with(&fingerprint(&new)))
+            .collect();
+        assert_eq!(after.lines().collect::<Vec<_>>(), live);
+        /* the retired key's pairs are forgotten, the new key's guarded */
+        let again = TestRng::new(1);
+        log.claim(&old, &again).unwrap();
+        assert!(matches!(
+            log.claim(&new, &again),
+            Err(NonceLogError::Reused { .. })
+        ));
+    }
+
+    #[test]
+    fn damaged_log_is_not_trusted() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = NonceLog::new(dir.path().join("nonces"));
+        log.claim(&KEY, &TestRng::new(1)).unwrap();
+        let mut file = OpenOptions::new().append(true).open(log.path()).unwrap();
+        file.write_all(b"half a li").unwrap();
+        match log.claim(&KEY, &TestRng::new(2)) {
+            Err(NonceLogError::Malformed { line, .. }) => assert_eq!(line, 2),
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,355 @@
+/* Progress for long rc4/chacha runs.
+ *
+ * The crypto side only sees a reader: `ProgressReader` counts the bytes
+ * pulled through it and hands the running total to a `Progress` after
+ * every read, which for chacha is once per chunk. `TerminalProgress`
+ * draws a single line on standard error and erases it when done, so the
+ * status line printed afterwards starts on a clean line. With --jobs the
+ * files share one `Batch` bar instead: each worker adds what it reads,
+ * and the main thread draws the sum between status lines. */
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/* Shown automatically only for inputs at least this large */
+const AUTO_THRESHOLD: u64 = 64 * 1024 * 1024;
+/// Redraw at most this often.
+pub const REDRAW_EVERY: Duration = Duration::from_millis(100);
+
+pub trait Progress {
+    /// `done` bytes of input have been read so far; never decreases.
+    fn update(&mut self, done: u64);
+    /// The operation is over, successfully or not.
+    fn finish(&mut self);
+}
+
+/// Counts bytes read from `inner` and reports the total to `progress`.
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    done: u64,
+    progress: &'a mut dyn Progress,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub fn new(inner: R, progress: &'a mut dyn Progress) -> Self {
+        ProgressReader {
+            inner,
+            done: 0,
+            progress,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.done += n as u64;
+            self.progress.update(self.done);
+        }
+        Ok(n)
+    }
+}
+
+/// Only keeps the total, for callers that want it afterwards.
+#[derive(Debug, Default)]
+pub struct ByteCount(pub u64);
+
+impl Progress for ByteCount {
+    fn update(&mut self, done: u64) {
+        self.0 = done;
+    }
+
+    fn finish(&mut self) {}
+}
+
+/// When to show progress: `--progress` forces it, `--no-progress` turns
+/// it off, and otherwise it appears for large files when standard error
+/// is a terminal. Never shown when the data goes to standard output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ProgressMode {
+    pub fn from_flags(progress: bool, no_progress: bool) -> Self {
+        match (progress, no_progress) {
+            (true, _) => ProgressMode::Always,
+            (_, true) => ProgressMode::Never,
+            _ => ProgressMode::Auto,
+        }
+    }
+
+    /// Whether to draw progress for an input of `len` bytes (None when
+    /// unknown, as for standard input).
+    pub fn shows(self, streaming: bool, len: Option<u64>) -> bool {
+        self.decide(streaming, len, io::stderr().is_terminal())
+    }
+
+    fn decide(self, streaming: bool, len: Option<u64>, stderr_is_tty: bool) -> bool {
+        if streaming {
+            return false;
+        }
+        match self {
+            ProgressMode::Always => true,
+            ProgressMode::Never => false,
+            ProgressMode::Auto => stderr_is_tty && len.is_some_and(|len| len >= AUTO_THRESHOLD),
+        }
+    }
+}
+
+/// A one-line bar on standard error: bytes done of the total, throughput
+/// and time left.
+#[derive(Debug)]
+pub struct TerminalProgress {
+    total: u64,
+    start: Instant,
+    last_draw: Option<Instant>,
+}
+
+impl TerminalProgress {
+    pub fn new(total: u64) -> Self {
+        TerminalProgress {
+            total,
+            start: Instant::now(),
+            last_draw: None,
+        }
+    }
+}
+
+impl Progress for TerminalProgress {
+    fn update(&mut self, done: u64) {
+        let now = Instant::now();
+        /* The first read is the header, possibly just before a passphrase
+         * prompt; wait a little so the bar does not draw over it */
+        let due = match self.last_draw {
+            Some(last) => now - last >= REDRAW_EVERY,
+            None => now - self.start >= REDRAW_EVERY,
+        };
+        if !due && done < self.total {
+            return;
+        }
+        self.last_draw = Some(now);
+        let line = render(done, self.total, now - self.start);
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K{}", line);
+        let _ = stderr.flush();
+    }
+
+    fn finish(&mut self) {
+        if self.last_draw.is_some() {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+        }
+    }
+}
+
+/// One bar for files worked on at once: bytes read by every worker
+/// together, drawn only by the main thread.
+#[derive(Debug)]
+pub struct Batch {
+    mode: ProgressMode,
+    done: AtomicU64,
+    bar: Mutex<Option<TerminalProgress>>,
+}
+
+impl Batch {
+    pub fn new(mode: ProgressMode) -> Self {
+        Batch {
+            mode,
+            done: AtomicU64::new(0),
+            bar: Mutex::new(None),
+        }
+    }
+
+    /// The files come to `total` bytes; the bar shows from now on if
+    /// `mode` would show it for a file that size.
+    pub fn start(&self, total: u64) {
+        if !crate::status::json() && self.mode.shows(false, Some(total)) {
+            *self.lock() = Some(TerminalProgress::new(total));
+        }
+    }
+
+    /// A `Progress` for one file's reader, adding to the batch.
+    pub fn share(&self) -> Share<'_> {
+        Share {
+            batch: self,
+            seen: 0,
+        }
+    }
+
+    /// Redraw, when due.
+    pub fn draw(&self) {
+        if let Some(bar) = self.lock().as_mut() {
+            bar.update(self.done.load(Ordering::Relaxed));
+        }
+    }
+
+    /// Erase the bar, before a status line.
+    pub fn clear(&self) {
+        if let Some(bar) = self.lock().as_mut() {
+            bar.finish();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<TerminalProgress>> {
+        self.bar.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// One file's part of a `Batch`.
+pub struct Share<'a> {
+    batch: &'a Batch,
+    seen: u64,
+}
+
+impl Progress for Share<'_> {
+    fn update(&mut self, done: u64) {
+        let new = done - self.seen;
+        self.seen = done;
+        self.batch.done.fetch_add(new, Ordering::Relaxed);
+    }
+
+    fn finish(&mut self) {}
+}
+
+/* "412.0 MiB / 1.5 GiB  27%  98.3 MiB/s  ETA 0:11" */
+fn render(done: u64, total: u64, elapsed: Duration) -> String {
+    let percent = (done * 100).checked_div(total).unwrap_or(100).min(100);
+    let secs = elapsed.as_secs_f64();
+    let rate = if secs > 0.0 { done as f64 / secs } else { 0.0 };
+    let eta = if rate > 0.0 {
+        let left = total.saturating_sub(done) as f64 / rate;
+        format_duration(left as u64)
+    } else {
+        "--:--".to_string()
+    };
+    format!(
+        "{} / {}  {}%  {}/s  ETA {}",
+        format_bytes(done),
+        format_bytes(total),
+        percent,
+        format_bytes(rate as u64),
+        eta
+    )
+}
+
+fn format_bytes(n: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if n < 1024 {
+        return format!("{} B", n);
+    }
+    let mut value = n as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn format_duration(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::fs::{self, EncryptOptions};
+    use crypto::header::MIN_CHUNK_SIZE;
+
+    #[derive(Default)]
+    struct Recorder {
+        updates: Vec<u64>,
+    }
+
+    impl Progress for Recorder {
+        fn update(&mut self, done: u64) {
+            self.updates.push(done);
+        }
+
+        fn finish(&mut self) {}
+    }
+
+    fn assert_increasing(updates: &[u64], total: u64) {
+        assert!(updates.windows(2).all(|w| w[0] < w[1]), "{:?}", updates);
+        assert_eq!(updates.last(), Some(&total));
+    }
+
+    #[test]
+    fn counts_grow_per_chunk_on_encrypt_and_decrypt() {
+        let plaintext = vec![0x3c; MIN_CHUNK_SIZE as usize * 10 + 5];
+        let opts = EncryptOptions {
+            chunk_size: MIN_CHUNK_SIZE,
+            ..EncryptOptions::default()
+        };
+        let key = [7u8; 32];
+
+        let mut recorder = Recorder::default();
+        let mut sealed = Vec::new();
+        let mut reader = ProgressReader::new(&plaintext[..], &mut recorder);
+        fs::encrypt_stream(&mut reader, &mut sealed, &key, &opts).unwrap();
+        assert_increasing(&recorder.updates, plaintext.len() as u64);
+        assert!(recorder.updates.len() >= 11, "{:?}", recorder.updates);
+
+        let mut recorder = Recorder::default();
+        let mut opened = Vec::new();
+        let mut reader = ProgressReader::new(&sealed[..], &mut recorder);
+        fs::decrypt_stream(&mut reader, &mut opened, &key).unwrap();
+        assert_increasing(&recorder.updates, sealed.len() as u64);
+        assert_eq!(opened, plaintext);
+    }
+
+    #[test]
+    fn mode_decisions() {
+        let big = Some(AUTO_THRESHOLD);
+        let small = Some(AUTO_THRESHOLD - 1);
+        assert!(ProgressMode::Auto.decide(false, big, true));
+        assert!(!ProgressMode::Auto.decide(false, big, false));
+        assert!(!ProgressMode::Auto.decide(false, small, true));
+        assert!(!ProgressMode::Auto.decide(false, None, true));
+        assert!(ProgressMode::Always.decide(false, small, false));
+        assert!(!ProgressMode::Never.decide(false, big, true));
+        /* standard output carries the data: never, even when forced */
+        assert!(!ProgressMode::Always.decide(true, big, true));
+        assert_eq!(ProgressMode::from_flags(true, false), ProgressMode::Always);
+        assert_eq!(ProgressMode::from_flags(false, true), ProgressMode::Never);
+        assert_eq!(ProgressMode::from_flags(false, false), ProgressMode::Auto);
+    }
+
+    #[test]
+    fn batch_adds_up_every_share() {
+        let batch = Batch::new(ProgressMode::Never);
+        batch.start(100);
+        let (mut a, mut b) = (batch.share(), batch.share());
+        a.update(10);
+        b.update(5);
+        a.update(30);
+        assert_eq!(batch.done.load(Ordering::Relaxed), 35);
+        assert!(batch.lock().is_none());
+    }
+
+    #[test]
+    fn rendering() {
+        let line = render(
+            512 * 1024 * 1024,
+            2048 * 1024 * 1024,
+            Duration::from_secs(4),
+        );
+        assert_eq!(line, "512.0 MiB / 2.0 GiB  25%  128.0 MiB/s  ETA 0:12");
+        assert_eq!(
+            render(0, 0, Duration::ZERO),
+            "0 B / 0 B  100%  0 B/s  ETA --:--"
+        );
+        assert_eq!(format_duration(3725), "1:02:05");
+        assert_eq!(format_bytes(1023), "1023 B");
+    }
+}
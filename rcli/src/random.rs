@@ -0,0 +1,152 @@
+/* rand: random bytes for salts, fixtures and overwriting files.
+ *
+ * Output is produced RAND_CHUNK bytes at a time, so memory use does not
+ * depend on --bytes. The chunk is a multiple of 3, which makes the base64
+ * of each chunk concatenate to the base64 of the whole, with padding only
+ * at the very end. */
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use base64::Engine;
+use clap::ValueEnum;
+
+/// Bytes generated and written per step.
+pub const RAND_CHUNK: usize = 48 * 1024;
+
+/// How `rand` writes its bytes.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RandFormat {
+    /// The bytes themselves; only with --out or when standard output is
+    /// not a terminal
+    Raw,
+    /// One lowercase hex string
+    Hex,
+    /// Standard base64 with padding
+    Base64,
+}
+
+/// Refuse raw bytes on a terminal, where they can garble its state.
+pub fn check_rand_output(format: RandFormat, to_terminal: bool) -> Result<(), String> {
+    if format == RandFormat::Raw && to_terminal {
+        return Err(
+            "refusing to write raw random bytes to the terminal; use --format hex or \
+             base64, --out, or redirect standard output"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Write `bytes` random bytes from `fill` to `writer` in `format`; the
+/// text formats are one line ending in a newline.
+pub fn write_random<W: Write>(
+    writer: &mut W,
+    bytes: u64,
+    format: RandFormat,
+    mut fill: impl FnMut(&mut [u8]),
+) -> io::Result<()> {
+    let mut buf = vec![0u8; RAND_CHUNK];
+    let mut left = bytes;
+    while left > 0 {
+        let chunk = &mut buf[..left.min(RAND_CHUNK as u64) as usize];
+        fill(chunk);
+        match format {
+            RandFormat::Raw => writer.write_all(chunk)?,
+            RandFormat::Hex => {
+                let hex: String = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+                writer.write_all(hex.as_bytes())?;
+            }
+            RandFormat::Base64 => {
+                let text = base64::engine::general_purpose::STANDARD.encode(&*chunk);
+                writer.write_all(text.as_bytes())?;
+            }
+        }
+        left -= chunk.len() as u64;
+    }
+    if format != RandFormat::Raw {
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/* rand --out: created or truncated in place, so overwriting a file reuses
+ * it. With `sensitive` a new file is created owner-only and an existing
+ * one is made so before anything is written to it. */
+pub fn open_output(path: &Path, sensitive: bool) -> io::Result<File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if sensitive {
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    }
+    let file = options.open(path)?;
+    #[cfg(unix)]
+    if sensitive {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* A recognisable stand-in for the RNG: a running byte counter */
+    fn counter() -> impl FnMut(&mut [u8]) {
+        let mut next = 0u8;
+        move |buf: &mut [u8]| {
+            for b in buf {
+                *b = next;
+                next = next.wrapping_add(1);
+            }
+        }
+    }
+
+    fn expected(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    fn random(bytes: u64, format: RandFormat) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_random(&mut out, bytes, format, counter()).unwrap();
+        out
+    }
+
+    #[test]
+    fn sizes_per_format() {
+        for len in [0, 1, 2, 3, 16, 1000] {
+            assert_eq!(random(len as u64, RandFormat::Raw), expected(len));
+            let hex = random(len as u64, RandFormat::Hex);
+            assert_eq!(hex.len(), 2 * len + 1);
+            let b64 = random(len as u64, RandFormat::Base64);
+            assert_eq!(b64.len(), 4 * len.div_ceil(3) + 1);
+        }
+    }
+
+    #[test]
+    fn chunks_join_seamlessly() {
+        for len in [RAND_CHUNK, RAND_CHUNK + 1, 2 * RAND_CHUNK + 2] {
+            assert_eq!(random(len as u64, RandFormat::Raw), expected(len));
+
+            let hex = String::from_utf8(random(len as u64, RandFormat::Hex)).unwrap();
+            let want: String = expected(len).iter().map(|b| format!("{:02x}", b)).collect();
+            assert_eq!(hex, format!("{}\n", want));
+
+            let b64 = String::from_utf8(random(len as u64, RandFormat::Base64)).unwrap();
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(b64.trim_end())
+                .unwrap();
+            assert_eq!(decoded, expected(len));
+        }
+    }
+
+    #[test]
+    fn raw_never_reaches_a_terminal() {
+        assert!(check_rand_output(RandFormat::Raw, true).is_err());
+        assert!(check_rand_output(RandFormat::Raw, false).is_ok());
+        assert!(check_rand_output(RandFormat::Hex, true).is_ok());
+        assert!(check_rand_output(RandFormat::Base64, true).is_ok());
+    }
+}
@@ -0,0 +1,329 @@
+/* chacha -r: every regular file under a directory.
+ *
+ * The tree is listed up front and sorted, so runs are repeatable and
+ * files written along the way are never picked up. Symlinks, sockets,
+ * FIFOs and devices are skipped rather than followed or opened, and
+ * --exclude'd entries are dropped without a word (excluded directories
+ * are not even listed). Results go in place, or under --output-dir at
+ * the same relative path. With --jobs the files are worked on together,
+ * but reported in the same sorted order. */
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::files::Excludes;
+use crate::jobs::{Jobs, Report};
+use crate::status::{self, Action, CliError, ErrorCode, Record};
+
+/// One entry found under the root, by path relative to it.
+#[derive(Debug)]
+pub enum Entry {
+    File(PathBuf),
+    /* Not a regular file or directory */
+    Special(PathBuf),
+    /* A directory that could not be listed */
+    Unreadable(PathBuf, io::Error),
+}
+
+impl Entry {
+    fn path(&self) -> &Path {
+        match self {
+            Entry::File(p) | Entry::Special(p) | Entry::Unreadable(p, _) => p,
+        }
+    }
+}
+
+/// Everything under `root` not excluded, sorted by relative path.
+pub fn walk(root: &Path, excludes: &Excludes) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(dir) = pending.pop() {
+        let listing = match fs::read_dir(root.join(&dir)) {
+            Ok(listing) => listing,
+            Err(e) => {
+                entries.push(Entry::Unreadable(dir, e));
+                continue;
+            }
+        };
+        for entry in listing {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    entries.push(Entry::Unreadable(dir.clone(), e));
+                    break;
+                }
+            };
+            let path = dir.join(entry.file_name());
+            let file_type = entry.file_type();
+            let is_dir = file_type.as_ref().is_ok_and(|t| t.is_dir());
+            if excludes.excludes(&path, is_dir) {
+                continue;
+            }
+            match file_type {
+                Ok(t) if t.is_dir() => pending.push(path),
+                Ok(t) if t.is_file() => entries.push(Entry::File(path)),
+                Ok(_) => entries.push(Entry::Special(path)),
+                Err(e) => entries.push(Entry::Unreadable(path, e)),
+            }
+        }
+    }
+    entries.sort_by(|a, b| a.path().cmp(b.path()));
+    entries
+}
+
+/// What happened to one file: done, with what is left to print, or
+/// skipped and why.
+pub enum Outcome {
+    Done(Report),
+    Skipped(String),
+}
+
+/// Counts for the closing summary.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub done: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// The exit status for the first failure.
+    pub first_failure: Option<i32>,
+}
+
+impl Tally {
+    pub fn summary(&self, verb: &str) -> String {
+        format!(
+            "{} {}, {} skipped, {} failed",
+            self.done, verb, self.skipped, self.failed
+        )
+    }
+}
+
+/// Run `op` on every regular file under `root`, `jobs` at a time, with
+/// the matching path under `output_dir` (parents created) when there is
+/// one. Skips and failures are reported on standard error, or as records
+/// of `action` with --json; without `keep_going` the walk stops at the
+/// first failure.
+pub fn process_tree<F>(
+    root: &Path,
+    output_dir: Option<&Path>,
+    excludes: &Excludes,
+    keep_going: bool,
+    action: Action,
+    jobs: Jobs,
+    op: F,
+) -> Result<Tally, String>
+where
+    F: Fn(&Path, Option<&Path>) -> Result<Outcome, CliError> + Sync,
+{
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", root.display()));
+    }
+    if let Some(output_dir) = output_dir {
+        check_output_dir(root, output_dir)?;
+    }
+
+    let entries = walk(root, excludes);
+    let sizes = entries.iter().filter_map(|entry| match entry {
+        Entry::File(rel) => fs::metadata(root.join(rel)).ok(),
+        _ => None,
+    });
+    jobs.start(sizes.map(|metadata| metadata.len()).sum());
+
+    let mut tally = Tally::default();
+    let each = |entry: &Entry| match entry {
+        Entry::File(rel) => {
+            let input = root.join(rel);
+            let output = output_dir.map(|dir| dir.join(rel));
+            let parent = output.as_deref().and_then(Path::parent);
+            match parent.map(fs::create_dir_all).transpose() {
+                Ok(_) => op(&input, output.as_deref()),
+                Err(e) => Err(CliError::new(ErrorCode::Io, e.to_string())),
+            }
+        }
+        Entry::Special(_) => Ok(Outcome::Skipped("not a regular file".to_string())),
+        Entry::Unreadable(_, e) => Err(CliError::new(ErrorCode::Io, e.to_string())),
+    };
+    jobs.run(&entries, each, |entry, result| {
+        let shown = root.join(entry.path());
+        let path = shown.display().to_string();
+        let result = result.and_then(|outcome| match outcome {
+            Outcome::Done(report) => report().map(|_| None),
+            Outcome::Skipped(why) => Ok(Some(why)),
+        });
+        match result {
+            Ok(None) => tally.done += 1,
+            Ok(Some(why)) if status::json() => {
+                status::emit(&Record::Skipped {
+                    action,
+                    path: &path,
+                    reason: &why,
+                });
+                tally.skipped += 1;
+            }
+            Ok(Some(why)) => {
+                eprintln!("Warning: skipping {}: {}", path, why);
+                tally.skipped += 1;
+            }
+            Err(e) => {
+                status::error(&e, Some(&path), format_args!("Error: {}: {}", path, e));
+                tally.first_failure.get_or_insert(e.exit_status());
+                tally.failed += 1;
+                return keep_going;
+            }
+        }
+        true
+    });
+    Ok(tally)
+}
+
+/* Writing into the tree being walked would encrypt the output on the
+ * next run, or the output of the output */
+fn check_output_dir(root: &Path, output_dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(output_dir)
+        .map_err(|e| format!("cannot create {}: {}", output_dir.display(), e))?;
+    let canonical = |p: &Path| {
+        p.canonicalize()
+            .map_err(|e| format!("{}: {}", p.display(), e))
+    };
+    if canonical(output_dir)?.starts_with(canonical(root)?) {
+        return Err("--output-dir must not be inside the directory being processed".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn done() -> Outcome {
+        Outcome::Done(Box::new(|| Ok(())))
+    }
+
+    #[test]
+    fn walk_is_sorted_and_recursive() {
+        let dir = tempfile::tempdir().unwrap();
+        for rel in ["b/z", "a", "b/c/d", "c"] {
+            let path = dir.path().join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, rel).unwrap();
+        }
+        let found: Vec<PathBuf> = walk(dir.path(), &Excludes::default())
+            .iter()
+            .map(|e| e.path().to_path_buf())
+            .collect();
+        let expected: Vec<PathBuf> = ["a", "b/c/d", "b/z", "c"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_special() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("real"), b"x").unwrap();
+        std::os::unix::fs::symlink("real", dir.path().join("link")).unwrap();
+        let entries = walk(dir.path(), &Excludes::default());
+        assert!(matches!(&entries[0], Entry::Special(p) if p == Path::new("link")));
+        assert!(matches!(&entries[1], Entry::File(p) if p == Path::new("real")));
+    }
+
+    #[test]
+    fn output_dir_inside_root_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let inside = dir.path().join("out");
+        let none = Excludes::default();
+        let err = process_tree(
+            dir.path(),
+            Some(&inside),
+            &none,
+            true,
+            Action::Encrypt,
+            Jobs::one(),
+            |_, _| Ok(done()),
+        );
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn keep_going_decides_whether_to_stop() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a", "b", "c"] {
+            fs::write(dir.path().join(name), name).unwrap();
+        }
+        let none = Excludes::default();
+        let fail_b = |input: &Path, _: Option<&Path>| match input.ends_with("b") {
+            true => Err("boom".into()),
+            false => Ok(done()),
+        };
+        let one = Jobs::one();
+        let tally =
+            process_tree(dir.path(), None, &none, false, Action::Encrypt, one, fail_b).unwrap();
+        assert_eq!((tally.done, tally.failed), (1, 1));
+        let tally =
+            process_tree(dir.path(), None, &none, true, Action::Encrypt, one, fail_b).unwrap();
+        assert_eq!((tally.done, tally.failed), (2, 1));
+        assert_eq!(tally.first_failure, Some(crate::exit::FAILURE));
+        assert_eq!(
+            tally.summary("encrypted"),
+            "2 encrypted, 0 skipped, 1 failed"
+        );
+    }
+
+    #[test]
+    fn jobs_tally_like_one_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        for n in 0..40 {
+            fs::write(dir.path().join(format!("{:02}", n)), b"x").unwrap();
+        }
+        let none = Excludes::default();
+        let fail_some = |input: &Path, _: Option<&Path>| {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            match input.ends_with("07") {
+                true => Err("boom".into()),
+                false => Ok(done()),
+            }
+        };
+        let jobs = Jobs::new(4, None);
+        let tally = process_tree(
+            dir.path(),
+            None,
+            &none,
+            true,
+            Action::Encrypt,
+            jobs,
+            fail_some,
+        )
+        .unwrap();
+        assert_eq!((tally.done, tally.failed), (39, 1));
+        /* Without --keep-going the files already started still count */
+        let tally = process_tree(
+            dir.path(),
+            None,
+            &none,
+            false,
+            Action::Encrypt,
+            jobs,
+            fail_some,
+        )
+        .unwrap();
+        assert_eq!(tally.failed, 1);
+        assert!(tally.done >= 7 && tally.done < 39, "{:?}", tally);
+    }
+
+    #[test]
+    fn excluded_directories_are_not_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        for rel in ["keep/a", "node_modules/x/y", "src/node_modules/z", "b.tmp"] {
+            let path = dir.path().join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, rel).unwrap();
+        }
+        let excludes = Excludes::new(&["node_modules/".to_string(), "*.tmp".to_string()]).unwrap();
+        let found: Vec<PathBuf> = walk(dir.path(), &excludes)
+            .iter()
+            .map(|e| e.path().to_path_buf())
+            .collect();
+        assert_eq!(found, [PathBuf::from("keep/a")]);
+    }
+}
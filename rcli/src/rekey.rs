@@ -0,0 +1,307 @@
+/* rekey: move encrypted files to a new key or passphrase without their
+ * plaintext ever reaching a disk.
+ *
+ * Each file streams through `fs::rekey_stream`, which opens one chunk
+ * under the old key and reseals it under the new one in a buffer that is
+ * zeroized afterwards. The result goes out through `OutputArgs` like any
+ * other command's, so in place it is written to a temporary file that only
+ * replaces the original once every chunk has authenticated: a damaged
+ * file is left as it was. The header keeps its format version, chunk
+ * size and mode, and gets a fresh nonce prefix. Files from before the
+ * chunked format (with --force) are upgraded to it; they were sealed in
+ * one piece, so they are opened in memory. Armored files stay armored.
+ *
+ * Either side can be a key file or a passphrase. A passphrase is read
+ * once: the old key is derived for each file from the KDF settings in its
+ * header, and the new one for each file with a fresh salt. */
+use std::io::{Cursor, Read, Write};
+use std::path::PathBuf;
+
+use clap::Args;
+use crypto::fs::{self, EncryptOptions, FsCryptoError, Summary};
+use crypto::header::{Algorithm, FileHeader, HeaderError, MAGIC};
+use crypto::{derive_file_key, passphrase_kdf, Argon2Params, ArmorWriter, ErrorStates};
+
+use crate::key::{read_key_file, PassphraseSource, Prompter, CHACHA_KEY_LEN};
+use crate::status::{self, CliError, ErrorCode};
+use crate::OutputArgs;
+use crate::{dearmored_if_armored, decrypt_single_shot, derive_header_key, failure, log_header};
+
+/// Where the key the files are encrypted under now comes from.
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct OldSecret {
+    /// The key the files are encrypted under now (raw bytes or hex)
+    #[arg(long, value_name = "PATH")]
+    old_key_file: Option<PathBuf>,
+
+    /// The files were encrypted with a passphrase: ask for it, or read
+    /// the first line of piped standard input
+    #[arg(long)]
+    old_passphrase: bool,
+
+    /// As --old-passphrase, from the first line of a file
+    #[arg(long, value_name = "PATH")]
+    old_passphrase_file: Option<PathBuf>,
+}
+
+/// Where the key to encrypt the files under from now on comes from.
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct NewSecret {
+    /// The key to encrypt under from now on (raw bytes or hex)
+    #[arg(long, value_name = "PATH")]
+    new_key_file: Option<PathBuf>,
+
+    /// Encrypt under a new passphrase: ask for it twice, or read it from
+    /// piped standard input (the line after any --old-passphrase)
+    #[arg(long)]
+    new_passphrase: bool,
+
+    /// As --new-passphrase, from the first line of a file
+    #[arg(long, value_name = "PATH")]
+    new_passphrase_file: Option<PathBuf>,
+}
+
+/// A key, or a passphrase to derive one from.
+pub enum Secret {
+    Key(Vec<u8>),
+    Passphrase(String),
+}
+
+fn source(file: Option<PathBuf>, flag: bool) -> Option<PassphraseSource> {
+    match file {
+        Some(path) => Some(PassphraseSource::File(path)),
+        None => flag.then(PassphraseSource::interactive),
+    }
+}
+
+fn load(
+    key_file: Option<PathBuf>,
+    passphrase: Option<PassphraseSource>,
+    prompter: &mut dyn Prompter,
+    confirm: bool,
+    what: &str,
+) -> Result<Secret, CliError> {
+    let bad_key = |e| CliError::new(ErrorCode::BadKey, e);
+    match (key_file, passphrase) {
+        (Some(path), _) => read_key_file(&path, &CHACHA_KEY_LEN)
+            .map(Secret::Key)
+            .map_err(bad_key),
+        (None, Some(source)) => source
+            .read_named(prompter, confirm, what)
+            .map(Secret::Passphrase)
+            .map_err(bad_key),
+        (None, None) => unreachable!("clap requires one of the group"),
+    }
+}
+
+/// Read both secrets, the old one first. `data_on_stdin` is whether a
+/// file or the list of files is read from standard input, which a
+/// passphrase then cannot be.
+pub fn load_secrets(
+    old: OldSecret,
+    new: NewSecret,
+    prompter: &mut dyn Prompter,
+    data_on_stdin: bool,
+) -> Result<(Secret, Secret), CliError> {
+    let old_source = source(old.old_passphrase_file, old.old_passphrase);
+    let new_source = source(new.new_passphrase_file, new.new_passphrase);
+    let piped = [&old_source, &new_source]
+        .iter()
+        .any(|source| **source == Some(PassphraseSource::Stdin));
+    if data_on_stdin && piped {
+        return Err(CliError::new(
+            ErrorCode::Usage,
+            "standard input carries the data, so it cannot carry a passphrase \
+             too; use --old-passphrase-file or --new-passphrase-file",
+        ));
+    }
+    let old = load(
+        old.old_key_file,
+        old_source,
+        prompter,
+        false,
+        "old passphrase",
+    )?;
+    let new = load(
+        new.new_key_file,
+        new_source,
+        prompter,
+        true,
+        "new passphrase",
+    )?;
+    Ok((old, new))
+}
+
+/// Rekey `file` from `old` to `new`, writing through `output`. Without
+/// `force` a file without the rcli header is refused.
+pub fn rekey_file(
+    file: &str,
+    output: &OutputArgs,
+    old: &Secret,
+    new: &Secret,
+) -> Result<(Summary, Algorithm), CliError> {
+    let mut algorithm = Algorithm::ChaCha20Poly1305;
+    let result = output.write(file, |reader, writer| {
+        let (mut reader, armored) = dearmored_if_armored(reader)?;
+        let mut rekey = |writer: &mut dyn Write| {
+            let rekeyed = rekey_stream(file, &mut reader, writer, old, new, output.force);
+            rekeyed.map_err(|e| match e {
+                Failed::Cli(e) => status::carry(e),
+                Failed::Fs(e) => e,
+            })
+        };
+        let (summary, used) = match armored {
+            true => {
+                let mut armor = ArmorWriter::new(writer)?;
+                let done = rekey(&mut armor)?;
+                armor.finish()?;
+                done
+            }
+            false => rekey(writer)?,
+        };
+        algorithm = used;
+        Ok(summary)
+    });
+    match result {
+        /* As decrypt --passphrase says it */
+        Err(e @ FsCryptoError::Crypto(ErrorStates::WrongKey | ErrorStates::DecryptionFailed))
+            if matches!(old, Secret::Passphrase(_)) =>
+        {
+            Err(CliError::new(
+                crate::status::error_code(&e),
+                "incorrect passphrase or corrupted file",
+            ))
+        }
+        result => result.map(|summary| (summary, algorithm)).map_err(failure),
+    }
+}
+
+/* What can stop a file: the library, or a problem with the secrets that
+ * is only found once the header has been read */
+enum Failed {
+    Cli(CliError),
+    Fs(FsCryptoError),
+}
+
+impl From<FsCryptoError> for Failed {
+    fn from(e: FsCryptoError) -> Self {
+        Failed::Fs(e)
+    }
+}
+
+impl From<std::io::Error> for Failed {
+    fn from(e: std::io::Error) -> Self {
+        Failed::Fs(e.into())
+    }
+}
+
+impl From<CliError> for Failed {
+    fn from(e: CliError) -> Self {
+        Failed::Cli(e)
+    }
+}
+
+fn rekey_stream(
+    file: &str,
+    reader: &mut dyn Read,
+    mut writer: &mut dyn Write,
+    old: &Secret,
+    new: &Secret,
+    force: bool,
+) -> Result<(Summary, Algorithm), Failed> {
+    let mut magic = Vec::with_capacity(MAGIC.len());
+    (&mut *reader)
+        .take(MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    let is_rcli = FileHeader::has_magic(&magic);
+    let mut reader = Cursor::new(magic).chain(reader);
+    if !is_rcli {
+        if !force {
+            return Err(FsCryptoError::Header(HeaderError::BadMagic).into());
+        }
+        let summary = upgrade(file, &mut reader, writer, old, new)?;
+        return Ok((summary, Algorithm::ChaCha20Poly1305));
+    }
+
+    let (header, header_bytes) = fs::read_header(&mut reader)?;
+    log_header(file, &header);
+    let derived;
+    let old_key = match old {
+        Secret::Key(key) => &key[..],
+        Secret::Passphrase(passphrase) => {
+            let kdf = header.passphrase.ok_or_else(|| {
+                CliError::new(
+                    ErrorCode::WrongKey,
+                    format!("{} was not encrypted with a passphrase", file),
+                )
+            })?;
+            derived = derive_header_key(passphrase, &kdf)?;
+            derived.as_bytes()
+        }
+    };
+    let mut reader = Cursor::new(header_bytes).chain(reader);
+    let summary = match new {
+        Secret::Key(key) => fs::rekey_stream(&mut reader, &mut writer, old_key, key)?,
+        Secret::Passphrase(passphrase) => {
+            let (key, kdf) = new_passphrase_key(passphrase)?;
+            let new_key = key.as_bytes();
+            fs::rekey_stream_to_passphrase(&mut reader, &mut writer, old_key, new_key, kdf)?
+        }
+    };
+    Ok((summary, header.algorithm))
+}
+
+/* A file from before the chunked format: nonce || ciphertext+tag under
+ * a raw key, resealed as an rcli stream */
+fn upgrade(
+    file: &str,
+    mut reader: &mut dyn Read,
+    mut writer: &mut dyn Write,
+    old: &Secret,
+    new: &Secret,
+) -> Result<Summary, Failed> {
+    let Secret::Key(old_key) = old else {
+        return Err(CliError::new(
+            ErrorCode::WrongKey,
+            format!(
+                "{} has no rcli header, so it was not encrypted with a passphrase",
+                file
+            ),
+        )
+        .into());
+    };
+    let mut plaintext = Vec::new();
+    let opened = decrypt_single_shot(&mut reader, &mut plaintext, old_key)?;
+    let sealed = match new {
+        Secret::Key(key) => {
+            let opts = EncryptOptions::default();
+            fs::encrypt_stream(&mut &plaintext[..], &mut writer, key, &opts)
+        }
+        Secret::Passphrase(passphrase) => {
+            let (key, kdf) = new_passphrase_key(passphrase)?;
+            let opts = EncryptOptions {
+                key_check: true,
+                passphrase: Some(kdf),
+                ..EncryptOptions::default()
+            };
+            fs::encrypt_stream(&mut &plaintext[..], &mut writer, key.as_bytes(), &opts)
+        }
+    };
+    plaintext.fill(0);
+    let sealed = sealed?;
+    Ok(Summary {
+        bytes_in: opened.bytes_in,
+        ..sealed
+    })
+}
+
+fn new_passphrase_key(
+    passphrase: &str,
+) -> Result<(crypto::SecretKey32, crypto::header::PassphraseKdf), CliError> {
+    let kdf = passphrase_kdf(Argon2Params::default());
+    let key = derive_file_key(passphrase, &kdf)
+        .map_err(|e| CliError::new(ErrorCode::Failed, format!("{:?}", e)))?;
+    Ok((key, kdf))
+}
@@ -0,0 +1,214 @@
+/* keypair, sign and verify-sig: Ed25519 signatures on files.
+ *
+ * keypair writes PREFIX.key, readable only by its owner, and PREFIX.pub.
+ * sign hashes the file in chunks and writes the signature envelope to
+ * FILE.sig; verify-sig reads it back. Every failure exits 1, but the
+ * message tells an unreadable or damaged signature file (or key) apart
+ * from a signature that is intact and simply does not match. */
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use crypto::{
+    prehash_reader, sign_prehashed, verify_prehashed, Ed25519SigningKey, Ed25519VerifyingKey,
+    Prehash, SignatureEnvelope, SigningError,
+};
+
+use crate::key::{warn_if_world_readable, write_key_file};
+use crate::status::{CliError, ErrorCode};
+
+/// The hash a file is reduced to before it is signed.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrehashArg {
+    Sha512,
+    Blake3,
+}
+
+impl From<PrehashArg> for Prehash {
+    fn from(arg: PrehashArg) -> Self {
+        match arg {
+            PrehashArg::Sha512 => Prehash::Sha512,
+            PrehashArg::Blake3 => Prehash::Blake3,
+        }
+    }
+}
+
+/* `path` with `suffix` added to its file name, so "v1.2" gives "v1.2.sig"
+ * rather than replacing the ".2" */
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// PREFIX.key and PREFIX.pub.
+pub fn keypair_paths(prefix: &Path) -> (PathBuf, PathBuf) {
+    (with_suffix(prefix, ".key"), with_suffix(prefix, ".pub"))
+}
+
+/// Write `sk` to PREFIX.key (owner-only) and its public key to
+/// PREFIX.pub. Unless `force`, neither file is written if either exists.
+pub fn write_keypair(
+    sk: &Ed25519SigningKey,
+    prefix: &Path,
+    force: bool,
+) -> Result<(PathBuf, PathBuf), String> {
+    let (secret, public) = keypair_paths(prefix);
+    if !force {
+        if let Some(existing) = [&secret, &public].into_iter().find(|p| p.exists()) {
+            return Err(format!(
+                "{} already exists; use --force to replace it",
+                existing.display()
+            ));
+        }
+    }
+    write_key_file(&secret, sk.to_text().as_bytes(), force)?;
+    write_new_file(&public, sk.public_key().to_text().as_bytes(), force)?;
+    Ok((secret, public))
+}
+
+/// Write `contents` to a new file at `path`, or replace it with `force`.
+pub fn write_new_file(path: &Path, contents: &[u8], force: bool) -> Result<(), String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut file = options.open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => format!(
+            "{} already exists; use --force to replace it",
+            path.display()
+        ),
+        _ => format!("cannot write {}: {}", path.display(), e),
+    })?;
+    file.write_all(contents)
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+fn read_text(path: &Path, what: &str) -> Result<String, String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {} {}: {}", what, path.display(), e))
+}
+
+/// Read the secret key written by keypair.
+pub fn read_signing_key(path: &Path) -> Result<Ed25519SigningKey, String> {
+    let text = read_text(path, "secret key")?;
+    warn_if_world_readable(path);
+    Ed25519SigningKey::from_text(&text)
+        .map_err(|_| format!("{} is not an rcli secret key", path.display()))
+}
+
+/// Read the public key written by keypair.
+pub fn read_verifying_key(path: &Path) -> Result<Ed25519VerifyingKey, String> {
+    Ed25519VerifyingKey::from_text(&read_text(path, "public key")?)
+        .map_err(|_| format!("{} is not an rcli public key", path.display()))
+}
+
+/// Read a signature file written by sign.
+pub fn read_signature(path: &Path) -> Result<SignatureEnvelope, CliError> {
+    let text = read_text(path, "signature").map_err(|e| CliError::new(ErrorCode::Io, e))?;
+    SignatureEnvelope::parse(&text).map_err(|e| {
+        let message = match e {
+            SigningError::UnsupportedPrehash => format!(
+                "signature file {} uses a prehash this build does not support",
+                path.display()
+            ),
+            _ => format!(
+                "signature file {} is damaged or not an rcli signature",
+                path.display()
+            ),
+        };
+        CliError::new(ErrorCode::Malformed, message)
+    })
+}
+
+/// Sign everything `reader` yields.
+pub fn sign_reader<R: io::Read>(
+    sk: &Ed25519SigningKey,
+    prehash: Prehash,
+    reader: R,
+) -> io::Result<SignatureEnvelope> {
+    Ok(sign_prehashed(
+        sk,
+        prehash,
+        &prehash_reader(prehash, reader)?,
+    ))
+}
+
+/// Check `envelope` over everything `reader` yields.
+pub fn verify_reader<R: io::Read>(
+    pk: &Ed25519VerifyingKey,
+    envelope: &SignatureEnvelope,
+    reader: R,
+) -> Result<(), CliError> {
+    let digest = prehash_reader(envelope.prehash, reader)?;
+    verify_prehashed(pk, envelope, &digest).map_err(|e| match e {
+        SigningError::KeyMismatch => CliError::new(
+            ErrorCode::WrongKey,
+            format!(
+                "signed by a different key (key ID {}, not {})",
+                envelope.key_id_hex(),
+                pk.key_id_hex()
+            ),
+        ),
+        _ => CliError::new(
+            ErrorCode::NotAuthentic,
+            "BAD signature: the file or its signature has been altered",
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::ed25519_generate;
+
+    #[test]
+    fn keypair_is_all_or_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("release");
+        std::fs::write(dir.path().join("release.pub"), b"old").unwrap();
+        let sk = ed25519_generate();
+        assert!(write_keypair(&sk, &prefix, false).is_err());
+        assert!(!dir.path().join("release.key").exists());
+
+        let (secret, public) = write_keypair(&sk, &prefix, true).unwrap();
+        let restored = read_signing_key(&secret).unwrap();
+        assert_eq!(restored.public_key(), read_verifying_key(&public).unwrap());
+    }
+
+    #[test]
+    fn suffixes_are_appended() {
+        assert_eq!(
+            keypair_paths(Path::new("keys/release")),
+            (
+                PathBuf::from("keys/release.key"),
+                PathBuf::from("keys/release.pub")
+            )
+        );
+        assert_eq!(
+            with_suffix(Path::new("app-v1.2"), ".sig"),
+            PathBuf::from("app-v1.2.sig")
+        );
+    }
+
+    #[test]
+    fn messages_tell_failures_apart() {
+        let sk = ed25519_generate();
+        let envelope = sign_reader(&sk, Prehash::Blake3, &b"artifact"[..]).unwrap();
+        assert_eq!(
+            verify_reader(&sk.public_key(), &envelope, &b"artifact"[..]),
+            Ok(())
+        );
+        let bad = verify_reader(&sk.public_key(), &envelope, &b"artifacT"[..]).unwrap_err();
+        assert_eq!(bad.code, ErrorCode::NotAuthentic);
+        assert!(bad.message.starts_with("BAD signature"), "{}", bad);
+        let other = ed25519_generate().public_key();
+        let wrong = verify_reader(&other, &envelope, &b"artifact"[..]).unwrap_err();
+        assert_eq!(wrong.code, ErrorCode::WrongKey);
+        assert!(wrong.message.contains(&other.key_id_hex()), "{}", wrong);
+    }
+}
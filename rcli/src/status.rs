@@ -0,0 +1,415 @@
+/* --json: status as JSON lines, for scripts.
+ *
+ * Normally each file rc4, encrypt, decrypt, chacha or verify processes
+ * gets a line of text on standard output and failures an "Error: ..."
+ * line on standard error.
+ * With --json each gets one `Record` instead, serialized on a line of its
+ * own to standard output, and progress is not drawn; warnings and prompts
+ * stay on standard error. When standard output carries the data itself
+ * (--file -) records go to standard error, so the stream stays clean.
+ * Error records carry the exit status (see exit.rs) as well as the code.
+ *
+ * The types below are the schema. Fields may be added, but existing ones
+ * keep their names and meaning, and an `ErrorCode` once given keeps its
+ * spelling. Every field is present in every record of its kind, null
+ * when it does not apply. */
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crypto::fs::FsCryptoError;
+use crypto::header::HeaderError;
+use crypto::{ArmorError, CompressError, ErrorStates};
+use serde::Serialize;
+
+use crate::exit;
+use crate::meter::Stats;
+use crate::nonce_log::NonceLogError;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static DATA_ON_STDOUT: AtomicBool = AtomicBool::new(false);
+
+/// `algorithm` of rc4's records.
+pub const RC4: &str = "RC4";
+
+/// Switch to JSON records.
+pub fn enable_json() {
+    JSON.store(true, Ordering::SeqCst);
+}
+
+/// Whether --json was given.
+pub fn json() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+/// Note that standard output now carries data, so records must not.
+pub fn data_on_stdout() {
+    DATA_ON_STDOUT.store(true, Ordering::SeqCst);
+}
+
+/// What was done to a file.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Encrypt,
+    Decrypt,
+    /// rc4, which is the same either way.
+    Process,
+    Verify,
+    Rekey,
+    Archive,
+    Extract,
+    Join,
+}
+
+/// Why something failed. Stable: scripts match on these.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Arguments that do not make sense together, or do not parse.
+    Usage,
+    /// A key, key file, passphrase or identity that cannot be read.
+    BadKey,
+    /// Reading or writing failed.
+    Io,
+    /// The input is not an rcli encrypted file.
+    NotEncrypted,
+    /// A damaged header or armor, or one from an unsupported version.
+    Malformed,
+    /// Not the key, passphrase, identity or associated data the file was
+    /// encrypted with.
+    WrongKey,
+    /// A chunk failed authentication: the file is damaged, or (for files
+    /// without a key check value) the key is wrong.
+    NotAuthentic,
+    /// The file ends before its last chunk.
+    Truncated,
+    /// Encrypting a file that already is, without --force.
+    AlreadyEncrypted,
+    /// The --nonce-log shows the key has already used the nonce.
+    NonceReused,
+    /// Decompressing would write more than --max-decompressed-size.
+    TooLarge,
+    /// Stopped by Ctrl-C or SIGTERM.
+    Interrupted,
+    /// Anything else.
+    Failed,
+}
+
+/// One line of --json output, tagged by `status`.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Record<'a> {
+    /// A file processed. `output` is where the result went: null for
+    /// standard output or, for verify, nowhere.
+    Ok {
+        action: Action,
+        path: &'a str,
+        output: Option<&'a str>,
+        bytes_in: u64,
+        bytes_out: u64,
+        algorithm: &'static str,
+        /// With --stats, the bytes that passed through, time and chunks.
+        stats: Option<Stats>,
+    },
+    /// A file left alone, as chacha -r --decrypt does with files that are
+    /// not encrypted.
+    Skipped {
+        action: Action,
+        path: &'a str,
+        reason: &'a str,
+    },
+    /// A failure, of one file when `path` is set and of the whole command
+    /// otherwise.
+    Error {
+        code: ErrorCode,
+        /// The status rcli exits with for this code; see exit.rs.
+        exit_code: i32,
+        message: &'a str,
+        path: Option<&'a str>,
+    },
+    /// With --stats, the sum over every file done, after the last.
+    Total { files: u64, stats: Stats },
+}
+
+/// Write `record` as a line of its own.
+pub fn emit(record: &Record) {
+    let line = serde_json::to_string(record).expect("records always serialize");
+    if DATA_ON_STDOUT.load(Ordering::SeqCst) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// A failure: what to tell the user, and its code, which decides the
+/// exit status.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CliError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl CliError {
+    pub fn new(code: ErrorCode, message: impl fmt::Display) -> Self {
+        CliError {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn exit_status(&self) -> i32 {
+        exit::status(self.code)
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::new(ErrorCode::Io, e)
+    }
+}
+
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        CliError::new(ErrorCode::Failed, message)
+    }
+}
+
+impl From<&str> for CliError {
+    fn from(message: &str) -> Self {
+        CliError::new(ErrorCode::Failed, message)
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// `e` carried out of an output callback, which can only return an
+/// FsCryptoError, as an I/O error. Its code and message are kept.
+pub fn carry(e: CliError) -> FsCryptoError {
+    FsCryptoError::Io(io::Error::other(e))
+}
+
+/// chacha --encrypt's refusal of input that starts with the rcli magic
+/// or armor, carried out of the output callback as an I/O error.
+#[derive(Debug)]
+pub struct AlreadyEncrypted;
+
+impl fmt::Display for AlreadyEncrypted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("already encrypted (use --force to encrypt again)")
+    }
+}
+
+impl std::error::Error for AlreadyEncrypted {}
+
+/// Decrypting one part of a split file on its own, carried out of the
+/// output callback as an I/O error.
+#[derive(Debug)]
+pub struct SplitPart;
+
+impl fmt::Display for SplitPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("one part of a file written with --split; put the parts back together with rcli join first")
+    }
+}
+
+impl std::error::Error for SplitPart {}
+
+/// A failure after --backup copied the original, carried out of the
+/// output callback as an I/O error so the message can say where the copy
+/// is. Its code is the underlying error's.
+#[derive(Debug)]
+pub struct BackedUp {
+    pub error: FsCryptoError,
+    pub backup: PathBuf,
+}
+
+impl fmt::Display for BackedUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (the original is intact in {})",
+            self.error,
+            self.backup.display()
+        )
+    }
+}
+
+impl std::error::Error for BackedUp {}
+
+/// The code for an error from crypto::fs.
+pub fn error_code(e: &FsCryptoError) -> ErrorCode {
+    match e {
+        FsCryptoError::Io(io) if io.get_ref().is_some_and(|inner| inner.is::<CliError>()) => {
+            let carried = io
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<CliError>());
+            carried.expect("checked above").code
+        }
+        FsCryptoError::Io(io) if io.get_ref().is_some_and(|inner| inner.is::<BackedUp>()) => {
+            let backed_up = io
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<BackedUp>());
+            error_code(&backed_up.expect("checked above").error)
+        }
+        FsCryptoError::Io(io)
+            if io
+                .get_ref()
+                .is_some_and(|inner| inner.is::<AlreadyEncrypted>()) =>
+        {
+            ErrorCode::AlreadyEncrypted
+        }
+        FsCryptoError::Io(io)
+            if io.get_ref().is_some_and(|inner| {
+                matches!(
+                    inner.downcast_ref::<NonceLogError>(),
+                    Some(NonceLogError::Reused { .. })
+                )
+            }) =>
+        {
+            ErrorCode::NonceReused
+        }
+        FsCryptoError::Io(io)
+            if io.get_ref().is_some_and(|inner| {
+                matches!(
+                    inner.downcast_ref::<CompressError>(),
+                    Some(CompressError::TooLarge { .. })
+                )
+            }) =>
+        {
+            ErrorCode::TooLarge
+        }
+        /* Armor problems reach us as I/O errors, as does compressed data
+         * that will not decompress */
+        FsCryptoError::Io(io) if io.get_ref().is_some_and(|inner| inner.is::<ArmorError>()) => {
+            ErrorCode::Malformed
+        }
+        FsCryptoError::Io(io)
+            if io
+                .get_ref()
+                .is_some_and(|inner| inner.is::<CompressError>()) =>
+        {
+            ErrorCode::Malformed
+        }
+        FsCryptoError::Io(io) if io.get_ref().is_some_and(|inner| inner.is::<SplitPart>()) => {
+            ErrorCode::NotEncrypted
+        }
+        FsCryptoError::Io(_) => ErrorCode::Io,
+        FsCryptoError::Header(HeaderError::BadMagic) => ErrorCode::NotEncrypted,
+        /* Sealed to a recipient, so no key or passphrase opens it */
+        FsCryptoError::Header(HeaderError::Recipient) => ErrorCode::WrongKey,
+        FsCryptoError::Header(_) => ErrorCode::Malformed,
+        FsCryptoError::Crypto(ErrorStates::WrongKey | ErrorStates::AadMismatch) => {
+            ErrorCode::WrongKey
+        }
+        FsCryptoError::Crypto(ErrorStates::DecryptionFailed) => ErrorCode::NotAuthentic,
+        FsCryptoError::Crypto(ErrorStates::StreamTruncated) => ErrorCode::Truncated,
+        FsCryptoError::Crypto(_) => ErrorCode::Failed,
+    }
+}
+
+/// Report `failure`: with --json as an error record about `path`, and
+/// otherwise as `text` on standard error.
+pub fn error(failure: &CliError, path: Option<&str>, text: fmt::Arguments) {
+    if json() {
+        emit(&Record::Error {
+            code: failure.code,
+            exit_code: failure.exit_status(),
+            message: &failure.message,
+            path,
+        });
+    } else {
+        eprintln!("{}", text);
+    }
+}
+
+/// Report a failure of the whole command as "Error: message" (or a
+/// record) and exit with its status.
+pub fn fail(failure: CliError) -> ! {
+    error(&failure, None, format_args!("Error: {}", failure));
+    std::process::exit(failure.exit_status());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, to_value};
+
+    #[test]
+    fn records_keep_their_shape() {
+        let ok = Record::Ok {
+            action: Action::Encrypt,
+            path: "a.txt",
+            output: None,
+            bytes_in: 10,
+            bytes_out: 38,
+            algorithm: "ChaCha20-Poly1305",
+            stats: None,
+        };
+        assert_eq!(
+            to_value(&ok).unwrap(),
+            json!({
+                "status": "ok",
+                "action": "encrypt",
+                "path": "a.txt",
+                "output": null,
+                "bytes_in": 10,
+                "bytes_out": 38,
+                "algorithm": "ChaCha20-Poly1305",
+                "stats": null,
+            })
+        );
+        let error = Record::Error {
+            code: ErrorCode::NotAuthentic,
+            exit_code: exit::AUTH,
+            message: "chunk 0 failed authentication",
+            path: Some("a.txt"),
+        };
+        assert_eq!(
+            to_value(&error).unwrap(),
+            json!({
+                "status": "error",
+                "code": "not_authentic",
+                "exit_code": 4,
+                "message": "chunk 0 failed authentication",
+                "path": "a.txt",
+            })
+        );
+    }
+
+    #[test]
+    fn crypto_errors_are_classified() {
+        let armor = FsCryptoError::Io(std::io::Error::other(ArmorError::MissingEnd));
+        assert_eq!(error_code(&armor), ErrorCode::Malformed);
+        let again = FsCryptoError::Io(std::io::Error::other(AlreadyEncrypted));
+        assert_eq!(error_code(&again), ErrorCode::AlreadyEncrypted);
+        let reused = NonceLogError::Reused {
+            fingerprint: String::new(),
+            prefix: String::new(),
+        };
+        let reused = FsCryptoError::Io(std::io::Error::other(reused));
+        assert_eq!(error_code(&reused), ErrorCode::NonceReused);
+        let carried = carry(CliError::new(ErrorCode::WrongKey, "not this identity"));
+        assert_eq!(error_code(&carried), ErrorCode::WrongKey);
+        let cases = [
+            (HeaderError::BadMagic.into(), ErrorCode::NotEncrypted),
+            (HeaderError::Truncated.into(), ErrorCode::Malformed),
+            (ErrorStates::AadMismatch.into(), ErrorCode::WrongKey),
+            (
+                ErrorStates::DecryptionFailed.into(),
+                ErrorCode::NotAuthentic,
+            ),
+            (ErrorStates::StreamTruncated.into(), ErrorCode::Truncated),
+        ];
+        for (e, code) in cases {
+            assert_eq!(error_code(&e), code, "{:?}", e);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+/* --aad/--aad-file: ciphertexts bound to contextual data */
+use assert_cmd::Command;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn chacha(input: &Path, output: &Path, mode: &str, aad: &[&str]) -> Command {
+    let mut cmd = Command::cargo_bin("rcli").unwrap();
+    cmd.args(["chacha", "--key-hex", KEY_HEX, mode, "--force"])
+        .args([
+            "-f",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+    for value in aad {
+        cmd.args(["--aad", value]);
+    }
+    cmd
+}
+
+/* Encrypt "backup contents" under `aad`, returning the encrypted path */
+fn sealed_with(dir: &Path, aad: &[&str]) -> std::path::PathBuf {
+    let (plain, sealed) = (dir.join("plain"), dir.join("sealed"));
+    std::fs::write(&plain, b"backup contents").unwrap();
+    chacha(&plain, &sealed, "--encrypt", aad).assert().success();
+    sealed
+}
+
+fn mismatch(sealed: &Path, aad: &[&str]) {
+    let opened = sealed.with_file_name("opened");
+    let output = chacha(sealed, &opened, "--decrypt", aad)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("associated data does not match"),
+        "{:?}: {}",
+        aad,
+        stderr
+    );
+    assert!(!opened.exists());
+}
+
+#[test]
+fn matching_aad_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let sealed = sealed_with(dir.path(), &["host=db1", "set=nightly"]);
+    let opened = dir.path().join("opened");
+    chacha(&sealed, &opened, "--decrypt", &["host=db1", "set=nightly"])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&opened).unwrap(), b"backup contents");
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args([
+            "verify",
+            "--key-hex",
+            KEY_HEX,
+            "-f",
+            sealed.to_str().unwrap(),
+        ])
+        .args(["--aad", "host=db1", "--aad", "set=nightly"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn missing_extra_or_different_aad_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let sealed = sealed_with(dir.path(), &["host=db1"]);
+    mismatch(&sealed, &[]);
+    mismatch(&sealed, &["host=db2"]);
+    mismatch(&sealed, &["host=db1", "set=nightly"]);
+
+    let unbound = sealed_with(dir.path(), &[]);
+    mismatch(&unbound, &["host=db1"]);
+}
+
+#[test]
+fn values_are_order_and_boundary_sensitive() {
+    let dir = tempfile::tempdir().unwrap();
+    let sealed = sealed_with(dir.path(), &["a", "b"]);
+    mismatch(&sealed, &["b", "a"]);
+    mismatch(&sealed, &["ab"]);
+
+    let sealed = sealed_with(dir.path(), &["ab", "c"]);
+    mismatch(&sealed, &["a", "bc"]);
+}
+
+#[test]
+fn aad_file_follows_strings() {
+    let dir = tempfile::tempdir().unwrap();
+    let context = dir.path().join("context");
+    std::fs::write(&context, b"set=nightly\n").unwrap();
+    let (plain, sealed, opened) = (
+        dir.path().join("plain"),
+        dir.path().join("sealed"),
+        dir.path().join("opened"),
+    );
+    std::fs::write(&plain, b"backup contents").unwrap();
+
+    chacha(&plain, &sealed, "--encrypt", &["host=db1"])
+        .args(["--aad-file", context.to_str().unwrap()])
+        .assert()
+        .success();
+    chacha(&sealed, &opened, "--decrypt", &["host=db1"])
+        .args(["--aad-file", context.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&opened).unwrap(), b"backup contents");
+    std::fs::remove_file(&opened).unwrap();
+
+    /* The file's bytes, newline and all, not a string of them */
+    mismatch(&sealed, &["host=db1", "set=nightly"]);
+}
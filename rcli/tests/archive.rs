@@ -0,0 +1,591 @@
+/* archive: whole directories packed into one encrypted file and back */
+mod common;
+
+use common::{file_with, rcli};
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+/* A small tree: a nested file, a large one, an empty one and an empty
+ * directory */
+fn project(dir: &Path) -> std::path::PathBuf {
+    let root = dir.join("project");
+    std::fs::create_dir_all(root.join("src/util")).unwrap();
+    std::fs::create_dir_all(root.join("empty")).unwrap();
+    file_with(&root, "README", b"read me");
+    file_with(&root.join("src"), "main.rs", b"fn main() {}\n");
+    file_with(&root.join("src/util"), "big.bin", &vec![0x42; 200_000]);
+    file_with(&root, "nothing", b"");
+    root
+}
+
+fn create(root: &Path, out: &str, key: &str) {
+    rcli()
+        .args(["archive", "create", "-f"])
+        .arg(root)
+        .args(["--out", out, "--key-file", key])
+        .assert()
+        .success();
+}
+
+/* Every file and directory under `root`, relative, with its contents */
+fn listing(root: &Path) -> Vec<(String, Option<Vec<u8>>)> {
+    let mut out = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.strip_prefix(root).unwrap().display().to_string();
+            if path.is_dir() {
+                pending.push(path);
+                out.push((name, None));
+            } else {
+                out.push((name, Some(std::fs::read(&path).unwrap())));
+            }
+        }
+    }
+    out.sort();
+    out
+}
+
+#[test]
+fn tree_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    let root = project(dir.path());
+    let archive = dir.path().join("backup.rcla");
+    let archive = archive.to_str().unwrap();
+    create(&root, archive, &key);
+
+    let sealed = std::fs::read(archive).unwrap();
+    assert!(!sealed.windows(7).any(|w| w == b"main.rs"));
+
+    let restore = dir.path().join("restore");
+    rcli()
+        .args(["archive", "extract", archive, "--into"])
+        .arg(&restore)
+        .args(["--key-file", &key])
+        .assert()
+        .success()
+        .stdout(format!("Extracted {} -> {}\n", archive, restore.display()));
+    assert_eq!(listing(&restore), listing(&root));
+}
+
+#[cfg(unix)]
+#[test]
+fn modes_and_times_are_kept() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    let root = project(dir.path());
+    let script = root.join("run.sh");
+    std::fs::write(&script, b"#!/bin/sh\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o750)).unwrap();
+    let when = UNIX_EPOCH + Duration::new(1_600_000_000, 123_000_000);
+    let file = std::fs::File::options().write(true).open(&script).unwrap();
+    file.set_modified(when).unwrap();
+    drop(file);
+
+    let archive = file_with(dir.path(), "backup.rcla", b"");
+    rcli()
+        .args(["archive", "create", "-f"])
+        .arg(&root)
+        .args(["--out", &archive, "--key-file", &key, "--force"])
+        .assert()
+        .success();
+    let restore = dir.path().join("restore");
+    std::fs::create_dir(&restore).unwrap();
+    rcli()
+        .args(["archive", "extract", &archive, "--into"])
+        .arg(&restore)
+        .args(["--key-file", &key])
+        .assert()
+        .success();
+
+    let restored = std::fs::metadata(restore.join("run.sh")).unwrap();
+    assert_eq!(restored.permissions().mode() & 0o777, 0o750);
+    assert_eq!(restored.modified().unwrap(), when);
+}
+
+#[test]
+fn existing_archive_needs_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    let root = project(dir.path());
+    let archive = file_with(dir.path(), "backup.rcla", b"keep me");
+    rcli()
+        .args(["archive", "create", "-f"])
+        .arg(&root)
+        .args(["--out", &archive, "--key-file", &key])
+        .assert()
+        .failure();
+    assert_eq!(std::fs::read(&archive).unwrap(), b"keep me");
+}
+
+#[test]
+fn escaping_paths_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    /* an archive written by hand, then encrypted as any file is */
+    let path = b"../evil";
+    let mut container = b"RCLA\x01".to_vec();
+    container.push(1);
+    container.extend_from_slice(&(path.len() as u16).to_be_bytes());
+    container.extend_from_slice(path);
+    container.extend_from_slice(&0o644u32.to_be_bytes());
+    container.extend_from_slice(&0i64.to_be_bytes());
+    container.extend_from_slice(&0u32.to_be_bytes());
+    container.extend_from_slice(&4u64.to_be_bytes());
+    container.extend_from_slice(b"evil");
+    container.push(0);
+    let archive = file_with(dir.path(), "evil.rcla", &container);
+    rcli()
+        .args(["encrypt", "--file", &archive, "--key-hex", KEY_HEX])
+        .assert()
+        .success();
+
+    let restore = dir.path().join("restore");
+    let output = rcli()
+        .args(["archive", "extract", &archive, "--into"])
+        .arg(&restore)
+        .args(["--key-file", &key])
+        .assert()
+        .code(5)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("escape"), "{}", stderr);
+    assert!(!dir.path().join("evil").exists());
+    assert!(!restore.exists());
+}
+
+#[test]
+fn truncated_archive_extracts_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    let root = project(dir.path());
+    let archive = dir.path().join("backup.rcla");
+    let archive = archive.to_str().unwrap();
+    rcli()
+        .args(["archive", "create", "-f"])
+        .arg(&root)
+        .args(["--out", archive, "--key-file", &key, "--chunk-size", "4K"])
+        .assert()
+        .success();
+    /* cut after the tenth chunk, so every chunk that is left authenticates */
+    let output = rcli()
+        .args(["inspect", "--json", "-f", archive])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let header = json["header_length"].as_u64().unwrap() as usize;
+    let sealed = std::fs::read(archive).unwrap();
+    std::fs::write(archive, &sealed[..header + 10 * (4096 + 16)]).unwrap();
+
+    let restore = dir.path().join("restore");
+    let output = rcli()
+        .args(["archive", "extract", archive, "--into"])
+        .arg(&restore)
+        .args(["--key-file", &key])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("truncated"), "{}", stderr);
+    assert!(!restore.exists());
+    let names: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names.len(), 3, "a staging directory was left: {:?}", names);
+}
+
+#[test]
+fn destination_must_be_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    let root = project(dir.path());
+    let archive = dir.path().join("backup.rcla");
+    let archive = archive.to_str().unwrap();
+    create(&root, archive, &key);
+    rcli()
+        .args(["archive", "extract", archive, "--into"])
+        .arg(&root)
+        .args(["--key-file", &key])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn listing_matches_the_tree() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    let root = project(dir.path());
+    let archive = dir.path().join("backup.rcla");
+    let archive = archive.to_str().unwrap();
+    create(&root, archive, &key);
+
+    let output = rcli()
+        .args(["archive", "list", archive, "--key-file", &key, "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let listed: Vec<(String, String, u64)> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            (
+                json["path"].as_str().unwrap().to_string(),
+                json["type"].as_str().unwrap().to_string(),
+                json["size"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    let mut expected: Vec<_> = listing(&root)
+        .into_iter()
+        .map(|(path, data)| match data {
+            Some(data) => (path, "file".to_string(), data.len() as u64),
+            None => (path, "directory".to_string(), 0),
+        })
+        .collect();
+    expected.sort();
+    assert_eq!(listed, expected);
+    /* nothing was unpacked */
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 3);
+
+    let output = rcli()
+        .args(["archive", "list", archive, "--key-file", &key])
+        .output()
+        .unwrap();
+    let text = String::from_utf8(output.stdout).unwrap();
+    let big = text.lines().find(|line| line.ends_with("src/util/big.bin"));
+    assert!(big.unwrap().contains(" 200000 "), "{}", text);
+    assert!(text
+        .lines()
+        .any(|line| line.starts_with("d") && line.ends_with(" empty/")));
+}
+
+#[test]
+fn large_archive_lists_from_its_framing() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    let root = dir.path().join("big");
+    std::fs::create_dir(&root).unwrap();
+    let mut block = vec![0u8; 1 << 20];
+    block.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+    {
+        use std::io::Write;
+        let mut file = std::fs::File::create(root.join("disk.img")).unwrap();
+        for _ in 0..48 {
+            file.write_all(&block).unwrap();
+        }
+    }
+    file_with(&root, "z.txt", b"after the big one");
+    let archive = dir.path().join("big.rcla");
+    let archive = archive.to_str().unwrap();
+    create(&root, archive, &key);
+    std::fs::remove_dir_all(&root).unwrap();
+
+    /* damage inside the image's data, which listing skips over */
+    let mut sealed = std::fs::read(archive).unwrap();
+    let middle = sealed.len() / 2;
+    sealed[middle] ^= 1;
+    std::fs::write(archive, &sealed).unwrap();
+    let output = rcli()
+        .args(["archive", "list", archive, "--key-file", &key])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(text.lines().count(), 2, "{}", text);
+    assert!(text.contains(&format!(" {} ", 48u64 << 20)), "{}", text);
+    assert!(text.ends_with(" z.txt\n"), "{}", text);
+    /* which extract still finds */
+    rcli()
+        .args(["archive", "extract", archive, "--into"])
+        .arg(dir.path().join("restore"))
+        .args(["--key-file", &key])
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn tampering_where_listing_reads_is_detected() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    let root = project(dir.path());
+    let archive = dir.path().join("backup.rcla");
+    let archive = archive.to_str().unwrap();
+    create(&root, archive, &key);
+    let sealed = std::fs::read(archive).unwrap();
+
+    /* the first chunk holds the first entries, and the last one the end */
+    let output = rcli()
+        .args(["inspect", "--json", "-f", archive])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let header = json["header_length"].as_u64().unwrap() as usize;
+    for at in [header + 3, sealed.len() - 3] {
+        let mut damaged = sealed.clone();
+        damaged[at] ^= 1;
+        std::fs::write(archive, &damaged).unwrap();
+        let output = rcli()
+            .args(["archive", "list", archive, "--key-file", &key])
+            .assert()
+            .code(4)
+            .get_output()
+            .clone();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("decryption failed"), "{}", stderr);
+    }
+}
+
+/* (archive, key file) for `project`, in `dir` */
+fn project_archive(dir: &Path) -> (String, String, std::path::PathBuf) {
+    let key = file_with(dir, "key.hex", KEY_HEX.as_bytes());
+    let root = project(dir);
+    let archive = dir.join("backup.rcla").to_str().unwrap().to_string();
+    create(&root, &archive, &key);
+    (archive, key, root)
+}
+
+#[test]
+fn first_middle_and_last_entries_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let (archive, key, root) = project_archive(dir.path());
+    /* sorted: README, empty, nothing, src, src/main.rs, src/util, src/util/big.bin */
+    for entry in ["README", "src/main.rs", "src/util/big.bin"] {
+        let out = dir.path().join("one.out");
+        rcli()
+            .args(["archive", "extract", &archive, "--entry", entry, "--out"])
+            .arg(&out)
+            .args(["--key-file", &key, "--force"])
+            .assert()
+            .success();
+        assert_eq!(
+            std::fs::read(&out).unwrap(),
+            std::fs::read(root.join(entry)).unwrap(),
+            "{}",
+            entry
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn single_file_keeps_its_mode() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    let root = project(dir.path());
+    let script = root.join("src/build.sh");
+    std::fs::write(&script, b"#!/bin/sh\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o700)).unwrap();
+    let archive = dir.path().join("backup.rcla");
+    let archive = archive.to_str().unwrap();
+    create(&root, archive, &key);
+
+    let out = dir.path().join("build.sh");
+    rcli()
+        .args([
+            "archive",
+            "extract",
+            archive,
+            "--entry",
+            "src/build.sh",
+            "--out",
+        ])
+        .arg(&out)
+        .args(["--key-file", &key])
+        .assert()
+        .success();
+    let mode = std::fs::metadata(&out).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+    /* and --out does not replace a file without --force */
+    rcli()
+        .args(["archive", "extract", archive, "--entry", "README", "--out"])
+        .arg(&out)
+        .args(["--key-file", &key])
+        .assert()
+        .failure();
+    assert_eq!(std::fs::read(&out).unwrap(), b"#!/bin/sh\n");
+}
+
+#[test]
+fn patterns_and_directories_into_a_tree() {
+    let dir = tempfile::tempdir().unwrap();
+    let (archive, key, root) = project_archive(dir.path());
+    let restore = dir.path().join("some");
+    rcli()
+        .args(["archive", "extract", &archive, "--entry", "src/*.rs"])
+        .args(["--entry", "src/util/", "--entry", "READ*", "--into"])
+        .arg(&restore)
+        .args(["--key-file", &key])
+        .assert()
+        .success();
+    let restored: Vec<String> = listing(&restore)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+    assert_eq!(
+        restored,
+        [
+            "README",
+            "src",
+            "src/main.rs",
+            "src/util",
+            "src/util/big.bin"
+        ]
+    );
+    assert_eq!(
+        std::fs::read(restore.join("src/util/big.bin")).unwrap(),
+        std::fs::read(root.join("src/util/big.bin")).unwrap()
+    );
+}
+
+#[test]
+fn missing_entry_suggests_near_matches() {
+    let dir = tempfile::tempdir().unwrap();
+    let (archive, key, _) = project_archive(dir.path());
+    let out = dir.path().join("main.rs");
+    let output = rcli()
+        .args([
+            "archive",
+            "extract",
+            &archive,
+            "--entry",
+            "src/mian.rs",
+            "--out",
+        ])
+        .arg(&out)
+        .args(["--key-file", &key])
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("no entry matches src/mian.rs"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("near matches: src/main.rs"), "{}", stderr);
+    assert!(!out.exists());
+
+    /* one good pattern does not let a bad one through */
+    let restore = dir.path().join("some");
+    let output = rcli()
+        .args(["archive", "extract", &archive, "--entry", "README"])
+        .args(["--entry", "big.bin", "--into"])
+        .arg(&restore)
+        .args(["--key-file", &key])
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("near matches: src/util/big.bin"),
+        "{}",
+        stderr
+    );
+    assert!(!restore.exists());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 3);
+}
+
+#[test]
+fn out_takes_exactly_one_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let (archive, key, _) = project_archive(dir.path());
+    let out = dir.path().join("many");
+    let output = rcli()
+        .args(["archive", "extract", &archive, "--entry", "src", "--out"])
+        .arg(&out)
+        .args(["--key-file", &key])
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("matches 2 files"), "{}", stderr);
+    assert!(!out.exists());
+}
+
+#[test]
+fn padded_archive_shows_neither_names_nor_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "key.hex", KEY_HEX.as_bytes());
+    let root = project(dir.path());
+    let archive = dir.path().join("backup.rcla");
+    let archive = archive.to_str().unwrap();
+    create_padded(&root, archive, &key);
+
+    /* a tree of a different size lands in the same bucket */
+    let other = dir.path().join("other");
+    std::fs::create_dir(&other).unwrap();
+    file_with(&other, "x", b"small");
+    let small = dir.path().join("small.rcla");
+    let small = small.to_str().unwrap();
+    create_padded(&other, small, &key);
+    let size = std::fs::metadata(archive).unwrap().len();
+    assert_eq!(size, std::fs::metadata(small).unwrap().len());
+
+    let sealed = std::fs::read(archive).unwrap();
+    let output = rcli().args(["inspect", "-f", archive]).output().unwrap();
+    assert!(output.status.success());
+    let shown = String::from_utf8(output.stdout).unwrap();
+    for path in [
+        "README", "main.rs", "big.bin", "src/util", "empty", "nothing",
+    ] {
+        assert!(
+            !sealed.windows(path.len()).any(|w| w == path.as_bytes()),
+            "{}",
+            path
+        );
+        assert!(!shown.contains(path), "{}", shown);
+    }
+
+    let output = rcli()
+        .args(["archive", "list", archive, "--key-file", &key])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 7);
+    let restore = dir.path().join("restore");
+    rcli()
+        .args(["archive", "extract", archive, "--into"])
+        .arg(&restore)
+        .args(["--key-file", &key])
+        .assert()
+        .success();
+    assert_eq!(listing(&restore), listing(&root));
+    let out = dir.path().join("main.rs");
+    rcli()
+        .args([
+            "archive",
+            "extract",
+            archive,
+            "--entry",
+            "src/main.rs",
+            "--out",
+        ])
+        .arg(&out)
+        .args(["--key-file", &key])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&out).unwrap(), b"fn main() {}\n");
+}
+
+fn create_padded(root: &Path, out: &str, key: &str) {
+    rcli()
+        .args(["archive", "create", "-f"])
+        .arg(root)
+        .args(["--out", out, "--key-file", key, "--pad", "1M"])
+        .assert()
+        .success();
+}
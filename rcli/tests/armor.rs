@@ -0,0 +1,142 @@
+/* --armor: base64 text between BEGIN/END lines, detected on decrypt */
+mod common;
+
+use assert_cmd::Command;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use common::rcli;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn chacha(file: &std::path::Path, mode: &str) -> Command {
+    let mut cmd = rcli();
+    cmd.args([
+        "chacha",
+        "-f",
+        file.to_str().unwrap(),
+        "--key-hex",
+        KEY_HEX,
+        mode,
+    ]);
+    cmd
+}
+
+#[test]
+fn armored_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    let plaintext: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&path, &plaintext).unwrap();
+
+    chacha(&path, "--encrypt").arg("--armor").assert().success();
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "-----BEGIN RCLI ENCRYPTED FILE-----");
+    assert_eq!(*lines.last().unwrap(), "-----END RCLI ENCRYPTED FILE-----");
+    assert!(lines[1..lines.len() - 1].iter().all(|l| l.len() <= 64));
+    assert_eq!(lines[1].len(), 64);
+
+    chacha(&path, "--decrypt").assert().success();
+    assert_eq!(std::fs::read(&path).unwrap(), plaintext);
+}
+
+#[test]
+fn armored_pipe_round_trip() {
+    let encrypted = rcli()
+        .args([
+            "chacha",
+            "-f",
+            "-",
+            "--key-hex",
+            KEY_HEX,
+            "--encrypt",
+            "--armor",
+        ])
+        .write_stdin("through a pipe")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert!(encrypted.starts_with(b"-----BEGIN RCLI ENCRYPTED FILE-----\n"));
+
+    rcli()
+        .args(["chacha", "-f", "-", "--key-hex", KEY_HEX, "--decrypt"])
+        .write_stdin(encrypted)
+        .assert()
+        .success()
+        .stdout("through a pipe");
+}
+
+#[test]
+fn decrypts_hand_armored_binary_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("report.txt");
+    std::fs::write(&path, b"encrypted without --armor").unwrap();
+    chacha(&path, "--encrypt").assert().success();
+
+    /* Armor the binary output by hand, wrapped at 76 columns with CRLF
+     * as a mail client might */
+    let body = STANDARD.encode(std::fs::read(&path).unwrap());
+    let mut text = String::from("-----BEGIN RCLI ENCRYPTED FILE-----\r\n");
+    for line in body.as_bytes().chunks(76) {
+        text.push_str(std::str::from_utf8(line).unwrap());
+        text.push_str("\r\n");
+    }
+    text.push_str("-----END RCLI ENCRYPTED FILE-----\r\n");
+    std::fs::write(&path, text).unwrap();
+
+    chacha(&path, "--decrypt").assert().success();
+    assert_eq!(std::fs::read(&path).unwrap(), b"encrypted without --armor");
+}
+
+#[test]
+fn truncated_armor_fails_cleanly() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, vec![7u8; 1000]).unwrap();
+    chacha(&path, "--encrypt").arg("--armor").assert().success();
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    let truncated: String = text.lines().take(5).map(|l| format!("{}\n", l)).collect();
+    std::fs::write(&path, &truncated).unwrap();
+
+    let output = chacha(&path, "--decrypt")
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("armor is truncated"), "{}", stderr);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), truncated);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn bad_base64_names_the_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, b"short").unwrap();
+    chacha(&path, "--encrypt").arg("--armor").assert().success();
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    lines[1].replace_range(10..11, "!");
+    std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+    let output = chacha(&path, "--decrypt")
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("invalid base64 on line 2"), "{}", stderr);
+}
+
+#[test]
+fn armor_is_encrypt_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, b"x").unwrap();
+    chacha(&path, "--decrypt").arg("--armor").assert().failure();
+}
@@ -0,0 +1,141 @@
+/* --backup: a copy of the original before it is changed in place */
+mod common;
+
+use assert_cmd::Command;
+use common::{file_with, rcli};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const OTHER_KEY_HEX: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+fn stderr_of(cmd: &mut Command, code: i32) -> String {
+    let output = cmd.assert().code(code).get_output().clone();
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn backup_keeps_contents_mode_and_mtime() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "ledger.csv", b"the only copy");
+    let then = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    std::fs::File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(then)
+        .unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o640)).unwrap();
+    }
+
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file, "--backup"])
+        .assert()
+        .success();
+    let backup = format!("{}.bak", file);
+    assert_eq!(std::fs::read(&backup).unwrap(), b"the only copy");
+    assert_ne!(std::fs::read(&file).unwrap(), b"the only copy");
+    let metadata = std::fs::metadata(&backup).unwrap();
+    assert_eq!(metadata.modified().unwrap(), then);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+    }
+}
+
+#[test]
+fn rc4_takes_a_suffix() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "old.bin", b"legacy data");
+    rcli()
+        .args(["rc4", "--insecure-ok", "--key-hex", "0102030405"])
+        .args(["-f", &file, "--backup=.orig"])
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read(format!("{}.orig", file)).unwrap(),
+        b"legacy data"
+    );
+    assert!(!Path::new(&format!("{}.bak", file)).exists());
+
+    rcli()
+        .args(["rc4", "--insecure-ok", "--key-hex", "0102030405"])
+        .args(["-f", &file, "--backup="])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn existing_backup_needs_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"new");
+    let backup = file_with(dir.path(), "a.txt.bak", b"older backup");
+
+    let stderr = stderr_of(
+        rcli().args(["encrypt", "--key-hex", KEY_HEX, "-f", &file, "--backup"]),
+        3,
+    );
+    assert!(stderr.contains("already exists"), "{}", stderr);
+    assert_eq!(std::fs::read(&file).unwrap(), b"new");
+    assert_eq!(std::fs::read(&backup).unwrap(), b"older backup");
+
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file, "--backup"])
+        .arg("--force")
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&backup).unwrap(), b"new");
+}
+
+#[test]
+fn failure_says_where_the_backup_is() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .success();
+    let sealed = std::fs::read(&file).unwrap();
+
+    let stderr = stderr_of(
+        rcli().args([
+            "decrypt",
+            "--key-hex",
+            OTHER_KEY_HEX,
+            "-f",
+            &file,
+            "--backup",
+        ]),
+        4,
+    );
+    let backup = format!("{}.bak", file);
+    assert!(
+        stderr.contains(&format!("the original is intact in {}", backup)),
+        "{}",
+        stderr
+    );
+    assert_eq!(std::fs::read(&file).unwrap(), sealed);
+    assert_eq!(std::fs::read(&backup).unwrap(), sealed);
+}
+
+#[test]
+fn output_makes_backup_a_no_op() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    let out = dir.path().join("a.enc");
+    let output = rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file, "--backup"])
+        .args(["-o", out.to_str().unwrap()])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--backup does nothing"), "{}", stderr);
+    assert!(!Path::new(&format!("{}.bak", file)).exists());
+    assert_eq!(std::fs::read(&file).unwrap(), b"hello");
+}
@@ -0,0 +1,82 @@
+/* bench: throughput of each cipher, with tiny sizes so CI stays quick */
+mod common;
+
+use common::rcli;
+
+fn bench(args: &[&str]) -> String {
+    let output = rcli()
+        .args(["bench", "--size", "4K", "--iterations", "1"])
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn bench_reports_every_enabled_cipher() {
+    let table = bench(&[]);
+    let mut lines = table.lines();
+    assert!(lines.next().unwrap().starts_with("Cipher"), "{}", table);
+    for row in [
+        "RC4 keystream",
+        "ChaCha20-Poly1305 one-shot",
+        "ChaCha20-Poly1305 stream",
+        "AES-256-GCM stream",
+    ] {
+        assert!(table.contains(row), "{} missing from\n{}", row, table);
+    }
+    /* three chunk sizes for each of the two AEADs */
+    assert_eq!(table.matches(" stream ").count(), 6, "{}", table);
+    assert!(!table.contains(" disk "));
+    assert!(lines.all(|line| line.ends_with("MB/s")), "{}", table);
+}
+
+#[test]
+fn bench_json_has_a_line_per_case() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = bench(&[
+        "--json",
+        "--chunk-size",
+        "1K,2K",
+        "--disk-dir",
+        dir.path().to_str().unwrap(),
+    ]);
+    let cases: Vec<serde_json::Value> = out
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(cases.len(), 7, "{}", out);
+    for case in &cases {
+        for field in [
+            "algorithm",
+            "mode",
+            "chunk_size",
+            "bytes",
+            "iterations",
+            "encrypt_mb_per_s",
+            "decrypt_mb_per_s",
+        ] {
+            assert!(case.get(field).is_some(), "{} lacks {}", case, field);
+        }
+        assert_eq!(case["bytes"], 4096);
+        assert!(case["encrypt_mb_per_s"].as_f64().unwrap() > 0.0);
+    }
+    assert_eq!(cases[2]["chunk_size"], 1024);
+    assert_eq!(cases[6]["mode"], "disk");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn bench_rejects_nonsense_sizes() {
+    rcli().args(["bench", "--size", "0"]).assert().code(2);
+    rcli()
+        .args(["bench", "--size", "1K", "--chunk-size", "12"])
+        .assert()
+        .code(2);
+    rcli()
+        .args(["bench", "--size", "1K", "--disk-dir", "/nonexistent/dir"])
+        .assert()
+        .code(3);
+}
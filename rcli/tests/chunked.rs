@@ -0,0 +1,111 @@
+/* chacha streams through fixed-size chunks instead of reading whole files */
+use assert_cmd::Command;
+use crypto::chacha20_poly1305_cipher;
+use crypto::fs::read_header;
+use crypto::stream::TAG_LEN;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const KEY: [u8; 32] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+];
+const CHUNK: usize = 4096;
+
+fn chacha(file: &std::path::Path, mode: &str) -> Command {
+    let mut cmd = Command::cargo_bin("rcli").unwrap();
+    cmd.args([
+        "chacha",
+        "-f",
+        file.to_str().unwrap(),
+        "--key-hex",
+        KEY_HEX,
+        mode,
+    ]);
+    cmd
+}
+
+fn megabyte() -> Vec<u8> {
+    (0..1024 * 1024u32).map(|i| (i * 31 % 257) as u8).collect()
+}
+
+/* Encrypt a 1 MiB file in 4 KiB chunks; returns the header length */
+fn encrypt_chunked(path: &std::path::Path) -> usize {
+    std::fs::write(path, megabyte()).unwrap();
+    chacha(path, "--encrypt")
+        .args(["--chunk-size", "4K"])
+        .assert()
+        .success();
+    let sealed = std::fs::read(path).unwrap();
+    let (header, header_bytes) = read_header(&mut &sealed[..]).unwrap();
+    assert_eq!(header.chunk_size as usize, CHUNK);
+    header_bytes.len()
+}
+
+#[test]
+fn small_chunks_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("disk.img");
+    let header_len = encrypt_chunked(&path);
+
+    let sealed_len = std::fs::metadata(&path).unwrap().len() as usize;
+    let chunks = (sealed_len - header_len).div_ceil(CHUNK + TAG_LEN);
+    assert!(chunks >= 256, "{} chunks", chunks);
+
+    chacha(&path, "--decrypt").assert().success();
+    assert_eq!(std::fs::read(&path).unwrap(), megabyte());
+}
+
+#[test]
+fn corrupted_middle_chunk_is_detected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("disk.img");
+    let header_len = encrypt_chunked(&path);
+
+    let mut sealed = std::fs::read(&path).unwrap();
+    sealed[header_len + 128 * (CHUNK + TAG_LEN) + 100] ^= 0x01;
+    std::fs::write(&path, &sealed).unwrap();
+
+    let output = chacha(&path, "--decrypt")
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("decryption failed"), "{}", stderr);
+    assert_eq!(std::fs::read(&path).unwrap(), sealed);
+}
+
+#[test]
+fn single_shot_files_still_decrypt() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("old.enc");
+    let nonce = [0x42u8; 12];
+    let ciphertext =
+        chacha20_poly1305_cipher(&KEY, &nonce, b"from before chunking".to_vec(), true).unwrap();
+    std::fs::write(&path, [&nonce[..], &ciphertext].concat()).unwrap();
+
+    /* Without the header they look like any other file, so --force */
+    chacha(&path, "--decrypt").assert().code(5);
+    chacha(&path, "--decrypt").arg("--force").assert().success();
+    assert_eq!(std::fs::read(&path).unwrap(), b"from before chunking");
+}
+
+#[test]
+fn chunk_size_is_checked() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, b"x").unwrap();
+    let output = chacha(&path, "--encrypt")
+        .args(["--chunk-size", "512"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("chunk size must be between"), "{}", stderr);
+    chacha(&path, "--decrypt")
+        .args(["--chunk-size", "4K"])
+        .assert()
+        .failure();
+    assert_eq!(std::fs::read(&path).unwrap(), b"x");
+}
@@ -0,0 +1,19 @@
+/* Helpers shared by the integration tests; each test crate uses some */
+#![allow(dead_code)]
+
+use assert_cmd::Command;
+use std::path::Path;
+
+/* rcli, with RC4 not allowed unless a test says so */
+pub fn rcli() -> Command {
+    let mut cmd = Command::cargo_bin("rcli").unwrap();
+    cmd.env_remove("RCLI_ALLOW_RC4");
+    cmd
+}
+
+/* Write `contents` to `dir`/`name`, returning the path */
+pub fn file_with(dir: &Path, name: &str, contents: &[u8]) -> String {
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
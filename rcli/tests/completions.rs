@@ -0,0 +1,90 @@
+/* completions: generated shell scripts */
+mod common;
+
+use common::rcli;
+
+fn script(shell: &str) -> String {
+    let output = rcli()
+        .args(["completions", shell])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn scripts_cover_subcommands_and_values() {
+    for shell in ["bash", "zsh"] {
+        let text = script(shell);
+        assert!(!text.is_empty());
+        for word in [
+            "encrypt",
+            "decrypt",
+            "chacha",
+            "rc4",
+            "keygen",
+            "inspect",
+            "completions",
+            "--key-hex",
+            "--recipients-file",
+            /* encrypt, keygen, hash and hmac --algorithm */
+            "aes256gcm",
+            "sha256",
+            "blake3",
+            "hmac-sha256",
+        ] {
+            assert!(text.contains(word), "{} script lacks {}", shell, word);
+        }
+    }
+
+    /* zsh says which flags exclude each other, and that files are paths */
+    let zsh = script("zsh");
+    let key_hex = zsh
+        .lines()
+        .find(|line| line.contains("--key-hex=") && line.contains("--recipient"))
+        .unwrap();
+    assert!(
+        key_hex.contains("--passphrase --passphrase-file"),
+        "{}",
+        key_hex
+    );
+    assert!(zsh.contains(":FILE_NAME:_files"), "{}", zsh);
+    assert!(zsh.contains(":DIR:_files -/"));
+
+    for shell in ["fish", "powershell", "elvish"] {
+        assert!(script(shell).contains("chacha"), "{}", shell);
+    }
+}
+
+#[test]
+fn out_dir_installs_under_the_shells_name() {
+    let dir = tempfile::tempdir().unwrap();
+    for (shell, name) in [
+        ("bash", "rcli.bash"),
+        ("zsh", "_rcli"),
+        ("fish", "rcli.fish"),
+    ] {
+        rcli()
+            .args(["completions", shell, "--out-dir"])
+            .arg(dir.path())
+            .assert()
+            .success()
+            .stdout("");
+        let written = std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(written, script(shell));
+    }
+}
+
+#[test]
+fn unknown_shells_are_usage_errors() {
+    let output = rcli()
+        .args(["completions", "tcsh"])
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("invalid value 'tcsh'"), "{}", stderr);
+    assert!(stderr.contains("zsh"), "{}", stderr);
+}
@@ -0,0 +1,260 @@
+/* --compress: compression inside the encryption, undone by decrypt */
+mod common;
+
+use common::{file_with, rcli};
+use serde_json::Value;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn log_lines() -> Vec<u8> {
+    (0..20_000)
+        .flat_map(|i| format!("{} GET /index.html 200\n", i).into_bytes())
+        .collect()
+}
+
+/* Bytes no codec can shrink, from a xorshift generator */
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn encrypt(file: &str, out: &str, compress: &str) {
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", file, "--output", out])
+        .args(["--compress", compress])
+        .assert()
+        .success();
+}
+
+fn decrypt(file: &str, out: &str) -> std::process::Output {
+    rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "-f", file, "--output", out])
+        .output()
+        .unwrap()
+}
+
+fn inspect(file: &str) -> Value {
+    let output = rcli()
+        .args(["inspect", "--json", "-f", file])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn compressible_file_shrinks_and_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = log_lines();
+    let file = file_with(dir.path(), "access.log", &plain);
+    for (codec, name) in [("zstd", "zstd"), ("zstd:19", "zstd"), ("gzip", "deflate")] {
+        let sealed = dir.path().join("access.log.enc");
+        let sealed = sealed.to_str().unwrap();
+        let _ = std::fs::remove_file(sealed);
+        encrypt(&file, sealed, codec);
+        assert!(std::fs::metadata(sealed).unwrap().len() * 5 < plain.len() as u64);
+
+        let header = inspect(sealed);
+        assert_eq!(header["compression"], name);
+        assert_eq!(header["original_length"], plain.len());
+
+        let out = dir.path().join(format!("{}.out", codec.replace(':', "-")));
+        assert!(decrypt(sealed, out.to_str().unwrap()).status.success());
+        assert_eq!(std::fs::read(&out).unwrap(), plain);
+    }
+}
+
+#[test]
+fn incompressible_file_is_stored_as_it_is() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = noise(300_000);
+    let file = file_with(dir.path(), "random.bin", &plain);
+    let compressed = dir.path().join("compressed.enc");
+    let stored = dir.path().join("stored.enc");
+    encrypt(&file, compressed.to_str().unwrap(), "zstd");
+    encrypt(&file, stored.to_str().unwrap(), "none");
+
+    let len = |path: &Path| std::fs::metadata(path).unwrap().len();
+    assert_eq!(len(&compressed), len(&stored));
+    assert!(inspect(compressed.to_str().unwrap())["compression"].is_null());
+
+    let out = dir.path().join("random.out");
+    let output = decrypt(compressed.to_str().unwrap(), out.to_str().unwrap());
+    assert!(output.status.success());
+    assert_eq!(std::fs::read(&out).unwrap(), plain);
+}
+
+#[test]
+fn compress_none_file_still_decrypts() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "notes.txt", &log_lines());
+    rcli()
+        .args(["chacha", "--encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .args(["--compress", "none"])
+        .assert()
+        .success();
+    let header = inspect(&file);
+    assert!(header["compression"].is_null());
+    assert!(header["original_length"].is_null());
+
+    rcli()
+        .args(["chacha", "--decrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .args(["--max-decompressed-size", "1K"])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), log_lines());
+}
+
+#[test]
+fn decompression_ceiling_is_its_own_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "zeros.bin", &vec![0u8; 4 << 20]);
+    let sealed = dir.path().join("zeros.enc");
+    encrypt(&file, sealed.to_str().unwrap(), "deflate");
+    assert!(std::fs::metadata(&sealed).unwrap().len() < 64 * 1024);
+
+    let out = dir.path().join("zeros.out");
+    let output = rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "--output"])
+        .arg(&out)
+        .arg("-f")
+        .arg(&sealed)
+        .args(["--max-decompressed-size", "1M"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(8));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1048576 bytes"), "{}", stderr);
+    assert!(stderr.contains("--max-decompressed-size"), "{}", stderr);
+    assert!(!out.exists());
+
+    /* Piped, the length is not known up front, and the ceiling still holds */
+    let output = rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "-f", "-"])
+        .args(["--max-decompressed-size", "1M"])
+        .write_stdin(std::fs::read(&sealed).unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(8));
+    assert!(output.stdout.len() <= 1 << 20);
+}
+
+#[test]
+fn piped_input_round_trips() {
+    let plain = log_lines();
+    let sealed = rcli()
+        .args([
+            "encrypt",
+            "--key-hex",
+            KEY_HEX,
+            "-f",
+            "-",
+            "--compress",
+            "zstd",
+        ])
+        .write_stdin(plain.clone())
+        .output()
+        .unwrap();
+    assert!(sealed.status.success());
+    assert!(sealed.stdout.len() * 5 < plain.len());
+    let opened = rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "-f", "-"])
+        .write_stdin(sealed.stdout)
+        .output()
+        .unwrap();
+    assert!(opened.status.success());
+    assert_eq!(opened.stdout, plain);
+}
+
+#[test]
+fn passphrase_files_compress_too() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "notes.txt", &log_lines());
+    let phrase = file_with(dir.path(), "phrase", b"correct horse battery staple\n");
+    rcli()
+        .args(["encrypt", "--passphrase-file", &phrase, "-f", &file])
+        .args(["--compress", "zstd:1"])
+        .assert()
+        .success();
+    assert_eq!(inspect(&file)["compression"], "zstd");
+    rcli()
+        .args(["decrypt", "--passphrase-file", &phrase, "-f", &file])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), log_lines());
+}
+
+#[test]
+fn bad_codecs_are_usage_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    for codec in ["zstd:0", "zstd:23", "brotli"] {
+        rcli()
+            .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file])
+            .args(["--compress", codec])
+            .assert()
+            .code(2);
+    }
+    assert_eq!(std::fs::read(&file).unwrap(), b"hello");
+}
+
+#[test]
+fn archives_compress_and_respect_the_ceiling() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("logs");
+    std::fs::create_dir(&root).unwrap();
+    file_with(&root, "access.log", &log_lines());
+    file_with(&root, "random.bin", &noise(10_000));
+    let key = file_with(dir.path(), "key", KEY_HEX.as_bytes());
+    let archive = dir.path().join("logs.rcla");
+    rcli()
+        .args(["archive", "create", "-f"])
+        .arg(&root)
+        .arg("--out")
+        .arg(&archive)
+        .args(["--key-file", &key, "--compress", "zstd"])
+        .assert()
+        .success();
+    assert!(std::fs::metadata(&archive).unwrap().len() < log_lines().len() as u64 / 5);
+
+    let into = dir.path().join("restored");
+    rcli()
+        .args(["archive", "extract"])
+        .arg(&archive)
+        .arg("--into")
+        .arg(&into)
+        .args(["--key-file", &key])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(into.join("access.log")).unwrap(), log_lines());
+    assert_eq!(
+        std::fs::read(into.join("random.bin")).unwrap(),
+        noise(10_000)
+    );
+
+    let output = rcli()
+        .args(["archive", "list"])
+        .arg(&archive)
+        .args(["--key-file", &key, "--max-decompressed-size", "64K"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(8));
+    assert!(output.stdout.is_empty());
+
+    rcli()
+        .args(["archive", "create", "-f"])
+        .arg(&root)
+        .arg("--out")
+        .arg(dir.path().join("padded.rcla"))
+        .args(["--key-file", &key, "--compress", "zstd", "--pad", "padme"])
+        .assert()
+        .code(2);
+}
@@ -0,0 +1,211 @@
+/* The config file and RCLI_* variables: defaults below the command line */
+mod common;
+
+use assert_cmd::Command;
+use common::file_with;
+use serde_json::Value;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+/* rcli with `home` as its home and no RCLI_* settings from the caller */
+fn rcli(home: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("rcli").unwrap();
+    cmd.env("HOME", home).env_remove("XDG_CONFIG_HOME");
+    for name in [
+        "RCLI_CONFIG",
+        "RCLI_KEY_FILE",
+        "RCLI_ALGORITHM",
+        "RCLI_CHUNK_SIZE",
+        "RCLI_PROGRESS",
+        "RCLI_SUFFIX",
+        "RCLI_NONCE_LOG",
+    ] {
+        cmd.env_remove(name);
+    }
+    cmd
+}
+
+/* ~/.config/rcli/config.toml under `home` */
+fn default_config(home: &Path, text: &str) {
+    let dir = home.join(".config").join("rcli");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("config.toml"), text).unwrap();
+}
+
+/* Encrypt a fresh file with `cmd`'s settings and extra `args`, and read
+ * the chunk size and algorithm back from its header */
+fn sealed_with(mut cmd: Command, dir: &Path, args: &[&str]) -> (u64, String) {
+    let file = file_with(dir, "data.txt", b"configured");
+    cmd.args(["encrypt", "--file", &file, "--key-hex", KEY_HEX])
+        .args(args)
+        .assert()
+        .success();
+    let output = Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["inspect", "--json", "-f", &file])
+        .output()
+        .unwrap();
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let algorithm = json["algorithm"].as_str().unwrap().to_string();
+    (json["chunk_size"].as_u64().unwrap(), algorithm)
+}
+
+#[test]
+fn command_line_beats_environment_beats_file_beats_default() {
+    let home = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let builtin = sealed_with(rcli(home.path()), dir.path(), &[]);
+    assert_eq!(builtin, (65536, "ChaCha20-Poly1305".to_string()));
+
+    default_config(
+        home.path(),
+        "chunk-size = \"4K\"\nalgorithm = \"aes256gcm\"\n",
+    );
+    let file = sealed_with(rcli(home.path()), dir.path(), &[]);
+    assert_eq!(file, (4096, "AES-256-GCM".to_string()));
+
+    let mut env = rcli(home.path());
+    env.env("RCLI_CHUNK_SIZE", "8K");
+    let env = sealed_with(env, dir.path(), &[]);
+    assert_eq!(env, (8192, "AES-256-GCM".to_string()));
+
+    let mut flags = rcli(home.path());
+    flags.env("RCLI_CHUNK_SIZE", "8K");
+    let args = ["--chunk-size", "16K", "--algorithm", "chacha20poly1305"];
+    let flags = sealed_with(flags, dir.path(), &args);
+    assert_eq!(flags, (16384, "ChaCha20-Poly1305".to_string()));
+}
+
+#[test]
+fn config_flag_and_variable_name_the_file() {
+    let home = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    default_config(home.path(), "chunk-size = \"4K\"\n");
+    let other = file_with(dir.path(), "other.toml", b"chunk-size = 2048\n");
+
+    let mut named = rcli(home.path());
+    named.env("RCLI_CONFIG", &other);
+    assert_eq!(sealed_with(named, dir.path(), &[]).0, 2048);
+
+    let mut flag = rcli(home.path());
+    flag.args(["--config", &other]);
+    assert_eq!(sealed_with(flag, dir.path(), &[]).0, 2048);
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+    let home = tempfile::tempdir().unwrap();
+    default_config(home.path(), "chunk-size = \"4K\"\nkey = \"00\"\n");
+    let output = rcli(home.path())
+        .args(["config", "show"])
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("config.toml"), "{}", stderr);
+    assert!(stderr.contains("unknown field `key`"), "{}", stderr);
+
+    let output = rcli(home.path())
+        .args(["--json", "keygen"])
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    let record: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(record["status"], "error");
+}
+
+#[test]
+fn missing_files() {
+    let home = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    /* the default file need not exist */
+    let shown = rcli(home.path())
+        .args(["config", "show"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let shown = String::from_utf8(shown).unwrap();
+    assert!(shown.contains("(not found)"), "{}", shown);
+    assert_eq!(sealed_with(rcli(home.path()), dir.path(), &[]).0, 65536);
+
+    /* one that was asked for must */
+    let missing = dir.path().join("missing.toml");
+    let output = rcli(home.path())
+        .arg("--config")
+        .arg(&missing)
+        .args(["config", "show"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("missing.toml"), "{}", stderr);
+}
+
+#[test]
+fn key_file_stands_in_for_key_options() {
+    let home = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let key = file_with(dir.path(), "k.hex", KEY_HEX.as_bytes());
+    let file = file_with(dir.path(), "secret.txt", b"from the config's key");
+    let sealed = format!("{}.enc", file);
+
+    /* without one, a key option is still required */
+    rcli(home.path())
+        .args(["encrypt", "--file", &file, "--output", &sealed])
+        .assert()
+        .code(2);
+
+    default_config(
+        home.path(),
+        &format!("key-file = {:?}\n", key.replace('\\', "/")),
+    );
+    rcli(home.path())
+        .args(["encrypt", "--file", &file, "--output", &sealed])
+        .assert()
+        .success();
+    rcli(home.path())
+        .args(["decrypt", "--file", &sealed, "--key-hex", KEY_HEX])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&sealed).unwrap(), b"from the config's key");
+}
+
+#[test]
+fn suffix_and_show() {
+    let home = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    default_config(home.path(), "suffix = \".sealed\"\nprogress = false\n");
+    let file = file_with(dir.path(), "notes.txt", b"kept");
+    rcli(home.path())
+        .args(["encrypt", "--file", &file, "--key-hex", KEY_HEX, "--keep"])
+        .assert()
+        .success();
+    assert!(Path::new(&format!("{}.sealed", file)).exists());
+
+    let output = rcli(home.path())
+        .env("RCLI_ALGORITHM", "aes256gcm")
+        .args(["config", "show"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let shown = String::from_utf8(output).unwrap();
+    let line = |key: &str| {
+        shown
+            .lines()
+            .find(|line| line.starts_with(key))
+            .unwrap_or_else(|| panic!("no {} in\n{}", key, shown))
+    };
+    assert!(line("suffix").contains("\".sealed\""), "{}", shown);
+    assert!(line("suffix").ends_with("# from the config file"));
+    assert!(line("progress").contains("false"));
+    assert!(line("algorithm").ends_with("# from RCLI_ALGORITHM"));
+    assert!(line("chunk-size").ends_with("# built in"));
+}
@@ -0,0 +1,124 @@
+/* chacha refuses to encrypt twice or to decrypt plaintext, unless --force */
+mod common;
+
+use assert_cmd::Command;
+use common::rcli;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn chacha(mode: &str, file: &Path) -> Command {
+    let mut cmd = rcli();
+    cmd.args(["chacha", mode, "--key-hex", KEY_HEX])
+        .args(["-f", file.to_str().unwrap()]);
+    cmd
+}
+
+fn stderr_of(cmd: &mut Command, status: i32) -> String {
+    let output = cmd.assert().code(status).get_output().clone();
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn each_direction_on_each_kind_of_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, b"meeting notes").unwrap();
+
+    /* Plaintext: decrypting is refused, encrypting works */
+    let stderr = stderr_of(&mut chacha("--decrypt", &path), 5);
+    assert!(stderr.contains("not an rcli encrypted file"), "{}", stderr);
+    assert_eq!(std::fs::read(&path).unwrap(), b"meeting notes");
+    chacha("--encrypt", &path).assert().success();
+    let sealed = std::fs::read(&path).unwrap();
+
+    /* Ciphertext: encrypting is refused, decrypting works */
+    let stderr = stderr_of(&mut chacha("--encrypt", &path), 5);
+    assert!(
+        stderr.contains("already encrypted (use --force to encrypt again)"),
+        "{}",
+        stderr
+    );
+    assert_eq!(std::fs::read(&path).unwrap(), sealed);
+    chacha("--decrypt", &path).assert().success();
+    assert_eq!(std::fs::read(&path).unwrap(), b"meeting notes");
+}
+
+#[test]
+fn force_overrides_both() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, b"meeting notes").unwrap();
+    chacha("--encrypt", &path).assert().success();
+
+    /* Twice encrypted takes two decryptions */
+    chacha("--encrypt", &path).arg("--force").assert().success();
+    chacha("--decrypt", &path).assert().success();
+    assert_ne!(std::fs::read(&path).unwrap(), b"meeting notes");
+    chacha("--decrypt", &path).assert().success();
+    assert_eq!(std::fs::read(&path).unwrap(), b"meeting notes");
+
+    /* Plaintext forced through decryption fails authentication instead */
+    let stderr = stderr_of(chacha("--decrypt", &path).arg("--force"), 4);
+    assert!(!stderr.contains("not an rcli encrypted file"), "{}", stderr);
+    assert_eq!(std::fs::read(&path).unwrap(), b"meeting notes");
+}
+
+#[test]
+fn armored_and_piped_input_count_as_encrypted() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, b"meeting notes").unwrap();
+    chacha("--encrypt", &path).arg("--armor").assert().success();
+    stderr_of(&mut chacha("--encrypt", &path), 5);
+
+    let sealed = std::fs::read(&path).unwrap();
+    let stderr = stderr_of(
+        rcli()
+            .args(["chacha", "--encrypt", "--key-hex", KEY_HEX, "-f", "-"])
+            .write_stdin(sealed),
+        5,
+    );
+    assert!(stderr.contains("already encrypted"), "{}", stderr);
+}
+
+#[test]
+fn recursive_skips_files_already_done() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = dir.path().join("tree");
+    std::fs::create_dir(&tree).unwrap();
+    std::fs::write(tree.join("a.txt"), b"alpha").unwrap();
+    chacha("--encrypt", &tree.join("a.txt")).assert().success();
+    let sealed = std::fs::read(tree.join("a.txt")).unwrap();
+    std::fs::write(tree.join("b.txt"), b"beta").unwrap();
+
+    let output = chacha("--encrypt", &tree)
+        .arg("-r")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("1 encrypted, 1 skipped"), "{}", stdout);
+    assert_eq!(std::fs::read(tree.join("a.txt")).unwrap(), sealed);
+}
+
+#[test]
+fn rc4_warns_about_rcli_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, b"meeting notes").unwrap();
+    let rc4 = || {
+        let mut cmd = rcli();
+        cmd.args(["rc4", "--insecure-ok", "--key-hex", "0102030405"])
+            .args(["-f", path.to_str().unwrap()]);
+        cmd
+    };
+    let stderr = stderr_of(&mut rc4(), 0);
+    assert!(!stderr.contains("is an rcli encrypted file"), "{}", stderr);
+
+    rc4().assert().success();
+    chacha("--encrypt", &path).assert().success();
+    let stderr = stderr_of(&mut rc4(), 0);
+    assert!(stderr.contains("is an rcli encrypted file"), "{}", stderr);
+}
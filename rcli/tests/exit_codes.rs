@@ -0,0 +1,114 @@
+/* Exit statuses: one per kind of failure, as listed in exit.rs */
+mod common;
+
+use assert_cmd::Command;
+use common::{file_with, rcli};
+use serde_json::Value;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const OTHER_KEY_HEX: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+fn chacha(mode: &str, key: &str, file: &str) -> Command {
+    let mut cmd = rcli();
+    cmd.args(["chacha", mode, "--key-hex", key, "-f", file]);
+    cmd
+}
+
+#[test]
+fn wrong_key_fails_authentication() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    chacha("--encrypt", KEY_HEX, &file).assert().success();
+    chacha("--decrypt", OTHER_KEY_HEX, &file).assert().code(4);
+
+    let mut sealed = std::fs::read(&file).unwrap();
+    let last = sealed.len() - 1;
+    sealed[last] ^= 1;
+    std::fs::write(&file, &sealed).unwrap();
+    chacha("--decrypt", KEY_HEX, &file).assert().code(4);
+}
+
+#[test]
+fn missing_file_is_an_io_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.txt");
+    chacha("--encrypt", KEY_HEX, missing.to_str().unwrap())
+        .assert()
+        .code(3);
+    rcli()
+        .args(["hash", missing.to_str().unwrap()])
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn bad_hex_key_is_a_usage_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    chacha("--encrypt", "zz", &file).assert().code(2);
+    chacha("--encrypt", "0102", &file).assert().code(2);
+    assert_eq!(std::fs::read(&file).unwrap(), b"hello");
+}
+
+#[test]
+fn plaintext_is_a_format_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"not encrypted at all");
+    rcli()
+        .args(["verify", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .code(5);
+    rcli().args(["inspect", "-f", &file]).assert().code(5);
+}
+
+#[test]
+fn wrong_passphrase_fails_the_key_check() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    let right = file_with(dir.path(), "right", b"correct horse battery staple\n");
+    let wrong = file_with(dir.path(), "wrong", b"incorrect horse\n");
+    for pass in [&right, &wrong] {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(pass, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+    }
+    rcli()
+        .args([
+            "chacha",
+            "--encrypt",
+            "--passphrase-file",
+            &right,
+            "-f",
+            &file,
+        ])
+        .assert()
+        .success();
+    rcli()
+        .args([
+            "chacha",
+            "--decrypt",
+            "--passphrase-file",
+            &wrong,
+            "-f",
+            &file,
+        ])
+        .assert()
+        .code(6);
+}
+
+#[test]
+fn json_errors_carry_the_status() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.txt");
+    let output = chacha("--encrypt", KEY_HEX, missing.to_str().unwrap())
+        .arg("--json")
+        .assert()
+        .code(3)
+        .get_output()
+        .clone();
+    let record: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(record["code"], "io");
+    assert_eq!(record["exit_code"], 3);
+}
@@ -0,0 +1,110 @@
+/* --files-from lists, newline- or NUL-separated (-0) */
+use assert_cmd::Command;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn chacha(args: &[&str]) -> Command {
+    let mut cmd = Command::cargo_bin("rcli").unwrap();
+    cmd.args(["chacha", "--key-hex", KEY_HEX, "--encrypt"]);
+    cmd.args(args);
+    cmd
+}
+
+fn encrypted(path: &Path) -> bool {
+    std::fs::read(path).unwrap().starts_with(b"RCLI")
+}
+
+#[test]
+fn list_file_with_spaces_and_blank_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let spaced = dir.path().join("quarterly report.txt");
+    let plain = dir.path().join("plain.txt");
+    let left = dir.path().join("left alone.txt");
+    for path in [&spaced, &plain, &left] {
+        std::fs::write(path, b"contents").unwrap();
+    }
+    let list = dir.path().join("list");
+    std::fs::write(
+        &list,
+        format!(
+            "{}\n\n{}\r\n",
+            spaced.to_str().unwrap(),
+            plain.to_str().unwrap()
+        ),
+    )
+    .unwrap();
+
+    chacha(&["--files-from", list.to_str().unwrap()])
+        .assert()
+        .success();
+    assert!(encrypted(&spaced));
+    assert!(encrypted(&plain));
+    assert!(!encrypted(&left));
+}
+
+#[test]
+fn nul_separated_list_on_stdin() {
+    let dir = tempfile::tempdir().unwrap();
+    let names = ["one two", "three\tfour", "five"];
+    let mut stream = Vec::new();
+    for name in names {
+        let path = dir.path().join(name);
+        std::fs::write(&path, name).unwrap();
+        stream.extend_from_slice(path.to_str().unwrap().as_bytes());
+        stream.push(0);
+    }
+    assert!(!stream.contains(&b'\n'));
+
+    let output = chacha(&["--files-from", "-", "-0"])
+        .write_stdin(stream)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 3);
+    for name in names {
+        assert!(encrypted(&dir.path().join(name)), "{}", name);
+    }
+}
+
+#[test]
+fn list_and_key_cannot_share_stdin() {
+    let output = Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["chacha", "--key-stdin", "--encrypt", "--files-from", "-"])
+        .write_stdin(format!("{}\n", KEY_HEX))
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("--files-from - and --key-stdin cannot both read standard input"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn missing_list_stops_before_processing() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.txt");
+    std::fs::write(&file, b"untouched").unwrap();
+    let missing = dir.path().join("no-such-list");
+
+    let output = chacha(&["-f", file.to_str().unwrap()])
+        .args(["--files-from", missing.to_str().unwrap()])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("cannot read file list"), "{}", stderr);
+    assert_eq!(std::fs::read(&file).unwrap(), b"untouched");
+
+    /* -0 only means something for a list */
+    chacha(&["-f", file.to_str().unwrap(), "-0"])
+        .assert()
+        .failure();
+}
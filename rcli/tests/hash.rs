@@ -0,0 +1,207 @@
+/* hash: coreutils-format checksums and --check */
+mod common;
+
+use assert_cmd::Command;
+use common::rcli;
+use crypto::digest::{hash, HashAlgorithm};
+use std::path::Path;
+
+/* FIPS 180-2 "abc" vectors and BLAKE3's */
+const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+const ABC_SHA512: &str = concat!(
+    "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a",
+    "2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+);
+const ABC_BLAKE3: &str = "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85";
+const EMPTY_BLAKE3: &str = "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262";
+
+fn stdout(cmd: &mut Command) -> String {
+    String::from_utf8(cmd.assert().success().get_output().stdout.clone()).unwrap()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn path(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
+
+#[test]
+fn known_vectors() {
+    let dir = tempfile::tempdir().unwrap();
+    let (abc, empty) = (dir.path().join("abc"), dir.path().join("empty"));
+    std::fs::write(&abc, b"abc").unwrap();
+    std::fs::write(&empty, b"").unwrap();
+
+    assert_eq!(
+        stdout(rcli().args(["hash", path(&abc)])),
+        format!("{}  {}\n", ABC_SHA256, path(&abc))
+    );
+    assert_eq!(
+        stdout(rcli().args(["hash", "--algorithm", "sha512", path(&abc)])),
+        format!("{}  {}\n", ABC_SHA512, path(&abc))
+    );
+    assert_eq!(
+        stdout(rcli().args(["hash", "--algorithm", "blake3", path(&abc), path(&empty)])),
+        format!(
+            "{}  {}\n{}  {}\n",
+            ABC_BLAKE3,
+            path(&abc),
+            EMPTY_BLAKE3,
+            path(&empty)
+        )
+    );
+}
+
+/* Many READ_CHUNK_SIZE reads, with a short one at the end */
+#[test]
+fn large_files_hash_in_chunks() {
+    let dir = tempfile::tempdir().unwrap();
+    let million_a = dir.path().join("million-a");
+    std::fs::write(&million_a, vec![b'a'; 1_000_000]).unwrap();
+    assert!(stdout(rcli().args(["hash", path(&million_a)]))
+        .starts_with("cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0  "));
+
+    let large = dir.path().join("large");
+    let data: Vec<u8> = (0..8 * 1024 * 1024 + 12345u32)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    std::fs::write(&large, &data).unwrap();
+    for (name, alg) in [
+        ("sha256", HashAlgorithm::Sha256),
+        ("sha512", HashAlgorithm::Sha512),
+    ] {
+        assert_eq!(
+            stdout(rcli().args(["hash", "--algorithm", name, path(&large)])),
+            format!("{}  {}\n", hex(hash(alg, &data).as_bytes()), path(&large))
+        );
+    }
+}
+
+#[test]
+fn dash_reads_stdin() {
+    assert_eq!(
+        stdout(rcli().args(["hash", "-"]).write_stdin("abc")),
+        format!("{}  -\n", ABC_SHA256)
+    );
+    assert_eq!(
+        stdout(
+            rcli()
+                .args(["hash", "--algorithm", "blake3", "-"])
+                .write_stdin("")
+        ),
+        format!("{}  -\n", EMPTY_BLAKE3)
+    );
+}
+
+#[test]
+fn check_detects_a_modified_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let (one, two) = (dir.path().join("one"), dir.path().join("two"));
+    std::fs::write(&one, b"first").unwrap();
+    std::fs::write(&two, b"second").unwrap();
+    let sums = dir.path().join("SUMS");
+    for alg in ["sha256", "sha512", "blake3"] {
+        std::fs::write(&two, b"second").unwrap();
+        let list = stdout(rcli().args(["hash", "--algorithm", alg, path(&one), path(&two)]));
+        std::fs::write(&sums, &list).unwrap();
+
+        assert_eq!(
+            stdout(rcli().args(["hash", "--algorithm", alg, "--check", path(&sums)])),
+            format!("{}: OK\n{}: OK\n", path(&one), path(&two))
+        );
+
+        std::fs::write(&two, b"second, edited").unwrap();
+        let output = rcli()
+            .args(["hash", "--algorithm", alg, "--check", path(&sums)])
+            .assert()
+            .code(1)
+            .get_output()
+            .clone();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!("{}: OK\n{}: FAILED\n", path(&one), path(&two))
+        );
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("WARNING: 1 computed checksum did NOT match"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn check_reports_missing_files_and_bad_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let gone = dir.path().join("gone");
+    let sums = dir.path().join("SUMS");
+    std::fs::write(
+        &sums,
+        format!("{}  {}\nthis is not a checksum\n", ABC_SHA256, path(&gone)),
+    )
+    .unwrap();
+    let output = rcli()
+        .args(["hash", "--check", path(&sums)])
+        .assert()
+        .code(1)
+        .get_output()
+        .clone();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}: FAILED open or read\n", path(&gone))
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("1 line is improperly formatted"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("1 listed file could not be read"),
+        "{}",
+        stderr
+    );
+
+    /* A list from another algorithm has no lines this one can use */
+    let output = rcli()
+        .args(["hash", "--algorithm", "sha512", "--check", "-"])
+        .write_stdin(format!("{}  abc\n", ABC_SHA256))
+        .assert()
+        .code(5)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("no properly formatted checksum lines"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn unreadable_files_fail_after_the_rest() {
+    let dir = tempfile::tempdir().unwrap();
+    let abc = dir.path().join("abc");
+    std::fs::write(&abc, b"abc").unwrap();
+    let output = rcli()
+        .args(["hash", path(&dir.path().join("missing")), path(&abc)])
+        .assert()
+        .code(3)
+        .get_output()
+        .clone();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}  {}\n", ABC_SHA256, path(&abc))
+    );
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("missing"));
+
+    rcli().args(["hash"]).assert().failure();
+    rcli()
+        .args(["hash", "--check", "SUMS", path(&abc)])
+        .assert()
+        .failure();
+}
@@ -0,0 +1,159 @@
+/* hmac: tags over files, and --verify */
+mod common;
+
+use common::rcli;
+use std::path::Path;
+
+/* RFC 4231 test cases 1 and 6 */
+const CASE1_KEY: &str = "0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b";
+const CASE1_TAG: &str = "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7";
+const CASE6_DATA: &str = "Test Using Larger Than Block-Size Key - Hash Key First";
+const CASE6_TAG: &str = "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54";
+
+fn path(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
+
+fn fixture(dir: &Path, contents: &[u8]) -> std::path::PathBuf {
+    let file = dir.join("message.txt");
+    std::fs::write(&file, contents).unwrap();
+    file
+}
+
+#[test]
+fn tags_match_rfc_4231() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = fixture(dir.path(), b"Hi There");
+    rcli()
+        .args(["hmac", "--key-hex", CASE1_KEY, "-f", path(&file)])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", CASE1_TAG));
+
+    /* A 131-byte key, longer than SHA-256's block, read from a key file */
+    let file = fixture(dir.path(), CASE6_DATA.as_bytes());
+    let key_file = dir.path().join("mac.key");
+    std::fs::write(&key_file, "aa".repeat(131)).unwrap();
+    rcli()
+        .args(["hmac", "--key-file", path(&key_file), "-f", path(&file)])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", CASE6_TAG));
+
+    /* Standard input */
+    rcli()
+        .args(["hmac", "--key-hex", CASE1_KEY, "-f", "-"])
+        .write_stdin("Hi There")
+        .assert()
+        .success()
+        .stdout(format!("{}\n", CASE1_TAG));
+}
+
+#[test]
+fn verify_accepts_the_tag_and_rejects_a_changed_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = fixture(dir.path(), b"Hi There");
+    rcli()
+        .args(["hmac", "--key-hex", CASE1_KEY, "-f", path(&file)])
+        .args(["--verify", CASE1_TAG])
+        .assert()
+        .success()
+        .stdout(format!("{}: OK\n", path(&file)))
+        .stderr("");
+
+    std::fs::write(&file, b"Hi There!").unwrap();
+    rcli()
+        .args(["hmac", "--key-hex", CASE1_KEY, "-f", path(&file)])
+        .args(["--verify", CASE1_TAG])
+        .assert()
+        .code(4)
+        .stdout(format!("{}: FAILED\n", path(&file)))
+        .stderr("");
+
+    /* One flipped bit in a large file */
+    let mut data = vec![0x5au8; 1 << 20];
+    std::fs::write(&file, &data).unwrap();
+    let output = rcli()
+        .args(["hmac", "--key-hex", CASE1_KEY, "-f", path(&file)])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let tag = String::from_utf8(output.stdout).unwrap();
+    data[700_000] ^= 1;
+    std::fs::write(&file, &data).unwrap();
+    rcli()
+        .args(["hmac", "--key-hex", CASE1_KEY, "-f", path(&file)])
+        .args(["--verify", tag.trim()])
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn blake3_keyed_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = fixture(dir.path(), b"backup contents");
+    let key = "42".repeat(32);
+    let output = rcli()
+        .args(["hmac", "--algorithm", "blake3-keyed", "-f", path(&file)])
+        .env("MAC_KEY", &key)
+        .args(["--key-env", "MAC_KEY"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let tag = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(tag.trim().len(), 64);
+
+    /* Not the HMAC-SHA256 tag under the same key */
+    rcli()
+        .args(["hmac", "--key-hex", &key, "-f", path(&file)])
+        .args(["--verify", tag.trim()])
+        .assert()
+        .code(4);
+    rcli()
+        .args(["hmac", "--algorithm", "blake3-keyed", "--key-hex", &key])
+        .args(["-f", path(&file), "--verify", tag.trim()])
+        .assert()
+        .success();
+}
+
+#[test]
+fn bad_keys_and_tags_fail_before_reading_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing");
+    let short_key = dir.path().join("short.key");
+    std::fs::write(&short_key, [1u8; 8]).unwrap();
+
+    for (args, message) in [
+        (
+            vec!["--algorithm", "blake3-keyed", "--key-hex", CASE1_KEY],
+            "BLAKE3 keyed keys must be exactly 32 bytes, got 20 bytes",
+        ),
+        (
+            vec!["--key-file", path(&short_key)],
+            "HMAC-SHA256 keys must be 16 to 1024 bytes, got 8 bytes",
+        ),
+        (
+            vec!["--key-hex", CASE1_KEY, "--verify", "abcd"],
+            "--verify expects a tag of 64 hex digits",
+        ),
+    ] {
+        let output = rcli()
+            .args(["hmac", "-f", path(&missing)])
+            .args(&args)
+            .assert()
+            .code(2)
+            .get_output()
+            .clone();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(message), "{:?}: {}", args, stderr);
+        assert!(!stderr.contains("No such file"), "{}", stderr);
+    }
+
+    /* Too short for HMAC at parse time, too */
+    rcli()
+        .args(["hmac", "--key-hex", "0b0b0b0b", "-f", path(&missing)])
+        .assert()
+        .failure();
+}
@@ -0,0 +1,206 @@
+/* inspect: header metadata without a key, as text or JSON */
+mod common;
+
+use common::rcli;
+use serde_json::Value;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn inspect(path: &Path, args: &[&str]) -> String {
+    let output = rcli()
+        .args(["inspect", "-f", path.to_str().unwrap()])
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn inspect_json(path: &Path) -> Value {
+    serde_json::from_str(&inspect(path, &["--json"])).unwrap()
+}
+
+fn failure(path: &Path, status: i32) -> String {
+    let output = rcli()
+        .args(["inspect", "-f", path.to_str().unwrap()])
+        .assert()
+        .code(status)
+        .get_output()
+        .clone();
+    String::from_utf8(output.stderr).unwrap()
+}
+
+/* 3000 bytes in 1K chunks under a raw key */
+fn raw_key_file(dir: &Path) -> std::path::PathBuf {
+    let path = dir.join("raw.bin");
+    std::fs::write(&path, vec![1u8; 3000]).unwrap();
+    rcli()
+        .args(["chacha", "-f", path.to_str().unwrap(), "--key-hex", KEY_HEX])
+        .args(["--encrypt", "--chunk-size", "1K"])
+        .assert()
+        .success();
+    path
+}
+
+fn passphrase_file(dir: &Path) -> std::path::PathBuf {
+    let path = dir.join("notes.txt");
+    std::fs::write(&path, b"meeting notes").unwrap();
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            path.to_str().unwrap(),
+            "--passphrase",
+            "--encrypt",
+        ])
+        .args(["--aad", "host=db1"])
+        .write_stdin("correct horse battery staple\n")
+        .assert()
+        .success();
+    path
+}
+
+#[test]
+fn passphrase_file_shows_kdf() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = passphrase_file(dir.path());
+    let sealed = std::fs::read(&path).unwrap();
+    let (header, _) = crypto::fs::read_header(&mut &sealed[..]).unwrap();
+    let kdf = header.passphrase.unwrap();
+    let salt: String = kdf.salt.iter().map(|b| format!("{:02x}", b)).collect();
+
+    let text = inspect(&path, &[]);
+    let expected = format!(
+        "Key derivation:  argon2id (memory {} KiB, {} iterations, parallelism {})",
+        kdf.m_cost_kib, kdf.t_cost, kdf.p_cost
+    );
+    assert!(text.contains(&expected), "{}", text);
+    assert!(
+        text.contains(&format!("Salt:            {}", salt)),
+        "{}",
+        text
+    );
+    assert!(text.contains("Associated data: yes"), "{}", text);
+    assert!(text.contains("Key check value: yes"), "{}", text);
+
+    let json = inspect_json(&path);
+    assert_eq!(json["kdf"]["name"], "argon2id");
+    assert_eq!(json["kdf"]["memory_kib"], kdf.m_cost_kib);
+    assert_eq!(json["kdf"]["salt"], salt);
+    assert_eq!(json["aad_bound"], true);
+    assert_eq!(json["key_check"], true);
+    assert_eq!(
+        json["header_length"].as_u64().unwrap() + json["ciphertext_length"].as_u64().unwrap(),
+        sealed.len() as u64
+    );
+}
+
+#[test]
+fn raw_key_file_has_no_kdf() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = raw_key_file(dir.path());
+    let sealed = std::fs::read(&path).unwrap();
+    let nonce: String = sealed[10..17]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    let text = inspect(&path, &[]);
+    for line in [
+        "Format version:  1".to_string(),
+        "Algorithm:       ChaCha20-Poly1305".to_string(),
+        "Chunk size:      1024 bytes".to_string(),
+        format!("Nonce prefix:    {}", nonce),
+        "Key derivation:  none (raw key)".to_string(),
+        "Associated data: no".to_string(),
+        "Key check value: no".to_string(),
+        "Header length:   19 bytes".to_string(),
+        /* Two full chunks and one of 952 bytes, each with a 16-byte tag */
+        "Ciphertext:      3048 bytes in 3 chunks".to_string(),
+    ] {
+        assert!(text.contains(&line), "{:?} in\n{}", line, text);
+    }
+
+    let json = inspect_json(&path);
+    assert_eq!(json["kdf"], Value::Null);
+    assert_eq!(json["nonce_prefix"], nonce);
+    assert_eq!(json["chunks"], 3);
+}
+
+#[test]
+fn truncated_header_and_foreign_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = raw_key_file(dir.path());
+    let sealed = std::fs::read(&path).unwrap();
+
+    let cut = dir.path().join("cut.bin");
+    std::fs::write(&cut, &sealed[..10]).unwrap();
+    let stderr = failure(&cut, 5);
+    assert!(stderr.contains("truncated header"), "{}", stderr);
+
+    let foreign = dir.path().join("photo.jpg");
+    std::fs::write(&foreign, b"\xff\xd8\xff\xe0 not encrypted").unwrap();
+    let stderr = failure(&foreign, 5);
+    assert!(stderr.contains("not an rcli encrypted file"), "{}", stderr);
+}
+
+/* Scripts depend on these names and types; changing them means bumping
+ * "schema" */
+#[test]
+fn json_schema_is_stable() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = inspect_json(&passphrase_file(dir.path()));
+    let fields: Vec<(&str, &str)> = json
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(name, value)| {
+            let kind = match value {
+                Value::Null => "null",
+                Value::Bool(_) => "bool",
+                Value::Number(_) => "number",
+                Value::String(_) => "string",
+                Value::Array(_) => "array",
+                Value::Object(_) => "object",
+            };
+            (name.as_str(), kind)
+        })
+        .collect();
+    assert_eq!(
+        fields,
+        [
+            ("aad_bound", "bool"),
+            ("algorithm", "string"),
+            ("armored", "bool"),
+            ("chunk_size", "number"),
+            ("chunks", "number"),
+            ("ciphertext_length", "number"),
+            ("compression", "null"),
+            ("file", "string"),
+            ("format_version", "number"),
+            ("header_length", "number"),
+            ("kdf", "object"),
+            ("key_check", "bool"),
+            ("nonce_prefix", "string"),
+            ("original_length", "null"),
+            ("padding", "null"),
+            ("ratchet", "bool"),
+            ("recipient_slots", "array"),
+            ("schema", "number"),
+        ]
+    );
+    assert_eq!(json["schema"], 1);
+    let mut kdf: Vec<&str> = json["kdf"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    kdf.sort();
+    assert_eq!(
+        kdf,
+        ["iterations", "memory_kib", "name", "parallelism", "salt"]
+    );
+}
@@ -0,0 +1,96 @@
+/* SIGINT mid-run: the target is either fully old or fully new */
+#![cfg(unix)]
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn rcli(args: &[&str]) -> Command {
+    let mut cmd = Command::new(assert_cmd::cargo::cargo_bin("rcli"));
+    cmd.args(args).stdout(Stdio::null()).stderr(Stdio::piped());
+    cmd
+}
+
+fn interrupt(child: &Child) {
+    let status = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn temporaries(dir: &Path) -> Vec<String> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.contains(".rcli-tmp-"))
+        .collect()
+}
+
+#[test]
+fn sigint_mid_file_leaves_old_or_new() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large.bin");
+    let data: Vec<u8> = (0..64 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&path, &data).unwrap();
+
+    let mut child = rcli(&[
+        "chacha",
+        "-f",
+        path.to_str().unwrap(),
+        "--key-hex",
+        KEY_HEX,
+        "--encrypt",
+        "--chunk-size",
+        "1K",
+    ])
+    .spawn()
+    .unwrap();
+    /* Signal as soon as the temporary file shows the write has begun */
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while temporaries(dir.path()).is_empty() && Instant::now() < deadline {
+        if child.try_wait().unwrap().is_some() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    interrupt(&child);
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    let result = std::fs::read(&path).unwrap();
+    match output.status.code() {
+        Some(130) => {
+            assert!(
+                stderr.contains("Interrupted, no files modified"),
+                "{}",
+                stderr
+            );
+            assert!(result == data, "partially rewritten");
+        }
+        /* Finished before the signal landed */
+        Some(0) => assert!(result.starts_with(b"RCLI")),
+        code => panic!("unexpected exit {:?}: {}", code, stderr),
+    }
+    assert_eq!(temporaries(dir.path()), Vec::<String>::new());
+}
+
+#[test]
+fn sigint_while_streaming_exits_at_once() {
+    let child = rcli(&["chacha", "-f", "-", "--key-hex", KEY_HEX, "--encrypt"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    /* Give it time to install the handler; standard input stays open */
+    std::thread::sleep(Duration::from_millis(500));
+    interrupt(&child);
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(130), "{}", stderr);
+    assert!(
+        stderr.contains("Interrupted, no files modified"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,240 @@
+/* --jobs: several files at once, reported as if one after another */
+mod common;
+
+use common::rcli;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+/* `count` files of different sizes under `dir`, by name */
+fn files(dir: &Path, count: usize) -> Vec<PathBuf> {
+    std::fs::create_dir_all(dir).unwrap();
+    (0..count)
+        .map(|n| {
+            let path = dir.join(format!("f{:03}", n));
+            std::fs::write(&path, contents(n)).unwrap();
+            path
+        })
+        .collect()
+}
+
+fn contents(n: usize) -> Vec<u8> {
+    format!("file {}\n", n)
+        .repeat(n * 37 % 500 + 1)
+        .into_bytes()
+}
+
+fn tree(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut found: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            (name, std::fs::read(&path).unwrap())
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+#[test]
+fn four_jobs_do_what_one_does() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("src");
+    files(&src, 100);
+
+    let mut reports = Vec::new();
+    for jobs in ["1", "4"] {
+        let sealed = dir.path().join(format!("sealed{}", jobs));
+        let output = rcli()
+            .args(["encrypt", "--key-hex", KEY_HEX, "-r", "-f"])
+            .arg(&src)
+            .arg("--output-dir")
+            .arg(&sealed)
+            .args(["--jobs", jobs])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.ends_with("100 encrypted, 0 skipped, 0 failed\n"));
+        reports.push(stdout.replace(sealed.to_str().unwrap(), "SEALED"));
+
+        let opened = dir.path().join(format!("opened{}", jobs));
+        rcli()
+            .args(["decrypt", "--key-hex", KEY_HEX, "-r", "-f"])
+            .arg(&sealed)
+            .arg("--output-dir")
+            .arg(&opened)
+            .args(["--jobs", jobs])
+            .assert()
+            .success();
+        assert_eq!(tree(&opened), tree(&src));
+    }
+    assert_eq!(reports[0], reports[1]);
+    let lines: Vec<&str> = reports[1].lines().collect();
+    assert_eq!(lines.len(), 101);
+    assert!(lines[42].starts_with("Encrypted ") && lines[42].ends_with("SEALED/f042"));
+}
+
+#[test]
+fn failures_leave_the_other_files_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = files(dir.path(), 20);
+    let names: Vec<&str> = paths.iter().map(|p| p.to_str().unwrap()).collect();
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "--jobs", "4", "-f"])
+        .args(&names)
+        .assert()
+        .success();
+    /* Two left as plaintext, and one gone */
+    std::fs::write(&paths[3], b"never encrypted").unwrap();
+    std::fs::write(&paths[11], b"never encrypted").unwrap();
+    std::fs::remove_file(&paths[7]).unwrap();
+
+    let output = rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "--jobs", "4", "-f"])
+        .args(&names)
+        .output()
+        .unwrap();
+    /* The first failure decides the exit status: not encrypted */
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let errors: Vec<&str> = stderr.lines().filter(|l| l.starts_with("Error:")).collect();
+    assert_eq!(errors.len(), 3, "{}", stderr);
+    for (error, n) in errors.iter().zip([3, 7, 11]) {
+        assert!(
+            error.starts_with(&format!("Error: {}: ", names[n])),
+            "{}",
+            error
+        );
+    }
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let decrypted: Vec<String> = [0, 1, 2, 4, 5, 6, 8, 9, 10, 12, 13, 14, 15, 16, 17, 18, 19]
+        .iter()
+        .map(|&n| {
+            assert_eq!(std::fs::read(&paths[n]).unwrap(), contents(n));
+            format!("Decrypted {}", names[n])
+        })
+        .collect();
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), decrypted);
+    assert_eq!(std::fs::read(&paths[3]).unwrap(), b"never encrypted");
+}
+
+#[test]
+fn json_records_are_whole_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("src");
+    let paths = files(&src, 100);
+    std::fs::write(src.join("f050"), b"RCLI but not really").unwrap();
+
+    let output = rcli()
+        .args(["--json", "encrypt", "--key-hex", KEY_HEX, "-r", "-f"])
+        .arg(&src)
+        .args(["--keep-going", "--stats", "--jobs", "8"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let records: Vec<Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{}: {}", e, line)))
+        .collect();
+
+    /* 99 files, one skip as already encrypted, and the total */
+    let ok: Vec<&Value> = records.iter().filter(|r| r["status"] == "ok").collect();
+    assert_eq!(ok.len(), 99);
+    let in_order: Vec<&str> = paths
+        .iter()
+        .filter(|p| !p.ends_with("f050"))
+        .map(|p| p.to_str().unwrap())
+        .collect();
+    let reported: Vec<&str> = ok.iter().map(|r| r["path"].as_str().unwrap()).collect();
+    assert_eq!(reported, in_order);
+    for record in &ok {
+        let path = record["path"].as_str().unwrap();
+        let stats = &record["stats"];
+        assert_eq!(
+            stats["bytes_written"],
+            std::fs::metadata(path).unwrap().len()
+        );
+    }
+    assert_eq!(
+        records.iter().filter(|r| r["status"] == "skipped").count(),
+        1
+    );
+    let total = records.last().unwrap();
+    assert_eq!(total["files"], 99);
+}
+
+#[test]
+fn jobs_must_be_positive() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = files(dir.path(), 2);
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "--jobs", "0", "-f"])
+        .arg(&paths[0])
+        .assert()
+        .code(2);
+    assert_eq!(std::fs::read(&paths[0]).unwrap(), contents(0));
+}
+
+#[test]
+fn one_file_is_split_by_chunk() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("big");
+    let plain: Vec<u8> = (0..300_000u32).map(|n| (n * 7 % 251) as u8).collect();
+    std::fs::write(&src, &plain).unwrap();
+
+    let mut sealed = Vec::new();
+    for jobs in ["1", "4"] {
+        let out = dir.path().join(format!("big{}.enc", jobs));
+        rcli()
+            .args([
+                "encrypt",
+                "--key-hex",
+                KEY_HEX,
+                "--chunk-size",
+                "4096",
+                "-f",
+            ])
+            .arg(&src)
+            .arg("-o")
+            .arg(&out)
+            .args(["--jobs", jobs])
+            .assert()
+            .success();
+        sealed.push(std::fs::read(&out).unwrap());
+
+        let opened = dir.path().join(format!("big{}", jobs));
+        rcli()
+            .args(["decrypt", "--key-hex", KEY_HEX, "-f"])
+            .arg(&out)
+            .arg("-o")
+            .arg(&opened)
+            .args(["--jobs", jobs])
+            .assert()
+            .success();
+        assert_eq!(std::fs::read(&opened).unwrap(), plain);
+    }
+    /* Nonce prefixes differ, but the layout does not */
+    assert_eq!(sealed[0].len(), sealed[1].len());
+
+    /* Damage in the middle: nothing but the error comes out */
+    let enc = dir.path().join("big4.enc");
+    let mut bad = sealed[1].clone();
+    let at = bad.len() / 2;
+    bad[at] ^= 1;
+    std::fs::write(&enc, &bad).unwrap();
+    let output = rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "--jobs", "4", "-f"])
+        .arg(&enc)
+        .arg("-o")
+        .arg(dir.path().join("never"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error:"), "{}", stderr);
+    assert!(!dir.path().join("never").exists());
+}
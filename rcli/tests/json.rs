@@ -0,0 +1,148 @@
+/* --json: one record per file on standard output, errors with a code */
+mod common;
+
+use common::rcli;
+use serde_json::Value;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const OTHER_KEY_HEX: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+fn records(output: &[u8]) -> Vec<Value> {
+    String::from_utf8(output.to_vec())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+fn encrypt(path: &Path) {
+    rcli()
+        .args(["chacha", "--encrypt", "--key-hex", KEY_HEX])
+        .args(["-f", path.to_str().unwrap()])
+        .assert()
+        .success();
+}
+
+#[test]
+fn encrypt_reports_each_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    std::fs::write(&a, b"hello").unwrap();
+    std::fs::write(&b, vec![0u8; 3000]).unwrap();
+
+    let output = rcli()
+        .args(["--json", "chacha", "--encrypt", "--key-hex", KEY_HEX])
+        .args(["-f", a.to_str().unwrap(), b.to_str().unwrap()])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let records = records(&output.stdout);
+    assert_eq!(records.len(), 2);
+    for (record, (path, len)) in records.iter().zip([(&a, 5), (&b, 3000)]) {
+        let path = path.to_str().unwrap();
+        assert_eq!(record["status"], "ok");
+        assert_eq!(record["action"], "encrypt");
+        assert_eq!(record["path"], path);
+        assert_eq!(record["output"], path);
+        assert_eq!(record["algorithm"], "ChaCha20-Poly1305");
+        assert_eq!(record["bytes_in"], len);
+        let written = std::fs::metadata(path).unwrap().len();
+        assert_eq!(record["bytes_out"], written);
+    }
+}
+
+#[test]
+fn failed_decrypt_reports_a_code() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.txt");
+    std::fs::write(&path, b"hello").unwrap();
+    encrypt(&path);
+
+    let output = rcli()
+        .args(["chacha", "--decrypt", "--key-hex", OTHER_KEY_HEX])
+        .args(["-f", path.to_str().unwrap(), "--json"])
+        .assert()
+        .code(4)
+        .get_output()
+        .clone();
+    let records = records(&output.stdout);
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record["status"], "error");
+    /* No key check value, so a wrong key looks like a damaged chunk */
+    assert_eq!(record["code"], "not_authentic");
+    assert_eq!(record["exit_code"], 4);
+    assert_eq!(record["path"], path.to_str().unwrap());
+    assert!(record["message"].is_string());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn verify_keeps_its_exit_statuses() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.txt");
+    std::fs::write(&path, vec![1u8; 3000]).unwrap();
+    encrypt(&path);
+    let verify = |key: &str, status: i32| {
+        let output = rcli()
+            .args(["--json", "verify", "--key-hex", key])
+            .args(["-f", path.to_str().unwrap()])
+            .assert()
+            .code(status)
+            .get_output()
+            .clone();
+        records(&output.stdout).remove(0)
+    };
+
+    let ok = verify(KEY_HEX, 0);
+    assert_eq!(ok["action"], "verify");
+    assert_eq!(ok["output"], Value::Null);
+    assert_eq!(ok["bytes_out"], 3000);
+    assert_eq!(ok["bytes_in"], std::fs::metadata(&path).unwrap().len());
+
+    let bad = verify(OTHER_KEY_HEX, 4);
+    assert_eq!(bad["code"], "not_authentic");
+}
+
+#[test]
+fn usage_errors_are_records() {
+    let output = rcli()
+        .args(["chacha", "--json", "--no-such-option"])
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    let records = records(&output.stdout);
+    assert_eq!(records[0]["code"], "usage");
+    assert_eq!(records[0]["exit_code"], 2);
+    assert_eq!(records[0]["path"], Value::Null);
+}
+
+#[test]
+fn records_stay_off_a_data_stream() {
+    let output = rcli()
+        .args([
+            "--json",
+            "rc4",
+            "--insecure-ok",
+            "--key-hex",
+            "0102030405",
+            "-f",
+            "-",
+        ])
+        .write_stdin("attack at dawn")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(output.stdout.len(), 14);
+    /* After rc4's warning */
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let records = records(&stderr.as_bytes()[stderr.find('{').unwrap()..]);
+    assert_eq!(records[0]["action"], "process");
+    assert_eq!(records[0]["path"], "-");
+    assert_eq!(records[0]["output"], Value::Null);
+}
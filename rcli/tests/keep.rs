@@ -0,0 +1,211 @@
+/* --keep: the result beside the file, under its name plus a suffix */
+mod common;
+
+use assert_cmd::Command;
+use common::{file_with, rcli};
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn stderr_of(cmd: &mut Command, code: i32) -> String {
+    let output = cmd.assert().code(code).get_output().clone();
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn keep_round_trips_through_the_suffix() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "notes.txt", b"keep me as I am");
+    rcli()
+        .args(["encrypt", "--file", &file, "--key-hex", KEY_HEX, "--keep"])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), b"keep me as I am");
+    let sealed = format!("{}.enc", file);
+    assert_ne!(std::fs::read(&sealed).unwrap(), b"keep me as I am");
+
+    std::fs::remove_file(&file).unwrap();
+    rcli()
+        .args(["decrypt", "--file", &sealed, "--key-hex", KEY_HEX, "--keep"])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), b"keep me as I am");
+    assert!(Path::new(&sealed).exists());
+}
+
+#[test]
+fn keep_takes_a_custom_suffix() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "notes.txt", b"elsewhere");
+    rcli()
+        .args(["encrypt", "--file", &file, "--key-hex", KEY_HEX])
+        .args(["--keep", "--suffix", ".sealed"])
+        .assert()
+        .success();
+    let sealed = format!("{}.sealed", file);
+    assert!(Path::new(&sealed).exists());
+    assert!(!Path::new(&format!("{}.enc", file)).exists());
+
+    std::fs::remove_file(&file).unwrap();
+    rcli()
+        .args(["decrypt", "--file", &sealed, "--key-hex", KEY_HEX])
+        .args(["--keep", "--suffix", ".sealed"])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), b"elsewhere");
+
+    let err = stderr_of(
+        rcli().args([
+            "encrypt",
+            "--file",
+            &file,
+            "--key-hex",
+            KEY_HEX,
+            "--suffix",
+            ".x",
+        ]),
+        2,
+    );
+    assert!(
+        err.contains("--suffix only means something with --keep"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn keep_refuses_to_overwrite_and_carries_on() {
+    let dir = tempfile::tempdir().unwrap();
+    let taken = file_with(dir.path(), "a.txt", b"first");
+    let taken_enc = file_with(dir.path(), "a.txt.enc", b"someone else's");
+    let free = file_with(dir.path(), "b.txt", b"second");
+    let err = stderr_of(
+        rcli()
+            .args(["encrypt", "--key-hex", KEY_HEX, "--keep"])
+            .args(["--file", &taken, "--file", &free]),
+        3,
+    );
+    assert!(err.contains("a.txt"), "{}", err);
+    assert_eq!(std::fs::read(&taken_enc).unwrap(), b"someone else's");
+    assert!(Path::new(&format!("{}.enc", free)).exists());
+
+    rcli()
+        .args([
+            "encrypt",
+            "--key-hex",
+            KEY_HEX,
+            "--keep",
+            "--force",
+            "--file",
+            &taken,
+        ])
+        .assert()
+        .success();
+    assert_ne!(std::fs::read(&taken_enc).unwrap(), b"someone else's");
+}
+
+#[test]
+fn keep_works_through_a_tree() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    file_with(dir.path(), "top.txt", b"top");
+    file_with(&dir.path().join("sub"), "deep.txt", b"deep");
+    file_with(&dir.path().join("sub"), "deep.txt.enc", b"in the way");
+    let root = dir.path().to_str().unwrap();
+
+    rcli()
+        .args(["encrypt", "-r", "--file", root, "--key-hex", KEY_HEX])
+        .args(["--keep", "--keep-going"])
+        .assert()
+        .code(3);
+    assert_eq!(std::fs::read(dir.path().join("top.txt")).unwrap(), b"top");
+    assert!(dir.path().join("top.txt.enc").exists());
+    assert_eq!(
+        std::fs::read(dir.path().join("sub/deep.txt.enc")).unwrap(),
+        b"in the way"
+    );
+
+    /* plaintext is skipped, so only the .enc files are decrypted */
+    std::fs::remove_file(dir.path().join("top.txt")).unwrap();
+    std::fs::remove_file(dir.path().join("sub/deep.txt.enc")).unwrap();
+    rcli()
+        .args([
+            "decrypt",
+            "-r",
+            "--file",
+            root,
+            "--key-hex",
+            KEY_HEX,
+            "--keep",
+        ])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(dir.path().join("top.txt")).unwrap(), b"top");
+    assert_eq!(
+        std::fs::read(dir.path().join("sub/deep.txt.enc")).unwrap(),
+        b"in the way"
+    );
+}
+
+#[test]
+fn keep_needs_a_file_and_no_destination() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"x");
+    let err = stderr_of(
+        rcli().args(["encrypt", "--file", "-", "--key-hex", KEY_HEX, "--keep"]),
+        2,
+    );
+    assert!(err.contains("standard input"), "{}", err);
+    rcli()
+        .args(["encrypt", "--file", &file, "--key-hex", KEY_HEX, "--keep"])
+        .args(["--output", "elsewhere"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn decrypting_recognizes_the_usual_suffixes() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "archive.tar.gz", b"layers");
+    let rcli_file = dir.path().join("archive.tar.gz.rcli");
+    rcli()
+        .args(["encrypt", "--file", &file, "--key-hex", KEY_HEX])
+        .arg("--output")
+        .arg(&rcli_file)
+        .assert()
+        .success();
+    std::fs::remove_file(&file).unwrap();
+    rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "--keep", "--suffix", ".x"])
+        .arg("--file")
+        .arg(&rcli_file)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), b"layers");
+}
+
+#[test]
+fn decrypting_an_unrecognized_name_adds_dec() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), ".bashrc", b"alias ll='ls -l'");
+    let sealed = dir.path().join("nodot");
+    rcli()
+        .args(["encrypt", "--file", &file, "--key-hex", KEY_HEX])
+        .arg("--output")
+        .arg(&sealed)
+        .assert()
+        .success();
+    let decrypt = || {
+        let mut cmd = rcli();
+        cmd.args(["decrypt", "--key-hex", KEY_HEX, "--keep", "--file"])
+            .arg(&sealed);
+        cmd
+    };
+    decrypt().assert().success();
+    assert_eq!(
+        std::fs::read(dir.path().join("nodot.dec")).unwrap(),
+        b"alias ll='ls -l'"
+    );
+    /* and never over an earlier one */
+    decrypt().assert().code(3);
+}
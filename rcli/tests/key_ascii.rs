@@ -0,0 +1,61 @@
+/* rc4 --key-ascii: a text password's bytes as the key, as legacy tools used */
+use assert_cmd::Command;
+
+fn rc4() -> Command {
+    let mut cmd = Command::cargo_bin("rcli").unwrap();
+    cmd.args(["rc4", "--insecure-ok"]);
+    cmd
+}
+
+#[test]
+fn ascii_key_matches_its_hex_bytes() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("legacy.dat");
+    std::fs::write(&file, b"payroll for march").unwrap();
+    let output = rc4()
+        .args(["--key-ascii", "secret99", "--file"])
+        .arg(&file)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("process listings"), "{}", stderr);
+    assert!(stderr.contains("--key-stdin"), "{}", stderr);
+    assert_ne!(std::fs::read(&file).unwrap(), b"payroll for march");
+
+    /* "secret99" is 73 65 63 72 65 74 39 39 */
+    rc4()
+        .args(["--key-hex", "7365637265743939", "--file"])
+        .arg(&file)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), b"payroll for march");
+}
+
+#[test]
+fn short_ascii_keys_are_refused() {
+    let output = rc4()
+        .args(["--key-ascii", "abcd", "--file", "unused"])
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("5 to 256 bytes, got 4"), "{}", stderr);
+}
+
+#[test]
+fn ascii_key_is_one_key_source_among_others() {
+    rc4()
+        .args(["--key-ascii", "secret99", "--key-hex", "7365637265743939"])
+        .args(["--file", "unused"])
+        .assert()
+        .code(2);
+    /* chacha keys are random bytes, never text */
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["encrypt", "--key-ascii", "secret99", "--file", "unused"])
+        .assert()
+        .code(2);
+}
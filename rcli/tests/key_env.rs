@@ -0,0 +1,147 @@
+/* --key-env, run against the built binary so the variable is set only in
+ * the child's environment */
+mod common;
+
+use common::rcli;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const KEY_BASE64: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+#[test]
+fn encrypt_and_decrypt_with_key_from_env() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("build.log");
+    let plaintext = b"artifact checksums, signed off in CI";
+    std::fs::write(&path, plaintext).unwrap();
+    let file = path.to_str().unwrap();
+
+    /* Either encoding of the same key works at both ends */
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            file,
+            "--key-env",
+            "RCLI_TEST_KEY",
+            "--encrypt",
+        ])
+        .env("RCLI_TEST_KEY", KEY_HEX)
+        .assert()
+        .success();
+    assert_ne!(std::fs::read(&path).unwrap(), plaintext);
+
+    rcli()
+        .args(["verify", "-f", file, "--key-env", "RCLI_TEST_KEY"])
+        .env("RCLI_TEST_KEY", format!("{}\n", KEY_BASE64))
+        .assert()
+        .success();
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            file,
+            "--key-env",
+            "RCLI_TEST_KEY",
+            "--decrypt",
+        ])
+        .env("RCLI_TEST_KEY", KEY_BASE64)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&path).unwrap(), plaintext);
+
+    /* RC4 round-trips through the same variable */
+    rcli()
+        .args([
+            "rc4",
+            "--insecure-ok",
+            "-f",
+            file,
+            "--key-env",
+            "RCLI_TEST_KEY",
+        ])
+        .env("RCLI_TEST_KEY", "0x0102030405")
+        .assert()
+        .success();
+    assert_ne!(std::fs::read(&path).unwrap(), plaintext);
+    rcli()
+        .args([
+            "rc4",
+            "--insecure-ok",
+            "-f",
+            file,
+            "--key-env",
+            "RCLI_TEST_KEY",
+        ])
+        .env("RCLI_TEST_KEY", "AQIDBAU")
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&path).unwrap(), plaintext);
+}
+
+#[test]
+fn unusable_variables_fail_without_echoing_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    std::fs::write(&path, b"untouched").unwrap();
+    let file = path.to_str().unwrap();
+
+    let stderr = |value: Option<&str>| {
+        let mut cmd = rcli();
+        cmd.args([
+            "chacha",
+            "-f",
+            file,
+            "--key-env",
+            "RCLI_TEST_KEY",
+            "--encrypt",
+        ]);
+        match value {
+            Some(value) => cmd.env("RCLI_TEST_KEY", value),
+            None => cmd.env_remove("RCLI_TEST_KEY"),
+        };
+        let output = cmd.assert().failure().get_output().clone();
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    assert!(stderr(None).contains("environment variable RCLI_TEST_KEY is not set"));
+    assert!(stderr(Some("")).contains("environment variable RCLI_TEST_KEY is empty"));
+    assert!(stderr(Some(" \n")).contains("environment variable RCLI_TEST_KEY is empty"));
+
+    let secret = "c2VjcmV0!c2VjcmV0";
+    let message = stderr(Some(secret));
+    assert!(
+        message.contains("is not valid hex or base64"),
+        "{}",
+        message
+    );
+    assert!(
+        !message.contains("c2VjcmV0") && !message.contains('!'),
+        "{}",
+        message
+    );
+
+    let short = &KEY_HEX[..62];
+    let message = stderr(Some(short));
+    assert!(
+        message.contains("keys must be exactly 32 bytes, got 31 bytes"),
+        "{}",
+        message
+    );
+    assert!(!message.contains(short), "{}", message);
+
+    assert_eq!(std::fs::read(&path).unwrap(), b"untouched");
+}
+
+#[test]
+fn key_env_is_exclusive_with_other_key_sources() {
+    rcli()
+        .args(["chacha", "-f", "x", "--key-env", "A", "--key-hex", KEY_HEX])
+        .assert()
+        .failure()
+        .code(2);
+    rcli()
+        .args(["rc4", "-f", "x", "--key-env", "A", "--key-file", "k"])
+        .assert()
+        .failure()
+        .code(2);
+}
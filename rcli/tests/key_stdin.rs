@@ -0,0 +1,91 @@
+/* --key-stdin, with the key piped into the built binary */
+mod common;
+
+use common::rcli;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const KEY_BASE64: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+#[test]
+fn encrypt_and_decrypt_with_piped_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bin");
+    let plaintext = b"pulled from the vault at deploy time";
+    std::fs::write(&path, plaintext).unwrap();
+    let file = path.to_str().unwrap();
+
+    rcli()
+        .args(["chacha", "--key-stdin", "--encrypt", "-f", file])
+        .write_stdin(format!("{}\n", KEY_BASE64))
+        .assert()
+        .success();
+    assert_ne!(std::fs::read(&path).unwrap(), plaintext);
+
+    /* Only the first line is the key */
+    rcli()
+        .args(["verify", "--key-stdin", "-f", file])
+        .write_stdin(format!("{}\nsomething else\n", KEY_HEX))
+        .assert()
+        .success();
+    rcli()
+        .args(["chacha", "--key-stdin", "--decrypt", "-f", file])
+        .write_stdin(KEY_HEX)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&path).unwrap(), plaintext);
+}
+
+#[test]
+fn bad_piped_keys_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bin");
+    std::fs::write(&path, b"untouched").unwrap();
+    let file = path.to_str().unwrap();
+
+    for (stdin, error) in [
+        ("", "standard input is empty"),
+        ("c2VjcmV0!\n", "standard input is not valid hex or base64"),
+        (&KEY_HEX[2..], "got 31 bytes"),
+    ] {
+        let output = rcli()
+            .args(["chacha", "--key-stdin", "--encrypt", "-f", file])
+            .write_stdin(stdin)
+            .assert()
+            .failure()
+            .get_output()
+            .clone();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(error), "{}", stderr);
+        assert!(
+            stdin.is_empty() || !stderr.contains(stdin.trim()),
+            "{}",
+            stderr
+        );
+    }
+    assert_eq!(std::fs::read(&path).unwrap(), b"untouched");
+}
+
+#[test]
+fn key_and_data_cannot_both_come_from_stdin() {
+    let output = rcli()
+        .args(["chacha", "--key-stdin", "--encrypt", "-f", "-"])
+        .write_stdin(KEY_HEX)
+        .assert()
+        .failure()
+        .code(2)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr
+            .contains("--key-stdin cannot be used when the data is also read from standard input"),
+        "{}",
+        stderr
+    );
+
+    rcli()
+        .args(["rc4", "--key-stdin", "--key-hex", "0102030405", "-f", "x"])
+        .assert()
+        .failure()
+        .code(2);
+}
@@ -0,0 +1,234 @@
+/* keygen: key sizes, output formats, and --out files */
+use assert_cmd::Command;
+
+fn keygen(args: &[&str]) -> Command {
+    let mut cmd = Command::cargo_bin("rcli").unwrap();
+    cmd.arg("keygen").args(args);
+    cmd
+}
+
+fn stdout_of(cmd: &mut Command) -> String {
+    String::from_utf8(cmd.assert().success().get_output().stdout.clone()).unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn key_file_is_owner_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("key.hex");
+    keygen(&["--out", path.to_str().unwrap()])
+        .assert()
+        .success();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    /* --force replaces a looser file rather than inheriting its mode */
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    keygen(&["--out", path.to_str().unwrap(), "--force"])
+        .assert()
+        .success();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn existing_file_needs_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("key.hex");
+    let out = path.to_str().unwrap();
+    std::fs::write(&path, b"precious").unwrap();
+
+    let output = keygen(&["--out", out])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("use --force"), "{}", stderr);
+    assert_eq!(std::fs::read(&path).unwrap(), b"precious");
+
+    keygen(&["--out", out, "--force"]).assert().success();
+    assert_ne!(std::fs::read(&path).unwrap(), b"precious");
+    /* --force only means something with --out */
+    keygen(&["--force"]).assert().failure();
+}
+
+#[test]
+fn key_file_matches_printed_fingerprint() {
+    let dir = tempfile::tempdir().unwrap();
+    let (key, plain) = (dir.path().join("key.hex"), dir.path().join("plain"));
+    std::fs::write(&plain, b"sealed with a file key").unwrap();
+    let key_arg = key.to_str().unwrap();
+
+    let printed = stdout_of(&mut keygen(&["--out", key_arg]));
+    let contents = std::fs::read_to_string(&key).unwrap();
+    assert!(!printed.contains(contents.trim()), "key leaked to stdout");
+
+    /* The fingerprint is the key's check value */
+    let bytes: Vec<u8> = contents
+        .split_whitespace()
+        .map(|b| u8::from_str_radix(b, 16).unwrap())
+        .collect();
+    let kcv: String = crypto::key_check_value(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(printed.trim(), kcv);
+
+    for mode in ["--encrypt", "--decrypt"] {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["chacha", "-f", plain.to_str().unwrap()])
+            .args(["--key-file", key_arg, mode])
+            .assert()
+            .success();
+    }
+    assert_eq!(std::fs::read(&plain).unwrap(), b"sealed with a file key");
+}
+
+/* Each format read back by the option made for it */
+#[test]
+fn formats_round_trip_through_key_options() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = dir.path().join("plain");
+    std::fs::write(&plain, b"round trip").unwrap();
+    let file = plain.to_str().unwrap();
+    let raw_key = dir.path().join("key.bin");
+    std::fs::write(&raw_key, b"").unwrap();
+    keygen(&[
+        "--format",
+        "raw",
+        "--out",
+        raw_key.to_str().unwrap(),
+        "--force",
+    ])
+    .assert()
+    .success();
+    assert_eq!(std::fs::read(&raw_key).unwrap().len(), 32);
+
+    let hex = stdout_of(&mut keygen(&["--format", "hex"]));
+    assert_eq!(hex.trim().len(), 64);
+    assert!(hex
+        .trim()
+        .bytes()
+        .all(|b| b.is_ascii_digit() || b.is_ascii_lowercase()));
+    let base64 = stdout_of(&mut keygen(&["--format", "base64"]));
+    let spaced = stdout_of(&mut keygen(&[]));
+    assert_eq!(spaced.split_whitespace().count(), 32);
+
+    let mut per_byte = vec!["--key"];
+    per_byte.extend(spaced.split_whitespace());
+    let options: [Vec<&str>; 4] = [
+        vec!["--key-hex", hex.trim()],
+        vec!["--key-base64", base64.trim()],
+        vec!["--key-file", raw_key.to_str().unwrap()],
+        per_byte,
+    ];
+    for key in &options {
+        for mode in ["--encrypt", "--decrypt"] {
+            Command::cargo_bin("rcli")
+                .unwrap()
+                .args(["chacha", "-f", file, mode])
+                .args(key)
+                .assert()
+                .success();
+        }
+        assert_eq!(std::fs::read(&plain).unwrap(), b"round trip", "{:?}", key);
+    }
+
+    /* Raw to a pipe is fine */
+    let output = keygen(&["--algorithm", "rc4", "--format", "raw"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(output.stdout.len(), 16);
+}
+
+/* rc4 keys fed straight back in; the default is 16 bytes */
+#[test]
+fn rc4_sized_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = dir.path().join("plain");
+    std::fs::write(&plain, b"short key").unwrap();
+    for bytes in [None, Some("5"), Some("256")] {
+        let mut args = vec!["--algorithm", "rc4", "--format", "hex"];
+        args.extend(bytes.iter().flat_map(|n| ["--bytes", n]));
+        let hex = stdout_of(&mut keygen(&args));
+        let expected = bytes.map_or(16, |n| n.parse().unwrap());
+        assert_eq!(hex.trim().len(), 2 * expected);
+        for _ in 0..2 {
+            Command::cargo_bin("rcli")
+                .unwrap()
+                .args([
+                    "rc4",
+                    "--insecure-ok",
+                    "-f",
+                    plain.to_str().unwrap(),
+                    "--key-hex",
+                    hex.trim(),
+                ])
+                .assert()
+                .success();
+        }
+        assert_eq!(std::fs::read(&plain).unwrap(), b"short key");
+    }
+    for bad in ["0", "4", "257", "32x"] {
+        keygen(&["--algorithm", "rc4", "--bytes", bad])
+            .assert()
+            .failure();
+    }
+}
+
+#[test]
+fn chacha_keys_are_exactly_32_bytes() {
+    let spaced = stdout_of(&mut keygen(&["--algorithm", "chacha", "--bytes", "32"]));
+    assert_eq!(spaced.split_whitespace().count(), 32);
+
+    let output = keygen(&["--bytes", "16"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("exactly 32 bytes, got 16"), "{}", stderr);
+}
+
+#[test]
+fn diceware_passphrases() {
+    let list = crypto::diceware_words();
+    let output = keygen(&["--words", "8", "--separator", " "])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let phrase = String::from_utf8(output.stdout).unwrap();
+    let words: Vec<&str> = phrase.trim_end().split(' ').collect();
+    assert_eq!(words.len(), 8);
+    assert!(words.iter().all(|w| list.contains(w)), "{}", phrase);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("103.4 bits"), "{}", stderr);
+
+    /* Six words and "-" by default; four list words contain "-" themselves */
+    let output = keygen(&["--words"]).assert().success().get_output().clone();
+    let phrase = String::from_utf8(output.stdout).unwrap();
+    assert!(!phrase.trim_end().contains(' '));
+    assert!((6..=10).contains(&phrase.trim_end().split('-').count()));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("77.5 bits (6 words"), "{}", stderr);
+
+    assert_ne!(
+        stdout_of(&mut keygen(&["--words"])),
+        stdout_of(&mut keygen(&["--words"]))
+    );
+    for bad in [
+        &["--words", "0"][..],
+        &["--words", "--bytes", "32"],
+        &["--separator", "."],
+    ] {
+        keygen(bad).assert().failure();
+    }
+}
@@ -0,0 +1,83 @@
+/* mangen: man pages for packagers */
+mod common;
+
+use common::rcli;
+use std::collections::BTreeSet;
+
+const COMMANDS: [&str; 22] = [
+    "rc4",
+    "keygen",
+    "pwgen",
+    "rand",
+    "encrypt",
+    "decrypt",
+    "chacha",
+    "rekey",
+    "join",
+    "archive",
+    "verify",
+    "inspect",
+    "hash",
+    "hmac",
+    "keypair",
+    "sign",
+    "identity",
+    "verify-sig",
+    "bench",
+    "config",
+    "nonce-log",
+    "completions",
+];
+
+#[test]
+fn one_page_per_command() {
+    let dir = tempfile::tempdir().unwrap();
+    rcli()
+        .args(["mangen", "--out-dir"])
+        .arg(dir.path())
+        .env("SOURCE_DATE_EPOCH", "1767225600")
+        .assert()
+        .success()
+        .stdout("");
+
+    let pages: BTreeSet<String> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    let mut expected: BTreeSet<String> = COMMANDS
+        .iter()
+        .map(|name| format!("rcli-{}.1", name))
+        .collect();
+    expected.insert("rcli.1".to_string());
+    expected.insert("rcli-archive-create.1".to_string());
+    expected.insert("rcli-archive-extract.1".to_string());
+    expected.insert("rcli-archive-list.1".to_string());
+    expected.insert("rcli-identity-new.1".to_string());
+    expected.insert("rcli-config-show.1".to_string());
+    expected.insert("rcli-nonce-log-compact.1".to_string());
+    assert_eq!(pages, expected);
+
+    let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+    let top = read("rcli.1");
+    assert!(
+        top.starts_with(".ie") && top.contains(".TH rcli 1 2026-01-01 \"rcli 0.1.0\""),
+        "{}",
+        top
+    );
+    for name in COMMANDS {
+        assert!(
+            top.contains(&format!("rcli\\-{}(1)", name.replace('-', "\\-"))),
+            "rcli.1 lacks {}",
+            name
+        );
+    }
+    assert!(top.contains("\\-\\-key\\-file"), "{}", top);
+    assert!(!top.contains("mangen"));
+
+    /* Options are documented, and the examples kept */
+    let chacha = read("rcli-chacha.1");
+    assert!(chacha.contains("\\fB\\-\\-key\\-file\\fR"), "{}", chacha);
+    assert!(chacha.contains("Read the 256\\-bit key from a file"));
+    assert!(chacha.contains(".SH EXAMPLES"), "{}", chacha);
+    assert!(chacha.contains("rcli chacha \\-\\-decrypt \\-\\-identity me.key"));
+}
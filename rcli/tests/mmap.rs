@@ -0,0 +1,109 @@
+/* rc4 --mmap: the same bytes as the usual path, changed in place */
+mod common;
+
+use common::{file_with, rcli};
+
+const KEY_HEX: &str = "0102030405";
+
+fn rc4(file: &str, extra: &[&str]) -> std::process::Output {
+    rcli()
+        .args(["rc4", "--insecure-ok", "--key-hex", KEY_HEX, "-f", file])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn mapped_and_streamed_agree() {
+    let dir = tempfile::tempdir().unwrap();
+    /* Over a window, so the keystream carries across windows */
+    let data: Vec<u8> = (0..3_000_000u32).map(|n| (n % 253) as u8).collect();
+    let streamed = file_with(dir.path(), "streamed", &data);
+    let mapped = file_with(dir.path(), "mapped", &data);
+
+    assert!(rc4(&streamed, &[]).status.success());
+    let output = rc4(&mapped, &["--mmap", "-v"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("not mapped"), "{}", stderr);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("Processed "));
+
+    let sealed = std::fs::read(&mapped).unwrap();
+    assert_ne!(sealed, data);
+    assert_eq!(sealed, std::fs::read(&streamed).unwrap());
+
+    /* And back again */
+    assert!(rc4(&mapped, &["--mmap"]).status.success());
+    assert_eq!(std::fs::read(&mapped).unwrap(), data);
+}
+
+#[test]
+fn every_path_uses_the_key_given() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = b"ADMIN_TOKEN=not special at all".to_vec();
+    let mut expected = data.clone();
+    crypto::Rc4::new(&[1, 2, 3, 4, 5]).apply_keystream(&mut expected);
+
+    let streamed = file_with(dir.path(), "streamed", &data);
+    let mapped = file_with(dir.path(), "mapped", &data);
+    assert!(rc4(&streamed, &[]).status.success());
+    assert!(rc4(&mapped, &["--mmap"]).status.success());
+    let piped = rcli()
+        .args(["rc4", "--insecure-ok", "--key-hex", KEY_HEX, "-f", "-"])
+        .write_stdin(data)
+        .output()
+        .unwrap();
+    assert!(piped.status.success());
+
+    assert_eq!(std::fs::read(&streamed).unwrap(), expected);
+    assert_eq!(std::fs::read(&mapped).unwrap(), expected);
+    assert_eq!(piped.stdout, expected);
+}
+
+#[test]
+fn empty_files_are_streamed() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "empty", b"");
+    let output = rc4(&file, &["--mmap", "-v"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!("{}: not mapped (it is empty); streaming it", file)),
+        "{}",
+        stderr
+    );
+    assert_eq!(std::fs::read(&file).unwrap(), b"");
+}
+
+#[test]
+fn backups_come_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "data", b"legacy data");
+    assert!(rc4(&file, &["--mmap", "--backup"]).status.success());
+    assert_eq!(
+        std::fs::read(format!("{}.bak", file)).unwrap(),
+        b"legacy data"
+    );
+    assert_ne!(std::fs::read(&file).unwrap(), b"legacy data");
+}
+
+#[test]
+fn output_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "data", b"legacy data");
+    let out = dir.path().join("out");
+    let output = rcli()
+        .args(["rc4", "--insecure-ok", "--key-hex", KEY_HEX, "--mmap", "-f"])
+        .arg(&file)
+        .arg("-o")
+        .arg(&out)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--mmap"), "{}", stderr);
+    assert!(!out.exists());
+    assert_eq!(std::fs::read(&file).unwrap(), b"legacy data");
+}
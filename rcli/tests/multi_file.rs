@@ -0,0 +1,183 @@
+/* Several files (and --glob patterns) in one invocation */
+mod common;
+
+use common::rcli;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+#[test]
+fn three_files_in_one_rc4_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<String> = ["one", "two", "three"]
+        .iter()
+        .map(|name| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, name.repeat(10)).unwrap();
+            path.to_str().unwrap().to_string()
+        })
+        .collect();
+
+    for _ in 0..2 {
+        let output = rcli()
+            .args(["rc4", "--insecure-ok", "--key-hex", "0102030405", "-f"])
+            .args(&paths)
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", stdout);
+        for (line, path) in lines.iter().zip(&paths) {
+            assert_eq!(*line, format!("Processed {}", path));
+        }
+    }
+    for (name, path) in ["one", "two", "three"].iter().zip(&paths) {
+        assert_eq!(std::fs::read(path).unwrap(), name.repeat(10).as_bytes());
+    }
+}
+
+#[test]
+fn missing_file_fails_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+    let missing = dir.path().join("missing");
+    std::fs::write(&a, b"alpha").unwrap();
+    std::fs::write(&b, b"bravo").unwrap();
+    let path = |p: &std::path::Path| p.to_str().unwrap().to_string();
+
+    let output = rcli()
+        .args(["chacha", "--key-hex", KEY_HEX, "--encrypt"])
+        .args(["-f", &path(&a), &path(&missing)])
+        .args(["-f", &path(&b)])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with(&format!("Error: {}: ", path(&missing))),
+        "{}",
+        stderr
+    );
+    assert_eq!(stdout.lines().count(), 2, "{}", stdout);
+    assert!(std::fs::read(&a).unwrap().starts_with(b"RCLI"));
+    assert!(std::fs::read(&b).unwrap().starts_with(b"RCLI"));
+}
+
+#[test]
+fn glob_skips_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("app.log"), b"log one").unwrap();
+    std::fs::write(dir.path().join("db.log"), b"log two").unwrap();
+    std::fs::write(dir.path().join("notes.txt"), b"not a log").unwrap();
+    std::fs::create_dir(dir.path().join("old.log")).unwrap();
+    let pattern = format!("{}/*.log", dir.path().to_str().unwrap());
+
+    let output = rcli()
+        .args([
+            "chacha",
+            "--key-hex",
+            KEY_HEX,
+            "--encrypt",
+            "--glob",
+            &pattern,
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 2, "{}", stdout);
+    assert!(std::fs::read(dir.path().join("app.log"))
+        .unwrap()
+        .starts_with(b"RCLI"));
+    assert!(std::fs::read(dir.path().join("db.log"))
+        .unwrap()
+        .starts_with(b"RCLI"));
+    assert_eq!(
+        std::fs::read(dir.path().join("notes.txt")).unwrap(),
+        b"not a log"
+    );
+
+    rcli()
+        .args([
+            "chacha",
+            "--key-hex",
+            KEY_HEX,
+            "--decrypt",
+            "--glob",
+            &pattern,
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read(dir.path().join("app.log")).unwrap(),
+        b"log one"
+    );
+}
+
+#[test]
+fn single_result_options_need_one_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+    std::fs::write(&a, b"alpha").unwrap();
+    std::fs::write(&b, b"bravo").unwrap();
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+    let out = dir.path().join("out");
+
+    rcli()
+        .args(["chacha", "--key-hex", KEY_HEX, "--encrypt", "-f", a, b])
+        .args(["-o", out.to_str().unwrap()])
+        .assert()
+        .failure();
+    rcli()
+        .args([
+            "rc4",
+            "--insecure-ok",
+            "--key-hex",
+            "0102030405",
+            "-f",
+            a,
+            "-",
+        ])
+        .assert()
+        .failure();
+    assert_eq!(std::fs::read(a).unwrap(), b"alpha");
+    assert!(!out.exists());
+}
+
+#[test]
+fn exclude_filters_glob_matches() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("old")).unwrap();
+    for name in ["app.log", "old/app.log", "app.log.enc"] {
+        std::fs::write(dir.path().join(name), name).unwrap();
+    }
+    let pattern = format!("{}/**/*", dir.path().to_str().unwrap());
+
+    rcli()
+        .args([
+            "chacha",
+            "--key-hex",
+            KEY_HEX,
+            "--encrypt",
+            "--glob",
+            &pattern,
+        ])
+        .args(["--exclude", "*.enc", "--exclude", "old/"])
+        .assert()
+        .success();
+    assert!(std::fs::read(dir.path().join("app.log"))
+        .unwrap()
+        .starts_with(b"RCLI"));
+    assert_eq!(
+        std::fs::read(dir.path().join("old/app.log")).unwrap(),
+        b"old/app.log"
+    );
+    assert_eq!(
+        std::fs::read(dir.path().join("app.log.enc")).unwrap(),
+        b"app.log.enc"
+    );
+}
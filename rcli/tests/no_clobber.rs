@@ -0,0 +1,98 @@
+/* --no-clobber: existing outputs are never replaced */
+mod common;
+
+use common::{file_with, rcli};
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+#[test]
+fn no_clobber_skips_existing_outputs_and_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = file_with(dir.path(), "a.txt", b"first");
+    let second = file_with(dir.path(), "b.txt", b"second");
+    let taken = file_with(dir.path(), "a.txt.enc", b"already here");
+    let output = rcli()
+        .args(["--no-clobber", "encrypt", "--key-hex", KEY_HEX, "--keep"])
+        .args(["--file", &first, "--file", &second])
+        .assert()
+        .code(3)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--no-clobber"), "{}", stderr);
+    assert_eq!(std::fs::read(&taken).unwrap(), b"already here");
+    assert!(Path::new(&format!("{}.enc", second)).exists());
+}
+
+#[test]
+fn no_clobber_outranks_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"plain");
+    let out = file_with(dir.path(), "out", b"keep this");
+    rcli()
+        .args([
+            "encrypt",
+            "--key-hex",
+            KEY_HEX,
+            "--file",
+            &file,
+            "--output",
+            &out,
+        ])
+        .args(["--force", "--no-clobber"])
+        .assert()
+        .code(3);
+    assert_eq!(std::fs::read(&out).unwrap(), b"keep this");
+
+    /* --force alone still replaces it */
+    rcli()
+        .args([
+            "encrypt",
+            "--key-hex",
+            KEY_HEX,
+            "--file",
+            &file,
+            "--output",
+            &out,
+        ])
+        .arg("--force")
+        .assert()
+        .success();
+    assert_ne!(std::fs::read(&out).unwrap(), b"keep this");
+}
+
+#[test]
+fn no_clobber_covers_backups() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"plain");
+    let backup = file_with(dir.path(), "a.txt.bak", b"older backup");
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "--file", &file, "--backup"])
+        .args(["--force", "--no-clobber"])
+        .assert()
+        .code(3);
+    assert_eq!(std::fs::read(&backup).unwrap(), b"older backup");
+    assert_eq!(std::fs::read(&file).unwrap(), b"plain");
+}
+
+#[test]
+fn without_no_clobber_new_outputs_are_written() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"plain");
+    let out = dir.path().join("fresh");
+    rcli()
+        .args([
+            "--no-clobber",
+            "encrypt",
+            "--key-hex",
+            KEY_HEX,
+            "--file",
+            &file,
+        ])
+        .arg("--output")
+        .arg(&out)
+        .assert()
+        .success();
+    assert!(out.exists());
+}
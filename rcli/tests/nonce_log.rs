@@ -0,0 +1,145 @@
+/* --nonce-log: every key and nonce prefix pair recorded once */
+mod common;
+
+use common::{file_with, rcli};
+use std::collections::HashSet;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const OTHER_KEY_HEX: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+fn log_lines(log: &Path) -> Vec<String> {
+    std::fs::read_to_string(log)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+fn encrypt(file: &str, key: &str, log: &Path) {
+    rcli()
+        .args(["encrypt", "--file", file, "--key-hex", key, "--nonce-log"])
+        .arg(log)
+        .assert()
+        .success();
+}
+
+#[test]
+fn each_encryption_is_logged_without_the_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("nonces");
+    let file = file_with(dir.path(), "a.txt", b"logged");
+    encrypt(&file, KEY_HEX, &log);
+
+    let lines = log_lines(&log);
+    assert_eq!(lines.len(), 1);
+    let (fingerprint, prefix) = lines[0].split_once(' ').unwrap();
+    assert_eq!(fingerprint.len(), 32);
+    assert!(!KEY_HEX.contains(fingerprint));
+    /* the prefix is the one in the file's header */
+    let sealed = std::fs::read(&file).unwrap();
+    let header: String = sealed[10..17]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(prefix, header);
+
+    rcli()
+        .args(["decrypt", "--file", &file, "--key-hex", KEY_HEX])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), b"logged");
+    assert_eq!(log_lines(&log).len(), 1);
+}
+
+#[test]
+fn parallel_encryptions_append_whole_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("nonces");
+    let children: Vec<_> = (0..12)
+        .map(|i| {
+            let file = file_with(dir.path(), &format!("{}.txt", i), b"in parallel");
+            std::process::Command::new(assert_cmd::cargo::cargo_bin("rcli"))
+                .args([
+                    "encrypt",
+                    "--file",
+                    &file,
+                    "--key-hex",
+                    KEY_HEX,
+                    "--nonce-log",
+                ])
+                .arg(&log)
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+    let lines = log_lines(&log);
+    assert_eq!(lines.len(), 12);
+    let distinct: HashSet<&String> = lines.iter().collect();
+    assert_eq!(distinct.len(), 12);
+    assert!(lines.iter().all(|line| line.len() == 32 + 1 + 14));
+}
+
+#[test]
+fn compact_drops_retired_keys_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("nonces");
+    for i in 0..2 {
+        encrypt(
+            &file_with(dir.path(), &format!("old{}", i), b"x"),
+            KEY_HEX,
+            &log,
+        );
+        encrypt(
+            &file_with(dir.path(), &format!("new{}", i), b"y"),
+            OTHER_KEY_HEX,
+            &log,
+        );
+    }
+    let lines = log_lines(&log);
+    let retired = lines[0].split_once(' ').unwrap().0.to_string();
+    rcli()
+        .args(["nonce-log", "compact", "--retire", &retired, "--log"])
+        .arg(&log)
+        .assert()
+        .success()
+        .stdout(format!(
+            "Compacted {}: kept 2 entries, dropped 2\n",
+            log.display()
+        ));
+    let kept: Vec<String> = lines
+        .into_iter()
+        .filter(|line| !line.starts_with(&retired))
+        .collect();
+    assert_eq!(log_lines(&log), kept);
+    assert!(!dir.path().join("nonces.compact").exists());
+}
+
+#[test]
+fn fresh_key_modes_take_no_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"x");
+    rcli()
+        .args([
+            "encrypt",
+            "--file",
+            &file,
+            "--passphrase",
+            "--nonce-log",
+            "n",
+        ])
+        .assert()
+        .code(2);
+    rcli()
+        .args(["chacha", "--decrypt", "--file", &file, "--key-hex", KEY_HEX])
+        .args(["--nonce-log", "n"])
+        .assert()
+        .code(2);
+    rcli()
+        .args(["nonce-log", "compact", "--retire", "abc", "--log", "n"])
+        .assert()
+        .code(2);
+}
@@ -0,0 +1,229 @@
+/* -o/--output: write the result to a new file instead of in place */
+mod common;
+
+use assert_cmd::Command;
+use common::rcli;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn stderr_of(cmd: &mut Command) -> String {
+    let output = cmd.assert().failure().get_output().clone();
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn output_round_trip_leaves_input_untouched() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = dir.path().join("report.txt");
+    let (enc, dec) = (dir.path().join("report.enc"), dir.path().join("report.dec"));
+    std::fs::write(&plain, b"my only copy").unwrap();
+    let path = |p: &std::path::Path| p.to_str().unwrap().to_string();
+
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            &path(&plain),
+            "--key-hex",
+            KEY_HEX,
+            "--encrypt",
+        ])
+        .args(["-o", &path(&enc)])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&plain).unwrap(), b"my only copy");
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            &path(&enc),
+            "--key-hex",
+            KEY_HEX,
+            "--decrypt",
+        ])
+        .args(["--output", &path(&dec)])
+        .assert()
+        .success();
+    assert_ne!(std::fs::read(&enc).unwrap(), b"my only copy");
+    assert_eq!(std::fs::read(&dec).unwrap(), b"my only copy");
+
+    /* RC4 the same way */
+    let (rc4, back) = (
+        dir.path().join("report.rc4"),
+        dir.path().join("report.back"),
+    );
+    for (from, to) in [(&plain, &rc4), (&rc4, &back)] {
+        rcli()
+            .args([
+                "rc4",
+                "--insecure-ok",
+                "-f",
+                &path(from),
+                "--key-hex",
+                "0102030405",
+                "-o",
+                &path(to),
+            ])
+            .assert()
+            .success();
+    }
+    assert_eq!(std::fs::read(&plain).unwrap(), b"my only copy");
+    assert_ne!(std::fs::read(&rc4).unwrap(), b"my only copy");
+    assert_eq!(std::fs::read(&back).unwrap(), b"my only copy");
+}
+
+#[test]
+fn dash_is_not_an_output_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = dir.path().join("plan.txt");
+    std::fs::write(&plain, b"meet at noon").unwrap();
+    let stderr = stderr_of(
+        rcli()
+            .current_dir(dir.path())
+            .args(["chacha", "--encrypt", "--key-hex", KEY_HEX])
+            .args(["-f", plain.to_str().unwrap(), "-o", "-"]),
+    );
+    assert!(
+        stderr.contains("--output - would write a file named -"),
+        "{}",
+        stderr
+    );
+    assert!(!dir.path().join("-").exists());
+    assert_eq!(std::fs::read(&plain).unwrap(), b"meet at noon");
+}
+
+#[test]
+fn existing_output_needs_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = dir.path().join("plain");
+    let out = dir.path().join("out");
+    std::fs::write(&plain, b"new contents").unwrap();
+    std::fs::write(&out, b"old contents").unwrap();
+    let (plain, out_str) = (plain.to_str().unwrap(), out.to_str().unwrap());
+
+    let stderr = stderr_of(rcli().args([
+        "chacha",
+        "-f",
+        plain,
+        "--key-hex",
+        KEY_HEX,
+        "--encrypt",
+        "-o",
+        out_str,
+    ]));
+    assert!(stderr.contains("already exists"), "{}", stderr);
+    assert_eq!(std::fs::read(&out).unwrap(), b"old contents");
+
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            plain,
+            "--key-hex",
+            KEY_HEX,
+            "--encrypt",
+            "-o",
+            out_str,
+        ])
+        .arg("--force")
+        .assert()
+        .success();
+    assert_ne!(std::fs::read(&out).unwrap(), b"old contents");
+    assert_eq!(std::fs::read(plain).unwrap(), b"new contents");
+
+    /* --force means nothing to rc4 without --output */
+    let output = rcli()
+        .args([
+            "rc4",
+            "--insecure-ok",
+            "-f",
+            plain,
+            "--key-hex",
+            "0102030405",
+        ])
+        .arg("--force")
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("without --output"), "{}", stderr);
+}
+
+#[cfg(unix)]
+#[test]
+fn same_file_through_symlink_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = dir.path().join("plain");
+    let link = dir.path().join("link");
+    std::fs::write(&plain, b"my only copy").unwrap();
+    std::os::unix::fs::symlink(&plain, &link).unwrap();
+    let (plain_str, link_str) = (plain.to_str().unwrap(), link.to_str().unwrap());
+
+    for args in [
+        vec![
+            "chacha",
+            "-f",
+            plain_str,
+            "--key-hex",
+            KEY_HEX,
+            "--encrypt",
+            "-o",
+            link_str,
+            "--force",
+        ],
+        vec![
+            "rc4",
+            "--insecure-ok",
+            "-f",
+            link_str,
+            "--key-hex",
+            "0102030405",
+            "-o",
+            plain_str,
+            "--force",
+        ],
+    ] {
+        let stderr = stderr_of(rcli().args(&args));
+        assert!(
+            stderr.contains("input and output are the same file"),
+            "{}",
+            stderr
+        );
+    }
+    assert_eq!(std::fs::read(&plain).unwrap(), b"my only copy");
+    assert!(std::fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+}
+
+#[cfg(unix)]
+#[test]
+fn in_place_keeps_mode_and_leaves_no_temporaries() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let plain = dir.path().join("payroll.csv");
+    std::fs::write(&plain, b"my only copy").unwrap();
+    std::fs::set_permissions(&plain, std::fs::Permissions::from_mode(0o600)).unwrap();
+    let file = plain.to_str().unwrap();
+
+    rcli()
+        .args(["chacha", "-f", file, "--key-hex", KEY_HEX, "--encrypt"])
+        .assert()
+        .success();
+    let mode = std::fs::metadata(&plain).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    /* a failed rewrite keeps the ciphertext and cleans up after itself */
+    let sealed = std::fs::read(&plain).unwrap();
+    let wrong = "ff".repeat(32);
+    stderr_of(rcli().args(["chacha", "-f", file, "--key-hex", &wrong, "--decrypt"]));
+    assert_eq!(std::fs::read(&plain).unwrap(), sealed);
+    let names: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(names, ["payroll.csv"]);
+}
@@ -0,0 +1,310 @@
+/* chacha --passphrase, with the passphrase piped in (standard input is not
+ * a terminal here, so it is read as one line instead of prompted for) */
+mod common;
+
+use common::rcli;
+use crypto::header::KDF_ARGON2ID;
+use crypto::Argon2Params;
+
+#[test]
+fn round_trip_through_header_parameters() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("diary.txt");
+    let plaintext = b"dear diary, today I picked a good passphrase";
+    std::fs::write(&path, plaintext).unwrap();
+    let file = path.to_str().unwrap();
+
+    rcli()
+        .args(["chacha", "-f", file, "--passphrase", "--encrypt"])
+        .write_stdin("correct horse battery staple\n")
+        .assert()
+        .success();
+
+    /* The header says how to derive the key again */
+    let sealed = std::fs::read(&path).unwrap();
+    let (header, _) = crypto::fs::read_header(&mut &sealed[..]).unwrap();
+    let kdf = header.passphrase.expect("passphrase extension");
+    let defaults = Argon2Params::default();
+    assert_eq!(kdf.kdf, KDF_ARGON2ID);
+    assert_eq!(
+        (kdf.m_cost_kib, kdf.t_cost, kdf.p_cost),
+        (defaults.m_cost_kib, defaults.t_cost, defaults.p_cost)
+    );
+    assert_ne!(kdf.salt, [0; 16]);
+    assert!(header.key_check.is_some());
+
+    /* No other flags are needed to decrypt */
+    rcli()
+        .args(["chacha", "-f", file, "--passphrase", "--decrypt"])
+        .write_stdin("correct horse battery staple\r\n")
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&path).unwrap(), plaintext);
+
+    /* A fresh salt every time */
+    let salt = kdf.salt;
+    rcli()
+        .args(["chacha", "-f", file, "--passphrase", "--encrypt"])
+        .write_stdin("correct horse battery staple\n")
+        .assert()
+        .success();
+    let sealed = std::fs::read(&path).unwrap();
+    let (header, _) = crypto::fs::read_header(&mut &sealed[..]).unwrap();
+    assert_ne!(header.passphrase.unwrap().salt, salt);
+}
+
+#[test]
+fn wrong_passphrase_is_an_error_not_a_crash() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    std::fs::write(&path, b"locked").unwrap();
+    let file = path.to_str().unwrap();
+
+    rcli()
+        .args(["chacha", "-f", file, "--passphrase", "--encrypt"])
+        .write_stdin("right one\n")
+        .assert()
+        .success();
+    let sealed = std::fs::read(&path).unwrap();
+
+    let decrypt = |passphrase: &str, status: i32| {
+        let output = rcli()
+            .args(["chacha", "-f", file, "--passphrase", "--decrypt"])
+            .write_stdin(passphrase)
+            .assert()
+            .failure()
+            .code(status)
+            .get_output()
+            .clone();
+        String::from_utf8(output.stderr).unwrap()
+    };
+    /* The key check value catches the wrong passphrase */
+    let stderr = decrypt("wrong one\n", 6);
+    assert!(
+        stderr.contains("incorrect passphrase or corrupted file"),
+        "{}",
+        stderr
+    );
+    assert!(decrypt("\n", 2).contains("passphrase is empty"));
+    assert_eq!(std::fs::read(&path).unwrap(), sealed);
+
+    /* A damaged chunk reads the same as a wrong passphrase, but fails
+     * authentication rather than the key check */
+    let mut damaged = sealed.clone();
+    let last = damaged.len() - 1;
+    damaged[last] ^= 1;
+    std::fs::write(&path, &damaged).unwrap();
+    assert!(decrypt("right one\n", 4).contains("incorrect passphrase or corrupted file"));
+}
+
+#[test]
+fn passphrase_needs_a_passphrase_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    std::fs::write(&path, b"keyed").unwrap();
+    let file = path.to_str().unwrap();
+    let key = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    rcli()
+        .args(["chacha", "-f", file, "--key-hex", key, "--encrypt"])
+        .assert()
+        .success();
+    let output = rcli()
+        .args(["chacha", "-f", file, "--passphrase", "--decrypt"])
+        .write_stdin("anything\n")
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("was not encrypted with a passphrase"),
+        "{}",
+        stderr
+    );
+
+    /* --passphrase is one more key source */
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            file,
+            "--passphrase",
+            "--key-hex",
+            key,
+            "--decrypt",
+        ])
+        .assert()
+        .failure()
+        .code(2);
+}
+
+fn passphrase_file(dir: &std::path::Path, name: &str, contents: &str) -> String {
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn passphrase_file_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("backup.tar");
+    let plaintext = b"nightly backup";
+    std::fs::write(&path, plaintext).unwrap();
+    let file = path.to_str().unwrap();
+    let pass = passphrase_file(dir.path(), "pass", "backup passphrase\n");
+
+    let output = rcli()
+        .args([
+            "chacha",
+            "-f",
+            file,
+            "--passphrase-file",
+            &pass,
+            "--encrypt",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(!String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Warning"));
+    let sealed = std::fs::read(&path).unwrap();
+    assert!(crypto::fs::read_header(&mut &sealed[..])
+        .unwrap()
+        .0
+        .passphrase
+        .is_some());
+
+    /* Same derivation as typing the passphrase in */
+    rcli()
+        .args(["chacha", "-f", file, "--passphrase", "--decrypt"])
+        .write_stdin("backup passphrase\n")
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&path).unwrap(), plaintext);
+
+    rcli()
+        .args(["chacha", "-f", file, "--passphrase", "--encrypt"])
+        .write_stdin("backup passphrase\n")
+        .assert()
+        .success();
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            file,
+            "--passphrase-file",
+            &pass,
+            "--decrypt",
+        ])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&path).unwrap(), plaintext);
+}
+
+#[test]
+fn bad_passphrase_files_fail_cleanly() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    std::fs::write(&path, b"locked").unwrap();
+    let file = path.to_str().unwrap();
+    let right = passphrase_file(dir.path(), "right", "right one\n");
+    let wrong = passphrase_file(dir.path(), "wrong", "right one \n");
+    let empty = passphrase_file(dir.path(), "empty", "");
+
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            file,
+            "--passphrase-file",
+            &right,
+            "--encrypt",
+        ])
+        .assert()
+        .success();
+    let sealed = std::fs::read(&path).unwrap();
+
+    let stderr = |pass: &str, status: i32| {
+        let output = rcli()
+            .args(["chacha", "-f", file, "--passphrase-file", pass, "--decrypt"])
+            .assert()
+            .failure()
+            .code(status)
+            .get_output()
+            .clone();
+        String::from_utf8(output.stderr).unwrap()
+    };
+    assert!(stderr(&wrong, 6).contains("incorrect passphrase or corrupted file"));
+    assert!(stderr(&empty, 2).contains("is empty"));
+    assert!(stderr(&dir.path().join("missing").to_string_lossy(), 2)
+        .contains("cannot read passphrase file"));
+    assert_eq!(std::fs::read(&path).unwrap(), sealed);
+
+    /* Exclusive with the prompt and with raw keys */
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            file,
+            "--passphrase-file",
+            &right,
+            "--passphrase",
+            "--decrypt",
+        ])
+        .assert()
+        .failure()
+        .code(2);
+    rcli()
+        .args([
+            "chacha",
+            "-f",
+            file,
+            "--passphrase-file",
+            &right,
+            "--key-env",
+            "K",
+            "--decrypt",
+        ])
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[cfg(unix)]
+#[test]
+fn warns_about_loose_passphrase_file_mode() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    std::fs::write(&path, b"x").unwrap();
+    let pass = passphrase_file(dir.path(), "pass", "loose\n");
+    std::fs::set_permissions(&pass, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+    let output = rcli()
+        .args([
+            "chacha",
+            "-f",
+            path.to_str().unwrap(),
+            "--passphrase-file",
+            &pass,
+            "--encrypt",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("has mode 640, broader than 600"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,171 @@
+/* --file -: read standard input, write standard output */
+mod common;
+
+use common::rcli;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+/* Run with `stdin`, returning standard output and standard error */
+fn pipe(args: &[&str], stdin: &[u8]) -> (Vec<u8>, String) {
+    let output = rcli()
+        .args(args)
+        .write_stdin(stdin)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    (output.stdout, String::from_utf8(output.stderr).unwrap())
+}
+
+fn data() -> Vec<u8> {
+    /* Several 64 KiB chunks, so streaming crosses chunk boundaries */
+    (0..200_000u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+        .collect()
+}
+
+#[test]
+fn chacha_pipes_match_file_mode() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    let file = path.to_str().unwrap();
+    let data = data();
+
+    /* Piped in, decrypted as a file */
+    let (sealed, stderr) = pipe(
+        &["chacha", "--encrypt", "--key-hex", KEY_HEX, "-f", "-"],
+        &data,
+    );
+    assert!(sealed.starts_with(b"RCLI"));
+    assert!(stderr.contains("Encrypted standard input"), "{}", stderr);
+    std::fs::write(&path, &sealed).unwrap();
+    rcli()
+        .args(["chacha", "--decrypt", "--key-hex", KEY_HEX, "-f", file])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+
+    /* Encrypted as a file, piped out; nothing but the data on stdout */
+    rcli()
+        .args(["chacha", "--encrypt", "--key-hex", KEY_HEX, "-f", file])
+        .assert()
+        .success();
+    let sealed = std::fs::read(&path).unwrap();
+    let (opened, _) = pipe(
+        &["chacha", "--decrypt", "--key-hex", KEY_HEX, "-f", "-"],
+        &sealed,
+    );
+    assert_eq!(opened, data);
+
+    rcli()
+        .args(["verify", "--key-hex", KEY_HEX, "-f", "-"])
+        .write_stdin(sealed.clone())
+        .assert()
+        .success();
+
+    /* A bad chunk fails without a status line on stdout */
+    let mut bad = sealed;
+    bad[30_000] ^= 1;
+    let output = rcli()
+        .args(["chacha", "--decrypt", "--key-hex", KEY_HEX, "-f", "-"])
+        .write_stdin(bad)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Decrypted"));
+}
+
+#[test]
+fn rc4_pipe_matches_file_mode() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    let file = path.to_str().unwrap();
+    let data = data();
+    std::fs::write(&path, &data).unwrap();
+
+    rcli()
+        .args([
+            "rc4",
+            "--insecure-ok",
+            "--key-hex",
+            "0102030405",
+            "-f",
+            file,
+        ])
+        .assert()
+        .success();
+    let from_file = std::fs::read(&path).unwrap();
+    let (from_pipe, _) = pipe(
+        &["rc4", "--insecure-ok", "--key-hex", "0102030405", "-f", "-"],
+        &data,
+    );
+    assert_eq!(from_pipe, from_file);
+    let (back, _) = pipe(
+        &["rc4", "--insecure-ok", "--key-hex", "0102030405", "-f", "-"],
+        &from_pipe,
+    );
+    assert_eq!(back, data);
+}
+
+#[test]
+fn passphrases_with_pipes() {
+    let dir = tempfile::tempdir().unwrap();
+    let pass = dir.path().join("pass");
+    std::fs::write(&pass, "pipe dream\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&pass, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+    let pass = pass.to_str().unwrap();
+
+    let (sealed, _) = pipe(
+        &["chacha", "--encrypt", "--passphrase-file", pass, "-f", "-"],
+        b"tarball",
+    );
+    let (opened, _) = pipe(
+        &["chacha", "--decrypt", "--passphrase-file", pass, "-f", "-"],
+        &sealed,
+    );
+    assert_eq!(opened, b"tarball");
+
+    /* Piped standard input cannot carry both the passphrase and the data */
+    let output = rcli()
+        .args(["chacha", "--encrypt", "--passphrase", "-f", "-"])
+        .write_stdin("pipe dream\ntarball")
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("use --passphrase-file"), "{}", stderr);
+}
+
+#[test]
+fn output_flag_does_not_apply_to_pipes() {
+    let output = rcli()
+        .args([
+            "rc4",
+            "--insecure-ok",
+            "--key-hex",
+            "0102030405",
+            "-f",
+            "-",
+            "-o",
+            "out",
+        ])
+        .write_stdin("data")
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("redirect standard output instead"),
+        "{}",
+        stderr
+    );
+}
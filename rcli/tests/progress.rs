@@ -0,0 +1,54 @@
+/* --progress / --no-progress on standard error */
+use assert_cmd::Command;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn chacha(file: &str, extra: &[&str]) -> std::process::Output {
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["chacha", "-f", file, "--key-hex", KEY_HEX, "--encrypt"])
+        .args(extra)
+        .write_stdin("piped")
+        .assert()
+        .success()
+        .get_output()
+        .clone()
+}
+
+#[test]
+fn forced_progress_is_erased_before_the_status_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("disk.img");
+    std::fs::write(&path, vec![0u8; 256 * 1024]).unwrap();
+
+    let output = chacha(path.to_str().unwrap(), &["--progress"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("256.0 KiB / 256.0 KiB  100%"),
+        "{:?}",
+        stderr
+    );
+    assert!(stderr.ends_with("\r\x1b[2K"), "{:?}", stderr);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("Encrypted "));
+}
+
+#[test]
+fn no_progress_by_default_off_a_terminal_or_when_disabled() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("disk.img");
+    std::fs::write(&path, vec![0u8; 4096]).unwrap();
+    let file = path.to_str().unwrap();
+
+    assert!(chacha(file, &[]).stderr.is_empty());
+    std::fs::write(&path, vec![0u8; 4096]).unwrap();
+    assert!(chacha(file, &["--no-progress"]).stderr.is_empty());
+}
+
+#[test]
+fn never_shown_when_streaming_to_stdout() {
+    let output = chacha("-", &["--progress"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "Encrypted standard input\n");
+}
@@ -0,0 +1,115 @@
+/* pwgen: random passwords from character classes */
+use assert_cmd::Command;
+
+fn pwgen(args: &[&str]) -> std::process::Output {
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .arg("pwgen")
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .clone()
+}
+
+fn passwords(args: &[&str]) -> Vec<String> {
+    let output = pwgen(args);
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn count_length_and_classes() {
+    let all = passwords(&["--length", "4", "--symbols", "--count", "500"]);
+    assert_eq!(all.len(), 500);
+    for password in &all {
+        assert_eq!(password.len(), 4, "{}", password);
+        assert!(
+            password.bytes().any(|b| b.is_ascii_lowercase()),
+            "{}",
+            password
+        );
+        assert!(
+            password.bytes().any(|b| b.is_ascii_uppercase()),
+            "{}",
+            password
+        );
+        assert!(password.bytes().any(|b| b.is_ascii_digit()), "{}", password);
+        assert!(
+            password.bytes().any(|b| b.is_ascii_punctuation()),
+            "{}",
+            password
+        );
+    }
+
+    /* Letters and digits, 20 long, by default */
+    let default = passwords(&[]);
+    assert_eq!(default.len(), 1);
+    assert_eq!(default[0].len(), 20);
+    assert!(default[0].bytes().all(|b| b.is_ascii_alphanumeric()));
+
+    let digits = passwords(&["--no-lower", "--no-upper", "--length", "300"]);
+    assert!(digits[0].bytes().all(|b| b.is_ascii_digit()));
+    assert_eq!(digits[0].len(), 300);
+}
+
+#[test]
+fn ambiguous_characters_left_out() {
+    let all = passwords(&[
+        "--symbols",
+        "--no-ambiguous",
+        "--length",
+        "64",
+        "--count",
+        "200",
+    ]);
+    for password in &all {
+        assert!(
+            !password.contains(['0', 'O', '1', 'l', 'I']),
+            "{}",
+            password
+        );
+    }
+}
+
+#[test]
+fn entropy_only_when_asked() {
+    assert!(pwgen(&[]).stderr.is_empty());
+    let output = pwgen(&[
+        "--no-lower",
+        "--no-upper",
+        "--length",
+        "10",
+        "--show-entropy",
+    ]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    /* 10 * log2(10) */
+    assert!(
+        stderr.contains("Entropy: 33.2 bits per password"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn impossible_requests_fail() {
+    for args in [
+        &["--no-lower", "--no-upper", "--no-digits"][..],
+        &["--length", "2"],
+        &["--length", "0"],
+        &["--count", "0"],
+    ] {
+        let output = Command::cargo_bin("rcli")
+            .unwrap()
+            .arg("pwgen")
+            .args(args)
+            .assert()
+            .failure()
+            .get_output()
+            .clone();
+        assert!(output.stdout.is_empty(), "{:?}", args);
+    }
+}
@@ -0,0 +1,98 @@
+/* rand: random bytes in each format, to standard output or a file */
+use assert_cmd::Command;
+use base64::Engine;
+
+/* One byte more than rand generates per step (RAND_CHUNK) */
+const OVER_ONE_CHUNK: usize = 48 * 1024 + 1;
+
+fn rand(args: &[&str]) -> Vec<u8> {
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .arg("rand")
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone()
+}
+
+#[test]
+fn byte_counts_per_format() {
+    for n in [1, 2, 3, 32, OVER_ONE_CHUNK] {
+        let bytes = n.to_string();
+        assert_eq!(rand(&["--bytes", &bytes]).len(), n);
+
+        let hex = String::from_utf8(rand(&["--bytes", &bytes, "--format", "hex"])).unwrap();
+        assert_eq!(hex.len(), 2 * n + 1);
+        assert!(hex.ends_with('\n'));
+        assert!(hex.trim_end().bytes().all(|b| b.is_ascii_hexdigit()));
+
+        let b64 = String::from_utf8(rand(&["--bytes", &bytes, "--format", "base64"])).unwrap();
+        assert_eq!(b64.len(), 4 * n.div_ceil(3) + 1);
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(b64.trim_end())
+            .unwrap();
+        assert_eq!(decoded.len(), n);
+    }
+    assert_ne!(rand(&["--bytes", "32"]), rand(&["--bytes", "32"]));
+}
+
+#[test]
+fn output_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("salt");
+    std::fs::write(&path, vec![0u8; 100_000]).unwrap();
+    let out = path.to_str().unwrap();
+
+    /* An existing file is truncated to the new size */
+    let size = OVER_ONE_CHUNK.to_string();
+    assert!(rand(&["--bytes", &size, "--out", out]).is_empty());
+    let written = std::fs::read(&path).unwrap();
+    assert_eq!(written.len(), OVER_ONE_CHUNK);
+    assert!(written.iter().any(|&b| b != 0));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |p: &std::path::Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        rand(&["--bytes", "16", "--out", out, "--sensitive"]);
+        assert_eq!(mode(&path), 0o600);
+
+        let fresh = dir.path().join("fresh");
+        rand(&[
+            "--bytes",
+            "16",
+            "--out",
+            fresh.to_str().unwrap(),
+            "--sensitive",
+        ]);
+        assert_eq!(mode(&fresh), 0o600);
+    }
+
+    /* --sensitive is about the --out file */
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["rand", "--bytes", "16", "--sensitive"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn reader_may_stop_early() {
+    use std::io::Read;
+    use std::process::Stdio;
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rcli"))
+        .args(["rand", "--bytes", "100000000000"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut head = [0u8; 16];
+    child.stdout.take().unwrap().read_exact(&mut head).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
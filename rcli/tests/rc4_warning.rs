@@ -0,0 +1,72 @@
+/* rc4 warns, and runs only when told RC4's weakness is understood */
+mod common;
+
+use common::{file_with, rcli};
+
+const KEY_HEX: &str = "0102030405";
+
+#[test]
+fn refuses_without_acknowledgment() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "old.bin", b"legacy data");
+    let output = rcli()
+        .args(["rc4", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("WARNING: RC4 is broken"), "{}", stderr);
+    assert!(stderr.contains("--insecure-ok"), "{}", stderr);
+    assert!(stderr.contains("rcli chacha"), "{}", stderr);
+    assert_eq!(std::fs::read(&file).unwrap(), b"legacy data");
+}
+
+#[test]
+fn insecure_ok_runs_with_the_warning_on_stderr() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "old.bin", b"legacy data");
+    let output = rcli()
+        .args(["rc4", "--insecure-ok", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("WARNING"), "{}", stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("WARNING: RC4 is broken"), "{}", stderr);
+    assert_ne!(std::fs::read(&file).unwrap(), b"legacy data");
+}
+
+#[test]
+fn environment_allows_it_for_automation() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "old.bin", b"legacy data");
+    rcli()
+        .args(["rc4", "--key-hex", KEY_HEX, "-f", &file])
+        .env("RCLI_ALLOW_RC4", "1")
+        .assert()
+        .success();
+    assert_ne!(std::fs::read(&file).unwrap(), b"legacy data");
+    rcli()
+        .args(["rc4", "--key-hex", KEY_HEX, "-f", &file])
+        .env("RCLI_ALLOW_RC4", "0")
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn warning_stays_off_a_data_stream() {
+    let output = rcli()
+        .args(["rc4", "--insecure-ok", "--key-hex", KEY_HEX, "-f", "-"])
+        .write_stdin("attack at dawn")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(output.stdout.len(), 14);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("WARNING: RC4 is broken"), "{}", stderr);
+}
@@ -0,0 +1,320 @@
+/* identity new, and chacha --recipient / --recipients-file / --identity */
+mod common;
+
+use assert_cmd::Command;
+use common::rcli;
+use std::path::{Path, PathBuf};
+
+fn path(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
+
+/* A new identity file in `dir`, and its recipient string */
+fn identity(dir: &Path, name: &str) -> (PathBuf, String) {
+    let key = dir.join(name);
+    let output = rcli()
+        .args(["identity", "new", "--out", path(&key)])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let recipient = String::from_utf8(output.stdout).unwrap().trim().to_string();
+    (key, recipient)
+}
+
+fn stderr_of(cmd: &mut Command, code: i32) -> String {
+    let output = cmd.assert().code(code).get_output().clone();
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn encrypt_to_recipient_and_decrypt_with_identity() {
+    let dir = tempfile::tempdir().unwrap();
+    let (key, recipient) = identity(dir.path(), "me.key");
+    assert!(recipient.starts_with("rcli1"), "{}", recipient);
+
+    let doc = dir.path().join("doc.pdf");
+    let plaintext: Vec<u8> = (0..200_000u32).map(|i| (i % 241) as u8).collect();
+    std::fs::write(&doc, &plaintext).unwrap();
+    for armor in [false, true] {
+        let sealed = dir.path().join("doc.pdf.rcli");
+        rcli()
+            .args(["chacha", "--encrypt", "--recipient", &recipient])
+            .args(["-f", path(&doc), "-o", path(&sealed), "--force"])
+            .args(armor.then_some("--armor"))
+            .assert()
+            .success();
+
+        let opened = dir.path().join("opened.pdf");
+        rcli()
+            .args(["chacha", "--decrypt", "--identity", path(&key)])
+            .args(["-f", path(&sealed), "-o", path(&opened), "--force"])
+            .assert()
+            .success();
+        assert_eq!(std::fs::read(&opened).unwrap(), plaintext);
+    }
+
+    /* Through a pipe, too */
+    let sealed = rcli()
+        .args(["chacha", "--encrypt", "--recipient", &recipient, "-f", "-"])
+        .write_stdin("piped secret")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    rcli()
+        .args(["chacha", "--decrypt", "--identity", path(&key), "-f", "-"])
+        .write_stdin(sealed)
+        .assert()
+        .success()
+        .stdout("piped secret");
+}
+
+#[test]
+fn another_identity_or_a_key_cannot_decrypt() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, recipient) = identity(dir.path(), "me.key");
+    let (other, _) = identity(dir.path(), "other.key");
+
+    let doc = dir.path().join("doc.txt");
+    std::fs::write(&doc, b"for my eyes only").unwrap();
+    rcli()
+        .args([
+            "chacha",
+            "--encrypt",
+            "--recipient",
+            &recipient,
+            "-f",
+            path(&doc),
+        ])
+        .assert()
+        .success();
+    let sealed = std::fs::read(&doc).unwrap();
+    let output = rcli()
+        .args(["inspect", "-f", path(&doc)])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let shown = String::from_utf8(output.stdout).unwrap();
+    assert!(shown.contains("key sealed to recipients"), "{}", shown);
+    assert!(shown.contains("Recipient slots: 1\n"), "{}", shown);
+
+    let opened = dir.path().join("opened.txt");
+    let stderr = stderr_of(
+        rcli()
+            .args(["chacha", "--decrypt", "--identity", path(&other)])
+            .args(["-f", path(&doc), "-o", path(&opened)]),
+        6,
+    );
+    assert!(
+        stderr.contains("none of the 1 recipient slots match this identity"),
+        "{}",
+        stderr
+    );
+    assert!(!opened.exists());
+
+    /* A symmetric key is told what the file needs instead */
+    let stderr = stderr_of(
+        rcli()
+            .args(["chacha", "--decrypt", "--key-hex", &"00".repeat(32)])
+            .args(["-f", path(&doc)]),
+        6,
+    );
+    assert!(stderr.contains("decrypt it with --identity"), "{}", stderr);
+    let stderr = stderr_of(
+        rcli()
+            .args(["chacha", "--decrypt", "--passphrase", "-f", path(&doc)])
+            .write_stdin("guess\n"),
+        6,
+    );
+    assert!(stderr.contains("decrypt it with --identity"), "{}", stderr);
+    assert_eq!(std::fs::read(&doc).unwrap(), sealed);
+
+    /* In place, the same refusal says where the backup is */
+    let stderr = stderr_of(
+        rcli()
+            .args(["chacha", "--decrypt", "--identity", path(&other)])
+            .args(["-f", path(&doc), "--backup"]),
+        6,
+    );
+    assert!(
+        stderr.contains(
+            "none of the 1 recipient slots match this identity (the original is intact in"
+        ),
+        "{}",
+        stderr
+    );
+    assert_eq!(std::fs::read(&doc).unwrap(), sealed);
+
+    /* And an identity is no use on a file encrypted under a key */
+    let plain = dir.path().join("plain.txt");
+    std::fs::write(&plain, b"shared secret").unwrap();
+    rcli()
+        .args(["chacha", "--encrypt", "--key-hex", &"11".repeat(32)])
+        .args(["-f", path(&plain)])
+        .assert()
+        .success();
+    let stderr = stderr_of(
+        rcli()
+            .args(["chacha", "--decrypt", "--identity", path(&other)])
+            .args(["-f", path(&plain)]),
+        6,
+    );
+    assert!(
+        stderr.contains("was not encrypted to a recipient"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn every_recipient_can_decrypt() {
+    let dir = tempfile::tempdir().unwrap();
+    let people: Vec<(PathBuf, String)> = ["alice", "bob", "carol", "mallory"]
+        .iter()
+        .map(|name| identity(dir.path(), name))
+        .collect();
+    let doc = dir.path().join("plan.txt");
+    let sealed = dir.path().join("plan.txt.rcli");
+    std::fs::write(&doc, b"meet at noon").unwrap();
+
+    /* Three times --recipient, or the same three from a file */
+    let list = dir.path().join("team.txt");
+    std::fs::write(
+        &list,
+        format!(
+            "# the team\n{}\n\n  {}  \n# carol joined in March\n\n{}\n",
+            people[0].1, people[1].1, people[2].1
+        ),
+    )
+    .unwrap();
+    let by_flag = ["--recipient", &people[0].1, "--recipient", &people[1].1]
+        .into_iter()
+        .chain(["--recipient", &people[2].1])
+        .collect::<Vec<_>>();
+    let by_file = vec!["--recipients-file", path(&list)];
+    for recipients in [by_flag, by_file] {
+        rcli()
+            .args(["chacha", "--encrypt"])
+            .args(&recipients)
+            .args(["-f", path(&doc), "-o", path(&sealed), "--force"])
+            .assert()
+            .success();
+
+        let output = rcli()
+            .args(["inspect", "-f", path(&sealed), "--json"])
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json["recipient_slots"].as_array().unwrap().len(), 3);
+
+        let opened = dir.path().join("opened.txt");
+        for (key, _) in &people[..3] {
+            rcli()
+                .args(["chacha", "--decrypt", "--identity", path(key)])
+                .args(["-f", path(&sealed), "-o", path(&opened), "--force"])
+                .assert()
+                .success();
+            assert_eq!(std::fs::read(&opened).unwrap(), b"meet at noon");
+        }
+        let stderr = stderr_of(
+            rcli()
+                .args(["chacha", "--decrypt", "--identity", path(&people[3].0)])
+                .args(["-f", path(&sealed), "-o", path(&opened), "--force"]),
+            6,
+        );
+        assert!(
+            stderr.contains("none of the 3 recipient slots match this identity"),
+            "{}",
+            stderr
+        );
+    }
+
+    /* A bad line is named, and nothing is encrypted */
+    std::fs::write(&list, format!("{}\n# next\nrcli1oops\n", people[0].1)).unwrap();
+    let stderr = stderr_of(
+        rcli()
+            .args(["chacha", "--encrypt", "--recipients-file", path(&list)])
+            .args(["-f", path(&doc)]),
+        2,
+    );
+    assert!(stderr.contains("team.txt line 3: recipient"), "{}", stderr);
+    assert_eq!(std::fs::read(&doc).unwrap(), b"meet at noon");
+}
+
+#[test]
+fn malformed_recipients_are_usage_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let (key, recipient) = identity(dir.path(), "me.key");
+    let secret_line = std::fs::read_to_string(&key)
+        .unwrap()
+        .lines()
+        .nth(1)
+        .unwrap()
+        .to_string();
+    let typo = recipient.replacen('A', "B", 1).replacen('Q', "R", 1);
+    let missing = dir.path().join("missing");
+
+    for (bad, message) in [
+        (&recipient[..recipient.len() - 1], "cut off"),
+        (typo.as_str(), "checksum does not match"),
+        (
+            "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p",
+            "age recipients",
+        ),
+        ("npub1xyz", "must start with \"rcli1\""),
+        (secret_line.as_str(), "that is an identity"),
+    ] {
+        /* Rejected before the (missing) input file is looked at */
+        let stderr = stderr_of(
+            rcli()
+                .args(["chacha", "--encrypt", "--recipient", bad])
+                .args(["-f", path(&missing)]),
+            2,
+        );
+        assert!(stderr.contains(message), "{}: {}", message, stderr);
+        assert!(!stderr.contains("No such file"), "{}", stderr);
+    }
+
+    /* --recipient encrypts and --identity decrypts, nothing else */
+    rcli()
+        .args(["chacha", "--decrypt", "--recipient", &recipient])
+        .args(["-f", path(&missing)])
+        .assert()
+        .code(2);
+    rcli()
+        .args(["chacha", "--encrypt", "--identity", path(&key)])
+        .args(["-f", path(&missing)])
+        .assert()
+        .code(2);
+}
+
+#[cfg(unix)]
+#[test]
+fn identity_file_is_owner_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (key, recipient) = identity(dir.path(), "me.key");
+    let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(&key), 0o600);
+    let text = std::fs::read_to_string(&key).unwrap();
+    assert!(text.starts_with(&format!("# recipient: {}\n", recipient)));
+
+    /* An existing identity is kept unless --force */
+    rcli()
+        .args(["identity", "new", "--out", path(&key)])
+        .assert()
+        .failure();
+    assert_eq!(std::fs::read_to_string(&key).unwrap(), text);
+    rcli()
+        .args(["identity", "new", "--out", path(&key), "--force"])
+        .assert()
+        .success();
+    assert_ne!(std::fs::read_to_string(&key).unwrap(), text);
+    assert_eq!(mode(&key), 0o600);
+}
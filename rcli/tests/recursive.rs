@@ -0,0 +1,304 @@
+/* chacha -r over a directory tree */
+use assert_cmd::Command;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const FILES: [&str; 4] = ["top.txt", "a/one.txt", "a/b/two.bin", "c/three.txt"];
+
+fn chacha(root: &Path, args: &[&str]) -> Command {
+    let mut cmd = Command::cargo_bin("rcli").unwrap();
+    cmd.args([
+        "chacha",
+        "-r",
+        "-f",
+        root.to_str().unwrap(),
+        "--key-hex",
+        KEY_HEX,
+    ]);
+    cmd.args(args);
+    cmd
+}
+
+fn make_tree(root: &Path) {
+    for rel in FILES {
+        let path = root.join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, rel.repeat(100)).unwrap();
+    }
+}
+
+fn stdout_of(cmd: &mut Command) -> String {
+    String::from_utf8(cmd.assert().success().get_output().stdout.clone()).unwrap()
+}
+
+#[test]
+fn nested_tree_round_trips_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    make_tree(dir.path());
+
+    let out = stdout_of(&mut chacha(dir.path(), &["--encrypt"]));
+    assert!(
+        out.ends_with("4 encrypted, 0 skipped, 0 failed\n"),
+        "{}",
+        out
+    );
+    let mut prefixes = Vec::new();
+    for rel in FILES {
+        let sealed = std::fs::read(dir.path().join(rel)).unwrap();
+        assert!(sealed.starts_with(b"RCLI"), "{}", rel);
+        prefixes.push(sealed[..32].to_vec());
+    }
+    /* fresh nonce prefix per file */
+    prefixes.sort();
+    prefixes.dedup();
+    assert_eq!(prefixes.len(), FILES.len());
+
+    let out = stdout_of(&mut chacha(dir.path(), &["--decrypt"]));
+    assert!(
+        out.ends_with("4 decrypted, 0 skipped, 0 failed\n"),
+        "{}",
+        out
+    );
+    for rel in FILES {
+        assert_eq!(
+            std::fs::read(dir.path().join(rel)).unwrap(),
+            rel.repeat(100).as_bytes()
+        );
+    }
+}
+
+#[test]
+fn output_dir_mirrors_the_tree() {
+    let dir = tempfile::tempdir().unwrap();
+    let (src, enc, dec) = (
+        dir.path().join("src"),
+        dir.path().join("enc"),
+        dir.path().join("dec"),
+    );
+    make_tree(&src);
+    let enc_arg = enc.to_str().unwrap();
+    let dec_arg = dec.to_str().unwrap();
+
+    chacha(&src, &["--encrypt", "--output-dir", enc_arg])
+        .assert()
+        .success();
+    chacha(&enc, &["--decrypt", "--output-dir", dec_arg])
+        .assert()
+        .success();
+    for rel in FILES {
+        let original = std::fs::read(src.join(rel)).unwrap();
+        assert_eq!(original, rel.repeat(100).as_bytes());
+        assert_ne!(std::fs::read(enc.join(rel)).unwrap(), original);
+        assert_eq!(std::fs::read(dec.join(rel)).unwrap(), original);
+    }
+
+    /* existing outputs need --force */
+    chacha(&src, &["--encrypt", "--output-dir", enc_arg])
+        .assert()
+        .failure();
+    chacha(&src, &["--encrypt", "--output-dir", enc_arg, "--force"])
+        .assert()
+        .success();
+
+    let inside = src.join("out");
+    chacha(
+        &src,
+        &["--encrypt", "--output-dir", inside.to_str().unwrap()],
+    )
+    .assert()
+    .failure();
+}
+
+#[cfg(unix)]
+#[test]
+fn non_regular_files_are_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    make_tree(dir.path());
+    let _socket = std::os::unix::net::UnixListener::bind(dir.path().join("a/sock")).unwrap();
+    std::os::unix::fs::symlink("top.txt", dir.path().join("link")).unwrap();
+
+    let output = chacha(dir.path(), &["--encrypt"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stdout.ends_with("4 encrypted, 2 skipped, 0 failed\n"),
+        "{}",
+        stdout
+    );
+    assert!(stderr.contains("sock: not a regular file"), "{}", stderr);
+    assert!(std::fs::symlink_metadata(dir.path().join("link"))
+        .unwrap()
+        .file_type()
+        .is_symlink());
+}
+
+#[test]
+fn decrypt_skips_files_without_the_magic() {
+    let dir = tempfile::tempdir().unwrap();
+    make_tree(dir.path());
+    chacha(dir.path(), &["--encrypt"]).assert().success();
+    std::fs::write(dir.path().join("a/plain.txt"), b"never encrypted").unwrap();
+
+    let output = chacha(dir.path(), &["--decrypt"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stdout.ends_with("4 decrypted, 1 skipped, 0 failed\n"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stderr.contains("plain.txt: not an rcli encrypted file"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        std::fs::read(dir.path().join("a/plain.txt")).unwrap(),
+        b"never encrypted"
+    );
+}
+
+#[test]
+fn keep_going_continues_past_a_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    make_tree(dir.path());
+    chacha(dir.path(), &["--encrypt"]).assert().success();
+    /* "a/b/two.bin" sorts first; corrupt it */
+    let bad = dir.path().join("a/b/two.bin");
+    let mut sealed = std::fs::read(&bad).unwrap();
+    let last = sealed.len() - 1;
+    sealed[last] ^= 1;
+    std::fs::write(&bad, &sealed).unwrap();
+
+    /* without --keep-going the walk stops at the bad file */
+    let output = chacha(dir.path(), &["--decrypt"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.ends_with("0 decrypted, 0 skipped, 1 failed\n"),
+        "{}",
+        stdout
+    );
+    assert!(std::fs::read(dir.path().join("top.txt"))
+        .unwrap()
+        .starts_with(b"RCLI"));
+
+    let output = chacha(dir.path(), &["--decrypt", "--keep-going"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stdout.ends_with("3 decrypted, 0 skipped, 1 failed\n"),
+        "{}",
+        stdout
+    );
+    assert!(stderr.contains("two.bin: decryption failed"), "{}", stderr);
+    assert_eq!(std::fs::read(&bad).unwrap(), sealed);
+    assert_eq!(
+        std::fs::read(dir.path().join("top.txt")).unwrap(),
+        "top.txt".repeat(100).as_bytes()
+    );
+}
+
+#[test]
+fn directories_need_recursive() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::cargo_bin("rcli")
+        .unwrap()
+        .args([
+            "chacha",
+            "-f",
+            dir.path().to_str().unwrap(),
+            "--key-hex",
+            KEY_HEX,
+            "--encrypt",
+        ])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("use -r"), "{}", stderr);
+}
+
+#[test]
+fn excluded_paths_are_left_untouched() {
+    let dir = tempfile::tempdir().unwrap();
+    make_tree(dir.path());
+    let untouched = [
+        ".git/HEAD",
+        ".git/objects/ab/cdef",
+        "a/scratch.tmp",
+        "notes.tmp",
+    ];
+    for rel in untouched {
+        let path = dir.path().join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, rel).unwrap();
+    }
+
+    let out = stdout_of(&mut chacha(
+        dir.path(),
+        &["--encrypt", "--exclude", ".git/", "--exclude", "*.tmp"],
+    ));
+    assert!(
+        out.ends_with("4 encrypted, 0 skipped, 0 failed\n"),
+        "{}",
+        out
+    );
+    for rel in untouched {
+        assert_eq!(std::fs::read(dir.path().join(rel)).unwrap(), rel.as_bytes());
+    }
+    for rel in FILES {
+        assert!(std::fs::read(dir.path().join(rel))
+            .unwrap()
+            .starts_with(b"RCLI"));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn excluded_directories_are_never_opened() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    make_tree(dir.path());
+    let locked = dir.path().join("node_modules");
+    std::fs::create_dir_all(locked.join("pkg")).unwrap();
+    /* a special file inside would be counted as skipped if it were seen */
+    let _socket = std::os::unix::net::UnixListener::bind(locked.join("pkg/sock")).unwrap();
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+    /* root can list it regardless */
+    let denied = std::fs::read_dir(&locked).is_err();
+
+    let out = stdout_of(&mut chacha(
+        dir.path(),
+        &["--encrypt", "--exclude", "node_modules/"],
+    ));
+    assert!(
+        out.ends_with("4 encrypted, 0 skipped, 0 failed\n"),
+        "{}",
+        out
+    );
+
+    if denied {
+        chacha(dir.path(), &["--decrypt", "--keep-going"])
+            .assert()
+            .failure();
+    }
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
@@ -0,0 +1,274 @@
+/* rekey: encrypted files moved to a new key or passphrase in place */
+mod common;
+
+use common::{file_with, rcli};
+use std::path::Path;
+
+const OLD_KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const NEW_KEY_HEX: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+/* Key files for both keys in `dir`: (old, new) */
+fn key_files(dir: &Path) -> (String, String) {
+    (
+        file_with(dir, "old.key", OLD_KEY_HEX.as_bytes()),
+        file_with(dir, "new.key", NEW_KEY_HEX.as_bytes()),
+    )
+}
+
+fn encrypt(file: &str, key_hex: &str, args: &[&str]) {
+    rcli()
+        .args(["encrypt", "--file", file, "--key-hex", key_hex])
+        .args(args)
+        .assert()
+        .success();
+}
+
+/* Whether `file` decrypts under `key_hex`, leaving the file alone */
+fn opens_with(file: &str, key_hex: &str) -> Option<Vec<u8>> {
+    let output = rcli()
+        .args(["decrypt", "--file", "-", "--key-hex", key_hex])
+        .pipe_stdin(file)
+        .unwrap()
+        .output()
+        .unwrap();
+    output.status.success().then_some(output.stdout)
+}
+
+#[test]
+fn rekeyed_file_opens_only_under_the_new_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let (old, new) = key_files(dir.path());
+    let plaintext = vec![0x5a; 100_000];
+    let file = file_with(dir.path(), "data.bin", &plaintext);
+    encrypt(&file, OLD_KEY_HEX, &["--chunk-size", "4K"]);
+    let before = std::fs::read(&file).unwrap();
+
+    rcli()
+        .args([
+            "rekey",
+            "-f",
+            &file,
+            "--old-key-file",
+            &old,
+            "--new-key-file",
+            &new,
+        ])
+        .assert()
+        .success()
+        .stdout(format!("Rekeyed {}\n", file));
+
+    assert_ne!(std::fs::read(&file).unwrap(), before);
+    assert_eq!(opens_with(&file, NEW_KEY_HEX), Some(plaintext));
+    assert_eq!(opens_with(&file, OLD_KEY_HEX), None);
+    /* the header keeps its chunk size */
+    let output = rcli()
+        .args(["inspect", "--json", "-f", &file])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["chunk_size"], 4096);
+}
+
+#[test]
+fn corrupted_source_is_left_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let (old, new) = key_files(dir.path());
+    let file = file_with(dir.path(), "data.bin", &[7u8; 50_000]);
+    encrypt(&file, OLD_KEY_HEX, &["--chunk-size", "4K"]);
+    let mut damaged = std::fs::read(&file).unwrap();
+    let last = damaged.len() - 10;
+    damaged[last] ^= 1;
+    std::fs::write(&file, &damaged).unwrap();
+
+    let output = rcli()
+        .args([
+            "rekey",
+            "-f",
+            &file,
+            "--old-key-file",
+            &old,
+            "--new-key-file",
+            &new,
+        ])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error"), "{}", stderr);
+    assert_eq!(std::fs::read(&file).unwrap(), damaged);
+    let names: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names.len(), 3, "a temporary file was left: {:?}", names);
+}
+
+#[test]
+fn wrong_old_key_changes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, new) = key_files(dir.path());
+    let file = file_with(dir.path(), "data.txt", b"under the old key");
+    encrypt(&file, OLD_KEY_HEX, &[]);
+    let before = std::fs::read(&file).unwrap();
+    rcli()
+        .args([
+            "rekey",
+            "-f",
+            &file,
+            "--old-key-file",
+            &new,
+            "--new-key-file",
+            &new,
+        ])
+        .assert()
+        /* a key file has no key check value, so the first chunk tells */
+        .code(4);
+    assert_eq!(std::fs::read(&file).unwrap(), before);
+}
+
+#[test]
+fn passphrase_to_key_and_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, new) = key_files(dir.path());
+    let pass = file_with(dir.path(), "pass.txt", b"correct horse battery staple\n");
+    let file = file_with(dir.path(), "notes.txt", b"from a passphrase to a key");
+    rcli()
+        .args(["encrypt", "--file", &file, "--passphrase-file", &pass])
+        .assert()
+        .success();
+
+    rcli()
+        .args([
+            "rekey",
+            "-f",
+            &file,
+            "--old-passphrase-file",
+            &pass,
+            "--new-key-file",
+            &new,
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        opens_with(&file, NEW_KEY_HEX).as_deref(),
+        Some(&b"from a passphrase to a key"[..])
+    );
+
+    /* and back, to a different passphrase */
+    let other = file_with(dir.path(), "other.txt", b"a new passphrase\n");
+    rcli()
+        .args([
+            "rekey",
+            "-f",
+            &file,
+            "--old-key-file",
+            &new,
+            "--new-passphrase-file",
+            &other,
+        ])
+        .assert()
+        .success();
+    assert_eq!(opens_with(&file, NEW_KEY_HEX), None);
+    rcli()
+        .args(["decrypt", "--file", &file, "--passphrase-file", &other])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), b"from a passphrase to a key");
+}
+
+#[test]
+fn wrong_old_passphrase_is_reported_as_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, new) = key_files(dir.path());
+    let pass = file_with(dir.path(), "pass.txt", b"right\n");
+    let wrong = file_with(dir.path(), "wrong.txt", b"wrong\n");
+    let file = file_with(dir.path(), "notes.txt", b"guarded");
+    rcli()
+        .args(["encrypt", "--file", &file, "--passphrase-file", &pass])
+        .assert()
+        .success();
+    let output = rcli()
+        .args([
+            "rekey",
+            "-f",
+            &file,
+            "--old-passphrase-file",
+            &wrong,
+            "--new-key-file",
+            &new,
+        ])
+        .assert()
+        .code(6)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("incorrect passphrase"), "{}", stderr);
+}
+
+#[test]
+fn armored_files_stay_armored() {
+    let dir = tempfile::tempdir().unwrap();
+    let (old, new) = key_files(dir.path());
+    let file = file_with(dir.path(), "mail.txt", b"pasted into mail");
+    encrypt(&file, OLD_KEY_HEX, &["--armor"]);
+    rcli()
+        .args([
+            "rekey",
+            "-f",
+            &file,
+            "--old-key-file",
+            &old,
+            "--new-key-file",
+            &new,
+        ])
+        .assert()
+        .success();
+    let text = std::fs::read_to_string(&file).unwrap();
+    assert!(text.starts_with("-----BEGIN"), "{}", text);
+    assert_eq!(
+        opens_with(&file, NEW_KEY_HEX).as_deref(),
+        Some(&b"pasted into mail"[..])
+    );
+}
+
+#[test]
+fn several_files_and_trees() {
+    let dir = tempfile::tempdir().unwrap();
+    let (old, new) = key_files(dir.path());
+    let tree = dir.path().join("tree");
+    std::fs::create_dir_all(tree.join("sub")).unwrap();
+    let a = file_with(&tree, "a.txt", b"first");
+    let b = file_with(&tree.join("sub"), "b.txt", b"second");
+    encrypt(&a, OLD_KEY_HEX, &[]);
+    encrypt(&b, OLD_KEY_HEX, &[]);
+    let plain = file_with(&tree, "readme.txt", b"never encrypted");
+
+    rcli()
+        .args(["rekey", "-r", "-f"])
+        .arg(&tree)
+        .args(["--old-key-file", &old, "--new-key-file", &new])
+        .assert()
+        .success();
+    assert_eq!(opens_with(&a, NEW_KEY_HEX).as_deref(), Some(&b"first"[..]));
+    assert_eq!(opens_with(&b, NEW_KEY_HEX).as_deref(), Some(&b"second"[..]));
+    assert_eq!(std::fs::read(&plain).unwrap(), b"never encrypted");
+
+    /* and back, naming both files */
+    rcli()
+        .args([
+            "rekey",
+            "-f",
+            &a,
+            "-f",
+            &b,
+            "--old-key-file",
+            &new,
+            "--new-key-file",
+            &old,
+        ])
+        .assert()
+        .success();
+    assert_eq!(opens_with(&a, OLD_KEY_HEX).as_deref(), Some(&b"first"[..]));
+    assert_eq!(opens_with(&b, OLD_KEY_HEX).as_deref(), Some(&b"second"[..]));
+}
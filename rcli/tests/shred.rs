@@ -0,0 +1,135 @@
+/* --shred: the plaintext goes only once its encrypted copy is written */
+mod common;
+
+use assert_cmd::Command;
+use common::rcli;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const PATTERN: &[u8] = b"SALARY-TABLE-2026 ";
+
+fn plaintext(path: &Path) -> String {
+    std::fs::write(path, PATTERN.repeat(5000)).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn encrypt(file: &str, out: &str) -> Command {
+    let mut cmd = rcli();
+    cmd.args([
+        "encrypt",
+        "--key-hex",
+        KEY_HEX,
+        "-f",
+        file,
+        "-o",
+        out,
+        "--shred",
+    ]);
+    cmd
+}
+
+#[test]
+fn original_is_gone_once_encrypted() {
+    let dir = tempfile::tempdir().unwrap();
+    let (plain, enc, dec) = (
+        dir.path().join("payroll.csv"),
+        dir.path().join("payroll.enc"),
+        dir.path().join("payroll.dec"),
+    );
+    let file = plaintext(&plain);
+    let output = encrypt(&file, enc.to_str().unwrap())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("copy-on-write"), "{}", stderr);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Shredded"), "{}", stdout);
+
+    assert!(!plain.exists());
+    for entry in std::fs::read_dir(dir.path()).unwrap() {
+        let contents = std::fs::read(entry.unwrap().path()).unwrap();
+        assert!(!contents.windows(PATTERN.len()).any(|w| w == PATTERN));
+    }
+    rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "-f", enc.to_str().unwrap()])
+        .args(["-o", dec.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&dec).unwrap(), PATTERN.repeat(5000));
+}
+
+#[test]
+fn failed_encryption_shreds_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = dir.path().join("plain");
+    let file = plaintext(&plain);
+
+    /* The output already exists and --force was not given */
+    let taken = dir.path().join("taken");
+    std::fs::write(&taken, b"someone else's").unwrap();
+    encrypt(&file, taken.to_str().unwrap()).assert().code(3);
+    assert_eq!(std::fs::read(&plain).unwrap(), PATTERN.repeat(5000));
+
+    /* The output is the input itself, under another name */
+    #[cfg(unix)]
+    {
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&plain, &link).unwrap();
+        encrypt(&file, link.to_str().unwrap())
+            .arg("--force")
+            .assert()
+            .failure();
+        assert_eq!(std::fs::read(&plain).unwrap(), PATTERN.repeat(5000));
+    }
+
+    /* Already encrypted, so refused */
+    let enc = dir.path().join("plain.enc");
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .success();
+    let sealed = std::fs::read(&plain).unwrap();
+    encrypt(&file, enc.to_str().unwrap()).assert().code(5);
+    assert_eq!(std::fs::read(&plain).unwrap(), sealed);
+    assert!(!enc.exists());
+}
+
+#[test]
+fn shred_needs_a_separate_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = dir.path().join("plain");
+    let file = plaintext(&plain);
+    for args in [
+        vec!["encrypt", "-f", &file, "--shred"],
+        vec!["encrypt", "-f", "-", "-o", "out", "--shred"],
+        vec!["chacha", "--decrypt", "-f", &file, "-o", "out", "--shred"],
+    ] {
+        rcli()
+            .args(&args)
+            .args(["--key-hex", KEY_HEX])
+            .assert()
+            .code(2);
+    }
+    assert_eq!(std::fs::read(&plain).unwrap(), PATTERN.repeat(5000));
+}
+
+#[test]
+fn recursive_shreds_each_file_written_elsewhere() {
+    let dir = tempfile::tempdir().unwrap();
+    let (tree, out) = (dir.path().join("tree"), dir.path().join("out"));
+    std::fs::create_dir_all(tree.join("sub")).unwrap();
+    plaintext(&tree.join("a"));
+    plaintext(&tree.join("sub/b"));
+    rcli()
+        .args(["encrypt", "-r", "--key-hex", KEY_HEX, "--shred"])
+        .args(["-f", tree.to_str().unwrap()])
+        .args(["--output-dir", out.to_str().unwrap()])
+        .assert()
+        .success();
+    assert!(!tree.join("a").exists());
+    assert!(!tree.join("sub/b").exists());
+    assert!(out.join("a").exists());
+    assert!(out.join("sub/b").exists());
+}
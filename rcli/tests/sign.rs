@@ -0,0 +1,166 @@
+/* keypair, sign and verify-sig */
+mod common;
+
+use assert_cmd::Command;
+use common::rcli;
+use std::path::{Path, PathBuf};
+
+fn path(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
+
+/* release.key and release.pub in `dir`, and an artifact to sign */
+fn setup(dir: &Path) -> (PathBuf, PathBuf, PathBuf) {
+    let prefix = dir.join("release");
+    rcli()
+        .args(["keypair", "--out-prefix", path(&prefix)])
+        .assert()
+        .success();
+    let artifact = dir.join("artifact.tar.gz");
+    let data: Vec<u8> = (0..300_000u32).map(|i| (i % 253) as u8).collect();
+    std::fs::write(&artifact, data).unwrap();
+    (dir.join("release.key"), dir.join("release.pub"), artifact)
+}
+
+fn sign(key: &Path, artifact: &Path, extra: &[&str]) {
+    rcli()
+        .args(["sign", "--key", path(key), "-f", path(artifact)])
+        .args(extra)
+        .assert()
+        .success();
+}
+
+fn verify_sig(public: &Path, artifact: &Path) -> Command {
+    let mut cmd = rcli();
+    cmd.args(["verify-sig", "--pub", path(public), "-f", path(artifact)]);
+    cmd
+}
+
+fn failure_message(mut cmd: Command, status: i32) -> String {
+    let output = cmd.assert().code(status).get_output().clone();
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn keypair_sign_verify_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let (key, public, artifact) = setup(dir.path());
+    for prehash in ["sha512", "blake3"] {
+        sign(&key, &artifact, &["--prehash", prehash, "--force"]);
+        let sig = std::fs::read_to_string(dir.path().join("artifact.tar.gz.sig")).unwrap();
+        assert!(sig.contains(&format!("prehash: {}\n", prehash)), "{}", sig);
+
+        let output = verify_sig(&public, &artifact)
+            .args(["--sig", path(&dir.path().join("artifact.tar.gz.sig"))])
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .starts_with("Good signature on"));
+    }
+
+    /* Standard input, with the signature somewhere else */
+    let sig = dir.path().join("piped.sig");
+    rcli()
+        .args(["sign", "--key", path(&key), "-f", "-", "--out", path(&sig)])
+        .write_stdin(std::fs::read(&artifact).unwrap())
+        .assert()
+        .success();
+    verify_sig(&public, &artifact)
+        .args(["--sig", path(&sig)])
+        .assert()
+        .success();
+}
+
+#[test]
+fn tampered_artifact_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let (key, public, artifact) = setup(dir.path());
+    sign(&key, &artifact, &[]);
+
+    let mut data = std::fs::read(&artifact).unwrap();
+    data[150_000] ^= 0x01;
+    std::fs::write(&artifact, &data).unwrap();
+    let stderr = failure_message(verify_sig(&public, &artifact), 4);
+    assert!(stderr.contains("BAD signature"), "{}", stderr);
+
+    /* Another key pair's public key */
+    data[150_000] ^= 0x01;
+    std::fs::write(&artifact, &data).unwrap();
+    let other = dir.path().join("other");
+    rcli()
+        .args(["keypair", "--out-prefix", path(&other)])
+        .assert()
+        .success();
+    let stderr = failure_message(verify_sig(&dir.path().join("other.pub"), &artifact), 6);
+    assert!(stderr.contains("signed by a different key"), "{}", stderr);
+}
+
+#[test]
+fn tampered_signature_file_is_parse_or_verify_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let (key, public, artifact) = setup(dir.path());
+    sign(&key, &artifact, &[]);
+    let sig_path = dir.path().join("artifact.tar.gz.sig");
+    let original = std::fs::read_to_string(&sig_path).unwrap();
+
+    /* Intact layout, altered signature bytes: verification fails */
+    let at = original.find("signature: ").unwrap() + "signature: ".len();
+    let mut altered = original.clone().into_bytes();
+    altered[at] = if altered[at] == b'A' { b'B' } else { b'A' };
+    std::fs::write(&sig_path, &altered).unwrap();
+    let stderr = failure_message(verify_sig(&public, &artifact), 4);
+    assert!(stderr.contains("BAD signature"), "{}", stderr);
+
+    /* Broken layout: the file does not parse */
+    for broken in [
+        original.replace("prehash: ", "prehash:"),
+        original.replace("signature: ", "signature: !!"),
+        original.lines().take(2).collect::<Vec<_>>().join("\n"),
+        "not a signature\n".to_string(),
+    ] {
+        std::fs::write(&sig_path, &broken).unwrap();
+        let stderr = failure_message(verify_sig(&public, &artifact), 5);
+        assert!(
+            stderr.contains("is damaged or not an rcli signature"),
+            "{}",
+            stderr
+        );
+        assert!(!stderr.contains("BAD signature"), "{}", stderr);
+    }
+
+    std::fs::remove_file(&sig_path).unwrap();
+    let stderr = failure_message(verify_sig(&public, &artifact), 3);
+    assert!(stderr.contains("cannot read signature"), "{}", stderr);
+}
+
+#[cfg(unix)]
+#[test]
+fn private_key_is_owner_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (key, public, _) = setup(dir.path());
+    let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(&key), 0o600);
+    assert!(std::fs::read_to_string(&public)
+        .unwrap()
+        .starts_with("rcli ed25519 public key\n"));
+
+    /* Existing keys are kept unless --force */
+    let before = std::fs::read(&key).unwrap();
+    rcli()
+        .args(["keypair", "--out-prefix", path(&dir.path().join("release"))])
+        .assert()
+        .failure();
+    assert_eq!(std::fs::read(&key).unwrap(), before);
+    rcli()
+        .args(["keypair", "--out-prefix", path(&dir.path().join("release"))])
+        .arg("--force")
+        .assert()
+        .success();
+    assert_ne!(std::fs::read(&key).unwrap(), before);
+    assert_eq!(mode(&key), 0o600);
+}
@@ -0,0 +1,201 @@
+/* --split and join: encrypted output in parts of a capped size */
+mod common;
+
+use common::{file_with, rcli};
+use std::path::{Path, PathBuf};
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn contents() -> Vec<u8> {
+    (0..60_000u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect()
+}
+
+/* Encrypt `file` to `out` in parts of `size`, returning the parts */
+fn split(file: &str, out: &Path, size: &str) -> Vec<PathBuf> {
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", file])
+        .args(["--split", size, "-o"])
+        .arg(out)
+        .assert()
+        .success();
+    (0..)
+        .map(|i| PathBuf::from(format!("{}.{:03}", out.display(), i)))
+        .take_while(|part| part.exists())
+        .collect()
+}
+
+#[test]
+fn split_join_decrypt_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "disk.img", &contents());
+    let out = dir.path().join("disk.img.enc");
+    let output = rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .args(["--split", "8K", "-o"])
+        .arg(&out)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let parts: Vec<PathBuf> = (0..)
+        .map(|i| PathBuf::from(format!("{}.{:03}", out.display(), i)))
+        .take_while(|part| part.exists())
+        .collect();
+    assert_eq!(parts.len(), 8);
+    assert!(!out.exists());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "Encrypted {} -> {} to {}\n",
+            file,
+            parts[0].display(),
+            parts[7].display()
+        )
+    );
+
+    /* Every part is exactly the size asked for, but the last */
+    let len = |path: &PathBuf| std::fs::metadata(path).unwrap().len();
+    for part in &parts[..7] {
+        assert_eq!(len(part), 8192);
+    }
+    assert!(len(&parts[7]) > 0 && len(&parts[7]) <= 8192);
+
+    /* The order given does not matter */
+    let joined = dir.path().join("joined.enc");
+    let output = rcli()
+        .arg("join")
+        .args(parts.iter().rev())
+        .arg("--out")
+        .arg(&joined)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("Joined 8 parts -> {}\n", joined.display())
+    );
+    let total: u64 = parts.iter().map(len).sum();
+    assert_eq!(len(&joined), total - 8 * 21);
+
+    let plain = dir.path().join("disk.out");
+    rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "-f"])
+        .arg(&joined)
+        .arg("-o")
+        .arg(&plain)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&plain).unwrap(), contents());
+}
+
+#[test]
+fn missing_middle_part_is_named() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "disk.img", &contents());
+    let out = dir.path().join("disk.img.enc");
+    let mut parts = split(&file, &out, "16K");
+    assert_eq!(parts.len(), 4);
+    let gone = parts.remove(1);
+    std::fs::remove_file(&gone).unwrap();
+
+    let joined = dir.path().join("joined.enc");
+    let output = rcli()
+        .arg("join")
+        .args(&parts)
+        .arg("--out")
+        .arg(&joined)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("part 001 of 4 is missing"), "{}", stderr);
+    assert!(stderr.contains(&gone.display().to_string()), "{}", stderr);
+    assert!(!joined.exists());
+}
+
+#[test]
+fn damaged_sets_are_refused_before_writing() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "disk.img", &contents());
+    let parts = split(&file, &dir.path().join("a.enc"), "16K");
+    let other = split(&file, &dir.path().join("b.enc"), "16K");
+    let joined = dir.path().join("joined.enc");
+    let join = |parts: &[&PathBuf]| {
+        let output = rcli()
+            .arg("join")
+            .args(parts)
+            .arg("--out")
+            .arg(&joined)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(5));
+        assert!(!joined.exists());
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    let stderr = join(&[&parts[0], &parts[1], &parts[1], &parts[2], &parts[3]]);
+    assert!(stderr.contains("part 001 is given twice"), "{}", stderr);
+    let stderr = join(&[&parts[0], &other[1], &parts[2], &parts[3]]);
+    assert!(stderr.contains("different split files"), "{}", stderr);
+
+    let bytes = std::fs::read(&parts[2]).unwrap();
+    std::fs::write(&parts[2], &bytes[..bytes.len() - 100]).unwrap();
+    let stderr = join(&[&parts[0], &parts[1], &parts[2], &parts[3]]);
+    assert!(
+        stderr.contains(&format!("{}: part 002 is not the size", parts[2].display())),
+        "{}",
+        stderr
+    );
+    let stderr = join(&[&parts[0], &PathBuf::from(&file)]);
+    assert!(stderr.contains("not a part of a split file"), "{}", stderr);
+}
+
+#[test]
+fn parts_are_not_decrypted_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "disk.img", &contents());
+    let parts = split(&file, &dir.path().join("disk.img.enc"), "16K");
+    let output = rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "-f"])
+        .arg(&parts[0])
+        .arg("-o")
+        .arg(dir.path().join("out"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rcli join"), "{}", stderr);
+}
+
+#[test]
+fn split_needs_somewhere_to_write() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "disk.img", b"in place");
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .args(["--split", "1M"])
+        .assert()
+        .code(2);
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", "-", "--split", "1M"])
+        .write_stdin("piped")
+        .assert()
+        .code(2);
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .args(["--split", "100"])
+        .args(["-o", "x.enc"])
+        .assert()
+        .code(2);
+    assert_eq!(std::fs::read(&file).unwrap(), b"in place");
+
+    /* --keep writes beside the file, so it splits there */
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .args(["--split", "1K", "--keep"])
+        .assert()
+        .success();
+    assert!(dir.path().join("disk.img.enc.000").exists());
+    assert_eq!(std::fs::read(&file).unwrap(), b"in place");
+}
@@ -0,0 +1,118 @@
+/* --stats: bytes, time and chunks for each file and the run */
+mod common;
+
+use assert_cmd::Command;
+use common::{file_with, rcli};
+use serde_json::Value;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn json_lines(cmd: &mut Command) -> Vec<Value> {
+    let output = cmd.assert().success().get_output().clone();
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn stats_count_exact_bytes() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.bin", &[7; 3000]);
+    let output = rcli()
+        .args([
+            "encrypt",
+            "--key-hex",
+            KEY_HEX,
+            "--chunk-size",
+            "1K",
+            "--stats",
+        ])
+        .args(["--file", &file])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let written = std::fs::metadata(&file).unwrap().len();
+    let line = format!("{}: read 3000 bytes, wrote {} in ", file, written);
+    assert!(stderr.contains(&line), "{}", stderr);
+    assert!(stderr.contains("MB/s), 3 chunks"), "{}", stderr);
+    assert!(
+        stderr.contains("Total: 1 file, read 3000 bytes"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn stats_total_a_batch_in_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let files = [
+        file_with(dir.path(), "a", &[7; 1000]),
+        file_with(dir.path(), "b", &[7; 70_000]),
+        file_with(dir.path(), "c", &[7; 0]),
+    ];
+    let records = json_lines(
+        rcli()
+            .args(["--json", "encrypt", "--key-hex", KEY_HEX, "--stats"])
+            .args(["-f", &files[0], "-f", &files[1], "-f", &files[2]]),
+    );
+    assert_eq!(records.len(), 4);
+    let (oks, total) = records.split_at(3);
+    for (record, file) in oks.iter().zip(&files) {
+        let stats = &record["stats"];
+        assert_eq!(stats["bytes_read"], record["bytes_in"]);
+        assert_eq!(
+            stats["bytes_written"],
+            std::fs::metadata(file).unwrap().len()
+        );
+        for field in ["seconds", "mb_per_s", "chunks"] {
+            assert!(stats.get(field).is_some(), "{} lacks {}", stats, field);
+        }
+    }
+    assert_eq!(oks[1]["stats"]["chunks"], 2);
+    let total = &total[0];
+    assert_eq!(total["status"], "total");
+    assert_eq!(total["files"], 3);
+    let sum = |field: &str| -> u64 {
+        oks.iter()
+            .map(|record| record["stats"][field].as_u64().unwrap())
+            .sum()
+    };
+    assert_eq!(total["stats"]["bytes_read"], 71_000);
+    assert_eq!(total["stats"]["bytes_read"], sum("bytes_read"));
+    assert_eq!(total["stats"]["bytes_written"], sum("bytes_written"));
+    assert_eq!(total["stats"]["chunks"], sum("chunks"));
+}
+
+#[test]
+fn stats_are_null_without_the_flag() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a", &[7; 10]);
+    let records = json_lines(
+        rcli()
+            .args(["--json", "encrypt", "--key-hex", KEY_HEX])
+            .args(["-f", &file]),
+    );
+    assert_eq!(records.len(), 1);
+    assert!(records[0]["stats"].is_null());
+}
+
+#[test]
+fn stats_meter_standard_input() {
+    let output = rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", "-", "--stats"])
+        .write_stdin(vec![1u8; 500])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let line = format!(
+        "standard input: read 500 bytes, wrote {} in ",
+        output.stdout.len()
+    );
+    assert!(stderr.contains(&line), "{}", stderr);
+}
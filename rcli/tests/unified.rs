@@ -0,0 +1,123 @@
+/* encrypt and decrypt: the algorithm is chosen once, and read back from
+ * the header */
+mod common;
+
+use assert_cmd::Command;
+use common::{file_with, rcli};
+use serde_json::Value;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn stdout_of(cmd: &mut Command) -> String {
+    let output = cmd.assert().success().get_output().clone();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn aes_gcm_decrypts_without_naming_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "db.dump", b"rows and rows");
+    rcli()
+        .args(["encrypt", "--algorithm", "aes256gcm", "--key-hex", KEY_HEX])
+        .args(["-f", &file])
+        .assert()
+        .success();
+    let shown = stdout_of(rcli().args(["inspect", "-f", &file]));
+    assert!(shown.contains("AES-256-GCM"), "{}", shown);
+
+    let record = stdout_of(rcli().args(["--json", "decrypt", "--key-hex", KEY_HEX, "-f", &file]));
+    let record: Value = serde_json::from_str(&record).unwrap();
+    assert_eq!(record["action"], "decrypt");
+    assert_eq!(record["algorithm"], "AES-256-GCM");
+    assert_eq!(std::fs::read(&file).unwrap(), b"rows and rows");
+}
+
+#[test]
+fn chacha_is_the_default_and_the_old_command_still_works() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .success();
+    let shown = stdout_of(rcli().args(["inspect", "-f", &file]));
+    assert!(shown.contains("ChaCha20-Poly1305"), "{}", shown);
+    rcli()
+        .args(["chacha", "--decrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .success();
+
+    rcli()
+        .args(["chacha", "--encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .success();
+    rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), b"hello");
+}
+
+#[test]
+fn passphrase_options_are_shared() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    let pass = file_with(dir.path(), "pass", b"correct horse battery staple\n");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&pass, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+    rcli()
+        .args(["encrypt", "--algorithm", "aes256gcm"])
+        .args(["--passphrase-file", &pass, "-f", &file])
+        .assert()
+        .success();
+    rcli()
+        .args(["decrypt", "--passphrase-file", &pass, "-f", &file])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&file).unwrap(), b"hello");
+}
+
+#[test]
+fn unknown_algorithm_in_the_header_is_named() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    rcli()
+        .args(["encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .success();
+    let mut sealed = std::fs::read(&file).unwrap();
+    sealed[5] = 0xee;
+    std::fs::write(&file, &sealed).unwrap();
+
+    let output = rcli()
+        .args(["decrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .code(5)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("unknown algorithm id 238"), "{}", stderr);
+    assert_eq!(std::fs::read(&file).unwrap(), sealed);
+}
+
+#[test]
+fn algorithm_is_only_for_encrypting() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    for args in [
+        vec!["decrypt", "--algorithm", "aes256gcm"],
+        vec!["encrypt", "--algorithm", "des"],
+        vec!["encrypt", "--identity", "me.key"],
+        vec!["decrypt", "--armor"],
+    ] {
+        rcli()
+            .args(&args)
+            .args(["--key-hex", KEY_HEX, "-f", &file])
+            .assert()
+            .code(2);
+    }
+    assert_eq!(std::fs::read(&file).unwrap(), b"hello");
+}
@@ -0,0 +1,115 @@
+/* --quiet and --verbose */
+mod common;
+
+use common::{file_with, rcli};
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const OTHER_KEY_HEX: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+const PASSPHRASE: &str = "correct horse battery staple";
+
+#[test]
+fn quiet_success_prints_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    for args in [
+        ["chacha", "--encrypt", "-q"],
+        ["chacha", "--decrypt", "--quiet"],
+    ] {
+        rcli()
+            .args(args)
+            .args(["--key-hex", KEY_HEX, "-f", &file])
+            .assert()
+            .success()
+            .stdout("")
+            .stderr("");
+    }
+    assert_eq!(std::fs::read(&file).unwrap(), b"hello");
+}
+
+#[test]
+fn errors_survive_quiet() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.txt", b"hello");
+    rcli()
+        .args(["chacha", "--encrypt", "--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .success();
+    let output = rcli()
+        .args(["-q", "chacha", "--decrypt", "--key-hex", OTHER_KEY_HEX])
+        .args(["-f", &file])
+        .assert()
+        .code(4)
+        .stdout("")
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Error: "), "{}", stderr);
+}
+
+#[test]
+fn verbose_shows_sizes_and_chunks() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "a.bin", &[0u8; 3000]);
+    let output = rcli()
+        .args(["chacha", "--encrypt", "-v", "--chunk-size", "1K"])
+        .args(["--key-hex", KEY_HEX, "-f", &file])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("3000 bytes in"), "{}", stderr);
+    assert!(stderr.contains("3 chunks"), "{}", stderr);
+    /* The status line stays on standard output */
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Encrypted "), "{}", stdout);
+}
+
+#[test]
+fn very_verbose_shows_kdf_but_no_secrets() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = file_with(dir.path(), "notes.txt", b"meeting notes");
+    let pass = file_with(dir.path(), "pass", PASSPHRASE.as_bytes());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&pass, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+    for mode in ["--encrypt", "--decrypt"] {
+        let output = rcli()
+            .args([
+                "-vv",
+                "chacha",
+                mode,
+                "--passphrase-file",
+                &pass,
+                "-f",
+                &file,
+            ])
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("key derivation: argon2id (memory"),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains("iterations"), "{}", stderr);
+        assert!(!stderr.contains(PASSPHRASE), "{}", stderr);
+        if mode == "--decrypt" {
+            assert!(stderr.contains("header: format version"), "{}", stderr);
+        }
+    }
+    assert_eq!(std::fs::read(&file).unwrap(), b"meeting notes");
+}
+
+#[test]
+fn quiet_and_verbose_conflict() {
+    rcli()
+        .args(["-q", "-v", "chacha", "--encrypt", "--key-hex", KEY_HEX])
+        .args(["-f", "x"])
+        .assert()
+        .code(2);
+}
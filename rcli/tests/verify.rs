@@ -0,0 +1,158 @@
+/* verify: authenticate without writing plaintext, with an exit status per
+ * kind of failure */
+mod common;
+
+use common::rcli;
+use std::path::Path;
+
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const OTHER_KEY_HEX: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+/* 5000 bytes in 1K chunks: four full sealed chunks and a last one of
+ * 904 + 16 bytes */
+const SEALED_CHUNK: usize = 1024 + 16;
+const LAST_CHUNK: usize = 904 + 16;
+
+fn sealed(dir: &Path) -> std::path::PathBuf {
+    let path = dir.join("backup.bin");
+    std::fs::write(&path, vec![7u8; 5000]).unwrap();
+    rcli()
+        .args(["chacha", "-f", path.to_str().unwrap(), "--key-hex", KEY_HEX])
+        .args(["--encrypt", "--chunk-size", "1K"])
+        .assert()
+        .success();
+    path
+}
+
+/* Verify `path` with `args`, expecting exit `status`; returns stderr */
+fn verify(path: &Path, args: &[&str], status: i32) -> String {
+    let output = rcli()
+        .args(["verify", "-f", path.to_str().unwrap()])
+        .args(args)
+        .assert()
+        .code(status)
+        .get_output()
+        .clone();
+    String::from_utf8(output.stderr).unwrap()
+}
+
+fn listing(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn valid_file_verifies_without_touching_anything() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = sealed(dir.path());
+    let before = std::fs::read(&path).unwrap();
+    let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+    let names = listing(dir.path());
+
+    let output = rcli()
+        .args(["verify", "-f", path.to_str().unwrap(), "--key-hex", KEY_HEX])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("(5000 bytes of plaintext)"), "{}", stdout);
+
+    /* Failures leave it alone too */
+    verify(&path, &["--key-hex", OTHER_KEY_HEX], 4);
+
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+    assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), mtime);
+    assert_eq!(listing(dir.path()), names);
+}
+
+#[test]
+fn flipped_byte_names_its_chunk() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = sealed(dir.path());
+    let mut data = std::fs::read(&path).unwrap();
+    let chunk_2 = data.len() - LAST_CHUNK - 2 * SEALED_CHUNK;
+    data[chunk_2 + 100] ^= 1;
+    std::fs::write(&path, &data).unwrap();
+
+    let stderr = verify(&path, &["--key-hex", KEY_HEX], 4);
+    assert!(
+        stderr.contains("chunk 2 failed authentication"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn truncated_file_is_reported_as_such() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = sealed(dir.path());
+    let data = std::fs::read(&path).unwrap();
+
+    /* Cut cleanly after chunk 3, and with the last chunk shorter than a
+     * tag. A cut elsewhere in a chunk just fails authentication */
+    for cut in [LAST_CHUNK, LAST_CHUNK - 10] {
+        std::fs::write(&path, &data[..data.len() - cut]).unwrap();
+        let stderr = verify(&path, &["--key-hex", KEY_HEX], 4);
+        assert!(stderr.contains("truncated at chunk"), "{}", stderr);
+    }
+}
+
+#[test]
+fn wrong_passphrase_or_aad_caught_by_check_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, b"meeting notes").unwrap();
+    let (right, wrong) = (dir.path().join("right"), dir.path().join("wrong"));
+    std::fs::write(&right, "correct horse battery staple\n").unwrap();
+    std::fs::write(&wrong, "incorrect horse\n").unwrap();
+    rcli()
+        .args(["chacha", "-f", path.to_str().unwrap(), "--encrypt"])
+        .args(["--passphrase-file", right.to_str().unwrap()])
+        .args(["--aad", "host=db1"])
+        .assert()
+        .success();
+
+    let stdin = ["--passphrase", "--aad", "host=db1"];
+    rcli()
+        .args(["verify", "-f", path.to_str().unwrap()])
+        .args(stdin)
+        .write_stdin("correct horse battery staple\n")
+        .assert()
+        .success();
+
+    let stderr = verify(
+        &path,
+        &[
+            "--passphrase-file",
+            wrong.to_str().unwrap(),
+            "--aad",
+            "host=db1",
+        ],
+        6,
+    );
+    assert!(stderr.contains("incorrect passphrase"), "{}", stderr);
+    let stderr = verify(
+        &path,
+        &[
+            "--passphrase-file",
+            right.to_str().unwrap(),
+            "--aad",
+            "host=db2",
+        ],
+        6,
+    );
+    assert!(
+        stderr.contains("associated data does not match"),
+        "{}",
+        stderr
+    );
+
+    /* Not an rcli file at all */
+    let stderr = verify(&right, &["--key-hex", KEY_HEX], 5);
+    assert!(stderr.contains("not an rcli encrypted file"), "{}", stderr);
+}